@@ -0,0 +1,196 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::random::Random;
+use crate::state_hasher::StateHasher;
+
+/// Specifies the order in which an arpeggiator plays the held notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpMode {
+    /// From the lowest note to the highest.
+    Up,
+    /// From the highest note to the lowest.
+    Down,
+    /// Up and then down, without repeating the highest and lowest notes at the turns.
+    UpDown,
+    /// A random note each step.
+    Random,
+}
+
+/// The settings of an arpeggiator attached by `Synthesizer::set_arpeggiator`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ArpConfig {
+    /// The order of the notes.
+    pub mode: ArpMode,
+    /// The number of steps per quarter note at the tempo of the synthesizer,
+    /// for example `4.0` for sixteenth notes. Must be positive.
+    pub rate: f64,
+    /// The number of octaves the held notes are repeated over, starting at the held ones.
+    /// Must be at least 1, and more than 8 octaves are played as 8.
+    pub octaves: i32,
+}
+
+impl ArpConfig {
+    /// Creates the settings with the mode, playing sixteenth notes over one octave.
+    pub fn new(mode: ArpMode) -> Self {
+        Self {
+            mode,
+            rate: 4.0,
+            octaves: 1,
+        }
+    }
+
+    pub(crate) fn validate(&self) {
+        if self.rate <= 0.0 || self.rate.is_nan() {
+            panic!("The rate of the arpeggiator must be a positive value.");
+        }
+
+        if self.octaves < 1 {
+            panic!("The octave range of the arpeggiator must be a positive value.");
+        }
+    }
+}
+
+// The note to stop and the note to start at a step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ArpStep {
+    pub(crate) note_off: Option<i32>,
+    pub(crate) note_on: Option<(i32, i32)>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Arpeggiator {
+    config: ArpConfig,
+
+    // The held keys and their velocities, in ascending order of the keys.
+    held: Vec<(i32, i32)>,
+
+    step: usize,
+    // The samples from the start of the next block until the next step.
+    countdown: f64,
+    playing: Option<i32>,
+
+    random: Random,
+}
+
+impl Arpeggiator {
+    const MAXIMUM_OCTAVES: i32 = 8;
+
+    pub(crate) fn new(mut config: ArpConfig) -> Self {
+        config.validate();
+        config.octaves = config.octaves.min(Arpeggiator::MAXIMUM_OCTAVES);
+
+        Self {
+            config,
+            // There are at most 128 keys, so pressing them does not allocate.
+            held: Vec::with_capacity(128),
+            step: 0,
+            countdown: 0.0,
+            playing: None,
+            random: Random::new(0),
+        }
+    }
+
+    pub(crate) fn press(&mut self, key: i32, velocity: i32) {
+        // The sequence starts at the next block when the first note is pressed.
+        if self.held.is_empty() {
+            self.step = 0;
+            self.countdown = 0.0;
+        }
+
+        match self.held.binary_search_by_key(&key, |&(k, _)| k) {
+            Ok(index) => self.held[index].1 = velocity,
+            Err(index) => self.held.insert(index, (key, velocity)),
+        }
+    }
+
+    // Returns the note to stop if no note is held any more.
+    pub(crate) fn release(&mut self, key: i32) -> Option<i32> {
+        if let Ok(index) = self.held.binary_search_by_key(&key, |&(k, _)| k) {
+            self.held.remove(index);
+        }
+
+        if self.held.is_empty() {
+            self.playing.take()
+        } else {
+            None
+        }
+    }
+
+    // Returns the note to stop, which is sounding.
+    pub(crate) fn clear(&mut self) -> Option<i32> {
+        self.held.clear();
+        self.playing.take()
+    }
+
+    // Returns the next step which falls within the block, with its offset from the start of the block.
+    // Once no step is left in the block, the clock moves to the next block and `None` is returned.
+    // The steps are at least one sample apart, so that a block has a bounded number of them.
+    pub(crate) fn next_step(
+        &mut self,
+        block_size: usize,
+        step_length: f64,
+    ) -> Option<(usize, ArpStep)> {
+        if self.held.is_empty() {
+            return None;
+        }
+
+        if self.countdown >= block_size as f64 {
+            self.countdown -= block_size as f64;
+            return None;
+        }
+        let offset = self.countdown as usize;
+        self.countdown += step_length.max(1.0);
+
+        let note_count = self.held.len() * self.config.octaves as usize;
+        let index = match self.config.mode {
+            ArpMode::Up => self.step % note_count,
+            ArpMode::Down => note_count - 1 - self.step % note_count,
+            ArpMode::UpDown => {
+                if note_count == 1 {
+                    0
+                } else {
+                    let period = 2 * note_count - 2;
+                    let position = self.step % period;
+                    if position < note_count {
+                        position
+                    } else {
+                        period - position
+                    }
+                }
+            }
+            ArpMode::Random => (self.random.next_u32() as usize) % note_count,
+        };
+        self.step += 1;
+
+        let (key, velocity) = self.held[index % self.held.len()];
+        let key = key + 12 * (index / self.held.len()) as i32;
+
+        let note_off = self.playing.take();
+        let note_on = if key <= 127 {
+            self.playing = Some(key);
+            Some((key, velocity))
+        } else {
+            None
+        };
+
+        Some((offset, ArpStep { note_off, note_on }))
+    }
+
+    pub(crate) fn get_config(&self) -> &ArpConfig {
+        &self.config
+    }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_usize(self.held.len());
+        for &(key, velocity) in &self.held {
+            hasher.write_i32(key);
+            hasher.write_i32(velocity);
+        }
+        hasher.write_usize(self.step);
+        hasher.write_f64(self.countdown);
+        hasher.write_i32(self.playing.unwrap_or(-1));
+    }
+}
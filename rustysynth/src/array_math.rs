@@ -6,16 +6,153 @@ pub(crate) struct ArrayMath {}
 
 impl ArrayMath {
     pub(crate) fn multiply_add(a: f32, x: &[f32], destination: &mut [f32]) {
+        let length = x.len().min(destination.len());
+        let (x, destination) = (&x[..length], &mut destination[..length]);
+
+        #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        let (x, destination) = {
+            let vectorized = length - length % simd::LANES;
+            simd::multiply_add(a, &x[..vectorized], &mut destination[..vectorized]);
+            (&x[vectorized..], &mut destination[vectorized..])
+        };
+
         for (x, destination) in x.iter().zip(destination.iter_mut()) {
             *destination += a * *x;
         }
     }
 
     pub(crate) fn multiply_add_slope(a: f32, step: f32, x: &[f32], destination: &mut [f32]) {
+        let length = x.len().min(destination.len());
+        let (x, destination) = (&x[..length], &mut destination[..length]);
         let mut a = a;
+
+        #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        let (x, destination) = {
+            let vectorized = length - length % simd::LANES;
+            simd::multiply_add_slope(a, step, &x[..vectorized], &mut destination[..vectorized]);
+            a += step * vectorized as f32;
+            (&x[vectorized..], &mut destination[vectorized..])
+        };
+
         for (x, destination) in x.iter().zip(destination.iter_mut()) {
             *destination += a * *x;
             a += step;
         }
     }
 }
+
+// The 4-lane versions, for the slices whose length is a multiple of LANES.
+// SSE and NEON are always available on x86_64 and aarch64, so no runtime detection is needed.
+// The products are not fused, so that multiply_add gives the same result as the scalar loop.
+// The gain of multiply_add_slope is computed per lane as a + i * step, which differs from
+// the running sum of the scalar loop by the rounding only.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use core::arch::x86_64::*;
+
+    pub(super) const LANES: usize = 4;
+
+    pub(super) fn multiply_add(a: f32, x: &[f32], destination: &mut [f32]) {
+        // SAFETY: SSE is part of the x86_64 baseline, and each chunk holds 4 values.
+        unsafe {
+            let a = _mm_set1_ps(a);
+            for (x, destination) in x
+                .chunks_exact(LANES)
+                .zip(destination.chunks_exact_mut(LANES))
+            {
+                let product = _mm_mul_ps(a, _mm_loadu_ps(x.as_ptr()));
+                let sum = _mm_add_ps(_mm_loadu_ps(destination.as_ptr()), product);
+                _mm_storeu_ps(destination.as_mut_ptr(), sum);
+            }
+        }
+    }
+
+    pub(super) fn multiply_add_slope(a: f32, step: f32, x: &[f32], destination: &mut [f32]) {
+        // SAFETY: SSE is part of the x86_64 baseline, and each chunk holds 4 values.
+        unsafe {
+            let mut a = _mm_add_ps(
+                _mm_set1_ps(a),
+                _mm_mul_ps(_mm_set1_ps(step), _mm_setr_ps(0.0, 1.0, 2.0, 3.0)),
+            );
+            let step = _mm_set1_ps(step * LANES as f32);
+            for (x, destination) in x
+                .chunks_exact(LANES)
+                .zip(destination.chunks_exact_mut(LANES))
+            {
+                let product = _mm_mul_ps(a, _mm_loadu_ps(x.as_ptr()));
+                let sum = _mm_add_ps(_mm_loadu_ps(destination.as_ptr()), product);
+                _mm_storeu_ps(destination.as_mut_ptr(), sum);
+                a = _mm_add_ps(a, step);
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod simd {
+    use core::arch::aarch64::*;
+
+    pub(super) const LANES: usize = 4;
+
+    pub(super) fn multiply_add(a: f32, x: &[f32], destination: &mut [f32]) {
+        // SAFETY: NEON is part of the aarch64 baseline, and each chunk holds 4 values.
+        unsafe {
+            let a = vdupq_n_f32(a);
+            for (x, destination) in x
+                .chunks_exact(LANES)
+                .zip(destination.chunks_exact_mut(LANES))
+            {
+                let product = vmulq_f32(a, vld1q_f32(x.as_ptr()));
+                let sum = vaddq_f32(vld1q_f32(destination.as_ptr()), product);
+                vst1q_f32(destination.as_mut_ptr(), sum);
+            }
+        }
+    }
+
+    pub(super) fn multiply_add_slope(a: f32, step: f32, x: &[f32], destination: &mut [f32]) {
+        const OFFSETS: [f32; 4] = [0.0, 1.0, 2.0, 3.0];
+
+        // SAFETY: NEON is part of the aarch64 baseline, and each chunk holds 4 values.
+        unsafe {
+            let mut a = vaddq_f32(
+                vdupq_n_f32(a),
+                vmulq_f32(vdupq_n_f32(step), vld1q_f32(OFFSETS.as_ptr())),
+            );
+            let step = vdupq_n_f32(step * LANES as f32);
+            for (x, destination) in x
+                .chunks_exact(LANES)
+                .zip(destination.chunks_exact_mut(LANES))
+            {
+                let product = vmulq_f32(a, vld1q_f32(x.as_ptr()));
+                let sum = vaddq_f32(vld1q_f32(destination.as_ptr()), product);
+                vst1q_f32(destination.as_mut_ptr(), sum);
+                a = vaddq_f32(a, step);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_multiply_add() {
+        let x: Vec<f32> = (0..11).map(|i| i as f32 * 0.25_f32 - 1_f32).collect();
+
+        let mut destination = vec![1_f32; 11];
+        ArrayMath::multiply_add(0.5_f32, &x, &mut destination);
+        for (x, y) in x.iter().zip(&destination) {
+            assert_eq!(*y, 1_f32 + 0.5_f32 * x);
+        }
+
+        // The gain ramps by the step from the first sample, including the ones after the last full lane.
+        let mut destination = vec![0_f32; 11];
+        ArrayMath::multiply_add_slope(0.5_f32, 0.125_f32, &x, &mut destination);
+        for (i, (x, y)) in x.iter().zip(&destination).enumerate() {
+            assert!((*y - (0.5_f32 + 0.125_f32 * i as f32) * x).abs() < 1e-6);
+        }
+    }
+}
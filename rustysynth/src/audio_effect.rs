@@ -0,0 +1,40 @@
+use alloc::boxed::Box;
+use core::fmt::Debug;
+
+/// A custom effect in the signal chain of the synthesizer, such as an EQ, a compressor,
+/// or a convolution reverb.
+///
+/// The effects added with `Synthesizer::add_effect` process the stereo mix block by block,
+/// after the built-in reverb and chorus and before the master low-pass filter.
+/// They are run in the order they were added. To use an effect instead of the built-in ones,
+/// disable `SynthesizerSettings::enable_reverb_and_chorus`.
+///
+/// The effect must be `Clone`, as the synthesizer is cloned with its effects,
+/// for example by the checkpoints of `MidiFileSequencer`.
+pub trait AudioEffect: Debug + Send + Sync + AudioEffectClone {
+    /// Processes one block of the left and right of the mix in place.
+    ///
+    /// Both have the block size given by `SynthesizerSettings::block_size`.
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]);
+
+    /// Clears the internal state, such as the delay lines, so that nothing is heard from the past.
+    /// `Synthesizer::reset` calls this, and the default implementation does nothing.
+    fn reset(&mut self) {}
+}
+
+/// Clones a boxed `AudioEffect`, which is implemented for all the effects that are `Clone`.
+pub trait AudioEffectClone {
+    fn clone_box(&self) -> Box<dyn AudioEffect>;
+}
+
+impl<T: AudioEffect + Clone + 'static> AudioEffectClone for T {
+    fn clone_box(&self) -> Box<dyn AudioEffect> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn AudioEffect> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
@@ -0,0 +1,296 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::Arc;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::error::AudioOutputError;
+use crate::midifile::MidiFile;
+use crate::midifile_sequencer::MidiFileSequencer;
+use crate::soundfont::SoundFont;
+use crate::synthesizer::Synthesizer;
+use crate::synthesizer_settings::SynthesizerSettings;
+
+// A command sent from the handles to the render callback.
+#[derive(Debug)]
+enum OutputCommand {
+    Message([i32; 4]),
+    Play(Arc<MidiFile>, bool),
+    Stop,
+    Pause,
+    Resume,
+    SetSpeed(f64),
+    SetMasterVolume(f32),
+}
+
+/// Plays a synthesizer and a MIDI file player on the default output device with cpal.
+///
+/// The synthesizer is created at the sample rate of the device, and renders in the callback
+/// of the stream, whatever the length of the buffers the device asks for.
+/// It is controlled from other threads through `AudioOutputHandle`, whose commands are applied
+/// at the start of the next callback. The sound stops when the output is dropped.
+#[non_exhaustive]
+pub struct AudioOutput {
+    stream: cpal::Stream,
+    handle: AudioOutputHandle,
+    sample_rate: i32,
+    channel_count: usize,
+}
+
+impl AudioOutput {
+    /// Opens the default output device, and starts playing the synthesizer created with the SoundFont
+    /// and the settings, whose sample rate is replaced by that of the device.
+    pub fn open(
+        sound_font: &Arc<SoundFont>,
+        mut settings: SynthesizerSettings,
+    ) -> Result<Self, AudioOutputError> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or(AudioOutputError::NoOutputDevice)?;
+        let supported = device
+            .default_output_config()
+            .map_err(AudioOutputError::DefaultStreamConfigError)?;
+        let config = supported.config();
+
+        let sample_rate = config.sample_rate.0 as i32;
+        let channel_count = config.channels as usize;
+        settings.sample_rate = sample_rate;
+        let synthesizer =
+            Synthesizer::new(sound_font, &settings).map_err(AudioOutputError::SynthesizerError)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let renderer = OutputRenderer::new(MidiFileSequencer::new(synthesizer), receiver);
+
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => AudioOutput::build_stream::<f32>(&device, &config, renderer),
+            cpal::SampleFormat::I16 => AudioOutput::build_stream::<i16>(&device, &config, renderer),
+            cpal::SampleFormat::U16 => AudioOutput::build_stream::<u16>(&device, &config, renderer),
+            format => return Err(AudioOutputError::UnsupportedSampleFormat(format)),
+        }?;
+        stream.play().map_err(AudioOutputError::PlayStreamError)?;
+
+        Ok(Self {
+            stream,
+            handle: AudioOutputHandle { sender },
+            sample_rate,
+            channel_count,
+        })
+    }
+
+    fn build_stream<T: cpal::SizedSample + cpal::FromSample<f32>>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        mut renderer: OutputRenderer,
+    ) -> Result<cpal::Stream, AudioOutputError> {
+        let channel_count = config.channels as usize;
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    renderer.render(data, channel_count)
+                },
+                // The stream keeps running after an error, and there is no one to report it to here.
+                |_| (),
+                None,
+            )
+            .map_err(AudioOutputError::BuildStreamError)
+    }
+
+    /// Gets a handle which sends the commands to the render callback. The handles can be cloned.
+    pub fn get_handle(&self) -> AudioOutputHandle {
+        self.handle.clone()
+    }
+
+    /// Gets the sample rate of the device, at which the synthesizer renders.
+    pub fn get_sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    /// Gets the number of the channels of the device.
+    ///
+    /// A mono device plays the average of the left and right, and on a device with more channels,
+    /// the left and right go to the first two and the others are silent.
+    pub fn get_channel_count(&self) -> usize {
+        self.channel_count
+    }
+}
+
+impl fmt::Debug for AudioOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioOutput")
+            .field("sample_rate", &self.sample_rate)
+            .field("channel_count", &self.channel_count)
+            .finish()
+    }
+}
+
+/// Sends the commands to the synthesizer playing on an `AudioOutput`, from any thread.
+///
+/// The commands which are sent after the output is dropped are ignored.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AudioOutputHandle {
+    sender: mpsc::Sender<OutputCommand>,
+}
+
+impl AudioOutputHandle {
+    fn send(&self, command: OutputCommand) {
+        // The output has been dropped if the receiver is gone.
+        let _ = self.sender.send(command);
+    }
+
+    /// Sends a MIDI message, in the same form as `Synthesizer::process_midi_message`.
+    pub fn process_midi_message(&self, channel: i32, command: i32, data1: i32, data2: i32) {
+        self.send(OutputCommand::Message([channel, command, data1, data2]));
+    }
+
+    pub fn note_on(&self, channel: i32, key: i32, velocity: i32) {
+        self.process_midi_message(channel, 0x90, key, velocity);
+    }
+
+    pub fn note_off(&self, channel: i32, key: i32) {
+        self.process_midi_message(channel, 0x80, key, 64);
+    }
+
+    /// Starts playing the MIDI file, as `MidiFileSequencer::play` does.
+    pub fn play(&self, midi_file: &Arc<MidiFile>, play_loop: bool) {
+        self.send(OutputCommand::Play(Arc::clone(midi_file), play_loop));
+    }
+
+    /// Stops the MIDI file and resets the synthesizer.
+    pub fn stop(&self) {
+        self.send(OutputCommand::Stop);
+    }
+
+    pub fn pause(&self) {
+        self.send(OutputCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(OutputCommand::Resume);
+    }
+
+    /// Sets the playback speed of the MIDI file, as `MidiFileSequencer::set_speed` does.
+    pub fn set_speed(&self, value: f64) {
+        if value.is_nan() {
+            panic!("The speed must not be NaN.");
+        }
+
+        self.send(OutputCommand::SetSpeed(value));
+    }
+
+    pub fn set_master_volume(&self, value: f32) {
+        self.send(OutputCommand::SetMasterVolume(value));
+    }
+}
+
+// Renders the sequencer into the buffers of the device, applying the commands before each buffer.
+struct OutputRenderer {
+    sequencer: MidiFileSequencer,
+    receiver: mpsc::Receiver<OutputCommand>,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl OutputRenderer {
+    // Enough for the buffers of most devices, which are not reallocated in the callback.
+    const INITIAL_BUFFER_SIZE: usize = 4096;
+
+    fn new(sequencer: MidiFileSequencer, receiver: mpsc::Receiver<OutputCommand>) -> Self {
+        Self {
+            sequencer,
+            receiver,
+            left: Vec::with_capacity(OutputRenderer::INITIAL_BUFFER_SIZE),
+            right: Vec::with_capacity(OutputRenderer::INITIAL_BUFFER_SIZE),
+        }
+    }
+
+    fn apply(&mut self, command: OutputCommand) {
+        match command {
+            OutputCommand::Message([channel, command, data1, data2]) => self
+                .sequencer
+                .get_synthesizer_mut()
+                .process_midi_message(channel, command, data1, data2),
+            OutputCommand::Play(midi_file, play_loop) => self.sequencer.play(&midi_file, play_loop),
+            OutputCommand::Stop => self.sequencer.stop(),
+            OutputCommand::Pause => self.sequencer.pause(),
+            OutputCommand::Resume => self.sequencer.resume(),
+            OutputCommand::SetSpeed(value) => self.sequencer.set_speed(value),
+            OutputCommand::SetMasterVolume(value) => self
+                .sequencer
+                .get_synthesizer_mut()
+                .set_master_volume(value),
+        }
+    }
+
+    // The sequencer renders in its own blocks, so the buffers can have any length.
+    fn render<T: cpal::FromSample<f32>>(&mut self, data: &mut [T], channel_count: usize) {
+        while let Ok(command) = self.receiver.try_recv() {
+            self.apply(command);
+        }
+
+        let channel_count = channel_count.max(1);
+        let frame_count = data.len() / channel_count;
+        self.left.resize(frame_count, 0_f32);
+        self.right.resize(frame_count, 0_f32);
+        self.sequencer.render(&mut self.left, &mut self.right);
+
+        let frames = data.chunks_exact_mut(channel_count);
+        for ((frame, &left), &right) in frames.zip(self.left.iter()).zip(self.right.iter()) {
+            if channel_count == 1 {
+                frame[0] = T::from_sample_(0.5 * (left + right));
+                continue;
+            }
+
+            frame[0] = T::from_sample_(left);
+            frame[1] = T::from_sample_(right);
+            for value in &mut frame[2..] {
+                *value = T::from_sample_(0_f32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_commands() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let mut reference = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        let mut renderer = OutputRenderer::new(MidiFileSequencer::new(synthesizer), receiver);
+        let handle = AudioOutputHandle { sender };
+
+        // The callbacks are not aligned with the blocks of the synthesizer.
+        handle.note_on(0, 60, 100);
+        handle.set_speed(2.0);
+        reference.note_on(0, 60, 100);
+        let mut expected_left = vec![0_f32; 700];
+        let mut expected_right = vec![0_f32; 700];
+        reference.render(&mut expected_left, &mut expected_right);
+        assert!(expected_right.iter().any(|value| *value != 0_f32));
+
+        let mut output = vec![0_f32; 3 * 700];
+        for chunk in output.chunks_mut(3 * 100) {
+            renderer.render(chunk, 3);
+        }
+        for (i, frame) in output.chunks_exact(3).enumerate() {
+            assert_eq!(frame, &[expected_left[i], expected_right[i], 0_f32]);
+        }
+        assert_eq!(renderer.sequencer.get_speed(), 2.0);
+
+        handle.stop();
+        let mut mono = vec![1_f32; 64];
+        renderer.render(&mut mono, 1);
+        assert!(mono.iter().all(|value| value.abs() < 1.0E-3));
+    }
+}
@@ -1,7 +1,10 @@
 #![allow(dead_code)]
 
-use std::f32::consts;
+use core::f32::consts;
 
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
 
 #[derive(Debug, Clone)]
@@ -106,4 +109,11 @@ impl BiQuadFilter {
         self.a3 = a1 / a0;
         self.a4 = a2 / a0;
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_bool(self.active);
+        for value in [self.x1, self.x2, self.y1, self.y2] {
+            hasher.write_f32(value);
+        }
+    }
 }
@@ -1,12 +1,15 @@
 #![allow(dead_code)]
 
-use std::io;
-use std::io::ErrorKind;
-use std::io::Read;
-use std::slice;
-use std::str;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::slice;
+use core::str;
 
 use crate::four_cc::FourCC;
+use crate::io;
+use crate::io::ErrorKind;
+use crate::io::Read;
 
 #[allow(unused)]
 #[non_exhaustive]
@@ -124,7 +127,8 @@ impl BinaryReader {
         reader: &mut R,
         size: usize,
     ) -> Result<Vec<i16>, io::Error> {
-        let length = size / 2;
+        // The compressed data of a SoundFont3 can have an odd size.
+        let length = size.div_ceil(2);
         let mut samples: Vec<i16> = vec![0; length];
 
         let ptr = samples.as_mut_ptr() as *mut u8;
@@ -1,5 +1,15 @@
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
+
+#[cfg(feature = "introspection")]
+use crate::channel_info::ChannelInfo;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::generator_type::GeneratorType;
+use crate::pan_curve::PanCurve;
+use crate::state_hasher::StateHasher;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum DataType {
     None,
@@ -7,6 +17,66 @@ enum DataType {
     Nrpn,
 }
 
+// The NRPNs of GS and XG, which change the sound relative to the value 64,
+// with the generator they are added to and the amount per step.
+// The numbers are the MSB and LSB of the NRPN.
+const NRPN_PARAMETERS: [(i16, u16, i16); 8] = [
+    ((0x01 << 7) | 0x08, GeneratorType::FREQUENCY_VIBRATO_LFO, 20), // Vibrato Rate
+    ((0x01 << 7) | 0x09, GeneratorType::VIBRATO_LFO_TO_PITCH, 1),   // Vibrato Depth
+    ((0x01 << 7) | 0x0A, GeneratorType::DELAY_VIBRATO_LFO, 40),     // Vibrato Delay
+    (
+        (0x01 << 7) | 0x20,
+        GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY,
+        60,
+    ), // Filter Cutoff
+    ((0x01 << 7) | 0x21, GeneratorType::INITIAL_FILTER_Q, 3),       // Filter Resonance
+    (
+        (0x01 << 7) | 0x63,
+        GeneratorType::ATTACK_VOLUME_ENVELOPE,
+        40,
+    ), // Attack Time
+    ((0x01 << 7) | 0x64, GeneratorType::DECAY_VOLUME_ENVELOPE, 40), // Decay Time
+    (
+        (0x01 << 7) | 0x66,
+        GeneratorType::RELEASE_VOLUME_ENVELOPE,
+        40,
+    ), // Release Time
+];
+
+// The MIDI state of a channel, saved by `Synthesizer::save_state`.
+// The settings of the host are not part of it, and the arrays are stored as vectors for serde.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct ChannelState {
+    is_percussion_channel: bool,
+    bank_number: i32,
+    patch_number: i32,
+    modulation: i16,
+    volume: i16,
+    pan: i16,
+    expression: i16,
+    hold_pedal: bool,
+    sostenuto: bool,
+    soft_pedal: bool,
+    reverb_send: u8,
+    chorus_send: u8,
+    rpn: i16,
+    nrpn: i16,
+    pitch_bend_range: i16,
+    coarse_tune: i16,
+    fine_tune: i16,
+    tuning_program: u8,
+    tuning_bank: u8,
+    scale_tuning: [f32; 12],
+    pitch_bend: f32,
+    nrpn_offsets: Vec<i16>,
+    controllers: Vec<u8>,
+    channel_pressure: u8,
+    key_pressure: Vec<u8>,
+    mono_mode: bool,
+    last_data_type: u8,
+}
+
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub(crate) struct Channel {
@@ -14,24 +84,73 @@ pub(crate) struct Channel {
 
     bank_number: i32,
     patch_number: i32,
+    // The SoundFont and the preset resolved from the bank and patch, cached by the note-on.
+    resolved_preset: Option<(usize, usize)>,
 
     modulation: i16,
     volume: i16,
     pan: i16,
     expression: i16,
     hold_pedal: bool,
+    sostenuto: bool,
+    soft_pedal: bool,
 
     reverb_send: u8,
     chorus_send: u8,
 
     rpn: i16,
+    nrpn: i16,
     pitch_bend_range: i16,
     coarse_tune: i16,
     fine_tune: i16,
+    // The tuning program and bank selected by RPN 3 and 4 from the MTS tunings.
+    tuning_program: u8,
+    tuning_bank: u8,
+    // The offsets of the 12 pitch classes in semitones, set by the MTS scale/octave tuning.
+    scale_tuning: [f32; 12],
 
     pitch_bend: f32,
 
+    // The offsets of the generators set by the NRPNs, applied to the notes started after them.
+    nrpn_offsets: [i16; GeneratorType::COUNT],
+
+    // The last values of the controllers and the pressures, read by the modulators of the SoundFont.
+    controllers: [u8; 128],
+    channel_pressure: u8,
+    key_pressure: [u8; 128],
+
+    // The mono mode (CC 126) plays a single note at a time, returning to the keys still held on the note-off.
+    mono_mode: bool,
+    mono_keys: Vec<(i32, i32)>,
+    // The key set by the portamento control (CC 84), from which the next note glides.
+    portamento_control: Option<i32>,
+    // The pitch of the last note started, from which the next note glides while the portamento is on.
+    last_pitch: Option<f32>,
+
     last_data_type: DataType,
+
+    // Host-side settings (not MIDI state), so these survive resets.
+    attack_scale: f32,
+    decay_scale: f32,
+    sustain_offset: f32,
+    release_scale: f32,
+    velocity_bias: i32,
+    expression_link: Option<(i32, bool)>,
+    transpose: i32,
+    transpose_regions: bool,
+    filter_key_tracking: f32,
+    filter_velocity_tracking: f32,
+    vibrato_delay_scale: f32,
+    filter_cutoff_offset: f32,
+    gain: f32,
+    // The detune in semitones and the linear gain of each key.
+    key_tune: [f32; 128],
+    key_gain: [f32; 128],
+    // Set by the host, this takes precedence over the range set by RPN 0.
+    pitch_bend_range_override: Option<f32>,
+    // The master channel of the MPE zone, if this is a member channel of it.
+    mpe_master: Option<usize>,
+    voice_limit: Option<usize>,
 }
 
 impl Channel {
@@ -40,19 +159,52 @@ impl Channel {
             is_percussion_channel,
             bank_number: 0,
             patch_number: 0,
+            resolved_preset: None,
             modulation: 0,
             volume: 0,
             pan: 0,
             expression: 0,
             hold_pedal: false,
+            sostenuto: false,
+            soft_pedal: false,
             reverb_send: 0,
             chorus_send: 0,
             rpn: 0,
+            nrpn: 0,
             pitch_bend_range: 0,
             coarse_tune: 0,
             fine_tune: 0,
+            tuning_program: 0,
+            tuning_bank: 0,
+            scale_tuning: [0_f32; 12],
             pitch_bend: 0_f32,
+            nrpn_offsets: [0; GeneratorType::COUNT],
+            controllers: [0; 128],
+            channel_pressure: 0,
+            key_pressure: [0; 128],
+            mono_mode: false,
+            mono_keys: Vec::with_capacity(128),
+            portamento_control: None,
+            last_pitch: None,
             last_data_type: DataType::None,
+            attack_scale: 1_f32,
+            decay_scale: 1_f32,
+            sustain_offset: 0_f32,
+            release_scale: 1_f32,
+            velocity_bias: 0,
+            expression_link: None,
+            transpose: 0,
+            transpose_regions: false,
+            filter_key_tracking: 0_f32,
+            filter_velocity_tracking: 0_f32,
+            vibrato_delay_scale: 1_f32,
+            filter_cutoff_offset: 0_f32,
+            gain: 1_f32,
+            key_tune: [0_f32; 128],
+            key_gain: [1_f32; 128],
+            pitch_bend_range_override: None,
+            mpe_master: None,
+            voice_limit: None,
         };
 
         channel.reset();
@@ -63,44 +215,128 @@ impl Channel {
     pub(crate) fn reset(&mut self) {
         self.bank_number = if self.is_percussion_channel { 128 } else { 0 };
         self.patch_number = 0;
+        self.resolved_preset = None;
 
         self.modulation = 0;
         self.volume = 100 << 7;
         self.pan = 64 << 7;
         self.expression = 127 << 7;
         self.hold_pedal = false;
+        self.sostenuto = false;
+        self.soft_pedal = false;
 
         self.reverb_send = 40;
         self.chorus_send = 0;
 
         self.rpn = -1;
-        self.pitch_bend_range = 2 << 7;
+        self.nrpn = -1;
+        // The member channels of an MPE zone bend by 48 semitones.
+        self.pitch_bend_range = if self.mpe_master.is_some() {
+            48 << 7
+        } else {
+            2 << 7
+        };
         self.coarse_tune = 0;
         self.fine_tune = 8192;
+        self.tuning_program = 0;
+        self.tuning_bank = 0;
+        self.scale_tuning = [0_f32; 12];
 
         self.pitch_bend = 0_f32;
+
+        self.nrpn_offsets = [0; GeneratorType::COUNT];
+
+        self.controllers = [0; 128];
+        self.controllers[7] = 100;
+        self.controllers[10] = 64;
+        self.controllers[11] = 127;
+        self.controllers[91] = 40;
+        // The sound controllers start at the center, where they do not change the sound.
+        self.controllers[71..=74].fill(64);
+        self.channel_pressure = 0;
+        self.key_pressure = [0; 128];
+
+        self.mono_mode = false;
+        self.mono_keys.clear();
+        self.portamento_control = None;
+        self.last_pitch = None;
+    }
+
+    // Same as reset, but the bank and patch are kept.
+    pub(crate) fn reset_keeping_program(&mut self) {
+        let bank_number = self.bank_number;
+        let patch_number = self.patch_number;
+        self.reset();
+        self.bank_number = bank_number;
+        self.patch_number = patch_number;
     }
 
     pub(crate) fn reset_all_controllers(&mut self) {
         self.modulation = 0;
         self.expression = 127 << 7;
         self.hold_pedal = false;
+        self.sostenuto = false;
+        self.soft_pedal = false;
 
         self.rpn = -1;
+        self.nrpn = -1;
 
         self.pitch_bend = 0_f32;
+
+        self.controllers[1] = 0;
+        self.controllers[33] = 0;
+        self.controllers[11] = 127;
+        self.controllers[43] = 0;
+        self.controllers[64] = 0;
+        self.controllers[65] = 0;
+        self.portamento_control = None;
+        self.channel_pressure = 0;
+        self.key_pressure = [0; 128];
     }
 
     pub(crate) fn set_bank(&mut self, value: i32) {
         self.bank_number = value;
+        self.resolved_preset = None;
 
         if self.is_percussion_channel {
             self.bank_number += 128;
         }
     }
 
+    pub(crate) fn set_percussion(&mut self, value: bool) {
+        if self.is_percussion_channel == value {
+            return;
+        }
+
+        self.is_percussion_channel = value;
+        self.resolved_preset = None;
+
+        if value {
+            self.bank_number += 128;
+        } else {
+            self.bank_number -= 128;
+        }
+    }
+
     pub(crate) fn set_patch(&mut self, value: i32) {
         self.patch_number = value;
+        self.resolved_preset = None;
+    }
+
+    pub(crate) fn set_controller(&mut self, number: i32, value: i32) {
+        if (0..128).contains(&number) {
+            self.controllers[number as usize] = value.clamp(0, 127) as u8;
+        }
+    }
+
+    pub(crate) fn set_channel_pressure(&mut self, value: i32) {
+        self.channel_pressure = value.clamp(0, 127) as u8;
+    }
+
+    pub(crate) fn set_key_pressure(&mut self, key: i32, value: i32) {
+        if (0..128).contains(&key) {
+            self.key_pressure[key as usize] = value.clamp(0, 127) as u8;
+        }
     }
 
     pub(crate) fn set_modulation_coarse(&mut self, value: i32) {
@@ -139,6 +375,18 @@ impl Channel {
         self.hold_pedal = value >= 64;
     }
 
+    // Returns true if the sostenuto has just been pressed,
+    // when the notes held down at the moment should be captured.
+    pub(crate) fn set_sostenuto(&mut self, value: i32) -> bool {
+        let pressed = !self.sostenuto && value >= 64;
+        self.sostenuto = value >= 64;
+        pressed
+    }
+
+    pub(crate) fn set_soft_pedal(&mut self, value: i32) {
+        self.soft_pedal = value >= 64;
+    }
+
     pub(crate) fn set_reverb_send(&mut self, value: i32) {
         self.reverb_send = value as u8;
     }
@@ -157,15 +405,27 @@ impl Channel {
         self.last_data_type = DataType::Rpn;
     }
 
-    pub(crate) fn set_nrpn_coarse(&mut self, _value: i32) {
+    pub(crate) fn set_nrpn_coarse(&mut self, value: i32) {
+        self.nrpn = (self.nrpn & 0x7F) | (value << 7) as i16;
         self.last_data_type = DataType::Nrpn;
     }
 
-    pub(crate) fn set_nrpn_fine(&mut self, _value: i32) {
+    pub(crate) fn set_nrpn_fine(&mut self, value: i32) {
+        self.nrpn = (((self.nrpn as i32) & 0xFF80) | value) as i16;
         self.last_data_type = DataType::Nrpn;
     }
 
     pub(crate) fn data_entry_coarse(&mut self, value: i32) {
+        if self.last_data_type == DataType::Nrpn {
+            if let Some(&(_, generator, step)) = NRPN_PARAMETERS
+                .iter()
+                .find(|(nrpn, _, _)| *nrpn == self.nrpn)
+            {
+                self.nrpn_offsets[generator as usize] = (value.clamp(0, 127) as i16 - 64) * step;
+            }
+            return;
+        }
+
         if self.last_data_type != DataType::Rpn {
             return;
         }
@@ -176,6 +436,10 @@ impl Channel {
             self.fine_tune = (self.fine_tune & 0x7F) | (value << 7) as i16;
         } else if self.rpn == 2 {
             self.coarse_tune = (value - 64) as i16;
+        } else if self.rpn == 3 {
+            self.tuning_program = value.clamp(0, 127) as u8;
+        } else if self.rpn == 4 {
+            self.tuning_bank = value.clamp(0, 127) as u8;
         }
     }
 
@@ -191,10 +455,189 @@ impl Channel {
         }
     }
 
+    // Moves the data entry of the parameter by the steps, for the data increment and decrement.
+    pub(crate) fn data_increment(&mut self, steps: i32) {
+        let value = match self.last_data_type {
+            DataType::Rpn if self.rpn == 0 => (self.pitch_bend_range >> 7) as i32,
+            DataType::Rpn if self.rpn == 1 => (self.fine_tune >> 7) as i32,
+            DataType::Rpn if self.rpn == 2 => self.coarse_tune as i32 + 64,
+            DataType::Rpn if self.rpn == 3 => self.tuning_program as i32,
+            DataType::Rpn if self.rpn == 4 => self.tuning_bank as i32,
+            DataType::Nrpn => match NRPN_PARAMETERS
+                .iter()
+                .find(|(nrpn, _, _)| *nrpn == self.nrpn)
+            {
+                Some(&(_, generator, step)) => {
+                    (self.nrpn_offsets[generator as usize] / step) as i32 + 64
+                }
+                None => return,
+            },
+            _ => return,
+        };
+
+        self.data_entry_coarse((value + steps).clamp(0, 127));
+    }
+
     pub(crate) fn set_pitch_bend(&mut self, value1: i32, value2: i32) {
         self.pitch_bend = (1_f32 / 8192_f32) * ((value1 | (value2 << 7)) - 8192) as f32;
     }
 
+    pub(crate) fn set_adsr_scale(
+        &mut self,
+        attack_scale: f32,
+        decay_scale: f32,
+        sustain_offset: f32,
+        release_scale: f32,
+    ) {
+        self.attack_scale = attack_scale.max(0_f32);
+        self.decay_scale = decay_scale.max(0_f32);
+        self.sustain_offset = sustain_offset;
+        self.release_scale = release_scale.max(0_f32);
+    }
+
+    pub(crate) fn set_attack_scale(&mut self, value: f32) {
+        self.attack_scale = value.max(0_f32);
+    }
+
+    pub(crate) fn set_expression_link(&mut self, value: Option<(i32, bool)>) {
+        self.expression_link = value;
+    }
+
+    // Sets the expression from the expression of the linked channel.
+    pub(crate) fn follow_expression(&mut self, value: i16, inverse: bool) {
+        self.expression = if inverse { 16383 - value } else { value };
+        self.controllers[11] = (self.expression >> 7) as u8;
+    }
+
+    pub(crate) fn set_transpose(&mut self, value: i32) {
+        self.transpose = value;
+    }
+
+    pub(crate) fn set_transpose_regions(&mut self, value: bool) {
+        self.transpose_regions = value;
+    }
+
+    pub(crate) fn set_filter_key_tracking(&mut self, value: f32) {
+        self.filter_key_tracking = value;
+    }
+
+    pub(crate) fn set_filter_velocity_tracking(&mut self, value: f32) {
+        self.filter_velocity_tracking = value;
+    }
+
+    pub(crate) fn set_vibrato_delay_scale(&mut self, value: f32) {
+        self.vibrato_delay_scale = value;
+    }
+
+    pub(crate) fn set_filter_cutoff_offset(&mut self, value: f32) {
+        self.filter_cutoff_offset = value;
+    }
+
+    pub(crate) fn set_key_tune(&mut self, key: usize, value: f32) {
+        self.key_tune[key] = value;
+    }
+
+    pub(crate) fn set_key_gain(&mut self, key: usize, value: f32) {
+        self.key_gain[key] = value;
+    }
+
+    pub(crate) fn clear_key_overrides(&mut self) {
+        self.key_tune.fill(0_f32);
+        self.key_gain.fill(1_f32);
+    }
+
+    pub(crate) fn set_pitch_bend_range_override(&mut self, value: Option<f32>) {
+        self.pitch_bend_range_override = value;
+    }
+
+    // Makes the channel a member of the MPE zone of the master channel, or not,
+    // with the default pitch bend range of the new role.
+    pub(crate) fn set_mpe_master(&mut self, value: Option<usize>) {
+        if self.mpe_master == value {
+            return;
+        }
+
+        self.mpe_master = value;
+        self.pitch_bend_range = if value.is_some() { 48 << 7 } else { 2 << 7 };
+    }
+
+    pub(crate) fn get_mpe_master(&self) -> Option<usize> {
+        self.mpe_master
+    }
+
+    pub(crate) fn set_voice_limit(&mut self, value: Option<usize>) {
+        self.voice_limit = value;
+    }
+
+    pub(crate) fn get_voice_limit(&self) -> Option<usize> {
+        self.voice_limit
+    }
+
+    pub(crate) fn set_mono_mode(&mut self, value: bool) {
+        self.mono_mode = value;
+        self.mono_keys.clear();
+    }
+
+    pub(crate) fn clear_mono_keys(&mut self) {
+        self.mono_keys.clear();
+    }
+
+    pub(crate) fn get_mono_mode(&self) -> bool {
+        self.mono_mode
+    }
+
+    // Adds the key to the keys held in the mono mode, as the one sounding.
+    pub(crate) fn press_mono_key(&mut self, key: i32, velocity: i32) {
+        self.mono_keys.retain(|(held, _)| *held != key);
+        self.mono_keys.push((key, velocity));
+    }
+
+    // Removes the key from the keys held in the mono mode.
+    // If it was the one sounding, returns the key held before it to play again.
+    pub(crate) fn release_mono_key(&mut self, key: i32) -> Option<(i32, i32)> {
+        let sounding = self.mono_keys.last().map(|(held, _)| *held);
+        self.mono_keys.retain(|(held, _)| *held != key);
+        if sounding == Some(key) {
+            self.mono_keys.last().copied()
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn get_portamento(&self) -> bool {
+        self.controllers[65] >= 64
+    }
+
+    // The portamento time (CC 5) maps exponentially from 1 ms at 0 to 10 seconds at 127.
+    pub(crate) fn get_portamento_time(&self) -> f32 {
+        10_f32.powf(4_f32 * self.controllers[5] as f32 / 127_f32 - 3_f32)
+    }
+
+    pub(crate) fn set_portamento_control(&mut self, key: i32) {
+        self.portamento_control = Some(key);
+    }
+
+    // The portamento control only applies to the next note.
+    pub(crate) fn take_portamento_control(&mut self) -> Option<i32> {
+        self.portamento_control.take()
+    }
+
+    pub(crate) fn set_last_pitch(&mut self, pitch: f32) {
+        self.last_pitch = Some(pitch);
+    }
+
+    pub(crate) fn get_last_pitch(&self) -> Option<f32> {
+        self.last_pitch
+    }
+
+    pub(crate) fn get_rpn(&self) -> i16 {
+        self.rpn
+    }
+
+    pub(crate) fn set_velocity_bias(&mut self, value: i32) {
+        self.velocity_bias = value;
+    }
+
     pub(crate) fn get_bank_number(&self) -> i32 {
         self.bank_number
     }
@@ -203,6 +646,43 @@ impl Channel {
         self.patch_number
     }
 
+    pub(crate) fn get_resolved_preset(&self) -> Option<(usize, usize)> {
+        self.resolved_preset
+    }
+
+    pub(crate) fn set_resolved_preset(&mut self, value: (usize, usize)) {
+        self.resolved_preset = Some(value);
+    }
+
+    pub(crate) fn clear_resolved_preset(&mut self) {
+        self.resolved_preset = None;
+    }
+
+    pub(crate) fn get_nrpn_offsets(&self) -> &[i16; GeneratorType::COUNT] {
+        &self.nrpn_offsets
+    }
+
+    pub(crate) fn get_controller(&self, number: usize) -> u8 {
+        self.controllers[number]
+    }
+
+    pub(crate) fn get_channel_pressure(&self) -> u8 {
+        self.channel_pressure
+    }
+
+    pub(crate) fn get_key_pressure(&self, key: i32) -> u8 {
+        if (0..128).contains(&key) {
+            self.key_pressure[key as usize]
+        } else {
+            0
+        }
+    }
+
+    // The position of the pitch wheel from 0 to 1, where the center is 0.5.
+    pub(crate) fn get_pitch_wheel(&self) -> f32 {
+        0.5_f32 * self.pitch_bend + 0.5_f32
+    }
+
     pub(crate) fn get_modulation(&self) -> f32 {
         (50_f32 / 16383_f32) * self.modulation as f32
     }
@@ -211,8 +691,19 @@ impl Channel {
         (1_f32 / 16383_f32) * self.volume as f32
     }
 
-    pub(crate) fn get_pan(&self) -> f32 {
-        (100_f32 / 16383_f32) * self.pan as f32 - 50_f32
+    pub(crate) fn get_pan(&self, curve: PanCurve) -> f32 {
+        match curve {
+            PanCurve::Gm => {
+                // The center is 64 << 7, and the full right is 127 << 7 without the fine value.
+                let offset = self.pan as f32 - 8192_f32;
+                if offset < 0_f32 {
+                    (50_f32 / 8192_f32) * offset
+                } else {
+                    ((50_f32 / 8064_f32) * offset).min(50_f32)
+                }
+            }
+            PanCurve::Linear => (100_f32 / 16383_f32) * self.pan as f32 - 50_f32,
+        }
     }
 
     pub(crate) fn get_expression(&self) -> f32 {
@@ -223,6 +714,14 @@ impl Channel {
         self.hold_pedal
     }
 
+    pub(crate) fn get_sostenuto(&self) -> bool {
+        self.sostenuto
+    }
+
+    pub(crate) fn get_soft_pedal(&self) -> bool {
+        self.soft_pedal
+    }
+
     pub(crate) fn get_reverb_send(&self) -> f32 {
         (1_f32 / 127_f32) * self.reverb_send as f32
     }
@@ -232,6 +731,10 @@ impl Channel {
     }
 
     pub(crate) fn get_pitch_bend_range(&self) -> f32 {
+        if let Some(value) = self.pitch_bend_range_override {
+            return value;
+        }
+
         (self.pitch_bend_range >> 7) as f32 + 0.01_f32 * (self.pitch_bend_range & 0x7F) as f32
     }
 
@@ -239,7 +742,264 @@ impl Channel {
         self.coarse_tune as f32 + (1_f32 / 8192_f32) * (self.fine_tune - 8192) as f32
     }
 
+    pub(crate) fn get_tuning_program(&self) -> (u8, u8) {
+        (self.tuning_bank, self.tuning_program)
+    }
+
+    pub(crate) fn get_scale_tuning(&self, key: i32) -> f32 {
+        self.scale_tuning[key.rem_euclid(12) as usize]
+    }
+
+    pub(crate) fn set_scale_tuning(&mut self, offsets: [f32; 12]) {
+        self.scale_tuning = offsets;
+    }
+
     pub(crate) fn get_pitch_bend(&self) -> f32 {
         self.get_pitch_bend_range() * self.pitch_bend
     }
+
+    pub(crate) fn get_attack_scale(&self) -> f32 {
+        self.attack_scale
+    }
+
+    pub(crate) fn get_decay_scale(&self) -> f32 {
+        self.decay_scale
+    }
+
+    pub(crate) fn get_sustain_offset(&self) -> f32 {
+        self.sustain_offset
+    }
+
+    pub(crate) fn get_release_scale(&self) -> f32 {
+        self.release_scale
+    }
+
+    pub(crate) fn get_velocity_bias(&self) -> i32 {
+        self.velocity_bias
+    }
+
+    pub(crate) fn get_filter_key_tracking(&self) -> f32 {
+        self.filter_key_tracking
+    }
+
+    pub(crate) fn get_filter_velocity_tracking(&self) -> f32 {
+        self.filter_velocity_tracking
+    }
+
+    pub(crate) fn get_vibrato_delay_scale(&self) -> f32 {
+        self.vibrato_delay_scale
+    }
+
+    pub(crate) fn set_gain(&mut self, value: f32) {
+        self.gain = value;
+    }
+
+    pub(crate) fn get_gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub(crate) fn get_filter_cutoff_offset(&self) -> f32 {
+        self.filter_cutoff_offset
+    }
+
+    pub(crate) fn get_key_tune(&self, key: i32) -> f32 {
+        if (0..128).contains(&key) {
+            self.key_tune[key as usize]
+        } else {
+            0_f32
+        }
+    }
+
+    pub(crate) fn get_key_gain(&self, key: i32) -> f32 {
+        if (0..128).contains(&key) {
+            self.key_gain[key as usize]
+        } else {
+            1_f32
+        }
+    }
+
+    pub(crate) fn get_transpose(&self) -> i32 {
+        self.transpose
+    }
+
+    pub(crate) fn get_transpose_regions(&self) -> bool {
+        self.transpose_regions
+    }
+
+    pub(crate) fn get_expression_link(&self) -> Option<(i32, bool)> {
+        self.expression_link
+    }
+
+    pub(crate) fn get_expression_value(&self) -> i16 {
+        self.expression
+    }
+
+    #[cfg(feature = "introspection")]
+    pub(crate) fn info(&self, channel: i32) -> ChannelInfo {
+        ChannelInfo {
+            channel,
+            bank: self.bank_number,
+            program: self.patch_number,
+            is_percussion: self.is_percussion_channel,
+            controllers: self.controllers,
+            pitch_wheel: self.pitch_bend,
+            pitch_bend: self.get_pitch_bend(),
+            pitch_bend_range: self.get_pitch_bend_range(),
+            channel_pressure: self.channel_pressure,
+        }
+    }
+
+    pub(crate) fn save_state(&self) -> ChannelState {
+        ChannelState {
+            is_percussion_channel: self.is_percussion_channel,
+            bank_number: self.bank_number,
+            patch_number: self.patch_number,
+            modulation: self.modulation,
+            volume: self.volume,
+            pan: self.pan,
+            expression: self.expression,
+            hold_pedal: self.hold_pedal,
+            sostenuto: self.sostenuto,
+            soft_pedal: self.soft_pedal,
+            reverb_send: self.reverb_send,
+            chorus_send: self.chorus_send,
+            rpn: self.rpn,
+            nrpn: self.nrpn,
+            pitch_bend_range: self.pitch_bend_range,
+            coarse_tune: self.coarse_tune,
+            fine_tune: self.fine_tune,
+            tuning_program: self.tuning_program,
+            tuning_bank: self.tuning_bank,
+            scale_tuning: self.scale_tuning,
+            pitch_bend: self.pitch_bend,
+            nrpn_offsets: self.nrpn_offsets.to_vec(),
+            controllers: self.controllers.to_vec(),
+            channel_pressure: self.channel_pressure,
+            key_pressure: self.key_pressure.to_vec(),
+            mono_mode: self.mono_mode,
+            last_data_type: match self.last_data_type {
+                DataType::None => 0,
+                DataType::Rpn => 1,
+                DataType::Nrpn => 2,
+            },
+        }
+    }
+
+    // The keys held in the mono mode and the portamento are not saved, and start over.
+    // The arrays of a state which was not saved by this version keep their values where they do not fit.
+    pub(crate) fn restore_state(&mut self, state: &ChannelState) {
+        self.is_percussion_channel = state.is_percussion_channel;
+        self.bank_number = state.bank_number;
+        self.patch_number = state.patch_number;
+        self.resolved_preset = None;
+        self.modulation = state.modulation;
+        self.volume = state.volume;
+        self.pan = state.pan;
+        self.expression = state.expression;
+        self.hold_pedal = state.hold_pedal;
+        self.sostenuto = state.sostenuto;
+        self.soft_pedal = state.soft_pedal;
+        self.reverb_send = state.reverb_send;
+        self.chorus_send = state.chorus_send;
+        self.rpn = state.rpn;
+        self.nrpn = state.nrpn;
+        self.pitch_bend_range = state.pitch_bend_range;
+        self.coarse_tune = state.coarse_tune;
+        self.fine_tune = state.fine_tune;
+        self.tuning_program = state.tuning_program;
+        self.tuning_bank = state.tuning_bank;
+        self.scale_tuning = state.scale_tuning;
+        self.pitch_bend = state.pitch_bend;
+        for (value, &saved) in self.nrpn_offsets.iter_mut().zip(&state.nrpn_offsets) {
+            *value = saved;
+        }
+        for (value, &saved) in self.controllers.iter_mut().zip(&state.controllers) {
+            *value = saved;
+        }
+        self.channel_pressure = state.channel_pressure;
+        for (value, &saved) in self.key_pressure.iter_mut().zip(&state.key_pressure) {
+            *value = saved;
+        }
+        self.mono_mode = state.mono_mode;
+        self.mono_keys.clear();
+        self.portamento_control = None;
+        self.last_pitch = None;
+        self.last_data_type = match state.last_data_type {
+            1 => DataType::Rpn,
+            2 => DataType::Nrpn,
+            _ => DataType::None,
+        };
+    }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_bool(self.is_percussion_channel);
+        hasher.write_i32(self.bank_number);
+        hasher.write_i32(self.patch_number);
+        for value in [
+            self.modulation,
+            self.volume,
+            self.pan,
+            self.expression,
+            self.rpn,
+            self.nrpn,
+            self.pitch_bend_range,
+            self.coarse_tune,
+            self.fine_tune,
+        ] {
+            hasher.write(&value.to_le_bytes());
+        }
+        hasher.write_bool(self.hold_pedal);
+        hasher.write_bool(self.sostenuto);
+        hasher.write_bool(self.soft_pedal);
+        hasher.write(&[self.reverb_send, self.chorus_send]);
+        hasher.write(&[self.tuning_program, self.tuning_bank]);
+        for value in self.scale_tuning {
+            hasher.write_f32(value);
+        }
+        for value in self.nrpn_offsets {
+            hasher.write(&value.to_le_bytes());
+        }
+        hasher.write(&self.controllers);
+        hasher.write(&[self.channel_pressure]);
+        hasher.write(&self.key_pressure);
+        hasher.write_f32(self.pitch_bend);
+        hasher.write(&[match self.last_data_type {
+            DataType::None => 0,
+            DataType::Rpn => 1,
+            DataType::Nrpn => 2,
+        }]);
+
+        for value in [
+            self.attack_scale,
+            self.decay_scale,
+            self.sustain_offset,
+            self.release_scale,
+            self.filter_key_tracking,
+            self.filter_velocity_tracking,
+            self.vibrato_delay_scale,
+            self.filter_cutoff_offset,
+            self.gain,
+            self.pitch_bend_range_override.unwrap_or(f32::NAN),
+        ] {
+            hasher.write_f32(value);
+        }
+        hasher.write_f32_slice(&self.key_tune);
+        hasher.write_f32_slice(&self.key_gain);
+        hasher.write_i32(self.velocity_bias);
+        hasher.write_i32(self.transpose);
+        hasher.write_bool(self.transpose_regions);
+        hasher.write_usize(self.mpe_master.map_or(usize::MAX, |master| master));
+        hasher.write_usize(self.voice_limit.unwrap_or(usize::MAX));
+        hasher.write_bool(self.mono_mode);
+        for (key, velocity) in &self.mono_keys {
+            hasher.write_i32(*key);
+            hasher.write_i32(*velocity);
+        }
+        hasher.write_i32(self.portamento_control.unwrap_or(-1));
+        hasher.write_f32(self.last_pitch.unwrap_or(f32::NAN));
+        if let Some((controller, invert)) = self.expression_link {
+            hasher.write_i32(controller);
+            hasher.write_bool(invert);
+        }
+    }
 }
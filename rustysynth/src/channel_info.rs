@@ -0,0 +1,19 @@
+/// The state of a MIDI channel, for visualization and debugging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ChannelInfo {
+    pub channel: i32,
+    /// The bank number, which is 128 on a percussion channel unless a bank select changed it.
+    pub bank: i32,
+    pub program: i32,
+    pub is_percussion: bool,
+    /// The last value of each control change, from 0 to 127.
+    pub controllers: [u8; 128],
+    /// The position of the pitch wheel, from -1 to 1, where the center is 0.
+    pub pitch_wheel: f32,
+    /// The pitch bend in semitones, which is the position of the wheel times the range.
+    pub pitch_bend: f32,
+    /// The pitch bend range in semitones.
+    pub pitch_bend_range: f32,
+    pub channel_pressure: u8,
+}
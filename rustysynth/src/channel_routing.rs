@@ -0,0 +1,141 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::synthesizer::Synthesizer;
+
+/// Maps the MIDI channels to the output channels of `Synthesizer::render_multichannel`.
+///
+/// Each MIDI channel is mixed in stereo as usual, including its pan,
+/// and each route adds the left and right of the mix to an output with the given gains.
+/// A channel can have any number of routes, and a channel without routes is not heard.
+///
+/// The reverb and chorus are shared by all the channels, as in the stereo rendering.
+/// Their stereo return is routed to the outputs by the effect routes in the same way.
+/// Alternatively, `set_channel_effects` gives each channel its own reverb and chorus.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ChannelRouting {
+    output_count: usize,
+    channel_routes: Vec<Vec<Route>>,
+    effect_routes: Vec<Route>,
+    channel_effects: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Route {
+    pub(crate) output: usize,
+    pub(crate) left_gain: f32,
+    pub(crate) right_gain: f32,
+}
+
+impl ChannelRouting {
+    /// Creates a routing to `output_count` outputs, without any route.
+    /// The number of the outputs must be positive.
+    pub fn new(output_count: usize) -> Self {
+        if output_count == 0 {
+            panic!("The number of the outputs must be a positive value.");
+        }
+
+        Self {
+            output_count,
+            channel_routes: vec![Vec::new(); Synthesizer::CHANNEL_COUNT],
+            effect_routes: Vec::new(),
+            channel_effects: false,
+        }
+    }
+
+    /// Creates a routing to two outputs, the left and right,
+    /// which renders the same as `Synthesizer::render`.
+    pub fn stereo() -> Self {
+        let mut routing = ChannelRouting::new(2);
+        for channel in 0..Synthesizer::CHANNEL_COUNT as i32 {
+            routing.add_channel_route(channel, 0, 1_f32, 0_f32);
+            routing.add_channel_route(channel, 1, 0_f32, 1_f32);
+        }
+        routing.add_effect_route(0, 1_f32, 0_f32);
+        routing.add_effect_route(1, 0_f32, 1_f32);
+        routing
+    }
+
+    /// Adds the left and right of the channel's mix, scaled by the gains, to the output.
+    ///
+    /// For a mono speaker, gains of 0.5 each sum the channel to mono.
+    pub fn add_channel_route(
+        &mut self,
+        channel: i32,
+        output: usize,
+        left_gain: f32,
+        right_gain: f32,
+    ) -> &mut Self {
+        if !(0 <= channel && channel < Synthesizer::CHANNEL_COUNT as i32) {
+            panic!("The channel must be in the range of 0 to 15.");
+        }
+        if output >= self.output_count {
+            panic!("The output must be less than the number of the outputs.");
+        }
+
+        self.channel_routes[channel as usize].push(Route {
+            output,
+            left_gain,
+            right_gain,
+        });
+        self
+    }
+
+    /// Adds the left and right of the reverb and chorus return, scaled by the gains, to the output.
+    pub fn add_effect_route(
+        &mut self,
+        output: usize,
+        left_gain: f32,
+        right_gain: f32,
+    ) -> &mut Self {
+        if output >= self.output_count {
+            panic!("The output must be less than the number of the outputs.");
+        }
+
+        self.effect_routes.push(Route {
+            output,
+            left_gain,
+            right_gain,
+        });
+        self
+    }
+
+    /// Sets whether each channel runs its own reverb and chorus.
+    ///
+    /// If true, the return of a channel's effects is added to its mix before the channel routes,
+    /// and the effect routes are ignored. The effects keep the settings and the effect sends of the
+    /// global ones, and have no effect if the reverb and chorus are disabled.
+    pub fn set_channel_effects(&mut self, value: bool) -> &mut Self {
+        self.channel_effects = value;
+        self
+    }
+
+    /// Gets whether each channel runs its own reverb and chorus.
+    pub fn get_channel_effects(&self) -> bool {
+        self.channel_effects
+    }
+
+    /// Removes all the routes of the channel.
+    pub fn clear_channel_routes(&mut self, channel: i32) {
+        if !(0 <= channel && channel < Synthesizer::CHANNEL_COUNT as i32) {
+            return;
+        }
+
+        self.channel_routes[channel as usize].clear();
+    }
+
+    /// Gets the number of the outputs.
+    pub fn get_output_count(&self) -> usize {
+        self.output_count
+    }
+
+    pub(crate) fn get_channel_routes(&self, channel: usize) -> &[Route] {
+        &self.channel_routes[channel][..]
+    }
+
+    pub(crate) fn get_effect_routes(&self) -> &[Route] {
+        &self.effect_routes[..]
+    }
+}
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use std::f64::consts;
+use alloc::vec::Vec;
+use core::f64::consts;
+
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::state_hasher::StateHasher;
 
 #[derive(Debug, Clone)]
 #[non_exhaustive]
@@ -14,33 +19,87 @@ pub(crate) struct Chorus {
 
     delay_table_index_l: usize,
     delay_table_index_r: usize,
+
+    sample_rate: i32,
+    delay: f64,
+    depth: f64,
+    frequency: f64,
+    feedback: f32,
+
+    // The left and right inputs are summed, so that both delay lines carry the same signal.
+    mono: bool,
+
+    denormal_threshold: f32,
 }
 
 impl Chorus {
-    pub(crate) fn new(sample_rate: i32, delay: f64, depth: f64, frequency: f64) -> Self {
-        let buffer_l = vec![0_f32; ((sample_rate as f64) * (delay + depth)) as usize + 2];
-        let buffer_r = vec![0_f32; ((sample_rate as f64) * (delay + depth)) as usize + 2];
-
-        let mut delay_table = vec![0_f32; ((sample_rate as f64) / frequency).round() as usize];
-        let delay_table_length = delay_table.len();
-        for (t, input) in delay_table.iter_mut().enumerate().take(delay_table_length) {
-            let phase = 2.0 * consts::PI * (t as f64) / (delay_table_length as f64);
-            *input = ((sample_rate as f64) * (delay + depth * phase.sin())) as f32;
-        }
+    // Values smaller than this are flushed to zero when written into the delay lines.
+    const DENORMAL_THRESHOLD: f32 = 1.0E-6;
+
+    // The range of the rate of the modulation in Hz.
+    pub(crate) const MIN_RATE: f64 = 0.1;
+    pub(crate) const MAX_RATE: f64 = 10.0;
+
+    pub(crate) fn new(
+        sample_rate: i32,
+        delay: f64,
+        depth: f64,
+        frequency: f64,
+        flush_denormals: bool,
+    ) -> Self {
+        // The buffers are long enough for the depth to be raised up to the delay later.
+        let length = ((sample_rate as f64) * (delay + depth.max(delay))) as usize + 2;
+        let buffer_l = vec![0_f32; length];
+        let buffer_r = vec![0_f32; length];
 
         let buffer_index: usize = 0;
 
-        let delay_table_index_l: usize = 0;
-        let delay_table_index_r: usize = delay_table_length / 4;
+        // The delay table has room for the lowest rate, so that changing the rate does not allocate.
+        let delay_table_capacity =
+            ((sample_rate as f64) / frequency.min(Chorus::MIN_RATE)).round() as usize;
 
-        Self {
+        let mut chorus = Self {
             buffer_l,
             buffer_r,
-            delay_table,
+            delay_table: Vec::with_capacity(delay_table_capacity),
             buffer_index,
-            delay_table_index_l,
-            delay_table_index_r,
+            delay_table_index_l: 0,
+            delay_table_index_r: 0,
+            sample_rate,
+            delay,
+            depth,
+            frequency,
+            feedback: 0_f32,
+            mono: false,
+            denormal_threshold: if flush_denormals {
+                Chorus::DENORMAL_THRESHOLD
+            } else {
+                0_f32
+            },
+        };
+
+        chorus.update_delay_table();
+        chorus.delay_table_index_r = chorus.delay_table.len() / 4;
+
+        chorus
+    }
+
+    // Rebuilds the delay modulation, keeping the phases of the left and right.
+    fn update_delay_table(&mut self) {
+        let previous_length = self.delay_table.len();
+        let delay_table_length = ((self.sample_rate as f64) / self.frequency).round() as usize;
+        self.delay_table.resize(delay_table_length, 0_f32);
+        for (t, input) in self.delay_table.iter_mut().enumerate() {
+            let phase = 2.0 * consts::PI * (t as f64) / (delay_table_length as f64);
+            *input = ((self.sample_rate as f64) * (self.delay + self.depth * phase.sin())) as f32;
         }
+
+        self.delay_table_index_l = (self.delay_table_index_l * delay_table_length)
+            .checked_div(previous_length)
+            .unwrap_or(0);
+        self.delay_table_index_r = (self.delay_table_index_r * delay_table_length)
+            .checked_div(previous_length)
+            .unwrap_or(0);
     }
 
     pub(crate) fn process(
@@ -103,8 +162,23 @@ impl Chorus {
                 }
             }
 
-            self.buffer_l[self.buffer_index] = input_left[t];
-            self.buffer_r[self.buffer_index] = input_right[t];
+            let (send_l, send_r) = if self.mono {
+                let send = 0.5_f32 * (input_left[t] + input_right[t]);
+                (send, send)
+            } else {
+                (input_left[t], input_right[t])
+            };
+            let mut input_l = send_l + self.feedback * output_left[t];
+            if input_l.abs() < self.denormal_threshold {
+                input_l = 0_f32;
+            }
+            let mut input_r = send_r + self.feedback * output_right[t];
+            if input_r.abs() < self.denormal_threshold {
+                input_r = 0_f32;
+            }
+
+            self.buffer_l[self.buffer_index] = input_l;
+            self.buffer_r[self.buffer_index] = input_r;
             self.buffer_index += 1;
             if self.buffer_index == buffer_length {
                 self.buffer_index = 0;
@@ -112,6 +186,45 @@ impl Chorus {
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn heap_size(&self) -> usize {
+        size_of::<f32>() * (self.buffer_l.len() + self.buffer_r.len() + self.delay_table.len())
+    }
+
+    // Feeds the same input to the left and right, and modulates their delays in phase,
+    // so that the output does not comb when summed to mono.
+    pub(crate) fn set_mono(&mut self) {
+        self.mono = true;
+        self.delay_table_index_r = self.delay_table_index_l;
+    }
+
+    pub(crate) fn get_rate(&self) -> f64 {
+        self.frequency
+    }
+
+    pub(crate) fn set_rate(&mut self, frequency: f64) {
+        self.frequency = frequency;
+        self.update_delay_table();
+    }
+
+    pub(crate) fn get_depth(&self) -> f64 {
+        self.depth / self.delay
+    }
+
+    // The depth is relative to the delay, so that the delay never goes below zero.
+    pub(crate) fn set_depth(&mut self, value: f64) {
+        self.depth = value * self.delay;
+        self.update_delay_table();
+    }
+
+    pub(crate) fn get_feedback(&self) -> f32 {
+        self.feedback
+    }
+
+    pub(crate) fn set_feedback(&mut self, value: f32) {
+        self.feedback = value;
+    }
+
     pub(crate) fn mute(&mut self) {
         let buffer_length = self.buffer_l.len();
 
@@ -123,4 +236,15 @@ impl Chorus {
             self.buffer_r[t] = 0_f32;
         }
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_f32_slice(&self.buffer_l);
+        hasher.write_f32_slice(&self.buffer_r);
+        hasher.write_usize(self.buffer_index);
+        hasher.write_usize(self.delay_table_index_l);
+        hasher.write_usize(self.delay_table_index_r);
+        hasher.write_f64(self.frequency);
+        hasher.write_f64(self.depth);
+        hasher.write_f32(self.feedback);
+    }
 }
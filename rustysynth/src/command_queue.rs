@@ -0,0 +1,139 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, TryLockError};
+
+use crate::queue_overflow::QueueOverflow;
+
+/// A bounded queue of MIDI messages, sent from other threads and processed by the render thread.
+///
+/// The queue can be shared between threads with `Arc`.
+/// The senders call `push`, and the render thread passes the queue to
+/// `Synthesizer::process_queue` before rendering.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CommandQueue {
+    messages: Mutex<VecDeque<[i32; 4]>>,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: QueueOverflow,
+    dropped_count: AtomicU64,
+}
+
+impl CommandQueue {
+    /// Creates a queue which holds up to `capacity` messages. The capacity must be positive.
+    pub fn new(capacity: usize, overflow: QueueOverflow) -> Self {
+        if capacity == 0 {
+            panic!("The capacity of the queue must be a positive value.");
+        }
+
+        Self {
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+            not_full: Condvar::new(),
+            capacity,
+            overflow,
+            dropped_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds a MIDI message to the queue, in the same form as `Synthesizer::process_midi_message`.
+    ///
+    /// Returns `false` if the message was discarded because the queue was full.
+    /// With `QueueOverflow::Block`, this waits for room and always returns `true`.
+    pub fn push(&self, channel: i32, command: i32, data1: i32, data2: i32) -> bool {
+        let message = [channel, command, data1, data2];
+
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() < self.capacity {
+            messages.push_back(message);
+            return true;
+        }
+
+        match self.overflow {
+            QueueOverflow::DropOldest => {
+                messages.pop_front();
+                messages.push_back(message);
+                self.dropped_count.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            QueueOverflow::DropNewest => {
+                self.dropped_count.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            QueueOverflow::Block => {
+                let mut messages = self
+                    .not_full
+                    .wait_while(messages, |messages| messages.len() >= self.capacity)
+                    .unwrap();
+                messages.push_back(message);
+                true
+            }
+        }
+    }
+
+    // Takes all the messages in the queue, in the order they were pushed,
+    // or none if a sender holds the lock, so that the render thread is never blocked.
+    pub(crate) fn drain(&self, f: impl FnMut([i32; 4])) {
+        let mut messages = match self.messages.try_lock() {
+            Ok(messages) => messages,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        messages.drain(..).for_each(f);
+        drop(messages);
+        self.not_full.notify_all();
+    }
+
+    /// Gets the number of the messages waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.messages.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no message is waiting in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_overflow(&self) -> QueueOverflow {
+        self.overflow
+    }
+
+    /// Gets the number of the messages discarded so far because the queue was full.
+    pub fn get_dropped_count(&self) -> u64 {
+        self.dropped_count.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain_keys(queue: &CommandQueue) -> Vec<i32> {
+        let mut keys: Vec<i32> = Vec::new();
+        queue.drain(|message| keys.push(message[2]));
+        keys
+    }
+
+    #[test]
+    fn test_overflow() {
+        let queue = CommandQueue::new(2, QueueOverflow::DropOldest);
+        assert!(queue.push(0, 0x90, 60, 100));
+        assert!(queue.push(0, 0x90, 62, 100));
+        assert!(queue.push(0, 0x90, 64, 100));
+        assert_eq!(queue.get_dropped_count(), 1);
+        assert_eq!(drain_keys(&queue), vec![62, 64]);
+
+        let queue = CommandQueue::new(2, QueueOverflow::DropNewest);
+        assert!(queue.push(0, 0x90, 60, 100));
+        assert!(queue.push(0, 0x90, 62, 100));
+        assert!(!queue.push(0, 0x90, 64, 100));
+        assert_eq!(queue.get_dropped_count(), 1);
+        assert_eq!(drain_keys(&queue), vec![60, 62]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+#![allow(dead_code)]
+
+use crate::dither_mode::DitherMode;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::random::Random;
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub(crate) struct Dither {
+    pub(crate) mode: DitherMode,
+    random: Random,
+
+    // The quantization errors of the previous samples, used for the noise shaping.
+    error_left: f32,
+    error_right: f32,
+}
+
+impl Dither {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            mode: DitherMode::None,
+            random: Random::new(seed),
+            error_left: 0_f32,
+            error_right: 0_f32,
+        }
+    }
+
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+        self.error_left = 0_f32;
+        self.error_right = 0_f32;
+    }
+
+    pub(crate) fn process(
+        &mut self,
+        left: &[f32],
+        right: &[f32],
+        destination_left: &mut [i16],
+        destination_right: &mut [i16],
+    ) {
+        for (x, y) in left.iter().zip(destination_left.iter_mut()) {
+            let mut error = self.error_left;
+            *y = self.convert(*x, &mut error);
+            self.error_left = error;
+        }
+        for (x, y) in right.iter().zip(destination_right.iter_mut()) {
+            let mut error = self.error_right;
+            *y = self.convert(*x, &mut error);
+            self.error_right = error;
+        }
+    }
+
+    // The same as process, but into the interleaved frames of the destination.
+    pub(crate) fn process_interleaved(
+        &mut self,
+        left: &[f32],
+        right: &[f32],
+        destination: &mut [i16],
+    ) {
+        for (x, y) in left.iter().zip(destination.iter_mut().step_by(2)) {
+            let mut error = self.error_left;
+            *y = self.convert(*x, &mut error);
+            self.error_left = error;
+        }
+        for (x, y) in right.iter().zip(destination.iter_mut().skip(1).step_by(2)) {
+            let mut error = self.error_right;
+            *y = self.convert(*x, &mut error);
+            self.error_right = error;
+        }
+    }
+
+    fn convert(&mut self, x: f32, error: &mut f32) -> i16 {
+        let x = 32768_f32 * x;
+        let value = match self.mode {
+            DitherMode::None => x,
+            DitherMode::Rectangular => x + self.random.next_f32() - 0.5_f32,
+            DitherMode::Triangular => x + self.random.next_f32() - self.random.next_f32(),
+            DitherMode::Shaped => {
+                let shaped = x - *error;
+                let value = shaped + self.random.next_f32() - self.random.next_f32();
+                *error = value.round().clamp(-32768_f32, 32767_f32) - shaped;
+                value
+            }
+        };
+
+        value.round().clamp(-32768_f32, 32767_f32) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The mean of the output for a constant input of a quarter of the least significant bit.
+    fn quarter_lsb_mean(mode: DitherMode) -> f32 {
+        let mut dither = Dither::new(1);
+        dither.mode = mode;
+        let input = vec![0.25_f32 / 32768_f32; 10000];
+        let mut left = vec![0_i16; 10000];
+        let mut right = vec![0_i16; 10000];
+        dither.process(&input, &input, &mut left, &mut right);
+        assert!(left.iter().all(|&x| (-2..=2).contains(&x)));
+        left.iter().map(|&x| x as f32).sum::<f32>() / 10000_f32
+    }
+
+    #[test]
+    fn test_dither_mode() {
+        // Without the dither, the level is lost, and the dither keeps it on average.
+        assert_eq!(quarter_lsb_mean(DitherMode::None), 0_f32);
+        for mode in [
+            DitherMode::Rectangular,
+            DitherMode::Triangular,
+            DitherMode::Shaped,
+        ] {
+            assert!((quarter_lsb_mean(mode) - 0.25_f32).abs() < 0.05_f32);
+        }
+    }
+}
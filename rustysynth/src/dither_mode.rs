@@ -0,0 +1,14 @@
+/// Specifies how the audio is dithered when converted to 16-bit integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherMode {
+    /// The samples are rounded to the nearest integer without dither.
+    /// This is the default behavior.
+    None,
+    /// Noise with a rectangular probability density of 1 LSB is added before rounding.
+    Rectangular,
+    /// Noise with a triangular probability density of 2 LSB is added before rounding.
+    Triangular,
+    /// Triangular dither with first-order noise shaping,
+    /// which moves the quantization noise towards the high frequencies.
+    Shaped,
+}
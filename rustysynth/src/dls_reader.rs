@@ -0,0 +1,994 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::error::SoundFontError;
+use crate::four_cc::FourCC;
+use crate::generator_type::GeneratorType;
+use crate::sfz_instrument::decode_pcm;
+use crate::soundfont::SoundFont;
+use crate::soundfont_builder::{push_address_offset, SoundFontBuilder};
+
+// A wave of the wave pool, decoded to 16 bits.
+#[derive(Debug)]
+struct DlsWave {
+    name: String,
+    channels: Vec<Vec<i16>>,
+    sample_rate: u32,
+    sample: Option<WaveSample>,
+}
+
+// The contents of a 'wsmp' chunk.
+#[derive(Debug, Clone, Copy)]
+struct WaveSample {
+    unity_note: u16,
+    fine_tune: i16,
+    gain: i32,
+    // The type, the start and the length of the first loop.
+    loop_points: Option<(u32, u32, u32)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Connection {
+    source: u16,
+    control: u16,
+    destination: u16,
+    scale: i32,
+}
+
+#[derive(Debug)]
+struct DlsRegion {
+    key_range: (u16, u16),
+    velocity_range: (u16, u16),
+    key_group: u16,
+    sample: Option<WaveSample>,
+    phase_group: u16,
+    channel: u32,
+    table_index: u32,
+    connections: Option<Vec<Connection>>,
+}
+
+#[derive(Debug)]
+struct DlsInstrument {
+    name: String,
+    bank: u32,
+    program: u32,
+    regions: Vec<DlsRegion>,
+    connections: Vec<Connection>,
+}
+
+// Reads a DLS level 1 or 2 file, and converts its instruments into the presets of a SoundFont.
+#[allow(unused)]
+#[non_exhaustive]
+pub(crate) struct DlsReader {}
+
+impl DlsReader {
+    const DRUM_FLAG: u32 = 0x8000_0000;
+    const LOOP_RELEASE: u32 = 1;
+
+    // The sources of the connections.
+    const SRC_NONE: u16 = 0x0000;
+    const SRC_LFO: u16 = 0x0001;
+    const SRC_KEY_ON_VELOCITY: u16 = 0x0002;
+    const SRC_KEY_NUMBER: u16 = 0x0003;
+    const SRC_EG2: u16 = 0x0005;
+    const SRC_PITCH_WHEEL: u16 = 0x0006;
+    const SRC_VIBRATO: u16 = 0x0009;
+    const SRC_CC1: u16 = 0x0081;
+    const SRC_CC7: u16 = 0x0087;
+    const SRC_CC10: u16 = 0x008A;
+    const SRC_CC11: u16 = 0x008B;
+    const SRC_CC91: u16 = 0x00DB;
+    const SRC_CC93: u16 = 0x00DD;
+    const SRC_RPN0: u16 = 0x0100;
+
+    // The destinations of the connections.
+    const DST_ATTENUATION: u16 = 0x0001;
+    const DST_PITCH: u16 = 0x0003;
+    const DST_PAN: u16 = 0x0004;
+    const DST_CHORUS: u16 = 0x0080;
+    const DST_REVERB: u16 = 0x0081;
+    const DST_LFO_FREQUENCY: u16 = 0x0104;
+    const DST_LFO_START_DELAY: u16 = 0x0105;
+    const DST_VIB_FREQUENCY: u16 = 0x0114;
+    const DST_VIB_START_DELAY: u16 = 0x0115;
+    const DST_EG1_ATTACK_TIME: u16 = 0x0206;
+    const DST_EG1_DECAY_TIME: u16 = 0x0207;
+    const DST_EG1_RELEASE_TIME: u16 = 0x0209;
+    const DST_EG1_SUSTAIN_LEVEL: u16 = 0x020A;
+    const DST_EG1_DELAY_TIME: u16 = 0x020B;
+    const DST_EG1_HOLD_TIME: u16 = 0x020C;
+    const DST_EG2_ATTACK_TIME: u16 = 0x030A;
+    const DST_EG2_DECAY_TIME: u16 = 0x030B;
+    const DST_EG2_RELEASE_TIME: u16 = 0x030D;
+    const DST_EG2_SUSTAIN_LEVEL: u16 = 0x030E;
+    const DST_EG2_DELAY_TIME: u16 = 0x030F;
+    const DST_EG2_HOLD_TIME: u16 = 0x0310;
+    const DST_FILTER_CUTOFF: u16 = 0x0500;
+    const DST_FILTER_Q: u16 = 0x0501;
+
+    // The filter cutoff which disables the filter.
+    const FILTER_DISABLED: i32 = 0x7FFF_FFFF;
+
+    pub(crate) fn read<R: Read>(reader: &mut R) -> Result<SoundFont, SoundFontError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut warnings: Vec<String> = Vec::new();
+        if data.len() < 12 || &data[..4] != b"RIFF" {
+            return Err(SoundFontError::RiffChunkNotFound);
+        }
+        let size = u32_at(&data, 4) as usize;
+        let body = &data[8..(8 + size).clamp(12, data.len())];
+        if &body[..4] != b"DLS " {
+            return Err(SoundFontError::InvalidRiffChunkType {
+                expected: FourCC::from_bytes(*b"DLS "),
+                actual: FourCC::from_bytes(body[..4].try_into().unwrap()),
+            });
+        }
+
+        let mut name = "DLS".to_string();
+        let mut instruments: Option<Vec<DlsInstrument>> = None;
+        let mut pool_table: Option<Vec<u32>> = None;
+        let mut waves: Option<Vec<(u32, DlsWave)>> = None;
+        for (id, chunk) in sub_chunks(&body[4..], &mut warnings) {
+            match (&id, list_type(&id, chunk)) {
+                (b"LIST", Some(b"lins")) => {
+                    instruments = Some(DlsReader::read_instruments(&chunk[4..], &mut warnings));
+                }
+                (b"LIST", Some(b"wvpl")) => {
+                    waves = Some(DlsReader::read_waves(&chunk[4..], &mut warnings));
+                }
+                (b"LIST", Some(b"INFO")) => {
+                    if let Some(value) = read_name(&chunk[4..], &mut warnings) {
+                        name = value;
+                    }
+                }
+                (b"ptbl", _) if chunk.len() >= 8 => {
+                    let header_size = u32_at(chunk, 0) as usize;
+                    let count = u32_at(chunk, 4) as usize;
+                    let cues = chunk.get(header_size.max(8)..).unwrap_or(&[]);
+                    pool_table = Some(
+                        cues.chunks_exact(4)
+                            .take(count)
+                            .map(|cue| u32_at(cue, 0))
+                            .collect(),
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        let instruments = instruments.ok_or(SoundFontError::SubChunkNotFound(
+            FourCC::from_bytes(*b"lins"),
+        ))?;
+        let waves = waves.ok_or(SoundFontError::SampleDataNotFound)?;
+
+        // The pool table gives the offset of each wave in the wave pool,
+        // and the waves are taken in order if it is missing.
+        let wave_indices: Vec<Option<usize>> = match pool_table {
+            Some(pool_table) => {
+                let by_offset: HashMap<u32, usize> = waves
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (offset, _))| (*offset, index))
+                    .collect();
+                pool_table
+                    .iter()
+                    .map(|offset| by_offset.get(offset).copied())
+                    .collect()
+            }
+            None => {
+                warnings.push("the pool table was not found".to_string());
+                (0..waves.len()).map(Some).collect()
+            }
+        };
+
+        // Each wave is added as a sample, whose loop and tuning are set by the zones playing it.
+        let mut builder = SoundFontBuilder::new(&name);
+        let sample_ids: Vec<usize> = waves
+            .iter()
+            .map(|(_, wave)| {
+                let ids: Vec<usize> = wave
+                    .channels
+                    .iter()
+                    .map(|channel| {
+                        builder.add_sample(&wave.name, channel, wave.sample_rate as i32, 60, None)
+                    })
+                    .collect();
+                if ids.len() == 2 {
+                    builder.link_samples(ids[0], ids[1]);
+                }
+                ids[0]
+            })
+            .collect();
+
+        let mut unsupported = false;
+        for instrument in &instruments {
+            let mut zones: Vec<(usize, Vec<(u16, i16)>)> = Vec::new();
+            for region in &instrument.regions {
+                let wave_index = wave_indices
+                    .get(region.table_index as usize)
+                    .copied()
+                    .flatten();
+                let wave_index = match wave_index {
+                    Some(wave_index) => wave_index,
+                    None => {
+                        warnings.push(format!(
+                            "the region of the instrument '{}' with an invalid wave {} was skipped",
+                            instrument.name, region.table_index
+                        ));
+                        continue;
+                    }
+                };
+
+                let wave = &waves[wave_index].1;
+                let connections = region
+                    .connections
+                    .as_deref()
+                    .unwrap_or(&instrument.connections);
+                let generators =
+                    DlsReader::create_zone(region, wave, connections, &mut unsupported);
+                zones.push((sample_ids[wave_index], generators));
+            }
+
+            if zones.is_empty() {
+                warnings.push(format!(
+                    "the instrument '{}' without any region was skipped",
+                    instrument.name
+                ));
+                continue;
+            }
+
+            let (bank, patch) = if instrument.bank & DlsReader::DRUM_FLAG != 0 {
+                (128, instrument.program & 0x7F)
+            } else {
+                ((instrument.bank >> 8) & 0x7F, instrument.program & 0x7F)
+            };
+            let index = builder.add_instrument(&instrument.name);
+            for (sample, generators) in &zones {
+                builder.add_instrument_zone(index, *sample, generators);
+            }
+            let preset = builder.add_preset(&instrument.name, bank as i32, patch as i32);
+            builder.add_preset_zone(preset, index, &[]);
+        }
+
+        if unsupported {
+            warnings.push("the unsupported articulation connections were skipped".to_string());
+        }
+
+        let mut sound_font = builder.build()?;
+        sound_font.warnings.extend(warnings);
+        Ok(sound_font)
+    }
+
+    fn create_zone(
+        region: &DlsRegion,
+        wave: &DlsWave,
+        connections: &[Connection],
+        unsupported: &mut bool,
+    ) -> Vec<(u16, i16)> {
+        let sample = region.sample.or(wave.sample).unwrap_or(WaveSample {
+            unity_note: 60,
+            fine_tune: 0,
+            gain: 0,
+            loop_points: None,
+        });
+
+        // The sample loops over the whole wave, which the zone moves to the loop points.
+        let length = wave.channels[0].len() as u32;
+        let (sample_modes, start_loop, end_loop) = match sample.loop_points {
+            Some((loop_type, start, loop_length)) => {
+                let end = start.saturating_add(loop_length).min(length);
+                let start = start.min(end);
+                let mode = if loop_type == DlsReader::LOOP_RELEASE {
+                    3
+                } else {
+                    1
+                };
+                if start < end {
+                    (mode, start, end)
+                } else {
+                    (0, 0, length)
+                }
+            }
+            None => (0, 0, length),
+        };
+
+        // DLS level 1 leaves the velocity range unused, which is then all zeros.
+        let (velocity_start, velocity_end) = match region.velocity_range {
+            (0, 0) => (0, 127),
+            range => range,
+        };
+        let mut generators = vec![
+            (
+                GeneratorType::KEY_RANGE,
+                (region.key_range.0.min(127) | region.key_range.1.min(127) << 8) as i16,
+            ),
+            (
+                GeneratorType::VELOCITY_RANGE,
+                (velocity_start.min(127) | velocity_end.min(127) << 8) as i16,
+            ),
+            (GeneratorType::SAMPLE_MODES, sample_modes),
+            (
+                GeneratorType::OVERRIDING_ROOT_KEY,
+                sample.unity_note.min(127) as i16,
+            ),
+        ];
+        push_address_offset(
+            &mut generators,
+            GeneratorType::START_LOOP_ADDRESS_OFFSET,
+            GeneratorType::START_LOOP_ADDRESS_COARSE_OFFSET,
+            start_loop as i32,
+        );
+        push_address_offset(
+            &mut generators,
+            GeneratorType::END_LOOP_ADDRESS_OFFSET,
+            GeneratorType::END_LOOP_ADDRESS_COARSE_OFFSET,
+            end_loop as i32 - length as i32,
+        );
+        if region.key_group > 0 {
+            let key_group = region.key_group.min(127) as i16;
+            generators.push((GeneratorType::EXCLUSIVE_CLASS, key_group));
+        }
+
+        // The gains are in 1/65536 centibels, and the times in 1/65536 timecents.
+        let mut gain = sample.gain as f64 / 65536_f64;
+        let mut pitch = sample.fine_tune as f64;
+        let mut pan = match (region.phase_group, region.channel) {
+            (0, _) => 0_f64,
+            (_, 1) => -500_f64,
+            (_, 2) => 500_f64,
+            _ => 0_f64,
+        };
+        let mut keyed_times: [(u16, u16, f64, f64); 4] = [
+            (
+                GeneratorType::HOLD_VOLUME_ENVELOPE,
+                GeneratorType::KEY_NUMBER_TO_VOLUME_ENVELOPE_HOLD,
+                -12000_f64,
+                0_f64,
+            ),
+            (
+                GeneratorType::DECAY_VOLUME_ENVELOPE,
+                GeneratorType::KEY_NUMBER_TO_VOLUME_ENVELOPE_DECAY,
+                -12000_f64,
+                0_f64,
+            ),
+            (
+                GeneratorType::HOLD_MODULATION_ENVELOPE,
+                GeneratorType::KEY_NUMBER_TO_MODULATION_ENVELOPE_HOLD,
+                -12000_f64,
+                0_f64,
+            ),
+            (
+                GeneratorType::DECAY_MODULATION_ENVELOPE,
+                GeneratorType::KEY_NUMBER_TO_MODULATION_ENVELOPE_DECAY,
+                -12000_f64,
+                0_f64,
+            ),
+        ];
+        let keyed_index = |destination: u16| match destination {
+            DlsReader::DST_EG1_HOLD_TIME => Some(0),
+            DlsReader::DST_EG1_DECAY_TIME => Some(1),
+            DlsReader::DST_EG2_HOLD_TIME => Some(2),
+            DlsReader::DST_EG2_DECAY_TIME => Some(3),
+            _ => None,
+        };
+
+        for connection in connections {
+            let value = connection.scale as f64 / 65536_f64;
+            let time = if connection.scale == i32::MIN {
+                -12000_f64
+            } else {
+                value.clamp(-12000_f64, 8000_f64)
+            };
+
+            let destination = connection.destination;
+            match (connection.source, connection.control, destination) {
+                (DlsReader::SRC_NONE, 0, _) => match destination {
+                    DlsReader::DST_ATTENUATION => gain += value,
+                    DlsReader::DST_PITCH => pitch += value,
+                    DlsReader::DST_PAN => pan += value,
+                    DlsReader::DST_CHORUS | DlsReader::DST_REVERB => {
+                        let generator_type = if destination == DlsReader::DST_CHORUS {
+                            GeneratorType::CHORUS_EFFECTS_SEND
+                        } else {
+                            GeneratorType::REVERB_EFFECTS_SEND
+                        };
+                        generators.push((generator_type, value.clamp(0_f64, 1000_f64) as i16));
+                    }
+                    DlsReader::DST_EG1_SUSTAIN_LEVEL => {
+                        let level = value.clamp(0_f64, 1000_f64) / 1000_f64;
+                        let attenuation = if level > 0_f64 {
+                            (-200_f64 * level.log10()).min(1440_f64)
+                        } else {
+                            1440_f64
+                        };
+                        generators
+                            .push((GeneratorType::SUSTAIN_VOLUME_ENVELOPE, attenuation as i16));
+                    }
+                    DlsReader::DST_EG2_SUSTAIN_LEVEL => {
+                        let decrease = 1000_f64 - value.clamp(0_f64, 1000_f64);
+                        generators
+                            .push((GeneratorType::SUSTAIN_MODULATION_ENVELOPE, decrease as i16));
+                    }
+                    DlsReader::DST_FILTER_CUTOFF => {
+                        if connection.scale != DlsReader::FILTER_DISABLED {
+                            let cents = value.clamp(1500_f64, 13500_f64) as i16;
+                            generators
+                                .push((GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY, cents));
+                        }
+                    }
+                    DlsReader::DST_FILTER_Q => {
+                        let q = value.clamp(0_f64, 960_f64) as i16;
+                        generators.push((GeneratorType::INITIAL_FILTER_Q, q));
+                    }
+                    DlsReader::DST_LFO_FREQUENCY | DlsReader::DST_VIB_FREQUENCY => {
+                        let generator_type = if destination == DlsReader::DST_LFO_FREQUENCY {
+                            GeneratorType::FREQUENCY_MODULATION_LFO
+                        } else {
+                            GeneratorType::FREQUENCY_VIBRATO_LFO
+                        };
+                        generators.push((generator_type, value.clamp(-16000_f64, 4500_f64) as i16));
+                    }
+                    _ => {
+                        let generator_type = match destination {
+                            DlsReader::DST_LFO_START_DELAY => GeneratorType::DELAY_MODULATION_LFO,
+                            DlsReader::DST_VIB_START_DELAY => GeneratorType::DELAY_VIBRATO_LFO,
+                            DlsReader::DST_EG1_DELAY_TIME => GeneratorType::DELAY_VOLUME_ENVELOPE,
+                            DlsReader::DST_EG1_ATTACK_TIME => GeneratorType::ATTACK_VOLUME_ENVELOPE,
+                            DlsReader::DST_EG1_RELEASE_TIME => {
+                                GeneratorType::RELEASE_VOLUME_ENVELOPE
+                            }
+                            DlsReader::DST_EG2_DELAY_TIME => {
+                                GeneratorType::DELAY_MODULATION_ENVELOPE
+                            }
+                            DlsReader::DST_EG2_ATTACK_TIME => {
+                                GeneratorType::ATTACK_MODULATION_ENVELOPE
+                            }
+                            DlsReader::DST_EG2_RELEASE_TIME => {
+                                GeneratorType::RELEASE_MODULATION_ENVELOPE
+                            }
+                            _ => match keyed_index(destination) {
+                                Some(index) => {
+                                    keyed_times[index].2 = time;
+                                    continue;
+                                }
+                                None => {
+                                    *unsupported = true;
+                                    continue;
+                                }
+                            },
+                        };
+                        generators.push((generator_type, time as i16));
+                    }
+                },
+                (DlsReader::SRC_LFO, 0, DlsReader::DST_PITCH) => {
+                    let cents = value.clamp(-12000_f64, 12000_f64) as i16;
+                    generators.push((GeneratorType::MODULATION_LFO_TO_PITCH, cents));
+                }
+                (DlsReader::SRC_VIBRATO, 0, DlsReader::DST_PITCH) => {
+                    let cents = value.clamp(-12000_f64, 12000_f64) as i16;
+                    generators.push((GeneratorType::VIBRATO_LFO_TO_PITCH, cents));
+                }
+                (DlsReader::SRC_LFO, 0, DlsReader::DST_ATTENUATION) => {
+                    let gain = value.clamp(-960_f64, 960_f64) as i16;
+                    generators.push((GeneratorType::MODULATION_LFO_TO_VOLUME, gain));
+                }
+                (DlsReader::SRC_LFO, 0, DlsReader::DST_FILTER_CUTOFF) => {
+                    let cents = value.clamp(-12000_f64, 12000_f64) as i16;
+                    let generator_type = GeneratorType::MODULATION_LFO_TO_FILTER_CUTOFF_FREQUENCY;
+                    generators.push((generator_type, cents));
+                }
+                (DlsReader::SRC_EG2, 0, DlsReader::DST_PITCH) => {
+                    let cents = value.clamp(-12000_f64, 12000_f64) as i16;
+                    generators.push((GeneratorType::MODULATION_ENVELOPE_TO_PITCH, cents));
+                }
+                (DlsReader::SRC_EG2, 0, DlsReader::DST_FILTER_CUTOFF) => {
+                    let cents = value.clamp(-12000_f64, 12000_f64) as i16;
+                    let generator_type =
+                        GeneratorType::MODULATION_ENVELOPE_TO_FILTER_CUTOFF_FREQUENCY;
+                    generators.push((generator_type, cents));
+                }
+                (DlsReader::SRC_KEY_NUMBER, 0, destination)
+                    if keyed_index(destination).is_some() =>
+                {
+                    // The scale is the change over the whole key range.
+                    keyed_times[keyed_index(destination).unwrap()].3 = value;
+                }
+                // The default modulators of the SoundFont already cover these connections.
+                (DlsReader::SRC_KEY_ON_VELOCITY, 0, DlsReader::DST_ATTENUATION)
+                | (DlsReader::SRC_PITCH_WHEEL, DlsReader::SRC_RPN0, DlsReader::DST_PITCH)
+                | (DlsReader::SRC_LFO, DlsReader::SRC_CC1, DlsReader::DST_PITCH)
+                | (DlsReader::SRC_VIBRATO, DlsReader::SRC_CC1, DlsReader::DST_PITCH)
+                | (DlsReader::SRC_CC7, 0, DlsReader::DST_ATTENUATION)
+                | (DlsReader::SRC_CC11, 0, DlsReader::DST_ATTENUATION)
+                | (DlsReader::SRC_CC10, 0, DlsReader::DST_PAN)
+                | (DlsReader::SRC_CC91, 0, DlsReader::DST_REVERB)
+                | (DlsReader::SRC_CC93, 0, DlsReader::DST_CHORUS) => (),
+                _ => *unsupported = true,
+            }
+        }
+
+        // The key number of DLS is scaled from 0 to 1 over the keys,
+        // while the SoundFont decreases the time by the timecents per key from the key 60.
+        for (time_type, key_type, time, scale) in keyed_times {
+            if scale != 0_f64 {
+                let time = (time + 60_f64 * scale / 128_f64).clamp(-12000_f64, 8000_f64);
+                generators.push((time_type, time as i16));
+                generators.push((
+                    key_type,
+                    (-scale / 128_f64).clamp(-1200_f64, 1200_f64) as i16,
+                ));
+            } else if time != -12000_f64 {
+                generators.push((time_type, time as i16));
+            }
+        }
+
+        // The initial attenuation is scaled by 0.4 when the voice is played, like the EMU synthesizers.
+        if gain != 0_f64 {
+            let attenuation = (-2.5_f64 * gain).round().clamp(-1440_f64, 1440_f64);
+            generators.push((GeneratorType::INITIAL_ATTENUATION, attenuation as i16));
+        }
+        let coarse_tune = (pitch / 100_f64).trunc();
+        if coarse_tune != 0_f64 {
+            let coarse_tune = coarse_tune.clamp(-120_f64, 120_f64) as i16;
+            generators.push((GeneratorType::COARSE_TUNE, coarse_tune));
+        }
+        let fine_tune = (pitch % 100_f64).round();
+        if fine_tune != 0_f64 {
+            generators.push((GeneratorType::FINE_TUNE, fine_tune as i16));
+        }
+        if pan != 0_f64 {
+            let pan = pan.round().clamp(-500_f64, 500_f64) as i16;
+            generators.push((GeneratorType::PAN, pan));
+        }
+
+        generators
+    }
+
+    fn read_instruments(data: &[u8], warnings: &mut Vec<String>) -> Vec<DlsInstrument> {
+        let mut instruments = Vec::new();
+        for (id, chunk) in sub_chunks(data, warnings) {
+            if list_type(&id, chunk) != Some(b"ins ") {
+                continue;
+            }
+
+            let mut instrument = DlsInstrument {
+                name: format!("Instrument {}", instruments.len()),
+                bank: 0,
+                program: 0,
+                regions: Vec::new(),
+                connections: Vec::new(),
+            };
+            for (id, chunk) in sub_chunks(&chunk[4..], warnings) {
+                match (&id, list_type(&id, chunk)) {
+                    (b"insh", _) if chunk.len() >= 12 => {
+                        instrument.bank = u32_at(chunk, 4);
+                        instrument.program = u32_at(chunk, 8);
+                    }
+                    (b"LIST", Some(b"lrgn")) => {
+                        for (id, chunk) in sub_chunks(&chunk[4..], warnings) {
+                            match list_type(&id, chunk) {
+                                Some(b"rgn ") | Some(b"rgn2") => {
+                                    if let Some(region) =
+                                        DlsReader::read_region(&chunk[4..], warnings)
+                                    {
+                                        instrument.regions.push(region);
+                                    }
+                                }
+                                _ => (),
+                            }
+                        }
+                    }
+                    (b"LIST", Some(b"lart")) | (b"LIST", Some(b"lar2")) => {
+                        instrument.connections = read_articulation(&chunk[4..], warnings);
+                    }
+                    (b"LIST", Some(b"INFO")) => {
+                        if let Some(name) = read_name(&chunk[4..], warnings) {
+                            instrument.name = name;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            instruments.push(instrument);
+        }
+        instruments
+    }
+
+    fn read_region(data: &[u8], warnings: &mut Vec<String>) -> Option<DlsRegion> {
+        let mut region = DlsRegion {
+            key_range: (0, 127),
+            velocity_range: (0, 127),
+            key_group: 0,
+            sample: None,
+            phase_group: 0,
+            channel: 1,
+            table_index: 0,
+            connections: None,
+        };
+        let mut has_header = false;
+        let mut has_link = false;
+        for (id, chunk) in sub_chunks(data, warnings) {
+            match (&id, list_type(&id, chunk)) {
+                (b"rgnh", _) if chunk.len() >= 12 => {
+                    region.key_range = (u16_at(chunk, 0), u16_at(chunk, 2));
+                    region.velocity_range = (u16_at(chunk, 4), u16_at(chunk, 6));
+                    region.key_group = u16_at(chunk, 10);
+                    has_header = true;
+                }
+                (b"wsmp", _) => region.sample = read_wave_sample(chunk),
+                (b"wlnk", _) if chunk.len() >= 12 => {
+                    region.phase_group = u16_at(chunk, 2);
+                    region.channel = u32_at(chunk, 4);
+                    region.table_index = u32_at(chunk, 8);
+                    has_link = true;
+                }
+                (b"LIST", Some(b"lart")) | (b"LIST", Some(b"lar2")) => {
+                    region.connections = Some(read_articulation(&chunk[4..], warnings));
+                }
+                _ => (),
+            }
+        }
+
+        if has_header && has_link {
+            Some(region)
+        } else {
+            warnings.push("the region without a header or a wave link was skipped".to_string());
+            None
+        }
+    }
+
+    // Reads the wave pool, with the offset of each wave from the start of the list data.
+    fn read_waves(data: &[u8], warnings: &mut Vec<String>) -> Vec<(u32, DlsWave)> {
+        let mut waves = Vec::new();
+        let mut offset = 0;
+        for (id, chunk) in sub_chunks(data, warnings) {
+            let position = offset;
+            offset += 8 + chunk.len() + (chunk.len() & 1);
+            if list_type(&id, chunk) != Some(b"wave") {
+                continue;
+            }
+
+            let mut format: Option<(u16, usize, u32, usize)> = None;
+            let mut samples: &[u8] = &[];
+            let mut sample = None;
+            let mut name = format!("Wave {}", waves.len());
+            for (id, chunk) in sub_chunks(&chunk[4..], warnings) {
+                match (&id, list_type(&id, chunk)) {
+                    (b"fmt ", _) if chunk.len() >= 16 => {
+                        let channels = u16_at(chunk, 2) as usize;
+                        let bits = u16_at(chunk, 14) as usize;
+                        format = Some((
+                            u16_at(chunk, 0),
+                            channels,
+                            u32_at(chunk, 4),
+                            bits.div_ceil(8),
+                        ));
+                    }
+                    (b"data", _) => samples = chunk,
+                    (b"wsmp", _) => sample = read_wave_sample(chunk),
+                    (b"LIST", Some(b"INFO")) => {
+                        if let Some(value) = read_name(&chunk[4..], warnings) {
+                            name = value;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            // A wave which cannot be decoded is kept as silence, so that the pool table still matches.
+            let decoded = match format {
+                Some((tag, channel_count, sample_rate, width)) if channel_count > 0 => {
+                    decode_pcm(tag, width, channel_count, samples)
+                        .map(|channels| (channels, sample_rate))
+                }
+                _ => Err("the 'fmt ' chunk was not found".to_string()),
+            };
+            let (channels, sample_rate) = match decoded {
+                Ok((channels, sample_rate)) if !channels[0].is_empty() && sample_rate > 0 => {
+                    (channels, sample_rate)
+                }
+                Ok(_) => {
+                    warnings.push(format!("the wave '{name}' has no sample"));
+                    (vec![vec![0; 1]], 44100)
+                }
+                Err(message) => {
+                    warnings.push(format!("the wave '{name}' could not be read: {message}"));
+                    (vec![vec![0; 1]], 44100)
+                }
+            };
+
+            waves.push((
+                position as u32,
+                DlsWave {
+                    name,
+                    channels,
+                    sample_rate,
+                    sample,
+                },
+            ));
+        }
+        waves
+    }
+}
+
+fn u16_at(data: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([data[index], data[index + 1]])
+}
+
+fn u32_at(data: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes([
+        data[index],
+        data[index + 1],
+        data[index + 2],
+        data[index + 3],
+    ])
+}
+
+// Splits the data into its sub-chunks. A sub-chunk running past the end is cut with a warning.
+fn sub_chunks<'a>(data: &'a [u8], warnings: &mut Vec<String>) -> Vec<([u8; 4], &'a [u8])> {
+    let mut chunks = Vec::new();
+    let mut position = 0;
+    while position + 8 <= data.len() {
+        let id: [u8; 4] = data[position..position + 4].try_into().unwrap();
+        let size = u32_at(data, position + 4) as usize;
+        let start = position + 8;
+        let end = start.saturating_add(size);
+        if end > data.len() {
+            let id = FourCC::from_bytes(id);
+            warnings.push(format!("the truncated '{id}' chunk was read to the end"));
+        }
+        chunks.push((id, &data[start..end.min(data.len())]));
+        position = end.saturating_add(size & 1);
+    }
+    chunks
+}
+
+fn list_type<'a>(id: &[u8; 4], chunk: &'a [u8]) -> Option<&'a [u8; 4]> {
+    if id == b"LIST" && chunk.len() >= 4 {
+        chunk[..4].try_into().ok()
+    } else {
+        None
+    }
+}
+
+fn read_name(data: &[u8], warnings: &mut Vec<String>) -> Option<String> {
+    let (_, chunk) = sub_chunks(data, warnings)
+        .into_iter()
+        .find(|(id, _)| id == b"INAM")?;
+    let length = chunk.iter().position(|&x| x == 0).unwrap_or(chunk.len());
+    let name = String::from_utf8_lossy(&chunk[..length]).trim().to_string();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+fn read_wave_sample(chunk: &[u8]) -> Option<WaveSample> {
+    if chunk.len() < 20 {
+        return None;
+    }
+
+    let size = u32_at(chunk, 0) as usize;
+    let loop_count = u32_at(chunk, 16);
+    // The loops follow the header, whose size is given by its first field.
+    let loop_points = match chunk.get(size..size + 16) {
+        Some(record) if loop_count > 0 => {
+            Some((u32_at(record, 4), u32_at(record, 8), u32_at(record, 12)))
+        }
+        _ => None,
+    };
+
+    Some(WaveSample {
+        unity_note: u16_at(chunk, 4),
+        fine_tune: u16_at(chunk, 6) as i16,
+        gain: u32_at(chunk, 8) as i32,
+        loop_points,
+    })
+}
+
+// Reads the connection blocks of the 'art1' and 'art2' chunks of an articulation list.
+fn read_articulation(data: &[u8], warnings: &mut Vec<String>) -> Vec<Connection> {
+    let mut connections = Vec::new();
+    for (id, chunk) in sub_chunks(data, warnings) {
+        if (&id != b"art1" && &id != b"art2") || chunk.len() < 8 {
+            continue;
+        }
+
+        let size = u32_at(chunk, 0) as usize;
+        let count = u32_at(chunk, 4) as usize;
+        let blocks = chunk.get(size.max(8)..).unwrap_or(&[]);
+        for block in blocks.chunks_exact(12).take(count) {
+            connections.push(Connection {
+                source: u16_at(block, 0),
+                control: u16_at(block, 2),
+                destination: u16_at(block, 4),
+                scale: u32_at(block, 8) as i32,
+            });
+        }
+    }
+    connections
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::error::SoundFontError;
+    use crate::loop_mode::LoopMode;
+    use crate::soundfont::SoundFont;
+    use crate::synthesizer::Synthesizer;
+    use crate::synthesizer_settings::SynthesizerSettings;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn list(list_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        chunk(b"LIST", &[&list_type[..], data].concat())
+    }
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    // An articulation list with the connections given as the source, control, destination and scale.
+    fn articulation(connections: &[(u16, u16, u16, i32)]) -> Vec<u8> {
+        let mut art1 = words(&[8, connections.len() as u32]);
+        for (source, control, destination, scale) in connections {
+            for value in [*source, *control, *destination, 0] {
+                art1.extend_from_slice(&value.to_le_bytes());
+            }
+            art1.extend_from_slice(&scale.to_le_bytes());
+        }
+        list(b"lart", &chunk(b"art1", &art1))
+    }
+
+    fn instrument(
+        name: &str,
+        bank: u32,
+        program: u32,
+        keys: (u32, u32),
+        key_group: u32,
+        lart: &[u8],
+    ) -> Vec<u8> {
+        let rgnh = [
+            words(&[keys.0 | keys.1 << 16, 0]),
+            words(&[key_group << 16]),
+        ]
+        .concat();
+        let wlnk = words(&[0, 1, 0]);
+        let region = list(
+            b"rgn ",
+            &[chunk(b"rgnh", &rgnh), chunk(b"wlnk", &wlnk)].concat(),
+        );
+        let mut body = chunk(b"insh", &words(&[1, bank, program]));
+        body.extend(list(b"lrgn", &region));
+        body.extend_from_slice(lart);
+        body.extend(list(
+            b"INFO",
+            &chunk(b"INAM", &[name.as_bytes(), &[0]].concat()),
+        ));
+        list(b"ins ", &body)
+    }
+
+    fn build_dls() -> Vec<u8> {
+        // A mono 16-bit wave, whose unity note is 62 and which loops from 10 to 90 at -6 dB.
+        let format = [words(&[1 | 1 << 16, 22050, 44100]), words(&[2 | 16 << 16])].concat();
+        let wsmp = [
+            words(&[20, 62 | 5 << 16, (-60_i32 * 65536) as u32, 0, 1]),
+            words(&[16, 0, 10, 80]),
+        ]
+        .concat();
+        let samples: Vec<u8> = (0..100)
+            .flat_map(|i| (if (i / 8) % 2 == 0 { 10000_i16 } else { -10000 }).to_le_bytes())
+            .collect();
+        let wave = list(
+            b"wave",
+            &[
+                chunk(b"fmt ", &format),
+                chunk(b"wsmp", &wsmp),
+                chunk(b"data", &samples),
+            ]
+            .concat(),
+        );
+
+        let piano = instrument(
+            "Piano",
+            0x0100,
+            5,
+            (0, 127),
+            0,
+            &articulation(&[(0, 0, 0x0206, 0), (0, 0, 0x020A, 500 << 16)]),
+        );
+        let drums = instrument(
+            "Drums",
+            0x8000_0000,
+            0,
+            (36, 36),
+            1,
+            &articulation(&[(0, 0, 0x0003, 250 << 16), (2, 0, 0x0206, 1200 << 16)]),
+        );
+
+        let body = [
+            &b"DLS "[..],
+            &chunk(b"colh", &words(&[2])),
+            &list(b"lins", &[piano, drums].concat()),
+            &chunk(b"ptbl", &words(&[8, 1, 0])),
+            &list(b"wvpl", &wave),
+            &list(b"INFO", &chunk(b"INAM", b"Bank\0\0")),
+        ]
+        .concat();
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn test_from_dls() {
+        let sound_font = Arc::new(SoundFont::from_dls(&mut &build_dls()[..]).unwrap());
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+        assert_eq!(
+            sound_font.get_warnings(),
+            ["the unsupported articulation connections were skipped"]
+        );
+        assert_eq!(sound_font.get_preset(1, 5).unwrap().get_name(), "Piano");
+        assert_eq!(sound_font.get_preset(128, 0).unwrap().get_name(), "Drums");
+
+        // The wave sample and the instrument articulation.
+        let instruments = sound_font.get_instruments();
+        let piano = &instruments[0].get_regions()[0];
+        assert_eq!(
+            (
+                piano.get_velocity_range_start(),
+                piano.get_velocity_range_end()
+            ),
+            (0, 127)
+        );
+        assert_eq!(piano.get_root_key(), 62);
+        assert_eq!(piano.get_sample_modes(), LoopMode::Continuous);
+        assert_eq!(piano.get_sample_start_loop() - piano.get_sample_start(), 10);
+        assert_eq!(piano.get_sample_end_loop() - piano.get_sample_start(), 90);
+        assert_eq!(piano.get_initial_attenuation(), 15_f32);
+        assert!((piano.get_attack_volume_envelope() - 1_f32).abs() < 0.01_f32);
+        assert!((piano.get_sustain_volume_envelope() - 6_f32).abs() < 0.1_f32);
+        assert_eq!(piano.get_fine_tune(), 5);
+
+        // The region articulation replaces the instrument one.
+        let drum = &instruments[1].get_regions()[0];
+        assert_eq!(
+            (drum.get_key_range_start(), drum.get_key_range_end()),
+            (36, 36)
+        );
+        assert_eq!(drum.get_exclusive_class(), 1);
+        // The fine tune includes the one of the wave.
+        assert_eq!((drum.get_coarse_tune(), drum.get_fine_tune()), (2, 55));
+
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.process_midi_message(0, 0xB0, 0x00, 1);
+        synthesizer.process_midi_message(0, 0xC0, 5, 0);
+        synthesizer.note_on(0, 60, 100);
+        // The attack takes a second, so the first 100 ms are rendered.
+        let mut left = vec![0_f32; 4410];
+        let mut right = vec![0_f32; 4410];
+        synthesizer.render(&mut left, &mut right);
+        assert!(left.iter().any(|&x| x != 0_f32));
+
+        let mut data = build_dls();
+        data[8..12].copy_from_slice(b"sfbk");
+        assert!(matches!(
+            SoundFont::from_dls(&mut &data[..]),
+            Err(SoundFontError::InvalidRiffChunkType { .. })
+        ));
+    }
+}
@@ -1,8 +1,10 @@
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub(crate) enum EnvelopeStage {
+/// Represents the stage of the volume envelope of a voice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EnvelopeStage {
     Delay = 0,
     Attack = 1,
     Hold = 2,
+    /// The decay stage, which continues as the sustain once the sustain level is reached.
     Decay = 3,
     Release = 4,
 }
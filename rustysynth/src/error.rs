@@ -1,8 +1,8 @@
-use std::error;
-use std::fmt;
-use std::io;
+use core::error;
+use core::fmt;
 
 use crate::four_cc::FourCC;
+use crate::io;
 
 /// Represents an error when initializing a synthesizer.
 #[derive(Debug)]
@@ -11,6 +11,7 @@ pub enum SynthesizerError {
     SampleRateOutOfRange(i32),
     BlockSizeOutOfRange(usize),
     MaximumPolyphonyOutOfRange(usize),
+    RenderThreadCountOutOfRange(usize),
 }
 
 impl error::Error for SynthesizerError {}
@@ -35,6 +36,11 @@ impl fmt::Display for SynthesizerError {
                     value
                 )
             }
+            SynthesizerError::RenderThreadCountOutOfRange(value) => write!(
+                f,
+                "the number of render threads must be between 1 and 64, but was {}",
+                value
+            ),
         }
     }
 }
@@ -77,10 +83,11 @@ pub enum SoundFontError {
     ZoneNotFound,
     InvalidGeneratorList,
     SanityCheckFailed,
+    InvalidCompressedSample(usize),
 }
 
 impl error::Error for SoundFontError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
             SoundFontError::IoError(ref err) => Some(err),
             _ => None,
@@ -108,7 +115,7 @@ impl fmt::Display for SoundFontError {
                 write!(f, "the INFO list contains an unknown ID '{id}'")
             }
             SoundFontError::SampleDataNotFound => write!(f, "no valid sample data was found"),
-            SoundFontError::UnsupportedSampleFormat => write!(f, "SoundFont3 is not yet supported"),
+            SoundFontError::UnsupportedSampleFormat => write!(f, "SoundFont3 requires the 'sf3' feature"),
             SoundFontError::SubChunkNotFound(id) => {
                 write!(f, "the '{}' sub-chunk was not found", id)
             }
@@ -143,6 +150,9 @@ impl fmt::Display for SoundFontError {
             SoundFontError::ZoneNotFound => write!(f, "no valid zone was found"),
             SoundFontError::InvalidGeneratorList => write!(f, "the generator list is invalid"),
             SoundFontError::SanityCheckFailed => write!(f, "sanity check failed"),
+            SoundFontError::InvalidCompressedSample(id) => {
+                write!(f, "the compressed sample {} could not be decoded", id)
+            }
         }
     }
 }
@@ -165,7 +175,7 @@ pub enum MidiFileError {
 }
 
 impl error::Error for MidiFileError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
             MidiFileError::IoError(ref err) => Some(err),
             _ => None,
@@ -196,3 +206,140 @@ impl From<io::Error> for MidiFileError {
         MidiFileError::IoError(err)
     }
 }
+
+/// Represents an error when loading a tuning.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TuningError {
+    InvalidScale { line: usize },
+    InvalidKeyboardMapping { line: usize },
+    ReferenceKeyNotMapped,
+}
+
+impl error::Error for TuningError {}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TuningError::InvalidScale { line } => {
+                write!(f, "the scale file is invalid at line {line}")
+            }
+            TuningError::InvalidKeyboardMapping { line } => {
+                write!(f, "the keyboard mapping file is invalid at line {line}")
+            }
+            TuningError::ReferenceKeyNotMapped => {
+                write!(f, "the reference key of the keyboard mapping is not mapped")
+            }
+        }
+    }
+}
+
+/// Represents an error when loading an SFZ instrument.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SfzError {
+    IoError(io::Error),
+    InvalidHeader { line: usize },
+    InvalidOpcode { line: usize },
+    InvalidDirective { line: usize },
+    RegionNotFound,
+}
+
+#[cfg(feature = "std")]
+impl error::Error for SfzError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            SfzError::IoError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for SfzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SfzError::IoError(err) => err.fmt(f),
+            SfzError::InvalidHeader { line } => {
+                write!(f, "the header is not closed at line {line}")
+            }
+            SfzError::InvalidOpcode { line } => {
+                write!(f, "the opcode is invalid at line {line}")
+            }
+            SfzError::InvalidDirective { line } => {
+                write!(f, "the preprocessor directive is invalid at line {line}")
+            }
+            SfzError::RegionNotFound => write!(f, "no playable region was found"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for SfzError {
+    fn from(err: io::Error) -> Self {
+        SfzError::IoError(err)
+    }
+}
+
+/// Represents an error when restoring a checkpoint of a sequencer.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CheckpointError {
+    MidiFileMismatch,
+}
+
+impl error::Error for CheckpointError {}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::MidiFileMismatch => write!(
+                f,
+                "the checkpoint was taken with a different MIDI file from the one being played"
+            ),
+        }
+    }
+}
+
+/// Represents an error when opening an audio output.
+#[cfg(feature = "output")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AudioOutputError {
+    NoOutputDevice,
+    UnsupportedSampleFormat(cpal::SampleFormat),
+    DefaultStreamConfigError(cpal::DefaultStreamConfigError),
+    BuildStreamError(cpal::BuildStreamError),
+    PlayStreamError(cpal::PlayStreamError),
+    SynthesizerError(SynthesizerError),
+}
+
+#[cfg(feature = "output")]
+impl error::Error for AudioOutputError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            AudioOutputError::DefaultStreamConfigError(ref err) => Some(err),
+            AudioOutputError::BuildStreamError(ref err) => Some(err),
+            AudioOutputError::PlayStreamError(ref err) => Some(err),
+            AudioOutputError::SynthesizerError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "output")]
+impl fmt::Display for AudioOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioOutputError::NoOutputDevice => write!(f, "no output device is available"),
+            AudioOutputError::UnsupportedSampleFormat(format) => {
+                write!(f, "the sample format {format} is not supported")
+            }
+            AudioOutputError::DefaultStreamConfigError(err) => err.fmt(f),
+            AudioOutputError::BuildStreamError(err) => err.fmt(f),
+            AudioOutputError::PlayStreamError(err) => err.fmt(f),
+            AudioOutputError::SynthesizerError(err) => err.fmt(f),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+#![allow(dead_code)]
+
+// Without std, the float methods which need the platform's math library
+// are provided by this trait through libm, so that the calls read the same with and without std.
+pub(crate) trait FloatMath {
+    fn ceil(self) -> Self;
+    fn cos(self) -> Self;
+    fn exp(self) -> Self;
+    fn floor(self) -> Self;
+    fn fract(self) -> Self;
+    fn hypot(self, other: Self) -> Self;
+    fn log10(self) -> Self;
+    fn log2(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn round(self) -> Self;
+    fn sin(self) -> Self;
+    fn tanh(self) -> Self;
+}
+
+impl FloatMath for f32 {
+    fn ceil(self) -> Self {
+        libm::ceilf(self)
+    }
+
+    fn cos(self) -> Self {
+        libm::cosf(self)
+    }
+
+    fn exp(self) -> Self {
+        libm::expf(self)
+    }
+
+    fn floor(self) -> Self {
+        libm::floorf(self)
+    }
+
+    fn fract(self) -> Self {
+        self - libm::truncf(self)
+    }
+
+    fn hypot(self, other: Self) -> Self {
+        libm::hypotf(self, other)
+    }
+
+    fn log10(self) -> Self {
+        libm::log10f(self)
+    }
+
+    fn log2(self) -> Self {
+        libm::log2f(self)
+    }
+
+    fn powf(self, n: Self) -> Self {
+        libm::powf(self, n)
+    }
+
+    fn round(self) -> Self {
+        libm::roundf(self)
+    }
+
+    fn sin(self) -> Self {
+        libm::sinf(self)
+    }
+
+    fn tanh(self) -> Self {
+        libm::tanhf(self)
+    }
+}
+
+impl FloatMath for f64 {
+    fn ceil(self) -> Self {
+        libm::ceil(self)
+    }
+
+    fn cos(self) -> Self {
+        libm::cos(self)
+    }
+
+    fn exp(self) -> Self {
+        libm::exp(self)
+    }
+
+    fn floor(self) -> Self {
+        libm::floor(self)
+    }
+
+    fn fract(self) -> Self {
+        self - libm::trunc(self)
+    }
+
+    fn hypot(self, other: Self) -> Self {
+        libm::hypot(self, other)
+    }
+
+    fn log10(self) -> Self {
+        libm::log10(self)
+    }
+
+    fn log2(self) -> Self {
+        libm::log2(self)
+    }
+
+    fn powf(self, n: Self) -> Self {
+        libm::pow(self, n)
+    }
+
+    fn round(self) -> Self {
+        libm::round(self)
+    }
+
+    fn sin(self) -> Self {
+        libm::sin(self)
+    }
+
+    fn tanh(self) -> Self {
+        libm::tanh(self)
+    }
+}
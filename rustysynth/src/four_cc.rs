@@ -1,4 +1,4 @@
-use std::fmt::{Debug, Display, Formatter, Result, Write};
+use core::fmt::{Debug, Display, Formatter, Result, Write};
 
 /// Reperesents the FourCC.
 /// This is used for error reporting when the binary format is invalid.
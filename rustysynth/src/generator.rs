@@ -1,7 +1,8 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::vec::Vec;
 
+use crate::io::Read;
 use crate::{binary_reader::BinaryReader, error::SoundFontError};
 
 #[derive(Clone, Copy)]
@@ -0,0 +1,330 @@
+#![allow(dead_code)]
+
+use crate::loop_mode::LoopMode;
+use crate::region_pair::RegionPair;
+
+/// The generator values of a preset region combined with an instrument region.
+/// The values are what the synthesizer uses to play a note, converted to the units noted on each getter.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct GeneratorSet {
+    pub(crate) instrument_id: usize,
+    pub(crate) sample_id: usize,
+    pub(crate) sample_start: i32,
+    pub(crate) sample_end: i32,
+    pub(crate) sample_start_loop: i32,
+    pub(crate) sample_end_loop: i32,
+    pub(crate) sample_modes: LoopMode,
+    pub(crate) root_key: i32,
+    pub(crate) coarse_tune: i32,
+    pub(crate) fine_tune: i32,
+    pub(crate) scale_tuning: i32,
+    pub(crate) exclusive_class: i32,
+    pub(crate) initial_attenuation: f32,
+    pub(crate) pan: f32,
+    pub(crate) reverb_effects_send: f32,
+    pub(crate) chorus_effects_send: f32,
+    pub(crate) initial_filter_cutoff_frequency: f32,
+    pub(crate) initial_filter_q: f32,
+    pub(crate) modulation_lfo_to_pitch: i32,
+    pub(crate) vibrato_lfo_to_pitch: i32,
+    pub(crate) modulation_envelope_to_pitch: i32,
+    pub(crate) modulation_lfo_to_filter_cutoff_frequency: i32,
+    pub(crate) modulation_envelope_to_filter_cutoff_frequency: i32,
+    pub(crate) modulation_lfo_to_volume: f32,
+    pub(crate) delay_modulation_lfo: f32,
+    pub(crate) frequency_modulation_lfo: f32,
+    pub(crate) delay_vibrato_lfo: f32,
+    pub(crate) frequency_vibrato_lfo: f32,
+    pub(crate) delay_modulation_envelope: f32,
+    pub(crate) attack_modulation_envelope: f32,
+    pub(crate) hold_modulation_envelope: f32,
+    pub(crate) decay_modulation_envelope: f32,
+    pub(crate) sustain_modulation_envelope: f32,
+    pub(crate) release_modulation_envelope: f32,
+    pub(crate) key_number_to_modulation_envelope_hold: i32,
+    pub(crate) key_number_to_modulation_envelope_decay: i32,
+    pub(crate) delay_volume_envelope: f32,
+    pub(crate) attack_volume_envelope: f32,
+    pub(crate) hold_volume_envelope: f32,
+    pub(crate) decay_volume_envelope: f32,
+    pub(crate) sustain_volume_envelope: f32,
+    pub(crate) release_volume_envelope: f32,
+    pub(crate) key_number_to_volume_envelope_hold: i32,
+    pub(crate) key_number_to_volume_envelope_decay: i32,
+}
+
+impl GeneratorSet {
+    pub(crate) fn new(region: &RegionPair) -> Self {
+        Self {
+            instrument_id: region.preset.get_instrument_id(),
+            sample_id: region.instrument.get_sample_id(),
+            sample_start: region.get_sample_start(),
+            sample_end: region.get_sample_end(),
+            sample_start_loop: region.get_sample_start_loop(),
+            sample_end_loop: region.get_sample_end_loop(),
+            sample_modes: region.get_sample_modes(),
+            root_key: region.get_root_key(),
+            coarse_tune: region.get_coarse_tune(),
+            fine_tune: region.get_fine_tune(),
+            scale_tuning: region.get_scale_tuning(),
+            exclusive_class: region.get_exclusive_class(),
+            initial_attenuation: region.get_initial_attenuation(),
+            pan: region.get_pan(),
+            reverb_effects_send: region.get_reverb_effects_send(),
+            chorus_effects_send: region.get_chorus_effects_send(),
+            initial_filter_cutoff_frequency: region.get_initial_filter_cutoff_frequency(),
+            initial_filter_q: region.get_initial_filter_q(),
+            modulation_lfo_to_pitch: region.get_modulation_lfo_to_pitch(),
+            vibrato_lfo_to_pitch: region.get_vibrato_lfo_to_pitch(),
+            modulation_envelope_to_pitch: region.get_modulation_envelope_to_pitch(),
+            modulation_lfo_to_filter_cutoff_frequency: region
+                .get_modulation_lfo_to_filter_cutoff_frequency(),
+            modulation_envelope_to_filter_cutoff_frequency: region
+                .get_modulation_envelope_to_filter_cutoff_frequency(),
+            modulation_lfo_to_volume: region.get_modulation_lfo_to_volume(),
+            delay_modulation_lfo: region.get_delay_modulation_lfo(),
+            frequency_modulation_lfo: region.get_frequency_modulation_lfo(),
+            delay_vibrato_lfo: region.get_delay_vibrato_lfo(),
+            frequency_vibrato_lfo: region.get_frequency_vibrato_lfo(),
+            delay_modulation_envelope: region.get_delay_modulation_envelope(),
+            attack_modulation_envelope: region.get_attack_modulation_envelope(),
+            hold_modulation_envelope: region.get_hold_modulation_envelope(),
+            decay_modulation_envelope: region.get_decay_modulation_envelope(),
+            sustain_modulation_envelope: region.get_sustain_modulation_envelope(),
+            release_modulation_envelope: region.get_release_modulation_envelope(),
+            key_number_to_modulation_envelope_hold: region
+                .get_key_number_to_modulation_envelope_hold(),
+            key_number_to_modulation_envelope_decay: region
+                .get_key_number_to_modulation_envelope_decay(),
+            delay_volume_envelope: region.get_delay_volume_envelope(),
+            attack_volume_envelope: region.get_attack_volume_envelope(),
+            hold_volume_envelope: region.get_hold_volume_envelope(),
+            decay_volume_envelope: region.get_decay_volume_envelope(),
+            sustain_volume_envelope: region.get_sustain_volume_envelope(),
+            release_volume_envelope: region.get_release_volume_envelope(),
+            key_number_to_volume_envelope_hold: region.get_key_number_to_volume_envelope_hold(),
+            key_number_to_volume_envelope_decay: region.get_key_number_to_volume_envelope_decay(),
+        }
+    }
+
+    /// Gets the ID of the instrument used by the region.
+    pub fn get_instrument_id(&self) -> usize {
+        self.instrument_id
+    }
+
+    /// Gets the ID of the sample used by the region.
+    pub fn get_sample_id(&self) -> usize {
+        self.sample_id
+    }
+
+    /// Gets the start point of the sample, including the address offsets.
+    pub fn get_sample_start(&self) -> i32 {
+        self.sample_start
+    }
+
+    /// Gets the end point of the sample, including the address offsets.
+    pub fn get_sample_end(&self) -> i32 {
+        self.sample_end
+    }
+
+    /// Gets the loop start point of the sample, including the address offsets.
+    pub fn get_sample_start_loop(&self) -> i32 {
+        self.sample_start_loop
+    }
+
+    /// Gets the loop end point of the sample, including the address offsets.
+    pub fn get_sample_end_loop(&self) -> i32 {
+        self.sample_end_loop
+    }
+
+    /// Gets how the sample loops during playback.
+    pub fn get_sample_modes(&self) -> LoopMode {
+        self.sample_modes.clone()
+    }
+
+    /// Gets the key number at which the sample plays at its original pitch.
+    pub fn get_root_key(&self) -> i32 {
+        self.root_key
+    }
+
+    /// Gets the coarse tuning in semitones.
+    pub fn get_coarse_tune(&self) -> i32 {
+        self.coarse_tune
+    }
+
+    /// Gets the fine tuning in cents, including the pitch correction of the sample.
+    pub fn get_fine_tune(&self) -> i32 {
+        self.fine_tune
+    }
+
+    /// Gets the pitch change per key in cents.
+    pub fn get_scale_tuning(&self) -> i32 {
+        self.scale_tuning
+    }
+
+    /// Gets the exclusive class.
+    pub fn get_exclusive_class(&self) -> i32 {
+        self.exclusive_class
+    }
+
+    /// Gets the attenuation in decibels.
+    pub fn get_initial_attenuation(&self) -> f32 {
+        self.initial_attenuation
+    }
+
+    /// Gets the pan position, from -50 (left) to 50 (right).
+    pub fn get_pan(&self) -> f32 {
+        self.pan
+    }
+
+    /// Gets the reverb send in percent.
+    pub fn get_reverb_effects_send(&self) -> f32 {
+        self.reverb_effects_send
+    }
+
+    /// Gets the chorus send in percent.
+    pub fn get_chorus_effects_send(&self) -> f32 {
+        self.chorus_effects_send
+    }
+
+    /// Gets the cutoff frequency of the low-pass filter in hertz.
+    pub fn get_initial_filter_cutoff_frequency(&self) -> f32 {
+        self.initial_filter_cutoff_frequency
+    }
+
+    /// Gets the resonance of the low-pass filter in decibels.
+    pub fn get_initial_filter_q(&self) -> f32 {
+        self.initial_filter_q
+    }
+
+    /// Gets the depth of the modulation LFO on the pitch in cents.
+    pub fn get_modulation_lfo_to_pitch(&self) -> i32 {
+        self.modulation_lfo_to_pitch
+    }
+
+    /// Gets the depth of the vibrato LFO on the pitch in cents.
+    pub fn get_vibrato_lfo_to_pitch(&self) -> i32 {
+        self.vibrato_lfo_to_pitch
+    }
+
+    /// Gets the depth of the modulation envelope on the pitch in cents.
+    pub fn get_modulation_envelope_to_pitch(&self) -> i32 {
+        self.modulation_envelope_to_pitch
+    }
+
+    /// Gets the depth of the modulation LFO on the filter cutoff in cents.
+    pub fn get_modulation_lfo_to_filter_cutoff_frequency(&self) -> i32 {
+        self.modulation_lfo_to_filter_cutoff_frequency
+    }
+
+    /// Gets the depth of the modulation envelope on the filter cutoff in cents.
+    pub fn get_modulation_envelope_to_filter_cutoff_frequency(&self) -> i32 {
+        self.modulation_envelope_to_filter_cutoff_frequency
+    }
+
+    /// Gets the depth of the modulation LFO on the volume in decibels.
+    pub fn get_modulation_lfo_to_volume(&self) -> f32 {
+        self.modulation_lfo_to_volume
+    }
+
+    /// Gets the delay of the modulation LFO in seconds.
+    pub fn get_delay_modulation_lfo(&self) -> f32 {
+        self.delay_modulation_lfo
+    }
+
+    /// Gets the frequency of the modulation LFO in hertz.
+    pub fn get_frequency_modulation_lfo(&self) -> f32 {
+        self.frequency_modulation_lfo
+    }
+
+    /// Gets the delay of the vibrato LFO in seconds.
+    pub fn get_delay_vibrato_lfo(&self) -> f32 {
+        self.delay_vibrato_lfo
+    }
+
+    /// Gets the frequency of the vibrato LFO in hertz.
+    pub fn get_frequency_vibrato_lfo(&self) -> f32 {
+        self.frequency_vibrato_lfo
+    }
+
+    /// Gets the delay of the modulation envelope in seconds.
+    pub fn get_delay_modulation_envelope(&self) -> f32 {
+        self.delay_modulation_envelope
+    }
+
+    /// Gets the attack time of the modulation envelope in seconds.
+    pub fn get_attack_modulation_envelope(&self) -> f32 {
+        self.attack_modulation_envelope
+    }
+
+    /// Gets the hold time of the modulation envelope in seconds.
+    pub fn get_hold_modulation_envelope(&self) -> f32 {
+        self.hold_modulation_envelope
+    }
+
+    /// Gets the decay time of the modulation envelope in seconds.
+    pub fn get_decay_modulation_envelope(&self) -> f32 {
+        self.decay_modulation_envelope
+    }
+
+    /// Gets the sustain level of the modulation envelope in percent decrease.
+    pub fn get_sustain_modulation_envelope(&self) -> f32 {
+        self.sustain_modulation_envelope
+    }
+
+    /// Gets the release time of the modulation envelope in seconds.
+    pub fn get_release_modulation_envelope(&self) -> f32 {
+        self.release_modulation_envelope
+    }
+
+    /// Gets the key scaling of the modulation envelope hold time in timecents per key.
+    pub fn get_key_number_to_modulation_envelope_hold(&self) -> i32 {
+        self.key_number_to_modulation_envelope_hold
+    }
+
+    /// Gets the key scaling of the modulation envelope decay time in timecents per key.
+    pub fn get_key_number_to_modulation_envelope_decay(&self) -> i32 {
+        self.key_number_to_modulation_envelope_decay
+    }
+
+    /// Gets the delay of the volume envelope in seconds.
+    pub fn get_delay_volume_envelope(&self) -> f32 {
+        self.delay_volume_envelope
+    }
+
+    /// Gets the attack time of the volume envelope in seconds.
+    pub fn get_attack_volume_envelope(&self) -> f32 {
+        self.attack_volume_envelope
+    }
+
+    /// Gets the hold time of the volume envelope in seconds.
+    pub fn get_hold_volume_envelope(&self) -> f32 {
+        self.hold_volume_envelope
+    }
+
+    /// Gets the decay time of the volume envelope in seconds.
+    pub fn get_decay_volume_envelope(&self) -> f32 {
+        self.decay_volume_envelope
+    }
+
+    /// Gets the sustain level of the volume envelope as attenuation in decibels.
+    pub fn get_sustain_volume_envelope(&self) -> f32 {
+        self.sustain_volume_envelope
+    }
+
+    /// Gets the release time of the volume envelope in seconds.
+    pub fn get_release_volume_envelope(&self) -> f32 {
+        self.release_volume_envelope
+    }
+
+    /// Gets the key scaling of the volume envelope hold time in timecents per key.
+    pub fn get_key_number_to_volume_envelope_hold(&self) -> i32 {
+        self.key_number_to_volume_envelope_hold
+    }
+
+    /// Gets the key scaling of the volume envelope decay time in timecents per key.
+    pub fn get_key_number_to_volume_envelope_decay(&self) -> i32 {
+        self.key_number_to_volume_envelope_decay
+    }
+}
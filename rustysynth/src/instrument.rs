@@ -1,5 +1,8 @@
 #![allow(dead_code)]
 
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::error::SoundFontError;
 use crate::instrument_info::InstrumentInfo;
 use crate::instrument_region::InstrumentRegion;
@@ -12,6 +15,9 @@ use crate::zone::Zone;
 pub struct Instrument {
     pub(crate) name: String,
     pub(crate) regions: Vec<InstrumentRegion>,
+    // The region for the other half of the linked stereo sample of each region,
+    // if the instrument does not have one of its own.
+    pub(crate) linked_regions: Vec<Option<InstrumentRegion>>,
 }
 
 impl Instrument {
@@ -32,8 +38,16 @@ impl Instrument {
         let span_end = span_start + zone_count as usize;
         let zone_span = &zones[span_start..span_end];
         let regions = InstrumentRegion::create(instrument_id, zone_span, samples)?;
+        let linked_regions = regions
+            .iter()
+            .map(|region| region.create_linked(&regions, samples))
+            .collect();
 
-        Ok(Self { name, regions })
+        Ok(Self {
+            name,
+            regions,
+            linked_regions,
+        })
     }
 
     pub(crate) fn create(
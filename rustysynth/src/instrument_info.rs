@@ -1,9 +1,11 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::binary_reader::BinaryReader;
 use crate::error::SoundFontError;
+use crate::io::Read;
 
 #[non_exhaustive]
 pub(crate) struct InstrumentInfo {
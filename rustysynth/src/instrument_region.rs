@@ -1,9 +1,12 @@
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
+
 use crate::error::SoundFontError;
 use crate::generator::Generator;
 use crate::generator_type::GeneratorType;
 use crate::loop_mode::LoopMode;
+use crate::modulator::Modulator;
 use crate::sample_header::SampleHeader;
 use crate::soundfont_math::SoundFontMath;
 use crate::zone::Zone;
@@ -22,6 +25,7 @@ fn set_parameter(gs: &mut [i16; GeneratorType::COUNT], generator: &Generator) {
 #[non_exhaustive]
 pub struct InstrumentRegion {
     pub(crate) gs: [i16; GeneratorType::COUNT],
+    pub(crate) modulators: Vec<Modulator>,
     pub(crate) sample_start: i32,
     pub(crate) sample_end: i32,
     pub(crate) sample_start_loop: i32,
@@ -29,15 +33,12 @@ pub struct InstrumentRegion {
     pub(crate) sample_sample_rate: i32,
     pub(crate) sample_original_pitch: i32,
     pub(crate) sample_pitch_correction: i32,
+    pub(crate) sample_type: u16,
 }
 
 impl InstrumentRegion {
-    fn new(
-        instrument_id: usize,
-        global: &Zone,
-        local: &Zone,
-        samples: &[SampleHeader],
-    ) -> Result<Self, SoundFontError> {
+    // The generators of a region before those of the zones are applied, as the SoundFont specification defines.
+    pub(crate) fn default_generators() -> [i16; GeneratorType::COUNT] {
         let mut gs: [i16; GeneratorType::COUNT] = [0; GeneratorType::COUNT];
         gs[GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY as usize] = 13500;
         gs[GeneratorType::DELAY_MODULATION_LFO as usize] = -12000;
@@ -58,6 +59,16 @@ impl InstrumentRegion {
         gs[GeneratorType::VELOCITY as usize] = -1;
         gs[GeneratorType::SCALE_TUNING as usize] = 100;
         gs[GeneratorType::OVERRIDING_ROOT_KEY as usize] = -1;
+        gs
+    }
+
+    fn new(
+        instrument_id: usize,
+        global: &Zone,
+        local: &Zone,
+        samples: &[SampleHeader],
+    ) -> Result<Self, SoundFontError> {
+        let mut gs = InstrumentRegion::default_generators();
 
         for generator in global.generators.iter() {
             set_parameter(&mut gs, generator);
@@ -78,6 +89,7 @@ impl InstrumentRegion {
 
         Ok(Self {
             gs,
+            modulators: Modulator::merge(&global.modulators, &local.modulators),
             sample_start: sample.start,
             sample_end: sample.end,
             sample_start_loop: sample.start_loop,
@@ -85,6 +97,46 @@ impl InstrumentRegion {
             sample_sample_rate: sample.sample_rate,
             sample_original_pitch: sample.original_pitch as i32,
             sample_pitch_correction: sample.pitch_correction as i32,
+            sample_type: sample.sample_type,
+        })
+    }
+
+    // Creates the region for the other half of a linked stereo sample,
+    // if the instrument does not have its own region for it over the same keys and velocities.
+    // The generators are shared, so that both halves play in sync.
+    pub(crate) fn create_linked(
+        &self,
+        regions: &[InstrumentRegion],
+        samples: &[SampleHeader],
+    ) -> Option<Self> {
+        let link = samples[self.get_sample_id()].get_stereo_link(samples)?;
+
+        let overlaps = |other: &InstrumentRegion| {
+            other.get_sample_id() == link
+                && other.get_key_range_start() <= self.get_key_range_end()
+                && self.get_key_range_start() <= other.get_key_range_end()
+                && other.get_velocity_range_start() <= self.get_velocity_range_end()
+                && self.get_velocity_range_start() <= other.get_velocity_range_end()
+        };
+        if regions.iter().any(overlaps) {
+            return None;
+        }
+
+        let sample = &samples[link];
+        let mut gs = self.gs;
+        gs[GeneratorType::SAMPLE_ID as usize] = link as i16;
+
+        Some(Self {
+            gs,
+            modulators: self.modulators.clone(),
+            sample_start: sample.start,
+            sample_end: sample.end,
+            sample_start_loop: sample.start_loop,
+            sample_end_loop: sample.end_loop,
+            sample_sample_rate: sample.sample_rate,
+            sample_original_pitch: sample.original_pitch as i32,
+            sample_pitch_correction: sample.pitch_correction as i32,
+            sample_type: sample.sample_type,
         })
     }
 
@@ -376,4 +428,14 @@ impl InstrumentRegion {
     pub fn get_sample_id(&self) -> usize {
         self.gs[GeneratorType::SAMPLE_ID as usize] as usize
     }
+
+    // Gets the pan which places the half of a linked stereo sample at its side,
+    // from -50 for the left to 50 for the right.
+    pub(crate) fn get_stereo_pan(&self) -> f32 {
+        match self.sample_type & !(SampleHeader::ROM_FLAG | SampleHeader::COMPRESSED_FLAG) {
+            SampleHeader::LEFT_SAMPLE => -50_f32,
+            SampleHeader::RIGHT_SAMPLE => 50_f32,
+            _ => 0_f32,
+        }
+    }
 }
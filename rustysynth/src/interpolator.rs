@@ -0,0 +1,193 @@
+use alloc::vec::Vec;
+use core::f64::consts;
+use core::fmt::Debug;
+
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+
+/// Computes the value of the sample data at a fractional position.
+///
+/// An implementation can be set to `SynthesizerSettings::interpolator`
+/// to replace the built-in linear interpolation of the oscillator.
+pub trait Interpolator: Debug + Send + Sync {
+    /// Returns the value at the position in the range of `[-1, 1)`.
+    ///
+    /// `data` is the whole wave data of the SoundFont, so the samples around the position can be read.
+    /// The samples after the end of a loop are read as they are in the SoundFont,
+    /// which usually contains at least 8 valid samples around the loop points.
+    fn sample(&self, data: &[i16], position: f64) -> f32;
+
+    /// Returns the value at the position, where the position advances by `pitch_ratio` samples
+    /// for each output sample.
+    ///
+    /// A ratio above `1` means that the sample is pitched up, and the frequencies above
+    /// `1 / pitch_ratio` of the Nyquist frequency of the sample alias.
+    /// The oscillator calls this, and the default implementation ignores the ratio and calls `sample`.
+    fn sample_at_rate(&self, data: &[i16], position: f64, pitch_ratio: f64) -> f32 {
+        let _ = pitch_ratio;
+        self.sample(data, position)
+    }
+}
+
+/// The 2-point linear interpolation.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct LinearInterpolator {}
+
+impl LinearInterpolator {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Interpolator for LinearInterpolator {
+    fn sample(&self, data: &[i16], position: f64) -> f32 {
+        let index = position as usize;
+        let a = (position - index as f64) as f32;
+
+        let x1 = get(data, index as isize);
+        let x2 = get(data, index as isize + 1);
+
+        x1 + a * (x2 - x1)
+    }
+}
+
+/// The 4-point cubic Hermite (Catmull-Rom) interpolation.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct CubicInterpolator {}
+
+impl CubicInterpolator {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Interpolator for CubicInterpolator {
+    fn sample(&self, data: &[i16], position: f64) -> f32 {
+        let index = position as isize;
+        let a = (position - index as f64) as f32;
+
+        let x0 = get(data, index - 1);
+        let x1 = get(data, index);
+        let x2 = get(data, index + 1);
+        let x3 = get(data, index + 2);
+
+        let c1 = 0.5_f32 * (x2 - x0);
+        let c2 = x0 - 2.5_f32 * x1 + 2_f32 * x2 - 0.5_f32 * x3;
+        let c3 = 0.5_f32 * (x3 - x0) + 1.5_f32 * (x1 - x2);
+
+        ((c3 * a + c2) * a + c1) * a + x1
+    }
+}
+
+/// The windowed sinc interpolation, which also filters out the frequencies
+/// that would alias when the sample is pitched up.
+///
+/// The kernel spans 16 points at the original pitch, and is widened up to 8 times
+/// as the sample is pitched up, so this costs much more CPU than the others.
+/// It is meant for offline rendering.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SincInterpolator {
+    // The Blackman-windowed sinc from 0 to the half width, with TABLE_RESOLUTION points per sample.
+    table: Vec<f32>,
+}
+
+impl SincInterpolator {
+    const HALF_WIDTH: usize = 8;
+    const TABLE_RESOLUTION: usize = 256;
+    const MAXIMUM_WIDENING: f64 = 8.0;
+
+    pub fn new() -> Self {
+        let length = SincInterpolator::HALF_WIDTH * SincInterpolator::TABLE_RESOLUTION + 1;
+        let table = (0..length)
+            .map(|i| {
+                let x = i as f64 / SincInterpolator::TABLE_RESOLUTION as f64;
+                let sinc = if i == 0 {
+                    1.0
+                } else {
+                    (consts::PI * x).sin() / (consts::PI * x)
+                };
+                // The Blackman window over [-HALF_WIDTH, HALF_WIDTH], centered at 0.
+                let w = consts::PI * x / SincInterpolator::HALF_WIDTH as f64;
+                let window = 0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+                (sinc * window) as f32
+            })
+            .collect();
+
+        Self { table }
+    }
+
+    fn kernel(&self, x: f64) -> f32 {
+        let x = x.abs() * SincInterpolator::TABLE_RESOLUTION as f64;
+        let index = x as usize;
+        if index + 1 >= self.table.len() {
+            return 0_f32;
+        }
+
+        let a = (x - index as f64) as f32;
+        self.table[index] + a * (self.table[index + 1] - self.table[index])
+    }
+}
+
+impl Default for SincInterpolator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpolator for SincInterpolator {
+    fn sample(&self, data: &[i16], position: f64) -> f32 {
+        self.sample_at_rate(data, position, 1.0)
+    }
+
+    fn sample_at_rate(&self, data: &[i16], position: f64, pitch_ratio: f64) -> f32 {
+        // The cutoff is lowered by widening the kernel, which keeps the gain at DC by the normalization.
+        let scale = pitch_ratio.clamp(1.0, SincInterpolator::MAXIMUM_WIDENING);
+        let half_width = (SincInterpolator::HALF_WIDTH as f64 * scale) as isize;
+
+        let index = position as isize;
+        let mut sum = 0_f32;
+        let mut weight_sum = 0_f32;
+        for i in (index - half_width + 1)..=(index + half_width) {
+            let weight = self.kernel((position - i as f64) / scale);
+            sum += weight * get(data, i);
+            weight_sum += weight;
+        }
+
+        sum / weight_sum
+    }
+}
+
+// The samples out of the data are regarded as the nearest edge.
+fn get(data: &[i16], index: isize) -> f32 {
+    let index = index.clamp(0, data.len() as isize - 1) as usize;
+    data[index] as f32 / 32768_f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sinc_interpolator() {
+        let interpolator = SincInterpolator::new();
+
+        // The samples are kept at the integer positions, and a constant stays constant.
+        let data: Vec<i16> = (0..64).map(|i| (i * 300 - 9000) as i16).collect();
+        assert!((interpolator.sample(&data, 20.0) - data[20] as f32 / 32768_f32).abs() < 1e-6);
+        let data = vec![16384_i16; 64];
+        assert!((interpolator.sample_at_rate(&data, 30.4, 3.0) - 0.5_f32).abs() < 1e-4);
+
+        // The frequency at the Nyquist frequency is removed when the sample is pitched up an octave,
+        // where the linear interpolation keeps it.
+        let data: Vec<i16> = (0..64)
+            .map(|i| if i % 2 == 0 { 16384 } else { -16384 })
+            .collect();
+        let sinc = interpolator.sample_at_rate(&data, 32.0, 2.0);
+        let linear = LinearInterpolator::new().sample_at_rate(&data, 32.0, 2.0);
+        assert!(sinc.abs() < 0.02_f32, "{}", sinc);
+        assert_eq!(linear, 0.5_f32);
+    }
+}
@@ -0,0 +1,93 @@
+// The reading used by the loaders.
+// With std, this is `std::io` itself, so that the loaders take any `std::io::Read`.
+// Without it, this is the subset needed to parse a byte slice.
+
+#[cfg(feature = "std")]
+pub(crate) use std::io::{Error, ErrorKind, Read, Result};
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::{Error, ErrorKind, Read, Result};
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use core::fmt;
+
+    /// The kind of an `IoError`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        InvalidData,
+        InvalidInput,
+        UnexpectedEof,
+    }
+
+    /// Represents an error when reading the data of a SoundFont or a MIDI file,
+    /// in place of `std::io::Error` without the `std` feature.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        message: &'static str,
+    }
+
+    impl Error {
+        pub(crate) fn new(kind: ErrorKind, message: &'static str) -> Self {
+            Self { kind, message }
+        }
+
+        /// Gets the kind of the error.
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self {
+            let message = match kind {
+                ErrorKind::InvalidData => "invalid data",
+                ErrorKind::InvalidInput => "invalid input parameter",
+                ErrorKind::UnexpectedEof => "unexpected end of file",
+            };
+            Self { kind, message }
+        }
+    }
+
+    impl core::error::Error for Error {}
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => return Err(Error::from(ErrorKind::UnexpectedEof)),
+                    n => buf = &mut buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let len = buf.len().min(self.len());
+            let (data, rest) = self.split_at(len);
+            buf[..len].copy_from_slice(data);
+            *self = rest;
+            Ok(len)
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+}
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
 
 #[derive(Debug, Clone)]
@@ -71,4 +72,9 @@ impl Lfo {
     pub(crate) fn get_value(&self) -> f32 {
         self.value
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_usize(self.processed_sample_count);
+        hasher.write_f32(self.value);
+    }
 }
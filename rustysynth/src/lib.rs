@@ -1,23 +1,50 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(clippy::collapsible_match)]
+// The test harness links std, whose float methods take the place of FloatMath without the feature.
+#![cfg_attr(all(test, not(feature = "std")), allow(unused_imports))]
+
+// The synthesis core uses only the core and alloc paths,
+// so that the crate builds without std, with the float math going through libm.
+#[macro_use]
+extern crate alloc;
 
 mod error;
+#[cfg(not(feature = "std"))]
+mod float_math;
+mod io;
 
+mod arpeggiator;
 mod array_math;
+mod audio_effect;
+#[cfg(feature = "output")]
+mod audio_output;
 mod binary_reader;
+#[cfg(feature = "std")]
+mod dls_reader;
 mod four_cc;
 mod read_counter;
+mod state_hasher;
+mod wav_format;
+#[cfg(feature = "std")]
+mod wave_writer;
 
 mod generator;
+mod generator_set;
 mod generator_type;
 mod instrument;
 mod instrument_info;
 mod instrument_region;
 mod loop_mode;
+mod modulator;
 mod preset;
 mod preset_info;
 mod preset_region;
 mod sample_header;
+#[cfg(feature = "std")]
+mod sfz_instrument;
 mod soundfont;
+#[cfg(feature = "std")]
+mod soundfont_builder;
 mod soundfont_info;
 mod soundfont_math;
 mod soundfont_parameters;
@@ -28,41 +55,147 @@ mod zone_info;
 
 mod bi_quad_filter;
 mod channel;
+#[cfg(feature = "introspection")]
+mod channel_info;
+mod channel_routing;
+#[cfg(feature = "std")]
+mod command_queue;
+mod dither;
+mod dither_mode;
 mod envelope_stage;
+mod interpolator;
 mod lfo;
+mod limiter_mode;
 mod modulation_envelope;
+mod mpe_zone;
+mod note;
 mod oscillator;
+mod pan_curve;
+mod queue_overflow;
+mod random;
 mod region_ex;
 mod region_pair;
+mod resampling_synthesizer;
+mod retrigger_mode;
 mod synthesizer;
 mod synthesizer_settings;
+mod synthesizer_settings_builder;
+mod synthesizer_state;
+mod tuning;
 mod voice;
 mod voice_collection;
+#[cfg(feature = "introspection")]
+mod voice_info;
+mod voice_stealing_policy;
+mod volume_curve;
 mod volume_envelope;
+#[cfg(feature = "wasm")]
+mod wasm_synthesizer;
 
+mod loop_note_cutoff;
+#[cfg(feature = "midi-input")]
+mod midi_input_driver;
+mod midi_recording;
 mod midifile;
 mod midifile_looptype;
+mod midifile_parser;
+#[cfg(feature = "std")]
+mod midifile_reader;
 mod midifile_sequencer;
+mod midifile_text;
+#[cfg(feature = "std")]
+mod midifile_writer;
+mod sequencer_event;
+mod sequencer_loop_mode;
 
 mod chorus;
 mod reverb;
 
+pub use self::arpeggiator::ArpConfig;
+pub use self::arpeggiator::ArpMode;
+pub use self::audio_effect::AudioEffect;
+#[cfg(feature = "output")]
+pub use self::audio_output::AudioOutput;
+#[cfg(feature = "output")]
+pub use self::audio_output::AudioOutputHandle;
+#[cfg(feature = "introspection")]
+pub use self::channel_info::ChannelInfo;
+pub use self::channel_routing::ChannelRouting;
+#[cfg(feature = "std")]
+pub use self::command_queue::CommandQueue;
+pub use self::dither_mode::DitherMode;
+#[cfg(feature = "introspection")]
+pub use self::envelope_stage::EnvelopeStage;
+#[cfg(feature = "output")]
+pub use self::error::AudioOutputError;
+pub use self::error::CheckpointError;
 pub use self::error::MidiFileError;
+#[cfg(feature = "std")]
+pub use self::error::SfzError;
 pub use self::error::SoundFontError;
 pub use self::error::SynthesizerError;
+pub use self::error::TuningError;
+pub use self::generator_set::GeneratorSet;
 pub use self::instrument::Instrument;
 pub use self::instrument_region::InstrumentRegion;
+pub use self::interpolator::CubicInterpolator;
+pub use self::interpolator::Interpolator;
+pub use self::interpolator::LinearInterpolator;
+pub use self::interpolator::SincInterpolator;
+#[cfg(not(feature = "std"))]
+pub use self::io::Error as IoError;
+#[cfg(not(feature = "std"))]
+pub use self::io::ErrorKind as IoErrorKind;
+pub use self::limiter_mode::LimiterMode;
 pub use self::loop_mode::LoopMode;
+pub use self::loop_note_cutoff::LoopNoteCutoff;
+#[cfg(feature = "midi-input")]
+pub use self::midi_input_driver::MidiInputDriver;
+pub use self::midi_recording::MidiRecording;
+pub use self::midifile::Message;
 pub use self::midifile::MidiFile;
 pub use self::midifile_looptype::MidiFileLoopType;
+pub use self::midifile_parser::MidiFileParser;
+#[cfg(feature = "std")]
+pub use self::midifile_reader::MidiFileReader;
+pub use self::midifile_sequencer::Checkpoint;
 pub use self::midifile_sequencer::MidiFileSequencer;
+pub use self::midifile_text::MidiFileText;
+pub use self::midifile_text::MidiFileTextKind;
+#[cfg(feature = "std")]
+pub use self::midifile_writer::MidiFileWriter;
+pub use self::mpe_zone::MpeZone;
+pub use self::note::key_to_frequency;
+pub use self::note::key_to_frequency_with_reference;
+pub use self::note::key_to_note_name;
+pub use self::note::DEFAULT_A4_FREQUENCY;
+pub use self::pan_curve::PanCurve;
 pub use self::preset::Preset;
 pub use self::preset_region::PresetRegion;
+pub use self::queue_overflow::QueueOverflow;
+pub use self::resampling_synthesizer::ResamplingSynthesizer;
+pub use self::retrigger_mode::RetriggerMode;
 pub use self::sample_header::SampleHeader;
+pub use self::sequencer_event::SequencerEvent;
+pub use self::sequencer_loop_mode::SequencerLoopMode;
+#[cfg(feature = "std")]
+pub use self::sfz_instrument::SfzInstrument;
 pub use self::soundfont::SoundFont;
 #[cfg(feature = "bevy_asset")]
 pub use self::soundfont::SoundFontAsset;
+#[cfg(feature = "std")]
+pub use self::soundfont_builder::SoundFontBuilder;
 pub use self::soundfont_info::SoundFontInfo;
 pub use self::soundfont_version::SoundFontVersion;
 pub use self::synthesizer::Synthesizer;
 pub use self::synthesizer_settings::SynthesizerSettings;
+pub use self::synthesizer_settings_builder::SynthesizerSettingsBuilder;
+pub use self::synthesizer_state::SynthesizerState;
+pub use self::tuning::Tuning;
+#[cfg(feature = "introspection")]
+pub use self::voice_info::VoiceInfo;
+pub use self::voice_stealing_policy::VoiceStealingPolicy;
+pub use self::volume_curve::VolumeCurve;
+#[cfg(feature = "wasm")]
+pub use self::wasm_synthesizer::WasmSynthesizer;
+pub use self::wav_format::WavFormat;
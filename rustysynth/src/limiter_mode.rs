@@ -0,0 +1,13 @@
+/// Specifies how the master output is kept within the range of `[-1, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimiterMode {
+    /// The output is passed as it is, and the samples beyond the range clip when converted.
+    /// This is the default behavior.
+    Off,
+    /// The peaks above 0.9 are rounded off smoothly toward 1,
+    /// which distorts the loudest peaks slightly but adds no delay or pumping.
+    SoftClip,
+    /// The gain is turned down at once when a peak would exceed the range,
+    /// and recovers over 100 ms, which keeps the waveform of the loud passages intact.
+    Limiter,
+}
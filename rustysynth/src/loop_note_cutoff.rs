@@ -0,0 +1,11 @@
+/// Specifies how the sounding notes are stopped when the sequencer jumps back to the loop start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopNoteCutoff {
+    /// The notes are released and ring out over the loop start.
+    /// The tails still ringing from the previous loop are faded out at the loop point,
+    /// so that they do not pile up over many loops.
+    /// This is the default behavior.
+    Release,
+    /// The notes are cut immediately, which suits tight rhythmic loops.
+    Immediate,
+}
@@ -0,0 +1,271 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError, TryLockError};
+
+use crate::synthesizer::Synthesizer;
+
+// A complete message parsed from the input, with its timestamp in microseconds.
+#[derive(Debug)]
+enum InputEvent {
+    Message(u64, [i32; 4]),
+    SysEx(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct InputState {
+    events: VecDeque<InputEvent>,
+
+    // The running status, which is 0 if there is none.
+    status: u8,
+    data: [u8; 2],
+    data_count: usize,
+    // The SysEx message being received, including the leading 0xF0.
+    sysex: Option<Vec<u8>>,
+
+    // The timestamp of the last byte, watched once an active sensing message is received.
+    active_sensing: bool,
+    last_timestamp: u64,
+}
+
+/// Connects a stream of raw MIDI bytes, such as a MIDI input port, to a synthesizer.
+///
+/// The input thread passes the bytes to `push` with their timestamps,
+/// for example from the callback of a `midir` input connection,
+/// and the render thread calls `process` before rendering, which schedules the messages
+/// at the samples matching their timestamps with `Synthesizer::process_midi_message_at`.
+/// The driver can be shared between threads with `Arc`.
+///
+/// The bytes can be split across the calls at any point. The running status, the real time messages
+/// within other messages, and SysEx messages are handled.
+/// If an active sensing message has been received, all the notes are released
+/// when no byte arrives for 300 milliseconds, as the MIDI specification requires.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct MidiInputDriver {
+    state: Mutex<InputState>,
+    latency: u64,
+}
+
+impl MidiInputDriver {
+    const ACTIVE_SENSING_TIMEOUT: u64 = 300_000;
+
+    /// Creates a driver which plays each message `latency` microseconds after its timestamp.
+    ///
+    /// The latency absorbs the jitter of the input and the render thread.
+    /// It should be at least the length of the buffer rendered after each `process`;
+    /// the messages which arrive later than their time are played at the start of the buffer.
+    pub fn new(latency: u64) -> Self {
+        Self {
+            state: Mutex::new(InputState {
+                events: VecDeque::new(),
+                status: 0,
+                data: [0; 2],
+                data_count: 0,
+                sysex: None,
+                active_sensing: false,
+                last_timestamp: 0,
+            }),
+            latency,
+        }
+    }
+
+    /// Parses the bytes received at the timestamp in microseconds.
+    ///
+    /// The timestamps must come from the same clock as the time passed to `process`,
+    /// and must not go backward.
+    pub fn push(&self, timestamp: u64, bytes: &[u8]) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.last_timestamp = timestamp;
+
+        for &byte in bytes {
+            match byte {
+                // Active sensing.
+                0xFE => state.active_sensing = true,
+                // The other real time messages may appear anywhere, and are ignored.
+                0xF8..=0xFF => (),
+                0xF0 => {
+                    state.status = 0;
+                    state.sysex = Some(vec![0xF0]);
+                }
+                0xF7 => {
+                    if let Some(mut data) = state.sysex.take() {
+                        data.push(0xF7);
+                        state.events.push_back(InputEvent::SysEx(data));
+                    }
+                }
+                // The system common messages cancel the running status, and are ignored.
+                0xF1..=0xF6 => {
+                    state.status = 0;
+                    state.sysex = None;
+                }
+                0x80..=0xEF => {
+                    state.status = byte;
+                    state.data_count = 0;
+                    state.sysex = None;
+                }
+                _ => {
+                    if let Some(data) = state.sysex.as_mut() {
+                        data.push(byte);
+                        continue;
+                    }
+
+                    // Data without a status is discarded.
+                    if state.status == 0 {
+                        continue;
+                    }
+
+                    let index = state.data_count;
+                    state.data[index] = byte;
+                    state.data_count += 1;
+
+                    let command = state.status & 0xF0;
+                    let length = if command == 0xC0 || command == 0xD0 {
+                        1
+                    } else {
+                        2
+                    };
+                    if state.data_count == length {
+                        let message = [
+                            (state.status & 0x0F) as i32,
+                            command as i32,
+                            state.data[0] as i32,
+                            if length == 2 { state.data[1] as i32 } else { 0 },
+                        ];
+                        state
+                            .events
+                            .push_back(InputEvent::Message(timestamp, message));
+                        state.data_count = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Schedules the messages received so far on the synthesizer,
+    /// where `now` is the time in microseconds of the next sample to be rendered.
+    ///
+    /// A message is placed `latency` microseconds after its timestamp,
+    /// counted from the next sample with the sample rate of the synthesizer.
+    /// SysEx messages are applied at once, in the order they were received.
+    ///
+    /// This never blocks: if the input thread is in `push` at the moment,
+    /// nothing is done, and the messages are scheduled by the next call instead.
+    pub fn process(&self, synthesizer: &mut Synthesizer, now: u64) {
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+
+        let sample_rate = synthesizer.get_sample_rate() as u64;
+        for event in state.events.drain(..) {
+            match event {
+                InputEvent::Message(timestamp, [channel, command, data1, data2]) => {
+                    let delay = (timestamp + self.latency).saturating_sub(now);
+                    let offset = (delay * sample_rate / 1_000_000) as usize;
+                    synthesizer.process_midi_message_at(channel, command, data1, data2, offset);
+                }
+                InputEvent::SysEx(data) => synthesizer.process_sysex(&data),
+            }
+        }
+
+        if state.active_sensing
+            && now.saturating_sub(state.last_timestamp) > MidiInputDriver::ACTIVE_SENSING_TIMEOUT
+        {
+            state.active_sensing = false;
+            synthesizer.note_off_all(false);
+        }
+    }
+
+    /// Discards the messages not processed yet and the partial message being received,
+    /// and stops watching the active sensing.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.events.clear();
+        state.status = 0;
+        state.data_count = 0;
+        state.sysex = None;
+        state.active_sensing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::synthesizer::tests::test_synthesizer;
+
+    fn drain(driver: &MidiInputDriver) -> Vec<[i32; 4]> {
+        let mut state = driver.state.lock().unwrap();
+        state
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                InputEvent::Message(_, message) => Some(message),
+                InputEvent::SysEx(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let driver = MidiInputDriver::new(0);
+
+        // A note-on split across the calls, and the running status with a clock in between.
+        driver.push(0, &[0x91]);
+        driver.push(0, &[0x3C, 0x64, 0x3E]);
+        driver.push(0, &[0xF8, 0x00, 0xC2, 0x05]);
+        assert_eq!(
+            drain(&driver),
+            vec![
+                [1, 0x90, 0x3C, 0x64],
+                [1, 0x90, 0x3E, 0x00],
+                [2, 0xC0, 0x05, 0]
+            ]
+        );
+
+        // A SysEx message ends the running status.
+        driver.push(
+            0,
+            &[
+                0xB0, 0x07, 0x64, 0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7, 0x07, 0x50,
+            ],
+        );
+        assert_eq!(drain(&driver), vec![[0, 0xB0, 0x07, 0x64]]);
+    }
+
+    #[test]
+    fn test_process() {
+        let mut synthesizer = test_synthesizer();
+
+        // The note is played 1 ms, or 44 samples, after its timestamp.
+        let driver = MidiInputDriver::new(1000);
+        driver.push(0, &[0xFE, 0x90, 0x3C, 0x64]);
+        driver.process(&mut synthesizer, 0);
+        let mut left = vec![0_f32; 100];
+        let mut right = vec![0_f32; 100];
+        synthesizer.render(&mut left, &mut right);
+        assert!(left[..44].iter().all(|&x| x == 0_f32));
+        assert!(left[44..54].iter().any(|&x| x != 0_f32));
+        assert!(synthesizer.is_note_on(0, 0x3C));
+
+        // The notes are released when the active sensing stops for 300 ms.
+        driver.process(&mut synthesizer, 200_000);
+        assert!(synthesizer.is_note_on(0, 0x3C));
+        driver.process(&mut synthesizer, 400_000);
+        assert!(!synthesizer.is_note_on(0, 0x3C));
+
+        // The messages are kept while the input thread holds the lock.
+        driver.push(400_000, &[0x90, 0x40, 0x64]);
+        let state = driver.state.lock().unwrap();
+        driver.process(&mut synthesizer, 400_000);
+        assert!(!synthesizer.is_note_on(0, 0x40));
+        drop(state);
+        driver.process(&mut synthesizer, 400_000);
+        let mut left = vec![0_f32; 100];
+        let mut right = vec![0_f32; 100];
+        synthesizer.render(&mut left, &mut right);
+        assert!(synthesizer.is_note_on(0, 0x40));
+    }
+}
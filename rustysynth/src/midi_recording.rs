@@ -0,0 +1,170 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+#[cfg(feature = "std")]
+use crate::midifile_writer::MidiFileWriter;
+
+// A message captured by the recording, as it was given to the synthesizer.
+#[derive(Debug, Clone)]
+enum RecordedEvent {
+    Message {
+        channel: i32,
+        command: i32,
+        data1: i32,
+        data2: i32,
+    },
+    SysEx(Vec<u8>),
+}
+
+/// The MIDI messages captured by `Synthesizer::start_recording`, with their times in samples.
+///
+/// The recording is exported as a format 1 MIDI file by `write`, or by `to_midi_file_writer`
+/// to choose the resolution and the tempo. The first track holds the tempo and the SysEx messages,
+/// and each channel used has its own track after it, in the order of the channels.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MidiRecording {
+    sample_rate: i32,
+    // The position of the synthesizer in samples when the recording started.
+    start: u64,
+    // The events in the order they were captured, with their times in samples from the start.
+    events: Vec<(u64, RecordedEvent)>,
+    length: u64,
+}
+
+impl MidiRecording {
+    /// The resolution used by `write`, in ticks per quarter note.
+    pub const DEFAULT_RESOLUTION: i32 = 960;
+    /// The tempo used by `write`, in BPM.
+    pub const DEFAULT_TEMPO: f64 = 120.0;
+
+    pub(crate) fn new(sample_rate: i32, start: u64) -> Self {
+        Self {
+            sample_rate,
+            start,
+            events: Vec::new(),
+            length: 0,
+        }
+    }
+
+    pub(crate) fn add_message(
+        &mut self,
+        time: u64,
+        channel: i32,
+        command: i32,
+        data1: i32,
+        data2: i32,
+    ) {
+        if !matches!(command, 0x80 | 0x90 | 0xA0 | 0xB0 | 0xC0 | 0xD0 | 0xE0) {
+            return;
+        }
+
+        let event = RecordedEvent::Message {
+            channel,
+            command,
+            data1,
+            data2,
+        };
+        self.events.push((time.saturating_sub(self.start), event));
+    }
+
+    // The SysEx data is stored with the leading 0xF0, which the synthesizer takes without it as well.
+    pub(crate) fn add_sysex(&mut self, time: u64, data: &[u8]) {
+        let data = match data.first() {
+            Some(0xF0) => data.to_vec(),
+            _ => [&[0xF0][..], data].concat(),
+        };
+        let time = time.saturating_sub(self.start);
+        self.events.push((time, RecordedEvent::SysEx(data)));
+    }
+
+    // The recording is as long as the time it was stopped, or its last event if that is later.
+    pub(crate) fn finish(&mut self, time: u64) {
+        let last = self.events.iter().map(|(time, _)| *time).max();
+        self.length = time.saturating_sub(self.start).max(last.unwrap_or(0));
+    }
+
+    /// Gets the sample rate of the synthesizer which recorded the messages.
+    pub fn get_sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    /// Gets the number of the messages captured.
+    pub fn get_event_count(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Gets the length of the recording in seconds.
+    pub fn get_length(&self) -> f64 {
+        self.length as f64 / self.sample_rate as f64
+    }
+
+    /// Converts the recording into a MIDI file at the resolution in ticks per quarter note
+    /// and the tempo in BPM, with the times rounded to the nearest tick.
+    #[cfg(feature = "std")]
+    pub fn to_midi_file_writer(&self, resolution: i32, bpm: f64) -> MidiFileWriter {
+        if bpm <= 0.0 || bpm.is_nan() {
+            panic!("The tempo must be a positive value.");
+        }
+
+        let mut writer = MidiFileWriter::new(resolution);
+        let ticks_per_sample = resolution as f64 * bpm / (60.0 * self.sample_rate as f64);
+        let to_tick = |time: u64| (time as f64 * ticks_per_sample).round() as u64;
+
+        let conductor = writer.add_track();
+        writer.add_tempo(conductor, 0, bpm);
+
+        let mut channel_tracks: [Option<usize>; 16] = [None; 16];
+        let mut used = [false; 16];
+        for (_, event) in &self.events {
+            if let RecordedEvent::Message { channel, .. } = event {
+                used[*channel as usize] = true;
+            }
+        }
+        for channel in 0..16 {
+            if used[channel] {
+                channel_tracks[channel] = Some(writer.add_track());
+            }
+        }
+
+        // The events are added in the order they were captured,
+        // which the writer keeps at the same tick.
+        for (time, event) in &self.events {
+            let tick = to_tick(*time);
+            match event {
+                RecordedEvent::Message {
+                    channel,
+                    command,
+                    data1,
+                    data2,
+                } => {
+                    let track = channel_tracks[*channel as usize].unwrap();
+                    writer.add_message(track, tick, *channel, *command, *data1, *data2);
+                }
+                RecordedEvent::SysEx(data) => writer.add_sysex(conductor, tick, data),
+            }
+        }
+
+        for track in 0..writer.get_track_count() {
+            writer.set_track_end(track, to_tick(self.length));
+        }
+
+        writer
+    }
+
+    /// Writes the recording as a format 1 MIDI file,
+    /// at `DEFAULT_RESOLUTION` ticks per quarter note and `DEFAULT_TEMPO`.
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        self.to_midi_file_writer(
+            MidiRecording::DEFAULT_RESOLUTION,
+            MidiRecording::DEFAULT_TEMPO,
+        )
+        .write(writer)
+    }
+}
@@ -1,24 +1,61 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Write;
 
 use crate::binary_reader::BinaryReader;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
 use crate::four_cc::FourCC;
+use crate::io::Read;
+#[cfg(feature = "tokio")]
+use crate::midifile_parser::MidiFileParser;
+use crate::midifile_text::MidiFileText;
+use crate::midifile_text::MidiFileTextKind;
+#[cfg(feature = "std")]
+use crate::midifile_writer::MidiFileWriter;
 use crate::read_counter::ReadCounter;
+use crate::state_hasher::StateHasher;
 use crate::MidiFileError;
 use crate::MidiFileLoopType;
 
+/// Represents an event in a MIDI file.
 #[derive(Clone, Copy, Debug)]
 #[non_exhaustive]
-pub(crate) enum Message {
+pub enum Message {
+    /// A channel message, such as a note-on or a control change.
     Normal { status: u8, data1: u8, data2: u8 },
+    /// A tempo change in microseconds per quarter note, as a big-endian u24.
+    /// The tempo changes are already applied to the event times,
+    /// and are kept only to tell where the tempo changes.
     TempoChange { bytes: [u8; 3] },
+    /// A system exclusive message. The data can be obtained with `MidiFile::get_sysex_data`.
+    // The SysEx data is stored in the MidiFile, and only the u24 index is kept here.
+    SysEx { bytes: [u8; 3] },
+    /// A time signature, such as 3/4 or 6/8.
+    TimeSignature { numerator: u8, denominator: u8 },
+    /// The loop start point.
     LoopStart,
+    /// The loop end point.
     LoopEnd,
+    /// The end of a track.
     EndOfTrack,
 }
 
 impl Message {
+    // The channel message returned by a message filter, with the values cut to the bytes of the message.
+    pub(crate) fn filtered(channel: i32, command: i32, data1: i32, data2: i32) -> Self {
+        Self::Normal {
+            status: (command as u8 & 0xF0) | (channel as u8 & 0x0F),
+            data1: data1 as u8,
+            data2: data2 as u8,
+        }
+    }
+
     pub(crate) fn common1(status: u8, data1: u8) -> Self {
         Self::Normal {
             status,
@@ -72,6 +109,49 @@ impl Message {
         let bytes = tempo.to_be_bytes()[1..].try_into().unwrap();
         Self::TempoChange { bytes }
     }
+
+    pub(crate) fn sysex(index: usize) -> Self {
+        // Truncate to u24
+        let bytes = (index as u32).to_be_bytes()[1..].try_into().unwrap();
+        Self::SysEx { bytes }
+    }
+
+    pub(crate) fn time_signature(numerator: u8, denominator_power: u8) -> Self {
+        Self::TimeSignature {
+            numerator,
+            denominator: 1 << denominator_power.min(7),
+        }
+    }
+
+    pub(crate) fn get_sysex_index(bytes: [u8; 3]) -> usize {
+        u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize
+    }
+
+    // Converts the microseconds per quarter note to BPM.
+    pub(crate) fn get_tempo(bytes: [u8; 3]) -> f64 {
+        let tempo_i32 = i32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        60000000.0 / tempo_i32 as f64
+    }
+
+    // A note-on with the velocity 0 is a note-off.
+    pub(crate) fn is_note_on(&self) -> bool {
+        match *self {
+            Message::Normal { status, data2, .. } => status & 0xF0 == 0x90 && data2 > 0,
+            _ => false,
+        }
+    }
+}
+
+// An event read from a track.
+pub(crate) enum TrackEvent {
+    Message(Message),
+    SysEx(Vec<u8>),
+    Text(MidiFileTextKind, Vec<u8>),
+    // The number of sharps, or flats if negative, and whether the key is minor.
+    KeySignature(i8, bool),
+    EndOfTrack,
+    // A meta event or an escape sequence which is not used.
+    Ignored,
 }
 
 /// Standard MIDI file (SMF) with pre-computed event times in seconds.
@@ -80,17 +160,113 @@ impl Message {
 pub struct MidiFile {
     pub(crate) messages: Vec<Message>,
     pub(crate) times: Vec<f64>,
+    // The times of the messages in ticks, as written in the file.
+    pub(crate) ticks: Vec<u64>,
+    // The division of the header, which is negative for SMPTE timing.
+    pub(crate) resolution: i32,
+    pub(crate) sysex_data: Vec<Vec<u8>>,
+    // The tempo in BPM at the start of the file.
+    pub(crate) initial_tempo: f64,
+
+    // The meta events which are not played, in the order of their times.
+    pub(crate) track_count: usize,
+    pub(crate) texts: Vec<MidiFileText>,
+    pub(crate) key_signatures: Vec<(f64, i8, bool)>,
+
+    // The indices of the tempo changes in the messages with the tempos in BPM,
+    // and the bars where the time signatures start, as the tick, the bar counted from 0,
+    // the ticks per beat, and the beats per bar, from the 4/4 at the start of the file.
+    // The conversions of the positions look them up instead of walking the messages.
+    tempo_changes: Vec<(usize, f64)>,
+    bars: Vec<(u64, u64, u64, u64)>,
+}
+
+// The meta events read from a track, with their times in ticks.
+#[derive(Debug, Default)]
+struct TrackMeta {
+    texts: Vec<(i32, MidiFileTextKind, String)>,
+    key_signatures: Vec<(i32, i8, bool)>,
+}
+
+// The fields of the header chunk.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MidiFileHeader {
+    pub(crate) format: i16,
+    pub(crate) track_count: i32,
+    pub(crate) resolution: i32,
+}
+
+// The tracks read so far, which are merged once all of them are read.
+#[derive(Debug, Default)]
+pub(crate) struct MidiFileTracks {
+    message_lists: Vec<Vec<Message>>,
+    tick_lists: Vec<Vec<i32>>,
+    sysex_data: Vec<Vec<u8>>,
+    metas: Vec<TrackMeta>,
+}
+
+impl MidiFileTracks {
+    pub(crate) fn read_track<R: Read>(
+        &mut self,
+        reader: &mut R,
+        loop_type: MidiFileLoopType,
+    ) -> Result<(), MidiFileError> {
+        let mut meta = TrackMeta::default();
+        let (message_list, tick_list) =
+            MidiFile::read_track(reader, loop_type, &mut self.sysex_data, &mut meta)?;
+        self.message_lists.push(message_list);
+        self.tick_lists.push(tick_list);
+        self.metas.push(meta);
+        Ok(())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.message_lists.len()
+    }
 }
 
 impl MidiFile {
+    #[cfg(feature = "std")]
     pub fn new<R: Read>(reader: &mut R) -> Result<Self, MidiFileError> {
-        MidiFile::new_with_loop_type(reader, MidiFileLoopType::LoopPoint(0))
+        MidiFile::read(reader, MidiFileLoopType::LoopPoint(0))
     }
 
+    #[cfg(feature = "std")]
     pub fn new_with_loop_type<R: Read>(
         reader: &mut R,
         loop_type: MidiFileLoopType,
     ) -> Result<Self, MidiFileError> {
+        MidiFile::read(reader, loop_type)
+    }
+
+    /// Loads a MIDI file from the bytes of a .mid file, such as a network response.
+    ///
+    /// Use `MidiFileParser` to parse the file while its data is still arriving.
+    /// Without the `std` feature, this replaces `new`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MidiFileError> {
+        MidiFile::read(&mut &data[..], MidiFileLoopType::LoopPoint(0))
+    }
+
+    /// Loads a MIDI file from the bytes of a .mid file with the loop type, as `new_with_loop_type` does.
+    pub fn from_bytes_with_loop_type(
+        data: &[u8],
+        loop_type: MidiFileLoopType,
+    ) -> Result<Self, MidiFileError> {
+        MidiFile::read(&mut &data[..], loop_type)
+    }
+
+    fn read<R: Read>(reader: &mut R, loop_type: MidiFileLoopType) -> Result<Self, MidiFileError> {
+        let header = MidiFile::read_header(reader)?;
+
+        let mut tracks = MidiFileTracks::default();
+        for _i in 0..header.track_count {
+            tracks.read_track(reader, loop_type)?;
+        }
+
+        Ok(MidiFile::from_tracks(header, tracks, loop_type))
+    }
+
+    pub(crate) fn read_header<R: Read>(reader: &mut R) -> Result<MidiFileHeader, MidiFileError> {
         let chunk_type = BinaryReader::read_four_cc(reader)?;
         if chunk_type != b"MThd" {
             return Err(MidiFileError::InvalidChunkType {
@@ -107,29 +283,51 @@ impl MidiFile {
         }
 
         let format = BinaryReader::read_i16_big_endian(reader)?;
-        if !(format == 0 || format == 1) {
+        if !(0..=2).contains(&format) {
             return Err(MidiFileError::UnsupportedFormat(format));
         }
 
         let track_count = BinaryReader::read_i16_big_endian(reader)? as i32;
         let resolution = BinaryReader::read_i16_big_endian(reader)? as i32;
 
-        let mut message_lists: Vec<Vec<Message>> = Vec::new();
-        let mut tick_lists: Vec<Vec<i32>> = Vec::new();
+        Ok(MidiFileHeader {
+            format,
+            track_count,
+            resolution,
+        })
+    }
+
+    // Merges the tracks read after the header into the file.
+    pub(crate) fn from_tracks(
+        header: MidiFileHeader,
+        tracks: MidiFileTracks,
+        loop_type: MidiFileLoopType,
+    ) -> Self {
+        let MidiFileHeader {
+            format,
+            track_count,
+            resolution,
+        } = header;
+        let MidiFileTracks {
+            mut message_lists,
+            mut tick_lists,
+            sysex_data,
+            mut metas,
+        } = tracks;
 
-        for _i in 0..track_count {
-            let (message_list, tick_list) = MidiFile::read_track(reader, loop_type)?;
-            message_lists.push(message_list);
-            tick_lists.push(tick_list);
+        // The tracks of a format 2 file are independent patterns, which are played one after another.
+        if format == 2 {
+            MidiFile::chain_tracks(&mut tick_lists, &mut metas);
         }
 
         match loop_type {
-            MidiFileLoopType::LoopPoint(loop_point) if loop_point != 0 => {
+            // A file without tracks has nowhere to put the loop point.
+            MidiFileLoopType::LoopPoint(loop_point) if loop_point != 0 && track_count > 0 => {
                 let loop_point = loop_point as i32;
                 let tick_list = &mut tick_lists[0];
                 let message_list = &mut message_lists[0];
 
-                if loop_point <= *tick_list.last().unwrap() {
+                if tick_list.last().is_some_and(|last| loop_point <= *last) {
                     for i in 0..tick_list.len() {
                         if tick_list[i] >= loop_point {
                             tick_list.insert(i, loop_point);
@@ -145,9 +343,191 @@ impl MidiFile {
             _ => (),
         }
 
-        let (messages, times) = MidiFile::merge_tracks(&message_lists, &tick_lists, resolution);
+        let initial_tempo = MidiFile::find_initial_tempo(&message_lists, &tick_lists);
+        let (messages, times, ticks) =
+            MidiFile::merge_tracks(&message_lists, &tick_lists, resolution);
+
+        let mut midi_file = Self {
+            messages,
+            times,
+            ticks,
+            resolution,
+            sysex_data,
+            initial_tempo,
+            track_count: track_count.max(0) as usize,
+            texts: Vec::new(),
+            key_signatures: Vec::new(),
+            tempo_changes: Vec::new(),
+            bars: Vec::new(),
+        };
+        midi_file.place_meta_events(metas);
+        midi_file.index_tempo_and_bars();
+
+        midi_file
+    }
+
+    // Shifts each track to start at the end of the previous one.
+    fn chain_tracks(tick_lists: &mut [Vec<i32>], metas: &mut [TrackMeta]) {
+        let mut offset: i32 = 0;
+        for (tick_list, meta) in tick_lists.iter_mut().zip(metas.iter_mut()) {
+            for tick in tick_list.iter_mut() {
+                *tick = tick.saturating_add(offset);
+            }
+            for text in meta.texts.iter_mut() {
+                text.0 = text.0.saturating_add(offset);
+            }
+            for key_signature in meta.key_signatures.iter_mut() {
+                key_signature.0 = key_signature.0.saturating_add(offset);
+            }
+            if let Some(&last) = tick_list.last() {
+                offset = offset.max(last);
+            }
+        }
+    }
+
+    // Gets the length of a tick in seconds at the tempo in BPM.
+    // A negative division is SMPTE timing, with the frames per second negated in the upper byte
+    // and the ticks per frame in the lower byte, where the tempo does not change the length of a tick.
+    pub(crate) fn seconds_per_tick(division: i32, tempo: f64) -> f64 {
+        match MidiFile::smpte_division(division) {
+            Some((frames_per_second, ticks_per_frame)) => {
+                1.0 / (frames_per_second * ticks_per_frame as f64)
+            }
+            None => 60.0 / (division as f64 * tempo),
+        }
+    }
+
+    fn smpte_division(division: i32) -> Option<(f64, i32)> {
+        if division >= 0 {
+            return None;
+        }
 
-        Ok(Self { messages, times })
+        let frames_per_second = match -((division >> 8) as i8 as i32) {
+            // The 29 frames per second is the drop frame rate of NTSC.
+            29 => 30000.0 / 1001.0,
+            value => value as f64,
+        };
+        let ticks_per_frame = (division & 0xFF).max(1);
+        Some((frames_per_second, ticks_per_frame))
+    }
+
+    // The number of ticks per quarter note, which is a quarter note at 120 BPM for SMPTE timing.
+    pub(crate) fn ticks_per_quarter(&self) -> u64 {
+        match MidiFile::smpte_division(self.resolution) {
+            Some((frames_per_second, ticks_per_frame)) => {
+                (frames_per_second * ticks_per_frame as f64 / 2.0).round() as u64
+            }
+            None => self.resolution as u64,
+        }
+        .max(1)
+    }
+
+    // Collects the tempo changes, and the bars where the time signatures start.
+    fn index_tempo_and_bars(&mut self) {
+        let ticks_per_quarter = self.ticks_per_quarter();
+        self.tempo_changes.clear();
+        self.bars.clear();
+        self.bars.push((0, 0, ticks_per_quarter, 4));
+
+        for (index, message) in self.messages.iter().enumerate() {
+            match *message {
+                Message::TempoChange { bytes } => {
+                    self.tempo_changes.push((index, Message::get_tempo(bytes)))
+                }
+                Message::TimeSignature {
+                    numerator,
+                    denominator,
+                } => {
+                    let tick = self.ticks[index];
+                    let (base_tick, bar, ticks_per_beat, beats_per_bar) =
+                        self.bars[self.bars.len() - 1];
+                    let bar = bar + (tick - base_tick).div_ceil(ticks_per_beat * beats_per_bar);
+                    let ticks_per_beat = (4 * ticks_per_quarter / denominator.max(1) as u64).max(1);
+                    self.bars
+                        .push((tick, bar, ticks_per_beat, numerator.max(1) as u64));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // Gets the tempo in BPM after the messages before the index.
+    fn tempo_before(&self, index: usize) -> f64 {
+        match self
+            .tempo_changes
+            .partition_point(|&(change, _)| change < index)
+        {
+            0 => 120.0,
+            count => self.tempo_changes[count - 1].1,
+        }
+    }
+
+    // Converts the ticks of the meta events to seconds, and sorts them by time.
+    // At the same tick, the order of the tracks and then of the file is kept.
+    fn place_meta_events(&mut self, metas: Vec<TrackMeta>) {
+        let mut texts: Vec<(i32, usize, MidiFileTextKind, String)> = Vec::new();
+        let mut key_signatures: Vec<(i32, i8, bool)> = Vec::new();
+        for (track, meta) in metas.into_iter().enumerate() {
+            texts.extend(
+                meta.texts
+                    .into_iter()
+                    .map(|(tick, kind, text)| (tick, track, kind, text)),
+            );
+            key_signatures.extend(meta.key_signatures);
+        }
+        texts.sort_by_key(|text| text.0);
+        key_signatures.sort_by_key(|key_signature| key_signature.0);
+
+        let texts: Vec<MidiFileText> = {
+            let mut to_seconds = self.tick_converter();
+            texts
+                .into_iter()
+                .map(|(tick, track, kind, text)| {
+                    let tick = tick.max(0) as u64;
+                    MidiFileText {
+                        time: to_seconds(tick),
+                        tick,
+                        track,
+                        kind,
+                        text,
+                    }
+                })
+                .collect()
+        };
+
+        let key_signatures: Vec<(f64, i8, bool)> = {
+            let mut to_seconds = self.tick_converter();
+            key_signatures
+                .into_iter()
+                .map(|(tick, sharps, minor)| (to_seconds(tick.max(0) as u64), sharps, minor))
+                .collect()
+        };
+
+        self.texts = texts;
+        self.key_signatures = key_signatures;
+    }
+
+    // Returns a function which converts the ticks given in ascending order to seconds,
+    // walking the events once for all the calls.
+    fn tick_converter(&self) -> impl FnMut(u64) -> f64 + '_ {
+        let mut index = 0;
+        let mut tempo: f64 = 120.0;
+        let mut base_tick: u64 = 0;
+        let mut base_time: f64 = 0.0;
+
+        move |tick| {
+            while index < self.messages.len() && self.ticks[index] <= tick {
+                base_tick = self.ticks[index];
+                base_time = self.times[index];
+                if let Message::TempoChange { bytes } = self.messages[index] {
+                    tempo = Message::get_tempo(bytes);
+                }
+                index += 1;
+            }
+
+            base_time
+                + MidiFile::seconds_per_tick(self.resolution, tempo) * (tick - base_tick) as f64
+        }
     }
 
     fn discard_data<R: Read>(reader: &mut R) -> Result<(), MidiFileError> {
@@ -156,6 +536,22 @@ impl MidiFile {
         Ok(())
     }
 
+    fn read_data<R: Read>(reader: &mut R) -> Result<Vec<u8>, MidiFileError> {
+        let size = BinaryReader::read_i32_variable_length(reader)? as usize;
+        let mut data = vec![0_u8; size];
+        reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    // The SysEx data is stored with the leading 0xF0.
+    fn read_sysex<R: Read>(reader: &mut R) -> Result<Vec<u8>, MidiFileError> {
+        let size = BinaryReader::read_i32_variable_length(reader)? as usize;
+        let mut data = vec![0_u8; size + 1];
+        data[0] = 0xF0;
+        reader.read_exact(&mut data[1..])?;
+        Ok(data)
+    }
+
     fn read_tempo<R: Read>(reader: &mut R) -> Result<i32, MidiFileError> {
         let size = BinaryReader::read_i32_variable_length(reader)?;
         if size != 3 {
@@ -169,9 +565,44 @@ impl MidiFile {
         Ok((b1 << 16) | (b2 << 8) | b3)
     }
 
+    // The time signature is given as the numerator and the power of two of the denominator,
+    // followed by the metronome clocks and the 32nd notes per quarter, which are ignored.
+    // A malformed time signature is skipped, as the other unused meta events are.
+    fn read_time_signature<R: Read>(reader: &mut R) -> Result<Option<(u8, u8)>, MidiFileError> {
+        let size = BinaryReader::read_i32_variable_length(reader)? as usize;
+        if size < 2 {
+            BinaryReader::discard_data(reader, size)?;
+            return Ok(None);
+        }
+
+        let numerator = BinaryReader::read_u8(reader)?;
+        let denominator_power = BinaryReader::read_u8(reader)?;
+        BinaryReader::discard_data(reader, size - 2)?;
+
+        Ok(Some((numerator, denominator_power)))
+    }
+
+    // The key signature is given as the number of sharps, or flats if negative, and 1 for a minor key.
+    // A malformed key signature is skipped.
+    fn read_key_signature<R: Read>(reader: &mut R) -> Result<Option<(i8, bool)>, MidiFileError> {
+        let size = BinaryReader::read_i32_variable_length(reader)? as usize;
+        if size < 2 {
+            BinaryReader::discard_data(reader, size)?;
+            return Ok(None);
+        }
+
+        let sharps = BinaryReader::read_u8(reader)? as i8;
+        let minor = BinaryReader::read_u8(reader)? != 0;
+        BinaryReader::discard_data(reader, size - 2)?;
+
+        Ok(Some((sharps, minor)))
+    }
+
     fn read_track<R: Read>(
         reader: &mut R,
         loop_type: MidiFileLoopType,
+        sysex_data: &mut Vec<Vec<u8>>,
+        meta: &mut TrackMeta,
     ) -> Result<(Vec<Message>, Vec<i32>), MidiFileError> {
         let chunk_type = BinaryReader::read_four_cc(reader)?;
         if chunk_type != b"MTrk" {
@@ -191,74 +622,138 @@ impl MidiFile {
         let mut last_status: u8 = 0;
 
         loop {
-            let delta = BinaryReader::read_i32_variable_length(reader)?;
-            let first = BinaryReader::read_u8(reader)?;
+            let (delta, event) = MidiFile::read_event(reader, loop_type, &mut last_status)?;
 
             tick += delta;
 
-            if (first & 128) == 0 {
-                let command = last_status & 0xF0;
-                if command == 0xC0 || command == 0xD0 {
-                    messages.push(Message::common1(last_status, first));
-                    ticks.push(tick);
-                } else {
-                    let data2 = BinaryReader::read_u8(reader)?;
-                    messages.push(Message::common2(last_status, first, data2, loop_type));
+            match event {
+                TrackEvent::Message(message) => {
+                    messages.push(message);
                     ticks.push(tick);
                 }
+                TrackEvent::SysEx(data) => {
+                    if sysex_data.len() < (1 << 24) {
+                        messages.push(Message::sysex(sysex_data.len()));
+                        ticks.push(tick);
+                        sysex_data.push(data);
+                    }
+                }
+                TrackEvent::Text(kind, data) => {
+                    meta.texts.push((tick, kind, MidiFileText::decode(&data)));
+                }
+                TrackEvent::KeySignature(sharps, minor) => {
+                    meta.key_signatures.push((tick, sharps, minor));
+                }
+                TrackEvent::EndOfTrack => {
+                    messages.push(Message::EndOfTrack);
+                    ticks.push(tick);
 
-                continue;
+                    // Some MIDI files may have events inserted after the EOT.
+                    // Such events should be ignored.
+                    if reader.bytes_read() < size {
+                        BinaryReader::discard_data(reader, size - reader.bytes_read())?;
+                    }
+
+                    return Ok((messages, ticks));
+                }
+                TrackEvent::Ignored => (),
             }
+        }
+    }
 
-            match first {
-                0xF0 => MidiFile::discard_data(reader)?,
-                0xF7 => MidiFile::discard_data(reader)?,
-                0xFF => match BinaryReader::read_u8(reader)? {
-                    0x2F => {
-                        BinaryReader::read_u8(reader)?;
-                        messages.push(Message::EndOfTrack);
-                        ticks.push(tick);
+    // Reads an event of a track and returns it with its delta time in ticks.
+    pub(crate) fn read_event<R: Read>(
+        reader: &mut R,
+        loop_type: MidiFileLoopType,
+        last_status: &mut u8,
+    ) -> Result<(i32, TrackEvent), MidiFileError> {
+        let delta = BinaryReader::read_i32_variable_length(reader)?;
+        let first = BinaryReader::read_u8(reader)?;
 
-                        // Some MIDI files may have events inserted after the EOT.
-                        // Such events should be ignored.
-                        if reader.bytes_read() < size {
-                            BinaryReader::discard_data(reader, size - reader.bytes_read())?;
-                        }
+        if (first & 128) == 0 {
+            let command = *last_status & 0xF0;
+            let message = if command == 0xC0 || command == 0xD0 {
+                Message::common1(*last_status, first)
+            } else {
+                let data2 = BinaryReader::read_u8(reader)?;
+                Message::common2(*last_status, first, data2, loop_type)
+            };
 
-                        return Ok((messages, ticks));
-                    }
-                    0x51 => {
-                        messages.push(Message::tempo_change(MidiFile::read_tempo(reader)?));
-                        ticks.push(tick);
+            return Ok((delta, TrackEvent::Message(message)));
+        }
+
+        let event = match first {
+            0xF0 => TrackEvent::SysEx(MidiFile::read_sysex(reader)?),
+            0xF7 => {
+                MidiFile::discard_data(reader)?;
+                TrackEvent::Ignored
+            }
+            0xFF => match BinaryReader::read_u8(reader)? {
+                0x2F => {
+                    BinaryReader::read_u8(reader)?;
+                    return Ok((delta, TrackEvent::EndOfTrack));
+                }
+                0x51 => TrackEvent::Message(Message::tempo_change(MidiFile::read_tempo(reader)?)),
+                0x58 => match MidiFile::read_time_signature(reader)? {
+                    Some((numerator, denominator_power)) => {
+                        TrackEvent::Message(Message::time_signature(numerator, denominator_power))
                     }
-                    _ => MidiFile::discard_data(reader)?,
+                    None => TrackEvent::Ignored,
                 },
-                _ => {
-                    let command = first & 0xF0;
-                    if command == 0xC0 || command == 0xD0 {
-                        let data1 = BinaryReader::read_u8(reader)?;
-                        messages.push(Message::common1(first, data1));
-                        ticks.push(tick);
-                    } else {
-                        let data1 = BinaryReader::read_u8(reader)?;
-                        let data2 = BinaryReader::read_u8(reader)?;
-                        messages.push(Message::common2(first, data1, data2, loop_type));
-                        ticks.push(tick);
+                0x59 => match MidiFile::read_key_signature(reader)? {
+                    Some((sharps, minor)) => TrackEvent::KeySignature(sharps, minor),
+                    None => TrackEvent::Ignored,
+                },
+                meta_type => match MidiFileTextKind::from_meta_type(meta_type) {
+                    Some(kind) => TrackEvent::Text(kind, MidiFile::read_data(reader)?),
+                    None => {
+                        MidiFile::discard_data(reader)?;
+                        TrackEvent::Ignored
                     }
-                }
+                },
+            },
+            _ => {
+                let command = first & 0xF0;
+                let message = if command == 0xC0 || command == 0xD0 {
+                    let data1 = BinaryReader::read_u8(reader)?;
+                    Message::common1(first, data1)
+                } else {
+                    let data1 = BinaryReader::read_u8(reader)?;
+                    let data2 = BinaryReader::read_u8(reader)?;
+                    Message::common2(first, data1, data2, loop_type)
+                };
+                TrackEvent::Message(message)
             }
+        };
+
+        *last_status = first;
 
-            last_status = first
+        Ok((delta, event))
+    }
+
+    fn find_initial_tempo(message_lists: &[Vec<Message>], tick_lists: &[Vec<i32>]) -> f64 {
+        for (message_list, tick_list) in message_lists.iter().zip(tick_lists.iter()) {
+            for (message, tick) in message_list.iter().zip(tick_list.iter()) {
+                if *tick > 0 {
+                    break;
+                }
+                if let Message::TempoChange { bytes } = *message {
+                    return Message::get_tempo(bytes);
+                }
+            }
         }
+
+        120.0
     }
 
     fn merge_tracks(
         message_lists: &[Vec<Message>],
         tick_lists: &[Vec<i32>],
         resolution: i32,
-    ) -> (Vec<Message>, Vec<f64>) {
+    ) -> (Vec<Message>, Vec<f64>, Vec<u64>) {
         let mut merged_messages: Vec<Message> = Vec::new();
         let mut merged_times: Vec<f64> = Vec::new();
+        let mut merged_ticks: Vec<u64> = Vec::new();
 
         let mut indices: Vec<usize> = vec![0; message_lists.len()];
 
@@ -270,13 +765,19 @@ impl MidiFile {
         loop {
             let mut min_tick = i32::MAX;
             let mut min_index: i32 = -1;
+            let mut min_is_note_on = false;
 
             for ch in 0..tick_lists.len() {
                 if indices[ch] < tick_lists[ch].len() {
                     let tick = tick_lists[ch][indices[ch]];
-                    if tick < min_tick {
+                    let is_note_on = message_lists[ch][indices[ch]].is_note_on();
+                    // At the same tick, the note-ons come after the events of the other tracks,
+                    // so that the program changes and controllers apply to the notes they set up.
+                    // Within a track, the order of the file is kept.
+                    if tick < min_tick || (tick == min_tick && min_is_note_on && !is_note_on) {
                         min_tick = tick;
                         min_index = ch as i32;
+                        min_is_note_on = is_note_on;
                     }
                 }
             }
@@ -287,39 +788,713 @@ impl MidiFile {
 
             let next_tick = tick_lists[min_index as usize][indices[min_index as usize]];
             let delta_tick = next_tick - current_tick;
-            let delta_time = 60.0 / (resolution as f64 * tempo) * delta_tick as f64;
+            let delta_time = MidiFile::seconds_per_tick(resolution, tempo) * delta_tick as f64;
 
             current_tick += delta_tick;
             current_time += delta_time;
 
             let message = message_lists[min_index as usize][indices[min_index as usize]];
+            // The tempo changes are kept so that the sequencer can report them.
             if let Message::TempoChange { bytes } = message {
-                let tempo_i32 = i32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
-                tempo = 60000000.0 / tempo_i32 as f64;
-            } else {
-                merged_messages.push(message);
-                merged_times.push(current_time);
+                tempo = Message::get_tempo(bytes);
             }
+            merged_messages.push(message);
+            merged_times.push(current_time);
+            merged_ticks.push(current_tick.max(0) as u64);
 
             indices[min_index as usize] += 1;
         }
 
-        (merged_messages, merged_times)
+        (merged_messages, merged_times, merged_ticks)
+    }
+
+    /// Writes the MIDI file as a standard MIDI file, as `MidiFileWriter::from_midi_file` would write it.
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        MidiFileWriter::from_midi_file(self).write(writer)
+    }
+
+    /// Gets the times of the events in ticks, as written in the file, in the same order as the events.
+    ///
+    /// Unlike the times in seconds, the ticks are exact, so they suit editing and musical positions.
+    /// The loop point given by `MidiFileLoopType::LoopPoint` is also in ticks.
+    pub fn ticks(&self) -> &[u64] {
+        &self.ticks[..]
+    }
+
+    /// Gets the resolution of the file in ticks per quarter note (PPQN).
+    ///
+    /// For a file timed in SMPTE frames, this is the division of the header as written,
+    /// which is negative, and the timing is given by `smpte_timing`.
+    pub fn resolution(&self) -> i32 {
+        self.resolution
+    }
+
+    /// Gets the frames per second and the ticks per frame if the file is timed in SMPTE frames,
+    /// or `None` if it is timed in ticks per quarter note.
+    ///
+    /// The 29 frames per second of the header is the drop frame rate, which is 29.97.
+    /// The ticks have a fixed length in seconds, so the tempo changes do not change the timing.
+    pub fn smpte_timing(&self) -> Option<(f64, i32)> {
+        MidiFile::smpte_division(self.resolution)
+    }
+
+    /// Converts a position in ticks to seconds, following the tempo changes of the file.
+    ///
+    /// A position after the last event is extrapolated at the last tempo.
+    pub fn tick_to_seconds(&self, tick: u64) -> f64 {
+        let index = self.ticks.partition_point(|&value| value <= tick);
+        let (base_tick, base_time) = match index {
+            0 => (0, 0.0),
+            _ => (self.ticks[index - 1], self.times[index - 1]),
+        };
+
+        let tempo = self.tempo_before(index);
+        base_time + MidiFile::seconds_per_tick(self.resolution, tempo) * (tick - base_tick) as f64
+    }
+
+    /// Converts a position in seconds to ticks, following the tempo changes of the file.
+    /// This is the inverse of `tick_to_seconds`, rounded down to a whole tick.
+    pub fn seconds_to_tick(&self, seconds: f64) -> u64 {
+        let index = self.times.partition_point(|&time| time <= seconds);
+        let (base_tick, base_time) = match index {
+            0 => (0, 0.0),
+            _ => (self.ticks[index - 1], self.times[index - 1]),
+        };
+
+        let tempo = self.tempo_before(index);
+        let delta =
+            (seconds - base_time).max(0.0) / MidiFile::seconds_per_tick(self.resolution, tempo);
+        // A little tolerance keeps the exact ticks from rounding down by the error of the seconds.
+        base_tick + (delta + 1.0E-6).floor() as u64
+    }
+
+    /// Converts a position in ticks to the bar, the beat, and the tick within the beat,
+    /// following the time signatures of the file, as a transport display shows it.
+    ///
+    /// The bar and the beat count from 1, and the tick from 0.
+    /// The beat is the note value of the denominator of the time signature,
+    /// such as an eighth note in 6/8. The file is 4/4 until its first time signature,
+    /// and a time signature in the middle of a bar starts a new bar.
+    pub fn tick_to_bar_beat(&self, tick: u64) -> (u32, u32, u32) {
+        let (base_tick, mut bar, ticks_per_beat, beats_per_bar) =
+            match self.bars.partition_point(|&(start, ..)| start <= tick) {
+                0 => (0, 0, self.ticks_per_quarter(), 4),
+                count => self.bars[count - 1],
+            };
+
+        let beats = (tick - base_tick) / ticks_per_beat;
+        bar += beats / beats_per_bar;
+        (
+            bar as u32 + 1,
+            (beats % beats_per_bar) as u32 + 1,
+            ((tick - base_tick) % ticks_per_beat) as u32,
+        )
+    }
+
+    /// Iterates over the tempo changes with their times in ticks and seconds, and the tempo in BPM.
+    ///
+    /// The tempo is 120 BPM until the first tempo change.
+    pub fn tempo_map(&self) -> impl Iterator<Item = (u64, f64, f64)> + '_ {
+        self.tempo_changes
+            .iter()
+            .map(|&(index, tempo)| (self.ticks[index], self.times[index], tempo))
     }
 
-    /// Total duration in seconds.
+    /// Total duration in seconds. This is `0` for a file without events.
     pub fn get_length(&self) -> f64 {
-        *self.times.last().unwrap()
+        self.times.last().copied().unwrap_or(0.0)
+    }
+
+    /// Total duration in ticks. This is `0` for a file without events.
+    pub fn get_length_ticks(&self) -> u64 {
+        self.ticks.last().copied().unwrap_or(0)
+    }
+
+    /// Gets the tempo in BPM (quarter notes per minute) at the start of the file.
+    /// The default tempo of 120 BPM is returned if the file does not set the tempo at the start.
+    pub fn get_initial_tempo(&self) -> f64 {
+        self.initial_tempo
+    }
+
+    /// Gets the numerator and denominator of the time signature at the start of the file.
+    /// 4/4 is returned if the file does not set the time signature at the start.
+    pub fn get_initial_time_signature(&self) -> (i32, i32) {
+        for (time, message) in self.times.iter().zip(self.messages.iter()) {
+            if *time > 0.0 {
+                break;
+            }
+            if let Message::TimeSignature {
+                numerator,
+                denominator,
+            } = *message
+            {
+                return (numerator as i32, denominator as i32);
+            }
+        }
+
+        (4, 4)
+    }
+
+    /// Gets the number of the tracks in the file.
+    pub fn get_track_count(&self) -> usize {
+        self.track_count
+    }
+
+    /// Gets the text meta events of all the tracks, in the order of their times.
+    ///
+    /// These are not a part of the events played, and are kept here for display,
+    /// such as the lyrics of a karaoke player or the song structure from the markers.
+    pub fn text_events(&self) -> &[MidiFileText] {
+        &self.texts[..]
+    }
+
+    /// Gets the name of the track, which is the first track name event in the track.
+    /// For the first track of a format 1 file, this is usually the name of the song.
+    pub fn get_track_name(&self, track: usize) -> Option<&str> {
+        self.find_text(MidiFileTextKind::TrackName, Some(track))
+    }
+
+    /// Gets the name of the instrument of the track, if the track has an instrument name event.
+    pub fn get_instrument_name(&self, track: usize) -> Option<&str> {
+        self.find_text(MidiFileTextKind::InstrumentName, Some(track))
+    }
+
+    /// Gets the first copyright notice of the file.
+    pub fn get_copyright(&self) -> Option<&str> {
+        self.find_text(MidiFileTextKind::Copyright, None)
+    }
+
+    /// Iterates over the markers with their times in seconds.
+    pub fn markers(&self) -> impl Iterator<Item = (f64, &str)> {
+        self.texts_of_kind(MidiFileTextKind::Marker)
+    }
+
+    /// Iterates over the lyrics with their times in seconds.
+    pub fn lyrics(&self) -> impl Iterator<Item = (f64, &str)> {
+        self.texts_of_kind(MidiFileTextKind::Lyric)
+    }
+
+    /// Iterates over the time signatures with their times in seconds,
+    /// as the numerator and denominator, such as `(3, 4)`.
+    pub fn time_signatures(&self) -> impl Iterator<Item = (f64, i32, i32)> + '_ {
+        self.times
+            .iter()
+            .zip(self.messages.iter())
+            .filter_map(|(time, message)| match *message {
+                Message::TimeSignature {
+                    numerator,
+                    denominator,
+                } => Some((*time, numerator as i32, denominator as i32)),
+                _ => None,
+            })
+    }
+
+    /// Iterates over the key signatures with their times in seconds, as the number of sharps,
+    /// which is negative for flats, and whether the key is minor.
+    /// For example, `(0.0, -3, true)` is C minor at the start.
+    pub fn key_signatures(&self) -> impl Iterator<Item = (f64, i32, bool)> + '_ {
+        self.key_signatures
+            .iter()
+            .map(|&(time, sharps, minor)| (time, sharps as i32, minor))
+    }
+
+    fn find_text(&self, kind: MidiFileTextKind, track: Option<usize>) -> Option<&str> {
+        self.texts
+            .iter()
+            .find(|text| text.kind == kind && track.is_none_or(|track| text.track == track))
+            .map(|text| text.get_text())
+    }
+
+    fn texts_of_kind(&self, kind: MidiFileTextKind) -> impl Iterator<Item = (f64, &str)> {
+        self.texts
+            .iter()
+            .filter(move |text| text.kind == kind)
+            .map(|text| (text.time, text.get_text()))
+    }
+
+    /// Iterates over the events whose times in seconds are in the range of `[start, end)`.
+    pub fn events_in_range(&self, start: f64, end: f64) -> impl Iterator<Item = (f64, &Message)> {
+        let index = self.times.partition_point(|time| *time < start);
+        self.times[index..]
+            .iter()
+            .zip(self.messages[index..].iter())
+            .take_while(move |(time, _)| **time < end)
+            .map(|(time, message)| (*time, message))
+    }
+
+    /// Gets the data of a SysEx event, including the leading 0xF0.
+    /// Returns `None` if the message is not a SysEx event.
+    pub fn get_sysex_data(&self, message: &Message) -> Option<&[u8]> {
+        match message {
+            Message::SysEx { bytes } => self
+                .sysex_data
+                .get(Message::get_sysex_index(*bytes))
+                .map(|data| &data[..]),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the file has a `LoopStart` or `LoopEnd` marker,
+    /// either from the file itself or from the loop type given at loading.
+    /// Without markers, a looping playback repeats the whole file.
+    pub fn has_loop_markers(&self) -> bool {
+        self.messages
+            .iter()
+            .any(|message| matches!(message, Message::LoopStart | Message::LoopEnd))
+    }
+
+    /// Gets the start and end of the loop region in seconds, as looped by `MidiFileSequencer`.
+    ///
+    /// The region starts at the first `LoopStart`, or at the beginning if there is none,
+    /// and ends at the first `LoopEnd` after it, or at the end of the file if there is none.
+    /// Returns `None` if the file has no loop markers.
+    pub fn loop_markers(&self) -> Option<(f64, f64)> {
+        if !self.has_loop_markers() {
+            return None;
+        }
+
+        let start_index = self
+            .messages
+            .iter()
+            .position(|message| matches!(message, Message::LoopStart));
+        let start = start_index.map_or(0.0, |index| self.times[index]);
+
+        let end = self
+            .messages
+            .iter()
+            .zip(self.times.iter())
+            .skip(start_index.unwrap_or(0))
+            .find(|(message, _)| matches!(message, Message::LoopEnd))
+            .map_or(self.get_length(), |(_, time)| *time);
+
+        Some((start, end))
+    }
+
+    /// Returns the peak number of notes held at the same time, which is useful to size
+    /// `SynthesizerSettings::maximum_polyphony`.
+    ///
+    /// Release tails, the hold pedal, and SoundFont presets with multiple layers are not
+    /// taken into account, so the actual number of voices can be larger.
+    pub fn max_simultaneous_notes(&self) -> usize {
+        let mut held: Vec<u16> = vec![0; 16 * 128];
+        let mut count: usize = 0;
+        let mut max_count: usize = 0;
+
+        for message in &self.messages {
+            if let Message::Normal {
+                status,
+                data1,
+                data2,
+            } = *message
+            {
+                let channel = (status & 0x0F) as usize;
+                let command = status & 0xF0;
+                let index = 128 * channel + (data1 & 0x7F) as usize;
+
+                match command {
+                    0x90 if data2 > 0 => {
+                        held[index] += 1;
+                        count += 1;
+                        max_count = max_count.max(count);
+                    }
+                    0x80 | 0x90 => {
+                        if held[index] > 0 {
+                            held[index] -= 1;
+                            count -= 1;
+                        }
+                    }
+                    // All Sound Off and All Notes Off
+                    0xB0 if data1 == 0x78 || data1 == 0x7B => {
+                        for value in &mut held[128 * channel..128 * (channel + 1)] {
+                            count -= *value as usize;
+                            *value = 0;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        max_count
+    }
+
+    // Computes a hash of the events to identify the MIDI file.
+    pub(crate) fn identity_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        let mut feed = |bytes: &[u8]| hasher.write(bytes);
+
+        for (time, message) in self.times.iter().zip(self.messages.iter()) {
+            feed(&time.to_bits().to_le_bytes());
+            match *message {
+                Message::Normal {
+                    status,
+                    data1,
+                    data2,
+                } => feed(&[0, status, data1, data2]),
+                Message::TempoChange { bytes } => feed(&[1, bytes[0], bytes[1], bytes[2]]),
+                Message::SysEx { bytes } => feed(&[2, bytes[0], bytes[1], bytes[2]]),
+                Message::TimeSignature {
+                    numerator,
+                    denominator,
+                } => feed(&[6, numerator, denominator]),
+                Message::LoopStart => feed(&[3]),
+                Message::LoopEnd => feed(&[4]),
+                Message::EndOfTrack => feed(&[5]),
+            }
+        }
+        for data in &self.sysex_data {
+            feed(data);
+        }
+
+        hasher.finish()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl MidiFile {
+    /// Loads a MIDI file from an asynchronous reader.
+    ///
+    /// The data is parsed by `MidiFileParser` as it is read, without blocking the runtime.
+    pub async fn from_async_reader<R: tokio::io::AsyncRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<Self, MidiFileError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut parser = MidiFileParser::new();
+        let mut buffer = vec![0_u8; 8192];
+        while !parser.is_complete() {
+            let length = reader.read(&mut buffer).await?;
+            if length == 0 {
+                break;
+            }
+            parser.push(&buffer[..length])?;
+        }
+        parser.finish()
     }
 }
 
-#[cfg(test)]
-mod tests {
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod tests {
     use super::*;
 
+    // A standard MIDI file with the tracks, in the format 0 if there is one and the format 1 otherwise.
+    pub(crate) fn midi_bytes(resolution: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let format: u16 = if tracks.len() == 1 { 0 } else { 1 };
+        let mut data: Vec<u8> = Vec::new();
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&6_u32.to_be_bytes());
+        data.extend_from_slice(&format.to_be_bytes());
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&resolution.to_be_bytes());
+        for track in tracks {
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            data.extend_from_slice(track);
+        }
+        data
+    }
+
     #[test]
     fn test_message_size() {
         // Avoid increasing the size of the Message type
         assert_eq!(size_of::<Message>(), 4);
     }
+
+    #[test]
+    fn test_max_simultaneous_notes() {
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x91, 0x40, 0x64, //
+            0x60, 0x90, 0x3C, 0x00, //
+            0x60, 0x90, 0x43, 0x64, //
+            0x60, 0x90, 0x48, 0x64, //
+            0x60, 0xB1, 0x7B, 0x00, // All notes off
+            0x60, 0x80, 0x43, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert_eq!(midi_file.max_simultaneous_notes(), 3);
+    }
+
+    #[test]
+    fn test_events_in_range() {
+        // Six volume changes at 0, 0.5, 1, 1, 1.5 and 2 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0xB0, 0x07, 0x64, //
+            0x60, 0xB0, 0x07, 0x64, //
+            0x60, 0xB0, 0x07, 0x64, //
+            0x00, 0xB0, 0x07, 0x64, //
+            0x60, 0xB0, 0x07, 0x64, //
+            0x60, 0xB0, 0x07, 0x64, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+
+        let times: Vec<f64> = midi_file
+            .events_in_range(1.0, 2.0)
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(times, vec![1.0, 1.0, 1.5]);
+        assert_eq!(midi_file.events_in_range(2.5, 3.0).count(), 0);
+    }
+
+    #[test]
+    fn test_initial_tempo_and_time_signature() {
+        let track: Vec<u8> = vec![
+            0x00, 0xFF, 0x51, 0x03, 0x09, 0x89, 0x68, // 96 BPM
+            0x00, 0xFF, 0x58, 0x04, 0x03, 0x03, 0x18, 0x08, // 3/8
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert!((midi_file.get_initial_tempo() - 96.0).abs() < 1e-9);
+        assert_eq!(midi_file.get_initial_time_signature(), (3, 8));
+        assert!((midi_file.get_length() - 0.625).abs() < 1e-9);
+        assert_eq!(midi_file.resolution(), 96);
+        assert_eq!(midi_file.ticks(), &[0, 0, 0, 96, 96]);
+    }
+
+    #[test]
+    fn test_loop_markers() {
+        // The notes at 0, 1 and 2 seconds, with the loop end at 0.5, the loop start at 1.5,
+        // and the loop end at 2.5 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0xB0, 0x6F, 0x00, //
+            0x60, 0x90, 0x3C, 0x64, //
+            0x60, 0xB0, 0x6E, 0x00, //
+            0x60, 0x90, 0x3C, 0x64, //
+            0x60, 0xB0, 0x6F, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+        let mut midi_file =
+            MidiFile::new_with_loop_type(&mut &data[..], MidiFileLoopType::IncredibleMachine)
+                .unwrap();
+        let note = midi_file.messages[0];
+        assert!(midi_file.has_loop_markers());
+        assert_eq!(midi_file.loop_markers(), Some((1.5, 2.5)));
+
+        midi_file.messages[5] = note;
+        assert_eq!(midi_file.loop_markers(), Some((1.5, 2.5)));
+
+        midi_file.messages[3] = note;
+        assert_eq!(midi_file.loop_markers(), Some((0.0, 0.5)));
+
+        midi_file.messages[1] = note;
+        assert!(!midi_file.has_loop_markers());
+        assert_eq!(midi_file.loop_markers(), None);
+    }
+
+    #[test]
+    fn test_load_file_without_events() {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&[0, 0, 0, 6, 0, 1, 0, 0, 0, 96]);
+
+        let loop_type = MidiFileLoopType::LoopPoint(96);
+        let midi_file = MidiFile::new_with_loop_type(&mut &data[..], loop_type).unwrap();
+        assert_eq!(midi_file.get_length(), 0.0);
+        assert_eq!(midi_file.events_in_range(0.0, 1.0).count(), 0);
+        assert_eq!(midi_file.loop_markers(), None);
+
+        // A track with nothing but the end of track.
+        data[11] = 1;
+        data.extend_from_slice(b"MTrk");
+        data.extend_from_slice(&[0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00]);
+        let midi_file = MidiFile::new_with_loop_type(&mut &data[..], loop_type).unwrap();
+        assert_eq!(midi_file.loop_markers(), Some((0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_program_change_before_note_on_at_same_tick() {
+        let note_track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let program_track: Vec<u8> = vec![
+            0x00, 0xC0, 0x05, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&note_track, &program_track]);
+
+        // The program change in the later track comes before the note-on at the same tick.
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        let program_change = midi_file
+            .messages
+            .iter()
+            .position(|message| matches!(message, Message::Normal { status: 0xC0, .. }));
+        let note_on = midi_file.messages.iter().position(Message::is_note_on);
+        assert!(program_change.unwrap() < note_on.unwrap());
+    }
+
+    #[test]
+    fn test_meta_events() {
+        let first_track: Vec<u8> = vec![
+            0x00, 0xFF, 0x03, 0x04, 0x53, 0x6F, 0x6E, 0x67, // Song
+            0x00, 0xFF, 0x02, 0x03, 0xA9, 0x20, 0x58, // (C) X in Latin-1
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 BPM
+            0x00, 0xFF, 0x59, 0x02, 0xFD, 0x01, // C minor
+            0x60, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, // 240 BPM
+            0x00, 0xFF, 0x06, 0x05, 0x56, 0x65, 0x72, 0x73, 0x65, // Verse
+            0x60, 0xFF, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08, // 6/8
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let second_track: Vec<u8> = vec![
+            0x00, 0xFF, 0x03, 0x05, 0x56, 0x6F, 0x63, 0x61, 0x6C, // Vocal
+            0x00, 0xFF, 0x04, 0x05, 0x43, 0x68, 0x6F, 0x69, 0x72, // Choir
+            0x60, 0xFF, 0x05, 0x02, 0x4C, 0x61, // La
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&first_track, &second_track]);
+
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert_eq!(midi_file.get_track_count(), 2);
+        assert_eq!(midi_file.get_track_name(0), Some("Song"));
+        assert_eq!(midi_file.get_track_name(1), Some("Vocal"));
+        assert_eq!(midi_file.get_instrument_name(0), None);
+        assert_eq!(midi_file.get_instrument_name(1), Some("Choir"));
+        assert_eq!(midi_file.get_copyright(), Some("\u{A9} X"));
+
+        // The times follow the tempo changes.
+        assert_eq!(
+            midi_file.markers().collect::<Vec<_>>(),
+            vec![(0.5, "Verse")]
+        );
+        assert_eq!(midi_file.lyrics().collect::<Vec<_>>(), vec![(0.5, "La")]);
+        assert_eq!(
+            midi_file.time_signatures().collect::<Vec<_>>(),
+            vec![(0.75, 6, 8)]
+        );
+        assert_eq!(
+            midi_file.key_signatures().collect::<Vec<_>>(),
+            vec![(0.0, -3, true)]
+        );
+
+        // The first track of the events at the same tick comes first.
+        let kinds: Vec<_> = midi_file
+            .text_events()
+            .iter()
+            .map(|text| (text.get_track(), text.get_kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0, MidiFileTextKind::TrackName),
+                (0, MidiFileTextKind::Copyright),
+                (1, MidiFileTextKind::TrackName),
+                (1, MidiFileTextKind::InstrumentName),
+                (0, MidiFileTextKind::Marker),
+                (1, MidiFileTextKind::Lyric),
+            ]
+        );
+        assert_eq!(midi_file.text_events()[5].get_tick(), 96);
+    }
+
+    #[test]
+    fn test_tempo_map_and_bar_beat() {
+        let track: Vec<u8> = vec![
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 BPM
+            0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08, // 3/4
+            0x81, 0x40, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, // 240 BPM at 192
+            0x81, 0x40, 0xFF, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08, // 6/8 at 384
+            0x81, 0x40, 0x90, 0x3C, 0x64, // At 576
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert_eq!(
+            midi_file.tempo_map().collect::<Vec<_>>(),
+            vec![(0, 0.0, 120.0), (192, 1.0, 240.0)]
+        );
+        assert_eq!(midi_file.get_length_ticks(), 576);
+        assert!((midi_file.get_length() - 2.0).abs() < 1e-9);
+        for tick in [0, 100, 192, 250, 576] {
+            assert_eq!(
+                midi_file.seconds_to_tick(midi_file.tick_to_seconds(tick)),
+                tick
+            );
+        }
+
+        // The 3/4 bars start at 0 and 288, and the 6/8 starts a new bar at 384 with the eighth note beats.
+        assert_eq!(midi_file.tick_to_bar_beat(0), (1, 1, 0));
+        assert_eq!(midi_file.tick_to_bar_beat(100), (1, 2, 4));
+        assert_eq!(midi_file.tick_to_bar_beat(288), (2, 1, 0));
+        assert_eq!(midi_file.tick_to_bar_beat(384), (3, 1, 0));
+        assert_eq!(midi_file.tick_to_bar_beat(576), (3, 5, 0));
+        assert_eq!(midi_file.tick_to_bar_beat(530), (3, 4, 2));
+    }
+
+    #[test]
+    fn test_format_2_with_smpte_timing() {
+        let first_track: Vec<u8> = vec![
+            0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, // 240 BPM, which is ignored
+            0x00, 0x90, 0x3C, 0x64, //
+            0x83, 0x74, 0x80, 0x3C, 0x00, // At 500
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let second_track: Vec<u8> = vec![
+            0x00, 0xFF, 0x03, 0x01, 0x42, // Track name
+            0x00, 0x91, 0x40, 0x64, //
+            0x81, 0x7A, 0xFF, 0x2F, 0x00, // At 250
+        ];
+        // 25 frames per second with 40 ticks per frame, which is 1000 ticks per second.
+        let mut data: Vec<u8> = Vec::new();
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&[0, 0, 0, 6, 0, 2, 0, 2, 0xE7, 0x28]);
+        for track in [&first_track, &second_track] {
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            data.extend_from_slice(track);
+        }
+
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert_eq!(midi_file.smpte_timing(), Some((25.0, 40)));
+        assert_eq!(midi_file.ticks(), &[0, 0, 500, 500, 500, 750]);
+        let expected_times = [0.0, 0.0, 0.5, 0.5, 0.5, 0.75];
+        for (time, expected) in midi_file.times.iter().zip(expected_times) {
+            assert!((time - expected).abs() < 1e-9);
+        }
+        assert_eq!(midi_file.texts[0].tick, 500);
+        assert_eq!(midi_file.seconds_to_tick(0.6), 600);
+
+        // The streaming reader plays the tracks in the same order.
+        let mut reader = crate::MidiFileReader::new(std::io::Cursor::new(&data)).unwrap();
+        for (message, expected) in midi_file.messages.iter().zip(expected_times) {
+            let (time, read) = reader.read_event().unwrap().unwrap();
+            assert!((time - expected).abs() < 1e-9);
+            assert_eq!(format!("{:?}", read), format!("{:?}", message));
+        }
+        assert!(reader.read_event().unwrap().is_none());
+
+        // The NTSC drop frame rate.
+        assert!(
+            (MidiFile::seconds_per_tick(-(29 << 8) | 2, 120.0) - 1001.0 / 60000.0).abs() < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_with_loop_type() {
+        // The loop start and end of the Incredible Machine, at 0.5 and 1 second.
+        let track: Vec<u8> = vec![
+            0x60, 0xB0, 0x6E, 0x00, //
+            0x60, 0xB0, 0x6F, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+        let midi_file =
+            MidiFile::from_bytes_with_loop_type(&data, MidiFileLoopType::IncredibleMachine)
+                .unwrap();
+        assert_eq!(midi_file.loop_markers(), Some((0.5, 1.0)));
+        assert!(!MidiFile::from_bytes(&data).unwrap().has_loop_markers());
+    }
 }
@@ -0,0 +1,204 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::io;
+use crate::midifile::MidiFile;
+use crate::midifile::MidiFileHeader;
+use crate::midifile::MidiFileTracks;
+use crate::MidiFileError;
+use crate::MidiFileLoopType;
+
+/// Parses a MIDI file from data which arrives in pieces, such as a network stream.
+///
+/// The data is given to `push` as it arrives, in pieces of any size,
+/// and each chunk of the file is parsed as soon as all of its bytes are there,
+/// so only the chunk being received is kept as bytes.
+/// `get_parsed_track_count` tells the progress, and `finish` returns the file once all the tracks are parsed.
+/// After an error, the parser should be dropped.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct MidiFileParser {
+    loop_type: MidiFileLoopType,
+    // The bytes of the chunk being received.
+    buffer: Vec<u8>,
+    bytes_read: u64,
+    header: Option<MidiFileHeader>,
+    tracks: MidiFileTracks,
+}
+
+impl MidiFileParser {
+    // The header chunk is the chunk type, its size, and the three fields.
+    const HEADER_SIZE: usize = 14;
+    // The chunk type and the size.
+    const CHUNK_HEADER_SIZE: usize = 8;
+
+    pub fn new() -> Self {
+        MidiFileParser::new_with_loop_type(MidiFileLoopType::LoopPoint(0))
+    }
+
+    pub fn new_with_loop_type(loop_type: MidiFileLoopType) -> Self {
+        Self {
+            loop_type,
+            buffer: Vec::new(),
+            bytes_read: 0,
+            header: None,
+            tracks: MidiFileTracks::default(),
+        }
+    }
+
+    /// Adds the next piece of the data, and parses the chunks which are complete.
+    ///
+    /// The data after the last track is ignored, as `MidiFile::new` does.
+    pub fn push(&mut self, data: &[u8]) -> Result<(), MidiFileError> {
+        if self.is_complete() {
+            self.bytes_read += data.len() as u64;
+            return Ok(());
+        }
+
+        self.buffer.extend_from_slice(data);
+
+        loop {
+            let header = match self.header {
+                Some(header) => header,
+                None => {
+                    if self.buffer.len() < MidiFileParser::HEADER_SIZE {
+                        return Ok(());
+                    }
+                    let header = MidiFile::read_header(&mut &self.buffer[..])?;
+                    self.consume(MidiFileParser::HEADER_SIZE);
+                    self.header = Some(header);
+                    header
+                }
+            };
+
+            if self.tracks.len() >= header.track_count.max(0) as usize {
+                let rest = self.buffer.len();
+                self.consume(rest);
+                return Ok(());
+            }
+
+            if self.buffer.len() < MidiFileParser::CHUNK_HEADER_SIZE {
+                return Ok(());
+            }
+            let size = u32::from_be_bytes([
+                self.buffer[4],
+                self.buffer[5],
+                self.buffer[6],
+                self.buffer[7],
+            ]) as usize;
+            let length = MidiFileParser::CHUNK_HEADER_SIZE.saturating_add(size);
+            if self.buffer.len() < length {
+                return Ok(());
+            }
+
+            // A track without its end of track fails here, rather than reading into the next chunk.
+            self.tracks
+                .read_track(&mut &self.buffer[..length], self.loop_type)?;
+            self.consume(length);
+        }
+    }
+
+    fn consume(&mut self, length: usize) {
+        self.buffer.drain(..length);
+        self.bytes_read += length as u64;
+    }
+
+    /// Gets the number of the bytes parsed so far, not counting those of the chunk being received.
+    pub fn get_bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Gets the number of the tracks given by the header, or `None` if the header is not parsed yet.
+    pub fn get_track_count(&self) -> Option<usize> {
+        self.header.map(|header| header.track_count.max(0) as usize)
+    }
+
+    /// Gets the number of the tracks parsed so far.
+    pub fn get_parsed_track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Returns `true` if all the tracks are parsed.
+    pub fn is_complete(&self) -> bool {
+        self.get_track_count() == Some(self.tracks.len())
+    }
+
+    /// Returns the MIDI file, or an `UnexpectedEof` error if the data ended before the last track.
+    pub fn finish(self) -> Result<MidiFile, MidiFileError> {
+        match self.header {
+            Some(header) if self.is_complete() => {
+                Ok(MidiFile::from_tracks(header, self.tracks, self.loop_type))
+            }
+            _ => Err(MidiFileError::IoError(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            ))),
+        }
+    }
+}
+
+impl Default for MidiFileParser {
+    fn default() -> Self {
+        MidiFileParser::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use crate::midifile::tests::midi_bytes;
+
+    fn build_midi_file() -> Vec<u8> {
+        let track: Vec<u8> = vec![
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, //
+            0x00, 0xF0, 0x03, 0x7E, 0x7F, 0xF7, //
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        midi_bytes(96, &[&track, &track])
+    }
+
+    #[test]
+    fn test_push_in_pieces() {
+        let data = build_midi_file();
+        let expected = MidiFile::from_bytes(&data).unwrap();
+
+        for piece in [1, 5, 13, data.len()] {
+            let mut parser = MidiFileParser::new();
+            assert_eq!(parser.get_track_count(), None);
+            for chunk in data.chunks(piece) {
+                parser.push(chunk).unwrap();
+                assert!(parser.get_bytes_read() <= data.len() as u64);
+            }
+            assert!(parser.is_complete());
+            assert_eq!(parser.get_track_count(), Some(2));
+            assert_eq!(parser.get_parsed_track_count(), 2);
+            assert_eq!(parser.get_bytes_read(), data.len() as u64);
+
+            let midi_file = parser.finish().unwrap();
+            assert_eq!(midi_file.ticks(), expected.ticks());
+            assert_eq!(midi_file.times, expected.times);
+            assert_eq!(midi_file.sysex_data, expected.sysex_data);
+        }
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let data = build_midi_file();
+        let mut parser = MidiFileParser::new();
+        parser.push(&data[..data.len() - 1]).unwrap();
+        assert_eq!(parser.get_parsed_track_count(), 1);
+        assert!(matches!(
+            parser.finish(),
+            Err(MidiFileError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+
+        let mut parser = MidiFileParser::new();
+        assert!(matches!(
+            parser.push(b"RIFF\0\0\0\x06\0\0\0\0\0\0"),
+            Err(MidiFileError::InvalidChunkType { .. })
+        ));
+    }
+}
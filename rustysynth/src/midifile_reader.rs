@@ -0,0 +1,335 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::binary_reader::BinaryReader;
+use crate::four_cc::FourCC;
+use crate::midifile::Message;
+use crate::midifile::MidiFile;
+use crate::midifile::TrackEvent;
+use crate::midifile_sequencer::EventSource;
+use crate::MidiFileError;
+use crate::MidiFileLoopType;
+
+/// Reads the events of a standard MIDI file (SMF) lazily, in the order of their times.
+///
+/// Unlike `MidiFile`, which parses the whole file up front, this reads only a small buffer
+/// of each track at a time, so that the playback can start at once and the memory stays bounded
+/// however long the file is. It can be played with `MidiFileSequencer::play_stream`.
+///
+/// The trade-off is that the events can only be read forward:
+/// the times are computed as the events are read, so there is no random seek,
+/// and the length of the file is not known until the end is reached.
+/// The loop points are not recognized, as looping needs to go back in the file.
+pub struct MidiFileReader<R: Read + Seek> {
+    reader: R,
+    resolution: i32,
+    tracks: Vec<TrackCursor>,
+    // Whether the tracks are played one after another, as in a format 2 file.
+    sequential: bool,
+
+    current_tick: i64,
+    current_time: f64,
+    tempo: f64,
+
+    // The data of the last SysEx message read.
+    sysex_data: Vec<u8>,
+}
+
+// The read position in a track, with the next event read ahead.
+struct TrackCursor {
+    position: u64,
+    end: u64,
+    buffer: Vec<u8>,
+    buffer_read: usize,
+
+    last_status: u8,
+    tick: i64,
+    next: Option<(Message, Option<Vec<u8>>)>,
+}
+
+impl<R: Read + Seek> MidiFileReader<R> {
+    const BUFFER_SIZE: usize = 4096;
+
+    /// Reads the header and the locations of the tracks, and the first event of each track.
+    pub fn new(mut reader: R) -> Result<Self, MidiFileError> {
+        let chunk_type = BinaryReader::read_four_cc(&mut reader)?;
+        if chunk_type != b"MThd" {
+            return Err(MidiFileError::InvalidChunkType {
+                expected: FourCC::from_bytes(*b"MThd"),
+                actual: chunk_type,
+            });
+        }
+
+        let size = BinaryReader::read_i32_big_endian(&mut reader)?;
+        if size != 6 {
+            return Err(MidiFileError::InvalidChunkData(FourCC::from_bytes(
+                *b"MThd",
+            )));
+        }
+
+        let format = BinaryReader::read_i16_big_endian(&mut reader)?;
+        if !(0..=2).contains(&format) {
+            return Err(MidiFileError::UnsupportedFormat(format));
+        }
+
+        let track_count = BinaryReader::read_i16_big_endian(&mut reader)? as i32;
+        let resolution = BinaryReader::read_i16_big_endian(&mut reader)? as i32;
+
+        // Only the chunk headers are read here, and the track data is skipped.
+        let mut tracks: Vec<TrackCursor> = Vec::new();
+        for _i in 0..track_count {
+            let chunk_type = BinaryReader::read_four_cc(&mut reader)?;
+            if chunk_type != b"MTrk" {
+                return Err(MidiFileError::InvalidChunkType {
+                    expected: FourCC::from_bytes(*b"MTrk"),
+                    actual: chunk_type,
+                });
+            }
+
+            let size = BinaryReader::read_i32_big_endian(&mut reader)? as u32 as u64;
+            let position = reader.stream_position()?;
+            reader.seek(SeekFrom::Start(position + size))?;
+
+            tracks.push(TrackCursor {
+                position,
+                end: position + size,
+                buffer: Vec::new(),
+                buffer_read: 0,
+                last_status: 0,
+                tick: 0,
+                next: None,
+            });
+        }
+
+        let mut midi_file_reader = Self {
+            reader,
+            resolution,
+            tracks,
+            sequential: format == 2,
+            current_tick: 0,
+            current_time: 0.0,
+            tempo: 120.0,
+            sysex_data: Vec::new(),
+        };
+        for index in 0..midi_file_reader.tracks.len() {
+            midi_file_reader.read_ahead(index)?;
+        }
+
+        Ok(midi_file_reader)
+    }
+
+    /// Reads the next event and returns it with its time in seconds,
+    /// or `None` if all the events have been read.
+    ///
+    /// The events are in the same order as in `MidiFile`.
+    /// The data of a SysEx message can be obtained with `get_sysex_data` until the next event is read.
+    pub fn read_event(&mut self) -> Result<Option<(f64, Message)>, MidiFileError> {
+        let mut min_tick = i64::MAX;
+        let mut min_index: Option<usize> = None;
+        let mut min_is_note_on = false;
+
+        for (index, track) in self.tracks.iter().enumerate() {
+            if let Some((message, _)) = track.next.as_ref() {
+                let is_note_on = message.is_note_on();
+                // The same order as `MidiFile::merge_tracks` at the same tick.
+                if track.tick < min_tick
+                    || (track.tick == min_tick && min_is_note_on && !is_note_on)
+                {
+                    min_tick = track.tick;
+                    min_index = Some(index);
+                    min_is_note_on = is_note_on;
+                }
+                // Only the first track which is not finished is played.
+                if self.sequential {
+                    break;
+                }
+            }
+        }
+
+        let index = match min_index {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let (message, sysex_data) = self.tracks[index].next.take().unwrap();
+
+        let delta_tick = min_tick - self.current_tick;
+        self.current_tick = min_tick;
+        self.current_time +=
+            MidiFile::seconds_per_tick(self.resolution, self.tempo) * delta_tick as f64;
+
+        if let Message::TempoChange { bytes } = message {
+            self.tempo = Message::get_tempo(bytes);
+        }
+        if let Some(data) = sysex_data {
+            self.sysex_data = data;
+        }
+
+        if !matches!(message, Message::EndOfTrack) {
+            self.read_ahead(index)?;
+        } else if self.sequential {
+            // The next track starts at the end of this one.
+            if let Some(track) = self.tracks.get_mut(index + 1) {
+                track.tick += min_tick;
+            }
+        }
+
+        Ok(Some((self.current_time, message)))
+    }
+
+    /// Gets the data of the last SysEx message read, including the leading 0xF0.
+    pub fn get_sysex_data(&self) -> &[u8] {
+        &self.sysex_data[..]
+    }
+
+    /// Gets the resolution of the file in ticks per quarter note (PPQN),
+    /// or the negative division of the header for a file timed in SMPTE frames.
+    pub fn get_resolution(&self) -> i32 {
+        self.resolution
+    }
+
+    /// Gets the time of the last event read in seconds.
+    pub fn get_position(&self) -> f64 {
+        self.current_time
+    }
+
+    // Reads the next event of the track, skipping the unused ones.
+    fn read_ahead(&mut self, index: usize) -> Result<(), MidiFileError> {
+        let track = &mut self.tracks[index];
+        let mut stream = TrackStream {
+            reader: &mut self.reader,
+            track,
+        };
+
+        loop {
+            let mut last_status = stream.track.last_status;
+            let (delta, event) = MidiFile::read_event(
+                &mut stream,
+                MidiFileLoopType::LoopPoint(0),
+                &mut last_status,
+            )?;
+            stream.track.last_status = last_status;
+            stream.track.tick += delta as i64;
+
+            let next = match event {
+                TrackEvent::Message(message) => (message, None),
+                TrackEvent::SysEx(data) => (Message::sysex(0), Some(data)),
+                TrackEvent::EndOfTrack => (Message::EndOfTrack, None),
+                TrackEvent::Text(..) | TrackEvent::KeySignature(..) | TrackEvent::Ignored => {
+                    continue
+                }
+            };
+            stream.track.next = Some(next);
+
+            return Ok(());
+        }
+    }
+}
+
+impl<R: Read + Seek> fmt::Debug for MidiFileReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MidiFileReader")
+            .field("resolution", &self.resolution)
+            .field("track_count", &self.tracks.len())
+            .field("current_time", &self.current_time)
+            .finish()
+    }
+}
+
+// Reads the data of a track through its buffer, which is refilled from the file as needed.
+struct TrackStream<'a, R: Read + Seek> {
+    reader: &'a mut R,
+    track: &'a mut TrackCursor,
+}
+
+impl<R: Read + Seek> Read for TrackStream<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let track = &mut *self.track;
+
+        if track.buffer_read == track.buffer.len() {
+            // The end of the track reads as the end of the file.
+            let length =
+                (track.end - track.position).min(MidiFileReader::<R>::BUFFER_SIZE as u64) as usize;
+            if length == 0 {
+                return Ok(0);
+            }
+
+            track.buffer.resize(length, 0);
+            self.reader.seek(SeekFrom::Start(track.position))?;
+            self.reader.read_exact(&mut track.buffer[..])?;
+            track.position += length as u64;
+            track.buffer_read = 0;
+        }
+
+        let length = buf.len().min(track.buffer.len() - track.buffer_read);
+        buf[..length].copy_from_slice(&track.buffer[track.buffer_read..track.buffer_read + length]);
+        track.buffer_read += length;
+
+        Ok(length)
+    }
+}
+
+impl<R: Read + Seek + Send> EventSource for MidiFileReader<R> {
+    fn read_event(&mut self) -> Result<Option<(f64, Message)>, MidiFileError> {
+        MidiFileReader::read_event(self)
+    }
+
+    fn get_sysex_data(&self) -> &[u8] {
+        MidiFileReader::get_sysex_data(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::midifile::tests::midi_bytes;
+
+    #[test]
+    fn test_same_events_as_midi_file() {
+        let first_track: Vec<u8> = vec![
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 BPM
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x3C, 0x00, // Running status
+            0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, // 240 BPM
+            0x60, 0x90, 0x40, 0x64, //
+            0x60, 0x80, 0x40, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let second_track: Vec<u8> = vec![
+            0x00, 0xC1, 0x05, //
+            0x30, 0xF0, 0x03, 0x7E, 0x7F, 0xF7, // SysEx
+            0x30, 0xFF, 0x01, 0x02, 0x41, 0x42, // Text
+            0x40, 0x91, 0x43, 0x64, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&first_track, &second_track]);
+
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        let mut reader = MidiFileReader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(reader.get_resolution(), 96);
+
+        let mut count = 0;
+        while let Some((time, message)) = reader.read_event().unwrap() {
+            let expected = midi_file.messages[count];
+            assert!((time - midi_file.times[count]).abs() < 1e-9);
+            if let Message::SysEx { .. } = message {
+                assert_eq!(
+                    reader.get_sysex_data(),
+                    midi_file.get_sysex_data(&expected).unwrap()
+                );
+            } else {
+                assert_eq!(format!("{:?}", message), format!("{:?}", expected));
+            }
+            count += 1;
+        }
+        assert_eq!(count, midi_file.messages.len());
+    }
+}
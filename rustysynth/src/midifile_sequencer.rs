@@ -1,11 +1,39 @@
 #![allow(dead_code)]
 
-use std::cmp;
-use std::sync::Arc;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "std")]
+use std::io::Seek;
+#[cfg(feature = "std")]
+use std::io::Write;
 
+use crate::channel_routing::ChannelRouting;
+use crate::error::CheckpointError;
+use crate::error::MidiFileError;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::loop_note_cutoff::LoopNoteCutoff;
 use crate::midifile::Message;
 use crate::midifile::MidiFile;
+#[cfg(feature = "std")]
+use crate::midifile_reader::MidiFileReader;
+use crate::midifile_text::MidiFileText;
+use crate::sequencer_event::SequencerEvent;
+use crate::sequencer_loop_mode::SequencerLoopMode;
 use crate::synthesizer::Synthesizer;
+#[cfg(feature = "std")]
+use crate::wav_format::WavFormat;
+#[cfg(feature = "std")]
+use crate::wave_writer::WaveWriter;
 
 /// Plays a MIDI file through a synthesizer, handling tempo and looping.
 #[derive(Debug)]
@@ -15,161 +43,2942 @@ pub struct MidiFileSequencer {
 
     speed: f64,
 
+    // The output is resampled by this ratio, which changes the pitch and the tempo together.
+    // The resampler stays active after the ratio returns to 1 until the frames it has read ahead
+    // are played out, and the phase is drawn back to a whole frame over them.
+    varispeed: f64,
+    varispeed_active: bool,
+    varispeed_drain: usize,
+    varispeed_drain_step: f64,
+    varispeed_phase: f64,
+    varispeed_previous: (f32, f32),
+    varispeed_next: (f32, f32),
+    varispeed_left: Vec<f32>,
+    varispeed_right: Vec<f32>,
+    varispeed_read: usize,
+
     midi_file: Option<Arc<MidiFile>>,
+    // The events read lazily by play_stream, used instead of the MIDI file.
+    stream: Option<EventStream>,
     play_loop: bool,
+    loop_note_cutoff: LoopNoteCutoff,
+    loop_mode: SequencerLoopMode,
+    // The loop region set by the host, which replaces the loop events of the file.
+    loop_region: Option<(f64, f64)>,
+    // The problems found in the loop events of the file under the loop mode.
+    warnings: Vec<String>,
 
     block_wrote: usize,
 
+    // The number of blocks which a call of render_partial renders at most.
+    max_render_blocks: Option<usize>,
+
+    // The routing of render_stems, created by the first call.
+    stem_routing: Option<ChannelRouting>,
+
+    current_time: f64,
+    msg_index: usize,
+    // The message and the time to go back to at the loop end,
+    // and the number of jumps left for a loop played a limited number of times.
+    loop_index: usize,
+    loop_time: f64,
+    loop_remaining: Option<u32>,
+    // The next text event of the MIDI file to be reported.
+    text_index: usize,
+
+    // The position is frozen while paused, and the synthesizer keeps rendering.
+    paused: bool,
+
+    // The metronome clicks played before the sequence.
+    count_in_bars: u32,
+    count_in: CountIn,
+
+    // The transport of the MIDI clock or the host, which drives the position instead of the speed.
+    sync: Option<ExternalSync>,
+
+    on_tempo_change: TempoChangeCallback,
+    on_event: EventCallback,
+    message_filter: MessageFilter,
+}
+
+// The source of the events for the sequencer, such as a `MidiFileReader`.
+pub(crate) trait EventSource: Send {
+    fn read_event(&mut self) -> Result<Option<(f64, Message)>, MidiFileError>;
+    fn get_sysex_data(&self) -> &[u8];
+}
+
+// The events of play_stream, with the next event read ahead.
+struct EventStream {
+    source: Box<dyn EventSource>,
+    next: Option<(f64, Message)>,
+    ended: bool,
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("next", &self.next)
+            .field("ended", &self.ended)
+            .finish()
+    }
+}
+
+// A callback is not Debug, so it is wrapped to keep the sequencer Debug.
+struct TempoChangeCallback(Option<Box<dyn FnMut(f64, f64) + Send>>);
+
+impl fmt::Debug for TempoChangeCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(..)"),
+            None => write!(f, "None"),
+        }
+    }
+}
+
+// A loop point found under the loop mode, with the number of times the loop is played, if limited.
+#[derive(Clone, Copy)]
+enum LoopMarker {
+    Start(Option<u32>),
+    End,
+}
+
+impl LoopMarker {
+    fn find(mode: SequencerLoopMode, message: &Message) -> Option<LoopMarker> {
+        match mode {
+            SequencerLoopMode::Markers => match message {
+                Message::LoopStart => Some(LoopMarker::Start(None)),
+                Message::LoopEnd => Some(LoopMarker::End),
+                _ => None,
+            },
+            SequencerLoopMode::WholeFile => None,
+            _ => {
+                let (controller, value) = match *message {
+                    Message::Normal {
+                        status,
+                        data1,
+                        data2,
+                    } if status & 0xF0 == 0xB0 => (data1, data2),
+                    _ => return None,
+                };
+                match (mode, controller) {
+                    (SequencerLoopMode::RpgMaker, 111)
+                    | (SequencerLoopMode::IncredibleMachine, 110)
+                    | (SequencerLoopMode::FinalFantasy, 116) => Some(LoopMarker::Start(None)),
+                    (SequencerLoopMode::Emidi, 116) => {
+                        Some(LoopMarker::Start((value > 0).then_some(value as u32)))
+                    }
+                    (SequencerLoopMode::IncredibleMachine, 111)
+                    | (SequencerLoopMode::FinalFantasy, 117)
+                    | (SequencerLoopMode::Emidi, 117) => Some(LoopMarker::End),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+type EventCallbackFn = dyn FnMut(f64, SequencerEvent<'_>) + Send;
+
+struct EventCallback(Option<Box<EventCallbackFn>>);
+
+impl EventCallback {
+    fn call(&mut self, time: f64, event: SequencerEvent<'_>) {
+        if let Some(callback) = self.0.as_mut() {
+            callback(time, event);
+        }
+    }
+
+    // Reports the text events up to the time, and moves the index past them.
+    fn call_texts(&mut self, texts: &[MidiFileText], index: &mut usize, time: f64) {
+        while *index < texts.len() && texts[*index].time <= time {
+            let text = &texts[*index];
+            self.call(text.time, SequencerEvent::Text(text));
+            *index += 1;
+        }
+    }
+}
+
+impl fmt::Debug for EventCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(..)"),
+            None => write!(f, "None"),
+        }
+    }
+}
+
+type MessageFilterFn = dyn FnMut(i32, i32, i32, i32) -> Option<(i32, i32, i32, i32)> + Send;
+
+struct MessageFilter(Option<Box<MessageFilterFn>>);
+
+impl MessageFilter {
+    fn apply(
+        &mut self,
+        channel: i32,
+        command: i32,
+        data1: i32,
+        data2: i32,
+    ) -> Option<(i32, i32, i32, i32)> {
+        match self.0.as_mut() {
+            Some(filter) => filter(channel, command, data1, data2),
+            None => Some((channel, command, data1, data2)),
+        }
+    }
+}
+
+impl fmt::Debug for MessageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(..)"),
+            None => write!(f, "None"),
+        }
+    }
+}
+
+// The state of the count-in of a playback.
+#[derive(Debug, Clone, Copy)]
+struct CountIn {
+    // The time where the sequence starts after the count-in.
+    start: f64,
+    beat_length: f64,
+    beats_per_bar: u32,
+    beat_count: u32,
+    beat: u32,
+    // The key of the click still sounding.
+    key: Option<i32>,
+}
+
+impl CountIn {
+    // The keys of the Hi and Low Wood Block in the GM percussion map.
+    const ACCENT_KEY: i32 = 76;
+    const BEAT_KEY: i32 = 77;
+
+    fn none() -> Self {
+        Self {
+            start: 0.0,
+            beat_length: 0.0,
+            beats_per_bar: 0,
+            beat_count: 0,
+            beat: 0,
+            key: None,
+        }
+    }
+}
+
+// The state of the playback driven by the MIDI clock or the host.
+#[derive(Debug, Clone, Copy)]
+struct ExternalSync {
+    running: bool,
+    // Start and Continue wait for the next clock to start the playback.
+    waiting: bool,
+    // The positions of the playback and of the last clock in quarter notes,
+    // and the position which the playback does not pass before the next clock.
+    position: f64,
+    clock_position: f64,
+    limit: f64,
+    samples_per_quarter: f64,
+    // The sample position of the synthesizer at the last clock.
+    last_clock: u64,
+}
+
+impl ExternalSync {
+    const CLOCKS_PER_QUARTER: f64 = 24.0;
+    // 1/8 of each interval between the clocks is taken into the estimate of the tempo.
+    const CLOCK_SMOOTHING: f64 = 0.125;
+
+    fn stopped(position: f64, samples_per_quarter: f64) -> Self {
+        Self {
+            running: false,
+            waiting: false,
+            position,
+            clock_position: position,
+            limit: position,
+            samples_per_quarter,
+            last_clock: 0,
+        }
+    }
+}
+
+/// A snapshot of the playback state of a sequencer, including the state of the synthesizer.
+///
+/// The MIDI file is not stored, only a hash to check that the same file is played on restore.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Checkpoint {
+    synthesizer: Synthesizer,
+    midi_file_hash: Option<u64>,
+    play_loop: bool,
+    loop_note_cutoff: LoopNoteCutoff,
+    loop_mode: SequencerLoopMode,
+    loop_region: Option<(f64, f64)>,
+    speed: f64,
+    varispeed: f64,
+    varispeed_active: bool,
+    varispeed_drain: usize,
+    varispeed_drain_step: f64,
+    varispeed_phase: f64,
+    varispeed_previous: (f32, f32),
+    varispeed_next: (f32, f32),
+    varispeed_left: Vec<f32>,
+    varispeed_right: Vec<f32>,
+    varispeed_read: usize,
+    block_wrote: usize,
     current_time: f64,
     msg_index: usize,
     loop_index: usize,
+    loop_time: f64,
+    loop_remaining: Option<u32>,
+    paused: bool,
+    count_in: CountIn,
+    sync: Option<ExternalSync>,
+}
+
+impl Checkpoint {
+    /// Gets the playback position of the checkpoint in seconds.
+    pub fn get_position(&self) -> f64 {
+        self.current_time
+    }
 }
 
 impl MidiFileSequencer {
+    const MAXIMUM_VARISPEED: f64 = 16.0;
+    // The left and right of the 16 channels and of the effect return.
+    const MAXIMUM_STEM_OUTPUTS: usize = 2 * (Synthesizer::CHANNEL_COUNT + 1);
+
     pub fn new(synthesizer: Synthesizer) -> Self {
         Self {
+            varispeed_left: vec![0_f32; synthesizer.block_size],
+            varispeed_right: vec![0_f32; synthesizer.block_size],
+            varispeed_read: synthesizer.block_size,
             synthesizer,
             speed: 1.0,
+            varispeed: 1.0,
+            varispeed_active: false,
+            varispeed_drain: 0,
+            varispeed_drain_step: 1.0,
+            varispeed_phase: 0.0,
+            varispeed_previous: (0_f32, 0_f32),
+            varispeed_next: (0_f32, 0_f32),
             midi_file: None,
+            stream: None,
             play_loop: false,
+            loop_note_cutoff: LoopNoteCutoff::Release,
+            loop_mode: SequencerLoopMode::Markers,
+            loop_region: None,
+            warnings: Vec::new(),
             block_wrote: 0,
+            max_render_blocks: None,
+            stem_routing: None,
             current_time: 0.0,
             msg_index: 0,
             loop_index: 0,
+            loop_time: 0.0,
+            loop_remaining: None,
+            text_index: 0,
+            paused: false,
+            count_in_bars: 0,
+            count_in: CountIn::none(),
+            sync: None,
+            on_tempo_change: TempoChangeCallback(None),
+            on_event: EventCallback(None),
+            message_filter: MessageFilter(None),
         }
     }
 
     pub fn play(&mut self, midi_file: &Arc<MidiFile>, play_loop: bool) {
         self.midi_file = Some(Arc::clone(midi_file));
+        self.stream = None;
         self.play_loop = play_loop;
+        self.loop_region = None;
+        self.check_loop_events();
 
         self.block_wrote = self.synthesizer.block_size;
 
         self.current_time = 0.0;
         self.msg_index = 0;
-        self.loop_index = 0;
+        self.reset_loop_start();
+        self.text_index = 0;
+        self.paused = false;
 
-        self.synthesizer.reset()
+        self.reset_varispeed();
+
+        self.synthesizer.reset();
+
+        if self.sync.is_some() {
+            let samples_per_quarter =
+                60.0 * self.synthesizer.sample_rate as f64 / midi_file.get_initial_tempo();
+            self.sync = Some(ExternalSync::stopped(0.0, samples_per_quarter));
+        }
+
+        self.start_count_in(0.0);
     }
 
-    pub fn stop(&mut self) {
+    /// Starts playing the events of the reader, which are read as they are needed.
+    ///
+    /// This starts at once and keeps the memory bounded however long the file is,
+    /// at the cost of what needs the whole file: the sequence does not loop, and seeking,
+    /// the checkpoints, skipping to the notes, the count-in and the reverse playback are not available.
+    /// `get_midi_file` returns `None` while a stream is played.
+    /// A read error ends the sequence at the event which could not be read.
+    ///
+    /// The events are read within `render`, which then waits for the reader and allocates the messages,
+    /// so a stream is not real-time safe. Unless the reader is over memory, such as a `Cursor`,
+    /// the stream should be rendered away from the audio callback, such as for an export.
+    #[cfg(feature = "std")]
+    pub fn play_stream<R: Read + Seek + Send + 'static>(&mut self, reader: MidiFileReader<R>) {
         self.midi_file = None;
+        self.stream = Some(EventStream {
+            source: Box::new(reader),
+            next: None,
+            ended: false,
+        });
+        self.play_loop = false;
+        self.loop_region = None;
+        self.warnings.clear();
+
+        self.block_wrote = self.synthesizer.block_size;
+
+        self.current_time = 0.0;
+        self.msg_index = 0;
+        self.reset_loop_start();
+        self.paused = false;
+
+        self.reset_varispeed();
+
         self.synthesizer.reset();
+
+        self.count_in = CountIn::none();
     }
 
-    /// Renders interleaved stereo audio. Both buffers must be the same length.
-    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
-        if left.len() != right.len() {
-            panic!("The output buffers for the left and right must be the same length.");
+    /// Starts playing the MIDI file from the given position in seconds.
+    ///
+    /// The events before the position are applied without playing the notes,
+    /// so that the programs and the controllers are correct from the first note.
+    pub fn play_from(&mut self, midi_file: &Arc<MidiFile>, play_loop: bool, start: f64) {
+        self.play(midi_file, play_loop);
+        self.apply_state_up_to(start.max(0.0));
+        self.start_count_in(start.max(0.0));
+    }
+
+    // Moves the playback position back by the length of the count-in before the given time.
+    fn start_count_in(&mut self, start: f64) {
+        self.count_in = CountIn::none();
+
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => value,
+            None => return,
+        };
+        if self.count_in_bars == 0 || self.sync.is_some() {
+            return;
         }
 
-        let left_length = left.len();
-        let mut wrote: usize = 0;
-        while wrote < left_length {
-            if self.block_wrote == self.synthesizer.block_size {
-                self.process_events();
-                self.block_wrote = 0;
-                self.current_time += self.speed * self.synthesizer.block_size as f64
-                    / self.synthesizer.sample_rate as f64;
-            }
+        let (numerator, denominator) = midi_file.get_initial_time_signature();
+        let beats_per_bar = numerator.max(1) as u32;
+        let beat_length = 60.0 / midi_file.get_initial_tempo() * 4.0 / denominator.max(1) as f64;
 
-            let src_rem = self.synthesizer.block_size - self.block_wrote;
-            let dst_rem = left_length - wrote;
-            let rem = cmp::min(src_rem, dst_rem);
+        self.count_in = CountIn {
+            start,
+            beat_length,
+            beats_per_bar,
+            beat_count: self.count_in_bars * beats_per_bar,
+            beat: 0,
+            key: None,
+        };
+        self.current_time = start - self.count_in.beat_count as f64 * beat_length;
+    }
+
+    // Plays the click of the count-in if its time has come,
+    // and stops the previous click when the next one or the sequence starts.
+    fn process_count_in(&mut self) {
+        let count_in = &mut self.count_in;
+        if count_in.beat == count_in.beat_count && count_in.key.is_none() {
+            return;
+        }
 
-            self.synthesizer.render(
-                &mut left[wrote..wrote + rem],
-                &mut right[wrote..wrote + rem],
-            );
+        let next_time =
+            count_in.start - (count_in.beat_count - count_in.beat) as f64 * count_in.beat_length;
+        if self.current_time < next_time {
+            return;
+        }
 
-            self.block_wrote += rem;
-            wrote += rem;
+        let channel = Synthesizer::PERCUSSION_CHANNEL as i32;
+        if let Some(key) = count_in.key.take() {
+            self.synthesizer.note_off(channel, key);
+        }
+
+        if count_in.beat < count_in.beat_count {
+            let (key, velocity) = if count_in.beat.is_multiple_of(count_in.beats_per_bar) {
+                (CountIn::ACCENT_KEY, 127)
+            } else {
+                (CountIn::BEAT_KEY, 100)
+            };
+            count_in.beat += 1;
+            count_in.key = Some(key);
+            self.synthesizer.note_on(channel, key, velocity);
         }
     }
 
-    fn process_events(&mut self) {
+    /// Processes the events before the given time in seconds without producing audio,
+    /// and moves the playback position to the time.
+    ///
+    /// The note-ons and note-offs are skipped, while the controllers, programs, RPNs and NRPNs,
+    /// pitch bends, and SysEx messages are applied to the synthesizer.
+    /// Nothing is done if the time is not after the current position.
+    pub fn apply_state_up_to(&mut self, time: f64) {
+        if time <= self.current_time {
+            return;
+        }
+
         let midi_file = match self.midi_file.as_ref() {
             Some(value) => value,
             None => return,
         };
 
         while self.msg_index < midi_file.messages.len() {
-            let time = midi_file.times[self.msg_index];
-            let msg = midi_file.messages[self.msg_index];
+            if midi_file.times[self.msg_index] >= time {
+                break;
+            }
 
-            if time <= self.current_time {
-                match msg {
-                    Message::Normal {
-                        status,
-                        data1,
-                        data2,
-                    } => {
-                        let channel = status & 0x0F;
-                        let command = status & 0xF0;
-                        self.synthesizer.process_midi_message(
-                            channel as i32,
-                            command as i32,
-                            data1 as i32,
-                            data2 as i32,
-                        );
+            match midi_file.messages[self.msg_index] {
+                Message::Normal {
+                    status,
+                    data1,
+                    data2,
+                } => {
+                    let channel = status & 0x0F;
+                    let command = status & 0xF0;
+                    if let Some((channel, command, data1, data2)) = self.message_filter.apply(
+                        channel as i32,
+                        command as i32,
+                        data1 as i32,
+                        data2 as i32,
+                    ) {
+                        if command != 0x80 && command != 0x90 {
+                            self.synthesizer
+                                .process_midi_message(channel, command, data1, data2);
+                        }
                     }
-                    Message::LoopStart if self.play_loop => self.loop_index = self.msg_index,
-                    Message::LoopEnd if self.play_loop => {
-                        self.current_time = midi_file.times[self.loop_index];
-                        self.msg_index = self.loop_index;
-                        self.synthesizer.note_off_all(false);
-                    }
-                    _ => (),
                 }
-                self.msg_index += 1;
-            } else {
-                break;
+                Message::SysEx { bytes } => {
+                    let index = Message::get_sysex_index(bytes);
+                    self.synthesizer.process_sysex(&midi_file.sysex_data[index]);
+                }
+                Message::TempoChange { bytes } => {
+                    self.synthesizer.set_tempo(Message::get_tempo(bytes))
+                }
+                _ => (),
+            }
+            if let Some(LoopMarker::Start(count)) =
+                LoopMarker::find(self.loop_mode, &midi_file.messages[self.msg_index])
+            {
+                if self.play_loop && self.loop_region.is_none() {
+                    self.loop_index = self.msg_index + 1;
+                    self.loop_time = midi_file.times[self.msg_index];
+                    self.loop_remaining = count.map(|count| count - 1);
+                }
             }
+            self.msg_index += 1;
         }
 
-        if self.msg_index == midi_file.messages.len() && self.play_loop {
-            self.current_time = midi_file.times[self.loop_index];
-            self.msg_index = self.loop_index;
+        self.current_time = time;
+        self.skip_texts();
+    }
+
+    // Forgets the loop start found so far, so that the whole file is looped until the next one.
+    fn reset_loop_start(&mut self) {
+        self.loop_index = 0;
+        self.loop_time = 0.0;
+        self.loop_remaining = None;
+    }
+
+    // Moves the text index to the current position after a jump,
+    // so that the text events passed over are not reported.
+    fn skip_texts(&mut self) {
+        if let Some(midi_file) = self.midi_file.as_ref() {
+            self.text_index = MidiFileSequencer::find_text(midi_file, self.current_time);
+        }
+    }
+
+    /// Moves the playback position to the next note-on after the current position,
+    /// applying the controllers and the other events in between as `apply_state_up_to` does.
+    ///
+    /// The sounding notes are released, and the note starts with the next rendered block.
+    /// Returns `false`, leaving the playback unchanged, if there is no note-on after the current position.
+    pub fn skip_to_next_note(&mut self) -> bool {
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => value,
+            None => return false,
+        };
+
+        let current_time = self.current_time;
+        let next = (self.msg_index..midi_file.messages.len()).find(|&index| {
+            midi_file.times[index] > current_time && midi_file.messages[index].is_note_on()
+        });
+        let time = match next {
+            Some(index) => midi_file.times[index],
+            None => return false,
+        };
+
+        self.count_in = CountIn::none();
+        self.synthesizer.note_off_all(false);
+        self.apply_state_up_to(time);
+        true
+    }
+
+    /// Moves the playback position to the last note-on before the current position.
+    ///
+    /// The note-ons within 0.25 seconds before the position are passed over,
+    /// so that calling this repeatedly while playing keeps going back.
+    /// The synthesizer is reset and the events before the note are applied again,
+    /// as `play_from` does, so the sounding notes are stopped at once.
+    /// Returns `false`, leaving the playback unchanged, if there is no such note-on.
+    pub fn skip_to_previous_note(&mut self) -> bool {
+        const GRACE_TIME_SEC: f64 = 0.25;
+
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => value,
+            None => return false,
+        };
+
+        let current_time = self.current_time - GRACE_TIME_SEC;
+        let previous = (0..midi_file.messages.len()).rev().find(|&index| {
+            midi_file.times[index] < current_time && midi_file.messages[index].is_note_on()
+        });
+        let time = match previous {
+            Some(index) => midi_file.times[index],
+            None => return false,
+        };
+
+        self.count_in = CountIn::none();
+        self.synthesizer.reset();
+        self.current_time = 0.0;
+        self.msg_index = 0;
+        self.reset_loop_start();
+        self.apply_state_up_to(time);
+        self.skip_texts();
+        true
+    }
+
+    /// Moves the playback position to the given time in seconds, forward or backward.
+    ///
+    /// The events before the time are applied as `apply_state_up_to` does,
+    /// so that the programs, the controllers, and the pitch bends are as if played from the start.
+    /// Seeking forward releases the sounding notes, while seeking backward resets the synthesizer,
+    /// as `skip_to_previous_note` does. The count-in is not played.
+    /// Nothing is done if no MIDI file is being played.
+    pub fn seek(&mut self, seconds: f64) {
+        if self.midi_file.is_none() {
+            return;
+        }
+
+        let time = seconds.max(0.0);
+
+        self.count_in = CountIn::none();
+        if time < self.current_time {
+            self.synthesizer.reset();
+            self.current_time = 0.0;
+            self.msg_index = 0;
+            self.reset_loop_start();
+        } else {
             self.synthesizer.note_off_all(false);
         }
+        self.apply_state_up_to(time);
+        self.skip_texts();
     }
 
-    pub fn get_synthesizer(&self) -> &Synthesizer {
-        &self.synthesizer
+    /// Moves the playback position to the given time in ticks of the MIDI file, as `seek` does.
+    pub fn seek_ticks(&mut self, ticks: u64) {
+        let time = match self.midi_file.as_ref() {
+            Some(value) => value.tick_to_seconds(ticks),
+            None => return,
+        };
+
+        self.seek(time);
     }
 
-    pub fn get_midi_file(&self) -> Option<&MidiFile> {
-        match &self.midi_file {
-            None => None,
-            Some(value) => Some(value),
+    /// Takes a checkpoint of the playback position, the speed, the loop, the pause, the external sync,
+    /// and the state of the synthesizer.
+    pub fn export_checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            synthesizer: self.synthesizer.clone(),
+            midi_file_hash: self.midi_file.as_ref().map(|value| value.identity_hash()),
+            play_loop: self.play_loop,
+            loop_note_cutoff: self.loop_note_cutoff,
+            loop_mode: self.loop_mode,
+            loop_region: self.loop_region,
+            speed: self.speed,
+            varispeed: self.varispeed,
+            varispeed_active: self.varispeed_active,
+            varispeed_drain: self.varispeed_drain,
+            varispeed_drain_step: self.varispeed_drain_step,
+            varispeed_phase: self.varispeed_phase,
+            varispeed_previous: self.varispeed_previous,
+            varispeed_next: self.varispeed_next,
+            varispeed_left: self.varispeed_left.clone(),
+            varispeed_right: self.varispeed_right.clone(),
+            varispeed_read: self.varispeed_read,
+            block_wrote: self.block_wrote,
+            current_time: self.current_time,
+            msg_index: self.msg_index,
+            loop_index: self.loop_index,
+            loop_time: self.loop_time,
+            loop_remaining: self.loop_remaining,
+            paused: self.paused,
+            count_in: self.count_in,
+            sync: self.sync,
         }
     }
 
-    /// Current playback position in seconds.
-    pub fn get_position(&self) -> f64 {
-        self.current_time
+    /// Restores the state saved by `export_checkpoint`, so that the playback continues
+    /// from exactly the same sample.
+    ///
+    /// The MIDI file is not part of the checkpoint; call `play` with the same file beforehand.
+    /// Fails without changing anything if the file being played differs from the one in the checkpoint.
+    pub fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let midi_file_hash = self.midi_file.as_ref().map(|value| value.identity_hash());
+        if midi_file_hash != checkpoint.midi_file_hash {
+            return Err(CheckpointError::MidiFileMismatch);
+        }
+
+        self.synthesizer = checkpoint.synthesizer.clone();
+        self.play_loop = checkpoint.play_loop;
+        self.loop_note_cutoff = checkpoint.loop_note_cutoff;
+        self.loop_mode = checkpoint.loop_mode;
+        self.loop_region = checkpoint.loop_region;
+        self.check_loop_events();
+        self.speed = checkpoint.speed;
+        self.varispeed = checkpoint.varispeed;
+        self.varispeed_active = checkpoint.varispeed_active;
+        self.varispeed_drain = checkpoint.varispeed_drain;
+        self.varispeed_drain_step = checkpoint.varispeed_drain_step;
+        self.varispeed_phase = checkpoint.varispeed_phase;
+        self.varispeed_previous = checkpoint.varispeed_previous;
+        self.varispeed_next = checkpoint.varispeed_next;
+        self.varispeed_left.clone_from(&checkpoint.varispeed_left);
+        self.varispeed_right.clone_from(&checkpoint.varispeed_right);
+        self.varispeed_read = checkpoint.varispeed_read;
+        self.block_wrote = checkpoint.block_wrote;
+        self.current_time = checkpoint.current_time;
+        self.msg_index = checkpoint.msg_index;
+        self.loop_index = checkpoint.loop_index;
+        self.loop_time = checkpoint.loop_time;
+        self.loop_remaining = checkpoint.loop_remaining;
+        self.paused = checkpoint.paused;
+        self.count_in = checkpoint.count_in;
+        self.sync = checkpoint.sync;
+        self.skip_texts();
+
+        Ok(())
     }
 
-    /// Returns `true` if playback has reached the end (or `play` was never called).
-    /// Always `false` when looping is enabled.
-    pub fn end_of_sequence(&self) -> bool {
-        match &self.midi_file {
-            None => true,
-            Some(value) => self.msg_index == value.messages.len(),
+    pub fn stop(&mut self) {
+        self.midi_file = None;
+        self.stream = None;
+        self.synthesizer.reset();
+    }
+
+    /// Renders interleaved stereo audio. Both buffers must be the same length.
+    ///
+    /// The buffers can have any length. The MIDI events are processed once per block
+    /// of the synthesizer, so small buffers do not cost extra event processing.
+    /// As `Synthesizer::render`, this does not allocate, lock, or panic once the MIDI file is playing,
+    /// except for a stream started by `play_stream`,
+    /// and a release build renders the length of the shorter buffer.
+    /// Each call returns once the buffers are filled, as the loop ends which would jump back
+    /// to themselves without the time going forward are ignored; see `get_warnings`.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        debug_assert_eq!(
+            left.len(),
+            right.len(),
+            "The output buffers for the left and right must be the same length."
+        );
+        let length = cmp::min(left.len(), right.len());
+        let (left, right) = (&mut left[..length], &mut right[..length]);
+
+        let start = if self.varispeed_active || self.varispeed != 1.0 {
+            self.render_varispeed(left, right)
+        } else {
+            0
+        };
+
+        if start < length {
+            self.render_source(&mut left[start..], &mut right[start..]);
+            // The last frame is where the resampler starts from when the varispeed is set.
+            self.varispeed_previous = (left[length - 1], right[length - 1]);
         }
     }
 
-    /// Playback speed multiplier (default 1.0).
-    pub fn get_speed(&self) -> f64 {
-        self.speed
+    // Resamples the output, and returns the number of the samples written,
+    // which is less than the length once the resampler has drained after the varispeed returned to 1.
+    fn render_varispeed(&mut self, left: &mut [f32], right: &mut [f32]) -> usize {
+        for (i, (left, right)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            if self.varispeed != 1.0 {
+                self.varispeed_drain = 0;
+                if !self.varispeed_active {
+                    // The resampler goes on from the last frame rendered without it.
+                    self.varispeed_active = true;
+                    self.varispeed_next = self.read_source_frame();
+                    self.varispeed_phase = 0.0;
+                    self.advance_varispeed(self.varispeed);
+                }
+            } else if self.varispeed_drain == 0 {
+                // The frames read ahead are played at nearly the normal rate,
+                // so that the last of them falls on a whole frame, followed by the source.
+                let last = (self.varispeed_left.len() - self.varispeed_read + 1) as f64;
+                self.varispeed_drain = self.varispeed_left.len() - self.varispeed_read + 2;
+                self.varispeed_drain_step = (last - self.varispeed_phase) / last;
+            }
+
+            if self.varispeed_drain == 1 {
+                (*left, *right) = self.varispeed_next;
+                self.varispeed_previous = self.varispeed_next;
+                self.varispeed_phase = 0.0;
+                self.varispeed_drain = 0;
+                self.varispeed_active = false;
+                return i + 1;
+            }
+
+            let a = self.varispeed_phase as f32;
+            let (previous_left, previous_right) = self.varispeed_previous;
+            let (next_left, next_right) = self.varispeed_next;
+            *left = previous_left + a * (next_left - previous_left);
+            *right = previous_right + a * (next_right - previous_right);
+
+            if self.varispeed_drain == 0 {
+                self.advance_varispeed(self.varispeed);
+            } else {
+                self.varispeed_drain -= 1;
+                if self.varispeed_drain > 1 {
+                    self.advance_varispeed(self.varispeed_drain_step);
+                }
+            }
+        }
+
+        left.len()
     }
 
-    /// Sets the playback speed. Must be non-negative.
-    pub fn set_speed(&mut self, value: f64) {
-        if value < 0.0 {
-            panic!("The playback speed must be a non-negative value.");
+    fn advance_varispeed(&mut self, step: f64) {
+        self.varispeed_phase += step;
+        while self.varispeed_phase >= 1.0 {
+            self.varispeed_phase -= 1.0;
+            self.varispeed_previous = self.varispeed_next;
+            self.varispeed_next = self.read_source_frame();
         }
+    }
 
-        self.speed = value;
+    fn reset_varispeed(&mut self) {
+        self.varispeed_active = false;
+        self.varispeed_drain = 0;
+        self.varispeed_phase = 0.0;
+        self.varispeed_previous = (0_f32, 0_f32);
+        self.varispeed_next = (0_f32, 0_f32);
+        self.varispeed_read = self.synthesizer.block_size;
+    }
+
+    /// Renders like `render`, but returns early after the number of blocks set by
+    /// `set_max_render_blocks`, and returns how many samples were written to the start of the buffers.
+    ///
+    /// This bounds the time spent in a single call, so that a cooperative scheduler can
+    /// interleave other work while rendering a large buffer. Calling it again with the rest
+    /// of the buffers continues the rendering seamlessly.
+    /// Without the limit, the buffers are filled completely.
+    /// As in `render`, the lengths are checked in debug builds only.
+    pub fn render_partial(&mut self, left: &mut [f32], right: &mut [f32]) -> usize {
+        debug_assert_eq!(
+            left.len(),
+            right.len(),
+            "The output buffers for the left and right must be the same length."
+        );
+
+        let length = cmp::min(left.len(), right.len());
+        let length = match self.max_render_blocks {
+            Some(blocks) => cmp::min(length, blocks * self.synthesizer.block_size),
+            None => length,
+        };
+
+        self.render(&mut left[..length], &mut right[..length]);
+        length
+    }
+
+    fn read_source_frame(&mut self) -> (f32, f32) {
+        if self.varispeed_read == self.varispeed_left.len() {
+            let mut left = core::mem::take(&mut self.varispeed_left);
+            let mut right = core::mem::take(&mut self.varispeed_right);
+            self.render_source(&mut left, &mut right);
+            self.varispeed_left = left;
+            self.varispeed_right = right;
+            self.varispeed_read = 0;
+        }
+
+        let frame = (
+            self.varispeed_left[self.varispeed_read],
+            self.varispeed_right[self.varispeed_read],
+        );
+        self.varispeed_read += 1;
+        frame
+    }
+
+    // Moves the synced playback by a block, at the tempo of the master,
+    // up to the limit if the clock is late.
+    fn advance_sync(&mut self) {
+        let sync = match self.sync.as_ref() {
+            Some(value) if value.running => value,
+            _ => return,
+        };
+
+        let quarters = (self.synthesizer.block_size as f64 / sync.samples_per_quarter)
+            .min(sync.limit - sync.position)
+            .max(0.0);
+        self.move_sync(quarters);
+    }
+
+    // Moves the synced playback by the quarter notes, at the tempo of the MIDI file at the position.
+    fn move_sync(&mut self, quarters: f64) {
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => value,
+            None => return,
+        };
+
+        // A quarter note of a file timed in SMPTE frames is that at 120 BPM, as `ticks_per_quarter` defines it.
+        let seconds_per_quarter = match midi_file.smpte_timing() {
+            Some(_) => 0.5,
+            None => 60.0 / self.synthesizer.get_tempo(),
+        };
+        if let Some(sync) = self.sync.as_mut() {
+            sync.position += quarters;
+        }
+        self.current_time += quarters * seconds_per_quarter;
+    }
+
+    // Moves the synced playback to the position in quarter notes, as `seek` does.
+    fn seek_sync(&mut self, quarters: f64) {
+        let time = match self.midi_file.as_ref() {
+            Some(value) => {
+                value.tick_to_seconds((quarters * value.ticks_per_quarter() as f64).round() as u64)
+            }
+            None => return,
+        };
+
+        self.seek(time);
+        if let Some(sync) = self.sync.as_mut() {
+            sync.position = quarters;
+            sync.clock_position = quarters;
+            sync.limit = quarters;
+        }
+    }
+
+    // Renders the output of the synthesizer without the varispeed.
+    fn render_source(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.advance(left.len(), |synthesizer, range| {
+            synthesizer.render(&mut left[range.clone()], &mut right[range]);
+        });
+    }
+
+    // Advances the sequence by the length, processing the events at each block,
+    // while the synthesizer renders the ranges of the output between them.
+    fn advance<F>(&mut self, length: usize, mut render: F)
+    where
+        F: FnMut(&mut Synthesizer, Range<usize>),
+    {
+        let mut wrote: usize = 0;
+        while wrote < length {
+            if self.block_wrote == self.synthesizer.block_size {
+                self.block_wrote = 0;
+                // The synced playback waits for the master to start, as if paused.
+                let waiting = self.is_synced() && !self.is_external_sync_running();
+                if !self.paused && !waiting {
+                    self.process_count_in();
+                    self.process_events();
+                    if self.is_synced() {
+                        self.advance_sync();
+                    } else {
+                        self.current_time += self.speed * self.synthesizer.block_size as f64
+                            / self.synthesizer.sample_rate as f64;
+                        // Reverse playback stops at the beginning.
+                        if self.speed < 0.0 && self.current_time < 0.0 {
+                            self.current_time = 0.0;
+                        }
+                    }
+                }
+            }
+
+            let src_rem = self.synthesizer.block_size - self.block_wrote;
+            let dst_rem = length - wrote;
+            let rem = cmp::min(src_rem, dst_rem);
+
+            render(&mut self.synthesizer, wrote..wrote + rem);
+
+            self.block_wrote += rem;
+            wrote += rem;
+        }
+    }
+
+    /// Renders each MIDI channel into its own stereo stem, for exporting the stems of the file in one pass.
+    ///
+    /// There must be 16 stems, one per channel, or 17, where the last one receives the return
+    /// of the reverb and chorus. The effects are shared by all the channels as in `render`,
+    /// so they cannot be split per channel: with 16 stems each stem is dry and the effects are dropped,
+    /// and with 17 the stems sum up to the output of `render`, up to rounding.
+    /// To get stems of groups of channels, sum the stems of the channels in each group.
+    ///
+    /// All the buffers must be the same length. The varispeed must be 1, as the stems are not resampled.
+    /// These are checked in debug builds only, and a release build renders the length of the shortest buffer,
+    /// to the channels which have a stem, without the varispeed.
+    /// The first call allocates the routing of the stems, and the later calls with as many stems do not allocate.
+    pub fn render_stems(&mut self, stems: &mut [(&mut [f32], &mut [f32])]) {
+        let channel_count = Synthesizer::CHANNEL_COUNT;
+        debug_assert!(
+            stems.len() == channel_count || stems.len() == channel_count + 1,
+            "There must be 16 stems, or 17 with the effect return."
+        );
+        debug_assert!(
+            stems.iter().all(|(left, right)| {
+                left.len() == stems[0].0.len() && right.len() == stems[0].0.len()
+            }),
+            "The output buffers for the stems must be the same length."
+        );
+        debug_assert!(
+            self.varispeed == 1.0,
+            "The stems cannot be rendered with a varispeed."
+        );
+        let length = match stems
+            .iter()
+            .map(|(left, right)| cmp::min(left.len(), right.len()))
+            .min()
+        {
+            Some(value) => value,
+            None => return,
+        };
+
+        let with_effects = stems.len() > channel_count;
+        let routing = match self.stem_routing.take() {
+            Some(routing) if routing.get_output_count() == 2 * stems.len() => routing,
+            _ => {
+                let mut routing = ChannelRouting::new(2 * stems.len());
+                for channel in 0..channel_count {
+                    routing.add_channel_route(channel as i32, 2 * channel, 1_f32, 0_f32);
+                    routing.add_channel_route(channel as i32, 2 * channel + 1, 0_f32, 1_f32);
+                }
+                if with_effects {
+                    routing.add_effect_route(2 * channel_count, 1_f32, 0_f32);
+                    routing.add_effect_route(2 * channel_count + 1, 0_f32, 1_f32);
+                }
+                routing
+            }
+        };
+
+        let output_count = cmp::min(2 * stems.len(), MidiFileSequencer::MAXIMUM_STEM_OUTPUTS);
+        self.advance(length, |synthesizer, range| {
+            // The outputs of the block are kept on the stack, so that no block allocates.
+            let mut outputs: [&mut [f32]; MidiFileSequencer::MAXIMUM_STEM_OUTPUTS] =
+                core::array::from_fn(|_| &mut [][..]);
+            for (outputs, (left, right)) in outputs.chunks_mut(2).zip(stems.iter_mut()) {
+                outputs[0] = &mut left[range.clone()];
+                outputs[1] = &mut right[range.clone()];
+            }
+            synthesizer.render_multichannel(&mut outputs[..output_count], &routing);
+        });
+
+        self.stem_routing = Some(routing);
+    }
+
+    /// Returns an iterator which renders the MIDI file in stereo chunks of the given length.
+    ///
+    /// The iterator ends when the sequence reaches its end, without the release and the effect tails;
+    /// use `render_all` to include them.
+    /// If looping is enabled, the speed is not positive, or the playback is paused, the iterator never ends.
+    /// The chunk length must be positive.
+    pub fn render_iter(&mut self, chunk: usize) -> impl Iterator<Item = (Vec<f32>, Vec<f32>)> + '_ {
+        if chunk == 0 {
+            panic!("The chunk length must be a positive value.");
+        }
+
+        core::iter::from_fn(move || {
+            if self.end_of_sequence() {
+                return None;
+            }
+
+            let mut left: Vec<f32> = vec![0_f32; chunk];
+            let mut right: Vec<f32> = vec![0_f32; chunk];
+            self.render(&mut left[..], &mut right[..]);
+            Some((left, right))
+        })
+    }
+
+    /// Renders the rest of the MIDI file, including the release and the effect tails
+    /// after the last event, and returns the stereo waveform.
+    ///
+    /// The rendering stops when the sequence has ended and `is_silent` returns `true`,
+    /// or 10 seconds after the end of the sequence at most.
+    /// Returns empty buffers if nothing is being played.
+    /// Panics if looping is enabled, the speed is not positive, or the playback is paused,
+    /// as the sequence never ends.
+    pub fn render_all(&mut self) -> (Vec<f32>, Vec<f32>) {
+        const MAXIMUM_TAIL_SEC: f64 = 10.0;
+
+        let playing = self.midi_file.is_some() || self.stream.is_some();
+        if self.play_loop && playing {
+            panic!("The MIDI file cannot be rendered to the end while looping.");
+        }
+        if self.speed <= 0.0 && playing {
+            panic!("The MIDI file cannot be rendered to the end unless the speed is positive.");
+        }
+        if self.paused && playing {
+            panic!("The MIDI file cannot be rendered to the end while paused.");
+        }
+
+        let block_size = self.synthesizer.block_size;
+        let maximum_tail_length = (self.synthesizer.sample_rate as f64 * MAXIMUM_TAIL_SEC) as usize;
+
+        let mut left: Vec<f32> = Vec::new();
+        let mut right: Vec<f32> = Vec::new();
+        let mut tail_length: usize = 0;
+        while playing && !(self.end_of_sequence() && self.is_silent()) {
+            if self.end_of_sequence() {
+                if tail_length >= maximum_tail_length {
+                    break;
+                }
+                tail_length += block_size;
+            }
+
+            let offset = left.len();
+            left.resize(offset + block_size, 0_f32);
+            right.resize(offset + block_size, 0_f32);
+            self.render(&mut left[offset..], &mut right[offset..]);
+        }
+
+        (left, right)
+    }
+
+    /// Renders the rest of the MIDI file as `render_all` does, and scales the waveform
+    /// so that its peak, across both channels, equals `target_peak`.
+    ///
+    /// The whole waveform is buffered in memory before it is scaled, as the peak is known only at the end.
+    /// A silent result is returned unscaled. The target peak must be positive.
+    /// Panics under the same conditions as `render_all`.
+    pub fn render_all_normalized(&mut self, target_peak: f32) -> (Vec<f32>, Vec<f32>) {
+        if target_peak <= 0_f32 || target_peak.is_nan() {
+            panic!("The target peak must be a positive value.");
+        }
+
+        let (mut left, mut right) = self.render_all();
+
+        let peak = left
+            .iter()
+            .chain(right.iter())
+            .fold(0_f32, |peak, value| peak.max(value.abs()));
+        if peak > 0_f32 {
+            let gain = target_peak / peak;
+            for value in left.iter_mut().chain(right.iter_mut()) {
+                *value *= gain;
+            }
+        }
+
+        (left, right)
+    }
+
+    /// Renders the rest of the MIDI file as `render_all` does, including the release and the effect tails
+    /// after the last event, and writes it as a stereo WAV file in the format.
+    ///
+    /// The whole waveform is buffered in memory before it is written, as the length is known only at the end.
+    /// An error of the kind `InvalidInput` is returned if it is too long for a WAV file, which holds up to 4 GB.
+    /// Panics under the same conditions as `render_all`.
+    #[cfg(feature = "std")]
+    pub fn render_all_to_wav<W: Write>(
+        &mut self,
+        writer: &mut W,
+        format: WavFormat,
+    ) -> Result<(), io::Error> {
+        let (left, right) = self.render_all();
+
+        WaveWriter::write_header(writer, self.synthesizer.sample_rate, left.len(), format)?;
+        match format {
+            WavFormat::Pcm16 => {
+                let mut left_i16: Vec<i16> = vec![0; left.len()];
+                let mut right_i16: Vec<i16> = vec![0; right.len()];
+                self.synthesizer
+                    .dither
+                    .process(&left, &right, &mut left_i16, &mut right_i16);
+                WaveWriter::write_samples(writer, &left_i16, &right_i16)
+            }
+            WavFormat::Pcm24 => WaveWriter::write_samples_24(writer, &left, &right),
+            WavFormat::Float32 => WaveWriter::write_samples_f32(writer, &left, &right),
+        }
+    }
+
+    /// Renders the MIDI file from `start` to `end` in seconds, and writes it as a stereo WAV file
+    /// in the format, such as `WavFormat::Float32` for a master without a lossy 16-bit intermediate.
+    ///
+    /// The events before `start` are applied without playing the notes,
+    /// so that the snippet sounds correct from its first sample.
+    /// The length of the output follows the playback speed and the varispeed.
+    /// The sequencer keeps playing the MIDI file after the call.
+    /// An error of the kind `InvalidInput` is returned before anything is played
+    /// if the output is too long for a WAV file, which holds up to 4 GB.
+    #[cfg(feature = "std")]
+    pub fn render_midi_to_wav<W: Write>(
+        &mut self,
+        midi_file: &Arc<MidiFile>,
+        writer: &mut W,
+        start: f64,
+        end: f64,
+        format: WavFormat,
+    ) -> Result<(), io::Error> {
+        let start = start.max(0.0);
+        let frame_count = if end > start && self.speed > 0.0 {
+            ((end - start) / (self.speed * self.varispeed) * self.synthesizer.sample_rate as f64)
+                as usize
+        } else {
+            0
+        };
+
+        WaveWriter::write_header(writer, self.synthesizer.sample_rate, frame_count, format)?;
+
+        // The count-in is not written to the file.
+        let count_in_bars = self.count_in_bars;
+        self.count_in_bars = 0;
+        self.play_from(midi_file, false, start);
+        self.count_in_bars = count_in_bars;
+
+        let block_size = self.synthesizer.block_size;
+        let mut left: Vec<f32> = vec![0_f32; block_size];
+        let mut right: Vec<f32> = vec![0_f32; block_size];
+        let mut left_i16: Vec<i16> = vec![0; block_size];
+        let mut right_i16: Vec<i16> = vec![0; block_size];
+
+        let mut wrote: usize = 0;
+        while wrote < frame_count {
+            let rem = cmp::min(block_size, frame_count - wrote);
+
+            self.render(&mut left[..rem], &mut right[..rem]);
+            match format {
+                WavFormat::Pcm16 => {
+                    self.synthesizer.dither.process(
+                        &left[..rem],
+                        &right[..rem],
+                        &mut left_i16[..rem],
+                        &mut right_i16[..rem],
+                    );
+                    WaveWriter::write_samples(writer, &left_i16[..rem], &right_i16[..rem])?;
+                }
+                WavFormat::Pcm24 => {
+                    WaveWriter::write_samples_24(writer, &left[..rem], &right[..rem])?
+                }
+                WavFormat::Float32 => {
+                    WaveWriter::write_samples_f32(writer, &left[..rem], &right[..rem])?
+                }
+            }
+
+            wrote += rem;
+        }
+
+        Ok(())
+    }
+
+    fn process_events(&mut self) {
+        if self.speed < 0.0 && !self.is_synced() {
+            self.process_events_backward();
+            return;
+        }
+
+        if self.stream.is_some() {
+            self.process_stream_events();
+            return;
+        }
+
+        // The file is held through a reference of its own, so that it can be read while the messages are dispatched.
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => Arc::clone(value),
+            None => return,
+        };
+        let midi_file = &midi_file;
+
+        // The master of the external sync decides where the playback goes, so the loops are not played.
+        let looping = self.play_loop && self.sync.is_none();
+
+        if let Some((start, end)) = self.loop_region.filter(|_| looping) {
+            if self.current_time >= end {
+                self.current_time = start;
+                self.msg_index = midi_file.times.partition_point(|&time| time < start);
+                self.text_index = MidiFileSequencer::find_text(midi_file, self.current_time);
+                self.synthesizer
+                    .note_off_all_at_loop(self.loop_note_cutoff == LoopNoteCutoff::Immediate);
+            }
+        }
+
+        while self.msg_index < midi_file.messages.len() {
+            let time = midi_file.times[self.msg_index];
+            let msg = midi_file.messages[self.msg_index];
+
+            if time <= self.current_time {
+                self.on_event
+                    .call_texts(&midi_file.texts, &mut self.text_index, time);
+                let sysex_data = match msg {
+                    Message::SysEx { bytes } => {
+                        &midi_file.sysex_data[Message::get_sysex_index(bytes)][..]
+                    }
+                    _ => &[],
+                };
+                self.dispatch_message(time, msg, sysex_data);
+
+                let marker = if looping && self.loop_region.is_none() {
+                    LoopMarker::find(self.loop_mode, &msg)
+                } else {
+                    None
+                };
+                match marker {
+                    Some(LoopMarker::Start(count)) => {
+                        self.loop_index = self.msg_index + 1;
+                        self.loop_time = time;
+                        self.loop_remaining = count.map(|count| count - 1);
+                    }
+                    // A loop end at the time of the loop start would be reached again right after the jump.
+                    Some(LoopMarker::End) if time <= self.loop_time => (),
+                    // After the last time of a limited loop, the playback goes on,
+                    // and the whole file is looped until the next loop start.
+                    Some(LoopMarker::End) if self.loop_remaining == Some(0) => {
+                        self.loop_index = 0;
+                        self.loop_time = 0.0;
+                        self.loop_remaining = None;
+                    }
+                    Some(LoopMarker::End) => {
+                        self.loop_remaining = self.loop_remaining.map(|count| count - 1);
+                        self.current_time = self.loop_time;
+                        self.msg_index = self.loop_index;
+                        self.text_index =
+                            MidiFileSequencer::find_text(midi_file, self.current_time);
+                        self.synthesizer.note_off_all_at_loop(
+                            self.loop_note_cutoff == LoopNoteCutoff::Immediate,
+                        );
+                        continue;
+                    }
+                    None => (),
+                }
+                self.msg_index += 1;
+            } else {
+                break;
+            }
+        }
+
+        self.on_event
+            .call_texts(&midi_file.texts, &mut self.text_index, self.current_time);
+
+        // An empty file has no loop start to go back to, and is regarded as ended.
+        // The loop region goes on to its end, even past the end of the file.
+        if self.msg_index == midi_file.messages.len()
+            && looping
+            && self.loop_region.is_none()
+            && !midi_file.messages.is_empty()
+        {
+            self.current_time = self.loop_time;
+            self.msg_index = self.loop_index;
+            self.text_index = MidiFileSequencer::find_text(midi_file, self.current_time);
+            self.synthesizer
+                .note_off_all_at_loop(self.loop_note_cutoff == LoopNoteCutoff::Immediate);
+        }
+    }
+
+    // Finds the first text event at or after the time.
+    fn find_text(midi_file: &MidiFile, time: f64) -> usize {
+        midi_file.texts.partition_point(|text| text.time < time)
+    }
+
+    // Plays the events of the stream up to the current time, reading them from the source as they are needed.
+    fn process_stream_events(&mut self) {
+        // The stream is taken out while its events are dispatched, and put back afterwards.
+        let mut stream = match self.stream.take() {
+            Some(value) => value,
+            None => return,
+        };
+
+        while !stream.ended {
+            if stream.next.is_none() {
+                match stream.source.read_event() {
+                    Ok(Some(event)) => stream.next = Some(event),
+                    _ => {
+                        stream.ended = true;
+                        break;
+                    }
+                }
+            }
+
+            let (time, msg) = stream.next.unwrap();
+            if time > self.current_time {
+                break;
+            }
+
+            // The SysEx data is still that of the event, as the next one has not been read yet.
+            self.dispatch_message(time, msg, stream.source.get_sysex_data());
+            stream.next = None;
+        }
+
+        self.stream = Some(stream);
+    }
+
+    // Plays the message at the time, and reports it to the event callback.
+    // The channel messages go through the message filter, and are reported as they are played.
+    // The data of a SysEx message is given along with it.
+    fn dispatch_message(&mut self, time: f64, msg: Message, sysex_data: &[u8]) {
+        match msg {
+            Message::Normal {
+                status,
+                data1,
+                data2,
+            } => {
+                if let Some(message) = self.play_channel_message(
+                    (status & 0x0F) as i32,
+                    (status & 0xF0) as i32,
+                    data1 as i32,
+                    data2 as i32,
+                ) {
+                    self.on_event.call(time, SequencerEvent::Message(message));
+                }
+            }
+            Message::SysEx { .. } => {
+                self.synthesizer.process_sysex(sysex_data);
+                self.on_event.call(time, SequencerEvent::SysEx(sysex_data));
+            }
+            Message::TempoChange { bytes } => {
+                self.on_event.call(time, SequencerEvent::Message(msg));
+                self.synthesizer.set_tempo(Message::get_tempo(bytes));
+                if let Some(callback) = self.on_tempo_change.0.as_mut() {
+                    callback(time, Message::get_tempo(bytes));
+                }
+            }
+            _ => self.on_event.call(time, SequencerEvent::Message(msg)),
+        }
+    }
+
+    // Passes the channel message through the message filter, and plays it unless the filter drops it.
+    // Returns the message as played.
+    fn play_channel_message(
+        &mut self,
+        channel: i32,
+        command: i32,
+        data1: i32,
+        data2: i32,
+    ) -> Option<Message> {
+        let (channel, command, data1, data2) =
+            self.message_filter.apply(channel, command, data1, data2)?;
+        self.synthesizer
+            .process_midi_message(channel, command, data1, data2);
+        Some(Message::filtered(channel, command, data1, data2))
+    }
+
+    // Undoes the events after the current time, from the latest one, while playing in reverse.
+    // A note-on becomes a note-off, and a note-off restarts the note with the velocity of its note-on.
+    // The other events are not undone, as their previous state is unknown,
+    // so the controllers keep the latest values reached.
+    fn process_events_backward(&mut self) {
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => Arc::clone(value),
+            None => return,
+        };
+
+        while self.msg_index > 0 && midi_file.times[self.msg_index - 1] > self.current_time {
+            self.msg_index -= 1;
+
+            if let Message::Normal {
+                status,
+                data1,
+                data2,
+            } = midi_file.messages[self.msg_index]
+            {
+                let channel = (status & 0x0F) as i32;
+                let command = status & 0xF0;
+                if command == 0x90 && data2 > 0 {
+                    self.play_channel_message(channel, 0x80, data1 as i32, 64);
+                } else if command == 0x80 || command == 0x90 {
+                    let velocity =
+                        midi_file.messages[..self.msg_index]
+                            .iter()
+                            .rev()
+                            .find_map(|message| match *message {
+                                Message::Normal {
+                                    status: on_status,
+                                    data1: key,
+                                    data2: velocity,
+                                } if on_status == (0x90 | (status & 0x0F))
+                                    && key == data1
+                                    && velocity > 0 =>
+                                {
+                                    Some(velocity)
+                                }
+                                _ => None,
+                            });
+                    if let Some(velocity) = velocity {
+                        self.play_channel_message(channel, 0x90, data1 as i32, velocity as i32);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_synthesizer(&self) -> &Synthesizer {
+        &self.synthesizer
+    }
+
+    /// Gets the synthesizer to send the MIDI messages and change the settings
+    /// while the MIDI file is playing. The messages are mixed with those of the file.
+    pub fn get_synthesizer_mut(&mut self) -> &mut Synthesizer {
+        &mut self.synthesizer
+    }
+
+    pub fn get_midi_file(&self) -> Option<&MidiFile> {
+        match &self.midi_file {
+            None => None,
+            Some(value) => Some(value),
+        }
+    }
+
+    /// Current playback position in seconds. This is negative during the count-in.
+    pub fn get_position(&self) -> f64 {
+        self.current_time
+    }
+
+    /// Gets the current playback position in ticks of the MIDI file, using its tempo map.
+    ///
+    /// This is `0` during the count-in and while no MIDI file is played, including with `play_stream`.
+    pub fn get_position_ticks(&self) -> u64 {
+        match self.midi_file.as_ref() {
+            Some(midi_file) => midi_file.seconds_to_tick(self.current_time.max(0.0)),
+            None => 0,
+        }
+    }
+
+    /// Gets the current playback position as the bar, the beat, and the tick within the beat,
+    /// as `MidiFile::tick_to_bar_beat` gives them, for a transport display or a metronome.
+    pub fn get_position_bar_beat(&self) -> (u32, u32, u32) {
+        match self.midi_file.as_ref() {
+            Some(midi_file) => midi_file.tick_to_bar_beat(self.get_position_ticks()),
+            None => (1, 1, 0),
+        }
+    }
+
+    /// Gets the total duration in seconds of the MIDI file, or `0` while no MIDI file is played.
+    pub fn get_length(&self) -> f64 {
+        self.midi_file
+            .as_ref()
+            .map_or(0.0, |midi_file| midi_file.get_length())
+    }
+
+    /// Gets the total duration in ticks of the MIDI file, or `0` while no MIDI file is played.
+    pub fn get_length_ticks(&self) -> u64 {
+        self.midi_file
+            .as_ref()
+            .map_or(0, |midi_file| midi_file.get_length_ticks())
+    }
+
+    /// Sets the number of bars of the metronome count-in played before the sequence.
+    ///
+    /// The count-in follows the tempo and the time signature at the start of the MIDI file,
+    /// and is played with the wood blocks of the drum kit on the percussion channel.
+    /// `0` (the default) disables the count-in.
+    /// This takes effect from the next `play` or `play_from`.
+    pub fn set_count_in(&mut self, bars: u32) {
+        self.count_in_bars = bars;
+    }
+
+    pub fn get_count_in(&self) -> u32 {
+        self.count_in_bars
+    }
+
+    /// Returns `true` while the count-in is being played.
+    pub fn is_counting_in(&self) -> bool {
+        self.midi_file.is_some()
+            && self.count_in.beat_count > 0
+            && self.current_time < self.count_in.start
+    }
+
+    /// Freezes the playback position, while the rendering goes on.
+    ///
+    /// The sounding notes are released, so that their release and the effect tails decay naturally
+    /// into silence instead of being cut off. The notes are not restarted by `resume`.
+    /// `play`, `play_from`, and `play_stream` start unpaused.
+    pub fn pause(&mut self) {
+        if self.paused {
+            return;
+        }
+
+        self.paused = true;
+        self.synthesizer.note_off_all(false);
+    }
+
+    /// Resumes the playback paused by `pause` from the position where it stopped.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns `true` if the playback is paused by `pause`.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns `true` if a MIDI file or a stream is being played, not paused,
+    /// and has not reached the end.
+    pub fn is_playing(&self) -> bool {
+        let playing = self.midi_file.is_some() || self.stream.is_some();
+        playing && !self.paused && !self.end_of_sequence()
+    }
+
+    /// Returns `true` if playback has reached the end (or `play` was never called).
+    /// Always `false` when looping is enabled.
+    pub fn end_of_sequence(&self) -> bool {
+        if let Some(stream) = self.stream.as_ref() {
+            return stream.ended;
+        }
+
+        match &self.midi_file {
+            None => true,
+            Some(value) => {
+                self.msg_index == value.messages.len()
+                    && !(self.play_loop && self.loop_region.is_some())
+            }
+        }
+    }
+
+    /// Returns `true` if no note is sounding and the effect tails have decayed to silence.
+    ///
+    /// Unlike `end_of_sequence`, this considers the release of the notes and the reverb after the last event.
+    pub fn is_silent(&self) -> bool {
+        self.synthesizer.is_idle()
+    }
+
+    /// Sets the callback called with the time in seconds and the new tempo in BPM
+    /// whenever the playback passes a tempo change.
+    ///
+    /// The callback is called from `render`, when the block containing the tempo change is processed,
+    /// so the time may be slightly ahead of the samples already rendered.
+    /// The tempo changes skipped by `play_from` or passed in reverse playback are not reported.
+    pub fn set_on_tempo_change(&mut self, callback: Box<dyn FnMut(f64, f64) + Send>) {
+        self.on_tempo_change = TempoChangeCallback(Some(callback));
+    }
+
+    /// Removes the callback set by `set_on_tempo_change`.
+    pub fn clear_on_tempo_change(&mut self) {
+        self.on_tempo_change = TempoChangeCallback(None);
+    }
+
+    /// Sets the callback called with the time in seconds and each event as the playback passes it,
+    /// for visualizers such as a piano roll or the lyrics of a karaoke player.
+    ///
+    /// The events are reported in the order they are processed, at the same timing as they are
+    /// played: the callback is called from `render`, when the block containing the event is processed,
+    /// so the time may be slightly ahead of the samples already rendered.
+    /// The channel messages are reported as they come out of the filter set by `set_message_filter`,
+    /// and those which it drops are not reported.
+    /// The text events are reported only while a `MidiFile` is played, not with `play_stream`.
+    /// The events skipped by `play_from`, `seek`, and the skips to the notes, and those passed
+    /// in reverse playback, are not reported. The events in a loop are reported on each repetition.
+    pub fn set_on_event(&mut self, callback: Box<EventCallbackFn>) {
+        self.on_event = EventCallback(Some(callback));
+    }
+
+    /// Removes the callback set by `set_on_event`.
+    pub fn clear_on_event(&mut self) {
+        self.on_event = EventCallback(None);
+    }
+
+    /// Sets the filter through which the channel messages of the file pass before the synthesizer,
+    /// for MIDI effects such as a velocity compressor or a channel remap.
+    ///
+    /// The filter is called with the channel, the command, and the two data bytes of each message,
+    /// as `Synthesizer::process_midi_message` takes them.
+    /// Returning `Some` passes the returned message, which may be modified, and `None` drops it.
+    /// The messages applied by `play_from` and `apply_state_up_to`, and those of the reverse playback,
+    /// pass through the filter as well; the SysEx messages and the count-in clicks do not.
+    pub fn set_message_filter(&mut self, filter: Box<MessageFilterFn>) {
+        self.message_filter = MessageFilter(Some(filter));
+    }
+
+    /// Removes the filter set by `set_message_filter`.
+    pub fn clear_message_filter(&mut self) {
+        self.message_filter = MessageFilter(None);
+    }
+
+    /// Gets the number of blocks which `render_partial` renders at most, if limited.
+    pub fn get_max_render_blocks(&self) -> Option<usize> {
+        self.max_render_blocks
+    }
+
+    /// Sets the number of blocks which `render_partial` renders at most per call,
+    /// or `None` to fill the buffers completely (the default). The number must be positive.
+    ///
+    /// `render` always fills the buffers completely.
+    pub fn set_max_render_blocks(&mut self, blocks: Option<usize>) {
+        if blocks == Some(0) {
+            panic!("The number of blocks must be a positive value.");
+        }
+
+        self.max_render_blocks = blocks;
+    }
+
+    pub fn get_loop_note_cutoff(&self) -> LoopNoteCutoff {
+        self.loop_note_cutoff
+    }
+
+    /// Sets how the sounding notes are stopped at the loop point. The default is `LoopNoteCutoff::Release`.
+    pub fn set_loop_note_cutoff(&mut self, value: LoopNoteCutoff) {
+        self.loop_note_cutoff = value;
+    }
+
+    pub fn get_loop_mode(&self) -> SequencerLoopMode {
+        self.loop_mode
+    }
+
+    /// Sets which events of the MIDI file mark the loop. The default is `SequencerLoopMode::Markers`.
+    ///
+    /// The mode takes effect from the next loop event, and the setting is kept across `play`.
+    /// The loop region set by `set_loop` takes precedence over the mode.
+    pub fn set_loop_mode(&mut self, value: SequencerLoopMode) {
+        self.loop_mode = value;
+        self.check_loop_events();
+    }
+
+    /// Gets the warnings about the loop events of the MIDI file being played under the loop mode,
+    /// such as a loop end which is ignored because it is not after its loop start.
+    pub fn get_warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // Finds the loop ends at or before their loop start, which process_events ignores
+    // since the jump back would reach them again without the time going forward.
+    fn check_loop_events(&mut self) {
+        self.warnings.clear();
+
+        let midi_file = match self.midi_file.as_ref() {
+            Some(value) => value,
+            None => return,
+        };
+
+        let mut loop_time = 0.0;
+        for (message, &time) in midi_file.messages.iter().zip(midi_file.times.iter()) {
+            match LoopMarker::find(self.loop_mode, message) {
+                Some(LoopMarker::Start(_)) => loop_time = time,
+                Some(LoopMarker::End) if time <= loop_time => self.warnings.push(format!(
+                    "the loop end at {time:.3} s, which is not after the loop start, was ignored"
+                )),
+                _ => (),
+            }
+        }
+    }
+
+    /// Gets the loop region set by `set_loop`, in seconds.
+    pub fn get_loop(&self) -> Option<(f64, f64)> {
+        self.loop_region
+    }
+
+    /// Loops the region from `start` to `end` in seconds, instead of the loop events of the MIDI file,
+    /// and enables looping.
+    ///
+    /// When the playback reaches the end, it goes back to the start, releasing the notes
+    /// as `set_loop_note_cutoff` specifies. If the position is already past the end,
+    /// it goes back with the next block. The region may extend past the end of the file.
+    /// The region is cleared by `play`, and nothing is done if no MIDI file is being played.
+    /// The end must be after the start.
+    pub fn set_loop(&mut self, start: f64, end: f64) {
+        let start = start.max(0.0);
+        if end.partial_cmp(&start) != Some(cmp::Ordering::Greater) {
+            panic!("The loop end must be after the loop start.");
+        }
+
+        if self.midi_file.is_none() {
+            return;
+        }
+
+        self.loop_region = Some((start, end));
+        self.play_loop = true;
+    }
+
+    /// Loops the region from `start` to `end` in ticks of the MIDI file, as `set_loop` does.
+    pub fn set_loop_ticks(&mut self, start: u64, end: u64) {
+        let (start, end) = match self.midi_file.as_ref() {
+            Some(value) => (value.tick_to_seconds(start), value.tick_to_seconds(end)),
+            None => return,
+        };
+
+        self.set_loop(start, end);
+    }
+
+    /// Removes the loop region set by `set_loop`, so that the loop events of the MIDI file are used again.
+    /// Looping stays enabled.
+    pub fn clear_loop(&mut self) {
+        self.loop_region = None;
+    }
+
+    /// Playback speed multiplier (default 1.0).
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Sets the playback speed.
+    ///
+    /// `0.0` pauses the sequence while the synthesizer keeps rendering, so the held notes ring on.
+    /// A negative value plays the sequence in reverse, like a jog wheel,
+    /// and the speed can cross zero smoothly while playing.
+    /// In reverse, the passed note-ons stop their notes and the passed note-offs restart them,
+    /// but the other events, such as the controllers, are not undone, and the loop points are ignored.
+    /// Reverse playback stops at the beginning of the sequence.
+    /// The speed must not be NaN, which is checked in debug builds,
+    /// and ignored in a release build so that this can be called from the audio callback.
+    pub fn set_speed(&mut self, value: f64) {
+        debug_assert!(!value.is_nan(), "The playback speed must not be NaN.");
+        if value.is_nan() {
+            return;
+        }
+
+        self.speed = value;
+    }
+
+    /// Gets the varispeed ratio (default 1.0).
+    pub fn get_varispeed(&self) -> f64 {
+        self.varispeed
+    }
+
+    /// Sets the varispeed ratio, which resamples the output like a tape machine
+    /// so that the pitch and the tempo change together.
+    ///
+    /// This is independent of `set_speed`, which changes only the tempo.
+    /// The two multiply: the MIDI file advances at `speed * varispeed` times the normal rate,
+    /// while the pitch is shifted by the varispeed alone.
+    /// The ratio can be changed while playing without a click. When it returns to 1,
+    /// the audio already resampled ahead is played out before the output is taken
+    /// from the synthesizer directly again.
+    /// The ratio is clamped within 1/16 and 16. It must not be NaN, which is checked in debug builds,
+    /// and ignored in a release build so that this can be called from the audio callback.
+    pub fn set_varispeed(&mut self, ratio: f64) {
+        debug_assert!(!ratio.is_nan(), "The varispeed ratio must not be NaN.");
+        if ratio.is_nan() {
+            return;
+        }
+
+        self.varispeed = ratio.clamp(
+            1.0 / MidiFileSequencer::MAXIMUM_VARISPEED,
+            MidiFileSequencer::MAXIMUM_VARISPEED,
+        );
+    }
+
+    /// Enables or disables the external sync, where the playback position of the MIDI file
+    /// is driven by a master such as a DAW or a hardware sequencer, instead of running freely.
+    ///
+    /// The master is followed either through its MIDI clock and transport messages,
+    /// given to `process_transport_message`, or through the transport of a plugin host,
+    /// given to `sync_to_host` before each buffer. A quarter note of the master is a quarter note
+    /// of the file, whatever the tempo of the file, so the tempo changes of the file are followed
+    /// as the master plays at its own tempo. While synced, the speed, the loops, and the count-in
+    /// are ignored, and the pauses by `pause` still freeze the position.
+    /// Enabling the sync stops the playback, releasing the sounding notes, until the master starts,
+    /// and disabling it lets the playback run freely from where it is.
+    /// A stream played by `play_stream` is not synced, as it cannot jump.
+    pub fn set_external_sync(&mut self, enabled: bool) {
+        if !enabled {
+            self.sync = None;
+            return;
+        }
+        if self.sync.is_some() {
+            return;
+        }
+
+        let position = match self.midi_file.as_ref() {
+            Some(midi_file) => {
+                midi_file.seconds_to_tick(self.current_time.max(0.0)) as f64
+                    / midi_file.ticks_per_quarter() as f64
+            }
+            None => 0.0,
+        };
+        let samples_per_quarter =
+            60.0 * self.synthesizer.sample_rate as f64 / self.synthesizer.get_tempo();
+        self.sync = Some(ExternalSync::stopped(position, samples_per_quarter));
+        self.count_in = CountIn::none();
+        self.synthesizer.note_off_all(false);
+    }
+
+    /// Returns `true` if the external sync is enabled by `set_external_sync`.
+    pub fn is_external_sync(&self) -> bool {
+        self.sync.is_some()
+    }
+
+    /// Returns `true` if the master of the external sync is playing.
+    pub fn is_external_sync_running(&self) -> bool {
+        self.sync.is_some_and(|sync| sync.running)
+    }
+
+    /// Follows a MIDI clock (`0xF8`), Start (`0xFA`), Continue (`0xFB`), Stop (`0xFC`),
+    /// or Song Position Pointer (`0xF2`) message of the master, with the data bytes of the pointer.
+    ///
+    /// The messages should be given as they arrive, between the calls of `render`:
+    /// the tempo is measured from the number of the samples rendered between the clocks,
+    /// and is smoothed over several clocks, so that the jitter of the buffers does not wobble it.
+    /// The playback starts with the first clock after Start or Continue, and does not go
+    /// past the next clock, so it waits for a late clock instead of running ahead.
+    /// Start goes back to the beginning, Stop releases the sounding notes, and the pointer,
+    /// which is ignored while the master is playing, moves to the position in sixteenth notes.
+    /// The other messages, and all the messages while the sync is disabled or no MIDI file is played,
+    /// are ignored.
+    pub fn process_transport_message(&mut self, status: i32, data1: i32, data2: i32) {
+        let mut sync = match self.sync {
+            Some(value) if self.midi_file.is_some() => value,
+            _ => return,
+        };
+
+        match status {
+            0xF8 => {
+                let now = self.synthesizer.get_sample_position();
+                if sync.waiting {
+                    sync.waiting = false;
+                    sync.running = true;
+                } else if sync.running {
+                    let interval =
+                        (now - sync.last_clock) as f64 * ExternalSync::CLOCKS_PER_QUARTER;
+                    sync.samples_per_quarter = (sync.samples_per_quarter
+                        + ExternalSync::CLOCK_SMOOTHING * (interval - sync.samples_per_quarter))
+                        .max(1.0);
+                    sync.clock_position += 1.0 / ExternalSync::CLOCKS_PER_QUARTER;
+                } else {
+                    return;
+                }
+                sync.last_clock = now;
+                sync.limit = sync.clock_position + 1.0 / ExternalSync::CLOCKS_PER_QUARTER;
+                self.sync = Some(sync);
+
+                // The playback catches up with a clock earlier than expected.
+                if sync.position < sync.clock_position {
+                    self.move_sync(sync.clock_position - sync.position);
+                }
+            }
+            0xFA => {
+                self.seek_sync(0.0);
+                if let Some(sync) = self.sync.as_mut() {
+                    sync.running = false;
+                    sync.waiting = true;
+                }
+            }
+            0xFB => {
+                if !sync.running {
+                    sync.waiting = true;
+                    self.sync = Some(sync);
+                }
+            }
+            0xFC => {
+                if sync.running || sync.waiting {
+                    sync.running = false;
+                    sync.waiting = false;
+                    self.sync = Some(sync);
+                    self.synthesizer.note_off_all(false);
+                }
+            }
+            0xF2 => {
+                if !sync.running {
+                    let sixteenths = ((data2 & 0x7F) << 7) | (data1 & 0x7F);
+                    self.seek_sync(sixteenths as f64 / 4.0);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Follows the transport of a plugin host, with the position of the host in quarter notes,
+    /// its tempo in BPM, and whether it is playing, which should be given before each call of `render`.
+    ///
+    /// The playback moves at the tempo of the host until the next call, and jumps to the position
+    /// of the host if it is more than a MIDI clock away, such as when the host is located or loops.
+    /// Stopping releases the sounding notes, and the position follows the host while it is stopped.
+    /// Nothing is done while the sync is disabled or no MIDI file is played.
+    /// The tempo must be positive and the position must not be NaN, which is checked in debug builds,
+    /// and the call is ignored in a release build so that this can be called from the audio callback.
+    pub fn sync_to_host(&mut self, quarter_notes: f64, tempo: f64, playing: bool) {
+        let valid = tempo > 0.0 && !quarter_notes.is_nan();
+        debug_assert!(
+            valid,
+            "The tempo must be a positive value, and the position must not be NaN."
+        );
+        if !valid {
+            return;
+        }
+
+        let mut sync = match self.sync {
+            Some(value) if self.midi_file.is_some() => value,
+            _ => return,
+        };
+
+        let quarter_notes = quarter_notes.max(0.0);
+        if !playing && sync.running {
+            self.synthesizer.note_off_all(false);
+        }
+        sync.running = playing;
+        sync.waiting = false;
+        sync.samples_per_quarter = 60.0 * self.synthesizer.sample_rate as f64 / tempo;
+        self.sync = Some(sync);
+
+        let difference = quarter_notes - sync.position;
+        if difference.abs() > 1.0 / ExternalSync::CLOCKS_PER_QUARTER {
+            self.seek_sync(quarter_notes);
+        } else {
+            self.move_sync(difference);
+        }
+        if let Some(sync) = self.sync.as_mut() {
+            sync.clock_position = quarter_notes;
+            sync.limit = f64::INFINITY;
+        }
+    }
+
+    // The external sync drives the playback only while a MIDI file is played.
+    fn is_synced(&self) -> bool {
+        self.sync.is_some() && self.midi_file.is_some()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use crate::midifile::tests::midi_bytes;
+    use crate::soundfont::tests::build_soundfont;
+    use crate::soundfont::SoundFont;
+    use crate::synthesizer::tests::{test_sound_font, test_synthesizer};
+    use crate::synthesizer_settings::SynthesizerSettings;
+
+    fn test_sequencer() -> MidiFileSequencer {
+        MidiFileSequencer::new(test_synthesizer())
+    }
+
+    // A MIDI file of the format 0 with the track.
+    fn test_midi_file(resolution: u16, track: &[u8]) -> Arc<MidiFile> {
+        let data = midi_bytes(resolution, &[track]);
+        Arc::new(MidiFile::new(&mut &data[..]).unwrap())
+    }
+
+    #[test]
+    fn test_on_tempo_change() {
+        // The tempo drops from 120 to 60 BPM after a beat, at 96 ticks per beat.
+        let track: Vec<u8> = vec![
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, //
+            0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(96, &track);
+
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut sequencer = test_sequencer();
+        let sink = Arc::clone(&changes);
+        sequencer.set_on_tempo_change(Box::new(move |time, tempo| {
+            sink.lock().unwrap().push((time, tempo));
+        }));
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 44096];
+        let mut right = vec![0_f32; 44096];
+        sequencer.render(&mut left, &mut right);
+        {
+            let changes = changes.lock().unwrap();
+            assert_eq!(changes.len(), 2);
+            assert!(changes[0].0.abs() < 1e-9 && (changes[0].1 - 120.0).abs() < 1e-9);
+            assert!((changes[1].0 - 0.5).abs() < 1e-9 && (changes[1].1 - 60.0).abs() < 1e-9);
+        }
+
+        // Nothing is reported once the callback is cleared.
+        sequencer.clear_on_tempo_change();
+        sequencer.play(&midi_file, false);
+        sequencer.render(&mut left, &mut right);
+        assert_eq!(changes.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_render_all_normalized() {
+        // A note of 0.1 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x40, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+        let (left, right) = sequencer.render_all();
+        sequencer.play(&midi_file, false);
+        let (normalized_left, normalized_right) = sequencer.render_all_normalized(0.5);
+
+        // The peak across both channels is the target, and the waveform keeps its shape.
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+        assert_eq!(normalized_left.len(), left.len());
+        assert!((peak(&normalized_left).max(peak(&normalized_right)) - 0.5).abs() < 1e-6);
+        let gain = 0.5 / peak(&left).max(peak(&right));
+        assert!(left
+            .iter()
+            .zip(normalized_left.iter())
+            .all(|(x, y)| (x * gain - y).abs() < 1e-6));
+
+        // A silent file is not scaled.
+        let silent = test_midi_file(480, &[0x60, 0xFF, 0x2F, 0x00]);
+        sequencer.play(&silent, false);
+        let (left, right) = sequencer.render_all_normalized(0.5);
+        assert!(left.iter().chain(right.iter()).all(|&x| x == 0_f32));
+    }
+
+    #[test]
+    fn test_skip_to_note() {
+        // Three notes of 0.5 seconds each, at 120 BPM and 96 ticks per beat.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x40, //
+            0x00, 0x90, 0x3E, 0x64, //
+            0x60, 0x80, 0x3E, 0x40, //
+            0x00, 0x90, 0x40, 0x64, //
+            0x60, 0x80, 0x40, 0x40, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(96, &track);
+
+        let mut sequencer = test_sequencer();
+        assert!(!sequencer.skip_to_next_note());
+        sequencer.play(&midi_file, false);
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+
+        // The next note starts with the next block, and the sounding note is released.
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.skip_to_next_note());
+        assert!((sequencer.get_position() - 0.5).abs() < 1e-9);
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 0x3E));
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 0x3C));
+
+        assert!(sequencer.skip_to_next_note());
+        assert!((sequencer.get_position() - 1.0).abs() < 1e-9);
+        // There is no note after the last one.
+        sequencer.render(&mut left, &mut right);
+        let position = sequencer.get_position();
+        assert!(!sequencer.skip_to_next_note());
+        assert_eq!(sequencer.get_position(), position);
+
+        // The note just started is passed over, and the one before it is played again.
+        assert!(sequencer.skip_to_previous_note());
+        assert!((sequencer.get_position() - 0.5).abs() < 1e-9);
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 0x3E));
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 0x40));
+
+        assert!(sequencer.skip_to_previous_note());
+        assert_eq!(sequencer.get_position(), 0.0);
+        assert!(!sequencer.skip_to_previous_note());
+    }
+
+    #[test]
+    fn test_program_change_before_note_on() {
+        // The preset 5 of the second SoundFont pans the notes to the left.
+        let mut data = build_soundfont(&[], &[(54, 1), (17, -500)]);
+        let offset = data.windows(6).position(|name| name == b"Preset").unwrap();
+        data[offset + 20] = 5;
+        let panned = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut sequencer = test_sequencer();
+        sequencer.get_synthesizer_mut().add_sound_font(&panned);
+
+        // The program change is in the later track, at the tick of the note-on.
+        let note_track = [
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let program_track = [
+            0x00, 0xC0, 0x05, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&note_track, &program_track]);
+        let midi_file = Arc::new(MidiFile::new(&mut &data[..]).unwrap());
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 4096];
+        let mut right = vec![0_f32; 4096];
+        sequencer.render(&mut left, &mut right);
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+        assert!(peak(&left) > 0_f32);
+        assert!(peak(&right) < 0.01 * peak(&left));
+    }
+
+    #[test]
+    fn test_loop_tails_do_not_pile_up() {
+        // A looped sample with a release of about 100 seconds.
+        let data = build_soundfont(&[], &[(54, 1), (38, 8000)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        // A note held over a loop of 0.1 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer = MidiFileSequencer::new(synthesizer);
+        sequencer.play(&midi_file, true);
+
+        let mut left = vec![0_f32; 441];
+        let mut right = vec![0_f32; 441];
+        let mut maximum_voice_count = 0;
+        for _ in 0..1000 {
+            sequencer.render(&mut left, &mut right);
+            let voice_count = sequencer.get_synthesizer().get_active_voice_count();
+            maximum_voice_count = maximum_voice_count.max(voice_count);
+        }
+
+        assert!(maximum_voice_count > 1);
+        assert!(maximum_voice_count <= 3);
+    }
+
+    #[test]
+    fn test_render_partial() {
+        let data = build_soundfont(&[], &[]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        let block_size = synthesizer.get_block_size();
+
+        let mut sequencer = MidiFileSequencer::new(synthesizer);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        assert_eq!(sequencer.render_partial(&mut left, &mut right), 1000);
+
+        sequencer.set_max_render_blocks(Some(2));
+        assert_eq!(
+            sequencer.render_partial(&mut left, &mut right),
+            2 * block_size
+        );
+        assert_eq!(
+            sequencer.render_partial(&mut left[..10], &mut right[..10]),
+            10
+        );
+    }
+
+    #[test]
+    fn test_render_stems() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+
+        // Notes on the channels 1 and 3.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x00, 0x92, 0x43, 0x64, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer =
+            MidiFileSequencer::new(Synthesizer::new(&sound_font, &settings).unwrap());
+        sequencer.play(&midi_file, false);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        sequencer.render(&mut left, &mut right);
+
+        let mut sequencer =
+            MidiFileSequencer::new(Synthesizer::new(&sound_font, &settings).unwrap());
+        sequencer.play(&midi_file, false);
+        let mut buffers = vec![vec![0_f32; 1000]; 34];
+        let mut stems: Vec<(&mut [f32], &mut [f32])> = buffers
+            .chunks_mut(2)
+            .map(|pair| {
+                let (left, right) = pair.split_at_mut(1);
+                (&mut left[0][..], &mut right[0][..])
+            })
+            .collect();
+        sequencer.render_stems(&mut stems);
+
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+        assert!(peak(&buffers[0]) > 0_f32);
+        assert!(peak(&buffers[4]) > 0_f32);
+        assert_eq!(peak(&buffers[2]), 0_f32);
+        for t in 0..1000 {
+            let sum: f32 = (0..17).map(|stem| buffers[2 * stem][t]).sum();
+            assert!((sum - left[t]).abs() < 1.0e-4);
+        }
+
+        // The later calls do not allocate.
+        let mut stems: Vec<(&mut [f32], &mut [f32])> = buffers
+            .chunks_mut(2)
+            .map(|pair| {
+                let (left, right) = pair.split_at_mut(1);
+                (&mut left[0][..], &mut right[0][..])
+            })
+            .collect();
+        let count = crate::synthesizer::tests::count_allocations(|| {
+            sequencer.render_stems(&mut stems);
+        });
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_note_on_with_zero_velocity_stops_note() {
+        // The note is stopped by a note-on with the velocity 0 in running status.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x3C, 0x00, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 2205];
+        let mut right = vec![0_f32; 2205];
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 60));
+
+        for _ in 0..4 {
+            sequencer.render(&mut left, &mut right);
+        }
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 60));
+        assert_eq!(sequencer.get_synthesizer().get_active_voice_count(), 0);
+    }
+
+    #[test]
+    fn test_message_filter() {
+        // Notes on the channels 1 and 2.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x00, 0x91, 0x43, 0x64, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(480, &[&track]);
+        let midi_file = Arc::new(MidiFile::new(&mut &data[..]).unwrap());
+
+        // The channel 1 is moved to the channel 4, and the channel 2 is dropped.
+        let mut sequencer = test_sequencer();
+        sequencer.set_message_filter(Box::new(|channel, command, data1, data2| match channel {
+            0 => Some((3, command, data1, data2)),
+            1 => None,
+            _ => Some((channel, command, data1, data2)),
+        }));
+        let messages = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&messages);
+        sequencer.set_on_event(Box::new(move |_, event| {
+            if let SequencerEvent::Message(Message::Normal {
+                status,
+                data1,
+                data2,
+            }) = event
+            {
+                sink.lock().unwrap().push((status, data1, data2));
+            }
+        }));
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        sequencer.render(&mut left, &mut right);
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 60));
+        assert!(sequencer.get_synthesizer().is_note_on(3, 60));
+        assert!(!sequencer.get_synthesizer().is_note_on(1, 67));
+
+        // The callback sees the messages as the synthesizer plays them, from a file or a stream.
+        let filtered = vec![(0x93, 0x3C, 0x64)];
+        assert_eq!(*messages.lock().unwrap(), filtered);
+        messages.lock().unwrap().clear();
+        sequencer.play_stream(MidiFileReader::new(std::io::Cursor::new(data.clone())).unwrap());
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_synthesizer().is_note_on(3, 60));
+        assert_eq!(*messages.lock().unwrap(), filtered);
+
+        sequencer.clear_message_filter();
+        sequencer.play(&midi_file, false);
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 60));
+        assert!(sequencer.get_synthesizer().is_note_on(1, 67));
+    }
+
+    #[test]
+    fn test_seek() {
+        // The pitch bend range is set to 12 at the start and to 5 at 1 second,
+        // and a note is played from 0.5 to 2 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0xB0, 0x65, 0x00, //
+            0x00, 0xB0, 0x64, 0x00, //
+            0x00, 0xB0, 0x06, 0x0C, //
+            0x83, 0x60, 0x90, 0x3C, 0x64, //
+            0x83, 0x60, 0xB0, 0x06, 0x05, //
+            0x87, 0x40, 0x80, 0x3C, 0x40, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+        assert!((midi_file.tick_to_seconds(960) - 1.0).abs() < 1e-9);
+        assert!((midi_file.tick_to_seconds(2400) - 2.5).abs() < 1e-9);
+
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+
+        // The note-on skipped over is not played.
+        sequencer.seek(1.5);
+        sequencer.render(&mut left, &mut right);
+        assert!((sequencer.get_position() - 1.5).abs() < 0.1);
+        assert_eq!(sequencer.get_synthesizer().get_pitch_bend_range(0), 5.0);
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 60));
+
+        // Seeking backward undoes the later events, and the note is played when reached.
+        sequencer.seek(0.25);
+        assert_eq!(sequencer.get_synthesizer().get_pitch_bend_range(0), 12.0);
+        for _ in 0..14 {
+            sequencer.render(&mut left, &mut right);
+        }
+        assert!(sequencer.get_synthesizer().is_note_on(0, 60));
+
+        sequencer.seek_ticks(1200);
+        assert!((sequencer.get_position() - 1.25).abs() < 1e-9);
+        assert_eq!(sequencer.get_synthesizer().get_pitch_bend_range(0), 5.0);
+    }
+
+    #[test]
+    fn test_pause() {
+        // A note at the start and another at 0.5 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x83, 0x60, 0x90, 0x40, 0x64, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+        assert!(sequencer.is_playing());
+
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 60));
+
+        // The note is released, and the position stays while the silence is rendered.
+        sequencer.pause();
+        assert!(sequencer.is_paused());
+        assert!(!sequencer.is_playing());
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 60));
+        let position = sequencer.get_position();
+        for _ in 0..100 {
+            sequencer.render(&mut left, &mut right);
+        }
+        assert_eq!(sequencer.get_position(), position);
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 64));
+        assert!(left.iter().all(|&x| x == 0_f32));
+
+        sequencer.resume();
+        assert!(sequencer.is_playing());
+        for _ in 0..25 {
+            sequencer.render(&mut left, &mut right);
+        }
+        assert!(sequencer.get_position() > position);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 64));
+    }
+
+    #[test]
+    fn test_reverse_playback() {
+        // The key 60 from 0 to 0.5 seconds, and the key 64 from 1 to 1.5 seconds.
+        let midi_file = test_midi_file(
+            96,
+            &[
+                0x00, 0x90, 0x3C, 0x64, //
+                0x60, 0x80, 0x3C, 0x00, //
+                0x60, 0x90, 0x40, 0x50, //
+                0x60, 0x80, 0x40, 0x00, //
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        );
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 11025];
+        let mut right = vec![0_f32; 11025];
+        let mut render = |sequencer: &mut MidiFileSequencer, count: usize| {
+            for _ in 0..count {
+                sequencer.render(&mut left, &mut right);
+            }
+        };
+        let notes = |sequencer: &MidiFileSequencer| {
+            let synthesizer = sequencer.get_synthesizer();
+            (synthesizer.is_note_on(0, 60), synthesizer.is_note_on(0, 64))
+        };
+
+        render(&mut sequencer, 5);
+        assert!((sequencer.get_position() - 1.25).abs() < 0.01);
+        assert_eq!(notes(&sequencer), (false, true));
+
+        // Going back past the note-on of the key 64 stops it.
+        sequencer.set_speed(-1.0);
+        render(&mut sequencer, 2);
+        assert!((sequencer.get_position() - 0.75).abs() < 0.01);
+        assert_eq!(notes(&sequencer), (false, false));
+
+        // Going back past the note-off of the key 60 restarts it with the velocity of its note-on.
+        render(&mut sequencer, 2);
+        assert!((sequencer.get_position() - 0.25).abs() < 0.01);
+        assert_eq!(notes(&sequencer), (true, false));
+        assert_eq!(sequencer.get_synthesizer().get_active_voice_count(), 1);
+        #[cfg(feature = "introspection")]
+        {
+            let voice = sequencer.get_synthesizer().voice_infos().next().unwrap();
+            assert_eq!((voice.key, voice.velocity), (60, 100));
+        }
+
+        // The reverse playback stops at the beginning, and crossing zero plays forward again.
+        render(&mut sequencer, 2);
+        assert_eq!(sequencer.get_position(), 0.0);
+        sequencer.set_speed(1.0);
+        render(&mut sequencer, 5);
+        assert_eq!(notes(&sequencer), (false, true));
+    }
+
+    #[test]
+    fn test_varispeed() {
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x90, 0x40, 0x64, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(96, &track);
+
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+        let mut source_left = vec![0_f32; 8000];
+        let mut source_right = vec![0_f32; 8000];
+        sequencer.render(&mut source_left, &mut source_right);
+
+        // The ratio is clamped.
+        sequencer.set_varispeed(-1.0);
+        assert_eq!(sequencer.get_varispeed(), 1.0 / 16.0);
+        sequencer.set_varispeed(100.0);
+        assert_eq!(sequencer.get_varispeed(), 16.0);
+
+        // At half the speed, 1000 samples play 500 frames of the source, after the silent frame before it.
+        sequencer.set_varispeed(0.5);
+        sequencer.play(&midi_file, false);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        sequencer.render(&mut left, &mut right);
+        assert_eq!(left[0], 0.5 * source_left[0]);
+        assert_eq!(left[1], source_left[0]);
+        assert!((left[2] - 0.5 * (source_left[0] + source_left[1])).abs() < 1e-6);
+
+        // Back at the normal speed, the frames read ahead are played out, so none is skipped,
+        // and the output follows the source 501 samples late.
+        sequencer.set_varispeed(1.0);
+        let mut left = vec![0_f32; 3000];
+        let mut right = vec![0_f32; 3000];
+        for (left, right) in left.chunks_mut(100).zip(right.chunks_mut(100)) {
+            sequencer.render(left, right);
+        }
+        assert!(!sequencer.varispeed_active);
+        assert_eq!(left[1000..], source_left[(2000 - 501)..(4000 - 501)]);
+        assert_eq!(right[1000..], source_right[(2000 - 501)..(4000 - 501)]);
+        let jump = left
+            .windows(2)
+            .fold(0_f32, |jump, pair| jump.max((pair[1] - pair[0]).abs()));
+        let source_jump = source_left
+            .windows(2)
+            .fold(0_f32, |jump, pair| jump.max((pair[1] - pair[0]).abs()));
+        assert!(jump <= 1.01 * source_jump);
+    }
+
+    #[test]
+    fn test_render_all_to_wav() {
+        // A short release, so that the tail ends well before the limit.
+        let data = build_soundfont(&[], &[(54, 1), (38, -2400)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        // A note of 0.1 seconds.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x40, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer = MidiFileSequencer::new(synthesizer);
+        sequencer.play(&midi_file, false);
+        let mut wav: Vec<u8> = Vec::new();
+        sequencer
+            .render_all_to_wav(&mut wav, WavFormat::Pcm16)
+            .unwrap();
+
+        assert_eq!(&wav[..4], b"RIFF");
+        let size = u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]) as usize;
+        assert_eq!(wav.len(), 44 + size);
+
+        // The release and the reverb tails are included after the last event, until they decay.
+        let frame_count = size / 4;
+        assert!(frame_count > 4410);
+        assert!(frame_count < 10 * 44100);
+        assert!(sequencer.is_silent());
+    }
+
+    #[test]
+    fn test_on_event() {
+        // A lyric with a note, and a marker between the events, at 120 BPM and 96 ticks per beat.
+        let track: Vec<u8> = vec![
+            0x00, 0xFF, 0x05, 0x02, 0x4C, 0x61, // La
+            0x00, 0x90, 0x3C, 0x64, //
+            0x30, 0xFF, 0x06, 0x01, 0x41, // A
+            0x30, 0xF0, 0x03, 0x7E, 0x7F, 0xF7, // SysEx
+            0x60, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = midi_bytes(96, &[&track]);
+        let midi_file = Arc::new(MidiFile::new(&mut &data[..]).unwrap());
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut sequencer = test_sequencer();
+        let sink = Arc::clone(&events);
+        sequencer.set_on_event(Box::new(move |time, event| {
+            let name = match event {
+                SequencerEvent::Message(Message::Normal { status, .. }) => format!("{:X}", status),
+                SequencerEvent::Message(_) => "Meta".to_string(),
+                SequencerEvent::SysEx(data) => format!("SysEx {}", data.len()),
+                SequencerEvent::Text(text) => text.get_text().to_string(),
+            };
+            sink.lock().unwrap().push((time, name));
+        }));
+        sequencer.play(&midi_file, false);
+
+        // The events are reported as the playback reaches them.
+        let mut left = vec![0_f32; 13230];
+        let mut right = vec![0_f32; 13230];
+        sequencer.render(&mut left, &mut right);
+        let names: Vec<String> = events.lock().unwrap().iter().map(|e| e.1.clone()).collect();
+        assert_eq!(names, vec!["La", "90", "A"]);
+
+        for _ in 0..3 {
+            sequencer.render(&mut left, &mut right);
+        }
+        let events = events.lock().unwrap();
+        let names: Vec<&str> = events.iter().map(|e| &e.1[..]).collect();
+        assert_eq!(names, vec!["La", "90", "A", "SysEx 4", "80", "Meta"]);
+        assert!((events[2].0 - 0.25).abs() < 1e-9);
+
+        // The skipped texts are not reported.
+        drop(events);
+        sequencer.seek(0.3);
+        assert_eq!(sequencer.text_index, 2);
+    }
+
+    #[test]
+    fn test_checkpoint_with_loop_region() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+
+        // A note every quarter note at 120 BPM.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x90, 0x40, 0x64, //
+            0x60, 0x90, 0x43, 0x64, //
+            0x60, 0x90, 0x48, 0x64, //
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(96, &track);
+
+        let mut sequencer =
+            MidiFileSequencer::new(Synthesizer::new(&sound_font, &settings).unwrap());
+        sequencer.set_loop_mode(SequencerLoopMode::Emidi);
+        sequencer.play(&midi_file, false);
+        sequencer.set_loop(0.5, 1.0);
+        let mut left = vec![0_f32; 33075];
+        let mut right = vec![0_f32; 33075];
+        sequencer.render(&mut left, &mut right);
+        let checkpoint = sequencer.export_checkpoint();
+
+        // The restored sequencer keeps looping the region in the same way.
+        let mut expected_left = vec![0_f32; 44100];
+        let mut expected_right = vec![0_f32; 44100];
+        sequencer.render(&mut expected_left, &mut expected_right);
+        assert!(sequencer.get_position() < 1.0);
+
+        let mut restored =
+            MidiFileSequencer::new(Synthesizer::new(&sound_font, &settings).unwrap());
+        restored.play(&midi_file, false);
+        restored.restore_checkpoint(&checkpoint).unwrap();
+        assert_eq!(restored.get_loop_mode(), SequencerLoopMode::Emidi);
+        let mut left = vec![0_f32; 44100];
+        let mut right = vec![0_f32; 44100];
+        restored.render(&mut left, &mut right);
+        assert_eq!(left, expected_left);
+        assert_eq!(right, expected_right);
+        assert_eq!(restored.get_position(), sequencer.get_position());
+
+        // The pause is restored as well.
+        sequencer.pause();
+        let checkpoint = sequencer.export_checkpoint();
+        restored.play(&midi_file, false);
+        restored.restore_checkpoint(&checkpoint).unwrap();
+        assert!(restored.is_paused());
+        let position = restored.get_position();
+        restored.render(&mut left, &mut right);
+        assert_eq!(restored.get_position(), position);
+    }
+
+    #[test]
+    fn test_loop_region_and_mode() {
+        // An EMIDI loop played twice from 0.25 to 0.5 seconds, with a note in and after the loop.
+        let track: Vec<u8> = vec![
+            0x30, 0xB0, 0x74, 0x02, //
+            0x0C, 0x90, 0x3C, 0x64, //
+            0x24, 0xB0, 0x75, 0x7F, //
+            0x30, 0x90, 0x40, 0x64, //
+            0x30, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(96, &track);
+
+        let keys = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut sequencer = test_sequencer();
+        let sink = Arc::clone(&keys);
+        sequencer.set_on_event(Box::new(move |_, event| {
+            if let SequencerEvent::Message(message) = event {
+                if message.is_note_on() {
+                    if let Message::Normal { data1, .. } = message {
+                        sink.lock().unwrap().push(data1);
+                    }
+                }
+            }
+        }));
+        let mut left = vec![0_f32; 52920];
+        let mut right = vec![0_f32; 52920];
+
+        // Without loop markers, the whole file is repeated.
+        sequencer.play(&midi_file, true);
+        sequencer.render(&mut left, &mut right);
+        assert_eq!(*keys.lock().unwrap(), vec![60, 64]);
+
+        // The EMIDI loop is played twice before the playback goes on.
+        keys.lock().unwrap().clear();
+        sequencer.set_loop_mode(SequencerLoopMode::Emidi);
+        sequencer.play(&midi_file, true);
+        sequencer.render(&mut left, &mut right);
+        assert_eq!(*keys.lock().unwrap(), vec![60, 60, 64]);
+
+        // The loop region replaces the loop events, and is cleared by play.
+        keys.lock().unwrap().clear();
+        sequencer.set_loop_ticks(0, 72);
+        assert_eq!(sequencer.get_loop(), Some((0.0, 0.375)));
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.get_position() < 0.375 + 64.0 / 44100.0);
+        assert!(keys.lock().unwrap().iter().all(|&key| key == 60));
+        assert!(!sequencer.end_of_sequence());
+        sequencer.play(&midi_file, false);
+        assert_eq!(sequencer.get_loop(), None);
+    }
+
+    #[test]
+    fn test_render_does_not_allocate() {
+        let synthesizer = test_synthesizer();
+
+        let track: Vec<u8> = vec![
+            0x00, 0xC0, 0x05, //
+            0x00, 0xF0, 0x03, 0x7E, 0x7F, 0xF7, //
+            0x00, 0x90, 0x3C, 0x64, //
+            0x30, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, //
+            0x30, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(96, &track);
+
+        let mut sequencer = MidiFileSequencer::new(synthesizer);
+        sequencer.play(&midi_file, true);
+        let mut left = vec![0_f32; 441];
+        let mut right = vec![0_f32; 441];
+        sequencer.render(&mut left, &mut right);
+
+        // The file loops a few times, at a speed which is changed while playing.
+        let count = crate::synthesizer::tests::count_allocations(|| {
+            for i in 0..200 {
+                sequencer.set_speed(if i % 2 == 0 { 1.5 } else { 1.0 });
+                sequencer.render(&mut left, &mut right);
+            }
+        });
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_external_sync() {
+        // At 120 BPM, a note at the start and another at 2 quarter notes, which is 1 second.
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x87, 0x40, 0x90, 0x40, 0x64, //
+            0x9E, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi_file = test_midi_file(480, &track);
+
+        let mut sequencer = test_sequencer();
+        sequencer.set_external_sync(true);
+        sequencer.play(&midi_file, true);
+        assert!(sequencer.is_external_sync());
+
+        // Nothing is played until the master starts.
+        let mut left = vec![0_f32; 2000];
+        let mut right = vec![0_f32; 2000];
+        sequencer.render(&mut left, &mut right);
+        assert_eq!(sequencer.get_position(), 0.0);
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 60));
+
+        // The clock of a master at 60 BPM plays the file at half the speed.
+        sequencer.process_transport_message(0xFA, 0, 0);
+        for i in 0..=48 {
+            sequencer.process_transport_message(0xF8, 0, 0);
+            assert!(sequencer.is_external_sync_running());
+            let length = if i % 2 == 0 { 1837 } else { 1838 };
+            sequencer.render(&mut left[..length], &mut right[..length]);
+            if i == 0 {
+                assert!(sequencer.get_synthesizer().is_note_on(0, 60));
+            }
+            assert!(sequencer.get_position() <= (i + 1) as f64 / 48.0 + 1.0E-9);
+        }
+        assert!((sequencer.get_position() - 1.01).abs() < 0.015);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 64));
+
+        // Stop freezes the position, and the song position pointer moves while stopped.
+        sequencer.process_transport_message(0xFC, 0, 0);
+        assert!(!sequencer.is_external_sync_running());
+        assert!(!sequencer.get_synthesizer().is_note_on(0, 64));
+        let position = sequencer.get_position();
+        sequencer.render(&mut left, &mut right);
+        assert_eq!(sequencer.get_position(), position);
+        sequencer.process_transport_message(0xF2, 4, 0);
+        assert!((sequencer.get_position() - 0.5).abs() < 1.0E-9);
+
+        // The host jumps to 1 quarter note, and plays at 60 BPM from there.
+        for i in 0..=100 {
+            sequencer.sync_to_host(1.0 + i as f64 / 100.0, 60.0, true);
+            sequencer.render(&mut left[..441], &mut right[..441]);
+        }
+        assert!((sequencer.get_position() - 1.005).abs() < 0.005);
+        assert!(sequencer.get_synthesizer().is_note_on(0, 64));
+
+        // Without the sync, the playback runs freely.
+        sequencer.set_external_sync(false);
+        let position = sequencer.get_position();
+        sequencer.render(&mut left[..441], &mut right[..441]);
+        assert!((sequencer.get_position() - position - 0.01).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_drum_part_sysex() {
+        // The XG "Part Mode" of the part 3 and the GS "Use for Rhythm Part" of the part 2 in a file.
+        let midi_file = test_midi_file(
+            96,
+            &[
+                0x00, 0xF0, 0x08, 0x43, 0x10, 0x4C, 0x08, 0x03, 0x07, 0x02, 0xF7, //
+                0x00, 0xF0, 0x0A, 0x41, 0x10, 0x42, 0x12, 0x40, 0x12, 0x15, 0x01, 0x18,
+                0xF7, //
+                0x60, 0xF0, 0x08, 0x43, 0x10, 0x4C, 0x08, 0x03, 0x07, 0x00, 0xF7, //
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        );
+        let mut sequencer = test_sequencer();
+        sequencer.play(&midi_file, false);
+
+        let mut left = vec![0_f32; 1024];
+        let mut right = vec![0_f32; 1024];
+        sequencer.render(&mut left, &mut right);
+        let synthesizer = sequencer.get_synthesizer();
+        assert!(synthesizer.is_channel_drum_mode(3));
+        assert!(synthesizer.is_channel_drum_mode(1));
+        assert!(!synthesizer.is_channel_drum_mode(2));
+
+        // The part 3 is switched back to the normal mode after 0.5 seconds.
+        for _ in 0..22 {
+            sequencer.render(&mut left, &mut right);
+        }
+        assert!(!sequencer.get_synthesizer().is_channel_drum_mode(3));
+        assert!(sequencer.get_synthesizer().is_channel_drum_mode(1));
+    }
+
+    #[test]
+    fn test_is_silent_after_the_reverb_tail() {
+        // A note of 0.1 seconds with a short release, sent fully to the reverb.
+        let data = build_soundfont(&[], &[(54, 1), (38, -2400)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let mut sequencer =
+            MidiFileSequencer::new(Synthesizer::new(&sound_font, &settings).unwrap());
+        let midi_file = test_midi_file(
+            480,
+            &[
+                0x00, 0xB0, 0x5B, 0x7F, //
+                0x00, 0x90, 0x3C, 0x64, //
+                0x60, 0x80, 0x3C, 0x40, //
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        );
+        sequencer.play(&midi_file, false);
+
+        // The sequence ends and the voice stops while the reverb is still ringing.
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+        while !sequencer.end_of_sequence()
+            || sequencer.get_synthesizer().get_active_voice_count() > 0
+        {
+            sequencer.render(&mut left, &mut right);
+        }
+        assert!(!sequencer.is_silent());
+
+        // The rest of the rendering is the tail of the reverb, which decays to silence.
+        let (left, _) = sequencer.render_all();
+        assert!(left.len() > 4410);
+        assert!(left[..4410].iter().any(|&x| x != 0_f32));
+        assert!(sequencer.is_silent());
+    }
+
+    #[test]
+    fn test_loop_end_at_loop_start() {
+        // The loop start and end on the same tick, and the IncredibleMachine end at the first tick.
+        let cases = [
+            (
+                SequencerLoopMode::FinalFantasy,
+                vec![0x00, 0xB0, 0x74, 0x00, 0x00, 0xB0, 0x75, 0x00],
+            ),
+            (
+                SequencerLoopMode::Emidi,
+                vec![0x00, 0xB0, 0x74, 0x00, 0x00, 0xB0, 0x75, 0x7F],
+            ),
+            (
+                SequencerLoopMode::IncredibleMachine,
+                vec![0x00, 0xB0, 0x6F, 0x00],
+            ),
+        ];
+        for (mode, markers) in cases {
+            let mut track = markers;
+            track.extend_from_slice(&[
+                0x00, 0x90, 0x3C, 0x64, //
+                0x60, 0x80, 0x3C, 0x00, //
+                0x00, 0xFF, 0x2F, 0x00,
+            ]);
+            let midi_file = test_midi_file(96, &track);
+
+            let mut sequencer = test_sequencer();
+            sequencer.set_loop_mode(mode);
+            sequencer.play(&midi_file, true);
+            assert_eq!(sequencer.get_warnings().len(), 1);
+
+            // The loop end is ignored, so the block is rendered and the note is played.
+            let mut left = vec![0_f32; 64];
+            let mut right = vec![0_f32; 64];
+            sequencer.render(&mut left, &mut right);
+            assert!(sequencer.get_synthesizer().is_note_on(0, 60));
+            assert!(sequencer.get_position() > 0.0);
+        }
+
+        // A loop end after its start is not warned about.
+        let mut sequencer = test_sequencer();
+        sequencer.set_loop_mode(SequencerLoopMode::FinalFantasy);
+        let track = [
+            0x00, 0xB0, 0x74, 0x00, //
+            0x60, 0xB0, 0x75, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        sequencer.play(&test_midi_file(96, &track), true);
+        assert!(sequencer.get_warnings().is_empty());
     }
 }
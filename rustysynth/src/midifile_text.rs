@@ -0,0 +1,97 @@
+/// Specifies the kind of a text meta event in a MIDI file.
+use alloc::string::String;
+use alloc::string::ToString;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MidiFileTextKind {
+    /// Any text, such as a comment (meta event 0x01).
+    Text,
+    /// The copyright notice (0x02).
+    Copyright,
+    /// The name of the sequence in the first track, or the name of the track in the others (0x03).
+    TrackName,
+    /// The name of the instrument played by the track (0x04).
+    InstrumentName,
+    /// A lyric, usually a syllable sung at the time of the event (0x05).
+    Lyric,
+    /// A marker of the song structure, such as a verse or a chorus (0x06).
+    Marker,
+    /// A cue point, such as a sound effect for a stage or a film (0x07).
+    CuePoint,
+}
+
+impl MidiFileTextKind {
+    pub(crate) fn from_meta_type(meta_type: u8) -> Option<Self> {
+        match meta_type {
+            0x01 => Some(MidiFileTextKind::Text),
+            0x02 => Some(MidiFileTextKind::Copyright),
+            0x03 => Some(MidiFileTextKind::TrackName),
+            0x04 => Some(MidiFileTextKind::InstrumentName),
+            0x05 => Some(MidiFileTextKind::Lyric),
+            0x06 => Some(MidiFileTextKind::Marker),
+            0x07 => Some(MidiFileTextKind::CuePoint),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn to_meta_type(self) -> u8 {
+        match self {
+            MidiFileTextKind::Text => 0x01,
+            MidiFileTextKind::Copyright => 0x02,
+            MidiFileTextKind::TrackName => 0x03,
+            MidiFileTextKind::InstrumentName => 0x04,
+            MidiFileTextKind::Lyric => 0x05,
+            MidiFileTextKind::Marker => 0x06,
+            MidiFileTextKind::CuePoint => 0x07,
+        }
+    }
+}
+
+/// A text meta event in a MIDI file, such as a track name, a marker, or a lyric.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct MidiFileText {
+    pub(crate) time: f64,
+    pub(crate) tick: u64,
+    pub(crate) track: usize,
+    pub(crate) kind: MidiFileTextKind,
+    pub(crate) text: String,
+}
+
+impl MidiFileText {
+    // The text is decoded as UTF-8 if it is valid, and as Latin-1 otherwise,
+    // which the older files commonly use.
+    pub(crate) fn decode(data: &[u8]) -> String {
+        match core::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => data.iter().map(|&byte| byte as char).collect(),
+        }
+    }
+
+    /// Gets the time of the event in seconds.
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    /// Gets the time of the event in ticks, as written in the file.
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Gets the index of the track which contains the event.
+    pub fn get_track(&self) -> usize {
+        self.track
+    }
+
+    /// Gets the kind of the event.
+    pub fn get_kind(&self) -> MidiFileTextKind {
+        self.kind
+    }
+
+    /// Gets the text, decoded as UTF-8 if it is valid, and as Latin-1 otherwise.
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
@@ -0,0 +1,362 @@
+#![allow(dead_code)]
+
+use std::io;
+use std::io::Write;
+
+use crate::midifile::Message;
+use crate::midifile::MidiFile;
+use crate::midifile_text::MidiFileTextKind;
+
+/// Writes a standard MIDI file (SMF) from the events added to its tracks.
+///
+/// The times of the events are given in ticks, at the resolution of the file.
+/// The events of a track can be added in any order, and are written in the order of their ticks,
+/// keeping the order they were added in at the same tick.
+/// A file with one track is written as format 0, and a file with more tracks as format 1,
+/// where the first track usually holds the tempo changes and the time signatures.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MidiFileWriter {
+    resolution: i32,
+    // The events of each track without their delta times, and the tick of the end of the track.
+    tracks: Vec<Vec<(u64, Vec<u8>)>>,
+    track_ends: Vec<u64>,
+}
+
+impl MidiFileWriter {
+    /// Creates a writer without any track, at the resolution in ticks per quarter note (PPQN).
+    pub fn new(resolution: i32) -> Self {
+        if !(1..=0x7FFF).contains(&resolution) {
+            panic!("The resolution must be in the range of 1 to 32767.");
+        }
+
+        Self {
+            resolution,
+            tracks: Vec::new(),
+            track_ends: Vec::new(),
+        }
+    }
+
+    /// Creates a writer with the events of the MIDI file, at the same ticks and resolution.
+    ///
+    /// The tracks were merged into one sequence when the file was loaded, so the events which are played,
+    /// along with the tempo changes, the time signatures and the key signatures, are all written to the first track,
+    /// and the other tracks keep only their text events, such as the track names.
+    /// The loop points are written as CC #116 and #117 on the first channel,
+    /// which `MidiFileLoopType::FinalFantasy` reads back as the start and end of the loop.
+    /// A format 2 file is written as format 1, with its tracks already placed one after another.
+    pub fn from_midi_file(midi_file: &MidiFile) -> Self {
+        let mut writer = MidiFileWriter::new(midi_file.resolution.clamp(1, 0x7FFF));
+        // The SMPTE timing is kept as the division of the header.
+        if midi_file.smpte_timing().is_some() {
+            writer.resolution = midi_file.resolution;
+        }
+        for _ in 0..midi_file.track_count.max(1) {
+            writer.add_track();
+        }
+
+        for (message, &tick) in midi_file.messages.iter().zip(midi_file.ticks.iter()) {
+            match *message {
+                Message::Normal {
+                    status,
+                    data1,
+                    data2,
+                } => match status & 0xF0 {
+                    0xC0 | 0xD0 => writer.push(0, tick, vec![status, data1]),
+                    _ => writer.push(0, tick, vec![status, data1, data2]),
+                },
+                Message::TempoChange { bytes } => {
+                    writer.push(0, tick, vec![0xFF, 0x51, 3, bytes[0], bytes[1], bytes[2]])
+                }
+                Message::SysEx { .. } => {
+                    if let Some(data) = midi_file.get_sysex_data(message) {
+                        writer.add_sysex(0, tick, data);
+                    }
+                }
+                Message::TimeSignature {
+                    numerator,
+                    denominator,
+                } => {
+                    let power = denominator.trailing_zeros() as u8;
+                    writer.push(0, tick, vec![0xFF, 0x58, 4, numerator, power, 24, 8]);
+                }
+                Message::LoopStart => writer.push(0, tick, vec![0xB0, 116, 0]),
+                Message::LoopEnd => writer.push(0, tick, vec![0xB0, 117, 0]),
+                Message::EndOfTrack => writer.set_track_end(0, tick),
+            }
+        }
+
+        for &(time, sharps, minor) in &midi_file.key_signatures {
+            let tick = midi_file.seconds_to_tick(time);
+            writer.push(0, tick, vec![0xFF, 0x59, 2, sharps as u8, minor as u8]);
+        }
+
+        for text in &midi_file.texts {
+            let track = text.track.min(writer.tracks.len() - 1);
+            writer.add_text(track, text.tick, text.kind, &text.text);
+        }
+
+        writer
+    }
+
+    /// Adds an empty track, and returns its index.
+    pub fn add_track(&mut self) -> usize {
+        self.tracks.push(Vec::new());
+        self.track_ends.push(0);
+        self.tracks.len() - 1
+    }
+
+    /// Gets the number of the tracks.
+    pub fn get_track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Gets the resolution in ticks per quarter note (PPQN),
+    /// or the negative division of the header for a file timed in SMPTE frames.
+    pub fn get_resolution(&self) -> i32 {
+        self.resolution
+    }
+
+    /// Adds a channel message, given as `Synthesizer::process_midi_message` takes it.
+    ///
+    /// The program change (0xC0) and the channel pressure (0xD0) have only the first data byte,
+    /// and the second one is ignored.
+    pub fn add_message(
+        &mut self,
+        track: usize,
+        tick: u64,
+        channel: i32,
+        command: i32,
+        data1: i32,
+        data2: i32,
+    ) {
+        if !(0..16).contains(&channel) {
+            panic!("The channel must be in the range of 0 to 15.");
+        }
+        if !matches!(command, 0x80 | 0x90 | 0xA0 | 0xB0 | 0xC0 | 0xD0 | 0xE0) {
+            panic!("The command must be a channel message from 0x80 to 0xE0.");
+        }
+
+        let status = (command | channel) as u8;
+        let (data1, data2) = ((data1 & 0x7F) as u8, (data2 & 0x7F) as u8);
+        match command {
+            0xC0 | 0xD0 => self.push(track, tick, vec![status, data1]),
+            _ => self.push(track, tick, vec![status, data1, data2]),
+        }
+    }
+
+    /// Adds a tempo change in BPM (quarter notes per minute).
+    pub fn add_tempo(&mut self, track: usize, tick: u64, bpm: f64) {
+        if bpm <= 0.0 || bpm.is_nan() {
+            panic!("The tempo must be a positive value.");
+        }
+
+        let tempo = (60_000_000_f64 / bpm).round().clamp(1_f64, 0xFFFFFF as f64) as u32;
+        let bytes = tempo.to_be_bytes();
+        self.push(
+            track,
+            tick,
+            vec![0xFF, 0x51, 3, bytes[1], bytes[2], bytes[3]],
+        );
+    }
+
+    /// Adds a time signature, such as 3/4 or 6/8. The denominator must be a power of two.
+    pub fn add_time_signature(
+        &mut self,
+        track: usize,
+        tick: u64,
+        numerator: i32,
+        denominator: i32,
+    ) {
+        if !(1..=255).contains(&numerator) {
+            panic!("The numerator must be in the range of 1 to 255.");
+        }
+        if !(1..=128).contains(&denominator) || denominator.count_ones() != 1 {
+            panic!("The denominator must be a power of two up to 128.");
+        }
+
+        // The metronome clicks once per quarter note, at 8 32nd notes per quarter.
+        let power = denominator.trailing_zeros() as u8;
+        self.push(
+            track,
+            tick,
+            vec![0xFF, 0x58, 4, numerator as u8, power, 24, 8],
+        );
+    }
+
+    /// Adds a key signature as the number of sharps, which is negative for flats, and whether the key is minor.
+    pub fn add_key_signature(&mut self, track: usize, tick: u64, sharps: i32, minor: bool) {
+        if !(-7..=7).contains(&sharps) {
+            panic!("The number of sharps must be in the range of -7 to 7.");
+        }
+
+        self.push(
+            track,
+            tick,
+            vec![0xFF, 0x59, 2, sharps as i8 as u8, minor as u8],
+        );
+    }
+
+    /// Adds a text meta event, such as a track name or a marker, written as UTF-8.
+    pub fn add_text(&mut self, track: usize, tick: u64, kind: MidiFileTextKind, text: &str) {
+        let mut event = vec![0xFF, kind.to_meta_type()];
+        write_variable_length(&mut event, text.len() as u32);
+        event.extend_from_slice(text.as_bytes());
+        self.push(track, tick, event);
+    }
+
+    /// Adds a SysEx message, including the leading 0xF0, as `Synthesizer::process_sysex` takes it.
+    pub fn add_sysex(&mut self, track: usize, tick: u64, data: &[u8]) {
+        if data.first() != Some(&0xF0) {
+            panic!("The SysEx message must start with 0xF0.");
+        }
+
+        let mut event = vec![0xF0];
+        write_variable_length(&mut event, (data.len() - 1) as u32);
+        event.extend_from_slice(&data[1..]);
+        self.push(track, tick, event);
+    }
+
+    /// Sets the tick of the end of the track, which is otherwise the tick of its last event.
+    /// A tick before the last event has no effect.
+    pub fn set_track_end(&mut self, track: usize, tick: u64) {
+        if track >= self.tracks.len() {
+            panic!("The track must be less than the number of the tracks.");
+        }
+
+        self.track_ends[track] = self.track_ends[track].max(tick);
+    }
+
+    /// Writes the tracks as a standard MIDI file.
+    ///
+    /// An error is returned if the ticks between two events do not fit in the file.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        let track_count = u16::try_from(self.tracks.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "there are too many tracks")
+        })?;
+        let format: u16 = if track_count == 1 { 0 } else { 1 };
+
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6_u32.to_be_bytes())?;
+        writer.write_all(&format.to_be_bytes())?;
+        writer.write_all(&track_count.to_be_bytes())?;
+        writer.write_all(&(self.resolution as u16).to_be_bytes())?;
+
+        for (events, &end) in self.tracks.iter().zip(self.track_ends.iter()) {
+            // The sort is stable, so the events at the same tick keep their order.
+            let mut sorted: Vec<&(u64, Vec<u8>)> = events.iter().collect();
+            sorted.sort_by_key(|(tick, _)| *tick);
+
+            let mut data: Vec<u8> = Vec::new();
+            let mut last_tick: u64 = 0;
+            for (tick, event) in sorted {
+                write_variable_length(&mut data, delta_time(*tick - last_tick)?);
+                data.extend_from_slice(event);
+                last_tick = *tick;
+            }
+            write_variable_length(&mut data, delta_time(end.max(last_tick) - last_tick)?);
+            data.extend_from_slice(&[0xFF, 0x2F, 0]);
+
+            writer.write_all(b"MTrk")?;
+            writer.write_all(&(data.len() as u32).to_be_bytes())?;
+            writer.write_all(&data)?;
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, track: usize, tick: u64, event: Vec<u8>) {
+        if track >= self.tracks.len() {
+            panic!("The track must be less than the number of the tracks.");
+        }
+
+        self.tracks[track].push((tick, event));
+        self.track_ends[track] = self.track_ends[track].max(tick);
+    }
+}
+
+// The largest delta time of a variable-length quantity is 0x0FFFFFFF.
+fn delta_time(ticks: u64) -> Result<u32, io::Error> {
+    if ticks > 0x0FFFFFFF {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the ticks between two events do not fit in a delta time",
+        ));
+    }
+
+    Ok(ticks as u32)
+}
+
+fn write_variable_length(data: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    data.extend_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let mut writer = MidiFileWriter::new(480);
+        let conductor = writer.add_track();
+        let piano = writer.add_track();
+        writer.add_tempo(conductor, 0, 100.0);
+        writer.add_time_signature(conductor, 0, 3, 4);
+        writer.add_key_signature(conductor, 0, -3, true);
+        writer.add_text(conductor, 0, MidiFileTextKind::TrackName, "Song");
+        writer.add_text(piano, 0, MidiFileTextKind::TrackName, "Piano");
+        // Added out of order, and sorted by the ticks when written.
+        writer.add_message(piano, 480, 0, 0x80, 60, 0);
+        writer.add_message(piano, 0, 0, 0xC0, 5, 0);
+        writer.add_message(piano, 0, 0, 0x90, 60, 100);
+        writer.add_sysex(conductor, 0, &[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]);
+        writer.set_track_end(piano, 960);
+
+        let mut data = Vec::new();
+        writer.write(&mut data).unwrap();
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert_eq!(midi_file.get_track_count(), 2);
+        assert_eq!(midi_file.resolution(), 480);
+        assert_eq!(midi_file.get_initial_tempo(), 100.0);
+        assert_eq!(midi_file.get_initial_time_signature(), (3, 4));
+        assert_eq!(midi_file.key_signatures().next(), Some((0.0, -3, true)));
+        assert_eq!(midi_file.get_track_name(1), Some("Piano"));
+        assert_eq!(midi_file.get_length_ticks(), 960);
+
+        let notes: Vec<(u64, u8, u8)> = midi_file
+            .messages
+            .iter()
+            .zip(midi_file.ticks.iter())
+            .filter_map(|(message, tick)| match *message {
+                Message::Normal { status, data1, .. } => Some((*tick, status, data1)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(notes, [(0, 0xC0, 5), (0, 0x90, 60), (480, 0x80, 60)]);
+        let sysex = midi_file
+            .messages
+            .iter()
+            .find_map(|message| midi_file.get_sysex_data(message));
+        assert_eq!(sysex, Some(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7][..]));
+
+        // Writing the loaded file again keeps the events, the tracks of the texts, and the length.
+        let mut rewritten = Vec::new();
+        midi_file.write(&mut rewritten).unwrap();
+        let reloaded = MidiFile::new(&mut &rewritten[..]).unwrap();
+        assert_eq!(reloaded.ticks, midi_file.ticks);
+        assert_eq!(reloaded.times, midi_file.times);
+        assert_eq!(reloaded.get_track_name(0), Some("Song"));
+        assert_eq!(reloaded.get_track_name(1), Some("Piano"));
+        assert_eq!(
+            reloaded.key_signatures().collect::<Vec<_>>(),
+            midi_file.key_signatures().collect::<Vec<_>>()
+        );
+    }
+}
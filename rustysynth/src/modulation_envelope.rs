@@ -2,6 +2,7 @@
 
 use crate::envelope_stage::EnvelopeStage;
 use crate::soundfont_math::SoundFontMath;
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
 
 #[derive(Debug, Clone)]
@@ -138,4 +139,10 @@ impl ModulationEnvelope {
     pub(crate) fn get_value(&self) -> f32 {
         self.value
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_usize(self.processed_sample_count);
+        hasher.write(&[self.stage as u8]);
+        hasher.write_f32(self.value);
+    }
 }
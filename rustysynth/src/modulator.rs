@@ -0,0 +1,463 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::binary_reader::BinaryReader;
+use crate::channel::Channel;
+use crate::error::SoundFontError;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::generator_type::GeneratorType;
+use crate::io::Read;
+
+// The controller which a modulator reads, with how its value is mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ModulatorSource(pub(crate) u16);
+
+impl ModulatorSource {
+    const NONE: u16 = 0;
+    const NOTE_ON_VELOCITY: u16 = 2;
+    const NOTE_ON_KEY_NUMBER: u16 = 3;
+    const POLY_PRESSURE: u16 = 10;
+    const CHANNEL_PRESSURE: u16 = 13;
+    const PITCH_WHEEL: u16 = 14;
+    const PITCH_WHEEL_SENSITIVITY: u16 = 16;
+
+    const CONTROLLER_FLAG: u16 = 0x80;
+    const NEGATIVE_FLAG: u16 = 0x100;
+    const BIPOLAR_FLAG: u16 = 0x200;
+
+    const LINEAR: u16 = 0;
+    const CONCAVE: u16 = 1;
+    const CONVEX: u16 = 2;
+    const SWITCH: u16 = 3;
+
+    fn index(self) -> u16 {
+        self.0 & 0x7F
+    }
+
+    fn is_controller(self) -> bool {
+        self.0 & ModulatorSource::CONTROLLER_FLAG != 0
+    }
+
+    fn curve(self) -> u16 {
+        self.0 >> 10
+    }
+
+    fn is_valid(self) -> bool {
+        let index = self.index();
+        let valid_index = if self.is_controller() {
+            // The bank select, the data entry, the RPN and NRPN and the channel mode messages
+            // are not valid as the source.
+            !matches!(index, 0 | 6 | 32 | 38 | 98..=101 | 120..=127)
+        } else {
+            matches!(
+                index,
+                ModulatorSource::NONE
+                    | ModulatorSource::NOTE_ON_VELOCITY
+                    | ModulatorSource::NOTE_ON_KEY_NUMBER
+                    | ModulatorSource::POLY_PRESSURE
+                    | ModulatorSource::CHANNEL_PRESSURE
+                    | ModulatorSource::PITCH_WHEEL
+                    | ModulatorSource::PITCH_WHEEL_SENSITIVITY
+            )
+        };
+        valid_index && self.curve() <= ModulatorSource::SWITCH
+    }
+
+    // The key and the velocity are fixed while the note is playing.
+    fn is_realtime(self) -> bool {
+        self.is_controller()
+            || !matches!(
+                self.index(),
+                ModulatorSource::NONE
+                    | ModulatorSource::NOTE_ON_VELOCITY
+                    | ModulatorSource::NOTE_ON_KEY_NUMBER
+            )
+    }
+
+    fn get_value(self, channel: &Channel, key: i32, velocity: i32) -> f32 {
+        let index = self.index();
+        let x = if self.is_controller() {
+            channel.get_controller(index as usize) as f32 / 128_f32
+        } else {
+            match index {
+                // The output of no controller is treated as 1, not as a way to turn off the modulator.
+                ModulatorSource::NONE => return 1_f32,
+                ModulatorSource::NOTE_ON_VELOCITY => velocity as f32 / 128_f32,
+                ModulatorSource::NOTE_ON_KEY_NUMBER => key as f32 / 128_f32,
+                ModulatorSource::POLY_PRESSURE => channel.get_key_pressure(key) as f32 / 128_f32,
+                ModulatorSource::CHANNEL_PRESSURE => {
+                    channel.get_channel_pressure() as f32 / 128_f32
+                }
+                ModulatorSource::PITCH_WHEEL => channel.get_pitch_wheel(),
+                ModulatorSource::PITCH_WHEEL_SENSITIVITY => {
+                    channel.get_pitch_bend_range() / 128_f32
+                }
+                _ => return 0_f32,
+            }
+        };
+
+        let x = if self.0 & ModulatorSource::NEGATIVE_FLAG != 0 {
+            1_f32 - x
+        } else {
+            x
+        };
+
+        let curve = self.curve();
+        if self.0 & ModulatorSource::BIPOLAR_FLAG == 0 {
+            ModulatorSource::apply_curve(curve, x)
+        } else if curve == ModulatorSource::SWITCH {
+            if x >= 0.5_f32 {
+                1_f32
+            } else {
+                -1_f32
+            }
+        } else {
+            // The bipolar curves are point symmetric around the center.
+            let y = 2_f32 * x - 1_f32;
+            y.signum() * ModulatorSource::apply_curve(curve, y.abs())
+        }
+    }
+
+    fn apply_curve(curve: u16, x: f32) -> f32 {
+        match curve {
+            ModulatorSource::CONCAVE => ModulatorSource::concave(x),
+            ModulatorSource::CONVEX => 1_f32 - ModulatorSource::concave(1_f32 - x),
+            ModulatorSource::SWITCH => {
+                if x >= 0.5_f32 {
+                    1_f32
+                } else {
+                    0_f32
+                }
+            }
+            _ => x,
+        }
+    }
+
+    // The concave curve follows the attenuation of 96 dB over the range of the controller.
+    fn concave(x: f32) -> f32 {
+        if x >= 1_f32 {
+            1_f32
+        } else {
+            (-(40_f32 / 96_f32) * (1_f32 - x).log10()).min(1_f32)
+        }
+    }
+}
+
+// A modulator of the SoundFont, which adds the value of a controller to a generator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub(crate) struct Modulator {
+    pub(crate) source: ModulatorSource,
+    pub(crate) destination: u16,
+    pub(crate) amount: i16,
+    pub(crate) amount_source: ModulatorSource,
+    pub(crate) transform: u16,
+}
+
+impl Modulator {
+    // The destination of the default pitch wheel modulator, which is not a generator in the file.
+    pub(crate) const INITIAL_PITCH: u16 = GeneratorType::UNUSED_5;
+
+    const LINEAR_TRANSFORM: u16 = 0;
+    const ABSOLUTE_TRANSFORM: u16 = 2;
+
+    // The default modulators which the synthesizer implements by itself,
+    // with the velocity curve, and the channel volume, expression, pan, sends, modulation and pitch bend.
+    // A modulator in the file identical to one of these only adds the difference of the amount.
+    const IMPLICIT_DEFAULTS: [Modulator; 8] = [
+        Modulator::new(0x0502, GeneratorType::INITIAL_ATTENUATION, 960, 0),
+        Modulator::new(0x0081, GeneratorType::VIBRATO_LFO_TO_PITCH, 50, 0),
+        Modulator::new(0x0587, GeneratorType::INITIAL_ATTENUATION, 960, 0),
+        Modulator::new(0x028A, GeneratorType::PAN, 1000, 0),
+        Modulator::new(0x058B, GeneratorType::INITIAL_ATTENUATION, 960, 0),
+        Modulator::new(0x00DB, GeneratorType::REVERB_EFFECTS_SEND, 200, 0),
+        Modulator::new(0x00DD, GeneratorType::CHORUS_EFFECTS_SEND, 200, 0),
+        Modulator::new(0x020E, Modulator::INITIAL_PITCH, 12700, 0x0010),
+    ];
+
+    // The other default modulators, which are evaluated as those in the file.
+    // The last ones are not in the SoundFont specification, and map the sound controllers of GM2,
+    // bipolar around their default of 64, to the resonance (CC 71), the release time (CC 72),
+    // the attack time (CC 73), and the cutoff (CC 74).
+    pub(crate) const EVALUATED_DEFAULTS: [Modulator; 6] = [
+        Modulator::new(
+            0x0102,
+            GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY,
+            -2400,
+            0,
+        ),
+        Modulator::new(0x000D, GeneratorType::VIBRATO_LFO_TO_PITCH, 50, 0),
+        Modulator::new(0x02C7, GeneratorType::INITIAL_FILTER_Q, 120, 0),
+        Modulator::new(0x02C8, GeneratorType::RELEASE_VOLUME_ENVELOPE, 2400, 0),
+        Modulator::new(0x02C9, GeneratorType::ATTACK_VOLUME_ENVELOPE, 2400, 0),
+        Modulator::new(
+            0x02CA,
+            GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY,
+            2400,
+            0,
+        ),
+    ];
+
+    // The modulators added to the notes on the member channels of an MPE zone,
+    // where the channel pressure adds up to 6 dB.
+    pub(crate) const MPE_DEFAULTS: [Modulator; 1] = [Modulator::new(
+        0x000D,
+        GeneratorType::INITIAL_ATTENUATION,
+        -150,
+        0,
+    )];
+
+    // The most default modulators a note can have, with those of the MPE.
+    pub(crate) const MAX_DEFAULT_COUNT: usize =
+        Modulator::EVALUATED_DEFAULTS.len() + Modulator::MPE_DEFAULTS.len();
+
+    const fn new(source: u16, destination: u16, amount: i16, amount_source: u16) -> Self {
+        Self {
+            source: ModulatorSource(source),
+            destination,
+            amount,
+            amount_source: ModulatorSource(amount_source),
+            transform: Modulator::LINEAR_TRANSFORM,
+        }
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
+        let source = BinaryReader::read_u16(reader)?;
+        let destination = BinaryReader::read_u16(reader)?;
+        let amount = BinaryReader::read_i16(reader)?;
+        let amount_source = BinaryReader::read_u16(reader)?;
+        let transform = BinaryReader::read_u16(reader)?;
+
+        Ok(Self {
+            source: ModulatorSource(source),
+            destination,
+            amount,
+            amount_source: ModulatorSource(amount_source),
+            transform,
+        })
+    }
+
+    pub(crate) fn read_from_chunk<R: Read>(
+        reader: &mut R,
+        size: usize,
+    ) -> Result<Vec<Modulator>, SoundFontError> {
+        let count = size / 10;
+
+        let mut modulators: Vec<Modulator> = Vec::new();
+        for _i in 0..count {
+            modulators.push(Modulator::read(reader)?);
+        }
+
+        // The last one is the terminator.
+        modulators.pop();
+
+        Ok(modulators)
+    }
+
+    // Modulators with an unknown source, destination or transform should be ignored.
+    // The linked modulators are not supported.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.source.is_valid()
+            && self.amount_source.is_valid()
+            && (self.destination as usize) < GeneratorType::COUNT
+            && matches!(
+                self.transform,
+                Modulator::LINEAR_TRANSFORM | Modulator::ABSOLUTE_TRANSFORM
+            )
+    }
+
+    // Two modulators of the same sources, destination and transform are regarded as the same one,
+    // and only the amounts differ.
+    fn is_identical(&self, other: &Modulator) -> bool {
+        self.source == other.source
+            && self.destination == other.destination
+            && self.amount_source == other.amount_source
+            && self.transform == other.transform
+    }
+
+    pub(crate) fn is_realtime(&self) -> bool {
+        self.source.is_realtime() || self.amount_source.is_realtime()
+    }
+
+    // Whether the controllers read by the realtime default modulators are at their defaults,
+    // which are no channel pressure and the sound controllers at the center, where those add nothing.
+    pub(crate) fn defaults_at_rest(channel: &Channel) -> bool {
+        channel.get_channel_pressure() == 0
+            && (71..=74).all(|number| channel.get_controller(number) == 64)
+    }
+
+    // The modulators of the local zone replace the identical ones of the global zone.
+    pub(crate) fn merge(global: &[Modulator], local: &[Modulator]) -> Vec<Modulator> {
+        let mut modulators: Vec<Modulator> = global
+            .iter()
+            .filter(|modulator| !local.iter().any(|other| other.is_identical(modulator)))
+            .copied()
+            .collect();
+        modulators.extend_from_slice(local);
+        modulators
+    }
+
+    // Collects the modulators of a voice, where those of the instrument replace the default ones,
+    // and those of the preset are added to them.
+    pub(crate) fn collect(
+        preset: &[Modulator],
+        instrument: &[Modulator],
+        modulators: &mut Vec<Modulator>,
+    ) {
+        modulators.clear();
+        modulators.extend_from_slice(&Modulator::EVALUATED_DEFAULTS);
+
+        for modulator in instrument {
+            let defaults = &mut modulators[..Modulator::EVALUATED_DEFAULTS.len()];
+            if let Some(default) = defaults.iter_mut().find(|m| m.is_identical(modulator)) {
+                default.amount = modulator.amount;
+            } else if let Some(default) = Modulator::IMPLICIT_DEFAULTS
+                .iter()
+                .find(|m| m.is_identical(modulator))
+            {
+                let amount = modulator.amount as i32 - default.amount as i32;
+                if amount != 0 {
+                    modulators.push(Modulator {
+                        amount: amount.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                        ..*modulator
+                    });
+                }
+            } else {
+                modulators.push(*modulator);
+            }
+        }
+
+        modulators.extend_from_slice(preset);
+    }
+
+    // Gets the sum of the modulators for each generator.
+    pub(crate) fn evaluate(
+        modulators: &[Modulator],
+        channel: &Channel,
+        key: i32,
+        velocity: i32,
+    ) -> [f32; GeneratorType::COUNT] {
+        let mut values = [0_f32; GeneratorType::COUNT];
+
+        for modulator in modulators {
+            let value = modulator.amount as f32
+                * modulator.source.get_value(channel, key, velocity)
+                * modulator.amount_source.get_value(channel, key, velocity);
+            let value = if modulator.transform == Modulator::ABSOLUTE_TRANSFORM {
+                value.abs()
+            } else {
+                value
+            };
+            values[modulator.destination as usize] += value;
+        }
+
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_curves() {
+        let channel = Channel::new(false);
+
+        // Velocity, linear, unipolar, negative.
+        let source = ModulatorSource(0x0102);
+        assert_eq!(source.get_value(&channel, 60, 0), 1_f32);
+        assert_eq!(source.get_value(&channel, 60, 64), 0.5_f32);
+
+        // Velocity, concave, unipolar, negative.
+        let source = ModulatorSource(0x0502);
+        assert_eq!(source.get_value(&channel, 60, 0), 1_f32);
+        assert!(source.get_value(&channel, 60, 64) < 0.5_f32);
+
+        // Key, switch, bipolar.
+        let source = ModulatorSource(0x0E03);
+        assert_eq!(source.get_value(&channel, 60, 100), -1_f32);
+        assert_eq!(source.get_value(&channel, 64, 100), 1_f32);
+
+        // The pan is at the center after a reset.
+        let source = ModulatorSource(0x028A);
+        assert_eq!(source.get_value(&channel, 60, 100), 0_f32);
+
+        assert_eq!(ModulatorSource(0).get_value(&channel, 60, 100), 1_f32);
+    }
+
+    #[test]
+    fn test_collect() {
+        let custom = Modulator::new(
+            0x0081,
+            GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY,
+            1200,
+            0,
+        );
+        let vel_to_cutoff =
+            Modulator::new(0x0102, GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY, 0, 0);
+        let cc1_to_vibrato = Modulator::new(0x0081, GeneratorType::VIBRATO_LFO_TO_PITCH, 100, 0);
+
+        let mut modulators = Vec::new();
+        Modulator::collect(&[custom], &[vel_to_cutoff, cc1_to_vibrato], &mut modulators);
+
+        let count = Modulator::EVALUATED_DEFAULTS.len();
+        assert_eq!(modulators.len(), count + 2);
+        // The velocity to the cutoff is turned off by the instrument.
+        assert_eq!(modulators[0], vel_to_cutoff);
+        // The CC 1 to the vibrato only adds the difference from the default.
+        assert_eq!(modulators[count].amount, 50);
+        assert_eq!(modulators[count + 1], custom);
+    }
+
+    #[test]
+    fn test_merge() {
+        let global = [
+            Modulator::new(0x0081, GeneratorType::PAN, 100, 0),
+            Modulator::new(0x0082, GeneratorType::PAN, 100, 0),
+        ];
+        let local = [Modulator::new(0x0081, GeneratorType::PAN, 200, 0)];
+
+        let modulators = Modulator::merge(&global, &local);
+        assert_eq!(modulators, vec![global[1], local[0]]);
+    }
+
+    #[test]
+    fn test_sound_controllers() {
+        let mut channel = Channel::new(false);
+        let mut modulators = Vec::new();
+        Modulator::collect(&[], &[], &mut modulators);
+
+        // The sound controllers do nothing at their defaults.
+        let values = Modulator::evaluate(&modulators, &channel, 60, 127);
+        assert_eq!(values[GeneratorType::INITIAL_FILTER_Q as usize], 0_f32);
+        assert_eq!(
+            values[GeneratorType::RELEASE_VOLUME_ENVELOPE as usize],
+            0_f32
+        );
+        assert_eq!(
+            values[GeneratorType::ATTACK_VOLUME_ENVELOPE as usize],
+            0_f32
+        );
+        // Only the velocity lowers the cutoff slightly.
+        let cutoff = values[GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY as usize];
+        assert_eq!(cutoff, -18.75_f32);
+
+        channel.set_controller(71, 127);
+        channel.set_controller(72, 0);
+        channel.set_controller(73, 127);
+        channel.set_controller(74, 0);
+        let values = Modulator::evaluate(&modulators, &channel, 60, 127);
+        assert!(values[GeneratorType::INITIAL_FILTER_Q as usize] > 100_f32);
+        assert_eq!(
+            values[GeneratorType::RELEASE_VOLUME_ENVELOPE as usize],
+            -2400_f32
+        );
+        assert!(values[GeneratorType::ATTACK_VOLUME_ENVELOPE as usize] > 2000_f32);
+        assert_eq!(
+            values[GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY as usize] - cutoff,
+            -2400_f32
+        );
+    }
+}
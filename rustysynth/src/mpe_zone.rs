@@ -0,0 +1,8 @@
+/// Specifies a zone of MIDI Polyphonic Expression (MPE), configured by `Synthesizer::configure_mpe_zone`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpeZone {
+    /// The zone whose master channel is the channel 1, with the member channels above it.
+    Lower,
+    /// The zone whose master channel is the channel 16, with the member channels below it.
+    Upper,
+}
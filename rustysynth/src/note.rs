@@ -0,0 +1,56 @@
+/// The frequency of A4 (key 69) in Hz, which the synthesizer plays unless retuned.
+use alloc::string::String;
+
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+
+pub const DEFAULT_A4_FREQUENCY: f64 = 440.0;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Gets the frequency in Hz of the key in 12-tone equal temperament with A4 (key 69) at 440 Hz,
+/// shifted by `tuning_cents`.
+///
+/// This is the pitch the synthesizer plays for the key, as the samples are pitched
+/// relative to their root key with the same math.
+pub fn key_to_frequency(key: i32, tuning_cents: f64) -> f64 {
+    key_to_frequency_with_reference(key, tuning_cents, DEFAULT_A4_FREQUENCY)
+}
+
+/// Gets the frequency in Hz of the key in 12-tone equal temperament with A4 (key 69) at `a4_frequency`,
+/// shifted by `tuning_cents`.
+pub fn key_to_frequency_with_reference(key: i32, tuning_cents: f64, a4_frequency: f64) -> f64 {
+    let semitones = (key - 69) as f64 + 0.01 * tuning_cents;
+    a4_frequency * 2_f64.powf(semitones / 12.0)
+}
+
+/// Gets the name of the key with sharps, where the middle C (key 60) is "C4".
+pub fn key_to_note_name(key: i32) -> String {
+    let name = NOTE_NAMES[key.rem_euclid(12) as usize];
+    let octave = key.div_euclid(12) - 1;
+    format!("{name}{octave}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_to_frequency() {
+        assert!((key_to_frequency(69, 0.0) - 440.0).abs() < 1e-9);
+        assert!((key_to_frequency(60, 0.0) - 261.625_565_300_598_6).abs() < 1e-9);
+        assert!((key_to_frequency(69, 100.0) - key_to_frequency(70, 0.0)).abs() < 1e-9);
+        assert!((key_to_frequency_with_reference(81, 0.0, 432.0) - 864.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_key_to_note_name() {
+        assert_eq!(key_to_note_name(60), "C4");
+        assert_eq!(key_to_note_name(69), "A4");
+        assert_eq!(key_to_note_name(61), "C#4");
+        assert_eq!(key_to_note_name(0), "C-1");
+        assert_eq!(key_to_note_name(127), "G9");
+    }
+}
@@ -1,6 +1,14 @@
 #![allow(dead_code)]
 
+use alloc::sync::Arc;
+use core::f64::consts;
+
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::interpolator::Interpolator;
 use crate::loop_mode::LoopMode;
+use crate::note;
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
 
 // In this class, fixed-point numbers are used for speed-up.
@@ -28,6 +36,12 @@ pub(crate) struct Oscillator {
     looping: bool,
 
     position_fp: i64,
+
+    interpolator: Option<Arc<dyn Interpolator>>,
+
+    // In the test tone mode, a sine at the pitch is generated instead of playing the sample.
+    test_tone: bool,
+    test_tone_phase: f64,
 }
 
 impl Oscillator {
@@ -50,6 +64,9 @@ impl Oscillator {
             sample_rate_ratio: 0_f32,
             looping: false,
             position_fp: 0,
+            interpolator: settings.interpolator.clone(),
+            test_tone: settings.test_tone_mode,
+            test_tone_phase: 0.0,
         }
     }
 
@@ -80,6 +97,12 @@ impl Oscillator {
         self.sample_rate_ratio = sample_rate as f32 / self.synthesizer_sample_rate as f32;
         self.looping = self.loop_mode != LoopMode::NoLoop;
         self.position_fp = (start as i64) << Oscillator::FRAC_BITS;
+        self.test_tone_phase = 0.0;
+    }
+
+    pub(crate) fn continue_from(&mut self, other: &Oscillator) {
+        self.position_fp = other.position_fp;
+        self.test_tone_phase = other.test_tone_phase;
     }
 
     pub(crate) fn release(&mut self) {
@@ -89,22 +112,125 @@ impl Oscillator {
     }
 
     pub(crate) fn process(&mut self, data: &[i16], block: &mut [f32], pitch: f32) -> bool {
+        if self.test_tone {
+            return self.fill_block_test_tone(block, pitch, pitch);
+        }
+
+        let pitch_ratio = self.get_pitch_ratio(pitch);
+        self.fill_block(data, block, pitch_ratio as f64, 0_f64)
+    }
+
+    // The pitch ratio changes linearly from the previous pitch to the current pitch within the block.
+    pub(crate) fn process_smooth(
+        &mut self,
+        data: &[i16],
+        block: &mut [f32],
+        previous_pitch: f32,
+        pitch: f32,
+    ) -> bool {
+        if self.test_tone {
+            return self.fill_block_test_tone(block, previous_pitch, pitch);
+        }
+
+        let previous_pitch_ratio = self.get_pitch_ratio(previous_pitch) as f64;
+        let pitch_ratio = self.get_pitch_ratio(pitch) as f64;
+        let step = (pitch_ratio - previous_pitch_ratio) / block.len() as f64;
+        self.fill_block(data, block, previous_pitch_ratio + step, step)
+    }
+
+    // The frequency changes linearly from the previous pitch to the current pitch within the block.
+    // The tone never ends, so the voice lasts until its volume envelope ends.
+    fn fill_block_test_tone(&mut self, block: &mut [f32], previous_pitch: f32, pitch: f32) -> bool {
+        let sample_rate = self.synthesizer_sample_rate as f64;
+        let previous_step = note::key_to_frequency(0, 100.0 * previous_pitch as f64) / sample_rate;
+        let step = note::key_to_frequency(0, 100.0 * pitch as f64) / sample_rate;
+        let step_change = (step - previous_step) / block.len() as f64;
+
+        let mut current_step = previous_step + step_change;
+        for value in block.iter_mut() {
+            *value = (2.0 * consts::PI * self.test_tone_phase).sin() as f32;
+            self.test_tone_phase = (self.test_tone_phase + current_step).fract();
+            current_step += step_change;
+        }
+
+        true
+    }
+
+    fn get_pitch_ratio(&self, pitch: f32) -> f32 {
         let pitch_change = self.pitch_change_scale * (pitch - self.root_key as f32) + self.tune;
-        let pitch_ratio = self.sample_rate_ratio * 2_f32.powf(pitch_change / 12_f32);
-        self.fill_block(data, block, pitch_ratio as f64)
+        self.sample_rate_ratio * 2_f32.powf(pitch_change / 12_f32)
     }
 
-    fn fill_block(&mut self, data: &[i16], block: &mut [f32], pitch_ratio: f64) -> bool {
+    fn fill_block(
+        &mut self,
+        data: &[i16],
+        block: &mut [f32],
+        pitch_ratio: f64,
+        pitch_ratio_step: f64,
+    ) -> bool {
         let pitch_ratio_fp = (Oscillator::FRAC_UNIT as f64 * pitch_ratio) as i64;
+        let pitch_ratio_step_fp = (Oscillator::FRAC_UNIT as f64 * pitch_ratio_step) as i64;
+
+        if let Some(interpolator) = self.interpolator.clone() {
+            return self.fill_block_custom(
+                interpolator.as_ref(),
+                data,
+                block,
+                pitch_ratio_fp,
+                pitch_ratio_step_fp,
+            );
+        }
 
         if self.looping {
-            self.fill_block_continuous(data, block, pitch_ratio_fp)
+            self.fill_block_continuous(data, block, pitch_ratio_fp, pitch_ratio_step_fp)
         } else {
-            self.fill_block_no_loop(data, block, pitch_ratio_fp)
+            self.fill_block_no_loop(data, block, pitch_ratio_fp, pitch_ratio_step_fp)
         }
     }
 
-    fn fill_block_no_loop(&mut self, data: &[i16], block: &mut [f32], pitch_ratio_fp: i64) -> bool {
+    fn fill_block_custom(
+        &mut self,
+        interpolator: &dyn Interpolator,
+        data: &[i16],
+        block: &mut [f32],
+        mut pitch_ratio_fp: i64,
+        pitch_ratio_step_fp: i64,
+    ) -> bool {
+        let end_loop_fp = (self.end_loop as i64) << Oscillator::FRAC_BITS;
+        let loop_length_fp = ((self.end_loop - self.start_loop) as i64) << Oscillator::FRAC_BITS;
+
+        for t in 0..block.len() {
+            if self.looping {
+                if self.position_fp >= end_loop_fp {
+                    self.position_fp -= loop_length_fp;
+                }
+            } else if (self.position_fp >> Oscillator::FRAC_BITS) >= self.end as i64 {
+                if t > 0 {
+                    block[t..].fill(0_f32);
+                    return true;
+                } else {
+                    return false;
+                }
+            }
+
+            let position = self.position_fp as f64 / Oscillator::FRAC_UNIT as f64;
+            let pitch_ratio = pitch_ratio_fp as f64 / Oscillator::FRAC_UNIT as f64;
+            block[t] = interpolator.sample_at_rate(data, position, pitch_ratio);
+
+            self.position_fp += pitch_ratio_fp;
+            pitch_ratio_fp += pitch_ratio_step_fp;
+        }
+
+        true
+    }
+
+    fn fill_block_no_loop(
+        &mut self,
+        data: &[i16],
+        block: &mut [f32],
+        mut pitch_ratio_fp: i64,
+        pitch_ratio_step_fp: i64,
+    ) -> bool {
         for t in 0..block.len() {
             let index = (self.position_fp >> Oscillator::FRAC_BITS) as usize;
             if index >= self.end as usize {
@@ -124,6 +250,7 @@ impl Oscillator {
                 * ((x1 << Oscillator::FRAC_BITS) + a_fp * (x2 - x1)) as f32;
 
             self.position_fp += pitch_ratio_fp;
+            pitch_ratio_fp += pitch_ratio_step_fp;
         }
 
         true
@@ -133,7 +260,8 @@ impl Oscillator {
         &mut self,
         data: &[i16],
         block: &mut [f32],
-        pitch_ratio_fp: i64,
+        mut pitch_ratio_fp: i64,
+        pitch_ratio_step_fp: i64,
     ) -> bool {
         let end_loop_fp = (self.end_loop as i64) << Oscillator::FRAC_BITS;
         let loop_length = (self.end_loop - self.start_loop) as i64;
@@ -157,8 +285,15 @@ impl Oscillator {
                 * ((x1 << Oscillator::FRAC_BITS) + a_fp * (x2 - x1)) as f32;
 
             self.position_fp += pitch_ratio_fp;
+            pitch_ratio_fp += pitch_ratio_step_fp;
         }
 
         true
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write(&self.position_fp.to_le_bytes());
+        hasher.write_bool(self.looping);
+        hasher.write_f64(self.test_tone_phase);
+    }
 }
@@ -0,0 +1,10 @@
+/// Specifies how the pan position (CC 10) is mapped from the full left to the full right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanCurve {
+    /// The GM convention, where 0 is the full left, 64 is the center, and 127 is the full right.
+    /// This is the default behavior.
+    Gm,
+    /// The 14-bit value is mapped linearly, from the full left at 0 to the full right at 16383.
+    /// The value 64 is then slightly right of the center, as in the earlier versions.
+    Linear,
+}
@@ -1,5 +1,8 @@
 #![allow(dead_code)]
 
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::error::SoundFontError;
 use crate::instrument::Instrument;
 use crate::preset_info::PresetInfo;
@@ -84,6 +87,11 @@ impl Preset {
         self.bank_number
     }
 
+    /// Returns `true` if the preset is a drum kit, i.e. its bank number is 128 as in the GM convention.
+    pub fn is_percussion(&self) -> bool {
+        self.bank_number == 128
+    }
+
     /// Gets the library info.
     pub fn get_library(&self) -> i32 {
         self.library
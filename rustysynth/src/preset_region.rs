@@ -1,9 +1,12 @@
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
+
 use crate::error::SoundFontError;
 use crate::generator::Generator;
 use crate::generator_type::GeneratorType;
 use crate::instrument::Instrument;
+use crate::modulator::Modulator;
 use crate::soundfont_math::SoundFontMath;
 use crate::zone::Zone;
 
@@ -21,6 +24,7 @@ fn set_parameter(gs: &mut [i16; GeneratorType::COUNT], generator: &Generator) {
 #[non_exhaustive]
 pub struct PresetRegion {
     pub(crate) gs: [i16; GeneratorType::COUNT],
+    pub(crate) modulators: Vec<Modulator>,
     pub(crate) instrument: usize,
 }
 
@@ -53,6 +57,7 @@ impl PresetRegion {
 
         Ok(Self {
             gs,
+            modulators: Modulator::merge(&global.modulators, &local.modulators),
             instrument: instrument_id,
         })
     }
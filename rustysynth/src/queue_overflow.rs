@@ -0,0 +1,11 @@
+/// Specifies what happens when a message is pushed to a full `CommandQueue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueOverflow {
+    /// The oldest message in the queue is discarded to make room for the new one.
+    DropOldest,
+    /// The new message is discarded.
+    DropNewest,
+    /// The sender waits until the render thread takes messages from the queue.
+    /// This must not be used from a real-time thread.
+    Block,
+}
@@ -0,0 +1,34 @@
+#![allow(dead_code)]
+
+// A small xorshift64* generator, so that the results are reproducible for a given seed.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+    pub(crate) fn new(seed: u64) -> Self {
+        // The state of xorshift must not be zero.
+        let state = if seed == 0 {
+            Random::DEFAULT_SEED
+        } else {
+            seed
+        };
+        Self { state }
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    // Returns a value in [0, 1).
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1_f32 / 16_777_216_f32)
+    }
+}
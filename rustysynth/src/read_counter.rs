@@ -1,4 +1,11 @@
-use std::io::Read;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp;
+
+use crate::binary_reader::BinaryReader;
+use crate::four_cc::FourCC;
+use crate::io;
+use crate::io::Read;
 
 pub(crate) struct ReadCounter<'a, R: Read> {
     reader: &'a mut R,
@@ -13,10 +20,28 @@ impl<'a, R: Read> ReadCounter<'a, R> {
     pub(crate) fn bytes_read(&self) -> usize {
         self.count
     }
+
+    // Skips the body of a sub-chunk without reading past the end of the enclosing list,
+    // so that a sub-chunk with a broken size does not eat into the next list.
+    pub(crate) fn skip_sub_chunk(
+        &mut self,
+        id: FourCC,
+        size: usize,
+        end: usize,
+        warnings: &mut Vec<String>,
+    ) -> io::Result<()> {
+        let remaining = end.saturating_sub(self.count);
+        if size > remaining {
+            warnings.push(format!(
+                "the '{id}' sub-chunk is truncated to {remaining} of its {size} bytes"
+            ));
+        }
+        BinaryReader::discard_data(self, cmp::min(size, remaining))
+    }
 }
 
 impl<R: Read> Read for ReadCounter<'_, R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let len = self.reader.read(buf)?;
         self.count += len;
         Ok(len)
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::channel::Channel;
 use crate::lfo::Lfo;
 use crate::modulation_envelope::ModulationEnvelope;
 use crate::oscillator::Oscillator;
@@ -41,13 +42,15 @@ impl RegionEx {
     pub(crate) fn start_volume_envelope(
         envelope: &mut VolumeEnvelope,
         region: &RegionPair,
+        channel: &Channel,
         key: i32,
         _velocity: i32,
     ) {
         // If the release time is shorter than 10 ms, it will be clamped to 10 ms to avoid pop noise.
+        // The channel's ADSR scale is applied on top of the values from the SoundFont.
 
         let delay = region.get_delay_volume_envelope();
-        let attack = region.get_attack_volume_envelope();
+        let attack = region.get_attack_volume_envelope() * channel.get_attack_scale();
         let hold = region.get_hold_volume_envelope()
             * SoundFontMath::key_number_to_multiplying_factor(
                 region.get_key_number_to_volume_envelope_hold(),
@@ -57,9 +60,15 @@ impl RegionEx {
             * SoundFontMath::key_number_to_multiplying_factor(
                 region.get_key_number_to_volume_envelope_decay(),
                 key,
-            );
-        let sustain = SoundFontMath::decibels_to_linear(-region.get_sustain_volume_envelope());
-        let release = SoundFontMath::max(region.get_release_volume_envelope(), 0.01_f32);
+            )
+            * channel.get_decay_scale();
+        let sustain = SoundFontMath::decibels_to_linear(
+            channel.get_sustain_offset() - region.get_sustain_volume_envelope(),
+        );
+        let release = SoundFontMath::max(
+            region.get_release_volume_envelope() * channel.get_release_scale(),
+            0.01_f32,
+        );
 
         envelope.start(delay, attack, hold, decay, sustain, release);
     }
@@ -90,9 +99,15 @@ impl RegionEx {
         envelope.start(delay, attack, hold, decay, sustain, release);
     }
 
-    pub(crate) fn start_vibrato(lfo: &mut Lfo, region: &RegionPair, _key: i32, _velocity: i32) {
+    pub(crate) fn start_vibrato(
+        lfo: &mut Lfo,
+        region: &RegionPair,
+        channel: &Channel,
+        _key: i32,
+        _velocity: i32,
+    ) {
         lfo.start(
-            region.get_delay_vibrato_lfo(),
+            channel.get_vibrato_delay_scale() * region.get_delay_vibrato_lfo(),
             region.get_frequency_vibrato_lfo(),
         );
     }
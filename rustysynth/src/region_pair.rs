@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
 use crate::generator_type::GeneratorType;
 use crate::instrument_region::InstrumentRegion;
 use crate::loop_mode::LoopMode;
+use crate::modulator::Modulator;
 use crate::preset_region::PresetRegion;
 use crate::soundfont_math::SoundFontMath;
 
@@ -10,15 +13,34 @@ use crate::soundfont_math::SoundFontMath;
 pub(crate) struct RegionPair<'a> {
     pub(crate) preset: &'a PresetRegion,
     pub(crate) instrument: &'a InstrumentRegion,
+    // The pan which places the half of a linked stereo sample at its side,
+    // when the other half is played by the region which the instrument creates for it.
+    pub(crate) stereo_pan: f32,
+    // The values of the modulators at the note-on, added to the generators.
+    modulation: [i32; GeneratorType::COUNT],
 }
 
 impl<'a> RegionPair<'a> {
     pub(crate) fn new(preset: &'a PresetRegion, instrument: &'a InstrumentRegion) -> Self {
-        Self { preset, instrument }
+        Self {
+            preset,
+            instrument,
+            stereo_pan: 0_f32,
+            modulation: [0; GeneratorType::COUNT],
+        }
+    }
+
+    pub(crate) fn with_modulation(&self, values: &[f32; GeneratorType::COUNT]) -> Self {
+        Self {
+            preset: self.preset,
+            instrument: self.instrument,
+            stereo_pan: self.stereo_pan,
+            modulation: values.map(|value| value.round() as i32),
+        }
     }
 
     fn gs(&self, i: usize) -> i32 {
-        self.preset.gs[i] as i32 + self.instrument.gs[i] as i32
+        self.preset.gs[i] as i32 + self.instrument.gs[i] as i32 + self.modulation[i]
     }
 
     pub(crate) fn get_sample_start(&self) -> i32 {
@@ -214,7 +236,9 @@ impl<'a> RegionPair<'a> {
     }
 
     pub(crate) fn get_fine_tune(&self) -> i32 {
-        self.gs(GeneratorType::FINE_TUNE as usize) + self.instrument.sample_pitch_correction
+        self.gs(GeneratorType::FINE_TUNE as usize)
+            + self.modulation[Modulator::INITIAL_PITCH as usize]
+            + self.instrument.sample_pitch_correction
     }
 
     pub(crate) fn get_sample_modes(&self) -> LoopMode {
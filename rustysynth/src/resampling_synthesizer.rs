@@ -0,0 +1,179 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::error::SynthesizerError;
+use crate::synthesizer::Synthesizer;
+use crate::synthesizer_settings::SynthesizerSettings;
+
+/// Renders a synthesizer at its own sample rate, and converts the output to the sample rate of the device.
+///
+/// The output sample rate can be changed at any time, for example when the device switches
+/// between 44.1 kHz and 48 kHz, without rebuilding the synthesizer or losing its state.
+/// The conversion uses the 4-point cubic Hermite interpolation, which delays the output
+/// by two samples of the synthesizer. It does not filter the frequencies above the Nyquist frequency
+/// of a lower output rate, which are quiet in most SoundFonts.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ResamplingSynthesizer {
+    synthesizer: Synthesizer,
+    output_sample_rate: i32,
+    // The number of the samples of the synthesizer for each output sample.
+    step: f64,
+    // The position of the next output sample after the second sample of the history,
+    // which is moved before the sample is computed if it is past the third.
+    position: f64,
+    // The last four samples of the synthesizer.
+    history_left: [f32; 4],
+    history_right: [f32; 4],
+
+    block_left: Vec<f32>,
+    block_right: Vec<f32>,
+    block_read: usize,
+}
+
+impl ResamplingSynthesizer {
+    /// Wraps the synthesizer, to render at the output sample rate.
+    pub fn new(
+        synthesizer: Synthesizer,
+        output_sample_rate: i32,
+    ) -> Result<Self, SynthesizerError> {
+        SynthesizerSettings::check_sample_rate(output_sample_rate)?;
+
+        let block_size = synthesizer.get_block_size();
+        let step = synthesizer.get_sample_rate() as f64 / output_sample_rate as f64;
+
+        Ok(Self {
+            synthesizer,
+            output_sample_rate,
+            step,
+            position: 1.0,
+            history_left: [0_f32; 4],
+            history_right: [0_f32; 4],
+            block_left: vec![0_f32; block_size],
+            block_right: vec![0_f32; block_size],
+            block_read: block_size,
+        })
+    }
+
+    /// Changes the output sample rate. The conversion continues from the samples already rendered,
+    /// so there is no gap in the output.
+    pub fn set_output_sample_rate(&mut self, value: i32) -> Result<(), SynthesizerError> {
+        SynthesizerSettings::check_sample_rate(value)?;
+
+        self.output_sample_rate = value;
+        self.step = self.synthesizer.get_sample_rate() as f64 / value as f64;
+
+        Ok(())
+    }
+
+    pub fn get_output_sample_rate(&self) -> i32 {
+        self.output_sample_rate
+    }
+
+    pub fn get_synthesizer(&self) -> &Synthesizer {
+        &self.synthesizer
+    }
+
+    /// Gets the synthesizer to send the MIDI messages and change the settings.
+    pub fn get_synthesizer_mut(&mut self) -> &mut Synthesizer {
+        &mut self.synthesizer
+    }
+
+    /// Returns the synthesizer, dropping the samples which are rendered but not yet read.
+    pub fn into_synthesizer(self) -> Synthesizer {
+        self.synthesizer
+    }
+
+    /// Renders the waveform at the output sample rate. Both buffers must be the same length.
+    ///
+    /// The buffers can have any length.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if left.len() != right.len() {
+            panic!("The output buffers for the left and right must be the same length.");
+        }
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            while self.position >= 1.0 {
+                self.advance();
+                self.position -= 1.0;
+            }
+
+            let a = self.position as f32;
+            *left = ResamplingSynthesizer::interpolate(&self.history_left, a);
+            *right = ResamplingSynthesizer::interpolate(&self.history_right, a);
+            self.position += self.step;
+        }
+    }
+
+    // Moves the history by one sample of the synthesizer.
+    fn advance(&mut self) {
+        if self.block_read == self.block_left.len() {
+            self.synthesizer
+                .render(&mut self.block_left, &mut self.block_right);
+            self.block_read = 0;
+        }
+
+        self.history_left.copy_within(1.., 0);
+        self.history_right.copy_within(1.., 0);
+        self.history_left[3] = self.block_left[self.block_read];
+        self.history_right[3] = self.block_right[self.block_read];
+        self.block_read += 1;
+    }
+
+    // The value between the second and third samples, as `CubicInterpolator` computes it.
+    fn interpolate(x: &[f32; 4], a: f32) -> f32 {
+        let c1 = 0.5_f32 * (x[2] - x[0]);
+        let c2 = x[0] - 2.5_f32 * x[1] + 2_f32 * x[2] - 0.5_f32 * x[3];
+        let c3 = 0.5_f32 * (x[3] - x[0]) + 1.5_f32 * (x[1] - x[2]);
+
+        ((c3 * a + c2) * a + c1) * a + x[1]
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::soundfont::SoundFont;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sample_rate_change() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let mut reference = Synthesizer::new(&sound_font, &settings).unwrap();
+        let synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        let mut resampler = ResamplingSynthesizer::new(synthesizer, 44100).unwrap();
+
+        // At the same rate, the output is that of the synthesizer, two samples later.
+        reference.note_on(0, 60, 100);
+        resampler.get_synthesizer_mut().note_on(0, 60, 100);
+        let mut expected_left = vec![0_f32; 300];
+        let mut expected_right = vec![0_f32; 300];
+        reference.render(&mut expected_left, &mut expected_right);
+        let mut left = vec![0_f32; 302];
+        let mut right = vec![0_f32; 302];
+        for (left, right) in left.chunks_mut(7).zip(right.chunks_mut(7)) {
+            resampler.render(left, right);
+        }
+        assert_eq!(&left[..2], &[0_f32; 2]);
+        assert_eq!(&right[2..], &expected_right[..]);
+
+        // At 48 kHz, the synthesizer renders 44100 samples for each 48000 output samples.
+        resampler.set_output_sample_rate(48000).unwrap();
+        assert_eq!(resampler.get_output_sample_rate(), 48000);
+        let position = resampler.synthesizer.get_sample_position();
+        let mut left = vec![0_f32; 4800];
+        let mut right = vec![0_f32; 4800];
+        resampler.render(&mut left, &mut right);
+        let rendered = resampler.synthesizer.get_sample_position() - position;
+        assert!((rendered as i64 - 4410).abs() <= 64);
+        assert!(right.iter().any(|value| *value != 0_f32));
+
+        assert!(matches!(
+            resampler.set_output_sample_rate(8000),
+            Err(SynthesizerError::SampleRateOutOfRange(8000))
+        ));
+    }
+}
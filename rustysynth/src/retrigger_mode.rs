@@ -0,0 +1,13 @@
+/// Specifies what happens when a key is struck again on a channel before its note-off.
+///
+/// In both modes, the new note gets its own voices, which play the sample from its start point,
+/// so the attacks of retriggered notes are identical,
+/// unless `SynthesizerSettings::reset_phase_on_note_on` is `false`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetriggerMode {
+    /// The new note is layered over the one still sounding.
+    /// This is the default behavior.
+    Layer,
+    /// The voices still sounding for the same channel and key are cut before the new note starts.
+    Steal,
+}
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use std::cmp;
+use alloc::vec::Vec;
+use core::cmp;
+
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::state_hasher::StateHasher;
 
 #[derive(Debug, Clone)]
 #[non_exhaustive]
@@ -10,6 +15,8 @@ pub(crate) struct Reverb {
     apfs_l: Vec<AllPassFilter>,
     apfs_r: Vec<AllPassFilter>,
 
+    sample_rate: i32,
+
     gain: f32,
     room_size: f32,
     room_size1: f32,
@@ -58,41 +65,122 @@ impl Reverb {
     const APF_TUNING_L4: usize = 225;
     const APF_TUNING_R4: usize = 225 + Reverb::STEREO_SPREAD;
 
-    pub(crate) fn new(sample_rate: i32) -> Self {
+    // Values smaller than this are flushed to zero in the feedback paths.
+    const DENORMAL_THRESHOLD: f32 = 1.0E-6;
+
+    pub(crate) fn new(sample_rate: i32, flush_denormals: bool) -> Self {
+        let threshold = if flush_denormals {
+            Reverb::DENORMAL_THRESHOLD
+        } else {
+            0_f32
+        };
+
         let cfs_l: Vec<CombFilter> = vec![
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L1)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L2)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L3)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L4)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L5)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L6)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L7)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L8)),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L1),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L2),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L3),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L4),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L5),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L6),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L7),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_L8),
+                threshold,
+            ),
         ];
 
         let cfs_r: Vec<CombFilter> = vec![
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R1)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R2)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R3)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R4)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R5)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R6)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R7)),
-            CombFilter::new(Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R8)),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R1),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R2),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R3),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R4),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R5),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R6),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R7),
+                threshold,
+            ),
+            CombFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::CF_TUNING_R8),
+                threshold,
+            ),
         ];
 
         let mut apfs_l: Vec<AllPassFilter> = vec![
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L1)),
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L2)),
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L3)),
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L4)),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L1),
+                threshold,
+            ),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L2),
+                threshold,
+            ),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L3),
+                threshold,
+            ),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_L4),
+                threshold,
+            ),
         ];
 
         let mut apfs_r: Vec<AllPassFilter> = vec![
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R1)),
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R2)),
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R3)),
-            AllPassFilter::new(Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R4)),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R1),
+                threshold,
+            ),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R2),
+                threshold,
+            ),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R3),
+                threshold,
+            ),
+            AllPassFilter::new(
+                Reverb::scale_tuning(sample_rate, Reverb::APF_TUNING_R4),
+                threshold,
+            ),
         ];
 
         for apf in apfs_l.iter_mut() {
@@ -108,6 +196,7 @@ impl Reverb {
             cfs_r,
             apfs_l,
             apfs_r,
+            sample_rate,
             gain: 0_f32,
             room_size: 0_f32,
             room_size1: 0_f32,
@@ -149,6 +238,13 @@ impl Reverb {
         ((sample_rate as f64) / 44100_f64 * (tuning as f64)).round() as usize
     }
 
+    // The damping is a one-pole low-pass filter tuned for 44.1 kHz.
+    // The pole is moved so that the cutoff frequency, and thus the decay of the high frequencies,
+    // does not depend on the sample rate.
+    fn scale_damp(sample_rate: i32, damp: f32) -> f32 {
+        damp.powf(44100_f32 / sample_rate as f32)
+    }
+
     pub(crate) fn process(
         &mut self,
         input: &[f32],
@@ -183,7 +279,7 @@ impl Reverb {
         }
 
         // With the default settings, we can skip this part.
-        if 1_f32 - self.wet1 > 1.0E-3_f32 || self.wet2 > 1.0E-3_f32 {
+        if (1_f32 - self.wet1).abs() > 1.0E-3_f32 || self.wet2 > 1.0E-3_f32 {
             for t in 0..input_length {
                 let left = output_left[t];
                 let right = output_right[t];
@@ -198,7 +294,7 @@ impl Reverb {
         self.wet2 = self.wet * ((1_f32 - self.width) / 2_f32);
 
         self.room_size1 = self.room_size;
-        self.damp1 = self.damp;
+        self.damp1 = Reverb::scale_damp(self.sample_rate, self.damp);
         self.gain = Reverb::FIXED_GAIN;
 
         for cf in self.cfs_l.iter_mut() {
@@ -212,29 +308,76 @@ impl Reverb {
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn heap_size(&self) -> usize {
+        let cfs = self.cfs_l.iter().chain(self.cfs_r.iter());
+        let apfs = self.apfs_l.iter().chain(self.apfs_r.iter());
+        let buffer_size: usize = cfs.map(|cf| cf.buffer.len()).sum::<usize>()
+            + apfs.map(|apf| apf.buffer.len()).sum::<usize>();
+
+        size_of::<f32>() * buffer_size
+    }
+
     pub fn get_input_gain(&self) -> f32 {
         self.gain
     }
 
-    fn set_room_size(&mut self, value: f32) {
+    pub(crate) fn get_room_size(&self) -> f32 {
+        (self.room_size - Reverb::OFFSET_ROOM) / Reverb::SCALE_ROOM
+    }
+
+    pub(crate) fn set_room_size(&mut self, value: f32) {
         self.room_size = (value * Reverb::SCALE_ROOM) + Reverb::OFFSET_ROOM;
         self.update();
     }
 
-    fn set_damp(&mut self, value: f32) {
+    pub(crate) fn get_damp(&self) -> f32 {
+        self.damp / Reverb::SCALE_DAMP
+    }
+
+    pub(crate) fn set_damp(&mut self, value: f32) {
         self.damp = value * Reverb::SCALE_DAMP;
         self.update();
     }
 
+    // The wet level is the gain of the return, which is 1 by default.
+    pub(crate) fn get_wet_level(&self) -> f32 {
+        self.wet
+    }
+
+    pub(crate) fn set_wet_level(&mut self, value: f32) {
+        self.wet = value;
+        self.update();
+    }
+
     fn set_wet(&mut self, value: f32) {
         self.wet = value * Reverb::SCALE_WET;
         self.update();
     }
 
-    fn set_width(&mut self, value: f32) {
+    pub(crate) fn get_width(&self) -> f32 {
+        self.width
+    }
+
+    pub(crate) fn set_width(&mut self, value: f32) {
         self.width = value;
         self.update();
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        for cf in self.cfs_l.iter().chain(self.cfs_r.iter()) {
+            hasher.write_f32_slice(&cf.buffer);
+            hasher.write_usize(cf.buffer_index);
+            hasher.write_f32(cf.filter_store);
+        }
+        for apf in self.apfs_l.iter().chain(self.apfs_r.iter()) {
+            hasher.write_f32_slice(&apf.buffer);
+            hasher.write_usize(apf.buffer_index);
+        }
+        for value in [self.room_size, self.damp, self.wet, self.width] {
+            hasher.write_f32(value);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -248,10 +391,12 @@ struct CombFilter {
     feedback: f32,
     damp1: f32,
     damp2: f32,
+
+    denormal_threshold: f32,
 }
 
 impl CombFilter {
-    fn new(buffer_size: usize) -> Self {
+    fn new(buffer_size: usize, denormal_threshold: f32) -> Self {
         Self {
             buffer: vec![0_f32; buffer_size],
             buffer_index: 0,
@@ -259,6 +404,7 @@ impl CombFilter {
             feedback: 0_f32,
             damp1: 0_f32,
             damp2: 0_f32,
+            denormal_threshold,
         }
     }
 
@@ -297,12 +443,12 @@ impl CombFilter {
                 // but the simple Math.Abs version was faster according to some benchmarks.
 
                 let mut output = self.buffer[buffer_pos];
-                if output.abs() < 1.0E-6_f32 {
+                if output.abs() < self.denormal_threshold {
                     output = 0_f32;
                 }
 
                 self.filter_store = (output * self.damp2) + (self.filter_store * self.damp1);
-                if self.filter_store.abs() < 1.0E-6_f32 {
+                if self.filter_store.abs() < self.denormal_threshold {
                     self.filter_store = 0_f32;
                 }
 
@@ -333,14 +479,17 @@ struct AllPassFilter {
     buffer_index: usize,
 
     feedback: f32,
+
+    denormal_threshold: f32,
 }
 
 impl AllPassFilter {
-    fn new(buffer_size: usize) -> Self {
+    fn new(buffer_size: usize, denormal_threshold: f32) -> Self {
         Self {
             buffer: vec![0_f32; buffer_size],
             buffer_index: 0,
             feedback: 0_f32,
+            denormal_threshold,
         }
     }
 
@@ -372,7 +521,7 @@ impl AllPassFilter {
                 let input = block[block_pos];
 
                 let mut bufout = self.buffer[buffer_pos];
-                if bufout.abs() < 1.0E-6_f32 {
+                if bufout.abs() < self.denormal_threshold {
                     bufout = 0_f32;
                 }
 
@@ -389,3 +538,62 @@ impl AllPassFilter {
         self.feedback = value;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Measures the RT60 of the pulse response from the -5 dB to -35 dB range
+    // of its Schroeder energy decay curve.
+    fn decay_time(sample_rate: i32) -> f64 {
+        let mut reverb = Reverb::new(sample_rate, false);
+
+        let block_size = 64;
+        let mut input = vec![0_f32; block_size];
+        let mut output_left = vec![0_f32; block_size];
+        let mut output_right = vec![0_f32; block_size];
+
+        // A 2 ms Hann pulse is used instead of a single sample,
+        // so that the input has the same spectrum at both sample rates.
+        let pulse_length = sample_rate as usize / 500;
+        let pulse = |t: usize| {
+            if t < pulse_length {
+                (core::f32::consts::PI * t as f32 / pulse_length as f32)
+                    .sin()
+                    .powi(2)
+            } else {
+                0_f32
+            }
+        };
+
+        let mut energy: Vec<f64> = Vec::new();
+        for block in 0..(4 * sample_rate as usize / block_size) {
+            for (t, value) in input.iter_mut().enumerate() {
+                *value = pulse(block * block_size + t);
+            }
+            reverb.process(&input, &mut output_left, &mut output_right);
+            energy.extend(output_left.iter().map(|x| (*x as f64) * (*x as f64)));
+        }
+
+        let mut remaining = 0.0;
+        let mut curve: Vec<f64> = vec![0.0; energy.len()];
+        for t in (0..energy.len()).rev() {
+            remaining += energy[t];
+            curve[t] = remaining;
+        }
+
+        let time_at = |decibels: f64| {
+            let threshold = curve[0] * 10_f64.powf(decibels / 10.0);
+            curve.iter().position(|x| *x < threshold).unwrap() as f64 / sample_rate as f64
+        };
+
+        2.0 * (time_at(-35.0) - time_at(-5.0))
+    }
+
+    #[test]
+    fn test_decay_time_independent_of_sample_rate() {
+        let t44 = decay_time(44100);
+        let t96 = decay_time(96000);
+        assert!((t96 - t44).abs() / t44 < 0.05, "{t44} s vs {t96} s");
+    }
+}
@@ -1,9 +1,11 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::binary_reader::BinaryReader;
 use crate::error::SoundFontError;
+use crate::io::Read;
 
 /// Represents a sample in the SoundFont.
 #[derive(Debug)]
@@ -22,6 +24,13 @@ pub struct SampleHeader {
 }
 
 impl SampleHeader {
+    // The sample types of the halves of a linked stereo sample, without the ROM and compression flags.
+    pub(crate) const RIGHT_SAMPLE: u16 = 2;
+    pub(crate) const LEFT_SAMPLE: u16 = 4;
+    pub(crate) const ROM_FLAG: u16 = 0x8000;
+    // The flag of SoundFont3 for a sample compressed with Ogg Vorbis.
+    pub(crate) const COMPRESSED_FLAG: u16 = 0x10;
+
     fn new<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
         let name = BinaryReader::read_fixed_length_string(reader, 20)?;
         let start = BinaryReader::read_i32(reader)?;
@@ -118,4 +127,21 @@ impl SampleHeader {
     pub fn get_sample_type(&self) -> i32 {
         self.sample_type as i32
     }
+
+    // Gets the index of the other half if this is one half of a linked stereo sample.
+    pub(crate) fn get_stereo_link(&self, samples: &[SampleHeader]) -> Option<usize> {
+        // The flags of the ROM and the SoundFont3 compression do not change the channel.
+        let flags = SampleHeader::ROM_FLAG | SampleHeader::COMPRESSED_FLAG;
+        let partner_type = match self.sample_type & !flags {
+            SampleHeader::LEFT_SAMPLE => SampleHeader::RIGHT_SAMPLE,
+            SampleHeader::RIGHT_SAMPLE => SampleHeader::LEFT_SAMPLE,
+            _ => return None,
+        };
+
+        let link = self.link as usize;
+        match samples.get(link) {
+            Some(partner) if partner.sample_type & !flags == partner_type => Some(link),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,16 @@
+use crate::midifile::Message;
+use crate::midifile_text::MidiFileText;
+
+/// An event dispatched by `MidiFileSequencer`, as passed to the callback of `set_on_event`.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum SequencerEvent<'a> {
+    /// A message of the file other than a SysEx message, such as a note-on,
+    /// a program change, a tempo change, or a time signature.
+    /// The channel messages are as played, after the message filter.
+    Message(Message),
+    /// A SysEx message with its data, including the leading 0xF0.
+    SysEx(&'a [u8]),
+    /// A text meta event of the file, such as a lyric or a marker.
+    Text(&'a MidiFileText),
+}
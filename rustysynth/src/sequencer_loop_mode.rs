@@ -0,0 +1,23 @@
+/// Specifies which events of a MIDI file mark the loop, when the sequencer loops the file.
+///
+/// Unlike `MidiFileLoopType`, which is fixed when the file is loaded,
+/// the mode can be changed on the sequencer at any time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SequencerLoopMode {
+    /// The `LoopStart` and `LoopEnd` messages of the file, as given by the `MidiFileLoopType` at loading.
+    /// This is the default behavior.
+    Markers,
+    /// The RPG Maker style loop. CC #111 is the loop start point.
+    RpgMaker,
+    /// The Incredible Machine style loop. CC #110 and #111 are the start and end points of the loop.
+    IncredibleMachine,
+    /// The Final Fantasy style loop. CC #116 and #117 are the start and end points of the loop.
+    FinalFantasy,
+    /// The EMIDI loop. CC #116 is the loop start point, whose value is the number of times
+    /// the loop is played, or 0 to loop endlessly, and CC #117 is the loop end point.
+    /// After the last time, the playback goes on past the loop end.
+    Emidi,
+    /// The loop events are ignored, and the whole file is repeated.
+    WholeFile,
+}
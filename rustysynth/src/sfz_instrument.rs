@@ -0,0 +1,1245 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::SfzError;
+use crate::error::SoundFontError;
+use crate::generator_type::GeneratorType;
+use crate::soundfont::SoundFont;
+use crate::soundfont_builder::{push_address_offset, SoundFontBuilder};
+
+// The opcodes set under a header, by name.
+type Opcodes = HashMap<String, String>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    None,
+    Control,
+    Global,
+    Master,
+    Group,
+    Region,
+    Unknown,
+}
+
+// A sample file decoded to 16 bits, with the halves of a stereo file separated.
+#[derive(Debug)]
+struct SfzSample {
+    name: String,
+    channels: Vec<Vec<i16>>,
+    sample_rate: u32,
+    // The first loop and the unity key stored in the 'smpl' chunk of a WAV file.
+    loop_points: Option<(u32, u32)>,
+    unity_key: Option<u8>,
+}
+
+// A region converted to the generators of an instrument zone.
+// The positions are in the sample points from the start of the file, with the ends exclusive.
+#[derive(Debug)]
+struct SfzRegion {
+    sample: usize,
+    generators: Vec<(u16, i16)>,
+    start: u32,
+    end: u32,
+    start_loop: u32,
+    end_loop: u32,
+    root_key: u8,
+}
+
+/// Represents an instrument loaded from an SFZ file and its samples.
+///
+/// The regions are converted to the zones of a SoundFont instrument by `to_sound_font`,
+/// so that the instrument can be played with `Synthesizer::new`, `Synthesizer::add_sound_font`,
+/// or `Synthesizer::set_sound_font` like any other SoundFont.
+///
+/// The `<control>`, `<global>`, `<master>`, `<group>` and `<region>` headers, `#define`, and `#include`
+/// are supported. The opcodes for the sample, the key and velocity ranges, the tuning, the volume and pan,
+/// the amplitude envelope, the filter cutoff and resonance, the loop, and the exclusive groups are converted;
+/// the others are skipped with a warning. The samples are read from WAV files in 8, 16, 24 or 32-bit PCM
+/// or floating point, and from FLAC files with the `flac` feature.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SfzInstrument {
+    name: String,
+    samples: Vec<SfzSample>,
+    regions: Vec<SfzRegion>,
+    warnings: Vec<String>,
+}
+
+impl SfzInstrument {
+    const MAX_INCLUDE_DEPTH: usize = 16;
+
+    // The opcodes which are converted, or which need no conversion.
+    const SUPPORTED_OPCODES: [&'static str; 37] = [
+        "sample",
+        "default_path",
+        "lokey",
+        "hikey",
+        "key",
+        "lovel",
+        "hivel",
+        "pitch_keycenter",
+        "pitch_keytrack",
+        "transpose",
+        "tune",
+        "volume",
+        "amplitude",
+        "pan",
+        "offset",
+        "end",
+        "loop_mode",
+        "loopmode",
+        "loop_start",
+        "loopstart",
+        "loop_end",
+        "loopend",
+        "ampeg_delay",
+        "ampeg_attack",
+        "ampeg_hold",
+        "ampeg_decay",
+        "ampeg_sustain",
+        "ampeg_release",
+        "cutoff",
+        "resonance",
+        "fil_type",
+        "group",
+        "off_by",
+        "trigger",
+        "global_label",
+        "master_label",
+        "group_label",
+    ];
+
+    /// Loads an SFZ file and the samples it refers to.
+    ///
+    /// The sample paths are relative to the directory of the file.
+    /// The regions whose sample cannot be read are skipped with a warning.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SfzError> {
+        let path = path.as_ref();
+        let text = read_text(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut instrument = SfzInstrument::parse(&text, directory)?;
+        if let Some(stem) = path.file_stem() {
+            instrument.name = stem.to_string_lossy().into_owned();
+        }
+
+        Ok(instrument)
+    }
+
+    /// Parses the contents of an SFZ file, whose sample paths are relative to the directory.
+    pub fn parse(text: &str, directory: &Path) -> Result<Self, SfzError> {
+        let mut parser = Parser::new(directory);
+        parser.parse_text(text, 0)?;
+        parser.finish_region();
+
+        let mut instrument = Self {
+            name: "Instrument".to_string(),
+            samples: Vec::new(),
+            regions: Vec::new(),
+            warnings: parser.warnings,
+        };
+
+        let mut loaded: HashMap<PathBuf, Option<usize>> = HashMap::new();
+        for opcodes in &parser.regions {
+            instrument.check_opcodes(opcodes);
+
+            if opcodes
+                .get("trigger")
+                .is_some_and(|trigger| trigger != "attack")
+            {
+                instrument.warn("the regions not triggered by the note-on were skipped");
+                continue;
+            }
+
+            let sample = match opcodes.get("sample") {
+                Some(sample) => sample,
+                None => {
+                    instrument.warn("the regions without a sample were skipped");
+                    continue;
+                }
+            };
+            if sample.starts_with('*') {
+                let message = format!("the generated sample '{sample}' is not supported");
+                instrument.warn(&message);
+                continue;
+            }
+
+            let path = directory.join(sample.replace('\\', "/"));
+            let sample = match loaded.get(&path) {
+                Some(sample) => *sample,
+                None => {
+                    let sample = match SfzInstrument::read_sample(&path) {
+                        Ok(sample) => {
+                            instrument.samples.push(sample);
+                            Some(instrument.samples.len() - 1)
+                        }
+                        Err(message) => {
+                            let message = format!(
+                                "the sample '{}' could not be read: {}",
+                                path.display(),
+                                message
+                            );
+                            instrument.warnings.push(message);
+                            None
+                        }
+                    };
+                    loaded.insert(path, sample);
+                    sample
+                }
+            };
+
+            if let Some(sample) = sample {
+                if let Some(region) = instrument.create_region(opcodes, sample) {
+                    instrument.regions.push(region);
+                }
+            }
+        }
+
+        if instrument.regions.is_empty() {
+            return Err(SfzError::RegionNotFound);
+        }
+
+        Ok(instrument)
+    }
+
+    /// Converts the instrument into a SoundFont with one preset at the bank and patch numbers.
+    pub fn to_sound_font(&self, bank: i32, patch: i32) -> Result<SoundFont, SoundFontError> {
+        if !(0..=128).contains(&bank) {
+            panic!("The bank number must be in the range of 0 to 128.");
+        }
+        if !(0..=127).contains(&patch) {
+            panic!("The patch number must be in the range of 0 to 127.");
+        }
+
+        // Each file is added as a sample, and the zones set the positions and the root key of their regions.
+        let mut builder = SoundFontBuilder::new(&self.name);
+        let sample_ids: Vec<usize> = self
+            .samples
+            .iter()
+            .map(|sample| {
+                let ids: Vec<usize> = sample
+                    .channels
+                    .iter()
+                    .map(|channel| {
+                        builder.add_sample(
+                            &sample.name,
+                            channel,
+                            sample.sample_rate as i32,
+                            60,
+                            None,
+                        )
+                    })
+                    .collect();
+                if ids.len() == 2 {
+                    builder.link_samples(ids[0], ids[1]);
+                }
+                ids[0]
+            })
+            .collect();
+
+        let instrument = builder.add_instrument(&self.name);
+        for region in &self.regions {
+            let length = self.samples[region.sample].channels[0].len() as i32;
+            let mut generators = region.generators.clone();
+            generators.push((GeneratorType::OVERRIDING_ROOT_KEY, region.root_key as i16));
+            for (fine_type, coarse_type, offset) in [
+                (
+                    GeneratorType::START_ADDRESS_OFFSET,
+                    GeneratorType::START_ADDRESS_COARSE_OFFSET,
+                    region.start as i32,
+                ),
+                (
+                    GeneratorType::END_ADDRESS_OFFSET,
+                    GeneratorType::END_ADDRESS_COARSE_OFFSET,
+                    region.end as i32 - length,
+                ),
+                (
+                    GeneratorType::START_LOOP_ADDRESS_OFFSET,
+                    GeneratorType::START_LOOP_ADDRESS_COARSE_OFFSET,
+                    region.start_loop as i32,
+                ),
+                (
+                    GeneratorType::END_LOOP_ADDRESS_OFFSET,
+                    GeneratorType::END_LOOP_ADDRESS_COARSE_OFFSET,
+                    region.end_loop as i32 - length,
+                ),
+            ] {
+                push_address_offset(&mut generators, fine_type, coarse_type, offset);
+            }
+            builder.add_instrument_zone(instrument, sample_ids[region.sample], &generators);
+        }
+
+        let preset = builder.add_preset(&self.name, bank, patch);
+        builder.add_preset_zone(preset, instrument, &[]);
+        builder.build()
+    }
+
+    fn create_region(&mut self, opcodes: &Opcodes, sample_id: usize) -> Option<SfzRegion> {
+        let sample = &self.samples[sample_id];
+        let length = sample.channels[0].len() as u32;
+        let loop_points = sample.loop_points;
+        let unity_key = sample.unity_key;
+
+        let key = self.key(opcodes, "key");
+        let key_start = self.key(opcodes, "lokey").or(key).unwrap_or(0);
+        let key_end = self.key(opcodes, "hikey").or(key).unwrap_or(127);
+        let velocity_start = self.number(opcodes, &["lovel"]).unwrap_or(0_f64) as i32;
+        let velocity_end = self.number(opcodes, &["hivel"]).unwrap_or(127_f64) as i32;
+        let root_key = match opcodes.get("pitch_keycenter").map(|value| value.as_str()) {
+            Some("sample") => unity_key.map(|key| key as i32),
+            _ => self.key(opcodes, "pitch_keycenter"),
+        }
+        .or(key)
+        .unwrap_or(60)
+        .clamp(0, 127);
+
+        // The end and the loop end of SFZ include the last point.
+        let start = self.number(opcodes, &["offset"]).unwrap_or(0_f64) as u32;
+        let end = match self.number(opcodes, &["end"]) {
+            Some(end) => (end as u32).saturating_add(1).min(length),
+            None => length,
+        };
+        if end <= start {
+            self.warn("the regions without any sample point were skipped");
+            return None;
+        }
+
+        let start_loop = self.number(opcodes, &["loop_start", "loopstart"]);
+        let end_loop = self.number(opcodes, &["loop_end", "loopend"]);
+        let (mut start_loop, mut end_loop) = match (start_loop, end_loop, loop_points) {
+            (None, None, Some(loop_points)) => loop_points,
+            (start_loop, end_loop, loop_points) => (
+                start_loop.map_or(loop_points.map_or(start, |(start, _)| start), |x| x as u32),
+                end_loop.map_or(loop_points.map_or(end, |(_, end)| end), |x| {
+                    (x as u32).saturating_add(1)
+                }),
+            ),
+        };
+        if !(start <= start_loop && start_loop < end_loop && end_loop <= end) {
+            start_loop = start;
+            end_loop = end;
+        }
+
+        let loop_mode = opcodes.get("loop_mode").or(opcodes.get("loopmode"));
+        let sample_modes = match loop_mode.map(|mode| mode.as_str()) {
+            Some("loop_continuous") => 1,
+            Some("loop_sustain") => 3,
+            Some("no_loop") => 0,
+            Some("one_shot") => {
+                self.warn("the one-shot regions are played as 'no_loop'");
+                0
+            }
+            Some(mode) => {
+                let message = format!("the loop mode '{mode}' is not supported");
+                self.warn(&message);
+                0
+            }
+            None => {
+                if loop_points.is_some() {
+                    1
+                } else {
+                    0
+                }
+            }
+        };
+
+        let mut generators = vec![
+            (
+                GeneratorType::KEY_RANGE,
+                (key_start.clamp(0, 127) | key_end.clamp(0, 127) << 8) as i16,
+            ),
+            (
+                GeneratorType::VELOCITY_RANGE,
+                (velocity_start.clamp(0, 127) | velocity_end.clamp(0, 127) << 8) as i16,
+            ),
+            (GeneratorType::SAMPLE_MODES, sample_modes),
+        ];
+
+        // The tuning in semitones and cents.
+        let transpose = self.number(opcodes, &["transpose"]).unwrap_or(0_f64) as i32;
+        let tune = self.number(opcodes, &["tune"]).unwrap_or(0_f64) as i32;
+        let coarse_tune = transpose.saturating_add(tune / 100);
+        if coarse_tune != 0 {
+            generators.push((
+                GeneratorType::COARSE_TUNE,
+                coarse_tune.clamp(-120, 120) as i16,
+            ));
+        }
+        if tune % 100 != 0 {
+            generators.push((GeneratorType::FINE_TUNE, (tune % 100) as i16));
+        }
+        if let Some(keytrack) = self.number(opcodes, &["pitch_keytrack"]) {
+            let keytrack = keytrack.clamp(-1200_f64, 1200_f64) as i16;
+            generators.push((GeneratorType::SCALE_TUNING, keytrack));
+        }
+
+        // The initial attenuation is scaled by 0.4 when the voice is played, like the EMU synthesizers.
+        let mut decibels = self.number(opcodes, &["volume"]).unwrap_or(0_f64);
+        if let Some(amplitude) = self.number(opcodes, &["amplitude"]) {
+            decibels += 20_f64 * (amplitude.max(0.001_f64) / 100_f64).log10();
+        }
+        if decibels != 0_f64 {
+            let attenuation = (-25_f64 * decibels).round().clamp(-1440_f64, 1440_f64);
+            generators.push((GeneratorType::INITIAL_ATTENUATION, attenuation as i16));
+        }
+        if let Some(pan) = self.number(opcodes, &["pan"]) {
+            let pan = (5_f64 * pan).round().clamp(-500_f64, 500_f64);
+            generators.push((GeneratorType::PAN, pan as i16));
+        }
+
+        for (name, generator_type) in [
+            ("ampeg_delay", GeneratorType::DELAY_VOLUME_ENVELOPE),
+            ("ampeg_attack", GeneratorType::ATTACK_VOLUME_ENVELOPE),
+            ("ampeg_hold", GeneratorType::HOLD_VOLUME_ENVELOPE),
+            ("ampeg_decay", GeneratorType::DECAY_VOLUME_ENVELOPE),
+            ("ampeg_release", GeneratorType::RELEASE_VOLUME_ENVELOPE),
+        ] {
+            if let Some(seconds) = self.number(opcodes, &[name]) {
+                generators.push((generator_type, timecents(seconds)));
+            }
+        }
+        if let Some(sustain) = self.number(opcodes, &["ampeg_sustain"]) {
+            let sustain = sustain.clamp(0_f64, 100_f64) / 100_f64;
+            let attenuation = if sustain > 0_f64 {
+                (-200_f64 * sustain.log10()).round().min(1440_f64)
+            } else {
+                1440_f64
+            };
+            generators.push((GeneratorType::SUSTAIN_VOLUME_ENVELOPE, attenuation as i16));
+        }
+
+        // The filter of the SoundFont is always a low-pass filter.
+        if let Some(fil_type) = opcodes.get("fil_type") {
+            if !fil_type.starts_with("lpf") {
+                let message =
+                    format!("the filter type '{fil_type}' is played as a low-pass filter");
+                self.warn(&message);
+            }
+        }
+        if let Some(cutoff) = self.number(opcodes, &["cutoff"]) {
+            let cents = 1200_f64 * (cutoff.max(1_f64) / 8.176_f64).log2();
+            let cents = cents.round().clamp(1500_f64, 13500_f64);
+            generators.push((GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY, cents as i16));
+        }
+        if let Some(resonance) = self.number(opcodes, &["resonance"]) {
+            let resonance = (10_f64 * resonance).round().clamp(0_f64, 960_f64);
+            generators.push((GeneratorType::INITIAL_FILTER_Q, resonance as i16));
+        }
+
+        // A group which turns itself off is an exclusive class, such as the hi-hats.
+        let group = self.number(opcodes, &["group"]).map(|group| group as i32);
+        let off_by = self.number(opcodes, &["off_by"]).map(|group| group as i32);
+        match (group, off_by) {
+            (Some(group), Some(off_by)) if group == off_by && group > 0 => {
+                generators.push((GeneratorType::EXCLUSIVE_CLASS, group.min(127) as i16));
+            }
+            (_, Some(_)) => {
+                self.warn("the groups turned off by another group are not supported");
+            }
+            _ => (),
+        }
+
+        Some(SfzRegion {
+            sample: sample_id,
+            generators,
+            start,
+            end,
+            start_loop,
+            end_loop,
+            root_key: root_key as u8,
+        })
+    }
+
+    fn check_opcodes(&mut self, opcodes: &Opcodes) {
+        let mut unsupported: Vec<&String> = opcodes
+            .keys()
+            .filter(|name| !SfzInstrument::SUPPORTED_OPCODES.contains(&name.as_str()))
+            .collect();
+        unsupported.sort();
+        for name in unsupported {
+            let message = format!("the unsupported opcode '{name}' was skipped");
+            self.warn(&message);
+        }
+    }
+
+    fn number(&mut self, opcodes: &Opcodes, names: &[&str]) -> Option<f64> {
+        let (name, value) = names
+            .iter()
+            .find_map(|name| opcodes.get(*name).map(|value| (name, value)))?;
+        match value.parse::<f64>() {
+            Ok(value) if value.is_finite() => Some(value),
+            _ => {
+                let message =
+                    format!("the invalid value '{value}' of the opcode '{name}' was skipped");
+                self.warn(&message);
+                None
+            }
+        }
+    }
+
+    // A key is either a number or a note name such as 'c#4', where 'c4' is 60.
+    fn key(&mut self, opcodes: &Opcodes, name: &str) -> Option<i32> {
+        let value = opcodes.get(name)?;
+        if let Ok(key) = value.parse::<i32>() {
+            return Some(key);
+        }
+
+        let lower = value.to_ascii_lowercase();
+        let mut chars = lower.chars();
+        let mut key = match chars.next() {
+            Some('c') => 0,
+            Some('d') => 2,
+            Some('e') => 4,
+            Some('f') => 5,
+            Some('g') => 7,
+            Some('a') => 9,
+            Some('b') => 11,
+            _ => -1000,
+        };
+        let rest = chars.as_str();
+        let octave = if let Some(rest) = rest.strip_prefix('#') {
+            key += 1;
+            rest
+        } else if let Some(rest) = rest.strip_prefix('b').filter(|rest| !rest.is_empty()) {
+            key -= 1;
+            rest
+        } else {
+            rest
+        };
+        let key = octave
+            .parse::<i32>()
+            .ok()
+            .filter(|_| key >= -1)
+            .and_then(|octave| octave.checked_add(1)?.checked_mul(12)?.checked_add(key));
+        match key {
+            Some(key) => Some(key),
+            None => {
+                let message =
+                    format!("the invalid value '{value}' of the opcode '{name}' was skipped");
+                self.warn(&message);
+                None
+            }
+        }
+    }
+
+    fn warn(&mut self, message: &str) {
+        if !self.warnings.iter().any(|warning| warning == message) {
+            self.warnings.push(message.to_string());
+        }
+    }
+
+    fn read_sample(path: &Path) -> Result<SfzSample, String> {
+        let data = fs::read(path).map_err(|err| err.to_string())?;
+        let name = path
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+
+        let mut sample = if data.starts_with(b"RIFF") {
+            read_wav(&data)?
+        } else if data.starts_with(b"fLaC") {
+            read_flac(&data)?
+        } else {
+            return Err("only WAV and FLAC files are supported".to_string());
+        };
+        if sample.channels.is_empty() || sample.channels[0].is_empty() || sample.sample_rate == 0 {
+            return Err("the file has no sample".to_string());
+        }
+
+        sample.name = name;
+        Ok(sample)
+    }
+
+    /// Gets the name of the instrument, which is the name of the SFZ file without the extension.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the number of the regions which will be played.
+    pub fn get_region_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Gets the problems that were tolerated while loading the instrument,
+    /// such as the unsupported opcodes and the samples which could not be read.
+    pub fn get_warnings(&self) -> &[String] {
+        &self.warnings[..]
+    }
+}
+
+struct Parser<'a> {
+    directory: &'a Path,
+    defines: Vec<(String, String)>,
+    section: Section,
+    control: Opcodes,
+    global: Opcodes,
+    master: Opcodes,
+    group: Opcodes,
+    region: Option<Opcodes>,
+    regions: Vec<Opcodes>,
+    in_comment: bool,
+    warnings: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(directory: &'a Path) -> Self {
+        Self {
+            directory,
+            defines: Vec::new(),
+            section: Section::None,
+            control: Opcodes::new(),
+            global: Opcodes::new(),
+            master: Opcodes::new(),
+            group: Opcodes::new(),
+            region: None,
+            regions: Vec::new(),
+            in_comment: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn parse_text(&mut self, text: &str, depth: usize) -> Result<(), SfzError> {
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = self.strip_comments(line);
+            let trimmed = line.trim();
+
+            if let Some(rest) = trimmed.strip_prefix("#define") {
+                let mut parts = rest.split_whitespace();
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if name.starts_with('$') => {
+                        self.defines.retain(|(other, _)| other != name);
+                        self.defines.push((name.to_string(), value.to_string()));
+                        // The longer names are replaced first, so that '$A' does not break '$AB'.
+                        self.defines
+                            .sort_by_key(|(name, _)| usize::MAX - name.len());
+                    }
+                    _ => return Err(SfzError::InvalidDirective { line: line_number }),
+                }
+                continue;
+            }
+
+            let mut line = trimmed.to_string();
+            for (name, value) in &self.defines {
+                line = line.replace(name.as_str(), value);
+            }
+
+            if let Some(rest) = line.strip_prefix("#include") {
+                let file = rest.trim().trim_matches('"');
+                if file.is_empty() || depth >= SfzInstrument::MAX_INCLUDE_DEPTH {
+                    return Err(SfzError::InvalidDirective { line: line_number });
+                }
+                let text = read_text(&self.directory.join(file.replace('\\', "/")))?;
+                self.parse_text(&text, depth + 1)?;
+                continue;
+            }
+
+            let mut rest = line.as_str();
+            while let Some(open) = rest.find('<') {
+                self.parse_opcodes(&rest[..open], line_number)?;
+                let close = match rest[open..].find('>') {
+                    Some(close) => open + close,
+                    None => return Err(SfzError::InvalidHeader { line: line_number }),
+                };
+                self.begin_header(rest[open + 1..close].trim());
+                rest = &rest[close + 1..];
+            }
+            self.parse_opcodes(rest, line_number)?;
+        }
+
+        Ok(())
+    }
+
+    fn strip_comments(&mut self, line: &str) -> String {
+        let mut output = String::new();
+        let mut rest = line;
+        loop {
+            if self.in_comment {
+                match rest.find("*/") {
+                    Some(end) => {
+                        self.in_comment = false;
+                        rest = &rest[end + 2..];
+                    }
+                    None => return output,
+                }
+            }
+
+            let line_comment = rest.find("//");
+            let block_comment = rest.find("/*");
+            match (line_comment, block_comment) {
+                (Some(line_comment), Some(block_comment)) if block_comment < line_comment => {
+                    output.push_str(&rest[..block_comment]);
+                    output.push(' ');
+                    self.in_comment = true;
+                    rest = &rest[block_comment + 2..];
+                }
+                (None, Some(block_comment)) => {
+                    output.push_str(&rest[..block_comment]);
+                    output.push(' ');
+                    self.in_comment = true;
+                    rest = &rest[block_comment + 2..];
+                }
+                (Some(line_comment), _) => {
+                    output.push_str(&rest[..line_comment]);
+                    return output;
+                }
+                (None, None) => {
+                    output.push_str(rest);
+                    return output;
+                }
+            }
+        }
+    }
+
+    // The value of an opcode lasts until the next opcode, so that a sample path may contain spaces.
+    fn parse_opcodes(&mut self, text: &str, line: usize) -> Result<(), SfzError> {
+        let bytes = text.as_bytes();
+        let is_name = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'_';
+
+        let mut names: Vec<(usize, usize)> = Vec::new();
+        for (equals, _) in bytes.iter().enumerate().filter(|(_, byte)| **byte == b'=') {
+            let start = bytes[..equals]
+                .iter()
+                .rposition(|byte| !is_name(byte))
+                .map_or(0, |position| position + 1);
+            if start == equals {
+                return Err(SfzError::InvalidOpcode { line });
+            }
+            names.push((start, equals));
+        }
+
+        let first = names.first().map_or(text.len(), |(start, _)| *start);
+        if !text[..first].trim().is_empty() {
+            return Err(SfzError::InvalidOpcode { line });
+        }
+
+        for (index, (start, equals)) in names.iter().enumerate() {
+            let end = names.get(index + 1).map_or(text.len(), |(start, _)| *start);
+            let name = &text[*start..*equals];
+            let value = text[equals + 1..end].trim();
+            self.set_opcode(name, value);
+        }
+
+        Ok(())
+    }
+
+    fn set_opcode(&mut self, name: &str, value: &str) {
+        let opcodes = match self.section {
+            Section::Control => &mut self.control,
+            Section::Global => &mut self.global,
+            Section::Master => &mut self.master,
+            Section::Group => &mut self.group,
+            Section::Region => self.region.get_or_insert_with(Opcodes::new),
+            Section::Unknown => return,
+            Section::None => {
+                let message = "the opcodes before the first header were skipped".to_string();
+                if !self.warnings.contains(&message) {
+                    self.warnings.push(message);
+                }
+                return;
+            }
+        };
+        opcodes.insert(name.to_string(), value.to_string());
+    }
+
+    fn begin_header(&mut self, name: &str) {
+        self.finish_region();
+
+        self.section = match name {
+            "control" => Section::Control,
+            "global" => {
+                self.global.clear();
+                self.master.clear();
+                self.group.clear();
+                Section::Global
+            }
+            "master" => {
+                self.master.clear();
+                self.group.clear();
+                Section::Master
+            }
+            "group" => {
+                self.group.clear();
+                Section::Group
+            }
+            "region" => {
+                self.region = Some(Opcodes::new());
+                Section::Region
+            }
+            _ => {
+                let message = format!("the unsupported header '<{name}>' was skipped");
+                if !self.warnings.contains(&message) {
+                    self.warnings.push(message);
+                }
+                Section::Unknown
+            }
+        };
+    }
+
+    // A region inherits the opcodes of the headers above it.
+    fn finish_region(&mut self) {
+        if let Some(region) = self.region.take() {
+            let mut opcodes = self.global.clone();
+            opcodes.extend(self.master.clone());
+            opcodes.extend(self.group.clone());
+            opcodes.extend(region);
+
+            if let Some(sample) = opcodes.get_mut("sample") {
+                if let Some(default_path) = self.control.get("default_path") {
+                    *sample = format!("{default_path}{sample}");
+                }
+            }
+
+            self.regions.push(opcodes);
+        }
+    }
+}
+
+// The SFZ files are often in Latin-1 or another legacy encoding,
+// so the invalid UTF-8 in the names and comments is replaced instead of failing the file.
+fn read_text(path: &Path) -> Result<String, SfzError> {
+    let data = fs::read(path)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn read_wav(data: &[u8]) -> Result<SfzSample, String> {
+    if data.len() < 12 || &data[8..12] != b"WAVE" {
+        return Err("the file is not a WAV file".to_string());
+    }
+
+    let u16_at = |bytes: &[u8], i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |bytes: &[u8], i: usize| {
+        u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+    };
+
+    let mut format: Option<(u16, usize, u32, usize)> = None;
+    let mut samples: Option<&[u8]> = None;
+    let mut loop_points = None;
+    let mut unity_key = None;
+
+    let mut position = 12;
+    while position + 8 <= data.len() {
+        let id = &data[position..position + 4];
+        let size = u32_at(data, position + 4) as usize;
+        let body = &data[position + 8..(position + 8).saturating_add(size).min(data.len())];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let mut tag = u16_at(body, 0);
+                // WAVE_FORMAT_EXTENSIBLE keeps the actual format in the sub-format GUID.
+                if tag == 0xFFFE && body.len() >= 26 {
+                    tag = u16_at(body, 24);
+                }
+                let channels = u16_at(body, 2) as usize;
+                let sample_rate = u32_at(body, 4);
+                let bits = u16_at(body, 14) as usize;
+                format = Some((tag, channels, sample_rate, bits.div_ceil(8)));
+            }
+            b"data" => samples = Some(body),
+            b"smpl" if body.len() >= 36 => {
+                let key = u32_at(body, 12);
+                if key < 128 {
+                    unity_key = Some(key as u8);
+                }
+                // The loop end of the 'smpl' chunk includes the last point.
+                if u32_at(body, 28) > 0 && body.len() >= 60 {
+                    loop_points = Some((u32_at(body, 44), u32_at(body, 48).saturating_add(1)));
+                }
+            }
+            _ => (),
+        }
+        position += 8 + size + (size & 1);
+    }
+
+    let (tag, channel_count, sample_rate, width) =
+        format.ok_or("the 'fmt ' chunk was not found")?;
+    let samples = samples.ok_or("the 'data' chunk was not found")?;
+    let channels = decode_pcm(tag, width, channel_count, samples)?;
+
+    Ok(SfzSample {
+        name: String::new(),
+        channels,
+        sample_rate,
+        loop_points,
+        unity_key,
+    })
+}
+
+// Decodes the sample points of a WAV 'data' chunk to 16 bits, with the format tag and the bytes per point
+// of its 'fmt ' chunk. Only the first two channels are kept.
+pub(crate) fn decode_pcm(
+    tag: u16,
+    width: usize,
+    channel_count: usize,
+    samples: &[u8],
+) -> Result<Vec<Vec<i16>>, String> {
+    if channel_count == 0 {
+        return Err("the file has no channel".to_string());
+    }
+
+    let decode: fn(&[u8]) -> i16 = match (tag, width) {
+        (1, 1) => |x| (x[0] as i16 - 128) << 8,
+        (1, 2) => |x| i16::from_le_bytes([x[0], x[1]]),
+        (1, 3) => |x| i16::from_le_bytes([x[1], x[2]]),
+        (1, 4) => |x| i16::from_le_bytes([x[2], x[3]]),
+        (3, 4) => |x| {
+            let value = f32::from_le_bytes([x[0], x[1], x[2], x[3]]);
+            (32767_f32 * value.clamp(-1_f32, 1_f32)) as i16
+        },
+        (3, 8) => |x| {
+            let value = f64::from_le_bytes(x[..8].try_into().unwrap());
+            (32767_f64 * value.clamp(-1_f64, 1_f64)) as i16
+        },
+        _ => return Err("the sample format is not supported".to_string()),
+    };
+
+    let mut channels = vec![Vec::new(); channel_count.min(2)];
+    for frame in samples.chunks_exact(width * channel_count) {
+        for (channel, output) in channels.iter_mut().enumerate() {
+            output.push(decode(&frame[width * channel..]));
+        }
+    }
+
+    Ok(channels)
+}
+
+#[cfg(feature = "flac")]
+fn read_flac(data: &[u8]) -> Result<SfzSample, String> {
+    let mut reader = claxon::FlacReader::new(data).map_err(|err| err.to_string())?;
+    let info = reader.streaminfo();
+    let channel_count = info.channels as usize;
+    let shift = info.bits_per_sample as i32 - 16;
+
+    // Only the first two channels are used.
+    let mut channels = vec![Vec::new(); channel_count.min(2)];
+    for (index, value) in reader.samples().enumerate() {
+        let value = value.map_err(|err| err.to_string())?;
+        if let Some(output) = channels.get_mut(index % channel_count) {
+            let value = if shift >= 0 {
+                value >> shift
+            } else {
+                value << -shift
+            };
+            output.push(value as i16);
+        }
+    }
+
+    Ok(SfzSample {
+        name: String::new(),
+        channels,
+        sample_rate: info.sample_rate,
+        loop_points: None,
+        unity_key: None,
+    })
+}
+
+#[cfg(not(feature = "flac"))]
+fn read_flac(_data: &[u8]) -> Result<SfzSample, String> {
+    Err("FLAC samples require the 'flac' feature".to_string())
+}
+
+// The time in seconds as timecents, where zero or less is the shortest time.
+fn timecents(seconds: f64) -> i16 {
+    if seconds > 0_f64 {
+        (1200_f64 * seconds.log2())
+            .round()
+            .clamp(-12000_f64, 8000_f64) as i16
+    } else {
+        -12000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::loop_mode::LoopMode;
+    use crate::synthesizer::Synthesizer;
+    use crate::synthesizer_settings::SynthesizerSettings;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    // A 16-bit WAV file of a square wave, with a 'smpl' chunk if the loop is given.
+    fn build_wav(channels: u16, length: usize, smpl: Option<(u32, u32, u32)>) -> Vec<u8> {
+        let mut format = Vec::new();
+        format.extend_from_slice(&1_u16.to_le_bytes());
+        format.extend_from_slice(&channels.to_le_bytes());
+        format.extend_from_slice(&22050_u32.to_le_bytes());
+        format.extend_from_slice(&(22050_u32 * 2 * channels as u32).to_le_bytes());
+        format.extend_from_slice(&(2 * channels).to_le_bytes());
+        format.extend_from_slice(&16_u16.to_le_bytes());
+
+        let mut samples = Vec::new();
+        for i in 0..length * channels as usize {
+            let value: i16 = if (i / 8) % 2 == 0 { 10000 } else { -10000 };
+            samples.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut body = b"WAVE".to_vec();
+        body.extend(chunk(b"fmt ", &format));
+        if let Some((key, start, end)) = smpl {
+            let mut record = vec![0_u8; 36];
+            record[12..16].copy_from_slice(&key.to_le_bytes());
+            record[28..32].copy_from_slice(&1_u32.to_le_bytes());
+            record.extend_from_slice(&[0; 8]);
+            record.extend_from_slice(&start.to_le_bytes());
+            record.extend_from_slice(&end.to_le_bytes());
+            record.extend_from_slice(&[0; 8]);
+            body.extend(chunk(b"smpl", &record));
+        }
+        body.extend(chunk(b"data", &samples));
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn test_sfz_instrument() {
+        let directory = std::env::temp_dir().join(format!("rustysynth-sfz-{}", std::process::id()));
+        fs::create_dir_all(directory.join("samples")).unwrap();
+        fs::write(
+            directory.join("samples/low note.wav"),
+            build_wav(1, 1000, Some((57, 100, 899))),
+        )
+        .unwrap();
+        fs::write(directory.join("samples/high.wav"), build_wav(2, 1000, None)).unwrap();
+        fs::write(
+            directory.join("groups.sfz"),
+            "<group> lokey=c5 hikey=127 volume=-6\n",
+        )
+        .unwrap();
+
+        let text = "
+            // The samples are in a sub-directory.
+            <control> default_path=samples\\
+            #define $KEY 59
+            <global> ampeg_release=0.5 seq_length=2
+            <group> ampeg_sustain=50 /* A comment
+                which spans the lines. */
+            <region> sample=low note.wav lokey=0 hikey=$KEY pitch_keycenter=sample
+            <region> sample=missing.wav key=60
+            #include \"groups.sfz\"
+            <region> sample=high.wav pitch_keycenter=d5 offset=10 end=499 loop_mode=loop_sustain
+                     loop_start=20 loop_end=399 group=1 off_by=1
+        ";
+        let instrument = SfzInstrument::parse(text, &directory).unwrap();
+        fs::remove_dir_all(&directory).ok();
+        assert_eq!(instrument.get_region_count(), 2);
+        let warnings = instrument.get_warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("seq_length"));
+        assert!(warnings[1].contains("missing.wav"));
+
+        let sound_font = Arc::new(instrument.to_sound_font(0, 5).unwrap());
+        assert!(sound_font.get_warnings().is_empty());
+        assert_eq!(
+            sound_font.get_preset(0, 5).unwrap().get_name(),
+            "Instrument"
+        );
+        let instrument = &sound_font.get_instruments()[0];
+        let regions = instrument.get_regions();
+        assert_eq!(regions.len(), 2);
+
+        // The key, the loop and the envelope of the mono sample come from the WAV file and the headers.
+        let low = &regions[0];
+        assert_eq!(
+            (low.get_key_range_start(), low.get_key_range_end()),
+            (0, 59)
+        );
+        assert_eq!(low.get_root_key(), 57);
+        assert_eq!(low.get_sample_modes(), LoopMode::Continuous);
+        assert_eq!(low.get_sample_end() - low.get_sample_start(), 1000);
+        assert_eq!(low.get_sample_start_loop() - low.get_sample_start(), 100);
+        assert_eq!(low.get_sample_end_loop() - low.get_sample_start(), 900);
+        assert!((low.get_release_volume_envelope() - 0.5_f32).abs() < 0.01_f32);
+        assert!((low.get_sustain_volume_envelope() - 6.0_f32).abs() < 0.1_f32);
+        assert_eq!(low.get_initial_attenuation(), 0_f32);
+
+        // The included group and the positions of the stereo sample, whose right half is linked.
+        let high = &regions[1];
+        assert_eq!(
+            (high.get_key_range_start(), high.get_key_range_end()),
+            (72, 127)
+        );
+        assert_eq!(high.get_root_key(), 74);
+        assert_eq!(high.get_sample_modes(), LoopMode::LoopUntilNoteOff);
+        assert_eq!(high.get_initial_attenuation(), 15_f32);
+        assert_eq!(high.get_exclusive_class(), 1);
+        let header = &sound_font.get_sample_headers()[high.get_sample_id()];
+        assert_eq!(
+            (
+                high.get_sample_end() - high.get_sample_start(),
+                header.get_sample_rate()
+            ),
+            (490, 22050)
+        );
+        assert_eq!(high.get_sample_start_loop() - high.get_sample_start(), 10);
+        assert_eq!(high.get_sample_end_loop() - high.get_sample_start(), 390);
+        assert!(instrument.linked_regions[1].is_some());
+
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.process_midi_message(0, 0xC0, 5, 0);
+        synthesizer.note_on(0, 80, 100);
+        let mut left = vec![0_f32; 256];
+        let mut right = vec![0_f32; 256];
+        synthesizer.render(&mut left, &mut right);
+        assert!(left.iter().any(|&x| x != 0_f32));
+        assert!(right.iter().any(|&x| x != 0_f32));
+    }
+
+    // Parses the text into the opcodes of each region, with the inherited ones.
+    fn parse_regions(text: &str, directory: &Path) -> Result<Vec<Opcodes>, SfzError> {
+        let mut parser = Parser::new(directory);
+        parser.parse_text(text, 0)?;
+        parser.finish_region();
+        Ok(parser.regions)
+    }
+
+    #[test]
+    fn test_define() {
+        let text = "
+            #define $A 10
+            #define $AB 20
+            <region> lokey=$A hikey=$AB
+            #define $A 30
+            <region> lokey=$A
+        ";
+        let regions = parse_regions(text, Path::new("")).unwrap();
+        assert_eq!(regions[0]["lokey"], "10");
+        assert_eq!(regions[0]["hikey"], "20");
+        assert_eq!(regions[1]["lokey"], "30");
+
+        for text in ["#define", "#define $A", "#define A 10"] {
+            assert!(matches!(
+                parse_regions(text, Path::new("")),
+                Err(SfzError::InvalidDirective { line: 1 })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_include() {
+        let directory =
+            std::env::temp_dir().join(format!("rustysynth-sfz-include-{}", std::process::id()));
+        fs::create_dir_all(directory.join("parts")).unwrap();
+        fs::write(directory.join("parts/inner.sfz"), "<region> key=62\n").unwrap();
+        fs::write(
+            directory.join("outer.sfz"),
+            "#define $KEY 61\n<region> key=$KEY\n#include \"parts\\inner.sfz\"\n",
+        )
+        .unwrap();
+        fs::write(directory.join("self.sfz"), "#include \"self.sfz\"\n").unwrap();
+        // A Latin-1 name, which is not valid UTF-8.
+        fs::write(
+            directory.join("latin1.sfz"),
+            b"<region> key=63 // Caf\xE9\n",
+        )
+        .unwrap();
+
+        // The included files are relative to the instrument, and see its defines.
+        let text = "#include \"outer.sfz\"\n<region> key=$KEY\n#include \"latin1.sfz\"";
+        let regions = parse_regions(text, &directory);
+        let recursive = parse_regions("#include \"self.sfz\"", &directory);
+        let missing = parse_regions("#include \"missing.sfz\"", &directory);
+        fs::remove_dir_all(&directory).ok();
+
+        let regions = regions.unwrap();
+        let keys: Vec<&str> = regions
+            .iter()
+            .map(|region| region["key"].as_str())
+            .collect();
+        assert_eq!(keys, vec!["61", "62", "61", "63"]);
+        assert!(matches!(
+            recursive,
+            Err(SfzError::InvalidDirective { line: 1 })
+        ));
+        assert!(matches!(missing, Err(SfzError::IoError(_))));
+    }
+
+    #[test]
+    fn test_header_inheritance() {
+        let text = "
+            <control> default_path=samples/
+            <global> volume=-1 pan=10
+            <master> volume=-2 tune=5
+            <group> volume=-3
+            <region> sample=a.wav
+            <region> sample=b.wav volume=-4
+            <group> lokey=40
+            <region> sample=c.wav
+            <master> lokey=50
+            <region> sample=d.wav
+            <global> hikey=60
+            <region> sample=e.wav
+        ";
+        let regions = parse_regions(text, Path::new("")).unwrap();
+        let get = |region: usize, name: &str| regions[region].get(name).map(String::as_str);
+
+        // Each header overrides the ones above it, and the region overrides them all.
+        assert_eq!(get(0, "sample"), Some("samples/a.wav"));
+        assert_eq!(get(0, "volume"), Some("-3"));
+        assert_eq!(get(0, "pan"), Some("10"));
+        assert_eq!(get(0, "tune"), Some("5"));
+        assert_eq!(get(1, "volume"), Some("-4"));
+
+        // A new group replaces the previous group, but keeps the master and the global.
+        assert_eq!(get(2, "volume"), Some("-2"));
+        assert_eq!(get(2, "lokey"), Some("40"));
+
+        // A new master ends the group, and a new global ends the master and the group.
+        assert_eq!(get(3, "volume"), Some("-1"));
+        assert_eq!(get(3, "tune"), None);
+        assert_eq!(get(3, "lokey"), Some("50"));
+        assert_eq!(get(4, "volume"), None);
+        assert_eq!(get(4, "lokey"), None);
+        assert_eq!(get(4, "hikey"), Some("60"));
+        assert_eq!(get(4, "sample"), Some("samples/e.wav"));
+    }
+
+    #[test]
+    fn test_out_of_range_values() {
+        let directory =
+            std::env::temp_dir().join(format!("rustysynth-sfz-range-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("a.wav"), build_wav(1, 1000, None)).unwrap();
+
+        // The values past the ranges of the integers are clamped or skipped instead of overflowing.
+        let text = "
+            <region> sample=a.wav lokey=c178956970 hikey=b9999999999 loop_end=4294967295
+                     transpose=2147483647 tune=2147483647
+            <region> sample=a.wav key=-1e30 lovel=1e30 offset=-5 end=1e30 loop_start=-1e30
+                     transpose=-2147483648 tune=-2147483648
+        ";
+        let instrument = SfzInstrument::parse(text, &directory).unwrap();
+        fs::remove_dir_all(&directory).ok();
+        assert_eq!(instrument.get_region_count(), 2);
+        let warnings = instrument.get_warnings();
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.contains("'c178956970'")));
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.contains("'b9999999999'")));
+
+        let sound_font = instrument.to_sound_font(0, 0).unwrap();
+        let regions = sound_font.get_instruments()[0].get_regions();
+        let high = &regions[0];
+        assert_eq!(
+            (high.get_key_range_start(), high.get_key_range_end()),
+            (0, 127)
+        );
+        assert_eq!(high.get_coarse_tune(), 120);
+        assert_eq!(high.get_sample_end_loop(), high.get_sample_end());
+        let low = &regions[1];
+        assert_eq!(low.get_coarse_tune(), -120);
+        assert_eq!(low.get_sample_end() - low.get_sample_start(), 1000);
+    }
+}
@@ -1,32 +1,135 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
+use core::mem;
+#[cfg(feature = "mmap")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::Write;
+#[cfg(feature = "mmap")]
+use std::path::Path;
 
 use crate::binary_reader::BinaryReader;
+#[cfg(feature = "std")]
+use crate::dls_reader::DlsReader;
 use crate::error::SoundFontError;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
 use crate::four_cc::FourCC;
+use crate::generator_set::GeneratorSet;
 use crate::instrument::Instrument;
+use crate::instrument_region::InstrumentRegion;
+use crate::io;
+use crate::io::Read;
 use crate::preset::Preset;
+use crate::preset_region::PresetRegion;
+use crate::region_pair::RegionPair;
 use crate::sample_header::SampleHeader;
+#[cfg(feature = "std")]
+use crate::soundfont_builder::SoundFontBuilder;
 use crate::soundfont_info::SoundFontInfo;
 use crate::soundfont_parameters::SoundFontParameters;
 use crate::soundfont_sampledata::SoundFontSampleData;
+use crate::soundfont_sampledata::WaveData;
+use crate::synthesizer::Synthesizer;
+use crate::synthesizer_settings::SynthesizerSettings;
 use crate::LoopMode;
 
-/// Parsed SoundFont (.sf2) containing samples, instruments, and presets.
+/// Parsed SoundFont (.sf2, or .sf3 with the `sf3` feature) containing samples, instruments, and presets.
 #[derive(Debug)]
 #[non_exhaustive]
 pub struct SoundFont {
     pub(crate) info: SoundFontInfo,
     pub(crate) bits_per_sample: i32,
-    pub(crate) wave_data: Vec<i16>,
+    pub(crate) wave_data: WaveData,
     pub(crate) sample_headers: Vec<SampleHeader>,
     pub(crate) presets: Vec<Preset>,
     pub(crate) instruments: Vec<Instrument>,
+    // Maps the bank and patch numbers to the index of the preset.
+    pub(crate) preset_lookup: BTreeMap<(i32, i32), usize>,
+    pub(crate) warnings: Vec<String>,
 }
 
 impl SoundFont {
+    #[cfg(feature = "std")]
     pub fn new<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
+        SoundFont::read(reader, SoundFontSampleData::new)
+    }
+
+    /// Loads the SoundFont from the bytes of a .sf2 file, as `new` does.
+    ///
+    /// Without the `std` feature, this replaces `new`,
+    /// such as for a SoundFont embedded in the firmware with `include_bytes!`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SoundFontError> {
+        SoundFont::read(&mut &data[..], SoundFontSampleData::new)
+    }
+
+    /// Loads a DLS (Downloadable Sounds) level 1 or 2 file as a SoundFont.
+    ///
+    /// Each instrument becomes a preset at its bank and program, with the drum instruments in the bank 128,
+    /// and the regions and their articulation are converted into the instrument zones and generators.
+    /// The connections which the SoundFont cannot express, such as the velocity to the attack time,
+    /// are skipped with a warning. The waves must be 8 or 16-bit PCM.
+    #[cfg(feature = "std")]
+    pub fn from_dls<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
+        DlsReader::read(reader)
+    }
+
+    /// Writes the SoundFont as a .sf2 file.
+    ///
+    /// The file plays the same as this one, and is written as `SoundFontBuilder::from_sound_font` would write it,
+    /// so the presets can be trimmed by writing a builder instead.
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        SoundFontBuilder::from_sound_font(self).write(writer)
+    }
+
+    /// Loads the SoundFont from the file, reading the samples in place from a memory map
+    /// instead of decoding them into memory.
+    ///
+    /// Only the presets, the instruments, and the sample headers are parsed when loading,
+    /// and the operating system reads the pages of the samples from the disk as the voices play them,
+    /// so that a large SoundFont loads quickly and takes only the memory of the samples in use.
+    /// The first note of a sample may take longer while its pages are read.
+    /// A SoundFont3, whose samples must be decompressed, is loaded into memory as `new` does,
+    /// and so are the samples on a big-endian target.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the SoundFont, or a synthesizer using it, exists.
+    /// The samples are read without a copy, so a change to the file changes the sound,
+    /// and truncating it makes reading the samples crash the process.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Self, SoundFontError> {
+        let file = File::open(path)?;
+        let map = Arc::new(memmap2::Mmap::map(&file)?);
+
+        let mut reader = std::io::Cursor::new(&map[..]);
+        SoundFont::read(&mut reader, |reader, end, is_sf3, warnings| {
+            // The sdta list is passed over without reading the samples.
+            let start = reader.position() as usize;
+            reader.set_position((start + end) as u64);
+            SoundFontSampleData::map_samples(&map, start, end, is_sf3, warnings)
+        })
+    }
+
+    // Reads the SoundFont, where the sdta list is read by the function
+    // from the reader, with the size of the list and whether the SoundFont is a SoundFont3.
+    fn read<R, F>(reader: &mut R, read_samples: F) -> Result<Self, SoundFontError>
+    where
+        R: Read,
+        F: FnOnce(
+            &mut R,
+            usize,
+            bool,
+            &mut Vec<String>,
+        ) -> Result<SoundFontSampleData, SoundFontError>,
+    {
         let chunk_id = BinaryReader::read_four_cc(reader)?;
         if chunk_id != b"RIFF" {
             return Err(SoundFontError::RiffChunkNotFound);
@@ -42,44 +145,98 @@ impl SoundFont {
             });
         }
 
-        let info = SoundFontInfo::new(reader)?;
-        let sample_data = SoundFontSampleData::new(reader)?;
-        let parameters = SoundFontParameters::new(reader)?;
+        let mut warnings: Vec<String> = Vec::new();
+
+        // The INFO list is optional, but the sdta and pdta lists are not.
+        let (mut list_type, mut end) =
+            SoundFont::read_list_header(reader, &[b"INFO", b"sdta", b"pdta"], &mut warnings)?;
+        let info = if list_type == b"INFO" {
+            let info = SoundFontInfo::new(reader, end, &mut warnings)?;
+            (list_type, end) =
+                SoundFont::read_list_header(reader, &[b"sdta", b"pdta"], &mut warnings)?;
+            info
+        } else {
+            warnings.push("the INFO list was not found".to_string());
+            SoundFontInfo::empty()
+        };
+
+        if list_type != b"sdta" {
+            return Err(SoundFontError::InvalidListChunkType {
+                expected: FourCC::from_bytes(*b"sdta"),
+                actual: list_type,
+            });
+        }
+        let is_sf3 = info.version.major == 3;
+        let mut sample_data = read_samples(reader, end, is_sf3, &mut warnings)?;
+
+        let (_, end) = SoundFont::read_list_header(reader, &[b"pdta"], &mut warnings)?;
+        let parameters = SoundFontParameters::new(reader, end, &mut sample_data, &mut warnings)?;
+
+        let mut preset_lookup: BTreeMap<(i32, i32), usize> = BTreeMap::new();
+        for (i, preset) in parameters.presets.iter().enumerate() {
+            preset_lookup.insert((preset.bank_number, preset.patch_number), i);
+        }
 
-        let sound_font = Self {
+        let mut sound_font = Self {
             info,
             bits_per_sample: sample_data.bits_per_sample,
             wave_data: sample_data.wave_data,
             sample_headers: parameters.sample_headers,
             presets: parameters.presets,
             instruments: parameters.instruments,
+            preset_lookup,
+            warnings,
         };
 
         sound_font.sanity_check()?;
+        sound_font.drop_invalid_linked_regions();
 
         Ok(sound_font)
     }
 
+    // Reads chunks until a LIST chunk of one of the given types is found,
+    // and returns its type and the size of its body.
+    // Any other chunk in between is skipped, since it cannot be interpreted anyway.
+    fn read_list_header<R: Read>(
+        reader: &mut R,
+        list_types: &[&[u8; 4]],
+        warnings: &mut Vec<String>,
+    ) -> Result<(FourCC, usize), SoundFontError> {
+        loop {
+            let chunk_id = match BinaryReader::read_four_cc(reader) {
+                Ok(value) => value,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(SoundFontError::ListChunkNotFound)
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let size = BinaryReader::read_u32(reader)? as usize;
+
+            if chunk_id != b"LIST" {
+                // RIFF chunks are padded to an even size.
+                BinaryReader::discard_data(reader, size + (size & 1))?;
+                warnings.push(format!("the unknown '{chunk_id}' chunk was skipped"));
+                continue;
+            }
+
+            let list_type = BinaryReader::read_four_cc(reader)?;
+            let end = size.saturating_sub(4);
+            if list_types.iter().any(|expected| list_type == *expected) {
+                return Ok((list_type, end));
+            }
+
+            BinaryReader::discard_data(reader, end)?;
+            warnings.push(format!("the unknown '{list_type}' list was skipped"));
+        }
+    }
+
     fn sanity_check(&self) -> Result<(), SoundFontError> {
         // https://github.com/sinshu/rustysynth/issues/22
         // https://github.com/sinshu/rustysynth/issues/33
         // https://github.com/sinshu/rustysynth/pull/51
         for instrument in &self.instruments {
             for region in &instrument.regions {
-                let start = region.get_sample_start();
-                let end = region.get_sample_end();
-                let start_loop = region.get_sample_start_loop();
-                let end_loop = region.get_sample_end_loop();
-                let loop_mode = region.get_sample_modes();
-
-                if start < 0
-                    || start_loop < 0
-                    || end as usize >= self.wave_data.len()
-                    || end_loop as usize >= self.wave_data.len()
-                    || end <= start
-                    || end_loop < start_loop
-                    || (loop_mode != LoopMode::NoLoop && start_loop >= end_loop)
-                {
+                if !self.is_region_valid(region) {
                     return Err(SoundFontError::SanityCheckFailed);
                 }
             }
@@ -88,6 +245,53 @@ impl SoundFont {
         Ok(())
     }
 
+    fn is_region_valid(&self, region: &InstrumentRegion) -> bool {
+        let start = region.get_sample_start();
+        let end = region.get_sample_end();
+        let start_loop = region.get_sample_start_loop();
+        let end_loop = region.get_sample_end_loop();
+        let loop_mode = region.get_sample_modes();
+
+        !(start < 0
+            || start_loop < 0
+            || end as usize >= self.wave_data.len()
+            || end_loop as usize >= self.wave_data.len()
+            || end <= start
+            || end_loop < start_loop
+            || (loop_mode != LoopMode::NoLoop && start_loop >= end_loop))
+    }
+
+    // The other half of a linked stereo sample is only an addition,
+    // so a broken one is skipped instead of failing the load.
+    fn drop_invalid_linked_regions(&mut self) {
+        let mut instruments = mem::take(&mut self.instruments);
+        for instrument in &mut instruments {
+            for linked_region in &mut instrument.linked_regions {
+                if let Some(region) = linked_region.as_ref() {
+                    if !self.is_region_valid(region) {
+                        self.warnings.push(format!(
+                            "the linked stereo sample {} of the instrument '{}' was skipped",
+                            region.get_sample_id(),
+                            instrument.name
+                        ));
+                        *linked_region = None;
+                    }
+                }
+            }
+        }
+        self.instruments = instruments;
+    }
+
+    /// Gets the problems that were tolerated while loading the SoundFont.
+    ///
+    /// Unknown chunks, and optional chunks that are truncated or malformed, are skipped
+    /// instead of failing the load, and each of them is reported here.
+    /// Missing or broken sample data and preset data are still an error.
+    /// The list is empty for a well-formed SoundFont.
+    pub fn get_warnings(&self) -> &[String] {
+        &self.warnings[..]
+    }
+
     pub fn get_info(&self) -> &SoundFontInfo {
         &self.info
     }
@@ -100,26 +304,282 @@ impl SoundFont {
         &self.wave_data[..]
     }
 
+    /// Gets the PCM data of the sample, from its start to its end.
+    ///
+    /// The data is the 16-bit representation held by the SoundFont;
+    /// divide by 32768 to get the values in the range of `[-1, 1)`.
+    /// The loop points, root key, and pitch correction are available from the `SampleHeader`,
+    /// where the loop points are relative to the whole wave data, not to the returned slice.
+    /// Returns an empty slice if the index is out of range.
+    pub fn get_sample_data(&self, sample_index: usize) -> &[i16] {
+        match self.sample_headers.get(sample_index) {
+            Some(header) => {
+                let end = cmp::min(header.end.max(0) as usize, self.wave_data.len());
+                let start = cmp::min(header.start.max(0) as usize, end);
+                &self.wave_data[start..end]
+            }
+            None => &[],
+        }
+    }
+
     /// Gets the samples of the SoundFont.
     pub fn get_sample_headers(&self) -> &[SampleHeader] {
         &self.sample_headers[..]
     }
 
     /// Gets the presets of the SoundFont.
+    /// The drum kits can be told apart from the melodic presets with `Preset::is_percussion`.
     pub fn get_presets(&self) -> &[Preset] {
         &self.presets[..]
     }
 
+    /// Gets the number of the presets.
+    pub fn preset_count(&self) -> usize {
+        self.presets.len()
+    }
+
+    /// Finds the preset with the bank and patch numbers.
+    /// If several presets have the same numbers, the last one is returned.
+    pub fn get_preset(&self, bank: i32, patch: i32) -> Option<&Preset> {
+        self.preset_lookup
+            .get(&(bank, patch))
+            .map(|index| &self.presets[*index])
+    }
+
+    /// Gets the names of the 128 GM programs, i.e. the presets in the bank 0, indexed by the program number.
+    /// The element is `None` if the SoundFont lacks the program.
+    pub fn gm_program_names(&self) -> Vec<Option<String>> {
+        (0..128)
+            .map(|patch| self.get_preset(0, patch).map(|preset| preset.name.clone()))
+            .collect()
+    }
+
     /// Gets the instruments of the SoundFont.
     pub fn get_instruments(&self) -> &[Instrument] {
         &self.instruments[..]
     }
+
+    // The most modulators a note can take from a preset region and an instrument region together.
+    pub(crate) fn get_max_region_modulator_count(&self) -> usize {
+        let preset = self
+            .presets
+            .iter()
+            .flat_map(|preset| preset.regions.iter())
+            .map(|region| region.modulators.len())
+            .max();
+        let instrument = self
+            .instruments
+            .iter()
+            .flat_map(|instrument| instrument.regions.iter())
+            .map(|region| region.modulators.len())
+            .max();
+        preset.unwrap_or(0) + instrument.unwrap_or(0)
+    }
+
+    /// Resolves the generator values used to play the note with the given preset.
+    ///
+    /// One set is returned for each pair of preset and instrument regions that the note triggers,
+    /// in the order the synthesizer would start the voices.
+    /// The result is empty if the preset does not exist or no region covers the note.
+    pub fn resolve_generators(
+        &self,
+        bank: i32,
+        patch: i32,
+        key: i32,
+        velocity: i32,
+    ) -> Vec<GeneratorSet> {
+        let mut sets: Vec<GeneratorSet> = Vec::new();
+
+        let preset = match self.get_preset(bank, patch) {
+            Some(value) => value,
+            None => return sets,
+        };
+
+        for preset_region in preset.regions.iter() {
+            if preset_region.contains(key, velocity) {
+                let instrument = &self.instruments[preset_region.instrument];
+                for instrument_region in instrument.regions.iter() {
+                    if instrument_region.contains(key, velocity) {
+                        let region_pair = RegionPair::new(preset_region, instrument_region);
+                        sets.push(GeneratorSet::new(&region_pair));
+                    }
+                }
+            }
+        }
+
+        sets
+    }
+
+    /// Estimates the loudness of the preset in LUFS, by rendering a standard note of it:
+    /// the middle C at the velocity 100, held for one second at 44.1 kHz without the reverb and chorus.
+    ///
+    /// This is the mean square of both channels over the held note in decibels,
+    /// as in EBU R128 but without the K-weighting and the gating, so it is only a rough estimate
+    /// meant to compare the presets with each other.
+    /// A silent preset gives negative infinity.
+    /// If the SoundFont has no such preset, the same fallback as `Synthesizer::note_on` applies.
+    pub fn estimate_preset_loudness(self: &Arc<Self>, bank: i32, patch: i32) -> f32 {
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        let synthesizer = match Synthesizer::new(self, &settings) {
+            Ok(value) => value,
+            Err(_) => return f32::NEG_INFINITY,
+        };
+
+        let (left, right) = synthesizer.render_preset_note(bank, patch, 60, 100, 1.0);
+        let length = cmp::min(left.len(), settings.sample_rate as usize);
+        if length == 0 {
+            return f32::NEG_INFINITY;
+        }
+
+        let sum: f64 = left[..length]
+            .iter()
+            .chain(right[..length].iter())
+            .map(|x| (*x as f64) * (*x as f64))
+            .sum();
+        (10.0 * (sum / length as f64).log10() - 0.691) as f32
+    }
+
+    /// Gets the gain in decibels which brings the estimated loudness of the preset to the target in LUFS,
+    /// so that all the presets audition at a similar loudness. A silent preset gives 0.
+    ///
+    /// See `estimate_preset_loudness` for how the loudness is estimated.
+    /// The gain can be applied with `Synthesizer::set_soundfont_gain`.
+    pub fn preset_loudness_trim(self: &Arc<Self>, bank: i32, patch: i32, target_lufs: f32) -> f32 {
+        let loudness = self.estimate_preset_loudness(bank, patch);
+        if loudness.is_finite() {
+            target_lufs - loudness
+        } else {
+            0_f32
+        }
+    }
+
+    /// Estimates the memory in bytes which the SoundFont takes when loaded by `SoundFont::new`,
+    /// so that a loader can reject an oversized bank before loading it.
+    ///
+    /// Only the chunk headers are read, and the sample data is not decoded.
+    /// The estimate is the sample pool, which is kept as 16-bit samples,
+    /// plus the presets, instruments, regions and sample headers counted from the sizes of their chunks.
+    /// The structure is slightly overestimated, as the global zones are counted as regions.
+    /// As with `SoundFont::new`, a missing list or sub-chunk is an error.
+    pub fn estimated_memory_bytes(data: &[u8]) -> Result<usize, SoundFontError> {
+        // The names are up to 20 characters long.
+        const NAME_SIZE: usize = 20;
+
+        let mut reader = data;
+
+        let chunk_id = BinaryReader::read_four_cc(&mut reader)?;
+        if chunk_id != b"RIFF" {
+            return Err(SoundFontError::RiffChunkNotFound);
+        }
+
+        let _size = BinaryReader::read_i32(&mut reader)?;
+
+        let form_type = BinaryReader::read_four_cc(&mut reader)?;
+        if form_type != b"sfbk" {
+            return Err(SoundFontError::InvalidRiffChunkType {
+                expected: FourCC::from_bytes(*b"sfbk"),
+                actual: form_type,
+            });
+        }
+
+        // The sizes of the sub-chunks of the sdta and pdta lists.
+        let mut sample_sizes: Option<BTreeMap<[u8; 4], usize>> = None;
+        let mut parameter_sizes: Option<BTreeMap<[u8; 4], usize>> = None;
+        while let Some((id, size, body)) = SoundFont::split_chunk(&mut reader) {
+            // RIFF chunks are padded to an even size.
+            if size & 1 == 1 && !reader.is_empty() {
+                reader = &reader[1..];
+            }
+
+            if &id != b"LIST" || body.len() < 4 {
+                continue;
+            }
+
+            let mut sub_chunks = &body[4..];
+            let mut sizes: BTreeMap<[u8; 4], usize> = BTreeMap::new();
+            while let Some((id, size, _)) = SoundFont::split_chunk(&mut sub_chunks) {
+                sizes.insert(id, size);
+            }
+
+            match &body[..4] {
+                b"sdta" => sample_sizes = Some(sizes),
+                b"pdta" => parameter_sizes = Some(sizes),
+                _ => (),
+            }
+        }
+
+        let sample_sizes = sample_sizes.ok_or(SoundFontError::ListChunkNotFound)?;
+        let sample_size = *sample_sizes
+            .get(b"smpl")
+            .ok_or(SoundFontError::SampleDataNotFound)?;
+
+        let parameter_sizes = parameter_sizes.ok_or(SoundFontError::ListChunkNotFound)?;
+        // Each list of records ends with a terminal record, which is not kept.
+        let count = |id: &[u8; 4], record_size: usize| match parameter_sizes.get(id) {
+            Some(size) => Ok((size / record_size).saturating_sub(1)),
+            None => Err(SoundFontError::SubChunkNotFound(FourCC::from_bytes(
+                id.map(|c| c.to_ascii_uppercase()),
+            ))),
+        };
+        let preset_count = count(b"phdr", 38)?;
+        let preset_zone_count = count(b"pbag", 4)?;
+        let instrument_count = count(b"inst", 22)?;
+        let instrument_zone_count = count(b"ibag", 4)?;
+        let sample_count = count(b"shdr", 46)?;
+
+        let wave_size = sample_size / 2 * mem::size_of::<i16>();
+        let preset_size = preset_count
+            * (mem::size_of::<Preset>() + NAME_SIZE + mem::size_of::<((i32, i32), usize)>())
+            + preset_zone_count * mem::size_of::<PresetRegion>();
+        let instrument_size = instrument_count * (mem::size_of::<Instrument>() + NAME_SIZE)
+            + instrument_zone_count * mem::size_of::<InstrumentRegion>();
+        let sample_header_size = sample_count * (mem::size_of::<SampleHeader>() + NAME_SIZE);
+
+        Ok(mem::size_of::<SoundFont>()
+            + wave_size
+            + preset_size
+            + instrument_size
+            + sample_header_size)
+    }
+
+    // Splits the next chunk off the data, and returns its ID, its size, and as much of its body as there is.
+    fn split_chunk<'a>(data: &mut &'a [u8]) -> Option<([u8; 4], usize, &'a [u8])> {
+        if data.len() < 8 {
+            return None;
+        }
+
+        let id = [data[0], data[1], data[2], data[3]];
+        let size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let body = &data[8..];
+        let length = cmp::min(size, body.len());
+        *data = &body[length..];
+
+        Some((id, size, &body[..length]))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl SoundFont {
+    /// Loads a SoundFont from an asynchronous reader.
+    ///
+    /// The whole data is read into memory without blocking the runtime,
+    /// and then parsed synchronously as `SoundFont::new` does.
+    pub async fn from_async_reader<R: tokio::io::AsyncRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<Self, SoundFontError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut data: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        SoundFont::new(&mut std::io::Cursor::new(data))
+    }
 }
 
 #[cfg(feature = "bevy_asset")]
 mod asset {
     use super::{SoundFont, SoundFontError};
-    use std::sync::Arc;
+    use alloc::sync::Arc;
 
     /// Asset wrapper around `Arc<SoundFont>`. Cheap-to-clone for use sites
     /// that need shared ownership of parsed sf2 data (`SoundFontUnit::new`
@@ -127,7 +587,7 @@ mod asset {
     #[derive(Debug, Clone, bevy_asset::Asset, bevy_reflect::TypePath)]
     pub struct SoundFontAsset(pub Arc<SoundFont>);
 
-    impl std::ops::Deref for SoundFontAsset {
+    impl core::ops::Deref for SoundFontAsset {
         type Target = SoundFont;
         fn deref(&self) -> &SoundFont {
             &self.0
@@ -147,8 +607,8 @@ mod asset {
 #[cfg(feature = "bevy_asset")]
 pub use asset::SoundFontAsset;
 
-#[cfg(test)]
-mod tests {
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod tests {
     use super::*;
 
     use std::{fs::File, path::PathBuf};
@@ -160,6 +620,7 @@ mod tests {
             .join("samples")
     }
 
+    #[cfg(not(feature = "sf3"))]
     #[test]
     fn test_load_reject_sf3() {
         let path = samples_dir_path().join("dummy.sf3");
@@ -170,6 +631,36 @@ mod tests {
         ));
     }
 
+    #[cfg(feature = "sf3")]
+    #[test]
+    fn test_load_sf3() {
+        let path = samples_dir_path().join("dummy.sf3");
+        let mut file = File::open(&path).unwrap();
+        let sound_font = Arc::new(SoundFont::new(&mut file).unwrap());
+        assert_eq!(sound_font.get_info().get_version().get_major(), 3);
+
+        // The 201 frames of the stream, with the loop points relative to the start made absolute.
+        let header = &sound_font.get_sample_headers()[0];
+        assert_eq!(header.get_start(), 0);
+        assert_eq!(header.get_end(), 201);
+        assert_eq!(header.get_start_loop(), 50);
+        assert_eq!(header.get_end_loop(), 150);
+        assert_eq!(header.get_sample_type(), 1);
+        assert!(sound_font.get_wave_data().len() >= 201 + 46);
+        assert!(sound_font
+            .get_sample_data(0)
+            .iter()
+            .any(|&x| x.unsigned_abs() > 1000));
+
+        let settings = SynthesizerSettings::new(44100);
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        synthesizer.render(&mut left, &mut right);
+        assert!(left.iter().any(|&x| x != 0_f32));
+    }
+
     // smpl sub-chunk exists, but is zero-length.
     #[test]
     fn test_load_empty_samples() {
@@ -180,4 +671,299 @@ mod tests {
             Err(SoundFontError::SampleDataNotFound)
         ));
     }
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn list(list_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        chunk(b"LIST", &[&list_type[..], data].concat())
+    }
+
+    fn name20(name: &str) -> Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+        bytes
+    }
+
+    // A SoundFont with one preset, one instrument, and one silent sample,
+    // preceded by the given lists. The generators are added to the instrument zone.
+    pub(crate) fn build_soundfont(leading_lists: &[u8], generators: &[(u16, i16)]) -> Vec<u8> {
+        let smpl = vec![0_u8; 2 * 146];
+        let shdr = sample_record("Sample", 0, 0, 1);
+        build_soundfont_with_samples(leading_lists, &smpl, &shdr, generators, &[])
+    }
+
+    // Builds a SoundFont whose only instrument zone has the modulators,
+    // given as the source, destination, amount, amount source and transform.
+    pub(crate) fn build_soundfont_with_modulators(
+        generators: &[(u16, i16)],
+        modulators: &[[u16; 5]],
+    ) -> Vec<u8> {
+        let smpl = vec![0_u8; 2 * 146];
+        let shdr = sample_record("Sample", 0, 0, 1);
+        build_soundfont_with_samples(&[], &smpl, &shdr, generators, modulators)
+    }
+
+    // Builds a SoundFont whose only region plays the left half of a linked stereo sample.
+    // The left half is silent, and the right half is a constant level.
+    pub(crate) fn build_stereo_soundfont(generators: &[(u16, i16)]) -> Vec<u8> {
+        let mut smpl = vec![0_u8; 2 * 146];
+        for _ in 0..146 {
+            smpl.extend_from_slice(&10000_i16.to_le_bytes());
+        }
+        let shdr = [
+            sample_record("Left", 0, 1, 4),
+            sample_record("Right", 146, 0, 2),
+        ]
+        .concat();
+        build_soundfont_with_samples(&[], &smpl, &shdr, generators, &[])
+    }
+
+    // A sample of 100 points from the offset, which loops from 10 to 90.
+    fn sample_record(name: &str, offset: u32, link: u16, sample_type: u16) -> Vec<u8> {
+        let mut record = name20(name);
+        for value in [offset, offset + 100, offset + 10, offset + 90, 44100] {
+            record.extend_from_slice(&value.to_le_bytes());
+        }
+        record.extend_from_slice(&[60, 0]);
+        record.extend_from_slice(&link.to_le_bytes());
+        record.extend_from_slice(&sample_type.to_le_bytes());
+        record
+    }
+
+    fn build_soundfont_with_samples(
+        leading_lists: &[u8],
+        smpl: &[u8],
+        samples: &[u8],
+        generators: &[(u16, i16)],
+        modulators: &[[u16; 5]],
+    ) -> Vec<u8> {
+        let mut shdr = samples.to_vec();
+        shdr.extend(name20("EOS"));
+        shdr.resize(samples.len() + 46, 0);
+
+        let mut phdr = name20("Preset");
+        phdr.extend_from_slice(&[0; 18]);
+        phdr.extend(name20("EOP"));
+        phdr.extend_from_slice(&[0, 0, 0, 0, 1, 0]);
+        phdr.extend_from_slice(&[0; 12]);
+
+        let mut inst = name20("Instrument");
+        inst.extend_from_slice(&[0, 0]);
+        inst.extend(name20("EOI"));
+        inst.extend_from_slice(&[1, 0]);
+
+        let bag = [0, 0, 0, 0, 1, 0, 0, 0];
+        let ibag = [
+            0,
+            0,
+            0,
+            0,
+            1 + generators.len() as u8,
+            0,
+            modulators.len() as u8,
+            0,
+        ];
+        let pgen = [41, 0, 0, 0, 0, 0, 0, 0];
+        let mut igen: Vec<u8> = Vec::new();
+        for (generator_type, value) in generators {
+            igen.extend_from_slice(&generator_type.to_le_bytes());
+            igen.extend_from_slice(&value.to_le_bytes());
+        }
+        igen.extend_from_slice(&[53, 0, 0, 0, 0, 0, 0, 0]);
+        let mut imod: Vec<u8> = Vec::new();
+        for value in modulators.iter().flatten() {
+            imod.extend_from_slice(&value.to_le_bytes());
+        }
+        imod.extend_from_slice(&[0; 10]);
+
+        let pdta = [
+            chunk(b"phdr", &phdr),
+            chunk(b"pbag", &bag),
+            chunk(b"pmod", &[0; 10]),
+            chunk(b"pgen", &pgen),
+            chunk(b"inst", &inst),
+            chunk(b"ibag", &ibag),
+            chunk(b"imod", &imod),
+            chunk(b"igen", &igen),
+            chunk(b"shdr", &shdr),
+        ]
+        .concat();
+
+        let body = [
+            &b"sfbk"[..],
+            leading_lists,
+            &list(b"sdta", &chunk(b"smpl", smpl)),
+            &list(b"pdta", &pdta),
+        ]
+        .concat();
+
+        chunk(b"RIFF", &body)
+    }
+
+    fn load(data: &[u8]) -> Result<SoundFont, SoundFontError> {
+        SoundFont::new(&mut std::io::Cursor::new(data))
+    }
+
+    #[test]
+    fn test_load_skip_unknown_chunks() {
+        let info = [chunk(b"ifil", &[2, 0, 1, 0]), chunk(b"INAM", b"Bank\0\0")].concat();
+        let sound_font = load(&build_soundfont(&list(b"INFO", &info), &[])).unwrap();
+        assert!(sound_font.get_warnings().is_empty());
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+
+        let info = [chunk(b"XXXX", &[1, 2, 3, 4]), chunk(b"INAM", b"Bank\0\0")].concat();
+        let leading = [chunk(b"JUNK", &[0; 6]), list(b"INFO", &info)].concat();
+        let sound_font = load(&build_soundfont(&leading, &[])).unwrap();
+        assert_eq!(sound_font.get_warnings().len(), 2);
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+        assert_eq!(sound_font.preset_count(), 1);
+    }
+
+    #[test]
+    fn test_load_truncated_optional_chunks() {
+        // The ifil sub-chunk is too short to hold a version,
+        // and the INAM sub-chunk claims more bytes than the INFO list has.
+        let mut info = [chunk(b"ifil", &[2, 0]), chunk(b"INAM", b"Bank\0\0")].concat();
+        let length = info.len();
+        info[length - 10..length - 6].copy_from_slice(&100_u32.to_le_bytes());
+        let sound_font = load(&build_soundfont(&list(b"INFO", &info), &[])).unwrap();
+        assert_eq!(sound_font.get_warnings().len(), 2);
+        assert_eq!(sound_font.get_info().get_version().get_major(), 0);
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+        assert_eq!(sound_font.get_preset(0, 0).unwrap().get_name(), "Preset");
+
+        // The INFO list ends in the middle of a sub-chunk header.
+        let info = [chunk(b"INAM", b"Bank\0\0"), b"ICMT".to_vec()].concat();
+        let sound_font = load(&build_soundfont(&list(b"INFO", &info), &[])).unwrap();
+        assert_eq!(sound_font.get_warnings().len(), 1);
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+
+        // No INFO list at all.
+        let sound_font = load(&build_soundfont(&[], &[])).unwrap();
+        assert_eq!(sound_font.get_warnings().len(), 1);
+        assert_eq!(sound_font.preset_count(), 1);
+    }
+
+    #[test]
+    fn test_load_reject_missing_mandatory_lists() {
+        let data = build_soundfont(&[], &[]);
+        // Cut the file right before the pdta list.
+        let pdta = data
+            .windows(4)
+            .position(|window| window == b"pdta")
+            .unwrap();
+        assert!(matches!(
+            load(&data[..pdta - 8]),
+            Err(SoundFontError::ListChunkNotFound)
+        ));
+
+        let sdta = data
+            .windows(4)
+            .position(|window| window == b"sdta")
+            .unwrap();
+        let without_sdta = [&data[..sdta - 8], &data[pdta - 8..]].concat();
+        assert!(matches!(
+            load(&without_sdta),
+            Err(SoundFontError::InvalidListChunkType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_loudness_of_audible_preset() {
+        // A looped sample at a constant level, with and without 6 dB of attenuation.
+        let loudness = |generators: &[(u16, i16)]| {
+            let mut smpl = Vec::new();
+            for _ in 0..146 {
+                smpl.extend_from_slice(&10000_i16.to_le_bytes());
+            }
+            let shdr = sample_record("Sample", 0, 0, 1);
+            let data = build_soundfont_with_samples(&[], &smpl, &shdr, generators, &[]);
+            let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+            let loudness = sound_font.estimate_preset_loudness(0, 0);
+            assert!(loudness.is_finite());
+            assert_eq!(
+                sound_font.preset_loudness_trim(0, 0, -23.0),
+                -23.0 - loudness
+            );
+            loudness
+        };
+
+        // The initial attenuation is reduced to 40% by the synthesizer, which gives 2.4 dB.
+        let open = loudness(&[(54, 1)]);
+        let attenuated = loudness(&[(54, 1), (48, 60)]);
+        assert!(open < 0_f32);
+        assert!((open - attenuated - 2.4).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_loudness_of_silent_preset() {
+        let data = build_soundfont(&[], &[]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        assert_eq!(sound_font.estimate_preset_loudness(0, 0), f32::NEG_INFINITY);
+        assert_eq!(sound_font.preset_loudness_trim(0, 0, -23.0), 0.0);
+    }
+
+    #[test]
+    fn test_estimated_memory_bytes() {
+        let data = build_soundfont(&[], &[]);
+        let sound_font = SoundFont::new(&mut &data[..]).unwrap();
+        let estimate = SoundFont::estimated_memory_bytes(&data).unwrap();
+        assert!(estimate >= 2 * sound_font.get_wave_data().len());
+        assert!(estimate < 4 * 1024);
+
+        let pdta = data
+            .windows(4)
+            .position(|window| window == b"pdta")
+            .unwrap();
+        assert!(matches!(
+            SoundFont::estimated_memory_bytes(&data[..pdta - 8]),
+            Err(SoundFontError::ListChunkNotFound)
+        ));
+        assert!(matches!(
+            SoundFont::estimated_memory_bytes(b"RIFX"),
+            Err(SoundFontError::RiffChunkNotFound)
+        ));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mmap() {
+        let info = [chunk(b"ifil", &[2, 0, 1, 0]), chunk(b"INAM", b"Bank\0\0")].concat();
+        let data = build_soundfont(&list(b"INFO", &info), &[(54, 1)]);
+        let path = std::env::temp_dir().join(format!("rustysynth-{}.sf2", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        let loaded = load(&data).unwrap();
+        let mapped = unsafe { SoundFont::open_mmap(&path) }.unwrap();
+        std::fs::remove_file(&path).ok();
+        #[cfg(target_endian = "little")]
+        assert!(matches!(mapped.wave_data, WaveData::Mapped { .. }));
+        assert_eq!(mapped.get_wave_data(), loaded.get_wave_data());
+        assert_eq!(mapped.get_info().get_bank_name(), "Bank");
+        assert_eq!(mapped.preset_count(), 1);
+        assert!(mapped.get_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let info = [chunk(b"ifil", &[2, 0, 1, 0]), chunk(b"INAM", b"Bank\0\0")].concat();
+        let data = build_soundfont(&list(b"INFO", &info), &[]);
+        let sound_font = SoundFont::from_bytes(&data).unwrap();
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+        assert_eq!(
+            sound_font.preset_count(),
+            load(&data).unwrap().preset_count()
+        );
+
+        // The end of the data is reported as the end of a file would be.
+        assert!(matches!(
+            SoundFont::from_bytes(&data[..10]),
+            Err(SoundFontError::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
 }
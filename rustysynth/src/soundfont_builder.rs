@@ -0,0 +1,711 @@
+#![allow(dead_code)]
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use crate::error::SoundFontError;
+use crate::generator_type::GeneratorType;
+use crate::instrument_region::InstrumentRegion;
+use crate::modulator::Modulator;
+use crate::sample_header::SampleHeader;
+use crate::soundfont::SoundFont;
+
+#[derive(Debug, Clone)]
+struct BuilderSample {
+    name: String,
+    wave: Vec<i16>,
+    // The loop, in sample points from the start of the wave.
+    start_loop: u32,
+    end_loop: u32,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+    link: Option<usize>,
+    sample_type: u16,
+}
+
+// A zone of an instrument or a preset, whose last generator is the sample ID or the instrument ID.
+#[derive(Debug, Clone)]
+struct BuilderZone {
+    generators: Vec<(u16, i16)>,
+    modulators: Vec<Modulator>,
+}
+
+#[derive(Debug, Clone)]
+struct BuilderInstrument {
+    name: String,
+    zones: Vec<BuilderZone>,
+}
+
+#[derive(Debug, Clone)]
+struct BuilderPreset {
+    name: String,
+    bank: i32,
+    patch: i32,
+    library: i32,
+    genre: i32,
+    morphology: i32,
+    zones: Vec<BuilderZone>,
+}
+
+/// Builds a SoundFont from samples, instruments and presets, and writes it as a .sf2 file.
+///
+/// The zones are given as the generators of the SoundFont specification, as pairs of
+/// the generator number and its amount, such as `(43, 0x7F00)` for the key range of 0 to 127
+/// or `(48, 60)` for 6 dB of initial attenuation.
+///
+/// `from_sound_font` starts from the contents of a loaded SoundFont, so that its presets
+/// can be trimmed or new instruments can be added to it. The instruments not played by any preset,
+/// and the samples not played by any of the written instruments, are left out of the file.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SoundFontBuilder {
+    bank_name: String,
+    version: (i16, i16),
+    target_sound_engine: String,
+    info: Vec<([u8; 4], String)>,
+    samples: Vec<BuilderSample>,
+    instruments: Vec<BuilderInstrument>,
+    presets: Vec<BuilderPreset>,
+}
+
+impl SoundFontBuilder {
+    const MONO_SAMPLE: u16 = 1;
+    const LINKED_SAMPLE_TYPES: u16 = SampleHeader::RIGHT_SAMPLE | SampleHeader::LEFT_SAMPLE | 8;
+
+    /// Creates an empty builder of a SoundFont with the bank name.
+    pub fn new(bank_name: &str) -> Self {
+        Self {
+            bank_name: bank_name.to_string(),
+            version: (2, 1),
+            target_sound_engine: "EMU8000".to_string(),
+            info: Vec::new(),
+            samples: Vec::new(),
+            instruments: Vec::new(),
+            presets: Vec::new(),
+        }
+    }
+
+    /// Creates a builder with the information, samples, instruments and presets of the SoundFont.
+    ///
+    /// The global zones were merged into the other zones when the SoundFont was loaded,
+    /// so each region is written as a zone of its own, which plays the same.
+    /// The samples of a SoundFont3 are written uncompressed.
+    pub fn from_sound_font(sound_font: &SoundFont) -> Self {
+        let info = sound_font.get_info();
+        let mut builder = SoundFontBuilder::new(info.get_bank_name());
+        builder.version = (info.version.major, info.version.minor);
+        builder.target_sound_engine = info.get_target_sound_engine().to_string();
+        builder.info = [
+            (*b"irom", info.get_rom_name()),
+            (*b"ICRD", info.get_creation_date()),
+            (*b"IENG", info.get_author()),
+            (*b"IPRD", info.get_target_product()),
+            (*b"ICOP", info.get_copyright()),
+            (*b"ICMT", info.get_comments()),
+            (*b"ISFT", info.get_tools()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(id, value)| (id, value.to_string()))
+        .collect();
+
+        let wave_data = sound_font.get_wave_data();
+        let headers = sound_font.get_sample_headers();
+        for header in headers {
+            let start = (header.start.max(0) as usize).min(wave_data.len());
+            let end = (header.end.max(0) as usize).clamp(start, wave_data.len());
+            let relative = |position: i32| {
+                (position as i64 - start as i64).clamp(0, (end - start) as i64) as u32
+            };
+            let linked = header.sample_type & SoundFontBuilder::LINKED_SAMPLE_TYPES != 0
+                && (header.link as usize) < headers.len();
+            builder.samples.push(BuilderSample {
+                name: header.name.clone(),
+                wave: wave_data[start..end].to_vec(),
+                start_loop: relative(header.start_loop),
+                end_loop: relative(header.end_loop),
+                sample_rate: header.sample_rate.max(0) as u32,
+                original_pitch: header.original_pitch,
+                pitch_correction: header.pitch_correction,
+                link: if linked {
+                    Some(header.link as usize)
+                } else {
+                    None
+                },
+                sample_type: header.sample_type & !SampleHeader::ROM_FLAG,
+            });
+        }
+
+        let defaults = InstrumentRegion::default_generators();
+        for instrument in sound_font.get_instruments() {
+            let zones = instrument
+                .regions
+                .iter()
+                .map(|region| BuilderZone {
+                    generators: changed_generators(&region.gs, &defaults, GeneratorType::SAMPLE_ID),
+                    modulators: region.modulators.clone(),
+                })
+                .collect();
+            builder.instruments.push(BuilderInstrument {
+                name: instrument.name.clone(),
+                zones,
+            });
+        }
+
+        let mut defaults = [0_i16; GeneratorType::COUNT];
+        defaults[GeneratorType::KEY_RANGE as usize] = 0x7F00;
+        defaults[GeneratorType::VELOCITY_RANGE as usize] = 0x7F00;
+        for preset in sound_font.get_presets() {
+            let zones = preset
+                .regions
+                .iter()
+                .map(|region| BuilderZone {
+                    generators: changed_generators(
+                        &region.gs,
+                        &defaults,
+                        GeneratorType::INSTRUMENT,
+                    ),
+                    modulators: region.modulators.clone(),
+                })
+                .collect();
+            builder.presets.push(BuilderPreset {
+                name: preset.name.clone(),
+                bank: preset.bank_number,
+                patch: preset.patch_number,
+                library: preset.library,
+                genre: preset.genre,
+                morphology: preset.morphology,
+                zones,
+            });
+        }
+
+        builder
+    }
+
+    /// Adds a mono sample of 16-bit points, and returns its index.
+    ///
+    /// The original pitch is the key number at which the sample plays at its own pitch.
+    /// The loop is given as the first point and the point after the last one, from the start of the wave.
+    /// Without a loop, the whole wave is set as the loop, which is only used if a zone loops the sample.
+    pub fn add_sample(
+        &mut self,
+        name: &str,
+        wave: &[i16],
+        sample_rate: i32,
+        original_pitch: i32,
+        loop_range: Option<(usize, usize)>,
+    ) -> usize {
+        if sample_rate <= 0 {
+            panic!("The sample rate must be a positive value.");
+        }
+
+        if let Some((start_loop, end_loop)) = loop_range {
+            if !(start_loop < end_loop && end_loop <= wave.len()) {
+                panic!("The loop must be a non-empty range within the wave.");
+            }
+        }
+        let (start_loop, end_loop) = loop_range.unwrap_or((0, wave.len()));
+
+        self.samples.push(BuilderSample {
+            name: name.to_string(),
+            wave: wave.to_vec(),
+            start_loop: start_loop as u32,
+            end_loop: end_loop as u32,
+            sample_rate: sample_rate as u32,
+            original_pitch: original_pitch.clamp(0, 127) as u8,
+            pitch_correction: 0,
+            link: None,
+            sample_type: SoundFontBuilder::MONO_SAMPLE,
+        });
+        self.samples.len() - 1
+    }
+
+    /// Links two samples as the left and right channels of a stereo sample.
+    ///
+    /// A zone playing one of them also plays the other,
+    /// unless the instrument has its own zone for it over the same keys and velocities.
+    pub fn link_samples(&mut self, left: usize, right: usize) {
+        if left >= self.samples.len() || right >= self.samples.len() {
+            panic!("The sample must be less than the number of the samples.");
+        }
+
+        self.samples[left].link = Some(right);
+        self.samples[left].sample_type = SampleHeader::LEFT_SAMPLE;
+        self.samples[right].link = Some(left);
+        self.samples[right].sample_type = SampleHeader::RIGHT_SAMPLE;
+    }
+
+    /// Adds an instrument without any zone, and returns its index.
+    pub fn add_instrument(&mut self, name: &str) -> usize {
+        self.instruments.push(BuilderInstrument {
+            name: name.to_string(),
+            zones: Vec::new(),
+        });
+        self.instruments.len() - 1
+    }
+
+    /// Adds a zone to the instrument, which plays the sample with the generators.
+    pub fn add_instrument_zone(
+        &mut self,
+        instrument: usize,
+        sample: usize,
+        generators: &[(u16, i16)],
+    ) {
+        if instrument >= self.instruments.len() {
+            panic!("The instrument must be less than the number of the instruments.");
+        }
+        if sample >= self.samples.len() {
+            panic!("The sample must be less than the number of the samples.");
+        }
+
+        self.instruments[instrument].zones.push(BuilderZone {
+            generators: ordered_generators(generators, GeneratorType::SAMPLE_ID, sample as i16),
+            modulators: Vec::new(),
+        });
+    }
+
+    /// Adds a preset without any zone, and returns its index.
+    pub fn add_preset(&mut self, name: &str, bank: i32, patch: i32) -> usize {
+        if !(0..=128).contains(&bank) {
+            panic!("The bank number must be in the range of 0 to 128.");
+        }
+        if !(0..=127).contains(&patch) {
+            panic!("The patch number must be in the range of 0 to 127.");
+        }
+
+        self.presets.push(BuilderPreset {
+            name: name.to_string(),
+            bank,
+            patch,
+            library: 0,
+            genre: 0,
+            morphology: 0,
+            zones: Vec::new(),
+        });
+        self.presets.len() - 1
+    }
+
+    /// Adds a zone to the preset, which plays the instrument with the generators
+    /// added to those of the instrument zones.
+    pub fn add_preset_zone(&mut self, preset: usize, instrument: usize, generators: &[(u16, i16)]) {
+        if preset >= self.presets.len() {
+            panic!("The preset must be less than the number of the presets.");
+        }
+        if instrument >= self.instruments.len() {
+            panic!("The instrument must be less than the number of the instruments.");
+        }
+
+        self.presets[preset].zones.push(BuilderZone {
+            generators: ordered_generators(
+                generators,
+                GeneratorType::INSTRUMENT,
+                instrument as i16,
+            ),
+            modulators: Vec::new(),
+        });
+    }
+
+    /// Keeps only the presets for which the predicate returns `true`,
+    /// given the name, the bank number and the patch number of each preset.
+    pub fn retain_presets<F: FnMut(&str, i32, i32) -> bool>(&mut self, mut predicate: F) {
+        self.presets
+            .retain(|preset| predicate(&preset.name, preset.bank, preset.patch));
+    }
+
+    /// Gets the number of the samples.
+    pub fn get_sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Gets the number of the instruments.
+    pub fn get_instrument_count(&self) -> usize {
+        self.instruments.len()
+    }
+
+    /// Gets the number of the presets.
+    pub fn get_preset_count(&self) -> usize {
+        self.presets.len()
+    }
+
+    /// Loads the SoundFont as it would be written.
+    pub fn build(&self) -> Result<SoundFont, SoundFontError> {
+        let mut data: Vec<u8> = Vec::new();
+        self.write(&mut data)?;
+        SoundFont::new(&mut &data[..])
+    }
+
+    /// Writes the SoundFont as a .sf2 file.
+    ///
+    /// An error is returned if there are more zones or generators than the file can index.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        // Only the instruments played by a preset, and the samples played by those, are written.
+        let mut instruments: Vec<usize> = Vec::new();
+        let mut instrument_ids: HashMap<usize, usize> = HashMap::new();
+        for zone in self.presets.iter().flat_map(|preset| &preset.zones) {
+            let instrument = zone.get_id();
+            if let Entry::Vacant(entry) = instrument_ids.entry(instrument) {
+                entry.insert(instruments.len());
+                instruments.push(instrument);
+            }
+        }
+
+        let mut samples: Vec<usize> = Vec::new();
+        let mut sample_ids: HashMap<usize, usize> = HashMap::new();
+        for &instrument in &instruments {
+            for zone in &self.instruments[instrument].zones {
+                let sample = zone.get_id();
+                for sample in [Some(sample), self.samples[sample].link]
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Entry::Vacant(entry) = sample_ids.entry(sample) {
+                        entry.insert(samples.len());
+                        samples.push(sample);
+                    }
+                }
+            }
+        }
+
+        // Each sample is followed by 46 zeros, as the SoundFont specification requires.
+        let mut smpl: Vec<u8> = Vec::new();
+        let mut shdr: Vec<u8> = Vec::new();
+        for &index in &samples {
+            let sample = &self.samples[index];
+            let start = (smpl.len() / 2) as u32;
+            for value in &sample.wave {
+                smpl.extend_from_slice(&value.to_le_bytes());
+            }
+            smpl.resize(smpl.len() + 2 * 46, 0);
+
+            let (link, sample_type) = match sample.link {
+                Some(link) => (sample_ids[&link], sample.sample_type),
+                None => (0, SoundFontBuilder::MONO_SAMPLE),
+            };
+            shdr.extend(name20(&sample.name));
+            for value in [
+                start,
+                start + sample.wave.len() as u32,
+                start + sample.start_loop,
+                start + sample.end_loop,
+                sample.sample_rate,
+            ] {
+                shdr.extend_from_slice(&value.to_le_bytes());
+            }
+            shdr.extend_from_slice(&[sample.original_pitch, sample.pitch_correction as u8]);
+            shdr.extend_from_slice(&index_u16(link)?.to_le_bytes());
+            shdr.extend_from_slice(&sample_type.to_le_bytes());
+        }
+        shdr.extend(name20("EOS"));
+        shdr.resize(shdr.len() + 26, 0);
+
+        let mut phdr: Vec<u8> = Vec::new();
+        let mut preset_zones = ZoneWriter::new();
+        for preset in &self.presets {
+            phdr.extend(name20(&preset.name));
+            phdr.extend_from_slice(&(preset.patch as u16).to_le_bytes());
+            phdr.extend_from_slice(&(preset.bank as u16).to_le_bytes());
+            phdr.extend_from_slice(&index_u16(preset_zones.zone_count)?.to_le_bytes());
+            for value in [preset.library, preset.genre, preset.morphology] {
+                phdr.extend_from_slice(&(value as u32).to_le_bytes());
+            }
+            preset_zones.write(&preset.zones, &instrument_ids)?;
+        }
+        phdr.extend(name20("EOP"));
+        phdr.extend_from_slice(&[0, 0, 0, 0]);
+        phdr.extend_from_slice(&index_u16(preset_zones.zone_count)?.to_le_bytes());
+        phdr.extend_from_slice(&[0; 12]);
+        preset_zones.finish()?;
+
+        let mut inst: Vec<u8> = Vec::new();
+        let mut instrument_zones = ZoneWriter::new();
+        for &index in &instruments {
+            let instrument = &self.instruments[index];
+            inst.extend(name20(&instrument.name));
+            inst.extend_from_slice(&index_u16(instrument_zones.zone_count)?.to_le_bytes());
+            instrument_zones.write(&instrument.zones, &sample_ids)?;
+        }
+        inst.extend(name20("EOI"));
+        inst.extend_from_slice(&index_u16(instrument_zones.zone_count)?.to_le_bytes());
+        instrument_zones.finish()?;
+
+        let pdta = [
+            chunk(b"phdr", &phdr),
+            chunk(b"pbag", &preset_zones.bag),
+            chunk(b"pmod", &preset_zones.modulators),
+            chunk(b"pgen", &preset_zones.generators),
+            chunk(b"inst", &inst),
+            chunk(b"ibag", &instrument_zones.bag),
+            chunk(b"imod", &instrument_zones.modulators),
+            chunk(b"igen", &instrument_zones.generators),
+            chunk(b"shdr", &shdr),
+        ]
+        .concat();
+
+        let mut version = self.version.0.to_le_bytes().to_vec();
+        version.extend_from_slice(&self.version.1.to_le_bytes());
+        let mut info = chunk(b"ifil", &version);
+        info.extend(chunk(b"isng", &zstr(&self.target_sound_engine)));
+        info.extend(chunk(b"INAM", &zstr(&self.bank_name)));
+        for (id, value) in &self.info {
+            info.extend(chunk(id, &zstr(value)));
+        }
+
+        let body = [
+            &b"sfbk"[..],
+            &list(b"INFO", &info),
+            &list(b"sdta", &chunk(b"smpl", &smpl)),
+            &list(b"pdta", &pdta),
+        ]
+        .concat();
+
+        writer.write_all(&chunk(b"RIFF", &body))
+    }
+}
+
+// Adds the generators which move an address of the sample by the offset,
+// split into the fine one and the coarse one of 32768 points.
+pub(crate) fn push_address_offset(
+    generators: &mut Vec<(u16, i16)>,
+    fine_type: u16,
+    coarse_type: u16,
+    offset: i32,
+) {
+    if offset % 32768 != 0 {
+        generators.push((fine_type, (offset % 32768) as i16));
+    }
+    if offset / 32768 != 0 {
+        generators.push((coarse_type, (offset / 32768) as i16));
+    }
+}
+
+impl BuilderZone {
+    fn get_id(&self) -> usize {
+        self.generators.last().unwrap().1 as u16 as usize
+    }
+}
+
+// Writes the bag, modulator and generator lists of the preset zones or the instrument zones.
+struct ZoneWriter {
+    bag: Vec<u8>,
+    modulators: Vec<u8>,
+    generators: Vec<u8>,
+    zone_count: usize,
+    modulator_count: usize,
+    generator_count: usize,
+}
+
+impl ZoneWriter {
+    fn new() -> Self {
+        Self {
+            bag: Vec::new(),
+            modulators: Vec::new(),
+            generators: Vec::new(),
+            zone_count: 0,
+            modulator_count: 0,
+            generator_count: 0,
+        }
+    }
+
+    // The last generator of each zone is given the index of the instrument or sample in the file.
+    fn write(
+        &mut self,
+        zones: &[BuilderZone],
+        ids: &HashMap<usize, usize>,
+    ) -> Result<(), io::Error> {
+        for zone in zones {
+            self.bag
+                .extend_from_slice(&index_u16(self.generator_count)?.to_le_bytes());
+            self.bag
+                .extend_from_slice(&index_u16(self.modulator_count)?.to_le_bytes());
+
+            let (last, generators) = zone.generators.split_last().unwrap();
+            let id = index_u16(ids[&zone.get_id()])? as i16;
+            for (generator_type, value) in generators.iter().chain([&(last.0, id)]) {
+                self.generators
+                    .extend_from_slice(&generator_type.to_le_bytes());
+                self.generators.extend_from_slice(&value.to_le_bytes());
+            }
+            for modulator in &zone.modulators {
+                for value in [
+                    modulator.source.0,
+                    modulator.destination,
+                    modulator.amount as u16,
+                    modulator.amount_source.0,
+                    modulator.transform,
+                ] {
+                    self.modulators.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+
+            self.zone_count += 1;
+            self.modulator_count += zone.modulators.len();
+            self.generator_count += zone.generators.len();
+        }
+
+        Ok(())
+    }
+
+    // Writes the terminators of the lists.
+    fn finish(&mut self) -> Result<(), io::Error> {
+        self.bag
+            .extend_from_slice(&index_u16(self.generator_count)?.to_le_bytes());
+        self.bag
+            .extend_from_slice(&index_u16(self.modulator_count)?.to_le_bytes());
+        self.modulators.extend_from_slice(&[0; 10]);
+        self.generators.extend_from_slice(&[0; 4]);
+        Ok(())
+    }
+}
+
+// The generators which differ from the defaults, ending with the sample ID or the instrument ID.
+fn changed_generators(
+    gs: &[i16; GeneratorType::COUNT],
+    defaults: &[i16; GeneratorType::COUNT],
+    id_type: u16,
+) -> Vec<(u16, i16)> {
+    let generators: Vec<(u16, i16)> = (0..GeneratorType::COUNT as u16)
+        .filter(|&generator_type| gs[generator_type as usize] != defaults[generator_type as usize])
+        .map(|generator_type| (generator_type, gs[generator_type as usize]))
+        .collect();
+    ordered_generators(&generators, id_type, gs[id_type as usize])
+}
+
+// The key range comes first, the velocity range second, and the sample ID or the instrument ID last,
+// as the SoundFont specification requires. Any IDs among the given generators are dropped.
+fn ordered_generators(generators: &[(u16, i16)], id_type: u16, id: i16) -> Vec<(u16, i16)> {
+    let mut ordered: Vec<(u16, i16)> = generators
+        .iter()
+        .filter(|(generator_type, _)| {
+            *generator_type != GeneratorType::SAMPLE_ID
+                && *generator_type != GeneratorType::INSTRUMENT
+        })
+        .copied()
+        .collect();
+    ordered.sort_by_key(|(generator_type, _)| match *generator_type {
+        GeneratorType::KEY_RANGE => 0,
+        GeneratorType::VELOCITY_RANGE => 1,
+        _ => 2,
+    });
+    ordered.push((id_type, id));
+    ordered
+}
+
+fn index_u16(value: usize) -> Result<u16, io::Error> {
+    u16::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "there are more zones, generators or samples than the file can index",
+        )
+    })
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn list(list_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    chunk(b"LIST", &[&list_type[..], data].concat())
+}
+
+// A string of the INFO list, terminated by a zero and padded to an even length.
+fn zstr(value: &str) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    bytes.resize(bytes.len().next_multiple_of(2), 0);
+    bytes
+}
+
+// A name of the pdta list, which is cut to 19 bytes to keep the terminating zero.
+fn name20(name: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = name.bytes().take(19).collect();
+    bytes.resize(20, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build() -> SoundFontBuilder {
+        let wave: Vec<i16> = (0..1000)
+            .map(|t| ((t % 100) * 300 - 15000) as i16)
+            .collect();
+        let mut builder = SoundFontBuilder::new("Bank");
+        let left = builder.add_sample("Left", &wave, 22050, 60, Some((100, 900)));
+        let right = builder.add_sample("Right", &wave, 22050, 60, Some((100, 900)));
+        builder.link_samples(left, right);
+        let mono = builder.add_sample("Mono", &wave[..500], 44100, 72, None);
+
+        let piano = builder.add_instrument("Piano");
+        builder.add_instrument_zone(
+            piano,
+            left,
+            &[
+                (GeneratorType::SAMPLE_MODES, 1),
+                (GeneratorType::INITIAL_ATTENUATION, 60),
+                (GeneratorType::KEY_RANGE, 0x3C00),
+            ],
+        );
+        builder.add_instrument_zone(piano, mono, &[(GeneratorType::KEY_RANGE, 0x7F3D)]);
+        let organ = builder.add_instrument("Organ");
+        builder.add_instrument_zone(organ, mono, &[]);
+
+        let preset = builder.add_preset("Piano", 0, 0);
+        builder.add_preset_zone(preset, piano, &[(GeneratorType::COARSE_TUNE, 12)]);
+        let preset = builder.add_preset("Organ", 1, 16);
+        builder.add_preset_zone(preset, organ, &[]);
+        builder
+    }
+
+    #[test]
+    fn test_write() {
+        let sound_font = build().build().unwrap();
+        assert_eq!(sound_font.get_info().get_bank_name(), "Bank");
+        assert_eq!(sound_font.get_sample_headers().len(), 3);
+
+        let preset = sound_font.get_preset(0, 0).unwrap();
+        assert_eq!(preset.get_regions()[0].get_coarse_tune(), 12);
+        let instrument = &sound_font.get_instruments()[preset.get_regions()[0].get_instrument_id()];
+        let regions = instrument.get_regions();
+        assert_eq!(
+            (
+                regions[0].get_key_range_start(),
+                regions[0].get_key_range_end()
+            ),
+            (0, 60)
+        );
+        assert_eq!(regions[0].get_initial_attenuation(), 6_f32);
+        assert_eq!(
+            regions[0].get_sample_start_loop() - regions[0].get_sample_start(),
+            100
+        );
+        assert_eq!(regions[1].get_root_key(), 72);
+        assert!(instrument.linked_regions[0].is_some());
+
+        // Writing the loaded SoundFont again gives the same file.
+        let mut data = Vec::new();
+        sound_font.write(&mut data).unwrap();
+        let mut rewritten = Vec::new();
+        SoundFont::new(&mut &data[..])
+            .unwrap()
+            .write(&mut rewritten)
+            .unwrap();
+        assert_eq!(data, rewritten);
+
+        // The samples and instruments of the removed presets are left out.
+        let mut builder = SoundFontBuilder::from_sound_font(&sound_font);
+        builder.retain_presets(|_, bank, _| bank == 1);
+        let trimmed = builder.build().unwrap();
+        assert_eq!(trimmed.get_presets().len(), 1);
+        assert_eq!(trimmed.get_instruments().len(), 1);
+        assert_eq!(trimmed.get_sample_headers().len(), 1);
+        assert_eq!(trimmed.get_sample_headers()[0].get_name(), "Mono");
+        assert_eq!(trimmed.get_wave_data().len(), 500 + 46);
+    }
+}
@@ -1,10 +1,13 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cmp;
 
 use crate::binary_reader::BinaryReader;
 use crate::error::SoundFontError;
-use crate::four_cc::FourCC;
+use crate::io::Read;
 use crate::read_counter::ReadCounter;
 use crate::soundfont_version::SoundFontVersion;
 
@@ -26,23 +29,13 @@ pub struct SoundFontInfo {
 }
 
 impl SoundFontInfo {
-    pub(crate) fn new<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
-        let chunk_id = BinaryReader::read_four_cc(reader)?;
-        if chunk_id != b"LIST" {
-            return Err(SoundFontError::ListChunkNotFound);
-        }
-
-        let end = BinaryReader::read_u32(reader)? as usize;
+    pub(crate) fn new<R: Read>(
+        reader: &mut R,
+        end: usize,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, SoundFontError> {
         let reader = &mut ReadCounter::new(reader);
 
-        let list_type = BinaryReader::read_four_cc(reader)?;
-        if list_type != b"INFO" {
-            return Err(SoundFontError::InvalidListChunkType {
-                expected: FourCC::from_bytes(*b"INFO"),
-                actual: list_type,
-            });
-        }
-
         let mut version: Option<SoundFontVersion> = None;
         let mut target_sound_engine: Option<String> = None;
         let mut bank_name: Option<String> = None;
@@ -55,30 +48,54 @@ impl SoundFontInfo {
         let mut comments: Option<String> = None;
         let mut tools: Option<String> = None;
 
+        // Every sub-chunk of the INFO list is optional.
+        // Anything unknown or malformed is skipped rather than failing the whole load,
+        // as long as it does not run past the end of the list.
         while reader.bytes_read() < end {
+            let remaining = end - reader.bytes_read();
+            if remaining < 8 {
+                BinaryReader::discard_data(reader, remaining)?;
+                warnings.push("the INFO list ends with a truncated sub-chunk header".to_string());
+                break;
+            }
+
             let id = BinaryReader::read_four_cc(reader)?;
             let size = BinaryReader::read_u32(reader)? as usize;
 
+            let remaining = end - reader.bytes_read();
+            if size > remaining {
+                warnings.push(format!(
+                    "the '{id}' sub-chunk is truncated to {remaining} of its {size} bytes"
+                ));
+            }
+            let mut data: Vec<u8> = vec![0; cmp::min(size, remaining)];
+            reader.read_exact(&mut data)?;
+            let length = data.len();
+            let data = &mut &data[..];
+
             match id.as_bytes() {
-                b"ifil" => version = Some(SoundFontVersion::new(reader)?),
+                b"ifil" | b"iver" => match SoundFontVersion::new(data) {
+                    Ok(value) if id == b"ifil" => version = Some(value),
+                    Ok(value) => rom_version = Some(value),
+                    Err(_) => warnings.push(format!("the '{id}' sub-chunk is malformed")),
+                },
                 b"isng" => {
                     target_sound_engine =
-                        Some(BinaryReader::read_fixed_length_string(reader, size)?)
+                        Some(BinaryReader::read_fixed_length_string(data, length)?)
                 }
-                b"INAM" => bank_name = Some(BinaryReader::read_fixed_length_string(reader, size)?),
-                b"irom" => rom_name = Some(BinaryReader::read_fixed_length_string(reader, size)?),
-                b"iver" => rom_version = Some(SoundFontVersion::new(reader)?),
+                b"INAM" => bank_name = Some(BinaryReader::read_fixed_length_string(data, length)?),
+                b"irom" => rom_name = Some(BinaryReader::read_fixed_length_string(data, length)?),
                 b"ICRD" => {
-                    creation_date = Some(BinaryReader::read_fixed_length_string(reader, size)?)
+                    creation_date = Some(BinaryReader::read_fixed_length_string(data, length)?)
                 }
-                b"IENG" => author = Some(BinaryReader::read_fixed_length_string(reader, size)?),
+                b"IENG" => author = Some(BinaryReader::read_fixed_length_string(data, length)?),
                 b"IPRD" => {
-                    target_product = Some(BinaryReader::read_fixed_length_string(reader, size)?)
+                    target_product = Some(BinaryReader::read_fixed_length_string(data, length)?)
                 }
-                b"ICOP" => copyright = Some(BinaryReader::read_fixed_length_string(reader, size)?),
-                b"ICMT" => comments = Some(BinaryReader::read_fixed_length_string(reader, size)?),
-                b"ISFT" => tools = Some(BinaryReader::read_fixed_length_string(reader, size)?),
-                _ => return Err(SoundFontError::ListContainsUnknownId(id)),
+                b"ICOP" => copyright = Some(BinaryReader::read_fixed_length_string(data, length)?),
+                b"ICMT" => comments = Some(BinaryReader::read_fixed_length_string(data, length)?),
+                b"ISFT" => tools = Some(BinaryReader::read_fixed_length_string(data, length)?),
+                _ => warnings.push(format!("the unknown '{id}' sub-chunk was skipped")),
             }
         }
 
@@ -109,6 +126,22 @@ impl SoundFontInfo {
         })
     }
 
+    pub(crate) fn empty() -> Self {
+        Self {
+            version: SoundFontVersion::default(),
+            target_sound_engine: String::new(),
+            bank_name: String::new(),
+            rom_name: String::new(),
+            rom_version: SoundFontVersion::default(),
+            creation_date: String::new(),
+            author: String::new(),
+            target_product: String::new(),
+            copyright: String::new(),
+            comments: String::new(),
+            tools: String::new(),
+        }
+    }
+
     /// Gets the version of the SoundFont.
     pub fn get_version(&self) -> &SoundFontVersion {
         &self.version
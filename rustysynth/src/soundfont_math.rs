@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
-use std::f32::consts;
+use core::f32::consts;
+
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
 
 #[allow(unused)]
 #[non_exhaustive]
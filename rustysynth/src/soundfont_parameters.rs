@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::binary_reader::BinaryReader;
 use crate::error::SoundFontError;
@@ -8,10 +9,13 @@ use crate::four_cc::FourCC;
 use crate::generator::Generator;
 use crate::instrument::Instrument;
 use crate::instrument_info::InstrumentInfo;
+use crate::io::Read;
+use crate::modulator::Modulator;
 use crate::preset::Preset;
 use crate::preset_info::PresetInfo;
 use crate::read_counter::ReadCounter;
 use crate::sample_header::SampleHeader;
+use crate::soundfont_sampledata::SoundFontSampleData;
 use crate::zone::Zone;
 use crate::zone_info::ZoneInfo;
 
@@ -23,28 +27,22 @@ pub(crate) struct SoundFontParameters {
 }
 
 impl SoundFontParameters {
-    pub(crate) fn new<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
-        let chunk_id = BinaryReader::read_four_cc(reader)?;
-        if chunk_id != b"LIST" {
-            return Err(SoundFontError::ListChunkNotFound);
-        }
-
-        let end = BinaryReader::read_u32(reader)? as usize;
+    pub(crate) fn new<R: Read>(
+        reader: &mut R,
+        end: usize,
+        #[cfg_attr(not(feature = "sf3"), allow(unused_variables))]
+        sample_data: &mut SoundFontSampleData,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, SoundFontError> {
         let reader = &mut ReadCounter::new(reader);
 
-        let list_type = BinaryReader::read_four_cc(reader)?;
-        if list_type != b"pdta" {
-            return Err(SoundFontError::InvalidListChunkType {
-                expected: FourCC::from_bytes(*b"pdta"),
-                actual: list_type,
-            });
-        }
-
         let mut preset_infos: Option<Vec<PresetInfo>> = None;
         let mut preset_bag: Option<Vec<ZoneInfo>> = None;
+        let mut preset_modulators: Vec<Modulator> = Vec::new();
         let mut preset_generators: Option<Vec<Generator>> = None;
         let mut instrument_infos: Option<Vec<InstrumentInfo>> = None;
         let mut instrument_bag: Option<Vec<ZoneInfo>> = None;
+        let mut instrument_modulators: Vec<Modulator> = Vec::new();
         let mut instrument_generators: Option<Vec<Generator>> = None;
         let mut sample_headers: Option<Vec<SampleHeader>> = None;
 
@@ -55,14 +53,26 @@ impl SoundFontParameters {
             match id.as_bytes() {
                 b"phdr" => preset_infos = Some(PresetInfo::read_from_chunk(reader, size)?),
                 b"pbag" => preset_bag = Some(ZoneInfo::read_from_chunk(reader, size)?),
-                b"pmod" => BinaryReader::discard_data(reader, size)?,
+                b"pmod" if size.is_multiple_of(10) => {
+                    preset_modulators = Modulator::read_from_chunk(reader, size)?
+                }
                 b"pgen" => preset_generators = Some(Generator::read_from_chunk(reader, size)?),
                 b"inst" => instrument_infos = Some(InstrumentInfo::read_from_chunk(reader, size)?),
                 b"ibag" => instrument_bag = Some(ZoneInfo::read_from_chunk(reader, size)?),
-                b"imod" => BinaryReader::discard_data(reader, size)?,
+                b"imod" if size.is_multiple_of(10) => {
+                    instrument_modulators = Modulator::read_from_chunk(reader, size)?
+                }
                 b"igen" => instrument_generators = Some(Generator::read_from_chunk(reader, size)?),
                 b"shdr" => sample_headers = Some(SampleHeader::read_from_chunk(reader, size)?),
-                _ => return Err(SoundFontError::ListContainsUnknownId(id)),
+                // The modulators are optional, so a malformed list is ignored.
+                b"pmod" | b"imod" => {
+                    reader.skip_sub_chunk(id, size, end, warnings)?;
+                    warnings.push(format!("the malformed '{id}' sub-chunk was skipped"));
+                }
+                _ => {
+                    reader.skip_sub_chunk(id, size, end, warnings)?;
+                    warnings.push(format!("the unknown '{id}' sub-chunk was skipped"));
+                }
             }
         }
 
@@ -94,11 +104,19 @@ impl SoundFontParameters {
             FourCC::from_bytes(*b"SHDR"),
         ))?;
 
-        let instrument_zones = Zone::create(&instrument_bag, &instrument_generators)?;
+        // The regions copy the positions of the samples, so they are decoded first.
+        #[cfg(feature = "sf3")]
+        let sample_headers = sample_data.decompress(sample_headers)?;
+
+        let instrument_zones = Zone::create(
+            &instrument_bag,
+            &instrument_generators,
+            &instrument_modulators,
+        )?;
         let instruments =
             Instrument::create(&instrument_infos, &instrument_zones, &sample_headers)?;
 
-        let preset_zones = Zone::create(&preset_bag, &preset_generators)?;
+        let preset_zones = Zone::create(&preset_bag, &preset_generators, &preset_modulators)?;
         let presets = Preset::create(&preset_infos, &preset_zones, &instruments)?;
 
         Ok(Self {
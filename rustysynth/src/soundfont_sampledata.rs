@@ -1,46 +1,98 @@
 #![allow(dead_code)]
 
-use std::io::Read;
+use alloc::string::String;
+#[cfg(feature = "mmap")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+#[cfg(all(feature = "mmap", target_endian = "little"))]
+use core::slice;
 
 use crate::binary_reader::BinaryReader;
 use crate::error::SoundFontError;
+#[cfg(feature = "mmap")]
 use crate::four_cc::FourCC;
+use crate::io::Read;
 use crate::read_counter::ReadCounter;
+#[cfg(feature = "sf3")]
+use crate::sample_header::SampleHeader;
+
+// The samples of a SoundFont, which are decoded into memory or read in place from a memory-mapped file.
+// The samples are read in place only on a little-endian target, where they are laid out as in the file.
+#[derive(Debug)]
+pub(crate) enum WaveData {
+    Owned(Vec<i16>),
+    #[cfg(all(feature = "mmap", target_endian = "little"))]
+    Mapped {
+        map: Arc<memmap2::Mmap>,
+        offset: usize,
+        length: usize,
+    },
+}
+
+impl Deref for WaveData {
+    type Target = [i16];
+
+    fn deref(&self) -> &[i16] {
+        match self {
+            WaveData::Owned(data) => data,
+            #[cfg(all(feature = "mmap", target_endian = "little"))]
+            WaveData::Mapped {
+                map,
+                offset,
+                length,
+            } => {
+                // SAFETY: The map is page-aligned and the offset is even, so the samples are aligned,
+                // and the map holds the length samples after the offset, as checked by map_samples.
+                unsafe { slice::from_raw_parts(map.as_ptr().add(*offset) as *const i16, *length) }
+            }
+        }
+    }
+}
 
 #[non_exhaustive]
 pub(crate) struct SoundFontSampleData {
     pub bits_per_sample: i32,
-    pub wave_data: Vec<i16>,
+    pub wave_data: WaveData,
+    // The Ogg Vorbis streams of a SoundFont3, decoded by decompress.
+    #[cfg(feature = "sf3")]
+    pub compressed_data: Option<Vec<u8>>,
 }
 
 impl SoundFontSampleData {
-    pub(crate) fn new<R: Read>(reader: &mut R) -> Result<Self, SoundFontError> {
-        let chunk_id = BinaryReader::read_four_cc(reader)?;
-        if chunk_id != b"LIST" {
-            return Err(SoundFontError::ListChunkNotFound);
-        }
+    // The number of zero samples which the SoundFont specification puts after each sample.
+    #[cfg(feature = "sf3")]
+    const SAMPLE_PADDING: usize = 46;
 
-        let end = BinaryReader::read_u32(reader)? as usize;
+    pub(crate) fn new<R: Read>(
+        reader: &mut R,
+        end: usize,
+        is_sf3: bool,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, SoundFontError> {
         let reader = &mut ReadCounter::new(reader);
 
-        let list_type = BinaryReader::read_four_cc(reader)?;
-        if list_type != b"sdta" {
-            return Err(SoundFontError::InvalidListChunkType {
-                expected: FourCC::from_bytes(*b"sdta"),
-                actual: list_type,
-            });
-        }
-
         let mut wave_data: Option<Vec<i16>> = None;
+        let mut size_in_bytes: usize = 0;
 
         while reader.bytes_read() < end {
             let id = BinaryReader::read_four_cc(reader)?;
             let size = BinaryReader::read_u32(reader)? as usize;
 
             match id.as_bytes() {
-                b"smpl" => wave_data = Some(BinaryReader::read_wave_data(reader, size)?),
-                b"sm24" => BinaryReader::discard_data(reader, size)?,
-                _ => return Err(SoundFontError::ListContainsUnknownId(id)),
+                b"smpl" => {
+                    wave_data = Some(BinaryReader::read_wave_data(reader, size)?);
+                    size_in_bytes = size;
+                    // RIFF chunks are padded to an even size.
+                    if size & 1 == 1 && reader.bytes_read() < end {
+                        BinaryReader::discard_data(reader, 1)?;
+                    }
+                }
+                b"sm24" => reader.skip_sub_chunk(id, size, end, warnings)?,
+                _ => {
+                    reader.skip_sub_chunk(id, size, end, warnings)?;
+                    warnings.push(format!("the unknown '{id}' sub-chunk was skipped"));
+                }
             }
         }
 
@@ -53,14 +105,210 @@ impl SoundFontSampleData {
         }
 
         // SoundFont3 compressed sample format
-        let four_cc = unsafe { std::slice::from_raw_parts(wave_data.as_ptr() as *const u8, 4) };
-        if four_cc == b"OggS" {
+        let data =
+            unsafe { core::slice::from_raw_parts(wave_data.as_ptr() as *const u8, size_in_bytes) };
+        if is_sf3 || data.starts_with(b"OggS") {
+            #[cfg(feature = "sf3")]
+            return Ok(Self {
+                bits_per_sample: 16,
+                wave_data: WaveData::Owned(Vec::new()),
+                compressed_data: Some(data.to_vec()),
+            });
+
+            #[cfg(not(feature = "sf3"))]
             return Err(SoundFontError::UnsupportedSampleFormat);
         }
 
         Ok(Self {
             bits_per_sample: 16,
-            wave_data,
+            wave_data: WaveData::Owned(wave_data),
+            #[cfg(feature = "sf3")]
+            compressed_data: None,
         })
     }
+
+    // Finds the samples in the sdta list, whose body of the size is at the offset of the map,
+    // so that they are read in place instead of being copied.
+    // The samples which cannot be read in place, which are the compressed ones of a SoundFont3,
+    // those at an odd offset, and all of them on a big-endian target, and a broken list are loaded by new as usual.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn map_samples(
+        map: &Arc<memmap2::Mmap>,
+        start: usize,
+        end: usize,
+        is_sf3: bool,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, SoundFontError> {
+        let data = &map[start.min(map.len())..(start + end).min(map.len())];
+
+        let mut samples: Option<(usize, usize)> = None;
+        let mut unknown: Vec<FourCC> = Vec::new();
+        let mut position: usize = 0;
+        let mut broken = data.len() < end;
+        while position < data.len() && !broken {
+            if data.len() - position < 8 {
+                broken = true;
+                break;
+            }
+            let id = FourCC::from_bytes(data[position..position + 4].try_into().unwrap());
+            let size = u32::from_le_bytes(data[position + 4..position + 8].try_into().unwrap());
+            let size = size as usize;
+            position += 8;
+            if size > data.len() - position {
+                broken = true;
+                break;
+            }
+
+            match id.as_bytes() {
+                b"smpl" => {
+                    samples = Some((position, size));
+                    // RIFF chunks are padded to an even size.
+                    position += size & 1;
+                }
+                b"sm24" => (),
+                _ => unknown.push(id),
+            }
+            position += size;
+        }
+
+        let in_place = samples.filter(|&(offset, size)| {
+            !broken
+                && !is_sf3
+                && (start + offset).is_multiple_of(2)
+                && size >= 4
+                && !data[offset..].starts_with(b"OggS")
+        });
+
+        #[cfg(target_endian = "little")]
+        if let Some((offset, size)) = in_place {
+            for id in unknown {
+                warnings.push(format!("the unknown '{id}' sub-chunk was skipped"));
+            }
+
+            return Ok(Self {
+                bits_per_sample: 16,
+                wave_data: WaveData::Mapped {
+                    map: Arc::clone(map),
+                    offset: start + offset,
+                    length: size / 2,
+                },
+                #[cfg(feature = "sf3")]
+                compressed_data: None,
+            });
+        }
+        // Nothing is read in place on a big-endian target.
+        #[cfg(not(target_endian = "little"))]
+        let _ = (in_place, unknown);
+
+        SoundFontSampleData::new(&mut &data[..], end, is_sf3, warnings)
+    }
+
+    // Decodes the samples of a SoundFont3 into the wave data, and rewrites the sample headers
+    // to point into it as those of a SoundFont2, so that the rest of the synthesizer is unchanged.
+    //
+    // The start and end of a compressed sample are byte offsets of its Ogg Vorbis stream,
+    // and its loop points are relative to its start. An uncompressed sample is copied as it is.
+    #[cfg(feature = "sf3")]
+    pub(crate) fn decompress(
+        &mut self,
+        mut sample_headers: Vec<SampleHeader>,
+    ) -> Result<Vec<SampleHeader>, SoundFontError> {
+        let Some(data) = self.compressed_data.take() else {
+            return Ok(sample_headers);
+        };
+
+        let mut wave_data: Vec<i16> = Vec::new();
+        for (index, header) in sample_headers.iter_mut().enumerate() {
+            let offset = wave_data.len() as i32;
+            let start = header.start.max(0) as usize;
+
+            let (start_loop, end_loop) = if header.sample_type & SampleHeader::COMPRESSED_FLAG != 0
+            {
+                let stream = data.get(start..).unwrap_or(&[]);
+                SoundFontSampleData::decode_vorbis(stream, &mut wave_data)
+                    .map_err(|_| SoundFontError::InvalidCompressedSample(index))?;
+                header.sample_type &= !SampleHeader::COMPRESSED_FLAG;
+                (header.start_loop, header.end_loop)
+            } else {
+                let end = (header.end.max(0) as usize).clamp(start, data.len() / 2);
+                let pcm = data.get(2 * start..2 * end).unwrap_or(&[]);
+                wave_data.extend(
+                    pcm.chunks_exact(2)
+                        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])),
+                );
+                (
+                    header.start_loop - header.start,
+                    header.end_loop - header.start,
+                )
+            };
+
+            header.start = offset;
+            header.end = wave_data.len() as i32;
+            header.start_loop = offset + start_loop;
+            header.end_loop = offset + end_loop;
+
+            wave_data.resize(wave_data.len() + SoundFontSampleData::SAMPLE_PADDING, 0);
+        }
+
+        self.wave_data = WaveData::Owned(wave_data);
+
+        Ok(sample_headers)
+    }
+
+    // Decodes the Ogg Vorbis stream at the start of the data, appending its first channel.
+    #[cfg(feature = "sf3")]
+    fn decode_vorbis(data: &[u8], wave_data: &mut Vec<i16>) -> Result<(), lewton::VorbisError> {
+        let (length, frame_count) = SoundFontSampleData::get_ogg_stream_length(data);
+        let start = wave_data.len();
+        let mut reader =
+            lewton::inside_ogg::OggStreamReader::new(std::io::Cursor::new(&data[..length]))?;
+        let channel_count = (reader.ident_hdr.audio_channels as usize).max(1);
+
+        while let Some(packet) = reader.read_dec_packet_itl()? {
+            wave_data.extend(packet.iter().step_by(channel_count));
+        }
+
+        // The last packet is decoded in full, past the end of the sample.
+        if let Some(frame_count) = frame_count {
+            wave_data.truncate(start + frame_count);
+        }
+
+        Ok(())
+    }
+
+    // Gets the length of the Ogg stream at the start of the data, up to its last page,
+    // as the stream of the next sample follows it directly.
+    // The granule position of the last page, which is the number of frames, is returned with it.
+    #[cfg(feature = "sf3")]
+    fn get_ogg_stream_length(data: &[u8]) -> (usize, Option<usize>) {
+        const HEADER_SIZE: usize = 27;
+        const END_OF_STREAM: u8 = 0x04;
+
+        let mut position: usize = 0;
+        let mut frame_count: Option<usize> = None;
+        while data.len() >= position + HEADER_SIZE && &data[position..position + 4] == b"OggS" {
+            let header_type = data[position + 5];
+            let mut granule_position = [0_u8; 8];
+            granule_position.copy_from_slice(&data[position + 6..position + 14]);
+            let granule_position = i64::from_le_bytes(granule_position);
+            let segment_count = data[position + 26] as usize;
+            let segments =
+                match data.get(position + HEADER_SIZE..position + HEADER_SIZE + segment_count) {
+                    Some(value) => value,
+                    None => break,
+                };
+            let body_size: usize = segments.iter().map(|&size| size as usize).sum();
+
+            position += HEADER_SIZE + segment_count + body_size;
+            // A page where no packet ends has the granule position of -1.
+            if granule_position >= 0 {
+                frame_count = Some(granule_position as usize);
+            }
+            if header_type & END_OF_STREAM != 0 {
+                break;
+            }
+        }
+
+        (position.min(data.len()), frame_count)
+    }
 }
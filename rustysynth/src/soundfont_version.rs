@@ -1,9 +1,8 @@
 #![allow(dead_code)]
 
-use std::io;
-use std::io::Read;
-
 use crate::binary_reader::BinaryReader;
+use crate::io;
+use crate::io::Read;
 
 /// Reperesents the version of a SoundFont.
 #[derive(Debug)]
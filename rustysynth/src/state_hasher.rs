@@ -0,0 +1,58 @@
+#![allow(dead_code)]
+
+// Hashes the internal state for `Synthesizer::state_fingerprint` and the MIDI file identity.
+// FNV-1a is used instead of the std hasher so that the value is stable across builds.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StateHasher {
+    hash: u64,
+}
+
+impl StateHasher {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            hash: StateHasher::OFFSET_BASIS,
+        }
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(StateHasher::PRIME);
+        }
+    }
+
+    pub(crate) fn write_bool(&mut self, value: bool) {
+        self.write(&[value as u8]);
+    }
+
+    pub(crate) fn write_i32(&mut self, value: i32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    // The sizes are hashed as 64-bit values to give the same hash on any platform.
+    pub(crate) fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    pub(crate) fn write_f32(&mut self, value: f32) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    pub(crate) fn write_f64(&mut self, value: f64) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    pub(crate) fn write_f32_slice(&mut self, values: &[f32]) {
+        self.write_usize(values.len());
+        for value in values {
+            self.write_f32(*value);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.hash
+    }
+}
@@ -1,30 +1,68 @@
 #![allow(dead_code)]
 
-use std::cmp;
-use std::collections::HashMap;
-use std::sync::Arc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
 
+use crate::arpeggiator::ArpConfig;
+use crate::arpeggiator::Arpeggiator;
 use crate::array_math::ArrayMath;
+use crate::audio_effect::AudioEffect;
+use crate::bi_quad_filter::BiQuadFilter;
 use crate::channel::Channel;
+#[cfg(feature = "introspection")]
+use crate::channel_info::ChannelInfo;
+use crate::channel_routing::ChannelRouting;
 use crate::chorus::Chorus;
+#[cfg(feature = "std")]
+use crate::command_queue::CommandQueue;
+use crate::dither::Dither;
+use crate::dither_mode::DitherMode;
 use crate::error::SynthesizerError;
+use crate::error::TuningError;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::limiter_mode::LimiterMode;
+use crate::midi_recording::MidiRecording;
+use crate::mpe_zone::MpeZone;
+use crate::note;
 use crate::region_pair::RegionPair;
+use crate::retrigger_mode::RetriggerMode;
 use crate::reverb::Reverb;
 use crate::soundfont::SoundFont;
 use crate::soundfont_math::SoundFontMath;
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
+use crate::synthesizer_state::SynthesizerState;
+use crate::tuning::Tuning;
+use crate::voice::Voice;
 use crate::voice_collection::VoiceCollection;
+#[cfg(feature = "introspection")]
+use crate::voice_info::VoiceInfo;
+use crate::voice_stealing_policy::VoiceStealingPolicy;
 
 /// SoundFont synthesizer with per-channel MIDI state, voice allocation, and optional reverb/chorus.
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct Synthesizer {
-    pub(crate) sound_font: Arc<SoundFont>,
+    // The stack of the SoundFonts from the bottom, where the presets are looked up from the top,
+    // with the linear gain of each.
+    pub(crate) sound_fonts: Vec<(Arc<SoundFont>, f32)>,
+    // The SoundFonts taken out of the stack, kept until `collect_garbage`
+    // so that the voices still playing them never free them on the render thread.
+    retired_sound_fonts: Vec<Arc<SoundFont>>,
     pub(crate) sample_rate: i32,
     pub(crate) block_size: usize,
     pub(crate) maximum_polyphony: usize,
+    pub(crate) retrigger_mode: RetriggerMode,
+    pub(crate) reset_phase_on_note_on: bool,
+    pub(crate) smooth_controllers: bool,
+    pub(crate) use_note_off_velocity: bool,
+    pub(crate) crossfade_program_change_ms: f32,
 
-    preset_lookup: HashMap<i32, usize>,
+    // The default preset of the bottom SoundFont.
     default_preset: usize,
 
     channels: Vec<Channel>,
@@ -33,54 +71,93 @@ pub struct Synthesizer {
 
     block_left: Vec<f32>,
     block_right: Vec<f32>,
+    // The stereo mix of each channel, laid out channel by channel,
+    // which is mixed only once `render_multichannel` has been called.
+    channel_block_left: Vec<f32>,
+    channel_block_right: Vec<f32>,
+    channel_mix: bool,
 
     inverse_block_size: f32,
 
     block_read: usize,
 
+    channel_levels: [f32; Synthesizer::CHANNEL_COUNT],
+    // The peak of the last rendered block, including the effects.
+    // This is measured only when no voice is active, as it is needed only for the idle check.
+    output_peak: f32,
+
     master_volume: f32,
 
+    tuning: Tuning,
+    // The tunings given to the channels by the host, which take precedence over the others.
+    channel_tunings: Vec<Option<Tuning>>,
+    // The tunings sent by the MTS messages, by the bank and the program.
+    mts_tunings: BTreeMap<(u8, u8), Tuning>,
+    // The frequency of A4, and the resulting pitch offset in semitones from 440 Hz.
+    tuning_reference: f64,
+    tuning_reference_offset: f32,
+
+    pub(crate) dither: Dither,
+
     effects: Option<Effects>,
+    // The effects of each channel, which are allocated once a routing asks for them,
+    // whether they are used, whether the current block ran them,
+    // and the peak of the return of each channel in the last block where it ran.
+    channel_effects: Vec<Effects>,
+    channel_effects_enabled: bool,
+    channel_effects_block: bool,
+    channel_effect_peaks: [f32; Synthesizer::CHANNEL_COUNT],
+    // The custom effects, run in order after the built-in ones.
+    custom_effects: Vec<Box<dyn AudioEffect>>,
+
+    master_lowpass: MasterLowPass,
+    output_stage: OutputStage,
+
+    // The clock of the arpeggiators in BPM.
+    tempo: f64,
+    arpeggiators: Vec<Option<Arpeggiator>>,
+
+    debug_solo_region: Option<usize>,
+
+    // The messages scheduled by process_midi_message_at, in order,
+    // with their offsets in samples from the start of the next block.
+    scheduled_messages: Vec<(usize, [i32; 4])>,
+    // The offset within the block of the voices started now.
+    start_delay: usize,
+
+    // The position in samples of the end of the current block, which is the clock of the recording,
+    // and the messages captured since start_recording.
+    block_end: u64,
+    recording: Option<MidiRecording>,
 }
 
 impl Synthesizer {
     pub const CHANNEL_COUNT: usize = 16;
     pub const PERCUSSION_CHANNEL: usize = 9;
 
+    const DEFAULT_MASTER_VOLUME: f32 = 0.5_f32;
+    const DEFAULT_TEMPO: f64 = 120.0;
+    // The output below this level (-100 dB) is regarded as silence.
+    const SILENCE_THRESHOLD: f32 = 1.0e-5_f32;
+
+    // The messages which can be scheduled ahead without allocating.
+    const SCHEDULED_MESSAGE_CAPACITY: usize = 1024;
+
     pub fn new(
         sound_font: &Arc<SoundFont>,
         settings: &SynthesizerSettings,
     ) -> Result<Self, SynthesizerError> {
         settings.validate()?;
 
-        let mut preset_lookup: HashMap<i32, usize> = HashMap::new();
-
-        let mut min_preset_id = i32::MAX;
-        let mut default_preset: usize = 0;
-        for i in 0..sound_font.presets.len() {
-            let preset = &sound_font.presets[i];
-
-            // The preset ID is Int32, where the upper 16 bits represent the bank number
-            // and the lower 16 bits represent the patch number.
-            // This ID is used to search for presets by the combination of bank number
-            // and patch number.
-            let preset_id = (preset.bank_number << 16) | preset.patch_number;
-            preset_lookup.insert(preset_id, i);
-
-            // The preset with the minimum ID number will be default.
-            // If the SoundFont is GM compatible, the piano will be chosen.
-            if preset_id < min_preset_id {
-                default_preset = i;
-                min_preset_id = preset_id;
-            }
-        }
+        let default_preset = Synthesizer::find_default_preset(sound_font);
 
         let mut channels: Vec<Channel> = Vec::new();
         for i in 0..Synthesizer::CHANNEL_COUNT {
             channels.push(Channel::new(i == Synthesizer::PERCUSSION_CHANNEL));
         }
 
-        let voices = VoiceCollection::new(settings);
+        let mut voices = VoiceCollection::new(settings);
+        voices.reserve_modulators(sound_font.get_max_region_modulator_count());
 
         let block_left: Vec<f32> = vec![0_f32; settings.block_size];
         let block_right: Vec<f32> = vec![0_f32; settings.block_size];
@@ -89,29 +166,57 @@ impl Synthesizer {
 
         let block_read = settings.block_size;
 
-        let master_volume = 0.5_f32;
+        let master_volume = Synthesizer::DEFAULT_MASTER_VOLUME;
 
-        let effects = if settings.enable_reverb_and_chorus {
-            Some(Effects::new(settings))
-        } else {
-            None
-        };
+        let effects = Effects::new(settings);
+
+        let master_lowpass = MasterLowPass::new(settings);
 
         Ok(Self {
-            sound_font: Arc::clone(sound_font),
+            sound_fonts: vec![(Arc::clone(sound_font), 1_f32)],
+            retired_sound_fonts: Vec::new(),
             sample_rate: settings.sample_rate,
             block_size: settings.block_size,
             maximum_polyphony: settings.maximum_polyphony,
-            preset_lookup,
+            retrigger_mode: settings.retrigger_mode,
+            reset_phase_on_note_on: settings.reset_phase_on_note_on,
+            smooth_controllers: settings.smooth_controllers,
+            use_note_off_velocity: settings.use_note_off_velocity,
+            crossfade_program_change_ms: settings.crossfade_program_change_ms,
             default_preset,
             channels,
             voices,
             block_left,
             block_right,
+            channel_block_left: vec![0_f32; Synthesizer::CHANNEL_COUNT * settings.block_size],
+            channel_block_right: vec![0_f32; Synthesizer::CHANNEL_COUNT * settings.block_size],
+            channel_mix: false,
             inverse_block_size,
             block_read,
+            channel_levels: [0_f32; Synthesizer::CHANNEL_COUNT],
+            output_peak: 0_f32,
             master_volume,
+            tuning: Tuning::equal_temperament(),
+            channel_tunings: vec![None; Synthesizer::CHANNEL_COUNT],
+            mts_tunings: BTreeMap::new(),
+            tuning_reference: note::DEFAULT_A4_FREQUENCY,
+            tuning_reference_offset: 0_f32,
+            dither: Dither::new(0),
             effects,
+            channel_effects: Vec::new(),
+            channel_effects_enabled: false,
+            channel_effects_block: false,
+            channel_effect_peaks: [0_f32; Synthesizer::CHANNEL_COUNT],
+            custom_effects: Vec::new(),
+            master_lowpass,
+            output_stage: OutputStage::new(settings),
+            tempo: Synthesizer::DEFAULT_TEMPO,
+            arpeggiators: vec![None; Synthesizer::CHANNEL_COUNT],
+            debug_solo_region: None,
+            scheduled_messages: Vec::with_capacity(Synthesizer::SCHEDULED_MESSAGE_CAPACITY),
+            start_delay: 0,
+            block_end: 0,
+            recording: None,
         })
     }
 
@@ -120,371 +225,5313 @@ impl Synthesizer {
             return;
         }
 
+        let time = self.get_sample_position();
+        if let Some(recording) = &mut self.recording {
+            recording.add_message(time, channel, command, data1, data2);
+        }
+
+        self.dispatch_midi_message(channel, command, data1, data2);
+    }
+
+    // Processes a message without recording it, as the messages forwarded to the MPE members
+    // and the scheduled messages were recorded when they were given.
+    fn dispatch_midi_message(&mut self, channel: i32, command: i32, data1: i32, data2: i32) {
         let channel_info = &mut self.channels[channel as usize];
 
         match command {
-            0x80 => self.note_off(channel, data1),       // Note Off
-            0x90 => self.note_on(channel, data1, data2), // Note On
-            0xB0 => match data1 // Controller
-            {
-                0x00 => channel_info.set_bank(data2), // Bank Selection
-                0x01 => channel_info.set_modulation_coarse(data2), // Modulation Coarse
-                0x21 => channel_info.set_modulation_fine(data2), // Modulation Fine
-                0x06 => channel_info.data_entry_coarse(data2), // Data Entry Coarse
-                0x26 => channel_info.data_entry_fine(data2), // Data Entry Fine
-                0x07 => channel_info.set_volume_coarse(data2), // Channel Volume Coarse
-                0x27 => channel_info.set_volume_fine(data2), // Channel Volume Fine
-                0x0A => channel_info.set_pan_coarse(data2), // Pan Coarse
-                0x2A => channel_info.set_pan_fine(data2), // Pan Fine
-                0x0B => channel_info.set_expression_coarse(data2), // Expression Coarse
-                0x2B => channel_info.set_expression_fine(data2), // Expression Fine
-                0x40 => channel_info.set_hold_pedal(data2), // Hold Pedal
-                0x5B => channel_info.set_reverb_send(data2), // Reverb Send
-                0x5D => channel_info.set_chorus_send(data2), // Chorus Send
-                0x63 => channel_info.set_nrpn_coarse(data2), // NRPN Coarse
-                0x62 => channel_info.set_nrpn_fine(data2), // NRPN Fine
-                0x65 => channel_info.set_rpn_coarse(data2), // RPN Coarse
-                0x64 => channel_info.set_rpn_fine(data2), // RPN Fine
-                0x78 => self.note_off_all_channel(channel, true), // All Sound Off
-                0x79 => self.reset_all_controllers_channel(channel), // Reset All Controllers
-                0x7B => self.note_off_all_channel(channel, false), // All Note Off
-                _ => (),
-            },
-            0xC0 => channel_info.set_patch(data1), // Program Change
+            0x80 => self.note_off_with_velocity(channel, data1, data2), // Note Off
+            0x90 => self.note_on(channel, data1, data2),                // Note On
+            0xA0 => channel_info.set_key_pressure(data1, data2),        // Polyphonic Key Pressure
+            0xB0 => {
+                // Controller
+                channel_info.set_controller(data1, data2);
+                match data1 {
+                    0x00 => channel_info.set_bank(data2), // Bank Selection
+                    0x01 => channel_info.set_modulation_coarse(data2), // Modulation Coarse
+                    0x21 => channel_info.set_modulation_fine(data2), // Modulation Fine
+                    0x06 => channel_info.data_entry_coarse(data2), // Data Entry Coarse
+                    0x26 => channel_info.data_entry_fine(data2), // Data Entry Fine
+                    0x60 => channel_info.data_increment(1), // Data Increment
+                    0x61 => channel_info.data_increment(-1), // Data Decrement
+                    0x07 => channel_info.set_volume_coarse(data2), // Channel Volume Coarse
+                    0x27 => channel_info.set_volume_fine(data2), // Channel Volume Fine
+                    0x0A => channel_info.set_pan_coarse(data2), // Pan Coarse
+                    0x2A => channel_info.set_pan_fine(data2), // Pan Fine
+                    0x0B => channel_info.set_expression_coarse(data2), // Expression Coarse
+                    0x2B => channel_info.set_expression_fine(data2), // Expression Fine
+                    0x40 => channel_info.set_hold_pedal(data2), // Hold Pedal
+                    0x42 => {
+                        // Sostenuto
+                        if channel_info.set_sostenuto(data2) {
+                            for voice in self.voices.get_active_voices().iter_mut() {
+                                if voice.channel() == channel {
+                                    voice.capture_sostenuto();
+                                }
+                            }
+                        }
+                    }
+                    0x43 => channel_info.set_soft_pedal(data2), // Soft Pedal
+                    0x54 => channel_info.set_portamento_control(data2), // Portamento Control
+                    0x5B => channel_info.set_reverb_send(data2), // Reverb Send
+                    0x5D => channel_info.set_chorus_send(data2), // Chorus Send
+                    0x63 => channel_info.set_nrpn_coarse(data2), // NRPN Coarse
+                    0x62 => channel_info.set_nrpn_fine(data2),  // NRPN Fine
+                    0x65 => channel_info.set_rpn_coarse(data2), // RPN Coarse
+                    0x64 => channel_info.set_rpn_fine(data2),   // RPN Fine
+                    0x78 => self.note_off_all_channel(channel, true), // All Sound Off
+                    0x79 => self.reset_all_controllers_channel(channel), // Reset All Controllers
+                    0x7B => self.note_off_all_channel(channel, false), // All Note Off
+                    0x7E | 0x7F => {
+                        // Mono Mode On / Poly Mode On, which also turn off all the notes
+                        channel_info.set_mono_mode(data1 == 0x7E);
+                        self.note_off_all_channel(channel, false);
+                    }
+                    _ => (),
+                }
+            }
+            0xC0 => {
+                // Program Change
+                channel_info.set_patch(data1);
+                self.fade_out_channel(channel);
+            }
+            0xD0 => channel_info.set_channel_pressure(data1), // Channel Pressure
             0xE0 => channel_info.set_pitch_bend(data1, data2), // Pitch Bend
             _ => (),
         }
-    }
 
-    pub fn note_off(&mut self, channel: i32, key: i32) {
-        if !(0 <= channel && channel < self.channels.len() as i32) {
+        if command == 0xB0 && (data1 == 0x0B || data1 == 0x2B || data1 == 0x79) {
+            self.update_linked_expression(channel);
+        }
+
+        // The MPE configuration message: RPN 6 on the channel 1 or 16.
+        if command == 0xB0
+            && data1 == 0x06
+            && (channel == 0 || channel == 15)
+            && self.channels[channel as usize].get_rpn() == 6
+        {
+            let zone = if channel == 0 {
+                MpeZone::Lower
+            } else {
+                MpeZone::Upper
+            };
+            self.configure_mpe_zone(zone, data2 as usize);
             return;
         }
 
-        for voice in self.voices.get_active_voices().iter_mut() {
-            if voice.channel() == channel && voice.key() == key {
-                voice.end();
+        // The controllers and program changes on the master channel of an MPE zone apply to all its member channels,
+        // except the RPN and NRPN messages, which are only for the master channel.
+        let forward = match command {
+            0xB0 => !matches!(data1, 0x06 | 0x26 | 0x60..=0x65),
+            0xC0 => true,
+            _ => false,
+        };
+        if forward {
+            for member in 0..self.channels.len() {
+                if self.channels[member].get_mpe_master() == Some(channel as usize) {
+                    self.dispatch_midi_message(member as i32, command, data1, data2);
+                }
             }
         }
     }
 
-    pub fn note_on(&mut self, channel: i32, key: i32, velocity: i32) {
-        if velocity == 0 {
-            self.note_off(channel, key);
+    /// Configures an MPE (MIDI Polyphonic Expression) zone with the number of its member channels,
+    /// or removes the zone if `member_count` is `0`.
+    ///
+    /// The lower zone has the channel 1 as its master channel and the channels above it as the members,
+    /// and the upper zone has the channel 16 as its master channel and the channels below it as the members.
+    /// The count is limited to 15, and the other zone is shrunk if the two would overlap.
+    /// The same configuration is made by the MPE configuration message (RPN 6) on the channel 1 or 16.
+    ///
+    /// On a member channel, the pitch bend defaults to a range of 48 semitones and is added to
+    /// that of the master channel, and the channel pressure raises the volume by up to 6 dB,
+    /// so that each note played on its own channel is expressed by them, along with the CC 74
+    /// which moves the filter cutoff as on any channel. The controllers and program changes
+    /// on the master channel are applied to the member channels as well.
+    /// The drum mode of the channels is not changed.
+    /// The configuration is kept across `reset`.
+    pub fn configure_mpe_zone(&mut self, zone: MpeZone, member_count: usize) {
+        let last = self.channels.len() - 1;
+        let member_count = member_count.min(last);
+        let count = |master: usize| {
+            self.channels
+                .iter()
+                .filter(|channel| channel.get_mpe_master() == Some(master))
+                .count()
+        };
+        let (lower, upper) = match zone {
+            MpeZone::Lower => (
+                member_count,
+                count(last).min((last - 1).saturating_sub(member_count)),
+            ),
+            MpeZone::Upper => (
+                count(0).min((last - 1).saturating_sub(member_count)),
+                member_count,
+            ),
+        };
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            let master = if 1 <= i && i <= lower {
+                Some(0)
+            } else if last - upper <= i && i < last {
+                Some(last)
+            } else {
+                None
+            };
+            channel.set_mpe_master(master);
+        }
+    }
+
+    /// Returns the number of the member channels of the MPE zone, which is `0` if the zone is not configured.
+    pub fn get_mpe_zone_member_count(&self, zone: MpeZone) -> usize {
+        let master = match zone {
+            MpeZone::Lower => 0,
+            MpeZone::Upper => self.channels.len() - 1,
+        };
+        self.channels
+            .iter()
+            .filter(|channel| channel.get_mpe_master() == Some(master))
+            .count()
+    }
+
+    /// Links the expression (CC 11) of the channel `a` to the channel `b`.
+    ///
+    /// While linked, every expression change on `a` also sets the expression of `b`,
+    /// or its complement if `inverse` is `true`, so that the two channels can be crossfaded
+    /// with a single controller. The link does not chain through the linked channel.
+    /// The link is kept across `reset`.
+    pub fn link_expression(&mut self, a: i32, b: i32, inverse: bool) {
+        if !(0 <= a && a < self.channels.len() as i32) {
             return;
         }
 
+        if !(0 <= b && b < self.channels.len() as i32) || a == b {
+            return;
+        }
+
+        self.channels[a as usize].set_expression_link(Some((b, inverse)));
+        self.update_linked_expression(a);
+    }
+
+    /// Removes the expression link from the channel.
+    pub fn unlink_expression(&mut self, channel: i32) {
         if !(0 <= channel && channel < self.channels.len() as i32) {
             return;
         }
 
+        self.channels[channel as usize].set_expression_link(None);
+    }
+
+    fn update_linked_expression(&mut self, channel: i32) {
         let channel_info = &self.channels[channel as usize];
+        if let Some((target, inverse)) = channel_info.get_expression_link() {
+            let value = channel_info.get_expression_value();
+            self.channels[target as usize].follow_expression(value, inverse);
+        }
+    }
 
-        let preset_id = (channel_info.get_bank_number() << 16) | channel_info.get_patch_number();
+    /// Processes a system exclusive message.
+    ///
+    /// The data may include the leading 0xF0 and the trailing 0xF7.
+    /// The following messages are recognized:
+    ///
+    /// * Universal real time (ID 0x7F) master volume, and the GS and XG master volume,
+    ///   which set the master volume.
+    /// * Universal non-real time (ID 0x7E) GM and GM2 system on, Roland GS (manufacturer ID 0x41) reset,
+    ///   and Yamaha XG (manufacturer ID 0x43) system on, which reset the synthesizer
+    ///   and restore the master volume and the channel 10 as the only percussion channel.
+    /// * Roland GS "Use for Rhythm Part", which switches a part to the drum mode.
+    /// * Yamaha XG "Part Mode", which switches a part to the drum mode.
+    /// * MIDI Tuning Standard bulk tuning dump (ID 0x7E, sub-IDs 08 01 and 08 04)
+    ///   and single note tuning change (sub-IDs 08 02 and 08 07), which store the tunings by the bank
+    ///   and the program, selected on each channel by RPN 3 (program) and RPN 4 (bank).
+    /// * MIDI Tuning Standard scale/octave tuning (sub-IDs 08 08 and 08 09),
+    ///   which shift the 12 pitch classes on the channels given by its bit mask.
+    ///
+    /// The tunings apply to the notes started after the message.
+    /// The checksums of the Roland and the MTS messages are not verified. Other messages are ignored.
+    pub fn process_sysex(&mut self, data: &[u8]) {
+        let time = self.get_sample_position();
+        if let Some(recording) = &mut self.recording {
+            recording.add_sysex(time, data);
+        }
 
-        let mut preset = self.default_preset;
-        match self.preset_lookup.get(&preset_id) {
-            Some(value) => preset = *value,
-            None => {
-                // Try fallback to the GM sound set.
-                // Normally, the given patch number + the bank number 0 will work.
-                // For drums (bank number >= 128), it seems to be better to select the standard set (128:0).
-                let gm_preset_id = if channel_info.get_bank_number() < 128 {
-                    channel_info.get_patch_number()
-                } else {
-                    128 << 16
-                };
+        let data = match data.first() {
+            Some(0xF0) => &data[1..],
+            _ => data,
+        };
+        let data = match data.last() {
+            Some(0xF7) => &data[..data.len() - 1],
+            _ => data,
+        };
 
-                // If no corresponding preset was found. Use the default one...
-                if let Some(value) = self.preset_lookup.get(&gm_preset_id) {
-                    preset = *value
+        match data {
+            // Master volume: 7F dev 04 01 lsb msb
+            [0x7F, _, 0x04, 0x01, lsb, msb, ..] => {
+                let value = ((*msb as i32) << 7) | (*lsb as i32);
+                // The default of the master volume corresponds to the maximum of this message.
+                self.master_volume = Synthesizer::DEFAULT_MASTER_VOLUME * value as f32 / 16383_f32;
+            }
+            // GS master volume: 41 dev 42 12 40 00 04 value sum
+            // XG master volume: 43 1n 4C 00 00 04 value
+            [0x41, _, 0x42, 0x12, 0x40, 0x00, 0x04, value, ..]
+            | [0x43, _, 0x4C, 0x00, 0x00, 0x04, value, ..] => {
+                self.master_volume = Synthesizer::DEFAULT_MASTER_VOLUME * *value as f32 / 127_f32;
+            }
+            // GM system on: 7E dev 09 01
+            // GM2 system on: 7E dev 09 03
+            // GS reset: 41 dev 42 12 40 00 7F 00 sum
+            // XG system on: 43 1n 4C 00 00 7E 00
+            [0x7E, _, 0x09, 0x01 | 0x03, ..]
+            | [0x41, _, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, ..]
+            | [0x43, _, 0x4C, 0x00, 0x00, 0x7E, 0x00, ..] => {
+                for (i, channel) in self.channels.iter_mut().enumerate() {
+                    channel.set_percussion(i == Synthesizer::PERCUSSION_CHANNEL);
                 }
+                self.master_volume = Synthesizer::DEFAULT_MASTER_VOLUME;
+                self.reset();
             }
-        }
-
-        let preset = &self.sound_font.presets[preset];
-        for preset_region in preset.regions.iter() {
-            if preset_region.contains(key, velocity) {
-                let instrument = &self.sound_font.instruments[preset_region.instrument];
-                for instrument_region in instrument.regions.iter() {
-                    if instrument_region.contains(key, velocity) {
-                        let region_pair = RegionPair::new(preset_region, instrument_region);
-
-                        if let Some(value) = self.voices.request_new(instrument_region, channel) {
-                            value.start(&region_pair, channel, key, velocity)
-                        }
-                    }
+            // GS: 41 dev 42 12 40 1x 15 value sum
+            [0x41, _, 0x42, 0x12, 0x40, part, 0x15, value, ..] if (*part & 0xF0) == 0x10 => {
+                // The part numbers of GS start from the channel 10.
+                let channel = match *part & 0x0F {
+                    0 => 9,
+                    p if p <= 9 => p as i32 - 1,
+                    p => p as i32,
+                };
+                self.channels[channel as usize].set_percussion(*value != 0);
+            }
+            // XG: 43 1n 4C 08 part 07 value
+            [0x43, device, 0x4C, 0x08, part, 0x07, value, ..] if (*device & 0xF0) == 0x10 => {
+                let channel = *part as i32;
+                if channel < self.channels.len() as i32 {
+                    self.channels[channel as usize].set_percussion(*value != 0);
+                }
+            }
+            // MTS bulk tuning dump: 7E dev 08 01 program name[16] data[384] sum
+            [0x7E, _, 0x08, 0x01, program, rest @ ..] if rest.len() >= 16 + 384 => {
+                let tuning = Tuning::from_mts_data(&rest[16..16 + 384]);
+                self.mts_tunings.insert((0, *program & 0x7F), tuning);
+            }
+            // MTS bank bulk tuning dump: 7E dev 08 04 bank program name[16] data[384] sum
+            [0x7E, _, 0x08, 0x04, bank, program, rest @ ..] if rest.len() >= 16 + 384 => {
+                let tuning = Tuning::from_mts_data(&rest[16..16 + 384]);
+                self.mts_tunings
+                    .insert((*bank & 0x7F, *program & 0x7F), tuning);
+            }
+            // MTS single note tuning change: 7F dev 08 02 program count [key data[3]]...
+            [0x7F, _, 0x08, 0x02, program, _, changes @ ..] => {
+                self.change_mts_notes(0, *program, changes);
+            }
+            // MTS single note tuning change with the bank: 7E/7F dev 08 07 bank program count [key data[3]]...
+            [0x7E | 0x7F, _, 0x08, 0x07, bank, program, _, changes @ ..] => {
+                self.change_mts_notes(*bank, *program, changes);
+            }
+            // MTS scale/octave tuning in 1 byte: 7E/7F dev 08 08 ff gg hh offsets[12]
+            [0x7E | 0x7F, _, 0x08, 0x08, ff, gg, hh, offsets @ ..] if offsets.len() >= 12 => {
+                let mut values = [0_f32; 12];
+                for (value, &offset) in values.iter_mut().zip(offsets) {
+                    // 0x40 is the center, in cents from -64 to 63.
+                    *value = 0.01 * (offset as i32 - 64) as f32;
+                }
+                self.set_mts_scale_tuning([*ff, *gg, *hh], values);
+            }
+            // MTS scale/octave tuning in 2 bytes: 7E/7F dev 08 09 ff gg hh (msb lsb)[12]
+            [0x7E | 0x7F, _, 0x08, 0x09, ff, gg, hh, offsets @ ..] if offsets.len() >= 24 => {
+                let mut values = [0_f32; 12];
+                for (value, offset) in values.iter_mut().zip(offsets.chunks_exact(2)) {
+                    // 0x2000 is the center, in steps of 100/8192 cents.
+                    let offset = ((offset[0] as i32 & 0x7F) << 7) | (offset[1] as i32 & 0x7F);
+                    *value = (offset - 8192) as f32 / 8192_f32;
                 }
+                self.set_mts_scale_tuning([*ff, *gg, *hh], values);
             }
+            _ => (),
         }
     }
 
-    pub fn note_off_all(&mut self, immediate: bool) {
-        if immediate {
-            self.voices.clear();
-        } else {
-            for voice in self.voices.get_active_voices().iter_mut() {
-                voice.end();
+    // Changes the pitches of the keys in the MTS tuning, starting from equal temperament if it is new.
+    fn change_mts_notes(&mut self, bank: u8, program: u8, changes: &[u8]) {
+        let tuning = self
+            .mts_tunings
+            .entry((bank & 0x7F, program & 0x7F))
+            .or_insert_with(Tuning::equal_temperament);
+        for change in changes.chunks_exact(4) {
+            if let Some(pitch) = Tuning::mts_pitch(&change[1..]) {
+                tuning.set_pitch(change[0] as i32, Some(pitch));
             }
         }
     }
 
-    pub fn note_off_all_channel(&mut self, channel: i32, immediate: bool) {
-        if immediate {
-            for voice in self.voices.get_active_voices().iter_mut() {
-                if voice.channel() == channel {
-                    voice.kill();
-                }
-            }
-        } else {
-            for voice in self.voices.get_active_voices().iter_mut() {
-                if voice.channel() == channel {
-                    voice.end();
-                }
+    // The channel mask gives the channels 14 and 15 in its first byte,
+    // 7 to 13 in the second, and 0 to 6 in the third.
+    fn set_mts_scale_tuning(&mut self, mask: [u8; 3], offsets: [f32; 12]) {
+        let mask = ((mask[0] as u32 & 0x03) << 14)
+            | ((mask[1] as u32 & 0x7F) << 7)
+            | (mask[2] as u32 & 0x7F);
+        for (i, channel) in self.channels.iter_mut().enumerate().take(16) {
+            if mask & (1 << i) != 0 {
+                channel.set_scale_tuning(offsets);
             }
         }
     }
 
-    pub fn reset_all_controllers(&mut self) {
-        for channel in &mut self.channels {
-            channel.reset_all_controllers();
-        }
+    pub fn note_off(&mut self, channel: i32, key: i32) {
+        self.note_off_with_velocity(channel, key, 64);
     }
 
-    pub fn reset_all_controllers_channel(&mut self, channel: i32) {
+    /// Stops the note with the release velocity.
+    ///
+    /// If `SynthesizerSettings::use_note_off_velocity` is `true`, the release time is scaled
+    /// from x2 at the velocity 0 to x0.5 at the velocity 127, where the velocity 64 keeps
+    /// the release time of the SoundFont. Otherwise, this is the same as `note_off`.
+    pub fn note_off_with_velocity(&mut self, channel: i32, key: i32, velocity: i32) {
         if !(0 <= channel && channel < self.channels.len() as i32) {
             return;
         }
 
-        self.channels[channel as usize].reset_all_controllers();
-    }
-
-    pub fn reset(&mut self) {
-        self.voices.clear();
-
-        for channel in &mut self.channels {
-            channel.reset();
+        // The held notes of an arpeggiated channel only change the notes the arpeggiator plays.
+        if let Some(arpeggiator) = self.arpeggiators[channel as usize].as_mut() {
+            if let Some(key) = arpeggiator.release(key) {
+                self.stop_note(channel, key, velocity);
+            }
+            return;
         }
 
-        if let Some(effects) = self.effects.as_mut() {
-            effects.reverb.mute();
-            effects.chorus.mute();
+        // In the mono mode, releasing the sounding key plays the key held before it again.
+        let channel_info = &mut self.channels[channel as usize];
+        if channel_info.get_mono_mode() {
+            if let Some((previous_key, previous_velocity)) = channel_info.release_mono_key(key) {
+                self.start_note(channel, previous_key, previous_velocity, None);
+                return;
+            }
         }
 
-        self.block_read = self.block_size;
+        self.stop_note(channel, key, velocity);
     }
 
-    /// Renders stereo audio into the provided buffers. Both must be the same length.
-    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
-        if left.len() != right.len() {
-            panic!("The output buffers for the left and right must be the same length.");
-        }
-
-        let left_length = left.len();
-
-        let mut wrote = 0;
-        while wrote < left_length {
-            if self.block_read == self.block_size {
-                self.render_block();
-                self.block_read = 0;
-            }
-
-            let src_rem = self.block_size - self.block_read;
-            let dst_rem = left_length - wrote;
-            let rem = cmp::min(src_rem, dst_rem);
+    fn stop_note(&mut self, channel: i32, key: i32, velocity: i32) {
+        let release_scale = if self.use_note_off_velocity {
+            2_f32.powf((64 - velocity.clamp(0, 127)) as f32 / 64_f32)
+        } else {
+            1_f32
+        };
 
-            for t in 0..rem {
-                left[wrote + t] = self.block_left[self.block_read + t];
-                right[wrote + t] = self.block_right[self.block_read + t];
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if voice.channel() == channel && voice.key() == key {
+                voice.end_with_release_scale(release_scale);
             }
-
-            self.block_read += rem;
-            wrote += rem;
         }
     }
 
-    fn render_block(&mut self) {
-        self.voices
-            .process(&self.sound_font.wave_data, &self.channels);
-
-        self.block_left.fill(0_f32);
-        self.block_right.fill(0_f32);
-        for voice in self.voices.get_active_voices().iter_mut() {
-            let previous_gain_left = self.master_volume * voice.previous_mix_gain_left;
-            let current_gain_left = self.master_volume * voice.current_mix_gain_left;
-            Synthesizer::write_block(
-                previous_gain_left,
-                current_gain_left,
-                voice.block(),
-                &mut self.block_left[..],
-                self.inverse_block_size,
-            );
-            let previous_gain_right = self.master_volume * voice.previous_mix_gain_right;
-            let current_gain_right = self.master_volume * voice.current_mix_gain_right;
-            Synthesizer::write_block(
-                previous_gain_right,
-                current_gain_right,
-                voice.block(),
-                &mut self.block_right[..],
-                self.inverse_block_size,
-            );
+    /// Starts the note. A velocity of 0 stops the note instead, as the MIDI specification requires,
+    /// which many files use in running status in place of the note-offs.
+    ///
+    /// On a channel with an arpeggiator, the note is held for the arpeggiator instead of being played.
+    /// On a channel in the mono mode (CC 126), the note replaces the note sounding on the channel,
+    /// and the replaced note sounds again if it is still held when this note is released.
+    /// While the portamento (CC 65) is on, the note glides from the pitch of the previous note
+    /// on the channel over the portamento time (CC 5). The portamento control (CC 84) makes the next note
+    /// glide from its key, even if the portamento is off.
+    pub fn note_on(&mut self, channel: i32, key: i32, velocity: i32) {
+        if velocity == 0 {
+            self.note_off(channel, key);
+            return;
         }
 
-        if let Some(effects) = self.effects.as_mut() {
-            let chorus = &mut effects.chorus;
-            let chorus_input_left = &mut effects.chorus_input_left[..];
-            let chorus_input_right = &mut effects.chorus_input_right[..];
-            let chorus_output_left = &mut effects.chorus_output_left[..];
-            let chorus_output_right = &mut effects.chorus_output_right[..];
-            chorus_input_left.fill(0_f32);
-            chorus_input_right.fill(0_f32);
-            for voice in self.voices.get_active_voices().iter_mut() {
-                let previous_gain_left = voice.previous_chorus_send * voice.previous_mix_gain_left;
-                let current_gain_left = voice.current_chorus_send * voice.current_mix_gain_left;
-                Synthesizer::write_block(
-                    previous_gain_left,
-                    current_gain_left,
-                    voice.block(),
-                    chorus_input_left,
-                    self.inverse_block_size,
-                );
-                let previous_gain_right =
-                    voice.previous_chorus_send * voice.previous_mix_gain_right;
-                let current_gain_right = voice.current_chorus_send * voice.current_mix_gain_right;
-                Synthesizer::write_block(
-                    previous_gain_right,
-                    current_gain_right,
-                    voice.block(),
-                    chorus_input_right,
-                    self.inverse_block_size,
-                );
-            }
-            chorus.process(
-                chorus_input_left,
-                chorus_input_right,
-                chorus_output_left,
-                chorus_output_right,
-            );
-            ArrayMath::multiply_add(
-                self.master_volume,
-                chorus_output_left,
-                &mut self.block_left[..],
-            );
-            ArrayMath::multiply_add(
-                self.master_volume,
-                chorus_output_right,
-                &mut self.block_right[..],
-            );
-
-            let reverb = &mut effects.reverb;
-            let reverb_input = &mut effects.reverb_input[..];
-            let reverb_output_left = &mut effects.reverb_output_left[..];
-            let reverb_output_right = &mut effects.reverb_output_right[..];
-            reverb_input.fill(0_f32);
-            for voice in self.voices.get_active_voices().iter_mut() {
-                let previous_gain = reverb.get_input_gain()
-                    * voice.previous_reverb_send
-                    * (voice.previous_mix_gain_left + voice.previous_mix_gain_right);
-                let current_gain = reverb.get_input_gain()
-                    * voice.current_reverb_send
-                    * (voice.current_mix_gain_left + voice.current_mix_gain_right);
-                Synthesizer::write_block(
-                    previous_gain,
-                    current_gain,
-                    voice.block(),
-                    &mut reverb_input[..],
-                    self.inverse_block_size,
-                );
-            }
-
-            reverb.process(reverb_input, reverb_output_left, reverb_output_right);
-            ArrayMath::multiply_add(
-                self.master_volume,
-                reverb_output_left,
-                &mut self.block_left[..],
-            );
-            ArrayMath::multiply_add(
-                self.master_volume,
-                reverb_output_right,
-                &mut self.block_right[..],
-            );
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
         }
-    }
 
-    fn write_block(
-        previous_gain: f32,
-        current_gain: f32,
-        source: &[f32],
-        destination: &mut [f32],
-        inverse_block_size: f32,
-    ) {
-        if SoundFontMath::max(previous_gain, current_gain) < SoundFontMath::NON_AUDIBLE {
+        if let Some(arpeggiator) = self.arpeggiators[channel as usize].as_mut() {
+            arpeggiator.press(key, velocity);
             return;
         }
 
-        if (current_gain - previous_gain).abs() < 1.0E-3_f32 {
-            ArrayMath::multiply_add(current_gain, source, destination);
-        } else {
-            let step = inverse_block_size * (current_gain - previous_gain);
-            ArrayMath::multiply_add_slope(previous_gain, step, source, destination);
+        let channel_info = &mut self.channels[channel as usize];
+        if channel_info.get_mono_mode() {
+            channel_info.press_mono_key(key, velocity);
         }
-    }
 
-    pub fn get_sound_font(&self) -> &SoundFont {
-        &self.sound_font
-    }
-
-    pub fn get_sample_rate(&self) -> i32 {
-        self.sample_rate
+        self.start_note(channel, key, velocity, None);
     }
 
-    pub fn get_block_size(&self) -> usize {
-        self.block_size
-    }
+    /// Starts the note and releases it by itself after the duration in milliseconds,
+    /// for fire-and-forget sounds such as the effects of a game. The duration must not be negative.
+    ///
+    /// Each call gets its own timed release, so overlapping one-shots of the same key
+    /// do not cut each other, while `note_off` still releases all of them at once.
+    /// The release falls on the block boundary after the duration, and it waits for the hold pedal
+    /// as a note-off does. A velocity of 0 plays nothing.
+    ///
+    /// The note starts as with `note_on`: the mono mode cuts the notes sounding on the channel,
+    /// and the portamento and the retrigger mode apply. It starts at the next block,
+    /// as it cannot be scheduled within a block as `process_midi_message_at` does for the note-ons.
+    /// The one-shot is not a held key, though. It bypasses the arpeggiator of the channel,
+    /// and in the mono mode it is not added to the held keys, so releasing another key never plays it again.
+    pub fn trigger_oneshot(&mut self, channel: i32, key: i32, velocity: i32, duration_ms: f32) {
+        if duration_ms < 0_f32 || duration_ms.is_nan() {
+            panic!("The duration must be a non-negative value.");
+        }
 
-    pub fn get_maximum_polyphony(&self) -> usize {
-        self.maximum_polyphony
-    }
+        if !(0 <= channel && channel < self.channels.len() as i32) || velocity <= 0 {
+            return;
+        }
 
-    pub fn get_enable_reverb_and_chorus(&self) -> bool {
-        self.effects.is_some()
+        let sample_count = (self.sample_rate as f64 * duration_ms as f64 / 1000.0).round() as usize;
+        self.start_note(channel, key, velocity, Some(sample_count));
     }
 
-    pub fn get_master_volume(&self) -> f32 {
-        self.master_volume
-    }
+    // Gets the pitch played by the key on the channel, or None if the tuning leaves the key silent.
+    // The tuning set by the host comes first, then the MTS tuning selected by the channel,
+    // and then the Scala tuning, with the MTS scale/octave tuning and the tuning reference on top.
+    fn get_key_pitch(&self, channel: i32, key: i32) -> Option<f32> {
+        let channel_info = &self.channels[channel as usize];
+        let tuning = match self.channel_tunings.get(channel as usize) {
+            Some(Some(tuning)) => tuning,
+            _ => self
+                .mts_tunings
+                .get(&channel_info.get_tuning_program())
+                .unwrap_or(&self.tuning),
+        };
 
-    pub fn set_master_volume(&mut self, value: f32) {
-        self.master_volume = value;
+        tuning
+            .get_pitch(key)
+            .map(|value| value + channel_info.get_scale_tuning(key) + self.tuning_reference_offset)
     }
-}
 
-#[derive(Debug, Clone)]
-struct Effects {
-    reverb: Reverb,
-    reverb_input: Vec<f32>,
-    reverb_output_left: Vec<f32>,
-    reverb_output_right: Vec<f32>,
+    fn start_note(&mut self, channel: i32, key: i32, velocity: i32, release_after: Option<usize>) {
+        // The preset is looked up only once after each bank or program change.
+        let preset = match self.channels[channel as usize].get_resolved_preset() {
+            Some(value) => value,
+            None => {
+                let value = self.resolve_preset(&self.channels[channel as usize]);
+                self.channels[channel as usize].set_resolved_preset(value);
+                value
+            }
+        };
 
-    chorus: Chorus,
-    chorus_input_left: Vec<f32>,
-    chorus_input_right: Vec<f32>,
-    chorus_output_left: Vec<f32>,
-    chorus_output_right: Vec<f32>,
-}
+        let channel_info = &self.channels[channel as usize];
+
+        // The transpose and the tuning are not applied to the percussion channel,
+        // where the keys select the instruments.
+        let (pitch, region_key) = if channel_info.is_percussion_channel {
+            (key as f32, key)
+        } else {
+            let transposed_key = key + channel_info.get_transpose();
+            let pitch = match self.get_key_pitch(channel, transposed_key) {
+                Some(value) => value,
+                None => return,
+            };
+            if channel_info.get_transpose_regions() {
+                (pitch, transposed_key)
+            } else {
+                (pitch, key)
+            }
+        };
+
+        // The portamento is not applied to the percussion channel either.
+        let portamento = if channel_info.is_percussion_channel {
+            None
+        } else {
+            let transpose = channel_info.get_transpose();
+            let from_pitch = match self.channels[channel as usize].take_portamento_control() {
+                Some(source_key) => self.get_key_pitch(channel, source_key + transpose),
+                None if self.channels[channel as usize].get_portamento() => {
+                    self.channels[channel as usize].get_last_pitch()
+                }
+                None => None,
+            };
+            let channel_info = &mut self.channels[channel as usize];
+            channel_info.set_last_pitch(pitch);
+            from_pitch.map(|from_pitch| {
+                let sample_count = self.sample_rate as f32 * channel_info.get_portamento_time();
+                (from_pitch, sample_count / self.block_size as f32)
+            })
+        };
+
+        // In the mono mode, the notes sounding on the channel are released and faded out in 5 ms.
+        if self.channels[channel as usize].get_mono_mode() {
+            let sample_count = (self.sample_rate / 200) as usize;
+            for voice in self.voices.get_active_voices().iter_mut() {
+                if voice.channel() == channel {
+                    voice.end();
+                    voice.fade_out(sample_count);
+                }
+            }
+        }
+
+        let channel_info = &self.channels[channel as usize];
+
+        if self.retrigger_mode == RetriggerMode::Steal {
+            self.voices.kill_note(channel, key);
+        }
+
+        // The soft pedal plays the notes as if a quarter softer, which selects the softer layers as well.
+        let velocity = if channel_info.get_soft_pedal() {
+            (velocity * 3 / 4).max(1)
+        } else {
+            velocity
+        };
+
+        // The velocity bias only affects which layers are selected, not the loudness.
+        let layer_velocity = (velocity + channel_info.get_velocity_bias()).clamp(1, 127);
+
+        let (font, preset) = preset;
+        let (sound_font, sound_font_gain) = &self.sound_fonts[font];
+        let preset = &sound_font.presets[preset];
+        for preset_region in preset.regions.iter() {
+            if preset_region.contains(region_key, layer_velocity) {
+                let instrument = &sound_font.instruments[preset_region.instrument];
+                for (i, instrument_region) in instrument.regions.iter().enumerate() {
+                    if instrument_region.contains(region_key, layer_velocity) {
+                        // The other half of a linked stereo sample starts together with it.
+                        let linked_region = instrument.linked_regions[i].as_ref();
+                        for instrument_region in
+                            core::iter::once(instrument_region).chain(linked_region)
+                        {
+                            let mut region_pair = RegionPair::new(preset_region, instrument_region);
+                            // The instrument which has regions for both halves places them with its own pans.
+                            if linked_region.is_some() {
+                                region_pair.stereo_pan = instrument_region.get_stereo_pan();
+                            }
+
+                            if let Some(value) = self.voices.request_new(
+                                instrument_region,
+                                channel,
+                                key,
+                                channel_info.get_voice_limit(),
+                            ) {
+                                value.start(
+                                    &region_pair,
+                                    channel_info,
+                                    channel,
+                                    key,
+                                    pitch,
+                                    velocity,
+                                );
+                                value.set_sound_font(sound_font, *sound_font_gain);
+                                if let Some((from_pitch, block_count)) = portamento {
+                                    value.set_portamento(from_pitch, block_count);
+                                }
+                                if let Some(sample_count) = release_after {
+                                    value.set_release_countdown(sample_count);
+                                }
+                                if self.start_delay > 0 {
+                                    value.set_start_delay(self.start_delay);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if !self.reset_phase_on_note_on {
+            self.voices.continue_phase(channel, key);
+        }
+    }
+
+    // Finds the SoundFont and the preset of the channel, searching the stack from the top.
+    fn resolve_preset(&self, channel_info: &Channel) -> (usize, usize) {
+        let find = |preset_id| {
+            self.sound_fonts
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(font, (sound_font, _))| {
+                    sound_font
+                        .preset_lookup
+                        .get(&preset_id)
+                        .map(|&preset| (font, preset))
+                })
+        };
+
+        let preset_id = (
+            channel_info.get_bank_number(),
+            channel_info.get_patch_number(),
+        );
+
+        match find(preset_id) {
+            Some(value) => value,
+            None => {
+                // Try fallback to the GM sound set.
+                // Normally, the given patch number + the bank number 0 will work.
+                // For drums (bank number >= 128), it seems to be better to select the standard set (128:0).
+                let gm_preset_id = if channel_info.get_bank_number() < 128 {
+                    (0, channel_info.get_patch_number())
+                } else {
+                    (128, 0)
+                };
+
+                // If no corresponding preset was found. Use the default one...
+                match find(gm_preset_id) {
+                    Some(value) => value,
+                    None => (0, self.default_preset),
+                }
+            }
+        }
+    }
+
+    fn find_default_preset(sound_font: &SoundFont) -> usize {
+        let mut min_preset_id = i32::MAX;
+        let mut default_preset: usize = 0;
+        for i in 0..sound_font.presets.len() {
+            let preset = &sound_font.presets[i];
+
+            // The preset ID is Int32, where the upper 16 bits represent the bank number
+            // and the lower 16 bits represent the patch number.
+            // The preset with the minimum ID number will be default.
+            // If the SoundFont is GM compatible, the piano will be chosen.
+            let preset_id = (preset.bank_number << 16) | preset.patch_number;
+            if preset_id < min_preset_id {
+                default_preset = i;
+                min_preset_id = preset_id;
+            }
+        }
+
+        default_preset
+    }
+
+    /// Gets the name of the preset which a note-on on the channel plays,
+    /// after the bank and program are resolved with the same fallback as `note_on`.
+    /// On a percussion channel, this is the drum kit.
+    ///
+    /// Returns `None` if the channel is out of range.
+    pub fn get_channel_preset_name(&self, channel: i32) -> Option<&str> {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return None;
+        }
+
+        let channel_info = &self.channels[channel as usize];
+        let (font, preset) = match channel_info.get_resolved_preset() {
+            Some(value) => value,
+            None => self.resolve_preset(channel_info),
+        };
+
+        self.sound_fonts[font]
+            .0
+            .presets
+            .get(preset)
+            .map(|preset| preset.get_name())
+    }
+
+    /// Returns `true` if the key is held down on the channel, i.e. a voice was started by a note-on
+    /// and has not received the corresponding note-off yet.
+    /// Notes kept sounding only by the hold pedal are not considered held.
+    pub fn is_note_on(&self, channel: i32, key: i32) -> bool {
+        self.voices
+            .get_active_voices_ref()
+            .iter()
+            .any(|voice| voice.channel() == channel && voice.key() == key && voice.is_playing())
+    }
+
+    // Fades out the sounding voices of the channel for the crossfade of program changes.
+    fn fade_out_channel(&mut self, channel: i32) {
+        if self.crossfade_program_change_ms <= 0_f32 {
+            return;
+        }
+
+        let sample_count =
+            (self.sample_rate as f32 * self.crossfade_program_change_ms / 1000_f32) as usize;
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if voice.channel() == channel {
+                voice.fade_out(sample_count);
+            }
+        }
+    }
+
+    /// Returns the channel and key of the notes that have been held for longer than
+    /// `older_than_sec` seconds without receiving a note-off.
+    /// Each pair appears only once, even if the note has several voices.
+    pub fn get_stuck_voices(&self, older_than_sec: f64) -> Vec<(i32, i32)> {
+        let threshold = (self.sample_rate as f64 * older_than_sec.max(0.0)) as usize;
+
+        let mut notes: Vec<(i32, i32)> = Vec::new();
+        for voice in self.voices.get_active_voices_ref().iter() {
+            if voice.is_playing() && voice.voice_length() > threshold {
+                let note = (voice.channel(), voice.key());
+                if !notes.contains(&note) {
+                    notes.push(note);
+                }
+            }
+        }
+
+        notes
+    }
+
+    /// Gets the state of every active voice, including the ones in their release.
+    ///
+    /// The values are those of the last rendered block.
+    /// This allocates, so call it from a UI thread on a copy, not from the audio callback.
+    #[cfg(feature = "introspection")]
+    pub fn voices_snapshot(&self) -> Vec<VoiceInfo> {
+        self.voice_infos().collect()
+    }
+
+    /// Iterates over the state of every active voice, including the ones in their release,
+    /// without allocating.
+    ///
+    /// The values are those of the last rendered block.
+    #[cfg(feature = "introspection")]
+    pub fn voice_infos(&self) -> impl Iterator<Item = VoiceInfo> + '_ {
+        self.voices
+            .get_active_voices_ref()
+            .iter()
+            .map(|voice| voice.info(&self.channels[voice.channel() as usize]))
+    }
+
+    /// Gets the state of the channel, or `None` if the channel does not exist.
+    #[cfg(feature = "introspection")]
+    pub fn get_channel_info(&self, channel: i32) -> Option<ChannelInfo> {
+        if !(0..self.channels.len() as i32).contains(&channel) {
+            return None;
+        }
+
+        Some(self.channels[channel as usize].info(channel))
+    }
+
+    /// Iterates over the state of every channel, in the order of the channels.
+    #[cfg(feature = "introspection")]
+    pub fn channel_infos(&self) -> impl Iterator<Item = ChannelInfo> + '_ {
+        self.channels
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| channel.info(i as i32))
+    }
+
+    /// Immediately stops all the voices of the note, regardless of the hold pedal.
+    pub fn kill_voice(&mut self, channel: i32, key: i32) {
+        self.voices.kill_note(channel, key);
+    }
+
+    pub fn note_off_all(&mut self, immediate: bool) {
+        for arpeggiator in self.arpeggiators.iter_mut().flatten() {
+            arpeggiator.clear();
+        }
+        for channel in &mut self.channels {
+            channel.clear_mono_keys();
+        }
+
+        if immediate {
+            self.voices.clear();
+        } else {
+            for voice in self.voices.get_active_voices().iter_mut() {
+                voice.end();
+            }
+        }
+    }
+
+    // Stops the notes when the sequencer jumps back to the loop start.
+    // The voices still releasing are faded out quickly, so that the tails cannot pile up over many loops.
+    pub(crate) fn note_off_all_at_loop(&mut self, immediate: bool) {
+        const TAIL_FADE_MS: i32 = 10;
+
+        if immediate {
+            self.voices.clear();
+            return;
+        }
+
+        let sample_count = (self.sample_rate * TAIL_FADE_MS / 1000) as usize;
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if voice.is_releasing() {
+                voice.fade_out(sample_count);
+            } else {
+                voice.end();
+            }
+        }
+    }
+
+    pub fn note_off_all_channel(&mut self, channel: i32, immediate: bool) {
+        if let Some(Some(arpeggiator)) = self.arpeggiators.get_mut(channel as usize) {
+            arpeggiator.clear();
+        }
+        if let Some(channel_info) = self.channels.get_mut(channel as usize) {
+            channel_info.clear_mono_keys();
+        }
+
+        if immediate {
+            for voice in self.voices.get_active_voices().iter_mut() {
+                if voice.channel() == channel {
+                    voice.kill();
+                }
+            }
+        } else {
+            for voice in self.voices.get_active_voices().iter_mut() {
+                if voice.channel() == channel {
+                    voice.end();
+                }
+            }
+        }
+    }
+
+    /// Releases all the notes on the channel with their release time multiplied by the scale,
+    /// for example `2.0` for twice the release time of the SoundFont or `0.5` for half of it.
+    /// The scale must be positive.
+    ///
+    /// The scale applies only to this release; the notes played later use the usual release time.
+    /// As with `note_off`, the notes held by the hold pedal are released when the pedal is lifted.
+    pub fn note_off_all_channel_with_release(&mut self, channel: i32, release_scale: f32) {
+        if release_scale <= 0_f32 || release_scale.is_nan() {
+            panic!("The release scale must be a positive value.");
+        }
+
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if voice.channel() == channel {
+                voice.end_with_release_scale(release_scale);
+            }
+        }
+    }
+
+    pub fn reset_all_controllers(&mut self) {
+        for channel in &mut self.channels {
+            channel.reset_all_controllers();
+        }
+
+        for channel in 0..self.channels.len() as i32 {
+            self.update_linked_expression(channel);
+        }
+    }
+
+    pub fn reset_all_controllers_channel(&mut self, channel: i32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].reset_all_controllers();
+    }
+
+    /// Restores a single channel to its initial state, leaving the other channels untouched.
+    ///
+    /// The controllers, pitch bend, RPN settings, and hold pedal return to their defaults,
+    /// and the voices of the channel are released.
+    /// The bank and program are reset as well if `reset_program` is `true`, and are kept otherwise.
+    /// Like `reset`, the host-side settings of the channel, such as the transpose, are kept.
+    pub fn reset_channel(&mut self, channel: i32, reset_program: bool) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        let channel_info = &mut self.channels[channel as usize];
+        if reset_program {
+            channel_info.reset();
+        } else {
+            channel_info.reset_keeping_program();
+        }
+
+        self.update_linked_expression(channel);
+
+        self.note_off_all_channel(channel, false);
+    }
+
+    /// Scales the volume envelope of the notes played on the channel.
+    ///
+    /// The attack, decay, and release times from the SoundFont are multiplied by the given factors,
+    /// and `sustain_add_db` is added to the sustain level in decibels.
+    /// Passing `(1.0, 1.0, 0.0, 1.0)` restores the SoundFont's envelope.
+    /// Only notes started after the call are affected; held notes keep their envelope.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_adsr_scale(
+        &mut self,
+        channel: i32,
+        attack_mul: f32,
+        decay_mul: f32,
+        sustain_add_db: f32,
+        release_mul: f32,
+    ) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_adsr_scale(
+            attack_mul,
+            decay_mul,
+            sustain_add_db,
+            release_mul,
+        );
+    }
+
+    /// Scales the attack time of the volume envelope of the notes played on the channel,
+    /// leaving the decay, sustain, and release set by `set_channel_adsr_scale` as they are.
+    ///
+    /// `1` (the default) restores the SoundFont's attack.
+    /// Only notes started after the call are affected.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_attack_scale(&mut self, channel: i32, scale: f32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+        if scale < 0_f32 || scale.is_nan() {
+            panic!("The attack scale must be a non-negative value.");
+        }
+
+        self.channels[channel as usize].set_attack_scale(scale);
+    }
+
+    /// Shifts the filter cutoff of the notes played on the channel by the given number of cents,
+    /// on top of the SoundFont's generators and modulators and the MIDI controllers.
+    ///
+    /// Positive values brighten the sound and negative values darken it. `0` (the default) removes the offset.
+    /// The sounding notes follow the change, smoothed over a few blocks to avoid clicks.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_filter_cutoff_offset(&mut self, channel: i32, cents: f32) {
+        if cents.is_nan() {
+            panic!("The cutoff offset must not be NaN.");
+        }
+
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_filter_cutoff_offset(cents);
+    }
+
+    /// Detunes the notes of the key played on the channel by the given number of semitones,
+    /// on top of the tuning, the pitch bend, and the SoundFont's generators.
+    ///
+    /// The key is the one in the note-on, before the transpose. The sounding notes follow the change.
+    /// The setting is kept across `reset`. Use `clear_channel_key_overrides` to remove it.
+    pub fn set_channel_key_tune(&mut self, channel: i32, key: i32, semitones: f32) {
+        if semitones.is_nan() {
+            panic!("The key tune must not be NaN.");
+        }
+
+        if !(0 <= channel && channel < self.channels.len() as i32 && (0..128).contains(&key)) {
+            return;
+        }
+
+        self.channels[channel as usize].set_key_tune(key as usize, semitones);
+    }
+
+    /// Sets the linear gain of the notes of the key played on the channel, `1.0` by default and `0.0` to mute it.
+    /// The gain must not be negative.
+    ///
+    /// This multiplies the fader set by `set_channel_gain`, and the sounding notes follow the change from the next block.
+    /// The setting is kept across `reset`. Use `clear_channel_key_overrides` to remove it.
+    pub fn set_channel_key_gain(&mut self, channel: i32, key: i32, gain: f32) {
+        if gain < 0_f32 || gain.is_nan() {
+            panic!("The key gain must be a non-negative value.");
+        }
+
+        if !(0 <= channel && channel < self.channels.len() as i32 && (0..128).contains(&key)) {
+            return;
+        }
+
+        self.channels[channel as usize].set_key_gain(key as usize, gain);
+    }
+
+    /// Removes the detune and the gain of every key of the channel set by
+    /// `set_channel_key_tune` and `set_channel_key_gain`.
+    pub fn clear_channel_key_overrides(&mut self, channel: i32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].clear_key_overrides();
+    }
+
+    /// Scales the delay of the vibrato LFO of the notes played on the channel.
+    ///
+    /// The delay set by the SoundFont is multiplied by the scale, so `0` starts the vibrato at once,
+    /// and values above `1` make it set in later. `1` (the default) restores the SoundFont's delay.
+    /// Only notes started after the call are affected.
+    /// The setting is kept across `reset`.
+    pub fn set_vibrato_delay_scale(&mut self, channel: i32, scale: f32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+        if scale < 0_f32 || scale.is_nan() {
+            panic!("The vibrato delay scale must be a non-negative value.");
+        }
+
+        self.channels[channel as usize].set_vibrato_delay_scale(scale);
+    }
+
+    /// Sets the pitch bend range of the channel in semitones, overriding the range set by RPN 0.
+    ///
+    /// While the override is set, the RPN 0 messages of the channel no longer change the range,
+    /// which helps with MIDI files that set a wrong range or none at all.
+    /// The change applies to the sounding notes as well.
+    /// The range must be non-negative.
+    /// The setting is kept across `reset`. Use `clear_pitch_bend_range` to remove it.
+    pub fn set_pitch_bend_range(&mut self, channel: i32, semitones: f64) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+        if semitones < 0.0 || semitones.is_nan() {
+            panic!("The pitch bend range must be a non-negative value.");
+        }
+
+        self.channels[channel as usize].set_pitch_bend_range_override(Some(semitones as f32));
+    }
+
+    /// Removes the override set by `set_pitch_bend_range`, so that RPN 0 controls the range again.
+    /// The range set by the last RPN 0 message, or 2 semitones by default, is used from then on.
+    pub fn clear_pitch_bend_range(&mut self, channel: i32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_pitch_bend_range_override(None);
+    }
+
+    /// Gets the pitch bend range of the channel in semitones currently in effect.
+    /// Returns `0` if the channel is out of range.
+    pub fn get_pitch_bend_range(&self, channel: i32) -> f64 {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return 0.0;
+        }
+
+        self.channels[channel as usize].get_pitch_bend_range() as f64
+    }
+
+    /// Limits the number of the voices which the channel can play at a time, or removes the limit with `None`.
+    ///
+    /// A note-on on a channel at its limit reuses one of the channel's own voices,
+    /// chosen by `SynthesizerSettings::voice_stealing_policy`, so that a dense part such as a drum track
+    /// cannot take over the voices of the others. A stereo sample counts as two voices,
+    /// and a limit of `0` mutes the following notes of the channel.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_voice_limit(&mut self, channel: i32, limit: Option<usize>) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_voice_limit(limit);
+    }
+
+    /// Offsets the velocity used to select the velocity layers of the notes played on the channel.
+    ///
+    /// This forces the SoundFont to pick a softer or harder layer regardless of the played velocity,
+    /// while the loudness still follows the played velocity.
+    /// Passing `0` restores the normal layer selection.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_articulation(&mut self, channel: i32, velocity_bias: i32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_velocity_bias(velocity_bias);
+    }
+
+    /// Processes all the MIDI messages waiting in the queue, in the order they were pushed.
+    ///
+    /// Call this on the render thread before rendering each buffer.
+    /// This never waits for the senders: if one of them is pushing a message,
+    /// the messages are left for the next call.
+    #[cfg(feature = "std")]
+    pub fn process_queue(&mut self, queue: &CommandQueue) {
+        queue.drain(|[channel, command, data1, data2]| {
+            self.process_midi_message(channel, command, data1, data2)
+        });
+    }
+
+    /// Schedules a MIDI message at the given number of samples after the next sample to be rendered,
+    /// instead of at the start of the next block as `process_midi_message` does.
+    ///
+    /// The note-ons start at the exact sample, even within a block, so fast passages do not flam.
+    /// The other messages, including the note-offs, take effect at the start of the block containing them,
+    /// as the envelopes and the controllers are updated once per block.
+    /// An offset within the samples already rendered into the current block is applied at the next block.
+    /// The messages at the same offset are processed in the order they were scheduled.
+    /// The scheduled messages are discarded by `reset`.
+    pub fn process_midi_message_at(
+        &mut self,
+        channel: i32,
+        command: i32,
+        data1: i32,
+        data2: i32,
+        sample_offset: usize,
+    ) {
+        if 0 <= channel && channel < self.channels.len() as i32 {
+            let time = self.get_sample_position() + sample_offset as u64;
+            if let Some(recording) = &mut self.recording {
+                recording.add_message(time, channel, command, data1, data2);
+            }
+        }
+
+        // The samples left in the current block are rendered before the next block starts.
+        let offset = sample_offset.saturating_sub(self.block_size - self.block_read);
+
+        let index = self
+            .scheduled_messages
+            .partition_point(|&(scheduled, _)| scheduled <= offset);
+        self.scheduled_messages
+            .insert(index, (offset, [channel, command, data1, data2]));
+    }
+
+    // Processes the scheduled messages which fall within the next block.
+    fn process_scheduled_messages(&mut self) {
+        let count = self
+            .scheduled_messages
+            .partition_point(|&(offset, _)| offset < self.block_size);
+
+        for i in 0..count {
+            let (offset, [channel, command, data1, data2]) = self.scheduled_messages[i];
+            self.start_delay = offset;
+            if 0 <= channel && channel < self.channels.len() as i32 {
+                self.dispatch_midi_message(channel, command, data1, data2);
+            }
+        }
+        self.start_delay = 0;
+        self.scheduled_messages.drain(..count);
+
+        for (offset, _) in self.scheduled_messages.iter_mut() {
+            *offset -= self.block_size;
+        }
+    }
+
+    /// Starts capturing the MIDI messages given to `process_midi_message`, `process_midi_message_at`,
+    /// `process_queue` and `process_sysex`, with their times in samples from now.
+    ///
+    /// The time of a message is the sample to be rendered next when it is given,
+    /// plus the offset for `process_midi_message_at`, so the recording follows the rendering exactly
+    /// however large the buffers are. The messages played by a `MidiFileSequencer` are captured as well.
+    /// A recording in progress is discarded. The recording is kept across `reset`.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(MidiRecording::new(
+            self.sample_rate,
+            self.get_sample_position(),
+        ));
+    }
+
+    /// Stops capturing the MIDI messages, and returns the recording,
+    /// which lasts until the sample to be rendered next.
+    /// Returns `None` if the synthesizer is not recording.
+    pub fn stop_recording(&mut self) -> Option<MidiRecording> {
+        let position = self.get_sample_position();
+        let mut recording = self.recording.take()?;
+        recording.finish(position);
+        Some(recording)
+    }
+
+    /// Returns `true` if the synthesizer is capturing the MIDI messages.
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // The position of the sample to be rendered next, counted from the creation of the synthesizer.
+    // The rest of a block discarded by reset is not counted.
+    pub(crate) fn get_sample_position(&self) -> u64 {
+        self.block_end - (self.block_size - self.block_read) as u64
+    }
+
+    /// Sets how much the key number raises the filter cutoff of the notes played on the channel,
+    /// in cents per key above the middle C (key 60). Lower keys lower the cutoff by the same amount.
+    ///
+    /// `100` makes the cutoff follow the pitch, and `0` (the default) disables the tracking.
+    /// This is applied on top of the SoundFont's own modulators, and only to notes started after the call.
+    /// The setting is kept across `reset`.
+    pub fn set_filter_key_tracking(&mut self, channel: i32, amount: f32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_filter_key_tracking(amount);
+    }
+
+    /// Sets how much the velocity raises the filter cutoff of the notes played on the channel,
+    /// in cents per velocity step above 64. Softer notes lower the cutoff by the same amount.
+    ///
+    /// `0` (the default) disables the tracking.
+    /// This is applied on top of the SoundFont's own modulators, and only to notes started after the call.
+    /// The setting is kept across `reset`.
+    pub fn set_filter_velocity_tracking(&mut self, channel: i32, amount: f32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_filter_velocity_tracking(amount);
+    }
+
+    /// Switches the channel between the drum mode and the melodic mode.
+    ///
+    /// A channel in the drum mode selects its presets from the percussion banks (128 and above),
+    /// and is not affected by the transpose and the tuning, as the channel 10 is by default.
+    /// Any number of channels can be in the drum mode. The bank select keeps working on top of it,
+    /// so a drum kit is chosen by a program change as usual.
+    /// The sounding notes are not affected, and the following note-ons use the new bank.
+    /// The mode is kept across `reset`, but the GM system on and the GS and XG resets
+    /// given to `process_sysex` restore the channel 10 as the only drum channel.
+    pub fn set_channel_drum_mode(&mut self, channel: i32, value: bool) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_percussion(value);
+    }
+
+    /// Returns `true` if the channel is in the drum mode, or `false` if the channel is out of range.
+    pub fn is_channel_drum_mode(&self, channel: i32) -> bool {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return false;
+        }
+
+        self.channels[channel as usize].is_percussion_channel
+    }
+
+    /// Sets the fader of the channel as a linear gain, `1.0` by default and `0.0` to mute it.
+    /// The gain must not be negative.
+    ///
+    /// This is independent of the channel volume (CC 7) and expression (CC 11) of the MIDI data,
+    /// so that the channels can be mixed without fighting their automation:
+    /// the gain of a note is the gain from the volume curve of CC 7 and CC 11, multiplied by this gain.
+    /// The effect sends follow it, as they are taken after the fader.
+    /// The sounding notes follow the change from the next block.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_gain(&mut self, channel: i32, gain: f32) {
+        if gain < 0_f32 || gain.is_nan() {
+            panic!("The channel gain must be a non-negative value.");
+        }
+
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_gain(gain);
+    }
+
+    /// Gets the fader of the channel set by `set_channel_gain`, or `1.0` if the channel is out of range.
+    pub fn get_channel_gain(&self, channel: i32) -> f32 {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return 1_f32;
+        }
+
+        self.channels[channel as usize].get_gain()
+    }
+
+    /// Transposes the notes played on the channel by the given number of semitones.
+    ///
+    /// By default, the SoundFont regions are selected by the played key and just pitched,
+    /// as with a capo. Use `set_channel_transpose_regions` to select the regions
+    /// by the transposed key instead.
+    /// The percussion channel is not affected. Only notes started after the call are affected.
+    /// The setting is kept across `reset`.
+    pub fn set_channel_transpose(&mut self, channel: i32, semitones: i32) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_transpose(semitones);
+    }
+
+    /// Sets whether the transpose of the channel also changes which SoundFont regions are selected.
+    pub fn set_channel_transpose_regions(&mut self, channel: i32, value: bool) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        self.channels[channel as usize].set_transpose_regions(value);
+    }
+
+    /// Freezes or releases the notes currently sounding.
+    ///
+    /// When `on` is `true`, every sounding voice is held at its current envelope level,
+    /// and ignores the note-off and the hold pedal, while new notes can be played over it.
+    /// Calling again with `true` adds the voices sounding at that time to the frozen set.
+    /// When `on` is `false`, all the frozen voices start their release.
+    /// All Sound Off and `reset` still stop the frozen voices.
+    pub fn set_freeze(&mut self, on: bool) {
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if on {
+                voice.freeze();
+            } else {
+                voice.unfreeze();
+            }
+        }
+    }
+
+    /// Retunes the keys with a Scala scale file (.scl) and an optional keyboard mapping file (.kbm).
+    ///
+    /// Without a keyboard mapping, the first degree of the scale is mapped to the middle C (key 60)
+    /// at 261.63 Hz, and the following keys are mapped linearly onto the scale.
+    /// Keys left unmapped by the keyboard mapping are silent.
+    /// The tuning is applied to the notes started after the call, except on the percussion channel,
+    /// and is kept across `reset`.
+    pub fn load_scala(&mut self, scl: &str, kbm: Option<&str>) -> Result<(), TuningError> {
+        self.tuning = Tuning::from_scala(scl, kbm)?;
+        Ok(())
+    }
+
+    /// Sets the tuning of the channel, which takes precedence over the Scala tuning
+    /// and the tunings selected by the MIDI Tuning Standard messages.
+    ///
+    /// The tuning is applied to the notes started after the call, except on the percussion channel,
+    /// and is kept across `reset` until `clear_tuning` or `reset_tuning`.
+    pub fn set_tuning(&mut self, channel: i32, tuning: &Tuning) {
+        if !(0 <= channel && channel < self.channel_tunings.len() as i32) {
+            return;
+        }
+
+        self.channel_tunings[channel as usize] = Some(tuning.clone());
+    }
+
+    /// Removes the tuning set on the channel by `set_tuning`.
+    pub fn clear_tuning(&mut self, channel: i32) {
+        if !(0 <= channel && channel < self.channel_tunings.len() as i32) {
+            return;
+        }
+
+        self.channel_tunings[channel as usize] = None;
+    }
+
+    /// Restores 12-tone equal temperament, removing the tunings of the channels
+    /// and those sent by the MIDI Tuning Standard messages.
+    pub fn reset_tuning(&mut self) {
+        self.tuning = Tuning::equal_temperament();
+        self.channel_tunings.fill(None);
+        self.mts_tunings.clear();
+        for channel in &mut self.channels {
+            channel.set_scale_tuning([0_f32; 12]);
+        }
+    }
+
+    /// Sets the frequency of A4 (key 69) in Hz, which shifts the pitch of all the keys. Must be positive.
+    ///
+    /// The shift is applied on top of the Scala tuning, the transpose, and the channel tuning,
+    /// except on the percussion channel. Only notes started after the call are affected.
+    /// The default is 440 Hz, and the setting is kept across `reset` and `reset_tuning`.
+    /// Pass `get_tuning_reference` to `key_to_frequency_with_reference` to get the played frequencies.
+    pub fn set_tuning_reference(&mut self, a4_hz: f64) {
+        if a4_hz <= 0.0 || a4_hz.is_nan() {
+            panic!("The tuning reference must be a positive value.");
+        }
+
+        self.tuning_reference = a4_hz;
+        self.tuning_reference_offset = (12.0 * (a4_hz / note::DEFAULT_A4_FREQUENCY).log2()) as f32;
+    }
+
+    pub fn get_tuning_reference(&self) -> f64 {
+        self.tuning_reference
+    }
+
+    /// Saves the MIDI state of the channels and the master volume,
+    /// and the sounding voices if `include_voices` is `true`.
+    ///
+    /// The settings set by the host, such as the transpose and the gain of the channels,
+    /// and the state of the effects are not saved.
+    pub fn save_state(&self, include_voices: bool) -> SynthesizerState {
+        SynthesizerState {
+            channels: self
+                .channels
+                .iter()
+                .map(|channel| channel.save_state())
+                .collect(),
+            master_volume: self.master_volume,
+            voices: include_voices.then(|| self.voices.clone()),
+        }
+    }
+
+    /// Restores the state saved by `save_state`, with the same number of channels as this synthesizer.
+    ///
+    /// The voices of the state replace those sounding, which should only be restored
+    /// on the synthesizer which saved them, or one with the same SoundFonts and settings.
+    /// Without the voices, the sounding notes are stopped at once.
+    pub fn restore_state(&mut self, state: &SynthesizerState) {
+        for (channel, channel_state) in self.channels.iter_mut().zip(state.channels.iter()) {
+            channel.restore_state(channel_state);
+        }
+        for channel in 0..self.channels.len() as i32 {
+            self.update_linked_expression(channel);
+        }
+
+        self.master_volume = state.master_volume;
+        match &state.voices {
+            Some(voices) => self.voices.clone_from(voices),
+            None => self.voices.clear(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.voices.clear();
+
+        for channel in &mut self.channels {
+            channel.reset();
+        }
+
+        for channel in 0..self.channels.len() as i32 {
+            self.update_linked_expression(channel);
+        }
+
+        for effects in self
+            .effects
+            .iter_mut()
+            .chain(self.channel_effects.iter_mut())
+        {
+            effects.reverb.mute();
+            effects.chorus.mute();
+        }
+
+        for effect in self.custom_effects.iter_mut() {
+            effect.reset();
+        }
+
+        self.master_lowpass.clear_buffer();
+        self.output_stage.clear_buffer();
+
+        for arpeggiator in self.arpeggiators.iter_mut().flatten() {
+            arpeggiator.clear();
+        }
+
+        self.scheduled_messages.clear();
+
+        self.output_peak = 0_f32;
+        self.channel_effect_peaks.fill(0_f32);
+        self.block_end = self.get_sample_position();
+        self.block_read = self.block_size;
+    }
+
+    /// Returns `true` if no voice is active and the reverb and chorus tails have decayed to silence.
+    ///
+    /// This only reads the state cached by the last rendered block, so it is cheap to call every frame.
+    /// While idle, the rendering can be paused, as it would only produce silence.
+    pub fn is_idle(&self) -> bool {
+        self.voices.active_voice_count == 0 && self.output_peak < Synthesizer::SILENCE_THRESHOLD
+    }
+
+    /// Renders stereo audio into the provided buffers. Both must be the same length.
+    ///
+    /// The buffers can have any length, even a single sample.
+    /// The synthesizer renders whole blocks internally and keeps the samples not yet read,
+    /// so that the next call continues from them before rendering a new block.
+    ///
+    /// The rendering is real-time safe: it does not allocate, lock, or panic,
+    /// so it can be called from the audio callback. The same holds for the other render methods,
+    /// except `render_multichannel` with the effects of the channels not prepared by `prepare_routing`,
+    /// and for `process_midi_message`, `process_queue`, `process_sysex` except for the tuning dumps,
+    /// which store a new tuning, and `process_midi_message_at` up to 1024 messages ahead.
+    /// This requires a single render thread, and no recording or custom effect,
+    /// as the threads are started for each block, and the others allocate as they please.
+    /// The lengths of the buffers are checked in debug builds only,
+    /// and a release build renders the length of the shorter one.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        debug_assert_eq!(
+            left.len(),
+            right.len(),
+            "The output buffers for the left and right must be the same length."
+        );
+
+        let left_length = cmp::min(left.len(), right.len());
+
+        self.render_blocks(left_length, |wrote, block_left, block_right| {
+            let length = block_left.len();
+            left[wrote..wrote + length].copy_from_slice(block_left);
+            right[wrote..wrote + length].copy_from_slice(block_right);
+        });
+    }
+
+    /// Renders stereo audio into a buffer split in two pieces, as a ring buffer hands out at its wrap,
+    /// filling the first piece and then the second as if they were one contiguous buffer.
+    ///
+    /// The first pieces of the left and right must be the same length, and so must the second pieces.
+    /// Either piece can be empty.
+    /// The output is the same as `render`, and the two can be mixed as they share the same position.
+    /// As in `render`, the lengths are checked in debug builds only.
+    pub fn render_split(
+        &mut self,
+        left: (&mut [f32], &mut [f32]),
+        right: (&mut [f32], &mut [f32]),
+    ) {
+        debug_assert!(
+            left.0.len() == right.0.len() && left.1.len() == right.1.len(),
+            "The pieces of the output buffers for the left and right must be the same length."
+        );
+
+        // The samples left in the block carry over from the first piece to the second.
+        self.render(left.0, right.0);
+        self.render(left.1, right.1);
+    }
+
+    /// Renders stereo audio into a buffer of interleaved frames, the left and then the right sample of each.
+    /// The length of the buffer must be even.
+    ///
+    /// The output is the same as `render`, and the two can be mixed as they share the same position.
+    /// The length is checked in debug builds only, and a release build leaves the last sample of an odd length.
+    pub fn render_interleaved(&mut self, output: &mut [f32]) {
+        debug_assert!(
+            output.len().is_multiple_of(2),
+            "The interleaved output buffer must have an even length."
+        );
+
+        let frames = output.len() / 2;
+
+        self.render_blocks(frames, |wrote, block_left, block_right| {
+            let length = block_left.len();
+            for ((frame, left), right) in output[2 * wrote..2 * (wrote + length)]
+                .chunks_exact_mut(2)
+                .zip(block_left)
+                .zip(block_right)
+            {
+                frame[0] = *left;
+                frame[1] = *right;
+            }
+        });
+    }
+
+    /// Renders the number of stereo frames, passing each of them to the sink
+    /// as `sink(frame_index, left, right)`, where the index counts from 0 in this call.
+    ///
+    /// This writes the output directly into any layout without intermediate buffers.
+    /// The output is the same as `render`, and the two can be mixed as they share the same position.
+    pub fn render_with<F: FnMut(usize, f32, f32)>(&mut self, frames: usize, mut sink: F) {
+        self.render_blocks(frames, |wrote, block_left, block_right| {
+            for (t, (left, right)) in block_left.iter().zip(block_right).enumerate() {
+                sink(wrote + t, *left, *right);
+            }
+        });
+    }
+
+    // Renders the number of frames, passing the output to the sink in runs within the blocks
+    // as `sink(wrote, left, right)`, where `wrote` counts the frames passed before the run in this call.
+    // This is the buffering shared by the render methods.
+    fn render_blocks<F: FnMut(usize, &[f32], &[f32])>(&mut self, frames: usize, mut sink: F) {
+        let mut wrote = 0;
+        while wrote < frames {
+            if self.block_read == self.block_size {
+                self.process_block();
+                self.block_read = 0;
+            }
+
+            let src_rem = self.block_size - self.block_read;
+            let dst_rem = frames - wrote;
+            let rem = cmp::min(src_rem, dst_rem);
+
+            let (block_left, block_right) = self.get_output_block();
+
+            let src = self.block_read..self.block_read + rem;
+            sink(wrote, &block_left[src.clone()], &block_right[src]);
+
+            self.block_read += rem;
+            wrote += rem;
+        }
+    }
+
+    // Gets the rendered block, through the master low-pass filter and the output stage if they are engaged.
+    fn get_output_block(&self) -> (&[f32], &[f32]) {
+        let (left, right) = self
+            .master_lowpass
+            .select(&self.block_left[..], &self.block_right[..]);
+        self.output_stage.select(left, right)
+    }
+
+    /// Renders the voice mix before the reverb and chorus, and the effect returns, into separate buffers.
+    /// All the buffers must be the same length.
+    ///
+    /// The sum of the dry and wet outputs equals the output of `render`, up to rounding,
+    /// and the two can be mixed with `render` calls as they share the same position.
+    /// The wet output is silent if the reverb and chorus are disabled.
+    /// As in `render`, the lengths are checked in debug builds only.
+    pub fn render_dry_wet(
+        &mut self,
+        dry_left: &mut [f32],
+        dry_right: &mut [f32],
+        wet_left: &mut [f32],
+        wet_right: &mut [f32],
+    ) {
+        let lengths = [
+            dry_left.len(),
+            dry_right.len(),
+            wet_left.len(),
+            wet_right.len(),
+        ];
+        debug_assert!(
+            lengths.iter().all(|&length| length == lengths[0]),
+            "The output buffers for the dry and wet signals must be the same length."
+        );
+        let length = lengths.into_iter().min().unwrap_or(0);
+
+        let mut wrote = 0;
+        while wrote < length {
+            if self.block_read == self.block_size {
+                self.process_block();
+                self.block_read = 0;
+            }
+
+            let src_rem = self.block_size - self.block_read;
+            let dst_rem = length - wrote;
+            let rem = cmp::min(src_rem, dst_rem);
+
+            let src = self.block_read..self.block_read + rem;
+            let dst = wrote..wrote + rem;
+            if let Some(effects) = self.effects.as_ref() {
+                dry_left[dst.clone()].copy_from_slice(&effects.dry_left[src.clone()]);
+                dry_right[dst.clone()].copy_from_slice(&effects.dry_right[src.clone()]);
+                for t in 0..rem {
+                    let i = self.block_read + t;
+                    wet_left[wrote + t] = self.block_left[i] - effects.dry_left[i];
+                    wet_right[wrote + t] = self.block_right[i] - effects.dry_right[i];
+                }
+            } else {
+                dry_left[dst.clone()].copy_from_slice(&self.block_left[src.clone()]);
+                dry_right[dst.clone()].copy_from_slice(&self.block_right[src]);
+                wet_left[dst.clone()].fill(0_f32);
+                wet_right[dst].fill(0_f32);
+            }
+
+            self.block_read += rem;
+            wrote += rem;
+        }
+    }
+
+    /// Renders each channel to the outputs given by the routing, for surround or other multi-speaker layouts.
+    /// There must be as many outputs as the routing has, and all of them must be the same length.
+    ///
+    /// By default, the reverb and chorus are applied globally, not per output:
+    /// all the channels share them as in `render`, and their stereo return is routed by the effect routes.
+    /// With `ChannelRouting::stereo`, the outputs are the same as `render`, up to rounding.
+    /// If the routing enables `ChannelRouting::set_channel_effects`, each channel runs its own
+    /// reverb and chorus instead, whose return follows the channel's routes, so that each output
+    /// can be a complete stem. This runs the effects once per channel in addition to the global ones,
+    /// but not those of the channels which have no voice and whose tails have decayed to silence.
+    /// The effects of the channels are allocated by `prepare_routing`, which should be called
+    /// from the control thread beforehand, or else by the first call with such a routing.
+    ///
+    /// The mix of each channel is kept from the first call, which mixes the block being read
+    /// from the voices, so that the calls can follow `render` calls without a gap.
+    /// The number and the lengths of the buffers are checked in debug builds only,
+    /// and a release build renders the length of the shortest one, to the outputs which exist.
+    pub fn render_multichannel(&mut self, outputs: &mut [&mut [f32]], routing: &ChannelRouting) {
+        debug_assert_eq!(
+            outputs.len(),
+            routing.get_output_count(),
+            "The number of the output buffers must match the routing."
+        );
+        debug_assert!(
+            outputs
+                .windows(2)
+                .all(|pair| pair[0].len() == pair[1].len()),
+            "The output buffers must be the same length."
+        );
+        let length = match outputs.iter().map(|output| output.len()).min() {
+            Some(value) => value,
+            None => return,
+        };
+
+        if !self.channel_mix {
+            self.channel_mix = true;
+            if self.block_read < self.block_size {
+                self.mix_channels();
+            }
+        }
+
+        if routing.get_channel_effects() != self.channel_effects_enabled {
+            self.prepare_routing(routing);
+        }
+
+        let mut wrote = 0;
+        while wrote < length {
+            if self.block_read == self.block_size {
+                self.process_block();
+                self.block_read = 0;
+            }
+
+            let src_rem = self.block_size - self.block_read;
+            let dst_rem = length - wrote;
+            let rem = cmp::min(src_rem, dst_rem);
+
+            for output in outputs.iter_mut() {
+                output[wrote..wrote + rem].fill(0_f32);
+            }
+
+            for channel in 0..Synthesizer::CHANNEL_COUNT {
+                let offset = channel * self.block_size + self.block_read;
+                let left = &self.channel_block_left[offset..offset + rem];
+                let right = &self.channel_block_right[offset..offset + rem];
+                for route in routing.get_channel_routes(channel) {
+                    let output = match outputs.get_mut(route.output) {
+                        Some(output) => &mut output[wrote..wrote + rem],
+                        None => continue,
+                    };
+                    for ((value, left), right) in output.iter_mut().zip(left).zip(right) {
+                        *value += route.left_gain * left + route.right_gain * right;
+                    }
+                }
+            }
+
+            if let Some(effects) = self
+                .effects
+                .as_ref()
+                .filter(|_| !self.channel_effects_block)
+            {
+                for route in routing.get_effect_routes() {
+                    let output = match outputs.get_mut(route.output) {
+                        Some(output) => &mut output[wrote..wrote + rem],
+                        None => continue,
+                    };
+                    for (t, value) in output.iter_mut().enumerate() {
+                        let i = self.block_read + t;
+                        let left = self.block_left[i] - effects.dry_left[i];
+                        let right = self.block_right[i] - effects.dry_right[i];
+                        *value += route.left_gain * left + route.right_gain * right;
+                    }
+                }
+            }
+
+            self.block_read += rem;
+            wrote += rem;
+        }
+    }
+
+    /// Prepares the synthesizer for `render_multichannel` with the routing,
+    /// allocating the reverb and chorus of each channel if the routing enables them.
+    ///
+    /// Calling this from the control thread keeps the allocation out of the audio callback.
+    /// The effects of the channels start silent when they are enabled again.
+    pub fn prepare_routing(&mut self, routing: &ChannelRouting) {
+        let enabled = routing.get_channel_effects() && self.effects.is_some();
+        if enabled && self.channel_effects.is_empty() {
+            if let Some(effects) = self.effects.as_ref() {
+                self.channel_effects = vec![effects.new_silent(); Synthesizer::CHANNEL_COUNT];
+            }
+        }
+
+        if enabled && !self.channel_effects_enabled {
+            for effects in self.channel_effects.iter_mut() {
+                effects.reverb.mute();
+                effects.chorus.mute();
+            }
+            self.channel_effect_peaks.fill(0_f32);
+        }
+        self.channel_effects_enabled = enabled;
+    }
+
+    /// Renders one note of the given preset and returns the stereo waveform.
+    ///
+    /// The note is played on channel 0 of a reset copy of this synthesizer,
+    /// so this synthesizer is left untouched.
+    /// It is held for `duration_sec` seconds, then released,
+    /// and rendering continues until the release has finished (up to 10 seconds).
+    /// If the SoundFont has no such preset, the same fallback as `note_on` applies.
+    pub fn render_preset_note(
+        &self,
+        bank: i32,
+        patch: i32,
+        key: i32,
+        velocity: i32,
+        duration_sec: f64,
+    ) -> (Vec<f32>, Vec<f32>) {
+        const MAXIMUM_RELEASE_SEC: f64 = 10.0;
+
+        let mut synthesizer = self.clone();
+        synthesizer.reset();
+        synthesizer.process_midi_message(0, 0xB0, 0x00, bank);
+        synthesizer.process_midi_message(0, 0xC0, patch, 0);
+
+        let hold_length = (self.sample_rate as f64 * duration_sec.max(0.0)) as usize;
+        let mut left: Vec<f32> = vec![0_f32; hold_length];
+        let mut right: Vec<f32> = vec![0_f32; hold_length];
+
+        synthesizer.note_on(0, key, velocity);
+        synthesizer.render(&mut left[..], &mut right[..]);
+        synthesizer.note_off(0, key);
+
+        let maximum_length = hold_length + (self.sample_rate as f64 * MAXIMUM_RELEASE_SEC) as usize;
+        while synthesizer.voices.active_voice_count > 0 && left.len() < maximum_length {
+            let offset = left.len();
+            left.resize(offset + self.block_size, 0_f32);
+            right.resize(offset + self.block_size, 0_f32);
+            synthesizer.render(&mut left[offset..], &mut right[offset..]);
+        }
+
+        (left, right)
+    }
+
+    /// Renders stereo audio as 64-bit floats. Both must be the same length.
+    ///
+    /// The voices and the effects are mixed in 32-bit floats internally,
+    /// so the output has the precision of `render`, converted without loss.
+    /// This is for the consumers which accumulate or analyze the output in 64-bit floats.
+    /// As in `render`, the lengths are checked in debug builds only.
+    pub fn render_f64(&mut self, left: &mut [f64], right: &mut [f64]) {
+        debug_assert_eq!(
+            left.len(),
+            right.len(),
+            "The output buffers for the left and right must be the same length."
+        );
+
+        let left_length = cmp::min(left.len(), right.len());
+
+        self.render_with(left_length, |t, block_left, block_right| {
+            left[t] = block_left as f64;
+            right[t] = block_right as f64;
+        });
+    }
+
+    /// Renders stereo audio as 16-bit integers. Both must be the same length.
+    ///
+    /// The samples are dithered as specified by `set_dither_mode`.
+    /// Like `render`, the buffers can have any length, and the lengths are checked in debug builds only.
+    pub fn render_i16(&mut self, left: &mut [i16], right: &mut [i16]) {
+        debug_assert_eq!(
+            left.len(),
+            right.len(),
+            "The output buffers for the left and right must be the same length."
+        );
+
+        let left_length = cmp::min(left.len(), right.len());
+
+        // The dither is copied out and back, as the output blocks borrow the synthesizer while it runs.
+        let mut dither = self.dither.clone();
+        self.render_blocks(left_length, |wrote, block_left, block_right| {
+            let length = block_left.len();
+            dither.process(
+                block_left,
+                block_right,
+                &mut left[wrote..wrote + length],
+                &mut right[wrote..wrote + length],
+            );
+        });
+        self.dither = dither;
+    }
+
+    /// Renders stereo audio as 16-bit integers into a buffer of interleaved frames,
+    /// the left and then the right sample of each. The length of the buffer must be even.
+    ///
+    /// The output is the same as `render_i16`, including the dither.
+    /// The length is checked as in `render_interleaved`.
+    pub fn render_interleaved_i16(&mut self, output: &mut [i16]) {
+        debug_assert!(
+            output.len().is_multiple_of(2),
+            "The interleaved output buffer must have an even length."
+        );
+
+        let frames = output.len() / 2;
+
+        // As in render_i16, the dither is copied out and back.
+        let mut dither = self.dither.clone();
+        self.render_blocks(frames, |wrote, block_left, block_right| {
+            let length = block_left.len();
+            dither.process_interleaved(
+                block_left,
+                block_right,
+                &mut output[2 * wrote..2 * (wrote + length)],
+            );
+        });
+        self.dither = dither;
+    }
+
+    /// Sets how `render_i16` and `render_interleaved_i16` dither the samples.
+    pub fn set_dither_mode(&mut self, mode: DitherMode) {
+        self.dither.mode = mode;
+    }
+
+    /// Restarts the random number generator of the dither with the given seed,
+    /// so that the output of `render_i16` is reproducible.
+    pub fn set_dither_seed(&mut self, seed: u64) {
+        self.dither.set_seed(seed);
+    }
+
+    /// Renders and discards the given duration of audio, to settle the effects and the gain smoothing
+    /// before the actual rendering starts.
+    ///
+    /// The voices sounding at the call advance as well, so this is usually called on a silent
+    /// synthesizer, optionally after playing a test signal to fill the reverb and chorus.
+    /// This does not advance the time of a `MidiFileSequencer`.
+    pub fn prime(&mut self, seconds: f64) {
+        let sample_count = (seconds.max(0.0) * self.sample_rate as f64).ceil() as usize;
+        let block_count = sample_count.div_ceil(self.block_size);
+
+        self.block_end = self.get_sample_position();
+        self.block_read = self.block_size;
+        for _i in 0..block_count {
+            self.process_block();
+        }
+
+        self.block_read = self.block_size;
+    }
+
+    /// Renders exactly one block of stereo audio, without the buffering of `render`.
+    ///
+    /// Both buffers must have the length of the block size.
+    /// The block includes the output of the reverb and chorus, and advances them by one block.
+    /// Samples left unread by a previous `render` call are discarded.
+    /// The lengths are checked in debug builds only, and a release build writes
+    /// the start of the block to shorter buffers, and leaves the rest of longer ones.
+    pub fn render_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        debug_assert!(
+            left.len() == self.block_size && right.len() == self.block_size,
+            "The output buffers for the left and right must have the length of the block size."
+        );
+
+        self.process_block();
+        self.block_read = self.block_size;
+
+        let (block_left, block_right) = self.get_output_block();
+        let length = cmp::min(left.len(), self.block_size);
+        left[..length].copy_from_slice(&block_left[..length]);
+        let length = cmp::min(right.len(), self.block_size);
+        right[..length].copy_from_slice(&block_right[..length]);
+    }
+
+    // Mixes the voices of the current block into the mix of their channels,
+    // without the voices started since the block was rendered.
+    fn mix_channels(&mut self) {
+        self.channel_block_left.fill(0_f32);
+        self.channel_block_right.fill(0_f32);
+        for voice in self.voices.get_active_voices_ref() {
+            if voice.voice_length() == 0 || !Synthesizer::is_audible(voice, self.debug_solo_region)
+            {
+                continue;
+            }
+
+            let output_gain = self.master_volume * voice.sound_font_gain;
+            let offset = voice.channel() as usize * self.block_size;
+            let range = offset..offset + self.block_size;
+            Synthesizer::write_block(
+                output_gain * voice.previous_mix_gain_left,
+                output_gain * voice.current_mix_gain_left,
+                voice.block(),
+                &mut self.channel_block_left[range.clone()],
+                self.inverse_block_size,
+                self.smooth_controllers,
+            );
+            Synthesizer::write_block(
+                output_gain * voice.previous_mix_gain_right,
+                output_gain * voice.current_mix_gain_right,
+                voice.block(),
+                &mut self.channel_block_right[range],
+                self.inverse_block_size,
+                self.smooth_controllers,
+            );
+        }
+    }
+
+    fn process_block(&mut self) {
+        self.block_end = self.get_sample_position() + self.block_size as u64;
+        self.process_scheduled_messages();
+        self.process_arpeggiators();
+
+        self.voices.process(&self.channels);
+
+        // The gain of the SoundFont is applied to its voices and their effect sends,
+        // and the master volume to the voices and the effect returns.
+        let output_gain = self.master_volume;
+
+        self.block_left.fill(0_f32);
+        self.block_right.fill(0_f32);
+        self.channel_levels.fill(0_f32);
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if !Synthesizer::is_audible(voice, self.debug_solo_region) {
+                continue;
+            }
+            let voice_gain = output_gain * voice.sound_font_gain;
+            let previous_gain_left = voice_gain * voice.previous_mix_gain_left;
+            let current_gain_left = voice_gain * voice.current_mix_gain_left;
+            let previous_gain_right = voice_gain * voice.previous_mix_gain_right;
+            let current_gain_right = voice_gain * voice.current_mix_gain_right;
+
+            let peak = voice.block().iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+            let gain = previous_gain_left
+                .max(current_gain_left)
+                .max(previous_gain_right)
+                .max(current_gain_right);
+            self.channel_levels[voice.channel() as usize] += gain * peak;
+
+            Synthesizer::write_block(
+                previous_gain_left,
+                current_gain_left,
+                voice.block(),
+                &mut self.block_left[..],
+                self.inverse_block_size,
+                self.smooth_controllers,
+            );
+            Synthesizer::write_block(
+                previous_gain_right,
+                current_gain_right,
+                voice.block(),
+                &mut self.block_right[..],
+                self.inverse_block_size,
+                self.smooth_controllers,
+            );
+        }
+
+        if self.channel_mix {
+            self.mix_channels();
+        }
+
+        let solo_sample = self.debug_solo_region;
+        if let Some(effects) = self.effects.as_mut() {
+            effects.process(
+                self.voices.get_active_voices_ref(),
+                |voice| Synthesizer::is_audible(voice, solo_sample),
+                output_gain,
+                &mut self.block_left[..],
+                &mut self.block_right[..],
+            );
+        }
+
+        // The channels with their own effects get the returns in their mix.
+        // A channel without voices is silent once its tails have decayed, so its effects are skipped.
+        self.channel_effects_block = self.channel_effects_enabled;
+        let channel_effects = self
+            .channel_effects
+            .iter_mut()
+            .enumerate()
+            .filter(|_| self.channel_effects_enabled);
+        for (channel, effects) in channel_effects {
+            let audible = |voice: &Voice| {
+                voice.channel() == channel as i32 && Synthesizer::is_audible(voice, solo_sample)
+            };
+            let voices = self.voices.get_active_voices_ref();
+            let peak = &mut self.channel_effect_peaks[channel];
+            if *peak < Synthesizer::SILENCE_THRESHOLD && !voices.iter().any(audible) {
+                continue;
+            }
+
+            let range = channel * self.block_size..(channel + 1) * self.block_size;
+            let left = &mut self.channel_block_left[range.clone()];
+            let right = &mut self.channel_block_right[range];
+            effects.process(voices, audible, output_gain, left, right);
+            *peak = effects.get_return_peak(left, right);
+        }
+
+        for effect in self.custom_effects.iter_mut() {
+            effect.process(&mut self.block_left[..], &mut self.block_right[..]);
+        }
+
+        self.master_lowpass
+            .process(&self.block_left[..], &self.block_right[..]);
+        let (left, right) = self
+            .master_lowpass
+            .select(&self.block_left[..], &self.block_right[..]);
+        self.output_stage.process(left, right);
+
+        self.output_peak = if self.voices.active_voice_count == 0 {
+            let channel_peaks = self
+                .channel_effect_peaks
+                .iter()
+                .filter(|_| self.channel_effects_enabled);
+            self.block_left
+                .iter()
+                .chain(self.block_right.iter())
+                .chain(channel_peaks)
+                .fold(0_f32, |peak, value| peak.max(value.abs()))
+        } else {
+            f32::MAX
+        };
+    }
+
+    // While a region is soloed, the voices playing the other samples are left out of the mix.
+    fn is_audible(voice: &Voice, solo_sample: Option<usize>) -> bool {
+        match solo_sample {
+            Some(sample_id) => voice.sample_id() == sample_id,
+            None => true,
+        }
+    }
+
+    // Plays the steps of the arpeggiators which fall within the next block,
+    // starting their notes at their offsets as the scheduled messages do.
+    fn process_arpeggiators(&mut self) {
+        let beat_length = self.sample_rate as f64 * 60.0 / self.tempo;
+
+        for channel in 0..self.arpeggiators.len() {
+            let step_length = match self.arpeggiators[channel].as_ref() {
+                Some(arpeggiator) => beat_length / arpeggiator.get_config().rate,
+                None => continue,
+            };
+
+            while let Some((offset, step)) = self.arpeggiators[channel]
+                .as_mut()
+                .and_then(|arpeggiator| arpeggiator.next_step(self.block_size, step_length))
+            {
+                if let Some(key) = step.note_off {
+                    self.stop_note(channel as i32, key, 64);
+                }
+                if let Some((key, velocity)) = step.note_on {
+                    self.start_delay = offset;
+                    self.start_note(channel as i32, key, velocity, None);
+                }
+            }
+        }
+        self.start_delay = 0;
+    }
+
+    fn write_block(
+        previous_gain: f32,
+        current_gain: f32,
+        source: &[f32],
+        destination: &mut [f32],
+        inverse_block_size: f32,
+        smooth: bool,
+    ) {
+        if SoundFontMath::max(previous_gain, current_gain) < SoundFontMath::NON_AUDIBLE {
+            return;
+        }
+
+        // Small gain changes are normally applied at once, which is cheaper than ramping.
+        let threshold = if smooth { 0_f32 } else { 1.0E-3_f32 };
+        if (current_gain - previous_gain).abs() <= threshold {
+            ArrayMath::multiply_add(current_gain, source, destination);
+        } else {
+            let step = inverse_block_size * (current_gain - previous_gain);
+            ArrayMath::multiply_add_slope(previous_gain, step, source, destination);
+        }
+    }
+
+    /// Computes a hash of the internal state, for regression tests of the rendering.
+    ///
+    /// The hash covers the controllers of the channels, the phases and envelopes of the active voices,
+    /// the contents of the reverb and chorus buffers, and the rendered block.
+    /// It is stable across builds and platforms, so the same input giving the same fingerprint
+    /// before and after a change shows that the change did not alter the output.
+    /// The SoundFont and the settings are not included.
+    pub fn state_fingerprint(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+
+        for channel in &self.channels {
+            channel.hash_state(&mut hasher);
+        }
+
+        let voices = self.voices.get_active_voices_ref();
+        hasher.write_usize(voices.len());
+        for voice in voices {
+            voice.hash_state(&mut hasher);
+        }
+
+        for effects in self.effects.iter().chain(self.channel_effects.iter()) {
+            effects.reverb.hash_state(&mut hasher);
+            effects.chorus.hash_state(&mut hasher);
+        }
+
+        let master_lowpass = &self.master_lowpass;
+        master_lowpass.left.hash_state(&mut hasher);
+        master_lowpass.right.hash_state(&mut hasher);
+        hasher.write_f32(master_lowpass.cutoff);
+        hasher.write_f32(self.output_stage.gain);
+        hasher.write_f32(self.output_stage.reduction);
+
+        hasher.write_f64(self.tempo);
+        for arpeggiator in &self.arpeggiators {
+            hasher.write_bool(arpeggiator.is_some());
+            if let Some(arpeggiator) = arpeggiator {
+                arpeggiator.hash_state(&mut hasher);
+            }
+        }
+
+        hasher.write_usize(self.scheduled_messages.len());
+        for (offset, message) in &self.scheduled_messages {
+            hasher.write_usize(*offset);
+            for &value in message {
+                hasher.write_i32(value);
+            }
+        }
+
+        hasher.write_f32(self.master_volume);
+        for (_, gain) in &self.sound_fonts {
+            hasher.write_f32(*gain);
+        }
+        hasher.write_usize(self.block_read);
+        hasher.write_f32_slice(&self.block_left);
+        hasher.write_f32_slice(&self.block_right);
+
+        hasher.finish()
+    }
+
+    /// Gets the peak level of each channel in the last rendered block, before the effects.
+    ///
+    /// The level of a channel is the sum of the peaks of its voices,
+    /// which is an upper bound of the actual peak of the channel's mix.
+    pub fn get_channel_levels(&self) -> [f32; 16] {
+        self.channel_levels
+    }
+
+    /// Gets the SoundFont at the bottom of the stack, which is the one given to `new`
+    /// unless the stack has been changed.
+    pub fn get_sound_font(&self) -> &SoundFont {
+        &self.sound_fonts[0].0
+    }
+
+    /// Gets the SoundFont at the index of the stack, counted from the bottom.
+    pub fn get_sound_font_at(&self, index: usize) -> Option<&SoundFont> {
+        self.sound_fonts
+            .get(index)
+            .map(|(sound_font, _)| &**sound_font)
+    }
+
+    /// Gets the number of the SoundFonts in the stack, which is at least 1.
+    pub fn get_sound_font_count(&self) -> usize {
+        self.sound_fonts.len()
+    }
+
+    /// Replaces the stack of the SoundFonts with the SoundFont, without resetting the synthesizer.
+    ///
+    /// The notes started after the call play the new SoundFont, while the sounding voices finish
+    /// on the samples of the old one, or are faded out over `fade_ms` milliseconds if it is positive,
+    /// so that the SoundFont can be changed during a performance without a dropout.
+    /// The channels, the controllers, the effects, and the settings are kept,
+    /// and the programs of the channels are looked up again in the new SoundFont.
+    /// The old SoundFonts are kept by the synthesizer until `collect_garbage` is called
+    /// after their last voice has ended.
+    pub fn set_sound_font(&mut self, sound_font: &Arc<SoundFont>, fade_ms: f32) {
+        if fade_ms < 0_f32 || fade_ms.is_nan() {
+            panic!("The fade time must be a non-negative value.");
+        }
+
+        if fade_ms > 0_f32 {
+            let sample_count = (self.sample_rate as f32 * fade_ms / 1000_f32) as usize;
+            for voice in self.voices.get_active_voices().iter_mut() {
+                if !voice.plays_sound_font(sound_font) {
+                    voice.fade_out(sample_count);
+                }
+            }
+        }
+
+        let old_sound_fonts =
+            core::mem::replace(&mut self.sound_fonts, vec![(Arc::clone(sound_font), 1_f32)]);
+        for (old_sound_font, _) in old_sound_fonts {
+            if !Arc::ptr_eq(&old_sound_font, sound_font) {
+                self.retire_sound_font(old_sound_font);
+            }
+        }
+        self.update_sound_fonts();
+    }
+
+    /// Puts the SoundFont on top of the stack, so that its presets take precedence over the others.
+    ///
+    /// A note-on looks for the bank and program of the channel from the top of the stack down,
+    /// and falls back to the GM sound set and then to the default preset of the bottom SoundFont
+    /// only if no SoundFont has it, so a small SoundFont, such as one with a better drum kit,
+    /// can be layered on a GM SoundFont. The change applies to the notes started after the call,
+    /// and the sample rate and the settings of the synthesizer are kept.
+    pub fn add_sound_font(&mut self, sound_font: &Arc<SoundFont>) {
+        self.sound_fonts.push((Arc::clone(sound_font), 1_f32));
+        self.update_sound_fonts();
+    }
+
+    /// Takes the SoundFont at the index, counted from the bottom, out of the stack.
+    /// The voices playing from it are stopped at once,
+    /// and the synthesizer keeps the SoundFont until `collect_garbage` is called.
+    ///
+    /// Returns `None` if the index is out of range or the SoundFont is the only one in the stack.
+    pub fn remove_sound_font(&mut self, index: usize) -> Option<Arc<SoundFont>> {
+        if index >= self.sound_fonts.len() || self.sound_fonts.len() == 1 {
+            return None;
+        }
+
+        let (sound_font, _) = self.sound_fonts.remove(index);
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if voice.plays_sound_font(&sound_font) {
+                voice.kill();
+            }
+        }
+
+        self.retire_sound_font(Arc::clone(&sound_font));
+        self.update_sound_fonts();
+        Some(sound_font)
+    }
+
+    /// Moves the SoundFont at the index `from` to the index `to` of the stack, counted from the bottom.
+    ///
+    /// The sounding voices keep playing. Nothing is done if either index is out of range.
+    pub fn move_sound_font(&mut self, from: usize, to: usize) {
+        if from >= self.sound_fonts.len() || to >= self.sound_fonts.len() {
+            return;
+        }
+
+        let sound_font = self.sound_fonts.remove(from);
+        self.sound_fonts.insert(to, sound_font);
+        self.update_sound_fonts();
+    }
+
+    /// Drops the SoundFonts taken out of the stack by `set_sound_font` or `remove_sound_font`
+    /// once no voice plays them any more.
+    ///
+    /// A voice which ends in `render` only lets go of its reference to the SoundFont,
+    /// so a replaced SoundFont is freed by this method, or by the caller if it keeps another reference.
+    /// This should be called on the control thread from time to time, such as after each change of the SoundFont.
+    pub fn collect_garbage(&mut self) {
+        self.retired_sound_fonts
+            .retain(|sound_font| Arc::strong_count(sound_font) > 1);
+    }
+
+    fn retire_sound_font(&mut self, sound_font: Arc<SoundFont>) {
+        if !self
+            .retired_sound_fonts
+            .iter()
+            .any(|retired| Arc::ptr_eq(retired, &sound_font))
+        {
+            self.retired_sound_fonts.push(sound_font);
+        }
+    }
+
+    // Looks up the presets again after the stack of the SoundFonts is changed.
+    fn update_sound_fonts(&mut self) {
+        self.default_preset = Synthesizer::find_default_preset(&self.sound_fonts[0].0);
+        let modulator_count = self
+            .sound_fonts
+            .iter()
+            .map(|(sound_font, _)| sound_font.get_max_region_modulator_count())
+            .max();
+        self.voices.reserve_modulators(modulator_count.unwrap_or(0));
+        for channel in self.channels.iter_mut() {
+            channel.clear_resolved_preset();
+        }
+    }
+
+    pub fn get_sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    pub fn get_block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Gets the number of the voices currently sounding, including those in their release.
+    pub fn get_active_voice_count(&self) -> usize {
+        self.voices.active_voice_count
+    }
+
+    pub fn get_maximum_polyphony(&self) -> usize {
+        self.maximum_polyphony
+    }
+
+    pub fn get_render_thread_count(&self) -> usize {
+        self.voices.get_thread_count()
+    }
+
+    pub fn get_enable_reverb_and_chorus(&self) -> bool {
+        self.effects.is_some()
+    }
+
+    pub fn get_retrigger_mode(&self) -> RetriggerMode {
+        self.retrigger_mode
+    }
+
+    pub fn get_reset_phase_on_note_on(&self) -> bool {
+        self.reset_phase_on_note_on
+    }
+
+    pub fn get_voice_stealing_policy(&self) -> VoiceStealingPolicy {
+        self.voices.get_stealing_policy()
+    }
+
+    pub fn get_dither_mode(&self) -> DitherMode {
+        self.dither.mode
+    }
+
+    pub fn get_use_note_off_velocity(&self) -> bool {
+        self.use_note_off_velocity
+    }
+
+    pub fn get_crossfade_program_change_ms(&self) -> f32 {
+        self.crossfade_program_change_ms
+    }
+
+    pub fn get_smooth_controllers(&self) -> bool {
+        self.smooth_controllers
+    }
+
+    /// Gets the gain in decibels of the SoundFont at the bottom of the stack.
+    pub fn get_soundfont_gain(&self) -> f32 {
+        SoundFontMath::linear_to_decibels(self.sound_fonts[0].1)
+    }
+
+    /// Sets the gain applied to the output of the SoundFont at the bottom of the stack
+    /// before the master volume, which can trim the different loudness of SoundFonts.
+    /// This is the same as `set_soundfont_gain_at(0, db)`.
+    pub fn set_soundfont_gain(&mut self, db: f32) {
+        self.set_soundfont_gain_at(0, db);
+    }
+
+    /// Gets the gain in decibels of the SoundFont at the index of the stack, counted from the bottom,
+    /// or `None` if the index is out of range.
+    pub fn get_soundfont_gain_at(&self, index: usize) -> Option<f32> {
+        self.sound_fonts
+            .get(index)
+            .map(|(_, gain)| SoundFontMath::linear_to_decibels(*gain))
+    }
+
+    /// Sets the gain applied to the voices of the SoundFont at the index of the stack,
+    /// counted from the bottom, and to their effect sends, so that the SoundFonts
+    /// layered on each other can be balanced. The sounding voices follow the change.
+    ///
+    /// The value is in decibels, clamped within -48 dB and +24 dB.
+    /// The gain is kept across `reset` and when the stack is reordered,
+    /// and is 0 dB for a SoundFont given to `set_sound_font` or `add_sound_font`.
+    /// Nothing is done if the index is out of range.
+    pub fn set_soundfont_gain_at(&mut self, index: usize, db: f32) {
+        let Some((sound_font, gain)) = self.sound_fonts.get_mut(index) else {
+            return;
+        };
+
+        *gain = SoundFontMath::decibels_to_linear(db.clamp(-48_f32, 24_f32));
+        for voice in self.voices.get_active_voices().iter_mut() {
+            if voice.plays_sound_font(sound_font) {
+                voice.sound_font_gain = *gain;
+            }
+        }
+    }
+
+    /// Gets the cutoff frequency of the master low-pass filter in Hz, or `None` if it is off.
+    pub fn get_master_lowpass(&self) -> Option<f64> {
+        self.master_lowpass.target.map(|cutoff| cutoff as f64)
+    }
+
+    /// Sets the cutoff frequency of a low-pass filter on the output of `render`, in Hz,
+    /// or turns it off with `None`. The cutoff frequency must be positive.
+    ///
+    /// This muffles the whole mix, for example when the listener goes underwater or behind a wall.
+    /// The cutoff glides to the new value over about 50 ms, so that it can be changed every frame
+    /// without clicks, and turning the filter off opens it up gradually as well.
+    /// `render_dry_wet` and `render_multichannel` are not filtered.
+    /// The setting is kept across `reset`.
+    pub fn set_master_lowpass(&mut self, cutoff_hz: Option<f64>) {
+        if let Some(cutoff_hz) = cutoff_hz {
+            if cutoff_hz <= 0.0 || cutoff_hz.is_nan() {
+                panic!("The cutoff frequency must be a positive value.");
+            }
+        }
+
+        self.master_lowpass.target =
+            cutoff_hz.map(|cutoff_hz| (cutoff_hz as f32).min(self.master_lowpass.open_cutoff));
+    }
+
+    /// Sets the room size of the reverb, from 0 to 1, which lengthens the decay.
+    /// The default is 0.5, and the value is clamped to the range.
+    ///
+    /// This and the other settings of the reverb and chorus have no effect
+    /// if they are disabled by `SynthesizerSettings::enable_reverb_and_chorus`.
+    /// The setting is kept across `reset`.
+    pub fn set_reverb_room_size(&mut self, value: f32) {
+        self.update_effects(|effects| effects.reverb.set_room_size(value.clamp(0_f32, 1_f32)));
+    }
+
+    /// Gets the room size of the reverb, or `None` if the reverb and chorus are disabled.
+    pub fn get_reverb_room_size(&self) -> Option<f32> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.reverb.get_room_size())
+    }
+
+    /// Sets the damping of the reverb, from 0 to 1, which makes the high frequencies decay faster.
+    /// The default is 0.5, and the value is clamped to the range.
+    /// The setting is kept across `reset`.
+    pub fn set_reverb_damping(&mut self, value: f32) {
+        self.update_effects(|effects| effects.reverb.set_damp(value.clamp(0_f32, 1_f32)));
+    }
+
+    /// Gets the damping of the reverb, or `None` if the reverb and chorus are disabled.
+    pub fn get_reverb_damping(&self) -> Option<f32> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.reverb.get_damp())
+    }
+
+    /// Sets the stereo width of the reverb, from 0 for mono to 1 for the full width.
+    /// The default is 1, or 0 with `SynthesizerSettings::mono_safe_effects`,
+    /// and the value is clamped to the range.
+    /// The setting is kept across `reset`.
+    pub fn set_reverb_width(&mut self, value: f32) {
+        self.update_effects(|effects| effects.reverb.set_width(value.clamp(0_f32, 1_f32)));
+    }
+
+    /// Gets the stereo width of the reverb, or `None` if the reverb and chorus are disabled.
+    pub fn get_reverb_width(&self) -> Option<f32> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.reverb.get_width())
+    }
+
+    /// Sets the gain of the reverb return. The default is 1, and the value is clamped to the range of 0 to 2.
+    /// The setting is kept across `reset`.
+    pub fn set_reverb_wet_level(&mut self, value: f32) {
+        self.update_effects(|effects| effects.reverb.set_wet_level(value.clamp(0_f32, 2_f32)));
+    }
+
+    /// Gets the gain of the reverb return, or `None` if the reverb and chorus are disabled.
+    pub fn get_reverb_wet_level(&self) -> Option<f32> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.reverb.get_wet_level())
+    }
+
+    /// Sets the rate of the chorus modulation in Hz.
+    /// The default is 0.4 Hz, and the value is clamped to the range of 0.1 to 10 Hz,
+    /// where NaN gives the lowest rate.
+    /// The setting is kept across `reset`.
+    pub fn set_chorus_rate(&mut self, hz: f64) {
+        let hz = if hz.is_nan() {
+            Chorus::MIN_RATE
+        } else {
+            hz.clamp(Chorus::MIN_RATE, Chorus::MAX_RATE)
+        };
+        self.update_effects(|effects| effects.chorus.set_rate(hz));
+    }
+
+    /// Gets the rate of the chorus modulation in Hz, or `None` if the reverb and chorus are disabled.
+    pub fn get_chorus_rate(&self) -> Option<f64> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.chorus.get_rate())
+    }
+
+    /// Sets the depth of the chorus modulation, from 0 to 1,
+    /// where 1 sweeps the delay from 0 to twice the base delay of 2 ms.
+    /// The default is 0.95, and the value is clamped to the range.
+    /// The setting is kept across `reset`.
+    pub fn set_chorus_depth(&mut self, value: f64) {
+        self.update_effects(|effects| effects.chorus.set_depth(value.clamp(0.0, 1.0)));
+    }
+
+    /// Gets the depth of the chorus modulation, or `None` if the reverb and chorus are disabled.
+    pub fn get_chorus_depth(&self) -> Option<f64> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.chorus.get_depth())
+    }
+
+    /// Sets how much of the chorus output is fed back into its delay lines, from 0 to 0.95.
+    /// The default is 0, and the value is clamped to the range.
+    /// The setting is kept across `reset`.
+    pub fn set_chorus_feedback(&mut self, value: f32) {
+        self.update_effects(|effects| effects.chorus.set_feedback(value.clamp(0_f32, 0.95_f32)));
+    }
+
+    /// Gets the feedback of the chorus, or `None` if the reverb and chorus are disabled.
+    pub fn get_chorus_feedback(&self) -> Option<f32> {
+        self.effects
+            .as_ref()
+            .map(|effects| effects.chorus.get_feedback())
+    }
+
+    // Applies a change of the settings to the global effects and the effects of each channel.
+    fn update_effects<F: Fn(&mut Effects)>(&mut self, update: F) {
+        for effects in self
+            .effects
+            .iter_mut()
+            .chain(self.channel_effects.iter_mut())
+        {
+            update(effects);
+        }
+    }
+
+    /// Gets the output gain in decibels.
+    pub fn get_output_gain(&self) -> f32 {
+        SoundFontMath::linear_to_decibels(self.output_stage.gain)
+    }
+
+    /// Sets the gain in decibels applied at the very end of the output, after the master volume,
+    /// the effects, and the master low-pass filter. The default is 0 dB.
+    ///
+    /// A negative gain gives headroom to loud MIDI files, which often exceed the range of `[-1, 1]`.
+    /// Unlike the master volume, this is not changed by the SysEx messages.
+    /// The value is clamped to the range of -48 to 24 dB.
+    /// This and the limiter apply to `render` and its variants, but not to `render_dry_wet`
+    /// and `render_multichannel`. The setting is kept across `reset`.
+    pub fn set_output_gain(&mut self, db: f32) {
+        let gain = SoundFontMath::decibels_to_linear(db.clamp(-48_f32, 24_f32));
+        // 0 dB passes the output as it is, without the rounding of the conversion.
+        self.output_stage.gain = if db == 0_f32 { 1_f32 } else { gain };
+    }
+
+    /// Gets how the output is kept within the range of `[-1, 1]`.
+    pub fn get_limiter_mode(&self) -> LimiterMode {
+        self.output_stage.mode
+    }
+
+    /// Sets how the output is kept within the range of `[-1, 1]`, after the output gain.
+    /// The default is `LimiterMode::Off`. The setting is kept across `reset`.
+    pub fn set_limiter_mode(&mut self, mode: LimiterMode) {
+        self.output_stage.mode = mode;
+    }
+
+    /// Returns `true` if a sample of the output has exceeded the range of `[-1, 1]`
+    /// since the synthesizer was created, reset, or `clear_clipped` was called.
+    ///
+    /// The level is checked after the output gain and before the limiter,
+    /// so this tells whether the limiter had to act, or whether the output clipped without it.
+    pub fn has_clipped(&self) -> bool {
+        self.output_stage.clipped
+    }
+
+    /// Clears the flag returned by `has_clipped`.
+    pub fn clear_clipped(&mut self) {
+        self.output_stage.clipped = false;
+    }
+
+    /// Adds a custom effect to the end of the effect chain.
+    ///
+    /// The effect processes the whole mix, including the returns of the built-in reverb and chorus.
+    /// Its change to the mix is a part of the wet output of `render_dry_wet`
+    /// and of the effect return of `render_multichannel`,
+    /// and is not heard when `ChannelRouting::set_channel_effects` is enabled.
+    /// The effects are kept across `reset`, which calls `AudioEffect::reset` on each of them.
+    pub fn add_effect(&mut self, effect: Box<dyn AudioEffect>) {
+        self.custom_effects.push(effect);
+    }
+
+    /// Removes all the custom effects added by `add_effect`.
+    pub fn clear_effects(&mut self) {
+        self.custom_effects.clear();
+    }
+
+    /// Gets the number of the custom effects added by `add_effect`.
+    pub fn get_effect_count(&self) -> usize {
+        self.custom_effects.len()
+    }
+
+    pub fn get_master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    /// Attaches an arpeggiator to the channel, or detaches it with `None`.
+    ///
+    /// While attached, the notes held on the channel are not played themselves,
+    /// but the arpeggiator plays them one at a time, each until the next step,
+    /// at the rate synced to the tempo set by `set_tempo`.
+    /// The notes of the steps start at their exact sample, even within a block, while the notes
+    /// they replace are released at the start of the block, as with `process_midi_message_at`.
+    /// Replacing or detaching the arpeggiator stops the note it is playing and forgets the held notes.
+    /// The setting is kept across `reset`.
+    pub fn set_arpeggiator(&mut self, channel: i32, config: Option<ArpConfig>) {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return;
+        }
+
+        let arpeggiator = config.map(Arpeggiator::new);
+        if let Some(mut previous) = self.arpeggiators[channel as usize].take() {
+            if let Some(key) = previous.clear() {
+                self.stop_note(channel, key, 64);
+            }
+        }
+        self.arpeggiators[channel as usize] = arpeggiator;
+    }
+
+    /// Gets the settings of the arpeggiator attached to the channel,
+    /// or `None` if there is none or the channel is out of range.
+    pub fn get_arpeggiator(&self, channel: i32) -> Option<ArpConfig> {
+        if !(0 <= channel && channel < self.channels.len() as i32) {
+            return None;
+        }
+
+        self.arpeggiators[channel as usize]
+            .as_ref()
+            .map(|arpeggiator| *arpeggiator.get_config())
+    }
+
+    /// Gets the index of the sample soloed by `set_debug_solo_region`.
+    pub fn get_debug_solo_region(&self) -> Option<usize> {
+        self.debug_solo_region
+    }
+
+    /// Mutes all the voices except those playing the sample, a development aid for hearing a region
+    /// of the SoundFont in isolation, for example to find which sample produces an artifact.
+    /// `None` makes all the voices audible again.
+    ///
+    /// The sample is the index into `SoundFont::get_sample_headers`, as `InstrumentRegion::get_sample_id` gives.
+    /// The muted voices still play and take up the polyphony, but are left out of the mix
+    /// and the effect sends, so the note-ons and note-offs are not disturbed.
+    /// The setting is kept across `reset`.
+    pub fn set_debug_solo_region(&mut self, sample_index: Option<usize>) {
+        self.debug_solo_region = sample_index;
+    }
+
+    /// Gets the tempo in BPM which the arpeggiators are synced to.
+    pub fn get_tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// Sets the tempo in BPM which the arpeggiators are synced to. The default is 120.
+    ///
+    /// `MidiFileSequencer` sets this to the tempo of the MIDI file as it plays.
+    /// The setting is kept across `reset`.
+    pub fn set_tempo(&mut self, bpm: f64) {
+        if bpm <= 0.0 || bpm.is_nan() {
+            panic!("The tempo must be a positive value.");
+        }
+
+        self.tempo = bpm;
+    }
+
+    pub fn set_master_volume(&mut self, value: f32) {
+        self.master_volume = value;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Effects {
+    reverb: Reverb,
+    reverb_input: Vec<f32>,
+    reverb_output_left: Vec<f32>,
+    reverb_output_right: Vec<f32>,
+
+    chorus: Chorus,
+    chorus_input_left: Vec<f32>,
+    chorus_input_right: Vec<f32>,
+    chorus_output_left: Vec<f32>,
+    chorus_output_right: Vec<f32>,
+
+    // The voice mix of the block before the effect returns are added.
+    dry_left: Vec<f32>,
+    dry_right: Vec<f32>,
+
+    inverse_block_size: f32,
+    smooth: bool,
+}
 
 impl Effects {
-    fn new(settings: &SynthesizerSettings) -> Effects {
+    // Nothing is allocated if the effects are disabled.
+    fn new(settings: &SynthesizerSettings) -> Option<Effects> {
+        if !settings.enable_reverb_and_chorus {
+            return None;
+        }
+
+        let mut reverb = Reverb::new(settings.sample_rate, settings.flush_denormals);
+        let mut chorus = Chorus::new(
+            settings.sample_rate,
+            0.002,
+            0.0019,
+            0.4,
+            settings.flush_denormals,
+        );
+        if settings.mono_safe_effects {
+            reverb.set_width(0_f32);
+            chorus.set_mono();
+        }
+
+        Some(Self {
+            reverb,
+            reverb_input: vec![0_f32; settings.block_size],
+            reverb_output_left: vec![0_f32; settings.block_size],
+            reverb_output_right: vec![0_f32; settings.block_size],
+            chorus,
+            chorus_input_left: vec![0_f32; settings.block_size],
+            chorus_input_right: vec![0_f32; settings.block_size],
+            chorus_output_left: vec![0_f32; settings.block_size],
+            chorus_output_right: vec![0_f32; settings.block_size],
+            dry_left: vec![0_f32; settings.block_size],
+            dry_right: vec![0_f32; settings.block_size],
+            inverse_block_size: 1_f32 / settings.block_size as f32,
+            smooth: settings.smooth_controllers,
+        })
+    }
+
+    // Mixes the sends of the voices into the effects, and adds their returns to the mix.
+    fn process<F: Fn(&Voice) -> bool>(
+        &mut self,
+        voices: &[Voice],
+        audible: F,
+        output_gain: f32,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        self.dry_left.copy_from_slice(left);
+        self.dry_right.copy_from_slice(right);
+
+        let chorus = &mut self.chorus;
+        let chorus_input_left = &mut self.chorus_input_left[..];
+        let chorus_input_right = &mut self.chorus_input_right[..];
+        let chorus_output_left = &mut self.chorus_output_left[..];
+        let chorus_output_right = &mut self.chorus_output_right[..];
+        chorus_input_left.fill(0_f32);
+        chorus_input_right.fill(0_f32);
+        for voice in voices.iter().filter(|voice| audible(voice)) {
+            let send = voice.sound_font_gain;
+            let previous_gain_left =
+                send * voice.previous_chorus_send * voice.previous_mix_gain_left;
+            let current_gain_left = send * voice.current_chorus_send * voice.current_mix_gain_left;
+            Synthesizer::write_block(
+                previous_gain_left,
+                current_gain_left,
+                voice.block(),
+                chorus_input_left,
+                self.inverse_block_size,
+                self.smooth,
+            );
+            let previous_gain_right =
+                send * voice.previous_chorus_send * voice.previous_mix_gain_right;
+            let current_gain_right =
+                send * voice.current_chorus_send * voice.current_mix_gain_right;
+            Synthesizer::write_block(
+                previous_gain_right,
+                current_gain_right,
+                voice.block(),
+                chorus_input_right,
+                self.inverse_block_size,
+                self.smooth,
+            );
+        }
+        chorus.process(
+            chorus_input_left,
+            chorus_input_right,
+            chorus_output_left,
+            chorus_output_right,
+        );
+        ArrayMath::multiply_add(output_gain, chorus_output_left, left);
+        ArrayMath::multiply_add(output_gain, chorus_output_right, right);
+
+        let reverb = &mut self.reverb;
+        let reverb_input = &mut self.reverb_input[..];
+        let reverb_output_left = &mut self.reverb_output_left[..];
+        let reverb_output_right = &mut self.reverb_output_right[..];
+        reverb_input.fill(0_f32);
+        for voice in voices.iter().filter(|voice| audible(voice)) {
+            let previous_gain = reverb.get_input_gain()
+                * voice.sound_font_gain
+                * voice.previous_reverb_send
+                * (voice.previous_mix_gain_left + voice.previous_mix_gain_right);
+            let current_gain = reverb.get_input_gain()
+                * voice.sound_font_gain
+                * voice.current_reverb_send
+                * (voice.current_mix_gain_left + voice.current_mix_gain_right);
+            Synthesizer::write_block(
+                previous_gain,
+                current_gain,
+                voice.block(),
+                &mut reverb_input[..],
+                self.inverse_block_size,
+                self.smooth,
+            );
+        }
+
+        reverb.process(reverb_input, reverb_output_left, reverb_output_right);
+        ArrayMath::multiply_add(output_gain, reverb_output_left, left);
+        ArrayMath::multiply_add(output_gain, reverb_output_right, right);
+    }
+
+    // The peak of the returns added to the mix by the last `process`.
+    fn get_return_peak(&self, left: &[f32], right: &[f32]) -> f32 {
+        let left = left.iter().zip(&self.dry_left);
+        let right = right.iter().zip(&self.dry_right);
+        left.chain(right)
+            .fold(0_f32, |peak, (value, dry)| peak.max((value - dry).abs()))
+    }
+
+    // A new chain with the same settings and silent delay lines.
+    fn new_silent(&self) -> Effects {
+        let mut effects = self.clone();
+        effects.reverb.mute();
+        effects.chorus.mute();
+        effects
+    }
+
+    #[cfg(test)]
+    fn heap_size(&self) -> usize {
+        let buffers = [
+            &self.reverb_input,
+            &self.reverb_output_left,
+            &self.reverb_output_right,
+            &self.chorus_input_left,
+            &self.chorus_input_right,
+            &self.chorus_output_left,
+            &self.chorus_output_right,
+            &self.dry_left,
+            &self.dry_right,
+        ];
+        let buffer_size: usize = buffers.iter().map(|buffer| buffer.len()).sum();
+
+        self.reverb.heap_size() + self.chorus.heap_size() + size_of::<f32>() * buffer_size
+    }
+}
+
+// The low-pass filter on the master output, with the cutoff gliding toward the target.
+#[derive(Debug, Clone)]
+struct MasterLowPass {
+    target: Option<f32>,
+    cutoff: f32,
+    // The cutoff at which the filter is bypassed.
+    open_cutoff: f32,
+    glide: f32,
+
+    left: BiQuadFilter,
+    right: BiQuadFilter,
+
+    // The filtered output of the block.
+    block_left: Vec<f32>,
+    block_right: Vec<f32>,
+}
+
+impl MasterLowPass {
+    const GLIDE_TIME: f32 = 0.05_f32;
+
+    fn new(settings: &SynthesizerSettings) -> Self {
+        let open_cutoff = 0.5_f32 * settings.sample_rate as f32;
+        let block_time = settings.block_size as f32 / settings.sample_rate as f32;
+
         Self {
-            reverb: Reverb::new(settings.sample_rate),
-            reverb_input: vec![0_f32; settings.block_size],
-            reverb_output_left: vec![0_f32; settings.block_size],
-            reverb_output_right: vec![0_f32; settings.block_size],
-            chorus: Chorus::new(settings.sample_rate, 0.002, 0.0019, 0.4),
-            chorus_input_left: vec![0_f32; settings.block_size],
-            chorus_input_right: vec![0_f32; settings.block_size],
-            chorus_output_left: vec![0_f32; settings.block_size],
-            chorus_output_right: vec![0_f32; settings.block_size],
+            target: None,
+            cutoff: open_cutoff,
+            open_cutoff,
+            glide: (-block_time / MasterLowPass::GLIDE_TIME).exp(),
+            left: BiQuadFilter::new(settings),
+            right: BiQuadFilter::new(settings),
+            block_left: vec![0_f32; settings.block_size],
+            block_right: vec![0_f32; settings.block_size],
+        }
+    }
+
+    // The filtered output is used while the filter is on or still opening up.
+    fn is_engaged(&self) -> bool {
+        self.target.is_some() || self.cutoff < self.open_cutoff
+    }
+
+    fn clear_buffer(&mut self) {
+        self.left.clear_buffer();
+        self.right.clear_buffer();
+    }
+
+    // Passes the given block through, or gives the filtered one while the filter is engaged.
+    fn select<'a>(&'a self, left: &'a [f32], right: &'a [f32]) -> (&'a [f32], &'a [f32]) {
+        if self.is_engaged() {
+            (&self.block_left[..], &self.block_right[..])
+        } else {
+            (left, right)
+        }
+    }
+
+    // The block is filtered even while the filter is disengaged,
+    // so that the filter state follows the signal and can be engaged at any time.
+    fn process(&mut self, left: &[f32], right: &[f32]) {
+        let target = self.target.unwrap_or(self.open_cutoff);
+
+        // The cutoff glides exponentially in the log-frequency domain.
+        self.cutoff = target * (self.cutoff / target).powf(self.glide);
+        if (self.cutoff / target - 1_f32).abs() < 1.0E-3_f32 {
+            self.cutoff = target;
+        }
+
+        self.left.set_low_pass_filter(self.cutoff, 1_f32);
+        self.right.set_low_pass_filter(self.cutoff, 1_f32);
+
+        self.block_left.copy_from_slice(left);
+        self.block_right.copy_from_slice(right);
+        self.left.process(&mut self.block_left[..]);
+        self.right.process(&mut self.block_right[..]);
+    }
+}
+
+// The output gain and the clip protection at the very end of the master output.
+#[derive(Debug, Clone)]
+struct OutputStage {
+    gain: f32,
+    mode: LimiterMode,
+
+    // The gain reduction of the limiter, which recovers toward 1.
+    reduction: f32,
+    release: f32,
+
+    // Whether a sample beyond the range has been seen since the flag was cleared.
+    clipped: bool,
+
+    // The processed output of the block.
+    block_left: Vec<f32>,
+    block_right: Vec<f32>,
+}
+
+impl OutputStage {
+    const RELEASE_TIME: f32 = 0.1_f32;
+    // The limiter keeps the peaks slightly below 1, so that the rounding does not push them over.
+    const CEILING: f32 = 0.999_f32;
+    const KNEE: f32 = 0.9_f32;
+
+    fn new(settings: &SynthesizerSettings) -> Self {
+        Self {
+            gain: 1_f32,
+            mode: LimiterMode::Off,
+            reduction: 1_f32,
+            release: (-1_f32 / (OutputStage::RELEASE_TIME * settings.sample_rate as f32)).exp(),
+            clipped: false,
+            block_left: vec![0_f32; settings.block_size],
+            block_right: vec![0_f32; settings.block_size],
+        }
+    }
+
+    fn is_engaged(&self) -> bool {
+        self.gain != 1_f32 || self.mode != LimiterMode::Off
+    }
+
+    fn clear_buffer(&mut self) {
+        self.reduction = 1_f32;
+        self.clipped = false;
+    }
+
+    fn select<'a>(&'a self, left: &'a [f32], right: &'a [f32]) -> (&'a [f32], &'a [f32]) {
+        if self.is_engaged() {
+            (&self.block_left[..], &self.block_right[..])
+        } else {
+            (left, right)
+        }
+    }
+
+    // The clipping is detected after the gain and before the protection,
+    // so that it tells whether the protection had anything to do.
+    fn process(&mut self, left: &[f32], right: &[f32]) {
+        if !self.is_engaged() {
+            self.clipped |= left.iter().chain(right).any(|x| x.abs() > 1_f32);
+            return;
+        }
+
+        for (destination, source) in [(&mut self.block_left, left), (&mut self.block_right, right)]
+        {
+            for (y, x) in destination.iter_mut().zip(source) {
+                *y = self.gain * x;
+            }
+        }
+        self.clipped |= self
+            .block_left
+            .iter()
+            .chain(self.block_right.iter())
+            .any(|x| x.abs() > 1_f32);
+
+        match self.mode {
+            LimiterMode::Off => (),
+            LimiterMode::SoftClip => {
+                for x in self
+                    .block_left
+                    .iter_mut()
+                    .chain(self.block_right.iter_mut())
+                {
+                    *x = OutputStage::soft_clip(*x);
+                }
+            }
+            LimiterMode::Limiter => {
+                for (left, right) in self.block_left.iter_mut().zip(self.block_right.iter_mut()) {
+                    let peak = left.abs().max(right.abs());
+                    let target = if peak > OutputStage::CEILING {
+                        OutputStage::CEILING / peak
+                    } else {
+                        1_f32
+                    };
+                    self.reduction = if target < self.reduction {
+                        target
+                    } else {
+                        target + self.release * (self.reduction - target)
+                    };
+                    *left *= self.reduction;
+                    *right *= self.reduction;
+                }
+            }
+        }
+    }
+
+    // Linear up to the knee, and then approaches 1 with the slope continuing smoothly.
+    fn soft_clip(x: f32) -> f32 {
+        let magnitude = x.abs();
+        if magnitude <= OutputStage::KNEE {
+            return x;
+        }
+
+        let range = 1_f32 - OutputStage::KNEE;
+        let y = OutputStage::KNEE + range * ((magnitude - OutputStage::KNEE) / range).tanh();
+        y.copysign(x)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::arpeggiator::ArpMode;
+    use crate::midifile::Message;
+    use crate::midifile::MidiFile;
+    use crate::pan_curve::PanCurve;
+    use crate::queue_overflow::QueueOverflow;
+
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    // Counts the allocations and the frees of each thread, so that the tests running in parallel do not interfere.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        // Freeing on the render thread is as costly as allocating, so it is counted as well.
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    pub(crate) fn count_allocations<F: FnOnce()>(f: F) -> usize {
+        let start = ALLOCATIONS.with(|count| count.get());
+        f();
+        ALLOCATIONS.with(|count| count.get()) - start
+    }
+
+    // The SoundFont of the tests, whose only region loops a silent sample.
+    pub(crate) fn test_sound_font() -> Arc<SoundFont> {
+        let data = crate::soundfont::tests::build_soundfont(&[], &[(54, 1)]);
+        Arc::new(SoundFont::new(&mut &data[..]).unwrap())
+    }
+
+    // The SoundFont of the tests with its only preset moved to a drum kit in the bank 128.
+    pub(crate) fn test_drum_sound_font() -> Arc<SoundFont> {
+        let mut data = crate::soundfont::tests::build_soundfont(&[], &[(54, 1)]);
+        let offset = data.windows(6).position(|name| name == b"Preset").unwrap();
+        data[offset..offset + 6].copy_from_slice(b"Kit\0\0\0");
+        data[offset + 22] = 128;
+        Arc::new(SoundFont::new(&mut &data[..]).unwrap())
+    }
+
+    // A synthesizer playing the test tone without the effects, so that the notes can be heard as they are.
+    pub(crate) fn test_synthesizer() -> Synthesizer {
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        settings.test_tone_mode = true;
+        Synthesizer::new(&test_sound_font(), &settings).unwrap()
+    }
+
+    #[test]
+    fn test_effects_not_allocated_when_disabled() {
+        let mut settings = SynthesizerSettings::new(44100);
+        let effects = Effects::new(&settings).unwrap();
+        // The delay lines of the reverb and chorus take more than 100 KB at 44.1 kHz.
+        assert!(effects.heap_size() > 100_000);
+
+        settings.enable_reverb_and_chorus = false;
+        assert!(Effects::new(&settings).is_none());
+    }
+
+    #[test]
+    fn test_vibrato_delay_scale() {
+        // A vibrato of 100 cents which sets in after 1 second.
+        let render = |generators: &[(u16, i16)], scale: Option<f32>| {
+            let data = crate::soundfont::tests::build_soundfont(&[], generators);
+            let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.enable_reverb_and_chorus = false;
+            settings.test_tone_mode = true;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            if let Some(scale) = scale {
+                synthesizer.set_vibrato_delay_scale(0, scale);
+            }
+            synthesizer.note_on(0, 60, 100);
+            let mut left = vec![0_f32; 11008];
+            let mut right = vec![0_f32; 11008];
+            synthesizer.render(&mut left, &mut right);
+            left
+        };
+
+        let plain = render(&[(54, 1)], None);
+        let vibrato = [(54, 1), (6, 100), (23, 0)];
+        assert_eq!(render(&vibrato, None), plain);
+        assert_eq!(render(&vibrato, Some(1_f32)), plain);
+        // Without the delay, the pitch moves from the start.
+        assert_ne!(render(&vibrato, Some(0_f32)), plain);
+
+        // The scale is ignored for a channel out of range.
+        let mut synthesizer = test_synthesizer();
+        synthesizer.set_vibrato_delay_scale(16, 0_f32);
+    }
+
+    #[test]
+    fn test_mono_safe_effects() {
+        let render = |mono_safe_effects: bool, pan: i32| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.test_tone_mode = true;
+            settings.mono_safe_effects = mono_safe_effects;
+            let mut synthesizer = Synthesizer::new(&test_sound_font(), &settings).unwrap();
+            synthesizer.process_midi_message(0, 0xB0, 0x0A, pan);
+            synthesizer.process_midi_message(0, 0xB0, 0x5B, 127);
+            synthesizer.process_midi_message(0, 0xB0, 0x5D, 127);
+            synthesizer.note_on(0, 60, 100);
+            let mut dry_left = vec![0_f32; 8192];
+            let mut dry_right = vec![0_f32; 8192];
+            let mut wet_left = vec![0_f32; 8192];
+            let mut wet_right = vec![0_f32; 8192];
+            synthesizer.render_dry_wet(
+                &mut dry_left,
+                &mut dry_right,
+                &mut wet_left,
+                &mut wet_right,
+            );
+            (wet_left, wet_right)
+        };
+
+        // The reverb and the chorus spread a centered note across the channels by default.
+        let (left, right) = render(false, 64);
+        assert!(left
+            .iter()
+            .zip(right.iter())
+            .any(|(x, y)| (x - y).abs() > 1e-4));
+
+        // The mono-safe effects return the same signal on both channels,
+        // even for a note panned to one side.
+        for pan in [64, 0, 127] {
+            let (left, right) = render(true, pan);
+            assert!(left.iter().any(|&x| x.abs() > 1e-4));
+            assert!(left
+                .iter()
+                .zip(right.iter())
+                .all(|(x, y)| (x - y).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_pitch_bend_range() {
+        let mut synthesizer = test_synthesizer();
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 2.0);
+
+        // The override takes precedence over RPN 0, and applies to the wheel already moved.
+        synthesizer.process_midi_message(0, 0xE0, 0x7F, 0x7F);
+        synthesizer.set_pitch_bend_range(0, 12.0);
+        for (controller, value) in [(0x65, 0), (0x64, 0), (0x06, 24)] {
+            synthesizer.process_midi_message(0, 0xB0, controller, value);
+        }
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 12.0);
+        assert!((synthesizer.channels[0].get_pitch_bend() - 12_f32).abs() < 0.01);
+
+        // The override is kept across reset, while the other channels are left alone.
+        synthesizer.reset();
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 12.0);
+        assert_eq!(synthesizer.get_pitch_bend_range(1), 2.0);
+
+        // Once cleared, RPN 0 controls the range again.
+        synthesizer.clear_pitch_bend_range(0);
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 2.0);
+        for (controller, value) in [(0x65, 0), (0x64, 0), (0x06, 24)] {
+            synthesizer.process_midi_message(0, 0xB0, controller, value);
+        }
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 24.0);
+        assert_eq!(synthesizer.get_pitch_bend_range(16), 0.0);
+    }
+
+    #[test]
+    fn test_recording() {
+        let data = crate::soundfont::tests::build_soundfont(&[], &[]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        let mut left = vec![0_f32; 100];
+        let mut right = vec![0_f32; 100];
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.process_midi_message(0, 0x90, 60, 100);
+        synthesizer.start_recording();
+        assert!(synthesizer.is_recording());
+
+        // The times count from the start of the recording, in the samples rendered.
+        synthesizer.process_midi_message(1, 0xC0, 5, 0);
+        synthesizer.process_midi_message_at(1, 0x90, 64, 100, 50);
+        synthesizer.render(&mut left, &mut right);
+        // The GM System On resets the synthesizer, which does not move the clock.
+        synthesizer.process_sysex(&[0x7E, 0x7F, 0x09, 0x01]);
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.process_midi_message(1, 0x80, 64, 0);
+        synthesizer.render(&mut left[..41], &mut right[..41]);
+        let recording = synthesizer.stop_recording().unwrap();
+        assert!(!synthesizer.is_recording());
+        assert_eq!(recording.get_event_count(), 4);
+        assert_eq!(recording.get_length(), 241_f64 / 44100_f64);
+
+        // At 120 BPM and 441 ticks per quarter note, a tick is 50 samples.
+        let mut data = Vec::new();
+        recording
+            .to_midi_file_writer(441, 120.0)
+            .write(&mut data)
+            .unwrap();
+        let midi_file = MidiFile::new(&mut &data[..]).unwrap();
+        assert_eq!(midi_file.get_track_count(), 2);
+        let events: Vec<(u64, u8, u8)> = midi_file
+            .messages
+            .iter()
+            .zip(midi_file.ticks())
+            .filter_map(|(message, tick)| match *message {
+                Message::Normal { status, data1, .. } => Some((*tick, status, data1)),
+                Message::SysEx { .. } => Some((*tick, 0xF0, 0)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [(0, 0xC1, 5), (1, 0x91, 64), (2, 0xF0, 0), (4, 0x81, 64)]
+        );
+        assert_eq!(midi_file.get_length_ticks(), 5);
+    }
+
+    #[test]
+    fn test_master_lowpass() {
+        let data = crate::soundfont::tests::build_soundfont(&[], &[]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        settings.test_tone_mode = true;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        // A tone of about 4.2 kHz.
+        synthesizer.note_on(0, 108, 100);
+
+        let mut left = vec![0_f32; 4410];
+        let mut right = vec![0_f32; 4410];
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+
+        synthesizer.render(&mut left, &mut right);
+        let open_peak = peak(&left);
+        assert!(open_peak > 0_f32);
+
+        synthesizer.set_master_lowpass(Some(200.0));
+        assert_eq!(synthesizer.get_master_lowpass(), Some(200.0));
+        for _ in 0..10 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert!(peak(&left) < 0.01 * open_peak);
+
+        synthesizer.set_master_lowpass(None);
+        for _ in 0..10 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert!((peak(&left) - open_peak).abs() < 0.01 * open_peak);
+    }
+
+    #[test]
+    fn test_default_modulators_at_rest() {
+        let mut synthesizer = test_synthesizer();
+
+        // A tone of about 8.4 kHz, with the controllers of the default modulators at rest.
+        synthesizer.note_on(0, 120, 100);
+        assert!(!synthesizer.voices.get_active_voices_ref()[0].is_modulated());
+
+        let mut left = vec![0_f32; 4096];
+        let mut right = vec![0_f32; 4096];
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+
+        synthesizer.render(&mut left, &mut right);
+        let open_peak = peak(&left);
+        assert!(open_peak > 0_f32);
+
+        // Closing the brightness still lowers the cutoff of the voice.
+        synthesizer.process_midi_message(0, 0xB0, 74, 0);
+        for _ in 0..4 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert!(synthesizer.voices.get_active_voices_ref()[0].is_modulated());
+        assert!(peak(&left) < 0.5 * open_peak);
+    }
+
+    #[test]
+    fn test_channel_preset_name() {
+        let data = crate::soundfont::tests::build_soundfont(&[], &[]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        assert_eq!(synthesizer.get_channel_preset_name(0), Some("Preset"));
+        assert_eq!(synthesizer.get_channel_preset_name(16), None);
+
+        // The missing program and drum kit fall back to the only preset.
+        synthesizer.process_midi_message(0, 0xC0, 5, 0);
+        assert_eq!(synthesizer.get_channel_preset_name(0), Some("Preset"));
+        synthesizer.note_on(9, 36, 100);
+        assert_eq!(synthesizer.get_channel_preset_name(9), Some("Preset"));
+    }
+
+    #[test]
+    fn test_note_off_all_channel_with_release() {
+        // A looped sample with a release of about 100 seconds.
+        let data = crate::soundfont::tests::build_soundfont(&[], &[(54, 1), (38, 8000)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        let mut left = vec![0_f32; 4410];
+        let mut right = vec![0_f32; 4410];
+
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(0, 64, 100);
+        synthesizer.note_on(1, 67, 100);
+        synthesizer.render(&mut left, &mut right);
+
+        synthesizer.note_off_all_channel_with_release(0, 0.001);
+        for _ in 0..10 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert_eq!(synthesizer.get_active_voice_count(), 1);
+    }
+
+    #[test]
+    fn test_state_fingerprint() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+
+        let render = |key: i32| {
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            synthesizer.process_midi_message(0, 0xB0, 0x07, 90);
+            synthesizer.note_on(0, key, 100);
+            let mut left = vec![0_f32; 1000];
+            let mut right = vec![0_f32; 1000];
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.state_fingerprint()
+        };
+
+        assert_eq!(render(60), render(60));
+        assert_ne!(render(60), render(61));
+    }
+
+    #[test]
+    fn test_render_with() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        synthesizer.render(&mut left, &mut right);
+
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        let mut interleaved = vec![0_f32; 2000];
+        synthesizer.render_with(1000, |t, left, right| {
+            interleaved[2 * t] = left;
+            interleaved[2 * t + 1] = right;
+        });
+
+        for t in 0..1000 {
+            assert_eq!(interleaved[2 * t], left[t]);
+            assert_eq!(interleaved[2 * t + 1], right[t]);
+        }
+    }
+
+    #[test]
+    fn test_arpeggiator() {
+        let mut synthesizer = test_synthesizer();
+
+        let mut config = ArpConfig::new(ArpMode::Up);
+        config.octaves = 2;
+        synthesizer.set_arpeggiator(0, Some(config));
+        assert_eq!(synthesizer.get_arpeggiator(0), Some(config));
+        assert_eq!(synthesizer.get_arpeggiator(1), None);
+
+        // The sixteenth notes at 120 BPM are 5512.5 samples long.
+        synthesizer.note_on(0, 64, 100);
+        synthesizer.note_on(0, 60, 100);
+        assert_eq!(synthesizer.get_active_voice_count(), 0);
+
+        let mut left = vec![0_f32; 5513];
+        let mut right = vec![0_f32; 5513];
+        let playing = |synthesizer: &Synthesizer| {
+            (0..128)
+                .filter(|&key| synthesizer.is_note_on(0, key))
+                .collect::<Vec<i32>>()
+        };
+
+        let mut keys: Vec<i32> = Vec::new();
+        synthesizer.render(&mut left[..1], &mut right[..1]);
+        keys.extend(playing(&synthesizer));
+        for _ in 0..4 {
+            synthesizer.render(&mut left, &mut right);
+            keys.extend(playing(&synthesizer));
+        }
+        assert_eq!(keys, vec![60, 64, 72, 76, 60]);
+
+        // Releasing all the held notes stops the sequence at once.
+        synthesizer.note_off(0, 60);
+        synthesizer.note_off(0, 64);
+        assert!(playing(&synthesizer).is_empty());
+        synthesizer.render(&mut left, &mut right);
+        assert!(playing(&synthesizer).is_empty());
+
+        // The steps follow the tempo.
+        synthesizer.set_tempo(240.0);
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(0, 62, 100);
+        let block_size = synthesizer.get_block_size();
+        synthesizer.render_block(&mut left[..block_size], &mut right[..block_size]);
+        assert_eq!(playing(&synthesizer), vec![60]);
+        synthesizer.render(&mut left[..2757], &mut right[..2757]);
+        assert_eq!(playing(&synthesizer), vec![62]);
+
+        synthesizer.set_arpeggiator(0, None);
+        assert!(playing(&synthesizer).is_empty());
+        synthesizer.note_on(0, 67, 100);
+        assert_eq!(playing(&synthesizer), vec![67]);
+
+        // Every step within a block is played, here four steps of 16 samples.
+        let mut synthesizer = test_synthesizer();
+        let mut config = ArpConfig::new(ArpMode::Up);
+        config.rate = 22050.0 / 16.0;
+        synthesizer.set_arpeggiator(0, Some(config));
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(0, 64, 100);
+        synthesizer.render_block(&mut left[..block_size], &mut right[..block_size]);
+        assert_eq!(synthesizer.get_active_voice_count(), 4);
+        assert_eq!(playing(&synthesizer), vec![64]);
+
+        // The octave range is limited to 8 octaves.
+        config.octaves = 100;
+        synthesizer.set_arpeggiator(0, Some(config));
+        assert_eq!(synthesizer.get_arpeggiator(0).unwrap().octaves, 8);
+    }
+
+    #[test]
+    fn test_debug_solo_region() {
+        let mut synthesizer = test_synthesizer();
+        synthesizer.note_on(0, 60, 100);
+
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+
+        // The only region plays the sample 0.
+        synthesizer.set_debug_solo_region(Some(0));
+        assert_eq!(synthesizer.get_debug_solo_region(), Some(0));
+        synthesizer.render(&mut left, &mut right);
+        assert!(peak(&left) > 0_f32);
+
+        synthesizer.set_debug_solo_region(Some(1));
+        synthesizer.reset();
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(peak(&left), 0_f32);
+        assert_eq!(synthesizer.get_active_voice_count(), 1);
+
+        synthesizer.set_debug_solo_region(None);
+        synthesizer.render(&mut left, &mut right);
+        assert!(peak(&left) > 0_f32);
+    }
+
+    #[test]
+    fn test_linked_stereo_sample() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        // The region plays only the left half, and the right half is started with it.
+        synthesizer.note_on(0, 60, 100);
+        assert_eq!(synthesizer.get_active_voice_count(), 2);
+
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        synthesizer.render(&mut left, &mut right);
+
+        // Only the right half is audible, and it stays on the right.
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+        assert!(peak(&right) > 0.01);
+        assert!(peak(&left) < 1.0e-3 * peak(&right));
+
+        // The halves are linked as well with the compression flag of SoundFont3.
+        let mut data = data;
+        let offset = data.windows(4).position(|name| name == b"Left").unwrap();
+        data[offset + 44] |= 0x10;
+        data[offset + 46 + 44] |= 0x10;
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        assert_eq!(synthesizer.get_active_voice_count(), 2);
+
+        // An instrument with a region for each half places them with its own pans, here at the center.
+        let mut builder = crate::soundfont_builder::SoundFontBuilder::new("Bank");
+        let silent = builder.add_sample("Left", &[0; 100], 44100, 60, Some((10, 90)));
+        let level = builder.add_sample("Right", &[10000; 100], 44100, 60, Some((10, 90)));
+        builder.link_samples(silent, level);
+        let instrument = builder.add_instrument("Instrument");
+        builder.add_instrument_zone(instrument, silent, &[(54, 1)]);
+        builder.add_instrument_zone(instrument, level, &[(54, 1)]);
+        let preset = builder.add_preset("Preset", 0, 0);
+        builder.add_preset_zone(preset, instrument, &[]);
+        let sound_font = Arc::new(builder.build().unwrap());
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        assert_eq!(synthesizer.get_active_voice_count(), 2);
+        synthesizer.render(&mut left, &mut right);
+        assert!(peak(&right) > 0.01);
+        assert!((peak(&left) - peak(&right)).abs() < 1.0e-3 * peak(&right));
+    }
+
+    #[test]
+    fn test_pan_center() {
+        let sound_font = test_sound_font();
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+
+        let mut gains = |curve: PanCurve, pan: i32| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.pan_curve = curve;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            synthesizer.process_midi_message(0, 0xB0, 10, pan);
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.render(&mut left, &mut right);
+            let voice = &synthesizer.voices.get_active_voices_ref()[0];
+            (voice.current_mix_gain_left, voice.current_mix_gain_right)
+        };
+
+        let (gain_left, gain_right) = gains(PanCurve::Gm, 64);
+        assert!(gain_left > 0_f32);
+        assert!((gain_left - gain_right).abs() < 1.0e-6 * gain_left);
+
+        assert_eq!(gains(PanCurve::Gm, 0).1, 0_f32);
+        assert_eq!(gains(PanCurve::Gm, 127).0, 0_f32);
+
+        let (gain_left, gain_right) = gains(PanCurve::Linear, 64);
+        assert!(gain_right > gain_left);
+    }
+
+    #[test]
+    fn test_render_split() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        let mut left = vec![0_f32; 300];
+        let mut right = vec![0_f32; 300];
+        synthesizer.render(&mut left, &mut right);
+
+        // The wrap falls in the middle of a block.
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.note_on(0, 60, 100);
+        let mut split_left = vec![0_f32; 300];
+        let mut split_right = vec![0_f32; 300];
+        let (left_head, left_tail) = split_left.split_at_mut(100);
+        let (right_head, right_tail) = split_right.split_at_mut(100);
+        synthesizer.render_split((left_tail, left_head), (right_tail, right_head));
+
+        assert_eq!(&split_left[100..], &left[..200]);
+        assert_eq!(&split_left[..100], &left[200..]);
+        assert_eq!(&split_right[100..], &right[..200]);
+        assert_eq!(&split_right[..100], &right[200..]);
+    }
+
+    #[test]
+    fn test_channel_gain() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        settings.enable_reverb_and_chorus = false;
+
+        let render = |gain: f32| {
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            synthesizer.set_channel_gain(0, gain);
+            synthesizer.reset();
+            assert_eq!(synthesizer.get_channel_gain(0), gain);
+            synthesizer.process_midi_message(0, 0xB0, 7, 64);
+            synthesizer.note_on(0, 60, 100);
+            let mut left = vec![0_f32; 1000];
+            let mut right = vec![0_f32; 1000];
+            synthesizer.render(&mut left, &mut right);
+            left
+        };
+
+        // The fader multiplies the gain of the volume.
+        let full = render(1.0);
+        let half = render(0.5);
+        assert!(full.iter().any(|x| *x != 0_f32));
+        for (x, y) in full.iter().zip(half.iter()) {
+            assert!((0.5 * x - y).abs() < 1.0e-6);
+        }
+        assert!(render(0.0).iter().all(|x| *x == 0_f32));
+    }
+
+    #[test]
+    fn test_trigger_oneshot() {
+        let mut synthesizer = test_synthesizer();
+
+        // Two overlapping one-shots of the same key, of 100 ms and 300 ms.
+        synthesizer.trigger_oneshot(0, 60, 100, 100.0);
+        let mut left = vec![0_f32; 2205];
+        let mut right = vec![0_f32; 2205];
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.trigger_oneshot(0, 60, 100, 300.0);
+
+        let playing_count = |synthesizer: &Synthesizer| {
+            synthesizer
+                .voices
+                .get_active_voices_ref()
+                .iter()
+                .filter(|voice| voice.is_playing())
+                .count()
+        };
+        assert_eq!(playing_count(&synthesizer), 2);
+
+        // At 150 ms, only the first one has been released.
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(playing_count(&synthesizer), 1);
+        assert!(synthesizer.is_note_on(0, 60));
+
+        // At 400 ms, the second one has been released too.
+        for _ in 0..5 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert!(!synthesizer.is_note_on(0, 60));
+    }
+
+    #[test]
+    fn test_trigger_oneshot_in_mono_mode() {
+        let mut synthesizer = test_synthesizer();
+        synthesizer.process_midi_message(0, 0xB0, 0x7E, 1);
+        let mut left = vec![0_f32; 2205];
+        let mut right = vec![0_f32; 2205];
+
+        // The one-shot cuts the held key as a note-on does.
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.trigger_oneshot(0, 64, 100, 100.0);
+        synthesizer.render(&mut left, &mut right);
+        assert!(!synthesizer.is_note_on(0, 60));
+        assert!(synthesizer.is_note_on(0, 64));
+
+        // Releasing the held key does not play the one-shot again once it has ended.
+        for _ in 0..4 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert!(!synthesizer.is_note_on(0, 64));
+        synthesizer.note_off(0, 60);
+        assert!(!synthesizer.is_note_on(0, 64));
+    }
+
+    #[test]
+    fn test_reset_phase_on_note_on() {
+        let sound_font = test_sound_font();
+        let render = |reset_phase: bool, retrigger: bool| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.enable_reverb_and_chorus = false;
+            settings.test_tone_mode = true;
+            settings.retrigger_mode = RetriggerMode::Steal;
+            settings.reset_phase_on_note_on = reset_phase;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            let mut left = vec![0_f32; 1024];
+            let mut right = vec![0_f32; 1024];
+            if retrigger {
+                synthesizer.note_on(0, 60, 100);
+                synthesizer.render(&mut left, &mut right);
+            }
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.render(&mut left, &mut right);
+            left
+        };
+
+        // By default, the stolen note starts over as a note struck for the first time.
+        let fresh = render(true, false);
+        assert_eq!(render(true, true), fresh);
+
+        // Otherwise, the tone goes on from where the stolen voice was, so only the attack differs.
+        let continued = render(false, true);
+        assert!(continued
+            .iter()
+            .zip(&fresh)
+            .any(|(a, b)| (a - b).abs() > 0.01));
+        assert_eq!(render(false, false), fresh);
+    }
+
+    #[test]
+    fn test_process_midi_message_at() {
+        let mut synthesizer = test_synthesizer();
+
+        // The note starts at the 100th sample, in the middle of the second block of 64.
+        synthesizer.process_midi_message_at(0, 0x90, 60, 100, 100);
+        let mut left = vec![0_f32; 200];
+        let mut right = vec![0_f32; 200];
+        synthesizer.render(&mut left, &mut right);
+        assert!(left[..100].iter().all(|&x| x == 0_f32));
+        assert!(left[100..110].iter().any(|&x| x != 0_f32));
+
+        // The offset counts from the next sample to be rendered, so from the middle of a block.
+        synthesizer.reset();
+        let mut left = vec![0_f32; 10];
+        let mut right = vec![0_f32; 10];
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.process_midi_message_at(0, 0x90, 60, 100, 90);
+        let mut left = vec![0_f32; 200];
+        let mut right = vec![0_f32; 200];
+        synthesizer.render(&mut left, &mut right);
+        assert!(left[..90].iter().all(|&x| x == 0_f32));
+        assert!(left[90..100].iter().any(|&x| x != 0_f32));
+
+        // The scheduled messages are discarded by reset.
+        synthesizer.reset();
+        synthesizer.process_midi_message_at(0, 0x90, 60, 100, 10);
+        synthesizer.reset();
+        synthesizer.render(&mut left, &mut right);
+        assert!(!synthesizer.is_note_on(0, 60));
+    }
+
+    #[test]
+    fn test_soundfont_modulators() {
+        // The CC 2 attenuates the note up to 96 dB.
+        let modulators = [[0x0082, 48, 960, 0, 0]];
+        let data =
+            crate::soundfont::tests::build_soundfont_with_modulators(&[(54, 1)], &modulators);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        settings.test_tone_mode = true;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        let peak = |synthesizer: &mut Synthesizer| {
+            let mut left = vec![0_f32; 4096];
+            let mut right = vec![0_f32; 4096];
+            synthesizer.render(&mut left, &mut right);
+            left[2048..].iter().fold(0_f32, |peak, x| peak.max(x.abs()))
+        };
+
+        synthesizer.note_on(0, 60, 100);
+        let loud = peak(&mut synthesizer);
+        assert!(loud > 0_f32);
+
+        // The controller is applied to the playing note.
+        synthesizer.process_midi_message(0, 0xB0, 0x02, 64);
+        let quiet = peak(&mut synthesizer);
+        assert!(quiet < 0.5_f32 * loud);
+
+        synthesizer.process_midi_message(0, 0xB0, 0x02, 0);
+        let restored = peak(&mut synthesizer);
+        assert!((restored - loud).abs() < 0.05_f32 * loud);
+    }
+
+    #[test]
+    fn test_rpn_and_nrpn() {
+        let mut synthesizer = test_synthesizer();
+
+        // RPN 0 sets the pitch bend range, and the data increment moves it by a semitone.
+        synthesizer.process_midi_message(0, 0xB0, 0x65, 0);
+        synthesizer.process_midi_message(0, 0xB0, 0x64, 0);
+        synthesizer.process_midi_message(0, 0xB0, 0x06, 12);
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 12_f64);
+        synthesizer.process_midi_message(0, 0xB0, 0x60, 0);
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 13_f64);
+
+        // The RPN null stops the data entry.
+        synthesizer.process_midi_message(0, 0xB0, 0x65, 0x7F);
+        synthesizer.process_midi_message(0, 0xB0, 0x64, 0x7F);
+        synthesizer.process_midi_message(0, 0xB0, 0x06, 2);
+        assert_eq!(synthesizer.get_pitch_bend_range(0), 13_f64);
+
+        let peak = |synthesizer: &mut Synthesizer| {
+            synthesizer.note_on(0, 120, 127);
+            let mut left = vec![0_f32; 4096];
+            let mut right = vec![0_f32; 4096];
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.note_off_all(true);
+            left[2048..].iter().fold(0_f32, |peak, x| peak.max(x.abs()))
+        };
+        let open = peak(&mut synthesizer);
+
+        // The NRPN of the filter cutoff lowers the cutoff of the next notes.
+        synthesizer.process_midi_message(0, 0xB0, 0x63, 0x01);
+        synthesizer.process_midi_message(0, 0xB0, 0x62, 0x20);
+        synthesizer.process_midi_message(0, 0xB0, 0x06, 0);
+        let closed = peak(&mut synthesizer);
+        assert!(closed < 0.5_f32 * open);
+
+        synthesizer.reset();
+        let reset = peak(&mut synthesizer);
+        assert!((reset - open).abs() < 0.05_f32 * open);
+    }
+
+    #[test]
+    fn test_sysex() {
+        let mut synthesizer = test_synthesizer();
+        let default_volume = synthesizer.get_master_volume();
+
+        // GS master volume, with the checksum.
+        synthesizer.process_sysex(&[
+            0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x04, 0x40, 0x7C, 0xF7,
+        ]);
+        assert!(
+            (synthesizer.get_master_volume() - default_volume * 64_f32 / 127_f32).abs() < 1.0e-6
+        );
+
+        // GS "Use for Rhythm Part" on the part 2, which is the channel 1.
+        synthesizer.process_sysex(&[
+            0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x12, 0x15, 0x01, 0x18, 0xF7,
+        ]);
+        assert!(synthesizer.channels[1].is_percussion_channel);
+
+        // GM2 system on restores the master volume and the percussion channels.
+        synthesizer.process_sysex(&[0xF0, 0x7E, 0x7F, 0x09, 0x03, 0xF7]);
+        assert_eq!(synthesizer.get_master_volume(), default_volume);
+        assert!(!synthesizer.channels[1].is_percussion_channel);
+        assert!(synthesizer.channels[9].is_percussion_channel);
+    }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        synthesizer.process_midi_message(0, 0xC0, 5, 0);
+        synthesizer.process_midi_message(0, 0xB0, 7, 90);
+        synthesizer.process_midi_message(0, 0xE0, 0x00, 0x50);
+        synthesizer.note_on(0, 60, 100);
+        let state = synthesizer.save_state(false);
+        assert!(!state.has_voices());
+        assert_eq!(state.get_channel_count(), Synthesizer::CHANNEL_COUNT);
+
+        synthesizer.reset();
+        synthesizer.restore_state(&state);
+        assert_eq!(synthesizer.channels[0].save_state(), state.channels[0]);
+        assert_eq!(synthesizer.get_active_voice_count(), 0);
+
+        // With the voices, the rendering continues from the same point.
+        synthesizer.note_on(0, 64, 100);
+        let state = synthesizer.save_state(true);
+        let mut left = vec![0_f32; 256];
+        let mut right = vec![0_f32; 256];
+        synthesizer.render(&mut left, &mut right);
+        assert!(right.iter().any(|value| *value != 0_f32));
+
+        synthesizer.note_off_all(true);
+        synthesizer.restore_state(&state);
+        let mut restored_left = vec![0_f32; 256];
+        let mut restored_right = vec![0_f32; 256];
+        synthesizer.render(&mut restored_left, &mut restored_right);
+        assert_eq!(left, restored_left);
+        assert_eq!(right, restored_right);
+    }
+
+    #[test]
+    fn test_mts_tuning() {
+        let mut synthesizer = test_synthesizer();
+        assert_eq!(synthesizer.get_key_pitch(0, 60), Some(60.0));
+
+        // The key 60 of the program 0 a quarter tone up, which the channels select by default.
+        synthesizer.process_sysex(&[
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 0x3C, 0x3C, 0x40, 0x00, 0xF7,
+        ]);
+        assert_eq!(synthesizer.get_key_pitch(0, 60), Some(60.5));
+        assert_eq!(synthesizer.get_key_pitch(0, 61), Some(61.0));
+
+        // The channel 1 selects the program 1 by RPN 3, which is not sent.
+        synthesizer.process_midi_message(1, 0xB0, 0x65, 0x00);
+        synthesizer.process_midi_message(1, 0xB0, 0x64, 0x03);
+        synthesizer.process_midi_message(1, 0xB0, 0x06, 0x01);
+        assert_eq!(synthesizer.get_key_pitch(1, 60), Some(60.0));
+
+        // The scale/octave tuning of the channel 1, with C 50 cents up.
+        let mut message = vec![0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x00, 0x00, 0x02, 0x72];
+        message.extend_from_slice(&[0x40; 11]);
+        message.push(0xF7);
+        synthesizer.process_sysex(&message);
+        assert!((synthesizer.get_key_pitch(1, 72).unwrap() - 72.5).abs() < 1.0E-6);
+        assert_eq!(synthesizer.get_key_pitch(0, 72), Some(72.0));
+
+        // The tuning set by the host takes precedence.
+        let mut tuning = Tuning::equal_temperament();
+        tuning.set_pitch(60, Some(61.0));
+        synthesizer.set_tuning(0, &tuning);
+        assert_eq!(synthesizer.get_key_pitch(0, 60), Some(61.0));
+        synthesizer.clear_tuning(0);
+        assert_eq!(synthesizer.get_key_pitch(0, 60), Some(60.5));
+
+        synthesizer.reset_tuning();
+        assert_eq!(synthesizer.get_key_pitch(0, 60), Some(60.0));
+        assert_eq!(synthesizer.get_key_pitch(1, 72), Some(72.0));
+    }
+
+    #[test]
+    fn test_channel_drum_mode() {
+        let mut synthesizer = test_synthesizer();
+        assert!(synthesizer.is_channel_drum_mode(9));
+        assert!(!synthesizer.is_channel_drum_mode(10));
+
+        // The bank select is kept on top of the drum mode.
+        synthesizer.process_midi_message(10, 0xB0, 0x00, 1);
+        synthesizer.set_channel_drum_mode(10, true);
+        assert!(synthesizer.is_channel_drum_mode(10));
+        assert_eq!(synthesizer.channels[10].get_bank_number(), 129);
+
+        // The mode is kept across reset.
+        synthesizer.reset();
+        assert!(synthesizer.is_channel_drum_mode(10));
+        assert_eq!(synthesizer.channels[10].get_bank_number(), 128);
+
+        synthesizer.set_channel_drum_mode(10, false);
+        assert_eq!(synthesizer.channels[10].get_bank_number(), 0);
+        assert!(!synthesizer.is_channel_drum_mode(16));
+    }
+
+    #[test]
+    fn test_render_interleaved() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.set_dither_mode(DitherMode::Triangular);
+        synthesizer.note_on(0, 60, 100);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        synthesizer.render(&mut left, &mut right);
+        let mut left_i16 = vec![0_i16; 1000];
+        let mut right_i16 = vec![0_i16; 1000];
+        synthesizer.render_i16(&mut left_i16, &mut right_i16);
+
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.set_dither_mode(DitherMode::Triangular);
+        synthesizer.note_on(0, 60, 100);
+        // The lengths do not have to be multiples of the block size.
+        let mut interleaved = vec![0_f32; 2000];
+        synthesizer.render_interleaved(&mut interleaved[..10]);
+        synthesizer.render_interleaved(&mut interleaved[10..]);
+        let mut interleaved_i16 = vec![0_i16; 2000];
+        synthesizer.render_interleaved_i16(&mut interleaved_i16);
+
+        for t in 0..1000 {
+            assert_eq!(interleaved[2 * t], left[t]);
+            assert_eq!(interleaved[2 * t + 1], right[t]);
+            assert_eq!(interleaved_i16[2 * t], left_i16[t]);
+            assert_eq!(interleaved_i16[2 * t + 1], right_i16[t]);
+        }
+    }
+
+    #[test]
+    fn test_mpe_zone() {
+        let mut synthesizer = test_synthesizer();
+
+        // The MPE configuration message on the channel 1 sets up the lower zone.
+        synthesizer.process_midi_message(0, 0xB0, 0x65, 0);
+        synthesizer.process_midi_message(0, 0xB0, 0x64, 6);
+        synthesizer.process_midi_message(0, 0xB0, 0x06, 4);
+        assert_eq!(synthesizer.get_mpe_zone_member_count(MpeZone::Lower), 4);
+        assert_eq!(synthesizer.channels[1].get_pitch_bend_range(), 48_f32);
+        assert_eq!(synthesizer.channels[4].get_pitch_bend_range(), 48_f32);
+        assert_eq!(synthesizer.channels[5].get_pitch_bend_range(), 2_f32);
+        assert_eq!(synthesizer.channels[0].get_pitch_bend_range(), 2_f32);
+
+        // The upper zone shrinks the lower zone when they overlap.
+        synthesizer.configure_mpe_zone(MpeZone::Upper, 12);
+        assert_eq!(synthesizer.get_mpe_zone_member_count(MpeZone::Lower), 2);
+        assert_eq!(synthesizer.get_mpe_zone_member_count(MpeZone::Upper), 12);
+        synthesizer.configure_mpe_zone(MpeZone::Upper, 0);
+        assert_eq!(synthesizer.channels[14].get_pitch_bend_range(), 2_f32);
+
+        // The controllers on the master channel apply to the members, but not the RPNs.
+        synthesizer.process_midi_message(0, 0xB0, 0x07, 50);
+        synthesizer.process_midi_message(0, 0xC0, 5, 0);
+        assert_eq!(synthesizer.channels[2].get_controller(7), 50);
+        assert_eq!(synthesizer.channels[2].get_patch_number(), 5);
+        assert_eq!(synthesizer.channels[3].get_controller(7), 100);
+        assert_eq!(synthesizer.channels[2].get_rpn(), -1);
+
+        // The configuration is kept across reset.
+        synthesizer.reset();
+        assert_eq!(synthesizer.get_mpe_zone_member_count(MpeZone::Lower), 2);
+        assert_eq!(synthesizer.channels[1].get_pitch_bend_range(), 48_f32);
+
+        // The channel pressure raises the volume of the note on a member channel.
+        let mut peak = |pressure: i32| {
+            synthesizer.reset();
+            synthesizer.note_on(1, 60, 100);
+            synthesizer.process_midi_message(1, 0xD0, pressure, 0);
+            let mut left = vec![0_f32; 4410];
+            let mut right = vec![0_f32; 4410];
+            synthesizer.render(&mut left, &mut right);
+            left[2205..].iter().fold(0_f32, |a, &x| a.max(x.abs()))
+        };
+        let ratio = peak(127) / peak(0);
+        assert!((ratio - 2_f32).abs() < 0.05, "{}", ratio);
+    }
+
+    #[test]
+    fn test_portamento_and_mono_mode() {
+        let mut synthesizer = test_synthesizer();
+        let mut left = vec![0_f32; 4410];
+        let mut right = vec![0_f32; 4410];
+        let mut crossings = |synthesizer: &mut Synthesizer| {
+            synthesizer.render(&mut left, &mut right);
+            left.windows(2)
+                .filter(|x| (x[0] < 0_f32) != (x[1] < 0_f32))
+                .count()
+        };
+
+        // Without the portamento, the note starts at its own pitch, about 52 crossings per 0.1 s for C4.
+        synthesizer.note_on(0, 48, 100);
+        synthesizer.note_off(0, 48);
+        synthesizer.note_off_all(true);
+        synthesizer.note_on(0, 60, 100);
+        let direct = crossings(&mut synthesizer);
+        assert!((50..=54).contains(&direct), "{}", direct);
+
+        // With the longest portamento, the note starts near the pitch of the previous note.
+        synthesizer.note_off_all(true);
+        synthesizer.process_midi_message(0, 0xB0, 0x41, 127);
+        synthesizer.process_midi_message(0, 0xB0, 0x05, 127);
+        synthesizer.note_on(0, 48, 100);
+        synthesizer.note_off_all(true);
+        synthesizer.note_on(0, 60, 100);
+        let glide = crossings(&mut synthesizer);
+        assert!((24..=30).contains(&glide), "{}", glide);
+
+        // The portamento control glides from its key once, even with the portamento off.
+        synthesizer.note_off_all(true);
+        synthesizer.process_midi_message(0, 0xB0, 0x41, 0);
+        synthesizer.process_midi_message(0, 0xB0, 0x54, 48);
+        synthesizer.note_on(0, 60, 100);
+        assert!((24..=30).contains(&crossings(&mut synthesizer)));
+        synthesizer.note_off_all(true);
+        synthesizer.note_on(0, 60, 100);
+        assert!((50..=54).contains(&crossings(&mut synthesizer)));
+        synthesizer.note_off_all(true);
+
+        // In the mono mode, a note replaces the sounding one, which returns on the release.
+        let sounding = |synthesizer: &mut Synthesizer| {
+            synthesizer
+                .voices
+                .get_active_voices()
+                .iter()
+                .filter(|voice| !voice.is_releasing())
+                .map(|voice| voice.key())
+                .collect::<Vec<_>>()
+        };
+        synthesizer.process_midi_message(0, 0xB0, 0x7E, 1);
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(0, 64, 100);
+        crossings(&mut synthesizer);
+        assert_eq!(
+            synthesizer
+                .voices
+                .get_active_voices()
+                .iter()
+                .filter(|voice| voice.key() == 60)
+                .count(),
+            0
+        );
+        synthesizer.note_off(0, 64);
+        assert_eq!(sounding(&mut synthesizer), vec![60]);
+        synthesizer.note_off(0, 60);
+        assert!(sounding(&mut synthesizer).is_empty());
+
+        // The poly mode plays the notes together again.
+        synthesizer.process_midi_message(0, 0xB0, 0x7F, 0);
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(0, 64, 100);
+        assert_eq!(sounding(&mut synthesizer).len(), 2);
+    }
+
+    #[test]
+    fn test_sostenuto_and_soft_pedal() {
+        let mut synthesizer = test_synthesizer();
+        let mut left = vec![0_f32; 4410];
+        let mut right = vec![0_f32; 4410];
+        // The released notes end in a few milliseconds with the default release.
+        let mut sounding = |synthesizer: &mut Synthesizer| {
+            synthesizer.render(&mut left, &mut right);
+            let mut keys = synthesizer
+                .voices
+                .get_active_voices()
+                .iter()
+                .map(|voice| voice.key())
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        // Only the note held down when the sostenuto is pressed is sustained.
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.process_midi_message(0, 0xB0, 0x42, 127);
+        synthesizer.note_on(0, 64, 100);
+        synthesizer.process_midi_message(0, 0xB0, 0x42, 127);
+        synthesizer.note_off(0, 60);
+        synthesizer.note_off(0, 64);
+        assert_eq!(sounding(&mut synthesizer), vec![60]);
+
+        // A note played after the pedal is pressed is not held, even if it is pressed again.
+        synthesizer.note_on(0, 67, 100);
+        synthesizer.note_off(0, 67);
+        assert_eq!(sounding(&mut synthesizer), vec![60]);
+
+        synthesizer.process_midi_message(0, 0xB0, 0x42, 0);
+        assert!(sounding(&mut synthesizer).is_empty());
+
+        // The soft pedal lowers the velocity of the following notes.
+        synthesizer.process_midi_message(0, 0xB0, 0x43, 127);
+        synthesizer.note_on(0, 60, 100);
+        let voices = synthesizer.voices.get_active_voices();
+        assert_eq!(voices.last().unwrap().velocity(), 75);
+    }
+
+    #[test]
+    fn test_voice_stealing() {
+        let sound_font = test_sound_font();
+        let play = |policy: VoiceStealingPolicy, keys: &[i32]| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.maximum_polyphony = 8;
+            settings.voice_stealing_policy = policy;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            let mut left = vec![0_f32; 64];
+            let mut right = vec![0_f32; 64];
+            for &key in keys {
+                synthesizer.note_on(0, key, 100);
+                synthesizer.render(&mut left, &mut right);
+            }
+            let mut keys = synthesizer
+                .voices
+                .get_active_voices_ref()
+                .iter()
+                .map(|voice| voice.key())
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        let keys = [60, 61, 62, 63, 64, 65, 66, 67];
+        assert_eq!(
+            play(VoiceStealingPolicy::Oldest, &[&keys[..], &[70]].concat()),
+            vec![61, 62, 63, 64, 65, 66, 67, 70]
+        );
+        assert_eq!(
+            play(
+                VoiceStealingPolicy::SameNoteFirst,
+                &[&keys[..], &[63]].concat()
+            ),
+            keys.to_vec()
+        );
+        assert_eq!(
+            play(VoiceStealingPolicy::None, &[&keys[..], &[70]].concat()),
+            keys.to_vec()
+        );
+
+        // The channel at its limit reuses its own voices, and leaves the others alone.
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.maximum_polyphony = 8;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.set_channel_voice_limit(9, Some(2));
+        synthesizer.note_on(0, 60, 100);
+        for key in 35..45 {
+            synthesizer.note_on(9, key, 100);
+        }
+        let voices = synthesizer.voices.get_active_voices_ref();
+        assert_eq!(
+            voices.iter().filter(|voice| voice.channel() == 9).count(),
+            2
+        );
+        assert_eq!(
+            voices.iter().filter(|voice| voice.channel() == 0).count(),
+            1
+        );
+
+        synthesizer.set_channel_voice_limit(9, Some(0));
+        synthesizer.note_off_all(true);
+        synthesizer.note_on(9, 36, 100);
+        assert_eq!(synthesizer.get_active_voice_count(), 0);
+    }
+
+    #[test]
+    fn test_render_threads() {
+        let sound_font = test_sound_font();
+        let render = |thread_count: usize| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.render_thread_count = thread_count;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            for key in 30..70 {
+                synthesizer.note_on(key % 16, key, 100);
+            }
+            let mut left = vec![0_f32; 4410];
+            let mut right = vec![0_f32; 4410];
+            synthesizer.render(&mut left, &mut right);
+            // Some voices end in the middle.
+            for key in (30..70).step_by(3) {
+                synthesizer.note_off(key % 16, key);
+            }
+            synthesizer.render(&mut left, &mut right);
+            (left, right, synthesizer.get_active_voice_count())
+        };
+
+        // The output of the threads is identical to the single-threaded one.
+        let (left, right, count) = render(1);
+        assert!(count < 40);
+        assert_eq!(render(4), (left, right, count));
+
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.render_thread_count = 0;
+        assert!(matches!(
+            Synthesizer::new(&sound_font, &settings),
+            Err(SynthesizerError::RenderThreadCountOutOfRange(0))
+        ));
+    }
+
+    #[test]
+    fn test_render_multichannel_after_render() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let mut stereo = Synthesizer::new(&sound_font, &settings).unwrap();
+        let mut multichannel = Synthesizer::new(&sound_font, &settings).unwrap();
+        for synthesizer in [&mut stereo, &mut multichannel] {
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.note_on(1, 67, 100);
+        }
+
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+        stereo.render(&mut left, &mut right);
+
+        // The samples of the block left by `render` are not lost by the switch.
+        let mut output_left = vec![0_f32; 1000];
+        let mut output_right = vec![0_f32; 1000];
+        multichannel.render(&mut output_left[..10], &mut output_right[..10]);
+        multichannel.render_multichannel(
+            &mut [&mut output_left[10..], &mut output_right[10..]],
+            &ChannelRouting::stereo(),
+        );
+        assert!(right.iter().any(|&x| x != 0_f32));
+        for (x, y) in left.iter().zip(&output_left) {
+            assert!((x - y).abs() < 1e-6);
+        }
+        for (x, y) in right.iter().zip(&output_right) {
+            assert!((x - y).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_channel_effects() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let mut stereo = Synthesizer::new(&sound_font, &settings).unwrap();
+        let mut stems = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        let mut routing = ChannelRouting::new(4);
+        routing.add_channel_route(0, 0, 1_f32, 0_f32);
+        routing.add_channel_route(0, 1, 0_f32, 1_f32);
+        routing.add_channel_route(1, 2, 1_f32, 0_f32);
+        routing.add_channel_route(1, 3, 0_f32, 1_f32);
+        routing.set_channel_effects(true);
+
+        let mut left = vec![0_f32; 4410];
+        let mut right = vec![0_f32; 4410];
+        let mut outputs = vec![vec![0_f32; 4410]; 4];
+        for synthesizer in [&mut stereo, &mut stems] {
+            synthesizer.process_midi_message(0, 0xB0, 0x5B, 127);
+            synthesizer.note_on(0, 60, 100);
+        }
+        for _ in 0..3 {
+            stereo.render(&mut left, &mut right);
+            let mut slices: Vec<&mut [f32]> = outputs.iter_mut().map(|x| &mut x[..]).collect();
+            stems.render_multichannel(&mut slices, &routing);
+            stereo.note_off(0, 60);
+            stems.note_off(0, 60);
+        }
+
+        // The reverb tail of the channel stays in its stem, which matches the stereo mix.
+        assert!(left.iter().any(|&x| x.abs() > 1e-4));
+        for (x, y) in left.iter().zip(&outputs[0]) {
+            assert!((x - y).abs() < 1e-5);
+        }
+        for (x, y) in right.iter().zip(&outputs[1]) {
+            assert!((x - y).abs() < 1e-5);
+        }
+        assert!(outputs[2].iter().chain(&outputs[3]).all(|&x| x == 0_f32));
+
+        // The effects of a silent channel are skipped, and those of the channel once its tail has decayed.
+        assert_eq!(stems.channel_effect_peaks[1], 0_f32);
+        assert!(stems.channel_effect_peaks[0] > Synthesizer::SILENCE_THRESHOLD);
+        assert!(!stems.is_idle());
+        for _ in 0..100 {
+            let mut slices: Vec<&mut [f32]> = outputs.iter_mut().map(|x| &mut x[..]).collect();
+            stems.render_multichannel(&mut slices, &routing);
+            if stems.is_idle() {
+                break;
+            }
+        }
+        assert!(stems.is_idle());
+        assert!(stems.channel_effect_peaks[0] < Synthesizer::SILENCE_THRESHOLD);
+        let chorus = stems.channel_effects[0].chorus.clone();
+        let mut slices: Vec<&mut [f32]> = outputs.iter_mut().map(|x| &mut x[..]).collect();
+        stems.render_multichannel(&mut slices, &routing);
+        assert_eq!(
+            format!("{:?}", stems.channel_effects[0].chorus),
+            format!("{:?}", chorus)
+        );
+
+        // Once prepared, switching the effects of the channels does not allocate.
+        let mut global = routing.clone();
+        global.set_channel_effects(false);
+        stems.note_on(0, 60, 100);
+        let mut slices: Vec<&mut [f32]> = outputs.iter_mut().map(|x| &mut x[..]).collect();
+        let count = count_allocations(|| {
+            for routing in [&global, &routing, &global] {
+                stems.render_multichannel(&mut slices, routing);
+            }
+        });
+        assert_eq!(count, 0);
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestGain {
+        gain: f32,
+        reset_count: usize,
+    }
+
+    impl AudioEffect for TestGain {
+        fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+            left.iter_mut().for_each(|x| *x *= self.gain);
+            right.iter_mut().for_each(|x| *x *= self.gain);
+        }
+
+        fn reset(&mut self) {
+            self.reset_count += 1;
+        }
+    }
+
+    #[test]
+    fn test_custom_effects() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        settings.test_tone_mode = true;
+        let render = |effects: &[TestGain]| {
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            for effect in effects {
+                synthesizer.add_effect(Box::new(effect.clone()));
+            }
+            synthesizer.note_on(0, 60, 100);
+            let mut left = vec![0_f32; 1000];
+            let mut right = vec![0_f32; 1000];
+            synthesizer.render(&mut left, &mut right);
+            (synthesizer, left)
+        };
+
+        // The effects are chained, and a clone of the synthesizer keeps them.
+        let effect = TestGain {
+            gain: 0.5_f32,
+            reset_count: 0,
+        };
+        let (_, reference) = render(&[]);
+        let (mut synthesizer, left) = render(&[effect.clone(), effect]);
+        assert!(reference.iter().any(|&x| x != 0_f32));
+        for (x, y) in reference.iter().zip(&left) {
+            assert!((0.25_f32 * x - y).abs() < 1e-6);
+        }
+        assert_eq!(synthesizer.clone().get_effect_count(), 2);
+
+        synthesizer.reset();
+        assert_eq!(
+            format!("{:?}", synthesizer.custom_effects)
+                .matches("reset_count: 1")
+                .count(),
+            2
+        );
+        synthesizer.clear_effects();
+        assert_eq!(synthesizer.get_effect_count(), 0);
+    }
+
+    #[test]
+    fn test_effect_parameters() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let render = |update: &dyn Fn(&mut Synthesizer)| {
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            update(&mut synthesizer);
+            synthesizer.process_midi_message(0, 0xB0, 0x5B, 127);
+            synthesizer.note_on(0, 60, 100);
+            let mut left = vec![0_f32; 4410];
+            let mut right = vec![0_f32; 4410];
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.note_off(0, 60);
+            // The energy of the reverb tail after the note.
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.render(&mut left, &mut right);
+            left.iter().map(|x| x * x).sum::<f32>()
+        };
+
+        let reference = render(&|_| ());
+        assert!(reference > 0_f32);
+        assert!(render(&|synthesizer| synthesizer.set_reverb_room_size(1_f32)) > reference);
+        assert!(render(&|synthesizer| synthesizer.set_reverb_room_size(0_f32)) < reference);
+        assert_eq!(
+            render(&|synthesizer| synthesizer.set_reverb_wet_level(0_f32)),
+            0_f32
+        );
+
+        // The defaults read back, and the values are clamped.
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        let initial = synthesizer.state_fingerprint();
+        assert!((synthesizer.get_reverb_room_size().unwrap() - 0.5_f32).abs() < 1e-6);
+        assert!((synthesizer.get_reverb_damping().unwrap() - 0.5_f32).abs() < 1e-6);
+        assert_eq!(synthesizer.get_reverb_width(), Some(1_f32));
+        assert!((synthesizer.get_reverb_wet_level().unwrap() - 1_f32).abs() < 1e-6);
+        assert_eq!(synthesizer.get_chorus_rate(), Some(0.4));
+        assert!((synthesizer.get_chorus_depth().unwrap() - 0.95).abs() < 1e-9);
+        assert_eq!(synthesizer.get_chorus_feedback(), Some(0_f32));
+        synthesizer.set_chorus_rate(100.0);
+        synthesizer.set_chorus_feedback(2_f32);
+        assert_eq!(synthesizer.get_chorus_rate(), Some(10.0));
+        assert_eq!(synthesizer.get_chorus_feedback(), Some(0.95_f32));
+        assert_ne!(synthesizer.state_fingerprint(), initial);
+
+        // A rate out of the range does not panic, and the lowest rate does not allocate.
+        let count = count_allocations(|| {
+            synthesizer.set_chorus_rate(0.0);
+            synthesizer.set_chorus_rate(f64::NAN);
+        });
+        assert_eq!(count, 0);
+        assert_eq!(synthesizer.get_chorus_rate(), Some(0.1));
+
+        settings.enable_reverb_and_chorus = false;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.set_reverb_room_size(1_f32);
+        assert_eq!(synthesizer.get_reverb_room_size(), None);
+    }
+
+    #[test]
+    fn test_output_stage() {
+        let sound_font = test_sound_font();
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.enable_reverb_and_chorus = false;
+        settings.test_tone_mode = true;
+        let render = |update: &dyn Fn(&mut Synthesizer)| {
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            update(&mut synthesizer);
+            for key in 60..72 {
+                synthesizer.note_on(0, key, 127);
+            }
+            let mut left = vec![0_f32; 4410];
+            let mut right = vec![0_f32; 4410];
+            synthesizer.render(&mut left, &mut right);
+            let peak = left.iter().fold(0_f32, |peak, x| peak.max(x.abs()));
+            (synthesizer.has_clipped(), peak)
+        };
+
+        // The chord clips without the protection.
+        let (clipped, peak) = render(&|_| ());
+        assert!(clipped);
+        assert!(peak > 1_f32);
+
+        // The gain gives headroom, and each mode keeps the peaks within the range.
+        let (clipped, quiet) = render(&|synthesizer| synthesizer.set_output_gain(-20_f32));
+        assert!(!clipped);
+        assert!((quiet / peak - 0.1_f32).abs() < 1e-3);
+        for mode in [LimiterMode::SoftClip, LimiterMode::Limiter] {
+            let (clipped, limited) = render(&|synthesizer| synthesizer.set_limiter_mode(mode));
+            assert!(clipped);
+            assert!(limited <= 1_f32);
+        }
+
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.set_output_gain(6_f32);
+        synthesizer.reset();
+        assert!((synthesizer.get_output_gain() - 6_f32).abs() < 1e-4);
+        assert!(!synthesizer.has_clipped());
+    }
+
+    #[test]
+    fn test_sound_font_stack() {
+        let base = test_sound_font();
+        let drums = test_drum_sound_font();
+
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let mut synthesizer = Synthesizer::new(&base, &settings).unwrap();
+        assert_eq!(synthesizer.get_channel_preset_name(9), Some("Preset"));
+
+        // The kit on top is used for the drums, and the other presets fall through to the base.
+        synthesizer.add_sound_font(&drums);
+        assert_eq!(synthesizer.get_sound_font_count(), 2);
+        assert_eq!(synthesizer.get_channel_preset_name(9), Some("Kit"));
+        assert_eq!(synthesizer.get_channel_preset_name(0), Some("Preset"));
+
+        // The voices follow their SoundFont when the stack is reordered.
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(9, 36, 100);
+        synthesizer.move_sound_font(1, 0);
+        assert_eq!(
+            synthesizer.get_sound_font().get_presets()[0].get_name(),
+            "Kit"
+        );
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(synthesizer.get_active_voice_count(), 2);
+
+        // Removing a SoundFont stops its voices.
+        assert!(Arc::ptr_eq(
+            &synthesizer.remove_sound_font(0).unwrap(),
+            &drums
+        ));
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(synthesizer.get_active_voice_count(), 1);
+        assert!(synthesizer.is_note_on(0, 60));
+        assert_eq!(synthesizer.get_channel_preset_name(9), Some("Preset"));
+        assert!(synthesizer.remove_sound_font(0).is_none());
+    }
+
+    #[test]
+    fn test_set_sound_font() {
+        let base = test_sound_font();
+        let drums = test_drum_sound_font();
+
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let mut synthesizer = Synthesizer::new(&base, &settings).unwrap();
+        synthesizer.add_sound_font(&drums);
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+
+        // The sounding voice keeps playing the old SoundFont, and the programs are looked up again.
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.set_sound_font(&drums, 0_f32);
+        assert_eq!(synthesizer.get_sound_font_count(), 1);
+        assert_eq!(synthesizer.get_channel_preset_name(0), Some("Kit"));
+        synthesizer.render(&mut left, &mut right);
+        assert!(synthesizer.is_note_on(0, 60));
+
+        // With a fade time, the voices of the old SoundFont fade out.
+        synthesizer.note_on(9, 36, 100);
+        synthesizer.set_sound_font(&base, 1_f32);
+        assert_eq!(synthesizer.get_channel_preset_name(9), Some("Preset"));
+        let weak_drums = Arc::downgrade(&drums);
+        drop(drums);
+        synthesizer.collect_garbage();
+        assert!(weak_drums.upgrade().is_some());
+        for _ in 0..2 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert_eq!(synthesizer.get_active_voice_count(), 1);
+        assert!(synthesizer.is_note_on(0, 60));
+
+        // The old SoundFont outlives its last voice until it is collected.
+        assert!(weak_drums.upgrade().is_some());
+        synthesizer.collect_garbage();
+        assert!(weak_drums.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_soundfont_gain_per_sound_font() {
+        let base = test_sound_font();
+        let drums = test_drum_sound_font();
+
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        settings.enable_reverb_and_chorus = false;
+        let render = |base_db: f32, drums_db: f32, channel: i32| {
+            let mut synthesizer = Synthesizer::new(&base, &settings).unwrap();
+            synthesizer.add_sound_font(&drums);
+            synthesizer.set_soundfont_gain_at(0, base_db);
+            synthesizer.note_on(channel, 60, 100);
+            synthesizer.set_soundfont_gain_at(1, drums_db);
+            let mut left = vec![0_f32; 256];
+            let mut right = vec![0_f32; 256];
+            synthesizer.render(&mut left, &mut right);
+            left.iter().fold(0_f32, |peak, x| peak.max(x.abs()))
+        };
+
+        // Each gain only applies to the voices of its SoundFont, including those already sounding.
+        let drums_peak = render(0_f32, 0_f32, 9);
+        assert!(drums_peak > 0_f32);
+        assert_eq!(render(-20_f32, 0_f32, 9), drums_peak);
+        assert!((render(0_f32, -20_f32, 9) / drums_peak - 0.1).abs() < 1e-3);
+        let base_peak = render(0_f32, 0_f32, 0);
+        assert_eq!(render(0_f32, -20_f32, 0), base_peak);
+        assert!((render(-20_f32, 0_f32, 0) / base_peak - 0.1).abs() < 1e-3);
+
+        let mut synthesizer = Synthesizer::new(&base, &settings).unwrap();
+        synthesizer.add_sound_font(&drums);
+        synthesizer.set_soundfont_gain(-6_f32);
+        assert!((synthesizer.get_soundfont_gain_at(0).unwrap() + 6_f32).abs() < 1e-3);
+        assert_eq!(synthesizer.get_soundfont_gain_at(1), Some(0_f32));
+        assert_eq!(synthesizer.get_soundfont_gain_at(2), None);
+    }
+
+    #[cfg(feature = "introspection")]
+    #[test]
+    fn test_voice_and_channel_infos() {
+        let mut synthesizer = test_synthesizer();
+
+        synthesizer.process_midi_message(2, 0xB0, 0x00, 3);
+        synthesizer.process_midi_message(2, 0xC0, 5, 0);
+        synthesizer.process_midi_message(2, 0xB0, 0x07, 90);
+        synthesizer.process_midi_message(2, 0xE0, 0x00, 0x60);
+        synthesizer.process_midi_message(2, 0xD0, 70, 0);
+        synthesizer.note_on(2, 60, 100);
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+        synthesizer.render(&mut left, &mut right);
+
+        let voices: Vec<VoiceInfo> = synthesizer.voice_infos().collect();
+        assert_eq!(voices, synthesizer.voices_snapshot());
+        assert_eq!(voices.len(), 1);
+        assert_eq!(
+            (voices[0].channel, voices[0].key, voices[0].velocity),
+            (2, 60, 100)
+        );
+
+        let info = synthesizer.get_channel_info(2).unwrap();
+        assert_eq!((info.bank, info.program), (3, 5));
+        assert_eq!(info.controllers[7], 90);
+        assert_eq!(info.channel_pressure, 70);
+        assert!((info.pitch_wheel - 0.5).abs() < 1.0E-6);
+        assert!((info.pitch_bend - 0.5 * info.pitch_bend_range).abs() < 1.0E-6);
+        assert!(synthesizer.get_channel_info(16).is_none());
+        assert!(synthesizer.get_channel_info(-1).is_none());
+
+        let infos: Vec<ChannelInfo> = synthesizer.channel_infos().collect();
+        assert_eq!(infos.len(), 16);
+        assert!(infos[9].is_percussion);
+        assert_eq!(infos[9].bank, 128);
+        assert_eq!(infos[2], info);
+    }
+
+    #[test]
+    fn test_host_automation() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let settings = SynthesizerSettings::new(44100);
+        let mut reference = Synthesizer::new(&sound_font, &settings).unwrap();
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+
+        let render = |synthesizer: &mut Synthesizer| {
+            let mut left = vec![0_f32; 256];
+            let mut right = vec![0_f32; 256];
+            synthesizer.render(&mut left, &mut right);
+            right
+        };
+
+        // A muted key is silent, and the other keys are not affected.
+        synthesizer.set_channel_key_gain(0, 60, 0_f32);
+        synthesizer.note_on(0, 60, 100);
+        assert!(render(&mut synthesizer).iter().all(|value| *value == 0_f32));
+        render(&mut reference);
+        synthesizer.note_on(0, 62, 100);
+        reference.note_on(0, 62, 100);
+        assert_eq!(render(&mut synthesizer), render(&mut reference));
+
+        // The overrides are kept across reset, and cleared on request.
+        synthesizer.set_channel_key_tune(0, 62, 0.5_f32);
+        synthesizer.set_channel_filter_cutoff_offset(0, -1200_f32);
+        synthesizer.set_channel_attack_scale(0, 2_f32);
+        synthesizer.reset();
+        reference.reset();
+        synthesizer.note_on(0, 62, 100);
+        reference.note_on(0, 62, 100);
+        assert_ne!(render(&mut synthesizer), render(&mut reference));
+
+        synthesizer.clear_channel_key_overrides(0);
+        synthesizer.set_channel_filter_cutoff_offset(0, 0_f32);
+        synthesizer.set_channel_attack_scale(0, 1_f32);
+        synthesizer.reset();
+        reference.reset();
+        synthesizer.note_on(0, 60, 100);
+        reference.note_on(0, 60, 100);
+        assert_eq!(render(&mut synthesizer), render(&mut reference));
+    }
+
+    #[test]
+    fn test_render_does_not_allocate() {
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.maximum_polyphony = 8;
+        let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+        synthesizer.set_arpeggiator(1, Some(ArpConfig::new(ArpMode::Up)));
+        let queue = CommandQueue::new(16, QueueOverflow::DropOldest);
+        let mut left = vec![0_f32; 1000];
+        let mut right = vec![0_f32; 1000];
+
+        // The voices are stolen once the polyphony is used up, and the buffers are not whole blocks.
+        let count = count_allocations(|| {
+            for key in 30..90 {
+                synthesizer.note_on(key % 3, key, 100);
+                synthesizer.process_midi_message_at(0, 0x80, key, 0, key as usize * 10);
+                synthesizer.render(&mut left[..37], &mut right[..37]);
+            }
+            for controller in 0..128 {
+                synthesizer.process_midi_message(0, 0xB0, controller, 64);
+            }
+            synthesizer.process_midi_message(0, 0xC0, 5, 0);
+            synthesizer.process_midi_message(0, 0xE0, 0, 100);
+            queue.push(2, 0x90, 61, 100);
+            synthesizer.process_queue(&queue);
+            synthesizer.render(&mut left, &mut right);
+
+            synthesizer.process_sysex(&[0x7E, 0x7F, 0x09, 0x01]);
+            synthesizer.note_off_all(false);
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.reset();
+            synthesizer.render(&mut left, &mut right);
+        });
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_channel_adsr_scale() {
+        let mut synthesizer = test_synthesizer();
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+        let mut left = vec![0_f32; 4096];
+        let mut right = vec![0_f32; 4096];
+
+        // The channel 1 has a release of 1 second instead of 1 ms, and a sustain 20 dB lower.
+        synthesizer.set_channel_adsr_scale(1, 1.0, 1.0, -20.0, 1000.0);
+        synthesizer.reset();
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(1, 60, 100);
+        // A note held on the channel 2 keeps its envelope when the scale changes.
+        synthesizer.note_on(2, 60, 100);
+        synthesizer.set_channel_adsr_scale(2, 1.0, 1.0, -20.0, 1000.0);
+
+        let channel_peak = |synthesizer: &Synthesizer, channel: i32| {
+            let mut synthesizer = synthesizer.clone();
+            for other in 0..3 {
+                if other != channel {
+                    synthesizer.note_off_all_channel(other, true);
+                }
+            }
+            let mut left = vec![0_f32; 1024];
+            let mut right = vec![0_f32; 1024];
+            synthesizer.render(&mut left, &mut right);
+            peak(&left)
+        };
+        synthesizer.render(&mut left, &mut right);
+        let open = channel_peak(&synthesizer, 0);
+        assert!(open > 0_f32);
+        assert!((channel_peak(&synthesizer, 1) / open - 0.1).abs() < 0.01);
+        assert!((channel_peak(&synthesizer, 2) / open - 1.0).abs() < 0.01);
+
+        // After 90 ms, only the note with the long release is still sounding.
+        synthesizer.note_off_all(false);
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(synthesizer.get_active_voice_count(), 1);
+        assert_eq!(synthesizer.voices.get_active_voices_ref()[0].channel(), 1);
+    }
+
+    #[test]
+    fn test_retrigger_mode() {
+        let playing_count = |retrigger_mode: RetriggerMode| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.enable_reverb_and_chorus = false;
+            settings.test_tone_mode = true;
+            settings.retrigger_mode = retrigger_mode;
+            let mut synthesizer = Synthesizer::new(&test_sound_font(), &settings).unwrap();
+            assert_eq!(synthesizer.get_retrigger_mode(), retrigger_mode);
+
+            // The key is struck again before its note-off, next to another key.
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.note_on(0, 64, 100);
+            let mut left = vec![0_f32; 1024];
+            let mut right = vec![0_f32; 1024];
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.get_active_voice_count()
+        };
+
+        assert_eq!(playing_count(RetriggerMode::Layer), 3);
+        assert_eq!(playing_count(RetriggerMode::Steal), 2);
+    }
+
+    #[test]
+    fn test_render_preset_note() {
+        let mut synthesizer = test_synthesizer();
+        synthesizer.note_on(1, 72, 100);
+        let fingerprint = synthesizer.state_fingerprint();
+
+        // A note held for 100 ms, followed by its release of 10 ms.
+        let (left, right) = synthesizer.render_preset_note(0, 0, 60, 100, 0.1);
+        assert_eq!(left.len(), right.len());
+        assert!(left.len() > 4410 && left.len() < 4410 + 1000);
+        assert!(left[..4410].iter().any(|&x| x != 0_f32));
+        assert!(left[4410..].iter().any(|&x| x != 0_f32));
+        assert!(left[left.len() - 64..].iter().all(|&x| x == 0_f32));
+
+        // The note is rendered on a copy, which leaves the held note alone.
+        assert_eq!(synthesizer.state_fingerprint(), fingerprint);
+        assert!(synthesizer.is_note_on(1, 72));
+        assert!(!synthesizer.is_note_on(0, 60));
+    }
+
+    #[test]
+    fn test_smooth_controllers() {
+        // The right half of the stereo sample is a constant level, which shows the gain of each sample.
+        let data = crate::soundfont::tests::build_stereo_soundfont(&[(54, 1)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let ramp = |smooth_controllers: bool| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.enable_reverb_and_chorus = false;
+            settings.smooth_controllers = smooth_controllers;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            assert_eq!(synthesizer.get_smooth_controllers(), smooth_controllers);
+
+            synthesizer.note_on(0, 60, 100);
+            let mut left = vec![0_f32; 64];
+            let mut right = vec![0_f32; 64];
+            for _ in 0..100 {
+                synthesizer.render(&mut left, &mut right);
+            }
+
+            // A change of the gain too small to be ramped normally.
+            synthesizer.set_channel_gain(0, 0.999);
+            synthesizer.render(&mut left, &mut right);
+            right[0] - right[63]
+        };
+
+        assert!(ramp(false).abs() < 1.0e-7);
+        assert!(ramp(true) > 1.0e-5);
+    }
+
+    #[test]
+    fn test_master_volume_and_reset_sysex() {
+        let mut synthesizer = test_synthesizer();
+        let default_volume = synthesizer.get_master_volume();
+        let mut left = vec![0_f32; 1024];
+        let mut right = vec![0_f32; 1024];
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.render(&mut left, &mut right);
+        let full = peak(&left);
+
+        // The universal master volume at the half of its range halves the output.
+        synthesizer.process_sysex(&[0xF0, 0x7F, 0x7F, 0x04, 0x01, 0x7F, 0x3F, 0xF7]);
+        assert!((synthesizer.get_master_volume() / default_volume - 0.5).abs() < 1.0e-3);
+        for _ in 0..4 {
+            synthesizer.render(&mut left, &mut right);
+        }
+        assert!((peak(&left) - 0.5 * full).abs() < 0.01 * full);
+
+        // Each reset stops the notes, resets the controllers, and restores the master volume and the drums.
+        let resets: [&[u8]; 3] = [
+            &[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7],
+            &[
+                0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41, 0xF7,
+            ],
+            &[0xF0, 0x43, 0x10, 0x4C, 0x00, 0x00, 0x7E, 0x00, 0xF7],
+        ];
+        for reset in resets {
+            synthesizer.process_sysex(&[0xF0, 0x7F, 0x7F, 0x04, 0x01, 0x00, 0x00, 0xF7]);
+            synthesizer.process_midi_message(1, 0xB0, 0x07, 10);
+            synthesizer.set_channel_drum_mode(1, true);
+            synthesizer.set_channel_drum_mode(9, false);
+            synthesizer.note_on(1, 60, 100);
+
+            synthesizer.process_sysex(reset);
+            assert_eq!(synthesizer.get_master_volume(), default_volume);
+            assert_eq!(synthesizer.channels[1].get_controller(0x07), 100);
+            assert!(!synthesizer.is_channel_drum_mode(1));
+            assert!(synthesizer.is_channel_drum_mode(9));
+            assert_eq!(synthesizer.get_active_voice_count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_render_i16_dither_seed() {
+        let render = |mode: DitherMode, seed: u64| {
+            let mut synthesizer = test_synthesizer();
+            synthesizer.set_dither_mode(mode);
+            synthesizer.set_dither_seed(seed);
+            assert_eq!(synthesizer.get_dither_mode(), mode);
+            let mut left = vec![0_i16; 1000];
+            let mut right = vec![0_i16; 1000];
+            synthesizer.render_i16(&mut left, &mut right);
+            left
+        };
+
+        // The silence stays silent without the dither, and the dither is reproducible from the seed.
+        assert!(render(DitherMode::None, 1).iter().all(|&x| x == 0));
+        let dithered = render(DitherMode::Triangular, 1);
+        assert!(dithered.iter().any(|&x| x != 0));
+        assert!(dithered.iter().all(|&x| (-1..=1).contains(&x)));
+        assert_eq!(render(DitherMode::Triangular, 1), dithered);
+        assert_ne!(render(DitherMode::Triangular, 2), dithered);
+    }
+
+    #[test]
+    fn test_link_expression() {
+        let mut synthesizer = test_synthesizer();
+        let mut left = vec![0_f32; 1024];
+        let mut right = vec![0_f32; 1024];
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+
+        // The channel 0 is on the left, and the channel 1 on the right.
+        synthesizer.process_midi_message(0, 0xB0, 0x0A, 0);
+        synthesizer.process_midi_message(1, 0xB0, 0x0A, 127);
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.note_on(1, 60, 100);
+        synthesizer.link_expression(0, 1, true);
+        let mut render = |synthesizer: &mut Synthesizer| {
+            for _ in 0..4 {
+                synthesizer.render(&mut left, &mut right);
+            }
+            (peak(&left), peak(&right))
+        };
+        let (full, _) = render(&mut synthesizer);
+        assert!(full > 0_f32);
+
+        // The expression of the channel 0 crossfades to the channel 1,
+        // where the complement of 0 is the full 14-bit value, slightly louder than 127.
+        synthesizer.process_midi_message(0, 0xB0, 0x0B, 0);
+        let (a, b) = render(&mut synthesizer);
+        assert!(a < 1.0e-3 * full && (b - full).abs() < 0.05 * full);
+        synthesizer.process_midi_message(0, 0xB0, 0x0B, 127);
+        let (a, b) = render(&mut synthesizer);
+        assert!((a - full).abs() < 0.05 * full && b < 1.0e-3 * full);
+
+        // Without the inversion, both follow, and the unlinked channel keeps its expression.
+        synthesizer.link_expression(0, 1, false);
+        synthesizer.process_midi_message(0, 0xB0, 0x0B, 0);
+        let (a, b) = render(&mut synthesizer);
+        assert!(a < 1.0e-3 * full && b < 1.0e-3 * full);
+        synthesizer.unlink_expression(0);
+        synthesizer.process_midi_message(0, 0xB0, 0x0B, 127);
+        let (a, b) = render(&mut synthesizer);
+        assert!((a - full).abs() < 0.05 * full && b < 1.0e-3 * full);
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut synthesizer = test_synthesizer();
+        let mut left = vec![0_f32; 4096];
+        let mut right = vec![0_f32; 4096];
+        let peak = |buffer: &[f32]| buffer.iter().fold(0_f32, |acc, x| acc.max(x.abs()));
+
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.render(&mut left, &mut right);
+        let held = peak(&left);
+        synthesizer.set_freeze(true);
+
+        // The frozen note ignores its note-off, while a note played over it is released.
+        synthesizer.note_off(0, 60);
+        synthesizer.note_on(0, 64, 100);
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.note_off(0, 64);
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(synthesizer.get_active_voice_count(), 1);
+        assert!((peak(&left) - held).abs() < 0.01 * held);
+
+        // Releasing the freeze releases the note.
+        synthesizer.set_freeze(false);
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(synthesizer.get_active_voice_count(), 0);
+
+        // All Sound Off stops the frozen notes.
+        synthesizer.note_on(0, 60, 100);
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.set_freeze(true);
+        synthesizer.process_midi_message(0, 0xB0, 0x78, 0);
+        synthesizer.render(&mut left, &mut right);
+        assert_eq!(synthesizer.get_active_voice_count(), 0);
+    }
+
+    #[test]
+    fn test_note_off_velocity() {
+        // A release of 0.5 seconds.
+        let data = crate::soundfont::tests::build_soundfont(&[], &[(54, 1), (38, -1200)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let release_blocks = |use_note_off_velocity: bool, velocity: i32| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.enable_reverb_and_chorus = false;
+            settings.test_tone_mode = true;
+            settings.use_note_off_velocity = use_note_off_velocity;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            assert_eq!(
+                synthesizer.get_use_note_off_velocity(),
+                use_note_off_velocity
+            );
+
+            let mut left = vec![0_f32; 64];
+            let mut right = vec![0_f32; 64];
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.render(&mut left, &mut right);
+            synthesizer.process_midi_message(0, 0x80, 60, velocity);
+            let mut count = 0;
+            while synthesizer.get_active_voice_count() > 0 {
+                synthesizer.render(&mut left, &mut right);
+                count += 1;
+            }
+            count as f32
+        };
+
+        // The release is twice as long at the velocity 0, and half as long at 127.
+        let normal = release_blocks(true, 64);
+        assert!((release_blocks(true, 0) / normal - 2_f32).abs() < 0.1);
+        assert!((release_blocks(true, 127) / normal - 0.5_f32).abs() < 0.05);
+        assert_eq!(release_blocks(false, 0), normal);
+        assert_eq!(release_blocks(false, 127), normal);
+    }
+
+    #[test]
+    fn test_crossfade_program_change() {
+        let playing_after_program_change = |crossfade_program_change_ms: f32| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.enable_reverb_and_chorus = false;
+            settings.test_tone_mode = true;
+            settings.crossfade_program_change_ms = crossfade_program_change_ms;
+            let mut synthesizer = Synthesizer::new(&test_sound_font(), &settings).unwrap();
+            assert_eq!(
+                synthesizer.get_crossfade_program_change_ms(),
+                crossfade_program_change_ms
+            );
+
+            synthesizer.note_on(0, 60, 100);
+            synthesizer.note_on(1, 64, 100);
+            let mut left = vec![0_f32; 1024];
+            let mut right = vec![0_f32; 1024];
+            synthesizer.render(&mut left, &mut right);
+
+            // The notes on the channel 0 fade out over 20 ms, which is 882 samples.
+            synthesizer.process_midi_message(0, 0xC0, 1, 0);
+            let mut left = vec![0_f32; 448];
+            let mut right = vec![0_f32; 448];
+            synthesizer.render(&mut left, &mut right);
+            let fading = synthesizer.is_note_on(0, 60);
+            let mut left = vec![0_f32; 1024];
+            let mut right = vec![0_f32; 1024];
+            synthesizer.render(&mut left, &mut right);
+            (
+                fading,
+                synthesizer.is_note_on(0, 60),
+                synthesizer.is_note_on(1, 64),
+            )
+        };
+
+        assert_eq!(playing_after_program_change(20_f32), (true, false, true));
+        assert_eq!(playing_after_program_change(0_f32), (true, true, true));
+    }
+
+    #[test]
+    fn test_render_dry_wet() {
+        let mut settings = SynthesizerSettings::new(44100);
+        settings.test_tone_mode = true;
+        let start = |settings: &SynthesizerSettings| {
+            let mut synthesizer = Synthesizer::new(&test_sound_font(), settings).unwrap();
+            synthesizer.process_midi_message(0, 0xB0, 0x5B, 127);
+            synthesizer.note_on(0, 60, 100);
+            synthesizer
+        };
+
+        // The dry and wet outputs add up to the output of render, even across the note-off.
+        let mut mixed = start(&settings);
+        let mut split = start(&settings);
+        let mut left = vec![0_f32; 4096];
+        let mut right = vec![0_f32; 4096];
+        let mut dry_left = vec![0_f32; 4096];
+        let mut dry_right = vec![0_f32; 4096];
+        let mut wet_left = vec![0_f32; 4096];
+        let mut wet_right = vec![0_f32; 4096];
+        for _ in 0..2 {
+            mixed.render(&mut left, &mut right);
+            split.render_dry_wet(&mut dry_left, &mut dry_right, &mut wet_left, &mut wet_right);
+            for t in 0..4096 {
+                assert!((dry_left[t] + wet_left[t] - left[t]).abs() < 1.0e-6);
+                assert!((dry_right[t] + wet_right[t] - right[t]).abs() < 1.0e-6);
+            }
+            assert!(wet_left.iter().any(|&x| x != 0_f32));
+            mixed.note_off(0, 60);
+            split.note_off(0, 60);
+        }
+
+        // After the release, only the reverb tail is left in the wet output.
+        assert!(dry_left[2048..].iter().all(|&x| x == 0_f32));
+        assert!(wet_left[2048..].iter().any(|&x| x != 0_f32));
+
+        // Without the effects, everything is dry.
+        settings.enable_reverb_and_chorus = false;
+        let mut mixed = start(&settings);
+        let mut split = start(&settings);
+        mixed.render(&mut left, &mut right);
+        split.render_dry_wet(&mut dry_left, &mut dry_right, &mut wet_left, &mut wet_right);
+        assert_eq!(dry_left, left);
+        assert!(wet_left.iter().all(|&x| x == 0_f32));
+    }
+
+    #[test]
+    fn test_honor_soundfont_effect_sends() {
+        // The SoundFont sends the note fully to the reverb.
+        let data = crate::soundfont::tests::build_soundfont(&[], &[(54, 1), (16, 1000)]);
+        let sound_font = Arc::new(SoundFont::new(&mut &data[..]).unwrap());
+        let sets = sound_font.resolve_generators(0, 0, 60, 100);
+        assert_eq!(sets[0].get_reverb_effects_send(), 100_f32);
+
+        let wet_peak = |honor_soundfont_effect_sends: bool, reverb_send: i32| {
+            let mut settings = SynthesizerSettings::new(44100);
+            settings.test_tone_mode = true;
+            settings.honor_soundfont_effect_sends = honor_soundfont_effect_sends;
+            let mut synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
+            synthesizer.process_midi_message(0, 0xB0, 0x5B, reverb_send);
+            synthesizer.process_midi_message(0, 0xB0, 0x5D, 0);
+            synthesizer.note_on(0, 60, 100);
+            let mut dry_left = vec![0_f32; 4096];
+            let mut dry_right = vec![0_f32; 4096];
+            let mut wet_left = vec![0_f32; 4096];
+            let mut wet_right = vec![0_f32; 4096];
+            synthesizer.render_dry_wet(
+                &mut dry_left,
+                &mut dry_right,
+                &mut wet_left,
+                &mut wet_right,
+            );
+            wet_left.iter().fold(0_f32, |acc, x| acc.max(x.abs()))
+        };
+
+        // Ignoring the sends of the SoundFont leaves only the send controller of the channel.
+        assert!(wet_peak(true, 0) > 0_f32);
+        assert_eq!(wet_peak(false, 0), 0_f32);
+        assert!(wet_peak(false, 127) > 0_f32);
+    }
+
+    #[test]
+    fn test_tuning_reference() {
+        // The frequency of the test tone, counted from its rising zero crossings over one second.
+        let frequency = |a4_hz: f64, channel: i32, transpose: i32| {
+            let mut synthesizer = test_synthesizer();
+            synthesizer.set_tuning_reference(a4_hz);
+            assert_eq!(synthesizer.get_tuning_reference(), a4_hz);
+            synthesizer.set_channel_transpose(channel, transpose);
+            synthesizer.note_on(channel, 69, 100);
+            let mut left = vec![0_f32; 44100];
+            let mut right = vec![0_f32; 44100];
+            synthesizer.render(&mut left, &mut right);
+            let crossings = left
+                .windows(2)
+                .filter(|pair| pair[0] <= 0_f32 && pair[1] > 0_f32)
+                .count();
+            crossings as f64
+        };
+
+        assert!((frequency(440.0, 0, 0) - 440.0).abs() <= 1.0);
+        assert!((frequency(432.0, 0, 0) - 432.0).abs() <= 1.0);
+        assert!((frequency(415.0, 0, 12) - 830.0).abs() <= 1.0);
+        // The percussion channel is not shifted.
+        assert!((frequency(432.0, 9, 0) - 440.0).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_reset_channel() {
+        let mut synthesizer = test_synthesizer();
+        for channel in 0..2 {
+            synthesizer.process_midi_message(channel, 0xC0, 5, 0);
+            synthesizer.process_midi_message(channel, 0xB0, 0x07, 10);
+            synthesizer.process_midi_message(channel, 0xB0, 0x40, 127);
+            synthesizer.process_midi_message(channel, 0xE0, 0x00, 0x7F);
+            synthesizer.note_on(channel, 60, 100);
         }
+        let mut left = vec![0_f32; 1024];
+        let mut right = vec![0_f32; 1024];
+        synthesizer.render(&mut left, &mut right);
+
+        // The channel 0 returns to its defaults and releases its notes, keeping its program.
+        synthesizer.reset_channel(0, false);
+        synthesizer.render(&mut left, &mut right);
+        let channel = &synthesizer.channels[0];
+        assert_eq!(channel.get_controller(0x07), 100);
+        assert!(!channel.get_hold_pedal());
+        assert_eq!(channel.get_pitch_bend(), 0_f32);
+        assert_eq!(channel.get_patch_number(), 5);
+        assert!(!synthesizer.is_note_on(0, 60));
+
+        // The channel 1 is left untouched until it is reset with its program.
+        let channel = &synthesizer.channels[1];
+        assert_eq!(channel.get_controller(0x07), 10);
+        assert!(channel.get_hold_pedal());
+        assert!(synthesizer.is_note_on(1, 60));
+        synthesizer.reset_channel(1, true);
+        assert_eq!(synthesizer.channels[1].get_patch_number(), 0);
+        assert!(!synthesizer.is_note_on(1, 60));
     }
 }
@@ -1,6 +1,14 @@
 #![allow(dead_code)]
 
+use alloc::sync::Arc;
+
 use crate::error::SynthesizerError;
+use crate::interpolator::Interpolator;
+use crate::pan_curve::PanCurve;
+use crate::retrigger_mode::RetriggerMode;
+use crate::synthesizer_settings_builder::SynthesizerSettingsBuilder;
+use crate::voice_stealing_policy::VoiceStealingPolicy;
+use crate::volume_curve::VolumeCurve;
 
 #[derive(Debug)]
 #[non_exhaustive]
@@ -8,32 +16,121 @@ pub struct SynthesizerSettings {
     pub sample_rate: i32,
     pub block_size: usize,
     pub maximum_polyphony: usize,
+    /// The number of threads which render the voices, including the calling thread.
+    ///
+    /// With more than `1`, the voices of each block are split across scoped threads
+    /// once there are enough of them to pay for starting the threads.
+    /// The voices are still mixed on the calling thread in the same order,
+    /// so the output is identical to the single-threaded rendering.
+    ///
+    /// The threads are started and joined for each block, which allocates and may take
+    /// longer than a block of audio, so this is meant for offline rendering only,
+    /// such as exporting a MIDI file at high polyphony.
+    /// A real-time audio callback should keep the default of `1`.
+    /// On wasm32, where the threads cannot be started, and without the `std` feature,
+    /// the voices are always rendered on the calling thread.
+    pub render_thread_count: usize,
+    /// If `false`, the reverb and chorus are not created and their delay lines are never allocated.
     pub enable_reverb_and_chorus: bool,
+    /// How a note-on for a key that is already sounding is handled.
+    pub retrigger_mode: RetriggerMode,
+    /// If `true`, every note plays its samples from their start point, so that the attacks are identical.
+    /// If `false`, a note struck again while it is sounding continues each sample
+    /// from where the voice still playing it is, whether the `retrigger_mode` layers or steals that voice.
+    /// The samples which are not sounding, and the voices reused for an exclusive class, still start from their start point.
+    pub reset_phase_on_note_on: bool,
+    /// Which voice is reused for a new note when all the voices, or those allowed for its channel, are in use.
+    pub voice_stealing_policy: VoiceStealingPolicy,
+    /// How the channel volume and expression are converted to gain.
+    pub volume_curve: VolumeCurve,
+    /// How the pan position (CC 10) is mapped to the stereo placement.
+    pub pan_curve: PanCurve,
+    /// If `true`, the pitch, volume, and pan changes are interpolated per sample within a block,
+    /// which avoids the stepping of fast sweeps at the cost of some CPU.
+    pub smooth_controllers: bool,
+    /// If `true`, tiny values in the reverb and chorus are flushed to zero,
+    /// which avoids the CPU spikes caused by denormal numbers in decaying tails.
+    pub flush_denormals: bool,
+    /// If `true`, the release velocity of the note-off scales the release time of the notes.
+    pub use_note_off_velocity: bool,
+    /// The time in milliseconds over which the sounding notes of a channel fade out
+    /// when the channel receives a program change. `0` keeps them sounding until their note-off.
+    pub crossfade_program_change_ms: f32,
+    /// If `false`, the reverb and chorus sends set by the SoundFont generators are ignored,
+    /// so that only the channel's send controllers feed the effects.
+    pub honor_soundfont_effect_sends: bool,
+    /// The interpolation of the sample data. `None` uses the built-in linear interpolation,
+    /// which is faster than `LinearInterpolator` thanks to fixed-point arithmetic.
+    /// `CubicInterpolator` and `SincInterpolator` trade CPU for less aliasing,
+    /// the latter also filtering the samples pitched far above their root key.
+    pub interpolator: Option<Arc<dyn Interpolator>>,
+    /// If `true`, every note plays a sine at its pitch instead of the samples of the SoundFont.
+    /// The envelopes, filter, velocity, and controllers still apply, which makes the output
+    /// depend on the timing and the articulation only, as needed for golden-output tests.
+    pub test_tone_mode: bool,
+    /// If `true`, the reverb and chorus take the sum of the left and right as their input
+    /// and return the same signal on both, so that folding the output to mono
+    /// does not cause phasing or comb filtering in the effects.
+    /// The voices themselves keep their pan, and only their dry signal is panned.
+    pub mono_safe_effects: bool,
 }
 
 impl SynthesizerSettings {
     const DEFAULT_BLOCK_SIZE: usize = 64;
     const DEFAULT_MAXIMUM_POLYPHONY: usize = 64;
+    const DEFAULT_RENDER_THREAD_COUNT: usize = 1;
     const DEFAULT_ENABLE_REVERB_AND_CHORUS: bool = true;
+    const DEFAULT_RETRIGGER_MODE: RetriggerMode = RetriggerMode::Layer;
+    const DEFAULT_RESET_PHASE_ON_NOTE_ON: bool = true;
+    const DEFAULT_VOICE_STEALING_POLICY: VoiceStealingPolicy = VoiceStealingPolicy::Quietest;
+    const DEFAULT_VOLUME_CURVE: VolumeCurve = VolumeCurve::Gm2;
+    const DEFAULT_PAN_CURVE: PanCurve = PanCurve::Gm;
+    const DEFAULT_SMOOTH_CONTROLLERS: bool = false;
+    const DEFAULT_FLUSH_DENORMALS: bool = true;
+    const DEFAULT_USE_NOTE_OFF_VELOCITY: bool = false;
+    const DEFAULT_CROSSFADE_PROGRAM_CHANGE_MS: f32 = 0_f32;
+    const DEFAULT_HONOR_SOUNDFONT_EFFECT_SENDS: bool = true;
+    const DEFAULT_TEST_TONE_MODE: bool = false;
+    const DEFAULT_MONO_SAFE_EFFECTS: bool = false;
 
     pub fn new(sample_rate: i32) -> Self {
         Self {
             sample_rate,
             block_size: SynthesizerSettings::DEFAULT_BLOCK_SIZE,
             maximum_polyphony: SynthesizerSettings::DEFAULT_MAXIMUM_POLYPHONY,
+            render_thread_count: SynthesizerSettings::DEFAULT_RENDER_THREAD_COUNT,
             enable_reverb_and_chorus: SynthesizerSettings::DEFAULT_ENABLE_REVERB_AND_CHORUS,
+            retrigger_mode: SynthesizerSettings::DEFAULT_RETRIGGER_MODE,
+            reset_phase_on_note_on: SynthesizerSettings::DEFAULT_RESET_PHASE_ON_NOTE_ON,
+            voice_stealing_policy: SynthesizerSettings::DEFAULT_VOICE_STEALING_POLICY,
+            volume_curve: SynthesizerSettings::DEFAULT_VOLUME_CURVE,
+            pan_curve: SynthesizerSettings::DEFAULT_PAN_CURVE,
+            smooth_controllers: SynthesizerSettings::DEFAULT_SMOOTH_CONTROLLERS,
+            flush_denormals: SynthesizerSettings::DEFAULT_FLUSH_DENORMALS,
+            use_note_off_velocity: SynthesizerSettings::DEFAULT_USE_NOTE_OFF_VELOCITY,
+            crossfade_program_change_ms: SynthesizerSettings::DEFAULT_CROSSFADE_PROGRAM_CHANGE_MS,
+            honor_soundfont_effect_sends: SynthesizerSettings::DEFAULT_HONOR_SOUNDFONT_EFFECT_SENDS,
+            interpolator: None,
+            test_tone_mode: SynthesizerSettings::DEFAULT_TEST_TONE_MODE,
+            mono_safe_effects: SynthesizerSettings::DEFAULT_MONO_SAFE_EFFECTS,
         }
     }
 
+    /// Creates a builder to set up the settings with fluent setters.
+    pub fn builder() -> SynthesizerSettingsBuilder {
+        SynthesizerSettingsBuilder::new()
+    }
+
     pub(crate) fn validate(&self) -> Result<(), SynthesizerError> {
         SynthesizerSettings::check_sample_rate(self.sample_rate)?;
         SynthesizerSettings::check_block_size(self.block_size)?;
         SynthesizerSettings::check_maximum_polyphony(self.maximum_polyphony)?;
+        SynthesizerSettings::check_render_thread_count(self.render_thread_count)?;
 
         Ok(())
     }
 
-    fn check_sample_rate(value: i32) -> Result<(), SynthesizerError> {
+    pub(crate) fn check_sample_rate(value: i32) -> Result<(), SynthesizerError> {
         if !(16_000..=192_000).contains(&value) {
             return Err(SynthesizerError::SampleRateOutOfRange(value));
         }
@@ -56,4 +153,12 @@ impl SynthesizerSettings {
 
         Ok(())
     }
+
+    fn check_render_thread_count(value: usize) -> Result<(), SynthesizerError> {
+        if !(1..=64).contains(&value) {
+            return Err(SynthesizerError::RenderThreadCountOutOfRange(value));
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,133 @@
+#![allow(dead_code)]
+
+use alloc::sync::Arc;
+
+use crate::error::SynthesizerError;
+use crate::interpolator::Interpolator;
+use crate::pan_curve::PanCurve;
+use crate::retrigger_mode::RetriggerMode;
+use crate::synthesizer_settings::SynthesizerSettings;
+use crate::voice_stealing_policy::VoiceStealingPolicy;
+use crate::volume_curve::VolumeCurve;
+
+/// Builds `SynthesizerSettings` with fluent setters, validating them on `build`.
+///
+/// The options which are not set keep the defaults of `SynthesizerSettings::new`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SynthesizerSettingsBuilder {
+    settings: SynthesizerSettings,
+}
+
+impl SynthesizerSettingsBuilder {
+    const DEFAULT_SAMPLE_RATE: i32 = 44100;
+
+    /// Creates a builder with the sample rate of 44100 Hz.
+    pub fn new() -> Self {
+        Self {
+            settings: SynthesizerSettings::new(SynthesizerSettingsBuilder::DEFAULT_SAMPLE_RATE),
+        }
+    }
+
+    pub fn sample_rate(mut self, value: i32) -> Self {
+        self.settings.sample_rate = value;
+        self
+    }
+
+    pub fn block_size(mut self, value: usize) -> Self {
+        self.settings.block_size = value;
+        self
+    }
+
+    pub fn maximum_polyphony(mut self, value: usize) -> Self {
+        self.settings.maximum_polyphony = value;
+        self
+    }
+
+    pub fn render_thread_count(mut self, value: usize) -> Self {
+        self.settings.render_thread_count = value;
+        self
+    }
+
+    pub fn enable_reverb_and_chorus(mut self, value: bool) -> Self {
+        self.settings.enable_reverb_and_chorus = value;
+        self
+    }
+
+    pub fn retrigger_mode(mut self, value: RetriggerMode) -> Self {
+        self.settings.retrigger_mode = value;
+        self
+    }
+
+    pub fn reset_phase_on_note_on(mut self, value: bool) -> Self {
+        self.settings.reset_phase_on_note_on = value;
+        self
+    }
+
+    pub fn voice_stealing_policy(mut self, value: VoiceStealingPolicy) -> Self {
+        self.settings.voice_stealing_policy = value;
+        self
+    }
+
+    pub fn volume_curve(mut self, value: VolumeCurve) -> Self {
+        self.settings.volume_curve = value;
+        self
+    }
+
+    pub fn pan_curve(mut self, value: PanCurve) -> Self {
+        self.settings.pan_curve = value;
+        self
+    }
+
+    pub fn smooth_controllers(mut self, value: bool) -> Self {
+        self.settings.smooth_controllers = value;
+        self
+    }
+
+    pub fn flush_denormals(mut self, value: bool) -> Self {
+        self.settings.flush_denormals = value;
+        self
+    }
+
+    pub fn use_note_off_velocity(mut self, value: bool) -> Self {
+        self.settings.use_note_off_velocity = value;
+        self
+    }
+
+    pub fn crossfade_program_change_ms(mut self, value: f32) -> Self {
+        self.settings.crossfade_program_change_ms = value;
+        self
+    }
+
+    pub fn honor_soundfont_effect_sends(mut self, value: bool) -> Self {
+        self.settings.honor_soundfont_effect_sends = value;
+        self
+    }
+
+    pub fn interpolator(mut self, value: Arc<dyn Interpolator>) -> Self {
+        self.settings.interpolator = Some(value);
+        self
+    }
+
+    pub fn test_tone_mode(mut self, value: bool) -> Self {
+        self.settings.test_tone_mode = value;
+        self
+    }
+
+    pub fn mono_safe_effects(mut self, value: bool) -> Self {
+        self.settings.mono_safe_effects = value;
+        self
+    }
+
+    /// Validates the settings and returns them.
+    pub fn build(self) -> Result<SynthesizerSettings, SynthesizerError> {
+        self.settings.validate()?;
+        Ok(self.settings)
+    }
+}
+
+impl Default for SynthesizerSettingsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
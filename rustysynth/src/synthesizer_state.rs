@@ -0,0 +1,35 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::channel::ChannelState;
+use crate::voice_collection::VoiceCollection;
+
+/// The MIDI state of a synthesizer, saved by `Synthesizer::save_state`.
+///
+/// This holds the program, the controllers, the pitch bend, and the parameters set by the RPNs
+/// and the NRPNs of each channel, and the master volume.
+/// The sounding voices are included only if asked for, and they are not serialized,
+/// as they refer to the SoundFonts loaded in the synthesizer.
+/// With the `serde` feature, the state can be serialized for a session file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct SynthesizerState {
+    pub(crate) channels: Vec<ChannelState>,
+    pub(crate) master_volume: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) voices: Option<VoiceCollection>,
+}
+
+impl SynthesizerState {
+    /// Gets the number of the channels in the state.
+    pub fn get_channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns `true` if the state includes the sounding voices.
+    pub fn has_voices(&self) -> bool {
+        self.voices.is_some()
+    }
+}
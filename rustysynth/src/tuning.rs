@@ -0,0 +1,333 @@
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+
+use crate::error::TuningError;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+
+/// Maps each key number to the pitch to play, expressed as a fractional key number
+/// in 12-tone equal temperament (e.g. 69.0 is 440 Hz).
+///
+/// A tuning is built from Scala files by `from_scala`, or key by key with `set_pitch`,
+/// and is given to a channel by `Synthesizer::set_tuning`.
+/// The tunings sent by the MIDI Tuning Standard messages are kept by the synthesizer.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Tuning {
+    pitches: [Option<f32>; 128],
+}
+
+impl Tuning {
+    // Without a keyboard mapping, the first degree of the scale is mapped to the middle C,
+    // which keeps the pitch of 12-tone equal temperament.
+    const DEFAULT_MIDDLE_KEY: i32 = 60;
+    const DEFAULT_REFERENCE_KEY: i32 = 60;
+    const DEFAULT_REFERENCE_FREQUENCY: f64 = 261.625_565_300_598_6;
+
+    /// Creates the tuning of 12-tone equal temperament, where each key plays its own pitch.
+    pub fn equal_temperament() -> Self {
+        let mut pitches: [Option<f32>; 128] = [None; 128];
+        for (key, pitch) in pitches.iter_mut().enumerate() {
+            *pitch = Some(key as f32);
+        }
+
+        Self { pitches }
+    }
+
+    /// Builds a tuning table from the contents of a Scala scale file (.scl)
+    /// and an optional keyboard mapping file (.kbm).
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, TuningError> {
+        let scale = Tuning::parse_scale(scl)?;
+        let mapping = match kbm {
+            Some(kbm) => KeyboardMapping::parse(kbm)?,
+            None => KeyboardMapping::linear(),
+        };
+
+        let reference_cents = match mapping.cents(&scale, mapping.reference_key) {
+            Some(value) => value,
+            None => return Err(TuningError::ReferenceKeyNotMapped),
+        };
+
+        let mut pitches: [Option<f32>; 128] = [None; 128];
+        for (key, pitch) in pitches.iter_mut().enumerate() {
+            let key = key as i32;
+            if key < mapping.first_key || key > mapping.last_key {
+                continue;
+            }
+
+            if let Some(cents) = mapping.cents(&scale, key) {
+                let frequency =
+                    mapping.reference_frequency * 2_f64.powf((cents - reference_cents) / 1200.0);
+                *pitch = Some((69.0 + 12.0 * (frequency / 440.0).log2()) as f32);
+            }
+        }
+
+        Ok(Self { pitches })
+    }
+
+    /// Gets the pitch of the key, or `None` if the key is not mapped.
+    pub fn get_pitch(&self, key: i32) -> Option<f32> {
+        if (0..128).contains(&key) {
+            self.pitches[key as usize]
+        } else {
+            Some(key as f32)
+        }
+    }
+
+    /// Sets the pitch of the key, or `None` to leave the key silent.
+    /// The keys out of the range of 0 to 127 are ignored.
+    pub fn set_pitch(&mut self, key: i32, pitch: Option<f32>) {
+        if (0..128).contains(&key) {
+            self.pitches[key as usize] = pitch;
+        }
+    }
+
+    // Reads the frequency data of the MIDI Tuning Standard, which is the semitone
+    // followed by the 14-bit fraction of it, or None for 7F 7F 7F, which leaves the key unchanged.
+    pub(crate) fn mts_pitch(data: &[u8]) -> Option<f32> {
+        match *data {
+            [0x7F, 0x7F, 0x7F] => None,
+            [semitone, msb, lsb] => {
+                let fraction = ((msb as i32 & 0x7F) << 7) | (lsb as i32 & 0x7F);
+                Some((semitone & 0x7F) as f32 + fraction as f32 / 16384_f32)
+            }
+            _ => None,
+        }
+    }
+
+    // Builds a tuning from the frequency data of the 128 keys of an MTS bulk dump.
+    pub(crate) fn from_mts_data(data: &[u8]) -> Self {
+        let mut tuning = Tuning::equal_temperament();
+        for (key, entry) in data.chunks_exact(3).take(128).enumerate() {
+            if let Some(pitch) = Tuning::mts_pitch(entry) {
+                tuning.pitches[key] = Some(pitch);
+            }
+        }
+
+        tuning
+    }
+
+    // Returns the degrees of the scale in cents.
+    // The first element is always the unison, and the last one is the period of the scale.
+    fn parse_scale(scl: &str) -> Result<Vec<f64>, TuningError> {
+        let mut lines = Tuning::non_comment_lines(scl);
+
+        // The first line is the description, which can be empty.
+        if lines.next().is_none() {
+            return Err(TuningError::InvalidScale { line: 0 });
+        }
+
+        let (line, count) = match lines.next() {
+            Some((line, text)) => match Tuning::first_token(text).parse::<usize>() {
+                Ok(value) if value > 0 => (line, value),
+                _ => return Err(TuningError::InvalidScale { line }),
+            },
+            None => return Err(TuningError::InvalidScale { line: 0 }),
+        };
+
+        let mut degrees: Vec<f64> = vec![0.0];
+        for _i in 0..count {
+            match lines.next() {
+                Some((line, text)) => match Tuning::parse_pitch(Tuning::first_token(text)) {
+                    Some(value) => degrees.push(value),
+                    None => return Err(TuningError::InvalidScale { line }),
+                },
+                None => return Err(TuningError::InvalidScale { line: line + 1 }),
+            }
+        }
+
+        Ok(degrees)
+    }
+
+    // A pitch containing a period is in cents, otherwise it is a ratio such as "3/2" or "2".
+    fn parse_pitch(text: &str) -> Option<f64> {
+        if text.contains('.') {
+            return text.parse::<f64>().ok();
+        }
+
+        let (numerator, denominator) = match text.split_once('/') {
+            Some((n, d)) => (n.parse::<f64>().ok()?, d.parse::<f64>().ok()?),
+            None => (text.parse::<f64>().ok()?, 1.0),
+        };
+
+        if numerator <= 0.0 || denominator <= 0.0 {
+            return None;
+        }
+
+        Some(1200.0 * (numerator / denominator).log2())
+    }
+
+    // Yields the lines which are not comments, along with the 1-based line numbers.
+    fn non_comment_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+        text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.starts_with('!'))
+    }
+
+    fn first_token(text: &str) -> &str {
+        text.split_whitespace().next().unwrap_or("")
+    }
+}
+
+struct KeyboardMapping {
+    first_key: i32,
+    last_key: i32,
+    middle_key: i32,
+    reference_key: i32,
+    reference_frequency: f64,
+    octave_degree: usize,
+    // An empty map means that the keys are mapped linearly onto the degrees.
+    map: Vec<Option<usize>>,
+}
+
+impl KeyboardMapping {
+    fn linear() -> Self {
+        Self {
+            first_key: 0,
+            last_key: 127,
+            middle_key: Tuning::DEFAULT_MIDDLE_KEY,
+            reference_key: Tuning::DEFAULT_REFERENCE_KEY,
+            reference_frequency: Tuning::DEFAULT_REFERENCE_FREQUENCY,
+            octave_degree: 0,
+            map: Vec::new(),
+        }
+    }
+
+    fn parse(kbm: &str) -> Result<Self, TuningError> {
+        let mut lines = Tuning::non_comment_lines(kbm).filter(|(_, line)| !line.is_empty());
+        let mut next_token = || -> Result<(usize, &str), TuningError> {
+            match lines.next() {
+                Some((line, text)) => Ok((line, Tuning::first_token(text))),
+                None => Err(TuningError::InvalidKeyboardMapping { line: 0 }),
+            }
+        };
+
+        let mut values: [i32; 5] = [0; 5];
+        for value in values.iter_mut() {
+            let (line, token) = next_token()?;
+            *value = token
+                .parse::<i32>()
+                .map_err(|_| TuningError::InvalidKeyboardMapping { line })?;
+        }
+        let [size, first_key, last_key, middle_key, reference_key] = values;
+
+        let (line, token) = next_token()?;
+        let reference_frequency = match token.parse::<f64>() {
+            Ok(value) if value > 0.0 => value,
+            _ => return Err(TuningError::InvalidKeyboardMapping { line }),
+        };
+
+        let (line, token) = next_token()?;
+        let octave_degree = token
+            .parse::<usize>()
+            .map_err(|_| TuningError::InvalidKeyboardMapping { line })?;
+
+        if size < 0 || !(0..128).contains(&reference_key) {
+            return Err(TuningError::InvalidKeyboardMapping { line: 0 });
+        }
+
+        let mut map: Vec<Option<usize>> = Vec::new();
+        for _i in 0..size {
+            // Missing entries at the end of the file are regarded as unmapped.
+            let entry = match next_token() {
+                Ok((_, "x")) | Err(_) => None,
+                Ok((line, token)) => Some(
+                    token
+                        .parse::<usize>()
+                        .map_err(|_| TuningError::InvalidKeyboardMapping { line })?,
+                ),
+            };
+            map.push(entry);
+        }
+
+        Ok(Self {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            map,
+        })
+    }
+
+    // Computes the pitch of the key in cents, relative to the first degree at the middle key.
+    fn cents(&self, degrees: &[f64], key: i32) -> Option<f64> {
+        let count = (degrees.len() - 1) as i32;
+        let offset = key - self.middle_key;
+
+        if self.map.is_empty() {
+            return Some(KeyboardMapping::degree_cents(degrees, offset));
+        }
+
+        let size = self.map.len() as i32;
+        let octave = offset.div_euclid(size);
+        let degree = self.map[offset.rem_euclid(size) as usize]? as i32;
+
+        let octave_cents = if self.octave_degree == 0 {
+            degrees[count as usize]
+        } else {
+            KeyboardMapping::degree_cents(degrees, self.octave_degree as i32)
+        };
+
+        Some(octave as f64 * octave_cents + KeyboardMapping::degree_cents(degrees, degree))
+    }
+
+    fn degree_cents(degrees: &[f64], degree: i32) -> f64 {
+        let count = (degrees.len() - 1) as i32;
+        let period = degrees[count as usize];
+        degree.div_euclid(count) as f64 * period + degrees[degree.rem_euclid(count) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWELVE_TET: &str = "! 12tet.scl\n12-tone equal temperament\n 12\n!\n 100.0\n 200.0\n 300.0\n 400.0\n 500.0\n 600.0\n 700.0\n 800.0\n 900.0\n 1000.0\n 1100.0\n 2/1\n";
+
+    #[test]
+    fn test_scala_equal_temperament() {
+        let tuning = Tuning::from_scala(TWELVE_TET, None).unwrap();
+        for key in 0..128 {
+            let pitch = tuning.get_pitch(key).unwrap();
+            assert!((pitch - key as f32).abs() < 1.0E-3, "{key}: {pitch}");
+        }
+    }
+
+    #[test]
+    fn test_scala_keyboard_mapping() {
+        // A whole-tone scale mapped onto the white keys, with A4 at 432 Hz.
+        let scl = "whole tone\n6\n200.\n400.\n600.\n800.\n1000.\n2/1\n";
+        let kbm = "12\n0\n127\n60\n69\n432.0\n6\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\nx\n";
+        let tuning = Tuning::from_scala(scl, Some(kbm)).unwrap();
+
+        let a4 = 69.0 + 12.0 * (432_f32 / 440_f32).log2();
+        assert!((tuning.get_pitch(69).unwrap() - a4).abs() < 1.0E-3);
+        assert!((tuning.get_pitch(81).unwrap() - (a4 + 12.0)).abs() < 1.0E-3);
+        assert!(tuning.get_pitch(61).is_none());
+    }
+
+    #[test]
+    fn test_mts_data() {
+        // Key 0 a quarter tone up, key 1 unchanged, and key 2 at the pitch of key 69.
+        let mut data: Vec<u8> = vec![0x00, 0x40, 0x00, 0x7F, 0x7F, 0x7F, 0x45, 0x00, 0x00];
+        data.resize(384, 0x7F);
+        let tuning = Tuning::from_mts_data(&data);
+
+        assert_eq!(tuning.get_pitch(0), Some(0.5));
+        assert_eq!(tuning.get_pitch(1), Some(1.0));
+        assert_eq!(tuning.get_pitch(2), Some(69.0));
+        assert_eq!(tuning.get_pitch(127), Some(127.0));
+    }
+
+    #[test]
+    fn test_scala_invalid() {
+        assert!(matches!(
+            Tuning::from_scala("broken\n3\n100.0\nfoo\n", None),
+            Err(TuningError::InvalidScale { line: 4 })
+        ));
+    }
+}
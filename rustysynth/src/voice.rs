@@ -1,16 +1,29 @@
 #![allow(dead_code)]
 
-use std::f32::consts;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::f32::consts;
+use core::mem;
 
 use crate::bi_quad_filter::BiQuadFilter;
 use crate::channel::Channel;
+#[cfg(not(feature = "std"))]
+use crate::float_math::FloatMath;
+use crate::generator_type::GeneratorType;
 use crate::lfo::Lfo;
 use crate::modulation_envelope::ModulationEnvelope;
+use crate::modulator::Modulator;
 use crate::oscillator::Oscillator;
+use crate::pan_curve::PanCurve;
 use crate::region_ex::RegionEx;
 use crate::region_pair::RegionPair;
+use crate::soundfont::SoundFont;
 use crate::soundfont_math::SoundFontMath;
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
+#[cfg(feature = "introspection")]
+use crate::voice_info::VoiceInfo;
+use crate::volume_curve::VolumeCurve;
 use crate::volume_envelope::VolumeEnvelope;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,6 +48,11 @@ pub(crate) struct Voice {
 
     block: Vec<f32>,
 
+    volume_curve: VolumeCurve,
+    pan_curve: PanCurve,
+    smooth_controllers: bool,
+    honor_effect_sends: bool,
+
     // A sudden change in the mix gain will cause pop noise.
     // To avoid this, we save the mix gain of the previous block,
     // and smooth out the gain if the gap between the current and previous gain is too large.
@@ -50,10 +68,22 @@ pub(crate) struct Voice {
     pub(crate) current_chorus_send: f32,
 
     exclusive_class: i32,
+    sample_id: usize,
+    // The SoundFont whose wave data is played, which is kept while the voice sounds
+    // even if the synthesizer has moved on to another one.
+    sound_font: Option<Arc<SoundFont>>,
+    // The linear gain of the SoundFont in the synthesizer, applied to the mix and the effect sends.
+    pub(crate) sound_font_gain: f32,
     channel: i32,
     key: i32,
+    pitch: f32,
     velocity: i32,
 
+    previous_pitch: f32,
+    // The offset of the pitch in semitones, which moves linearly to zero for the portamento.
+    portamento: f32,
+    portamento_step: f32,
+
     note_gain: f32,
 
     cutoff: f32,
@@ -74,11 +104,32 @@ pub(crate) struct Voice {
     instrument_reverb: f32,
     instrument_chorus: f32,
 
+    // The modulators of the SoundFont, and their values at the note-on, which are already applied.
+    // While a controller moves, the difference from those is applied to the realtime parameters.
+    // The default modulators are only evaluated once their controllers leave their defaults,
+    // so that most voices skip the evaluation.
+    modulators: Vec<Modulator>,
+    initial_modulation: [f32; GeneratorType::COUNT],
+    realtime_modulation: bool,
+    default_modulation: bool,
+
     // Some instruments require fast cutoff change, which can cause pop noise.
     // This is used to smooth out the cutoff frequency.
     smoothed_cutoff: f32,
 
     voice_state: VoiceState,
+    // A frozen voice ignores the note-off and holds its envelope.
+    frozen: bool,
+    // Set if the key was down when the sostenuto was pressed, which holds the voice until it is lifted.
+    sostenuto: bool,
+    release_scale: f32,
+    // The samples until the voice releases itself, for the one-shot notes.
+    release_countdown: Option<usize>,
+    // The offset within the first block where the voice starts, for the messages scheduled within a block.
+    start_delay: usize,
+    // The gain decreasing linearly to fade out the voice, e.g. on program changes.
+    fade_gain: f32,
+    fade_step: f32,
     /// Time elapsed in samples
     voice_length: usize,
     min_voice_length: usize,
@@ -94,6 +145,10 @@ impl Voice {
             oscillator: Oscillator::new(settings),
             filter: BiQuadFilter::new(settings),
             block: vec![0_f32; settings.block_size],
+            volume_curve: settings.volume_curve,
+            pan_curve: settings.pan_curve,
+            smooth_controllers: settings.smooth_controllers,
+            honor_effect_sends: settings.honor_soundfont_effect_sends,
             previous_mix_gain_left: 0_f32,
             previous_mix_gain_right: 0_f32,
             current_mix_gain_left: 0_f32,
@@ -103,9 +158,16 @@ impl Voice {
             current_reverb_send: 0_f32,
             current_chorus_send: 0_f32,
             exclusive_class: 0,
+            sample_id: 0,
+            sound_font: None,
+            sound_font_gain: 1_f32,
             channel: 0,
             key: 0,
+            pitch: 0_f32,
             velocity: 0,
+            previous_pitch: 0_f32,
+            portamento: 0_f32,
+            portamento_step: 0_f32,
             note_gain: 0_f32,
             cutoff: 0_f32,
             resonance: 0_f32,
@@ -120,18 +182,68 @@ impl Voice {
             instrument_pan: 0_f32,
             instrument_reverb: 0_f32,
             instrument_chorus: 0_f32,
+            modulators: Vec::new(),
+            initial_modulation: [0_f32; GeneratorType::COUNT],
+            realtime_modulation: false,
+            default_modulation: false,
             smoothed_cutoff: 0_f32,
             voice_state: VoiceState::Playing,
+            frozen: false,
+            sostenuto: false,
+            release_scale: 1_f32,
+            release_countdown: None,
+            start_delay: 0,
+            fade_gain: 1_f32,
+            fade_step: 0_f32,
             voice_length: 0,
             min_voice_length: (settings.sample_rate / 500) as usize,
         }
     }
 
-    pub(crate) fn start(&mut self, region: &RegionPair, channel: i32, key: i32, velocity: i32) {
+    pub(crate) fn start(
+        &mut self,
+        region: &RegionPair,
+        channel_info: &Channel,
+        channel: i32,
+        key: i32,
+        pitch: f32,
+        velocity: i32,
+    ) {
         self.exclusive_class = region.get_exclusive_class();
+        self.sample_id = region.instrument.get_sample_id();
         self.channel = channel;
         self.key = key;
+        self.pitch = pitch;
         self.velocity = velocity;
+        self.portamento = 0_f32;
+        self.portamento_step = 0_f32;
+
+        Modulator::collect(
+            &region.preset.modulators,
+            &region.instrument.modulators,
+            &mut self.modulators,
+        );
+        let file_modulators = Modulator::EVALUATED_DEFAULTS.len()..self.modulators.len();
+        if channel_info.get_mpe_master().is_some() {
+            self.modulators.extend_from_slice(&Modulator::MPE_DEFAULTS);
+        }
+        self.initial_modulation =
+            Modulator::evaluate(&self.modulators, channel_info, key, velocity);
+        self.realtime_modulation = self.modulators[file_modulators.clone()]
+            .iter()
+            .any(Modulator::is_realtime);
+        self.default_modulation = !Modulator::defaults_at_rest(channel_info);
+        // The NRPNs of the channel are added to the generators as the modulators.
+        let mut offsets = self.initial_modulation;
+        for (offset, nrpn) in offsets.iter_mut().zip(channel_info.get_nrpn_offsets()) {
+            *offset += *nrpn as f32;
+        }
+        let region = &region.with_modulation(&offsets);
+        let realtime = |destination: u16| {
+            self.modulators[file_modulators.clone()]
+                .iter()
+                .any(|modulator| modulator.is_realtime() && modulator.destination == destination)
+        };
 
         if velocity > 0 {
             // According to the Polyphone's implementation, the initial attenuation should be reduced to 40%.
@@ -146,7 +258,11 @@ impl Voice {
             self.note_gain = 0_f32;
         }
 
-        self.cutoff = region.get_initial_filter_cutoff_frequency();
+        // The host-side filter tracking, in cents per key from the middle C and per velocity from 64.
+        let tracking_cents = channel_info.get_filter_key_tracking() * (key - 60) as f32
+            + channel_info.get_filter_velocity_tracking() * (velocity - 64) as f32;
+        self.cutoff = region.get_initial_filter_cutoff_frequency()
+            * SoundFontMath::cents_to_multiplying_factor(tracking_cents);
         self.resonance = SoundFontMath::decibels_to_linear(region.get_initial_filter_q());
 
         self.vib_lfo_to_pitch = 0.01_f32 * region.get_vibrato_lfo_to_pitch() as f32;
@@ -155,18 +271,31 @@ impl Voice {
 
         self.mod_lfo_to_cutoff = region.get_modulation_lfo_to_filter_cutoff_frequency();
         self.mod_env_to_cutoff = region.get_modulation_envelope_to_filter_cutoff_frequency();
-        self.dynamic_cutoff = self.mod_lfo_to_cutoff != 0 || self.mod_env_to_cutoff != 0;
+        self.dynamic_cutoff = self.mod_lfo_to_cutoff != 0
+            || self.mod_env_to_cutoff != 0
+            || realtime(GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY)
+            || realtime(GeneratorType::INITIAL_FILTER_Q)
+            || realtime(GeneratorType::MODULATION_LFO_TO_FILTER_CUTOFF_FREQUENCY)
+            || realtime(GeneratorType::MODULATION_ENVELOPE_TO_FILTER_CUTOFF_FREQUENCY);
 
         self.mod_lfo_to_volume = region.get_modulation_lfo_to_volume();
-        self.dynamic_volume = self.mod_lfo_to_volume > 0.05_f32;
-
-        self.instrument_pan = SoundFontMath::clamp(region.get_pan(), -50_f32, 50_f32);
-        self.instrument_reverb = 0.01_f32 * region.get_reverb_effects_send();
-        self.instrument_chorus = 0.01_f32 * region.get_chorus_effects_send();
+        self.dynamic_volume =
+            self.mod_lfo_to_volume > 0.05_f32 || realtime(GeneratorType::MODULATION_LFO_TO_VOLUME);
+
+        // The halves of a linked stereo sample are placed at their sides, and the pan moves them from there.
+        self.instrument_pan =
+            SoundFontMath::clamp(region.get_pan() + region.stereo_pan, -50_f32, 50_f32);
+        if self.honor_effect_sends {
+            self.instrument_reverb = 0.01_f32 * region.get_reverb_effects_send();
+            self.instrument_chorus = 0.01_f32 * region.get_chorus_effects_send();
+        } else {
+            self.instrument_reverb = 0_f32;
+            self.instrument_chorus = 0_f32;
+        }
 
-        RegionEx::start_volume_envelope(&mut self.vol_env, region, key, velocity);
+        RegionEx::start_volume_envelope(&mut self.vol_env, region, channel_info, key, velocity);
         RegionEx::start_modulation_envelope(&mut self.mod_env, region, key, velocity);
-        RegionEx::start_vibrato(&mut self.vib_lfo, region, key, velocity);
+        RegionEx::start_vibrato(&mut self.vib_lfo, region, channel_info, key, velocity);
         RegionEx::start_modulation(&mut self.mod_lfo, region, key, velocity);
         RegionEx::start_oscillator(&mut self.oscillator, region);
         self.filter.clear_buffer();
@@ -175,49 +304,196 @@ impl Voice {
         self.smoothed_cutoff = self.cutoff;
 
         self.voice_state = VoiceState::Playing;
+        self.frozen = false;
+        self.sostenuto = false;
+        self.release_scale = 1_f32;
+        self.release_countdown = None;
+        self.start_delay = 0;
+        self.fade_gain = 1_f32;
+        self.fade_step = 0_f32;
         self.voice_length = 0;
     }
 
+    // Makes the voice end by itself after the samples, as if a note-off was received.
+    pub(crate) fn set_release_countdown(&mut self, sample_count: usize) {
+        self.release_countdown = Some(sample_count);
+    }
+
+    // Makes the voice glide from the pitch to its own pitch over the blocks.
+    pub(crate) fn set_portamento(&mut self, from_pitch: f32, block_count: f32) {
+        self.portamento = from_pitch - self.pitch;
+        self.portamento_step = self.portamento.abs() / block_count.max(1_f32);
+    }
+
+    pub(crate) fn set_sound_font(&mut self, sound_font: &Arc<SoundFont>, gain: f32) {
+        self.sound_font = Some(Arc::clone(sound_font));
+        self.sound_font_gain = gain;
+    }
+
+    // Makes the first block of the voice silent up to the offset, where the sample starts.
+    pub(crate) fn set_start_delay(&mut self, offset: usize) {
+        self.start_delay = offset.min(self.block.len());
+    }
+
     pub(crate) fn end(&mut self) {
-        if self.voice_state == VoiceState::Playing {
+        if self.voice_state == VoiceState::Playing && !self.frozen {
             self.voice_state = VoiceState::ReleaseRequested;
         }
     }
 
+    // The release time is multiplied by the scale when the release starts.
+    pub(crate) fn end_with_release_scale(&mut self, scale: f32) {
+        if self.voice_state == VoiceState::Playing && !self.frozen {
+            self.release_scale = scale;
+        }
+        self.end();
+    }
+
+    pub(crate) fn is_releasing(&self) -> bool {
+        self.voice_state != VoiceState::Playing
+    }
+
+    // Fades out the voice to silence over the given number of samples.
+    pub(crate) fn fade_out(&mut self, sample_count: usize) {
+        let step = self.fade_gain / sample_count.max(1) as f32;
+        self.fade_step = self.fade_step.max(step);
+    }
+
+    // Captures the voice by the sostenuto if its key is still down.
+    pub(crate) fn capture_sostenuto(&mut self) {
+        self.sostenuto = self.voice_state == VoiceState::Playing;
+    }
+
+    pub(crate) fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    // The voice starts releasing, as the note-off may have been ignored while frozen.
+    pub(crate) fn unfreeze(&mut self) {
+        if self.frozen {
+            self.frozen = false;
+            self.end();
+        }
+    }
+
+    // Plays the sample from the playback position of the other voice, which plays the same sample.
+    pub(crate) fn continue_phase_from(&mut self, other: &Voice) {
+        self.oscillator.continue_from(&other.oscillator);
+    }
+
     pub(crate) fn kill(&mut self) {
         self.note_gain = 0_f32;
     }
 
-    pub(crate) fn process(&mut self, data: &[i16], channels: &[Channel]) -> bool {
+    pub(crate) fn process(&mut self, channels: &[Channel]) -> bool {
+        let playing = self.render(channels);
+        // An ended voice lets go of its SoundFont, which the synthesizer keeps if it has been replaced,
+        // so that it is never freed here.
+        if !playing {
+            self.sound_font = None;
+        }
+        playing
+    }
+
+    fn render(&mut self, channels: &[Channel]) -> bool {
         if self.note_gain < SoundFontMath::NON_AUDIBLE {
             return false;
         }
 
         let channel_info = &channels[self.channel as usize];
 
-        self.release_if_necessary(channel_info);
+        if !self.frozen {
+            if let Some(remaining) = self.release_countdown {
+                if remaining <= self.block.len() {
+                    self.release_countdown = None;
+                    self.end();
+                } else {
+                    self.release_countdown = Some(remaining - self.block.len());
+                }
+            }
 
-        if !self.vol_env.process(self.block.len()) {
-            return false;
-        }
+            self.release_if_necessary(channel_info);
+
+            if !self.vol_env.process(self.block.len()) {
+                return false;
+            }
 
-        self.mod_env.process(self.block.len());
+            self.mod_env.process(self.block.len());
+        }
         self.vib_lfo.process();
         self.mod_lfo.process();
 
-        let vib_pitch_change = (0.01_f32 * channel_info.get_modulation() + self.vib_lfo_to_pitch)
+        if !self.default_modulation && !Modulator::defaults_at_rest(channel_info) {
+            self.default_modulation = true;
+        }
+        let modulation = self.get_modulation_change(channel_info);
+        let m = |destination: u16| modulation[destination as usize];
+
+        let vib_pitch_change = (0.01_f32
+            * (channel_info.get_modulation() + m(GeneratorType::VIBRATO_LFO_TO_PITCH))
+            + self.vib_lfo_to_pitch)
             * self.vib_lfo.get_value();
-        let mod_pitch_change = self.mod_lfo_to_pitch * self.mod_lfo.get_value()
-            + self.mod_env_to_pitch * self.mod_env.get_value();
-        let channel_pitch_change = channel_info.get_tune() + channel_info.get_pitch_bend();
-        let pitch = self.key as f32 + vib_pitch_change + mod_pitch_change + channel_pitch_change;
-        if !self.oscillator.process(data, &mut self.block[..], pitch) {
+        let mod_pitch_change = (self.mod_lfo_to_pitch
+            + 0.01_f32 * m(GeneratorType::MODULATION_LFO_TO_PITCH))
+            * self.mod_lfo.get_value()
+            + (self.mod_env_to_pitch + 0.01_f32 * m(GeneratorType::MODULATION_ENVELOPE_TO_PITCH))
+                * self.mod_env.get_value();
+        let mut channel_pitch_change = channel_info.get_tune()
+            + channel_info.get_pitch_bend()
+            + channel_info.get_key_tune(self.key);
+        // The pitch bend of the master channel of an MPE zone applies to all its member channels.
+        if let Some(master) = channel_info.get_mpe_master() {
+            channel_pitch_change += channels[master].get_pitch_bend();
+        }
+        let modulation_pitch_change = m(GeneratorType::COARSE_TUNE)
+            + 0.01_f32 * (m(GeneratorType::FINE_TUNE) + m(Modulator::INITIAL_PITCH));
+        let pitch = self.pitch
+            + self.portamento
+            + vib_pitch_change
+            + mod_pitch_change
+            + channel_pitch_change
+            + modulation_pitch_change;
+        let start = mem::take(&mut self.start_delay);
+        self.block[..start].fill(0_f32);
+        let data = match self.sound_font.as_ref() {
+            Some(value) => &value.wave_data[..],
+            None => return false,
+        };
+        let filled = if self.smooth_controllers && self.voice_length > 0 {
+            self.oscillator.process_smooth(
+                data,
+                &mut self.block[start..],
+                self.previous_pitch,
+                pitch,
+            )
+        } else {
+            self.oscillator
+                .process(data, &mut self.block[start..], pitch)
+        };
+        self.previous_pitch = pitch;
+        self.portamento -= self
+            .portamento
+            .clamp(-self.portamento_step, self.portamento_step);
+        if !filled {
             return false;
         }
 
-        if self.dynamic_cutoff {
-            let cents = self.mod_lfo_to_cutoff as f32 * self.mod_lfo.get_value()
-                + self.mod_env_to_cutoff as f32 * self.mod_env.get_value();
+        // The offset set by the host can change at any time, and the cutoff follows it back to
+        // the SoundFont's value after it is cleared.
+        let cutoff_offset = channel_info.get_filter_cutoff_offset();
+        if self.dynamic_cutoff
+            || self.default_modulation
+            || cutoff_offset != 0_f32
+            || self.smoothed_cutoff != self.cutoff
+        {
+            let lfo_to_cutoff = self.mod_lfo_to_cutoff as f32
+                + m(GeneratorType::MODULATION_LFO_TO_FILTER_CUTOFF_FREQUENCY);
+            let env_to_cutoff = self.mod_env_to_cutoff as f32
+                + m(GeneratorType::MODULATION_ENVELOPE_TO_FILTER_CUTOFF_FREQUENCY);
+            let cents = lfo_to_cutoff * self.mod_lfo.get_value()
+                + env_to_cutoff * self.mod_env.get_value()
+                + m(GeneratorType::INITIAL_FILTER_CUTOFF_FREQUENCY)
+                + cutoff_offset;
             let factor = SoundFontMath::cents_to_multiplying_factor(cents);
             let new_cutoff = factor * self.cutoff;
 
@@ -226,8 +502,10 @@ impl Voice {
             let upper_limit = 2_f32 * self.smoothed_cutoff;
             self.smoothed_cutoff = SoundFontMath::clamp(new_cutoff, lower_limit, upper_limit);
 
+            let resonance = self.resonance
+                * SoundFontMath::decibels_to_linear(0.1_f32 * m(GeneratorType::INITIAL_FILTER_Q));
             self.filter
-                .set_low_pass_filter(self.smoothed_cutoff, self.resonance);
+                .set_low_pass_filter(self.smoothed_cutoff, resonance.max(1_f32));
         }
         self.filter.process(&mut self.block[..]);
 
@@ -236,18 +514,45 @@ impl Voice {
         self.previous_reverb_send = self.current_reverb_send;
         self.previous_chorus_send = self.current_chorus_send;
 
-        // According to the GM spec, the following value should be squared.
+        // According to the GM2 spec, the following value should be squared,
+        // which gives an attenuation of 40 * log10(value) decibels.
         let ve = channel_info.get_volume() * channel_info.get_expression();
-        let channel_gain = ve * ve;
+        // The gain set by the host is a fader after the volume and expression of the MIDI data.
+        let channel_gain = match self.volume_curve {
+            VolumeCurve::Gm2 => ve * ve,
+            VolumeCurve::Linear => ve,
+        } * channel_info.get_gain()
+            * channel_info.get_key_gain(self.key);
+
+        if self.fade_step > 0_f32 {
+            self.fade_gain = (self.fade_gain - self.fade_step * self.block.len() as f32).max(0_f32);
+            if self.fade_gain == 0_f32 {
+                // Let the gain reach zero in this block, then the voice stops.
+                self.note_gain = 0_f32;
+            }
+        }
 
-        let mut mix_gain = self.note_gain * channel_gain * self.vol_env.get_value();
+        let mut mix_gain =
+            self.note_gain * channel_gain * self.vol_env.get_value() * self.fade_gain;
         if self.dynamic_volume {
-            let decibels = self.mod_lfo_to_volume * self.mod_lfo.get_value();
+            let decibels = (self.mod_lfo_to_volume
+                + 0.1_f32 * m(GeneratorType::MODULATION_LFO_TO_VOLUME))
+                * self.mod_lfo.get_value();
+            mix_gain *= SoundFontMath::decibels_to_linear(decibels);
+        }
+        if self.is_modulated() {
+            // The initial attenuation is reduced to 40% as in the start.
+            let decibels = -0.04_f32 * m(GeneratorType::INITIAL_ATTENUATION);
             mix_gain *= SoundFontMath::decibels_to_linear(decibels);
         }
 
-        let angle =
-            (consts::PI / 200_f32) * (channel_info.get_pan() + self.instrument_pan + 50_f32);
+        let instrument_pan = SoundFontMath::clamp(
+            self.instrument_pan + 0.1_f32 * m(GeneratorType::PAN),
+            -50_f32,
+            50_f32,
+        );
+        let angle = (consts::PI / 200_f32)
+            * (channel_info.get_pan(self.pan_curve) + instrument_pan + 50_f32);
         if angle <= 0_f32 {
             self.current_mix_gain_left = mix_gain;
             self.current_mix_gain_right = 0_f32;
@@ -259,13 +564,21 @@ impl Voice {
             self.current_mix_gain_right = mix_gain * angle.sin();
         }
 
+        let (instrument_reverb, instrument_chorus) = if self.honor_effect_sends {
+            (
+                self.instrument_reverb + 0.001_f32 * m(GeneratorType::REVERB_EFFECTS_SEND),
+                self.instrument_chorus + 0.001_f32 * m(GeneratorType::CHORUS_EFFECTS_SEND),
+            )
+        } else {
+            (0_f32, 0_f32)
+        };
         self.current_reverb_send = SoundFontMath::clamp(
-            channel_info.get_reverb_send() + self.instrument_reverb,
+            channel_info.get_reverb_send() + instrument_reverb,
             0_f32,
             1_f32,
         );
         self.current_chorus_send = SoundFontMath::clamp(
-            channel_info.get_chorus_send() + self.instrument_chorus,
+            channel_info.get_chorus_send() + instrument_chorus,
             0_f32,
             1_f32,
         );
@@ -282,12 +595,35 @@ impl Voice {
         true
     }
 
+    // Gets the change of the modulators since the note-on, for each generator.
+    fn get_modulation_change(&self, channel_info: &Channel) -> [f32; GeneratorType::COUNT] {
+        if !self.is_modulated() {
+            return [0_f32; GeneratorType::COUNT];
+        }
+
+        let mut values =
+            Modulator::evaluate(&self.modulators, channel_info, self.key, self.velocity);
+        for (value, initial) in values.iter_mut().zip(&self.initial_modulation) {
+            *value -= initial;
+        }
+        values
+    }
+
     fn release_if_necessary(&mut self, channel_info: &Channel) {
         if self.voice_length < self.min_voice_length {
             return;
         }
 
-        if self.voice_state == VoiceState::ReleaseRequested && !channel_info.get_hold_pedal() {
+        let held =
+            channel_info.get_hold_pedal() || (self.sostenuto && channel_info.get_sostenuto());
+        if self.voice_state == VoiceState::ReleaseRequested && !held {
+            // The release time follows the modulators up to the release, such as the CC 72.
+            let timecents = self.get_modulation_change(channel_info)
+                [GeneratorType::RELEASE_VOLUME_ENVELOPE as usize];
+            let release_scale = self.release_scale * SoundFontMath::timecents_to_seconds(timecents);
+            if release_scale != 1_f32 {
+                self.vol_env.scale_release(release_scale);
+            }
             self.vol_env.release();
             self.mod_env.release();
             self.oscillator.release();
@@ -296,10 +632,35 @@ impl Voice {
         }
     }
 
+    #[cfg(feature = "introspection")]
+    pub(crate) fn info(&self, channel_info: &Channel) -> VoiceInfo {
+        let pan = SoundFontMath::clamp(
+            channel_info.get_pan(self.pan_curve) + self.instrument_pan,
+            -50_f32,
+            50_f32,
+        );
+        VoiceInfo {
+            channel: self.channel,
+            key: self.key,
+            velocity: self.velocity,
+            gain: self
+                .current_mix_gain_left
+                .hypot(self.current_mix_gain_right),
+            pan: pan / 50_f32,
+            cutoff: self.smoothed_cutoff,
+            stage: self.vol_env.get_stage(),
+        }
+    }
+
     pub(crate) fn block(&self) -> &[f32] {
         &self.block
     }
 
+    // Whether the modulators are evaluated in each block.
+    pub(crate) fn is_modulated(&self) -> bool {
+        self.realtime_modulation || self.default_modulation
+    }
+
     pub(crate) fn voice_length(&self) -> usize {
         self.voice_length
     }
@@ -308,6 +669,23 @@ impl Voice {
         self.exclusive_class
     }
 
+    pub(crate) fn sample_id(&self) -> usize {
+        self.sample_id
+    }
+
+    // Makes room for the modulators of a note, so that starting it does not allocate.
+    pub(crate) fn reserve_modulators(&mut self, region_count: usize) {
+        let count = Modulator::MAX_DEFAULT_COUNT + region_count;
+        self.modulators
+            .reserve(count.saturating_sub(self.modulators.len()));
+    }
+
+    pub(crate) fn plays_sound_font(&self, sound_font: &Arc<SoundFont>) -> bool {
+        self.sound_font
+            .as_ref()
+            .is_some_and(|value| Arc::ptr_eq(value, sound_font))
+    }
+
     pub(crate) fn channel(&self) -> i32 {
         self.channel
     }
@@ -316,6 +694,15 @@ impl Voice {
         self.key
     }
 
+    pub(crate) fn velocity(&self) -> i32 {
+        self.velocity
+    }
+
+    /// Returns `true` if the note has not received a note-off yet.
+    pub(crate) fn is_playing(&self) -> bool {
+        self.voice_state == VoiceState::Playing && self.note_gain >= SoundFontMath::NON_AUDIBLE
+    }
+
     pub(crate) fn priority(&self) -> f32 {
         if self.note_gain < SoundFontMath::NON_AUDIBLE {
             0_f32
@@ -323,4 +710,45 @@ impl Voice {
             self.vol_env.get_priority()
         }
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_i32(self.channel);
+        hasher.write_i32(self.key);
+        hasher.write_i32(self.velocity);
+        hasher.write(&[self.voice_state.clone() as u8]);
+        hasher.write_bool(self.frozen);
+        hasher.write_bool(self.sostenuto);
+        hasher.write_usize(self.voice_length);
+
+        self.vol_env.hash_state(hasher);
+        self.mod_env.hash_state(hasher);
+        self.vib_lfo.hash_state(hasher);
+        self.mod_lfo.hash_state(hasher);
+        self.oscillator.hash_state(hasher);
+        self.filter.hash_state(hasher);
+
+        for value in [
+            self.pitch,
+            self.previous_pitch,
+            self.portamento,
+            self.portamento_step,
+            self.smoothed_cutoff,
+            self.previous_mix_gain_left,
+            self.previous_mix_gain_right,
+            self.current_mix_gain_left,
+            self.current_mix_gain_right,
+            self.previous_reverb_send,
+            self.previous_chorus_send,
+            self.current_reverb_send,
+            self.current_chorus_send,
+            self.fade_gain,
+            self.fade_step,
+            self.release_scale,
+        ] {
+            hasher.write_f32(value);
+        }
+        hasher.write_usize(self.release_countdown.unwrap_or(usize::MAX));
+        hasher.write_usize(self.start_delay);
+        hasher.write_f32_slice(&self.block);
+    }
 }
@@ -1,18 +1,30 @@
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::thread;
+
 use crate::channel::Channel;
 use crate::instrument_region::InstrumentRegion;
 use crate::synthesizer_settings::SynthesizerSettings;
 use crate::voice::Voice;
+use crate::voice_stealing_policy::VoiceStealingPolicy;
 
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub(crate) struct VoiceCollection {
     voices: Vec<Voice>,
     pub(crate) active_voice_count: usize,
+    stealing_policy: VoiceStealingPolicy,
+    thread_count: usize,
+    // Whether each voice is still playing after the block, written by the render threads.
+    playing: Vec<bool>,
 }
 
 impl VoiceCollection {
+    // Starting the threads for each block only pays off with some voices for each of them.
+    const MINIMUM_VOICES_PER_THREAD: usize = 8;
+
     pub(crate) fn new(settings: &SynthesizerSettings) -> Self {
         let mut voices: Vec<Voice> = Vec::new();
         for _i in 0..settings.maximum_polyphony {
@@ -22,26 +34,50 @@ impl VoiceCollection {
         Self {
             voices,
             active_voice_count: 0,
+            stealing_policy: settings.voice_stealing_policy,
+            thread_count: settings.render_thread_count,
+            playing: vec![false; settings.maximum_polyphony],
         }
     }
 
+    // The voice limit of the channel, if any, makes a note on a channel at its limit reuse one of its own voices.
     pub(crate) fn request_new(
         &mut self,
         region: &InstrumentRegion,
         channel: i32,
+        key: i32,
+        voice_limit: Option<usize>,
     ) -> Option<&mut Voice> {
         // If an exclusive class is assigned to the region, find a voice with the same class.
         // If found, reuse it to avoid playing multiple voices with the same class at a time.
+        // The voices just started by the same note, such as the other half of a stereo sample, are kept.
         let exclusive_class = region.get_exclusive_class();
         if exclusive_class != 0 {
             for i in 0..self.active_voice_count {
                 let voice = &self.voices[i];
-                if voice.exclusive_class() == exclusive_class && voice.channel() == channel {
+                let same_note = voice.key() == key && voice.voice_length() == 0;
+                if voice.exclusive_class() == exclusive_class
+                    && voice.channel() == channel
+                    && !same_note
+                {
                     return Some(&mut self.voices[i]);
                 }
             }
         }
 
+        // If the channel has used up its voices, reuse one of them.
+        if let Some(limit) = voice_limit {
+            let count = self
+                .get_active_voices_ref()
+                .iter()
+                .filter(|voice| voice.channel() == channel)
+                .count();
+            if count >= limit {
+                let candidate = self.find_candidate(channel, key, true)?;
+                return Some(&mut self.voices[candidate]);
+            }
+        }
+
         // If the number of active voices is less than the limit, use a free one.
         if (self.active_voice_count) < self.voices.len() {
             let i = self.active_voice_count;
@@ -50,27 +86,111 @@ impl VoiceCollection {
         }
 
         // Too many active voices...
-        // Find one which has the lowest priority.
-        let mut candidate: usize = 0;
+        let candidate = self.find_candidate(channel, key, false)?;
+        Some(&mut self.voices[candidate])
+    }
+
+    // Finds the voice to reuse with the stealing policy, among those of the channel if `same_channel` is true.
+    fn find_candidate(&self, channel: i32, key: i32, same_channel: bool) -> Option<usize> {
+        // The voices just started by the same note, such as the other half of a stereo sample, are kept.
+        let candidates = (0..self.active_voice_count).filter(|&i| {
+            let voice = &self.voices[i];
+            let same_note = voice.channel() == channel && voice.key() == key;
+            (!same_channel || voice.channel() == channel)
+                && !(same_note && voice.voice_length() == 0)
+        });
+
+        match self.stealing_policy {
+            VoiceStealingPolicy::Quietest => self.find_quietest(candidates),
+            VoiceStealingPolicy::Oldest => {
+                candidates.max_by_key(|&i| self.voices[i].voice_length())
+            }
+            VoiceStealingPolicy::SameNoteFirst => {
+                let same_note = candidates
+                    .clone()
+                    .filter(|&i| self.voices[i].channel() == channel && self.voices[i].key() == key)
+                    .max_by_key(|&i| self.voices[i].voice_length());
+                same_note.or_else(|| self.find_quietest(candidates))
+            }
+            VoiceStealingPolicy::None => None,
+        }
+    }
+
+    // Finds the one which has the lowest priority.
+    fn find_quietest(&self, candidates: impl Iterator<Item = usize>) -> Option<usize> {
+        let mut candidate: Option<usize> = None;
         let mut lowest_priority = f32::MAX;
-        for i in 0..self.active_voice_count {
+        for i in candidates {
             let voice = &self.voices[i];
             let priority = voice.priority();
-            if priority < lowest_priority {
-                lowest_priority = priority;
-                candidate = i;
-            } else if priority == lowest_priority {
+            let better = match candidate {
+                None => true,
                 // Same priority...
                 // The older one should be more suitable for reuse.
-                if voice.voice_length() > self.voices[candidate].voice_length() {
-                    candidate = i;
+                Some(c) => {
+                    priority < lowest_priority
+                        || (priority == lowest_priority
+                            && voice.voice_length() > self.voices[c].voice_length())
                 }
+            };
+            if better {
+                lowest_priority = priority;
+                candidate = Some(i);
+            }
+        }
+        candidate
+    }
+
+    // The voices just started for the note continue from the playback position
+    // of an older voice of the same note playing the same sample, which may have been killed but is still active.
+    pub(crate) fn continue_phase(&mut self, channel: i32, key: i32) {
+        for i in 0..self.active_voice_count {
+            let voice = &self.voices[i];
+            if voice.channel() != channel || voice.key() != key || voice.voice_length() != 0 {
+                continue;
+            }
+
+            let sample_id = voice.sample_id();
+            let source = (0..self.active_voice_count).find(|&j| {
+                let other = &self.voices[j];
+                other.channel() == channel
+                    && other.key() == key
+                    && other.voice_length() > 0
+                    && other.sample_id() == sample_id
+            });
+            if let Some(j) = source {
+                let (target, source) = if i < j {
+                    let (head, tail) = self.voices.split_at_mut(j);
+                    (&mut head[i], &tail[0])
+                } else {
+                    let (head, tail) = self.voices.split_at_mut(i);
+                    (&mut tail[0], &head[j])
+                };
+                target.continue_phase_from(source);
+            }
+        }
+    }
+
+    pub(crate) fn kill_note(&mut self, channel: i32, key: i32) {
+        for voice in self.get_active_voices().iter_mut() {
+            if voice.channel() == channel && voice.key() == key {
+                voice.kill();
             }
         }
-        Some(&mut self.voices[candidate])
     }
 
-    pub(crate) fn process(&mut self, data: &[i16], channels: &[Channel]) {
+    pub(crate) fn process(&mut self, channels: &[Channel]) {
+        // The threads cannot be started in a browser or without std,
+        // where the voices are rendered on the calling thread.
+        #[cfg(feature = "std")]
+        if self.thread_count > 1
+            && !cfg!(target_arch = "wasm32")
+            && self.active_voice_count >= 2 * VoiceCollection::MINIMUM_VOICES_PER_THREAD
+        {
+            self.process_parallel(channels);
+            return;
+        }
+
         let mut i: usize = 0;
 
         loop {
@@ -78,7 +198,7 @@ impl VoiceCollection {
                 return;
             }
 
-            if self.voices[i].process(data, channels) {
+            if self.voices[i].process(channels) {
                 i += 1;
             } else {
                 self.active_voice_count -= 1;
@@ -87,11 +207,73 @@ impl VoiceCollection {
         }
     }
 
+    // Renders the voices on the threads, and then removes the ended ones
+    // in the same way as the sequential loop, so that the order of the voices is the same.
+    // The threads are started for each block, so this is only for offline rendering.
+    #[cfg(feature = "std")]
+    fn process_parallel(&mut self, channels: &[Channel]) {
+        let count = self.active_voice_count;
+        let chunk_size = count
+            .div_ceil(self.thread_count)
+            .max(VoiceCollection::MINIMUM_VOICES_PER_THREAD);
+
+        let voices = &mut self.voices[..count];
+        let playing = &mut self.playing[..count];
+        thread::scope(|scope| {
+            let mut chunks = voices
+                .chunks_mut(chunk_size)
+                .zip(playing.chunks_mut(chunk_size));
+            // The first chunk is rendered on the calling thread.
+            let first = chunks.next();
+            for (voices, playing) in chunks {
+                scope.spawn(move || VoiceCollection::process_chunk(voices, playing, channels));
+            }
+            if let Some((voices, playing)) = first {
+                VoiceCollection::process_chunk(voices, playing, channels);
+            }
+        });
+
+        let mut i: usize = 0;
+        while i < self.active_voice_count {
+            if self.playing[i] {
+                i += 1;
+            } else {
+                self.active_voice_count -= 1;
+                self.voices.swap(i, self.active_voice_count);
+                self.playing.swap(i, self.active_voice_count);
+            }
+        }
+    }
+
+    fn process_chunk(voices: &mut [Voice], playing: &mut [bool], channels: &[Channel]) {
+        for (voice, playing) in voices.iter_mut().zip(playing.iter_mut()) {
+            *playing = voice.process(channels);
+        }
+    }
+
     pub(crate) fn get_active_voices(&mut self) -> &mut [Voice] {
         &mut self.voices[0..self.active_voice_count]
     }
 
+    pub(crate) fn get_active_voices_ref(&self) -> &[Voice] {
+        &self.voices[0..self.active_voice_count]
+    }
+
+    pub(crate) fn get_stealing_policy(&self) -> VoiceStealingPolicy {
+        self.stealing_policy
+    }
+
+    pub(crate) fn get_thread_count(&self) -> usize {
+        self.thread_count
+    }
+
     pub(crate) fn clear(&mut self) {
         self.active_voice_count = 0;
     }
+
+    pub(crate) fn reserve_modulators(&mut self, region_count: usize) {
+        for voice in self.voices.iter_mut() {
+            voice.reserve_modulators(region_count);
+        }
+    }
 }
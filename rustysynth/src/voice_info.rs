@@ -0,0 +1,18 @@
+use crate::envelope_stage::EnvelopeStage;
+
+/// The state of an active voice, for visualization and debugging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct VoiceInfo {
+    pub channel: i32,
+    pub key: i32,
+    pub velocity: i32,
+    /// The linear gain of the last rendered block, before the master volume.
+    pub gain: f32,
+    /// The pan, from -1 (left) to 1 (right).
+    pub pan: f32,
+    /// The cutoff frequency of the low-pass filter in Hz.
+    pub cutoff: f32,
+    /// The stage of the volume envelope.
+    pub stage: EnvelopeStage,
+}
@@ -0,0 +1,15 @@
+/// Specifies which voice is reused for a new note when no voice is free,
+/// either in the whole synthesizer or on a channel at its voice limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceStealingPolicy {
+    /// The voice with the lowest volume envelope, such as a voice in its release, is reused,
+    /// and the oldest one among the equals. This is the default behavior.
+    Quietest,
+    /// The voice started first is reused, whatever its volume.
+    Oldest,
+    /// A voice playing the same key on the same channel is reused if any,
+    /// and the quietest voice otherwise.
+    SameNoteFirst,
+    /// No voice is reused, and the new notes are dropped until a voice becomes free.
+    None,
+}
@@ -0,0 +1,9 @@
+/// Specifies how the channel volume (CC 7) and expression (CC 11) are mapped to gain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeCurve {
+    /// The GM2/DLS curve, where the attenuation is `40 * log10(value / 127)` decibels.
+    /// This is the default behavior.
+    Gm2,
+    /// The controller value is used as a linear gain.
+    Linear,
+}
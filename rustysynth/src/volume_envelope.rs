@@ -2,6 +2,7 @@
 
 use crate::envelope_stage::EnvelopeStage;
 use crate::soundfont_math::SoundFontMath;
+use crate::state_hasher::StateHasher;
 use crate::synthesizer_settings::SynthesizerSettings;
 
 #[derive(Debug, Clone)]
@@ -76,6 +77,11 @@ impl VolumeEnvelope {
         self.process(0);
     }
 
+    // Multiplies the release time, before the release starts.
+    pub(crate) fn scale_release(&mut self, scale: f32) {
+        self.release_slope /= scale as f64;
+    }
+
     pub(crate) fn release(&mut self) {
         self.stage = EnvelopeStage::Release;
         self.release_start_time = self.processed_sample_count as f64 / self.sample_rate as f64;
@@ -138,6 +144,10 @@ impl VolumeEnvelope {
         }
     }
 
+    pub(crate) fn get_stage(&self) -> EnvelopeStage {
+        self.stage
+    }
+
     pub(crate) fn get_value(&self) -> f32 {
         self.value
     }
@@ -145,4 +155,11 @@ impl VolumeEnvelope {
     pub(crate) fn get_priority(&self) -> f32 {
         self.priority
     }
+
+    pub(crate) fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_usize(self.processed_sample_count);
+        hasher.write(&[self.stage as u8]);
+        hasher.write_f32(self.value);
+        hasher.write_f64(self.release_slope);
+    }
 }
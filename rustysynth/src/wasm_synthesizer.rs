@@ -0,0 +1,167 @@
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use js_sys::{Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::midifile::MidiFile;
+use crate::midifile_sequencer::MidiFileSequencer;
+use crate::soundfont::SoundFont;
+use crate::synthesizer::Synthesizer;
+use crate::synthesizer_settings::SynthesizerSettings;
+
+/// A synthesizer with a MIDI file player for JavaScript, built with wasm-bindgen.
+///
+/// The SoundFont and the MIDI files are loaded from byte buffers,
+/// such as a `Uint8Array` over the `ArrayBuffer` of a fetch response,
+/// and `load` awaits the SoundFont from a promise instead.
+/// `render` fills the two channels of an `AudioWorkletProcessor` output,
+/// which are 128 samples long, and can be called with buffers of any length.
+/// The messages can be sent while a MIDI file is playing, and are mixed with it.
+#[wasm_bindgen]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct WasmSynthesizer {
+    sequencer: MidiFileSequencer,
+}
+
+#[wasm_bindgen]
+impl WasmSynthesizer {
+    /// Loads the SoundFont from the bytes of an .sf2 file,
+    /// and creates a synthesizer at the sample rate of the audio context.
+    #[wasm_bindgen(constructor)]
+    pub fn new(sound_font: &[u8], sample_rate: i32) -> Result<WasmSynthesizer, JsError> {
+        let sound_font = Arc::new(SoundFont::new(&mut &sound_font[..])?);
+        let settings = SynthesizerSettings::new(sample_rate);
+        let synthesizer = Synthesizer::new(&sound_font, &settings)?;
+
+        Ok(Self {
+            sequencer: MidiFileSequencer::new(synthesizer),
+        })
+    }
+
+    /// Awaits the SoundFont from a promise which resolves to an `ArrayBuffer` or a `Uint8Array`,
+    /// such as `response.arrayBuffer()` of a fetch, and creates the synthesizer as `new` does.
+    ///
+    /// The page keeps running while the file is downloaded.
+    pub async fn load(sound_font: Promise, sample_rate: i32) -> Result<WasmSynthesizer, JsError> {
+        let value = JsFuture::from(sound_font)
+            .await
+            .map_err(|_| JsError::new("the SoundFont could not be loaded"))?;
+        let data = Uint8Array::new(&value).to_vec();
+        WasmSynthesizer::new(&data, sample_rate)
+    }
+
+    pub fn process_midi_message(&mut self, channel: i32, command: i32, data1: i32, data2: i32) {
+        self.sequencer
+            .get_synthesizer_mut()
+            .process_midi_message(channel, command, data1, data2);
+    }
+
+    /// Processes a system exclusive message, as `Synthesizer::process_sysex` does.
+    pub fn process_sysex(&mut self, data: &[u8]) {
+        self.sequencer.get_synthesizer_mut().process_sysex(data);
+    }
+
+    pub fn note_on(&mut self, channel: i32, key: i32, velocity: i32) {
+        self.sequencer
+            .get_synthesizer_mut()
+            .note_on(channel, key, velocity);
+    }
+
+    pub fn note_off(&mut self, channel: i32, key: i32) {
+        self.sequencer.get_synthesizer_mut().note_off(channel, key);
+    }
+
+    pub fn note_off_all(&mut self, immediate: bool) {
+        self.sequencer.get_synthesizer_mut().note_off_all(immediate);
+    }
+
+    pub fn get_master_volume(&self) -> f32 {
+        self.sequencer.get_synthesizer().get_master_volume()
+    }
+
+    pub fn set_master_volume(&mut self, value: f32) {
+        self.sequencer
+            .get_synthesizer_mut()
+            .set_master_volume(value);
+    }
+
+    pub fn get_sample_rate(&self) -> i32 {
+        self.sequencer.get_synthesizer().get_sample_rate()
+    }
+
+    /// Loads the MIDI file from the bytes of a .mid file, and starts playing it.
+    pub fn play_midi_file(&mut self, data: &[u8], play_loop: bool) -> Result<(), JsError> {
+        let midi_file = Arc::new(MidiFile::from_bytes(data)?);
+        self.sequencer.play(&midi_file, play_loop);
+        Ok(())
+    }
+
+    /// Stops the MIDI file and resets the synthesizer.
+    pub fn stop(&mut self) {
+        self.sequencer.stop();
+    }
+
+    pub fn pause(&mut self) {
+        self.sequencer.pause();
+    }
+
+    pub fn resume(&mut self) {
+        self.sequencer.resume();
+    }
+
+    /// Gets the playback position of the MIDI file in seconds.
+    pub fn get_position(&self) -> f64 {
+        self.sequencer.get_position()
+    }
+
+    /// Gets the length of the MIDI file in seconds, or 0 if none is playing.
+    pub fn get_length(&self) -> f64 {
+        self.sequencer.get_length()
+    }
+
+    pub fn end_of_sequence(&self) -> bool {
+        self.sequencer.end_of_sequence()
+    }
+
+    /// Renders the waveform into the left and right buffers, which must be the same length.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.sequencer.render(left, right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::midifile::tests::midi_bytes;
+
+    #[test]
+    fn test_play_from_bytes() {
+        let data = crate::soundfont::tests::build_soundfont(&[], &[(54, 1)]);
+        // The errors are converted to JavaScript, which is only possible on wasm32.
+        let Ok(mut synthesizer) = WasmSynthesizer::new(&data, 44100) else {
+            panic!("The SoundFont must be loaded.");
+        };
+
+        let track: Vec<u8> = vec![
+            0x00, 0x90, 0x3C, 0x64, //
+            0x60, 0x80, 0x3C, 0x00, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let midi = midi_bytes(96, &[&track]);
+        assert!(synthesizer.play_midi_file(&midi, false).is_ok());
+        assert!((synthesizer.get_length() - 0.5).abs() < 1.0E-9);
+
+        // The render quantum of an AudioWorklet.
+        let mut left = vec![0_f32; 128];
+        let mut right = vec![0_f32; 128];
+        synthesizer.render(&mut left, &mut right);
+        synthesizer.note_on(1, 64, 100);
+        synthesizer.render(&mut left, &mut right);
+        assert!((synthesizer.get_position() - 256.0 / 44100.0).abs() < 1.0E-9);
+    }
+}
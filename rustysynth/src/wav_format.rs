@@ -0,0 +1,10 @@
+/// Specifies the sample encoding of the WAV files written by `MidiFileSequencer::render_midi_to_wav`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavFormat {
+    /// 16-bit integer PCM, dithered as set by `Synthesizer::set_dither_mode`.
+    Pcm16,
+    /// 24-bit integer PCM, packed in 3 bytes per sample, with a WAVE_FORMAT_EXTENSIBLE header.
+    Pcm24,
+    /// 32-bit floating point, written without clipping.
+    Float32,
+}
@@ -0,0 +1,268 @@
+#![allow(dead_code)]
+
+use std::io;
+use std::io::Write;
+
+use crate::wav_format::WavFormat;
+
+#[allow(unused)]
+#[non_exhaustive]
+pub(crate) struct WaveWriter {}
+
+impl WaveWriter {
+    const WAVE_FORMAT_PCM: u16 = 1;
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+    const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+    // The GUID of the PCM sub-format, KSDATAFORMAT_SUBTYPE_PCM.
+    const SUBTYPE_PCM: [u8; 16] = [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B,
+        0x71,
+    ];
+    // The front left and front right speakers.
+    const STEREO_CHANNEL_MASK: u32 = 0x3;
+
+    // Writes the header of a stereo WAV file with the given number of frames,
+    // or fails with nothing written if the sizes do not fit in the 32-bit fields.
+    pub(crate) fn write_header<W: Write>(
+        writer: &mut W,
+        sample_rate: i32,
+        frame_count: usize,
+        format: WavFormat,
+    ) -> Result<(), io::Error> {
+        let channel_count: u16 = 2;
+        let bits_per_sample: u16 = match format {
+            WavFormat::Pcm16 => 16,
+            WavFormat::Pcm24 => 24,
+            WavFormat::Float32 => 32,
+        };
+        let block_align = channel_count * bits_per_sample / 8;
+        let too_long = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the samples do not fit in a WAV file",
+            )
+        };
+        let data_size = frame_count
+            .checked_mul(block_align as usize)
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(too_long)?;
+        let frame_count = u32::try_from(frame_count).map_err(|_| too_long())?;
+
+        // The extensible format adds the valid bits, the channel mask, and the sub-format,
+        // and a non-PCM format needs the fact chunk with the number of frames.
+        let (format_tag, fmt_size, fact_size) = match format {
+            WavFormat::Pcm16 => (WaveWriter::WAVE_FORMAT_PCM, 16_u32, 0_u32),
+            WavFormat::Pcm24 => (WaveWriter::WAVE_FORMAT_EXTENSIBLE, 40, 0),
+            WavFormat::Float32 => (WaveWriter::WAVE_FORMAT_IEEE_FLOAT, 18, 12),
+        };
+
+        let riff_size = (4 + 8 + fmt_size + fact_size + 8)
+            .checked_add(data_size)
+            .ok_or_else(too_long)?;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&riff_size.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&fmt_size.to_le_bytes())?;
+        writer.write_all(&format_tag.to_le_bytes())?;
+        writer.write_all(&channel_count.to_le_bytes())?;
+        writer.write_all(&(sample_rate as u32).to_le_bytes())?;
+        writer.write_all(&(sample_rate as u32 * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&bits_per_sample.to_le_bytes())?;
+        match format {
+            WavFormat::Pcm16 => (),
+            WavFormat::Pcm24 => {
+                writer.write_all(&22_u16.to_le_bytes())?;
+                writer.write_all(&bits_per_sample.to_le_bytes())?;
+                writer.write_all(&WaveWriter::STEREO_CHANNEL_MASK.to_le_bytes())?;
+                writer.write_all(&WaveWriter::SUBTYPE_PCM)?;
+            }
+            WavFormat::Float32 => {
+                writer.write_all(&0_u16.to_le_bytes())?;
+
+                writer.write_all(b"fact")?;
+                writer.write_all(&4_u32.to_le_bytes())?;
+                writer.write_all(&frame_count.to_le_bytes())?;
+            }
+        }
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+
+        Ok(())
+    }
+
+    pub(crate) fn write_samples<W: Write>(
+        writer: &mut W,
+        left: &[i16],
+        right: &[i16],
+    ) -> Result<(), io::Error> {
+        for (left, right) in left.iter().zip(right.iter()) {
+            writer.write_all(&left.to_le_bytes())?;
+            writer.write_all(&right.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the samples as 24-bit integers, rounded and clipped to the range.
+    pub(crate) fn write_samples_24<W: Write>(
+        writer: &mut W,
+        left: &[f32],
+        right: &[f32],
+    ) -> Result<(), io::Error> {
+        const MAX: f32 = 8_388_607_f32;
+
+        for (left, right) in left.iter().zip(right.iter()) {
+            for value in [*left, *right] {
+                let sample = (MAX * value).round().clamp(-MAX - 1_f32, MAX) as i32;
+                writer.write_all(&sample.to_le_bytes()[..3])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn write_samples_f32<W: Write>(
+        writer: &mut W,
+        left: &[f32],
+        right: &[f32],
+    ) -> Result<(), io::Error> {
+        for (left, right) in left.iter().zip(right.iter()) {
+            writer.write_all(&left.to_le_bytes())?;
+            writer.write_all(&right.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    #[test]
+    fn test_headers() {
+        for (format, format_tag, bits_per_sample, data_offset) in [
+            (WavFormat::Pcm16, 1, 16, 44),
+            (WavFormat::Pcm24, 0xFFFE, 24, 68),
+            (WavFormat::Float32, 3, 32, 58),
+        ] {
+            let mut data: Vec<u8> = Vec::new();
+            WaveWriter::write_header(&mut data, 48000, 10, format).unwrap();
+            let block_align = 2 * bits_per_sample / 8;
+
+            assert_eq!(data.len(), data_offset);
+            assert_eq!(&data[0..4], b"RIFF");
+            // The RIFF size covers the whole file after the size field, with the samples to follow.
+            assert_eq!(
+                read_u32(&data, 4) as usize,
+                data_offset - 8 + 10 * block_align as usize
+            );
+            assert_eq!(&data[8..16], b"WAVEfmt ");
+            assert_eq!(read_u16(&data, 20), format_tag);
+            assert_eq!(read_u16(&data, 22), 2);
+            assert_eq!(read_u32(&data, 24), 48000);
+            assert_eq!(read_u32(&data, 28), 48000 * block_align as u32);
+            assert_eq!(read_u16(&data, 32), block_align);
+            assert_eq!(read_u16(&data, 34), bits_per_sample);
+            assert_eq!(&data[data_offset - 8..data_offset - 4], b"data");
+            assert_eq!(read_u32(&data, data_offset - 4), 10 * block_align as u32);
+
+            match format {
+                WavFormat::Pcm24 => {
+                    assert_eq!(read_u16(&data, 36), 22);
+                    assert_eq!(read_u16(&data, 38), 24);
+                    assert_eq!(read_u32(&data, 40), 0x3);
+                    assert_eq!(&data[44..60], &WaveWriter::SUBTYPE_PCM);
+                }
+                WavFormat::Float32 => {
+                    assert_eq!(&data[38..42], b"fact");
+                    assert_eq!(read_u32(&data, 46), 10);
+                }
+                WavFormat::Pcm16 => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_too_long() {
+        // The data fits in 32 bits, but not the RIFF size, or neither does.
+        for frame_count in [(u32::MAX / 4) as usize, 1 << 40] {
+            let mut data: Vec<u8> = Vec::new();
+            let err = WaveWriter::write_header(&mut data, 48000, frame_count, WavFormat::Pcm16)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(data.is_empty());
+        }
+
+        // The longest float file, where the RIFF size is just within 32 bits,
+        // has the frame count in the fact chunk.
+        let mut data: Vec<u8> = Vec::new();
+        let frame_count = ((u32::MAX - 50) / 8) as usize;
+        WaveWriter::write_header(&mut data, 48000, frame_count, WavFormat::Float32).unwrap();
+        assert_eq!(read_u32(&data, 46) as usize, frame_count);
+        let err = WaveWriter::write_header(&mut data, 48000, frame_count + 1, WavFormat::Float32)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_samples_round_trip() {
+        let left = [0_f32, 0.5, -0.25, 1.0, -1.0];
+        let right = [0.125_f32, -0.5, 0.75, 2.0, -2.0];
+
+        let mut data: Vec<u8> = Vec::new();
+        WaveWriter::write_samples_24(&mut data, &left, &right).unwrap();
+        assert_eq!(data.len(), 2 * 3 * left.len());
+        for (i, chunk) in data.chunks(3).enumerate() {
+            // Sign-extend the packed 3 bytes.
+            let sample = i32::from_le_bytes([0, chunk[0], chunk[1], chunk[2]]) >> 8;
+            let expected = if i % 2 == 0 {
+                left[i / 2]
+            } else {
+                right[i / 2]
+            };
+            let expected = expected.clamp(-1_f32, 1_f32);
+            assert!((sample as f32 / 8_388_607_f32 - expected).abs() < 1.0e-6);
+        }
+
+        let mut data: Vec<u8> = Vec::new();
+        WaveWriter::write_samples_f32(&mut data, &left, &right).unwrap();
+        assert_eq!(data.len(), 2 * 4 * left.len());
+        for (i, chunk) in data.chunks(4).enumerate() {
+            let sample = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let expected = if i % 2 == 0 {
+                left[i / 2]
+            } else {
+                right[i / 2]
+            };
+            assert_eq!(sample, expected);
+        }
+
+        let mut data: Vec<u8> = Vec::new();
+        WaveWriter::write_samples(&mut data, &[1000, -1000], &[i16::MAX, i16::MIN]).unwrap();
+        let samples: Vec<i16> = data
+            .chunks(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        assert_eq!(samples, vec![1000, i16::MAX, -1000, i16::MIN]);
+    }
+}
@@ -1,34 +1,52 @@
+use alloc::vec::Vec;
+
 use crate::error::SoundFontError;
 use crate::generator::Generator;
+use crate::modulator::Modulator;
 use crate::zone_info::ZoneInfo;
 
 #[non_exhaustive]
 pub(crate) struct Zone {
     pub(crate) generators: Vec<Generator>,
+    pub(crate) modulators: Vec<Modulator>,
 }
 
 impl Zone {
     pub(crate) fn empty() -> Self {
         Self {
             generators: Vec::new(),
+            modulators: Vec::new(),
         }
     }
 
-    fn new(info: &ZoneInfo, generators: &[Generator]) -> Self {
+    fn new(info: &ZoneInfo, generators: &[Generator], modulators: &[Modulator]) -> Self {
         let mut segment: Vec<Generator> = Vec::new();
 
         for i in 0..info.generator_count {
             segment.push(generators[(info.generator_index + i) as usize]);
         }
 
+        // The modulators are optional, and the invalid ones are ignored.
+        let start = info.modulator_index.max(0) as usize;
+        let end = start + info.modulator_count.max(0) as usize;
+        let modulators = modulators
+            .get(start..end)
+            .unwrap_or(&[])
+            .iter()
+            .filter(|modulator| modulator.is_valid())
+            .copied()
+            .collect();
+
         Self {
             generators: segment,
+            modulators,
         }
     }
 
     pub(crate) fn create(
         infos: &[ZoneInfo],
         generators: &[Generator],
+        modulators: &[Modulator],
     ) -> Result<Vec<Zone>, SoundFontError> {
         if infos.len() <= 1 {
             return Err(SoundFontError::ZoneNotFound);
@@ -39,7 +57,7 @@ impl Zone {
 
         let mut zones: Vec<Zone> = Vec::new();
         for info in infos.iter().take(count) {
-            zones.push(Zone::new(info, generators));
+            zones.push(Zone::new(info, generators, modulators));
         }
 
         Ok(zones)
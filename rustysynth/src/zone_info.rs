@@ -1,7 +1,8 @@
-use std::io::Read;
+use alloc::vec::Vec;
 
 use crate::binary_reader::BinaryReader;
 use crate::error::SoundFontError;
+use crate::io::Read;
 
 #[non_exhaustive]
 pub(crate) struct ZoneInfo {